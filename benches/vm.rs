@@ -0,0 +1,78 @@
+#[macro_use]
+extern crate criterion;
+extern crate rapidus;
+
+use criterion::Criterion;
+use rapidus::{gc, parser, vm::vm::VM};
+
+/// Compile 'code' and run it on a fresh VM. The tracing JIT is disabled so that the
+/// interpreter itself is measured.
+fn execute(code: &str) {
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    let node = parser::Parser::new(code.to_string()).parse_all().unwrap();
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    vm.run(iseq).unwrap();
+    gc::free_all();
+}
+
+fn fib(c: &mut Criterion) {
+    c.bench_function("fib 20", |b| {
+        b.iter(|| {
+            execute(
+                "function fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2) }
+                 fib(20)",
+            )
+        })
+    });
+}
+
+fn nested_loop(c: &mut Criterion) {
+    c.bench_function("nested loop 300x300", |b| {
+        b.iter(|| {
+            execute(
+                "var sum = 0
+                 for (var i = 0; i < 300; i++) {
+                   for (var j = 0; j < 300; j++) {
+                     sum += i * j % 7
+                   }
+                 }",
+            )
+        })
+    });
+}
+
+fn property_access(c: &mut Criterion) {
+    c.bench_function("property access", |b| {
+        b.iter(|| {
+            execute(
+                "function Point(x, y) { this.x = x; this.y = y }
+                 Point.prototype.norm = function() { return this.x * this.x + this.y * this.y }
+                 var p = new Point(3, 4)
+                 var sum = 0
+                 for (var i = 0; i < 20000; i++) {
+                   p.x = i
+                   sum += p.norm()
+                 }",
+            )
+        })
+    });
+}
+
+fn string_building(c: &mut Criterion) {
+    c.bench_function("string building 20000", |b| {
+        b.iter(|| {
+            execute(
+                "var s = ''
+                 for (var i = 0; i < 20000; i++) {
+                   s += 'item ' + i + ', '
+                 }
+                 s.length",
+            )
+        })
+    });
+}
+
+criterion_group!(benches, fib, nested_loop, property_access, string_building);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::fmt;
+
+/// An interned string. Atoms made from equal strings have the same id, so they are
+/// compared and hashed as integers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Atom(u32);
+
+#[derive(Debug)]
+pub struct AtomTable {
+    ids: FxHashMap<&'static str, Atom>,
+    strings: Vec<&'static str>,
+}
+
+impl AtomTable {
+    pub fn new() -> AtomTable {
+        AtomTable {
+            ids: FxHashMap::default(),
+            strings: vec![],
+        }
+    }
+
+    pub fn intern(&mut self, s: &str) -> Atom {
+        if let Some(atom) = self.ids.get(s) {
+            return *atom;
+        }
+        // Interned strings live as long as the program.
+        let s: &'static str = Box::leak(s.to_string().into_boxed_str());
+        let atom = Atom(self.strings.len() as u32);
+        self.strings.push(s);
+        self.ids.insert(s, atom);
+        atom
+    }
+
+    pub fn get(&self, atom: Atom) -> &'static str {
+        self.strings[atom.0 as usize]
+    }
+}
+
+thread_local!(pub static ATOM_TABLE: RefCell<AtomTable> = {
+    RefCell::new(AtomTable::new())
+});
+
+impl Atom {
+    pub fn new(s: &str) -> Atom {
+        ATOM_TABLE.with(|table| table.borrow_mut().intern(s))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        ATOM_TABLE.with(|table| table.borrow().get(self))
+    }
+
+    pub fn id(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Atom({}, {:?})", self.0, self.as_str())
+    }
+}
+
+#[test]
+fn test() {
+    let mut table = AtomTable::new();
+    let a = table.intern("length");
+    let b = table.intern("prototype");
+    assert_ne!(a, b);
+    assert_eq!(table.intern("length"), a);
+    assert_eq!(table.get(a), "length");
+    assert_eq!(table.get(b), "prototype");
+
+    let atom = Atom::new("foo");
+    assert_eq!(Atom::new(&"foo".to_string()), atom);
+    assert_eq!(atom.as_str(), "foo");
+    assert_eq!(format!("{}", atom), "foo");
+}
@@ -1,23 +1,27 @@
+use builtins;
 use chrono::Utc;
 use gc;
 use libc;
 use libloading;
+#[cfg(feature = "jit")]
 use llvm::prelude::LLVMValueRef;
 use parser;
-use std::ffi::CString;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path;
+use std::rc::Rc;
 use vm::{
     error::RuntimeError,
-    task::{Task, TimerID, TimerKind},
-    value::{CallObjectRef, FuncInfo, ObjectKind, Property, RawStringPtr, Value},
+    task::{Job, JobQueue, Task, TimerID, TimerKind},
+    value::{CallObjectRef, FuncInfo, ObjectKind, RawStringPtr, Value},
     vm::VM,
 };
 use vm_codegen;
 
 pub type BuiltinFuncTy = fn(&mut VM, &Vec<Value>, CallObjectRef) -> Result<(), RuntimeError>;
 pub type BuiltinJITFuncTy = *mut libc::c_void;
+#[cfg(not(feature = "jit"))]
+pub type LLVMValueRef = *mut libc::c_void;
 
 #[derive(Clone)]
 pub struct BuiltinFuncInfo {
@@ -147,6 +151,32 @@ pub fn clear_timer(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(
     Ok(())
 }
 
+/// queueMicrotask(callback). 'callback' runs as a job, before the next timer or other task.
+pub fn queue_microtask(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let callback = match args.get(0) {
+        Some(callback @ Value::Object(_, ObjectKind::Function(_)))
+        | Some(callback @ Value::Object(_, ObjectKind::BuiltinFunction(_))) => callback.clone(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: queueMicrotask() needs a function".to_string(),
+            ))
+        }
+    };
+
+    vm.task_mgr.enqueue_job(Job {
+        callback,
+        args: vec![],
+    });
+
+    vm.state.stack.push(Value::Undefined);
+
+    Ok(())
+}
+
 pub fn console_log(
     self_: &mut VM,
     args: &Vec<Value>,
@@ -162,6 +192,7 @@ pub fn console_log(
         }
         libc::puts(b"\0".as_ptr() as RawStringPtr);
     }
+    builtins::console::notify_debugger(self_, "log", args);
     self_.state.stack.push(Value::Undefined);
     Ok(())
 }
@@ -223,155 +254,19 @@ pub fn assert_seq(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<()
 }
 
 pub fn debug_print(val: &Value, nest: bool) {
-    fn show_obj(sorted_key_val: Vec<(&String, &Property)>) {
-        for (i, tupple) in sorted_key_val.iter().enumerate() {
-            unsafe {
-                libc::printf(
-                    "'%s'\0".as_ptr() as RawStringPtr,
-                    CString::new(tupple.0.as_str()).unwrap().into_raw(),
-                );
-                libc::printf(": \0".as_ptr() as RawStringPtr);
-                debug_print(&tupple.1.val, true);
-                libc::printf(if i != sorted_key_val.len() - 1 {
-                    ", \0".as_ptr() as RawStringPtr
-                } else {
-                    " \0".as_ptr() as RawStringPtr
-                });
-            }
-        }
-    }
-
-    unsafe {
-        match val {
-            Value::Empty => {
-                libc::printf(b"empty\0".as_ptr() as RawStringPtr);
-            }
-            Value::Object(_, ObjectKind::Arguments(callobj)) => {
-                let callobj = &callobj.clone();
-                let args = &callobj.arguments;
-                libc::printf("[ \0".as_ptr() as RawStringPtr);
-
-                let mut i = 0;
-                let length = args.len();
-                while i < length {
-                    if i != 0 {
-                        libc::printf(", \0".as_ptr() as RawStringPtr);
-                    };
-                    match callobj.get_arguments_nth_value(i) {
-                        Ok(val) => {
-                            debug_print(&val, true);
-                        }
-                        Err(_) => {
-                            libc::printf(" \0".as_ptr() as RawStringPtr);
-                        }
-                    };
-
-                    i += 1;
-                }
-                libc::printf(" ]\0".as_ptr() as RawStringPtr);
-            }
-            Value::Null => {
-                libc::printf(b"null\0".as_ptr() as RawStringPtr);
-            }
-            Value::Undefined => {
-                libc::printf(b"undefined\0".as_ptr() as RawStringPtr);
-            }
-            Value::Bool(true) => {
-                libc::printf(b"true\0".as_ptr() as RawStringPtr);
-            }
-            Value::Bool(false) => {
-                libc::printf(b"false\0".as_ptr() as RawStringPtr);
-            }
-            Value::Number(n) => {
-                if n.is_nan() {
-                    libc::printf("NaN\0".as_ptr() as RawStringPtr);
-                } else if n.is_infinite() {
-                    libc::printf("Infinity\0".as_ptr() as RawStringPtr);
-                } else {
-                    libc::printf("%.15g\0".as_ptr() as RawStringPtr, *n);
-                }
-            }
-            Value::String(ref s) => {
-                libc::printf(
-                    if nest { "'%s'\0" } else { "%s\0" }.as_ptr() as RawStringPtr,
-                    s.as_ptr(),
-                );
-            }
-            Value::Object(ref map, ObjectKind::Ordinary) => {
-                libc::printf("{ \0".as_ptr() as RawStringPtr);
-
-                let mut sorted_key_val = (&*map).iter().collect::<Vec<(&String, &Property)>>();
-                sorted_key_val.sort_by(|(key1, _), (key2, _)| key1.as_str().cmp(key2.as_str()));
-                sorted_key_val.retain(|(ref key, _)| key != &"__proto__");
-
-                show_obj(sorted_key_val);
-
-                libc::printf("}\0".as_ptr() as RawStringPtr);
-            }
-            Value::Object(map, ObjectKind::Array(ref values)) => {
-                libc::printf("[ \0".as_ptr() as RawStringPtr);
-                let arr = &*(values);
-                let elems = &arr.elems;
-                let is_last_idx = |idx: usize| -> bool { idx == arr.length - 1 };
-                let mut i = 0;
-                let mut sorted_key_val = (&*map).iter().collect::<Vec<(&String, &Property)>>();
-                sorted_key_val.sort_by(|(key1, _), (key2, _)| key1.as_str().cmp(key2.as_str()));
-                sorted_key_val.retain(|(ref key, _)| key != &"__proto__");
-
-                while i < arr.length {
-                    let mut empty_elems = 0;
-                    while i < arr.length && Value::Empty == elems[i].val {
-                        empty_elems += 1;
-                        i += 1;
-                    }
-
-                    if empty_elems > 0 {
-                        libc::printf(
-                            "<%u empty item%s>%s\0".as_ptr() as RawStringPtr,
-                            empty_elems,
-                            if empty_elems >= 2 { "s\0" } else { "\0" }.as_ptr() as RawStringPtr,
-                            if is_last_idx(i - 1) && sorted_key_val.len() == 0 {
-                                " \0"
-                            } else {
-                                ", \0"
-                            }
-                            .as_ptr() as RawStringPtr,
-                        );
-
-                        if is_last_idx(i - 1) {
-                            break;
-                        }
-                    }
-
-                    debug_print(&elems[i].val, true);
-                    libc::printf(
-                        if is_last_idx(i) && sorted_key_val.len() == 0 {
-                            " \0"
-                        } else {
-                            ", \0"
-                        }
-                        .as_ptr() as RawStringPtr,
-                    );
-
-                    i += 1;
-                }
-
-                show_obj(sorted_key_val);
+    builtins::console::printf_str(&builtins::console::inspect(val, nest));
+}
 
-                libc::printf("]\0".as_ptr() as RawStringPtr);
-            }
-            Value::Object(_, ObjectKind::Function(_))
-            | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {
-                libc::printf("[Function]\0".as_ptr() as RawStringPtr);
-            }
-            Value::Object(_, ObjectKind::Date(box time_val)) => {
-                // TODO: Date needs toString() ?
-                libc::printf(
-                    "%s\0".as_ptr() as RawStringPtr,
-                    CString::new(time_val.to_rfc3339()).unwrap().as_ptr(),
-                );
-            }
-        }
+/// modules built into the engine, which require() loads before looking for files.
+fn builtin_module(name: &str) -> Option<Value> {
+    match name {
+        "child_process" => Some(builtins::child_process::init()),
+        "crypto" => Some(builtins::crypto::init()),
+        "fs" => Some(builtins::fs::init()),
+        "os" => Some(builtins::os::init()),
+        "path" => Some(builtins::path::init()),
+        "util" => Some(builtins::util::init()),
+        _ => None,
     }
 }
 
@@ -383,6 +278,12 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
     }
 
     fn find_file(file_name: &str) -> RequireFileKind {
+        let style = builtins::path::Style::native();
+        let file_name = style.normalize(file_name);
+        let file_name = file_name.as_str();
+        if style.extname(file_name) == ".js" && path::Path::new(file_name).is_file() {
+            return RequireFileKind::Normal(file_name.to_string());
+        }
         let paths = vec!["#.js", "lib#.so", "lib#.dylib"];
         match paths
             .iter()
@@ -411,7 +312,7 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
     }
 
     let file_name = match args[0] {
-        Value::String(ref s) => s.to_str().unwrap().clone(),
+        Value::String(ref s) => s.to_string(),
         _ => {
             return Err(RuntimeError::Type(
                 "require() arguments must be string.".to_string(),
@@ -419,6 +320,11 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
         }
     };
 
+    if let Some(module) = builtin_module(file_name) {
+        vm.set_return_value(module);
+        return Ok(());
+    }
+
     match find_file(file_name) {
         RequireFileKind::DLL(name) => {
             let dylib_path = name.as_str();
@@ -483,17 +389,24 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
             // this FuncInfo is a dummy.
             let func_info = FuncInfo::new(0, vec![], vec![]);
             let mut callobj = callobj.new_callobj_from_func(func_info, &args, None);
+            // The functions of the module refer to the scope of the caller.
+            vm.capture_scope();
             callobj.parent = Some(vm.state.scope.last().unwrap().clone());
             vm.state.scope.push(callobj);
 
             let mut iseq = vec![];
-            match vm.codegen.compile(&node, &mut iseq, false) {
+            // The variables of the module are not global.
+            let global_scope = vm.codegen.global_scope;
+            vm.codegen.global_scope = false;
+            let res = vm.codegen.compile(&node, &mut iseq, false);
+            vm.codegen.global_scope = global_scope;
+            match res {
                 Ok(()) => {}
-                Err(vm_codegen::Error::General { msg, token_pos }) => {
+                Err(vm_codegen::Error::General { msg, token_pos })
+                | Err(vm_codegen::Error::Unimplemented { msg, token_pos }) => {
                     parser.show_error_at(token_pos, msg.as_str());
                     return Ok(());
                 }
-                Err(e) => panic!(e),
             }
 
             vm.state.scope.last_mut().unwrap().set_value(
@@ -501,7 +414,8 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
                 Value::object_from_npp(&make_npp!(exports: Value::Undefined)),
             );
 
-            vm.do_run(&iseq)?;
+            vm.do_run(Rc::new(iseq))?;
+            vm.state.stack.pop(); // the result of the module code is not used
 
             let module_exports = vm
                 .state
@@ -513,7 +427,7 @@ pub fn require(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result
             vm.state.scope.pop();
 
             vm.state.stack.push(module_exports);
-            gc::mark_and_sweep(vm);
+            gc::collect_if_needed(vm);
         }
         RequireFileKind::NotFound => {
             return Err(RuntimeError::General(format!(
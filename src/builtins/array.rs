@@ -1,7 +1,14 @@
+use builtins::error::new_error;
+use builtins::function::call_with_this;
 use builtins::object::*;
 use gc;
 use vm::{error::RuntimeError, value::*, vm::VM};
 
+// The internal slots of an array iterator, kept in the call object of its next().
+const ITERATED_ARRAY: &str = "[[IteratedArrayLike]]";
+const NEXT_INDEX: &str = "[[ArrayLikeNextIndex]]";
+const ITERATION_KIND: &str = "[[ArrayLikeIterationKind]]";
+
 thread_local!(
     pub static ARRAY_PROTOTYPE: Value = {
         let map = Value::propmap_from_npp(&make_npp!(
@@ -10,16 +17,19 @@ thread_local!(
             push: Value::default_builtin_function(prototype_push),
             pop: Value::default_builtin_function(prototype_pop),
             map: Value::default_builtin_function(prototype_map),
+            sort: Value::default_builtin_function(prototype_sort),
+            flat: Value::default_builtin_function(prototype_flat),
+            flatMap: Value::default_builtin_function(prototype_flat_map),
+            fill: Value::default_builtin_function(prototype_fill),
+            copyWithin: Value::default_builtin_function(prototype_copy_within),
+            at: Value::default_builtin_function(prototype_at),
+            entries: Value::default_builtin_function(prototype_entries),
+            keys: Value::default_builtin_function(prototype_keys),
+            values: Value::default_builtin_function(prototype_values),
             __proto__: OBJECT_PROTOTYPE.with(|x| x.clone())
         ));
 
-        Value::Object(
-            map,
-            ObjectKind::Array(gc::new(ArrayValue {
-                elems: vec![],
-                length: 0,
-            })),
-        )
+        Value::Object(map, ObjectKind::Array(gc::new(ArrayValue::new(vec![]))))
     };
 );
 pub fn init() -> Value {
@@ -27,14 +37,11 @@ pub fn init() -> Value {
     let array = Value::builtin_function(
         prototype_new,
         None,
-        &mut vec![
-            // TODO: Add:
-            //          - Array.from()
-            //          - Array.isArray()
-            //          - Array.observe()
-            //          - Array.of()
-            //          etc...
-        ],
+        &mut make_npp!(
+            isArray:    Value::default_builtin_function(array_is_array),
+            from:       Value::default_builtin_function(array_from),
+            of:         Value::default_builtin_function(array_of)
+        ),
         Some(prototype.clone()),
     );
     prototype.set_constructor(array.clone());
@@ -47,28 +54,132 @@ fn prototype_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(),
 
     if args_len == 0 {
         vm.set_return_value(Value::array_from_elems(vec![]));
-        gc::mark_and_sweep(vm);
+        gc::collect_if_needed(vm);
         return Ok(());
     }
 
-    let mut elems = vec![];
-
-    match args[0] {
+    let array = match args[0] {
+        // new Array(length) makes an array of holes.
         Value::Number(length) if args_len == 1 => {
-            for _ in 0..length as usize {
-                elems.push(Value::empty());
+            if length != args[0].to_uint32() {
+                return Err(RuntimeError::Exception(new_error(
+                    "RangeError: Invalid array length".to_string(),
+                )));
             }
+            let array = Value::array_from_elems(vec![]);
+            if let Value::Object(_, ObjectKind::Array(mut aryval)) = array.clone() {
+                aryval.set_length(length as usize);
+            }
+            array
         }
+        _ => Value::array_from_elems(args.clone()),
+    };
+
+    vm.set_return_value(array);
+    gc::collect_if_needed(vm);
+
+    Ok(())
+}
+
+/// Array.isArray(arg)
+fn array_is_array(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let is_array = match args.get(0) {
+        Some(Value::Object(_, ObjectKind::Array(_))) => true,
+        _ => false,
+    };
+    vm.set_return_value(Value::Bool(is_array));
+    Ok(())
+}
+
+/// Array.of(...items)
+fn array_of(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::array_from_elems(args.clone()));
+    gc::collect_if_needed(vm);
+    Ok(())
+}
+
+/// Array.from(items, mapFn, thisArg). There are no iterators yet, so arrays, strings (by code
+/// points), typed arrays and arguments are read directly, and other objects as array-likes.
+fn array_from(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let items = args.get(0).cloned().unwrap_or(Value::Undefined);
+    let map_fn = args.get(1).cloned().unwrap_or(Value::Undefined);
+    let this_arg = args.get(2).cloned().unwrap_or(Value::Undefined);
+    match map_fn {
+        Value::Undefined
+        | Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {}
         _ => {
-            for arg in args {
-                elems.push(arg.clone());
-            }
+            return Err(RuntimeError::Type(
+                "type error: Array.from: the map function is not callable".to_string(),
+            ))
         }
     }
 
-    vm.set_return_value(Value::array_from_elems(elems));
-    gc::mark_and_sweep(vm);
+    let elems = match items {
+        Value::Undefined | Value::Null => {
+            return Err(RuntimeError::Type(
+                "type error: Array.from: cannot convert undefined or null to object".to_string(),
+            ))
+        }
+        Value::String(ref s) => {
+            let mut elems = vec![];
+            let mut i = 0;
+            while i < s.len() {
+                let len = if s.code_point_at(i).unwrap() > 0xffff {
+                    2
+                } else {
+                    1
+                };
+                elems.push(Value::js_string(s.substring(i, i + len)));
+                i += len;
+            }
+            elems
+        }
+        Value::Object(_, ObjectKind::Array(ref array)) => array.values()?,
+        Value::Object(_, ObjectKind::TypedArray(ref ta)) => {
+            (0..ta.length).map(|i| ta.get(i as f64)).collect()
+        }
+        Value::Object(_, ObjectKind::Arguments(ref callobj)) => {
+            let mut elems = vec![];
+            for i in 0..callobj.get_arguments_length() {
+                elems.push(callobj.get_arguments_nth_value(i)?);
+            }
+            elems
+        }
+        Value::Object(_, _) => {
+            let length = items
+                .get_property(Value::string("length".to_string()), None)
+                .to_number();
+            let length = if length.is_nan() || length <= 0.0 {
+                0.0
+            } else {
+                length.min(MAX_ARRAY_LENGTH).floor()
+            };
+            (0..length as usize)
+                .map(|i| items.get_property(Value::Number(i as f64), None))
+                .collect()
+        }
+        _ => vec![],
+    };
 
+    let elems = if map_fn == Value::Undefined {
+        elems
+    } else {
+        let mut mapped = vec![];
+        for (i, val) in elems.into_iter().enumerate() {
+            call_with_this(
+                vm,
+                &map_fn,
+                this_arg.clone(),
+                &vec![val, Value::Number(i as f64)],
+            )?;
+            mapped.push(vm.state.stack.pop().unwrap_or(Value::Undefined));
+        }
+        mapped
+    };
+
+    vm.set_return_value(Value::array_from_elems(elems));
+    gc::collect_if_needed(vm);
     Ok(())
 }
 
@@ -87,11 +198,9 @@ fn prototype_push(
     };
 
     for val in args {
-        array.elems.push(val.to_property());
+        array.push(val.clone());
     }
 
-    array.length += args.len();
-
     vm.set_return_value(Value::Number(array.length as f64));
 
     Ok(())
@@ -109,13 +218,8 @@ fn prototype_pop(
         return Err(RuntimeError::Unknown);
     };
 
-    if let Some(prop) = array.clone().elems.pop() {
-        array.length -= 1;
-        vm.set_return_value(prop.val);
-        return Ok(());
-    }
-
-    vm.set_return_value(Value::Undefined);
+    let val = array.pop();
+    vm.set_return_value(val);
 
     Ok(())
 }
@@ -132,8 +236,9 @@ pub fn prototype_map(
         return Err(RuntimeError::Unknown);
     };
 
-    let mut new_array = vec![];
+    let mut new_array = ArrayValue::new(vec![]);
     let callback = &args[0];
+    let this_arg = args.get(1).cloned().unwrap_or(Value::Undefined);
 
     let mut args_for_callback = vec![
         Value::Undefined,
@@ -141,17 +246,390 @@ pub fn prototype_map(
         /* array itself = */ (*callobj.this).clone(),
     ];
 
-    for i in 0..array.length {
-        args_for_callback[0] = array.elems[i].val.clone();
+    // The length is read once, and the callback is not called for holes, including the
+    // elements removed by the callback shortening the array. They stay holes in the result.
+    // Holes are skipped over rather than visited, so sparse arrays are cheap to map.
+    let length = array.length;
+    let mut next = array.next_index(0);
+    while let Some(i) = next.filter(|&i| i < length) {
+        args_for_callback[0] = array.get_value(i);
         args_for_callback[1].set_number_if_possible(i as f64);
 
-        vm.call_function_simply(callback, &args_for_callback)?;
+        vm.call_function_with_this(callback, this_arg.clone(), &args_for_callback)?;
 
         let val = vm.state.stack.pop().unwrap();
-        new_array.push(val);
+        // Only 'new_array' refers to it until the result is made.
+        vm.root(val.clone());
+        new_array.set(i, val);
+        next = array.next_index(i + 1);
+    }
+    new_array.set_length(length);
+
+    vm.set_return_value(Value::array_from_value(new_array));
+
+    Ok(())
+}
+
+/// Array.prototype.sort(comparefn). The sort is stable. Undefined is sorted to the end,
+/// followed by the holes.
+pub fn prototype_sort(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut array = if let Value::Object(_, ObjectKind::Array(ref array)) = *callobj.this {
+        array.clone()
+    } else {
+        return Err(RuntimeError::Type(
+            "type error: Array.prototype.sort called on non-array object".to_string(),
+        ));
+    };
+
+    let comparefn = match args.get(0) {
+        None | Some(Value::Undefined) => None,
+        Some(f @ Value::Object(_, ObjectKind::Function(_)))
+        | Some(f @ Value::Object(_, ObjectKind::BuiltinFunction(_))) => Some(f.clone()),
+        Some(_) => {
+            return Err(RuntimeError::Type(
+                "type error: The comparison function must be either a function or undefined"
+                    .to_string(),
+            ))
+        }
+    };
+
+    let length = array.length;
+    let mut undefined_count = 0;
+    let mut elems = vec![];
+    for (_, prop) in array.elements() {
+        if prop.val == Value::Undefined {
+            undefined_count += 1;
+        } else {
+            // 'comparefn' may remove it from the array.
+            vm.root(prop.val.clone());
+            elems.push(prop.val.clone())
+        }
+    }
+
+    let mut sorted = merge_sort(vm, elems, &comparefn)?;
+    for _ in 0..undefined_count {
+        sorted.push(Value::Undefined);
     }
 
-    vm.set_return_value(Value::array_from_elems(new_array));
+    array.set_length(0);
+    for (i, val) in sorted.into_iter().enumerate() {
+        array.set(i, val);
+    }
+    array.set_length(length);
 
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+fn merge_sort(
+    vm: &mut VM,
+    mut elems: Vec<Value>,
+    comparefn: &Option<Value>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if elems.len() <= 1 {
+        return Ok(elems);
+    }
+    let right = elems.split_off(elems.len() / 2);
+    let left = merge_sort(vm, elems, comparefn)?;
+    let right = merge_sort(vm, right, comparefn)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    loop {
+        let take_right = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) => compare(vm, l, r, comparefn)? > 0.0,
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (None, None) => break,
+        };
+        merged.push(if take_right {
+            right.next().unwrap()
+        } else {
+            left.next().unwrap()
+        });
+    }
+    Ok(merged)
+}
+
+/// SortCompare(x, y). A negative number if 'x' comes before 'y', and a positive number if after.
+/// https://tc39.github.io/ecma262/#sec-sortcompare
+fn compare(
+    vm: &mut VM,
+    x: &Value,
+    y: &Value,
+    comparefn: &Option<Value>,
+) -> Result<f64, RuntimeError> {
+    match comparefn {
+        Some(comparefn) => {
+            vm.call_function_simply(comparefn, &vec![x.clone(), y.clone()])?;
+            let val = vm.state.stack.pop().unwrap_or(Value::Undefined).to_number();
+            Ok(if val.is_nan() { 0.0 } else { val })
+        }
+        None => Ok(match x.to_js_string().cmp(&y.to_js_string()) {
+            ::std::cmp::Ordering::Less => -1.0,
+            ::std::cmp::Ordering::Equal => 0.0,
+            ::std::cmp::Ordering::Greater => 1.0,
+        }),
+    }
+}
+
+/// get 'this' as an array for the Array.prototype methods which work only on arrays.
+fn this_array(callobj: &CallObjectRef, name: &str) -> Result<ArrayValueRef, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::Array(ref array)) => Ok(array.clone()),
+        _ => Err(RuntimeError::Type(format!(
+            "type error: Array.prototype.{} called on non-array object",
+            name
+        ))),
+    }
+}
+
+/// the index which the relative index 'arg' stands for in an array of 'length'. A negative one
+/// counts from the end. It is clamped to 0..length, and 'default' if 'arg' is undefined.
+fn relative_index(arg: Option<&Value>, length: usize, default: usize) -> usize {
+    let n = match arg {
+        None | Some(Value::Undefined) => return default,
+        Some(arg) => arg.to_number(),
+    };
+    let n = if n.is_nan() { 0.0 } else { n.trunc() };
+    if n < 0.0 {
+        (length as f64 + n).max(0.0) as usize
+    } else {
+        n.min(length as f64) as usize
+    }
+}
+
+/// https://tc39.github.io/ecma262/#sec-flattenintoarray
+/// push the elements of 'array' to 'target', flattening the arrays among them down to 'depth'.
+/// Holes are skipped.
+fn flatten_into(target: &mut Vec<Value>, array: &ArrayValue, depth: f64) {
+    for (_, prop) in array.elements() {
+        match prop.val {
+            Value::Object(_, ObjectKind::Array(ref inner)) if depth >= 1.0 => {
+                flatten_into(target, inner, depth - 1.0)
+            }
+            ref val => target.push(val.clone()),
+        }
+    }
+}
+
+/// Array.prototype.flat(depth)
+fn prototype_flat(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let array = this_array(&callobj, "flat")?;
+    let depth = match args.get(0) {
+        None | Some(Value::Undefined) => 1.0,
+        Some(depth) => {
+            let depth = depth.to_number();
+            if depth.is_nan() {
+                0.0
+            } else {
+                depth.trunc()
+            }
+        }
+    };
+
+    let mut elems = vec![];
+    flatten_into(&mut elems, &array, depth);
+    vm.set_return_value(Value::array_from_elems(elems));
+    gc::collect_if_needed(vm);
+    Ok(())
+}
+
+/// Array.prototype.flatMap(callback, thisArg). The callback is not called for holes.
+fn prototype_flat_map(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let array = this_array(&callobj, "flatMap")?;
+    let callback = args.get(0).cloned().unwrap_or(Value::Undefined);
+    let this_arg = args.get(1).cloned().unwrap_or(Value::Undefined);
+    match callback {
+        Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {}
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Array.prototype.flatMap: the callback is not callable".to_string(),
+            ))
+        }
+    }
+
+    let mut elems = vec![];
+    let length = array.length;
+    let mut next = array.next_index(0);
+    while let Some(i) = next.filter(|&i| i < length) {
+        call_with_this(
+            vm,
+            &callback,
+            this_arg.clone(),
+            &vec![
+                array.get_value(i),
+                Value::Number(i as f64),
+                (*callobj.this).clone(),
+            ],
+        )?;
+        match vm.state.stack.pop().unwrap_or(Value::Undefined) {
+            Value::Object(_, ObjectKind::Array(ref inner)) => flatten_into(&mut elems, inner, 0.0),
+            val => elems.push(val),
+        }
+        next = array.next_index(i + 1);
+    }
+    vm.set_return_value(Value::array_from_elems(elems));
+    gc::collect_if_needed(vm);
+    Ok(())
+}
+
+/// Array.prototype.fill(value, start, end). Holes in the range are filled too.
+fn prototype_fill(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut array = this_array(&callobj, "fill")?;
+    let val = args.get(0).cloned().unwrap_or(Value::Undefined);
+    let length = array.length;
+    let start = relative_index(args.get(1), length, 0);
+    let end = relative_index(args.get(2), length, length);
+    for n in start..end {
+        array.set(n, val.clone());
+    }
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// Array.prototype.copyWithin(target, start, end). A hole is copied as a hole.
+fn prototype_copy_within(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut array = this_array(&callobj, "copyWithin")?;
+    let length = array.length;
+    let to = relative_index(args.get(0), length, 0);
+    let from = relative_index(args.get(1), length, 0);
+    let end = relative_index(args.get(2), length, length);
+    let count = end.saturating_sub(from).min(length - to);
+
+    // The elements are read before any is written, so overlapping ranges work.
+    let elems: Vec<Option<Value>> = (from..from + count)
+        .map(|n| array.get(n).map(|prop| prop.val.clone()))
+        .collect();
+    for (i, elem) in elems.into_iter().enumerate() {
+        match elem {
+            Some(val) => array.set(to + i, val),
+            None => array.delete(to + i),
+        }
+    }
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// Array.prototype.at(index). A negative index counts from the end.
+fn prototype_at(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let array = this_array(&callobj, "at")?;
+    let index = args.get(0).map_or(0.0, |index| index.to_number());
+    let index = if index.is_nan() { 0.0 } else { index.trunc() };
+    let index = if index < 0.0 {
+        array.length as f64 + index
+    } else {
+        index
+    };
+    let val = if 0.0 <= index && index < array.length as f64 {
+        array.get_value(index as usize)
+    } else {
+        Value::Undefined
+    };
+    vm.set_return_value(val);
+    Ok(())
+}
+
+/// Array.prototype.entries()
+fn prototype_entries(
+    vm: &mut VM,
+    _args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    this_array(&callobj, "entries")?;
+    vm.set_return_value(array_iterator((*callobj.this).clone(), "entries"));
+    Ok(())
+}
+
+/// Array.prototype.keys()
+fn prototype_keys(
+    vm: &mut VM,
+    _args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    this_array(&callobj, "keys")?;
+    vm.set_return_value(array_iterator((*callobj.this).clone(), "keys"));
+    Ok(())
+}
+
+/// Array.prototype.values()
+fn prototype_values(
+    vm: &mut VM,
+    _args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    this_array(&callobj, "values")?;
+    vm.set_return_value(array_iterator((*callobj.this).clone(), "values"));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-createarrayiterator
+/// make an iterator of 'array' whose next() returns { value, done }. 'kind' is "keys",
+/// "values" or "entries". Holes are iterated as undefined.
+fn array_iterator(array: Value, kind: &str) -> Value {
+    let mut next = Value::default_builtin_function(array_iterator_next);
+    if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = next {
+        callobj.set_value(ITERATED_ARRAY.to_string(), array);
+        callobj.set_value(NEXT_INDEX.to_string(), Value::Number(0.0));
+        callobj.set_value(ITERATION_KIND.to_string(), Value::string(kind.to_string()));
+    }
+    make_object!(next: next)
+}
+
+/// %ArrayIteratorPrototype%.next()
+fn array_iterator_next(
+    vm: &mut VM,
+    _args: &Vec<Value>,
+    mut callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let array = match callobj.get_value(ITERATED_ARRAY)? {
+        Value::Object(_, ObjectKind::Array(array)) => array,
+        _ => {
+            vm.set_return_value(make_object!(value: Value::Undefined, done: Value::Bool(true)));
+            return Ok(());
+        }
+    };
+    let index = callobj.get_value(NEXT_INDEX)?.to_number() as usize;
+    // The length is read every time, so elements pushed while iterating are iterated. Once
+    // done, the iterator stays done.
+    if index >= array.length {
+        callobj.set_value(ITERATED_ARRAY.to_string(), Value::Undefined);
+        vm.set_return_value(make_object!(value: Value::Undefined, done: Value::Bool(true)));
+        return Ok(());
+    }
+    callobj.set_value(NEXT_INDEX.to_string(), Value::Number(index as f64 + 1.0));
+
+    let val = match callobj.get_value(ITERATION_KIND)?.to_string().as_str() {
+        "keys" => Value::Number(index as f64),
+        "values" => array.get_value(index),
+        _ => Value::array_from_elems(vec![Value::Number(index as f64), array.get_value(index)]),
+    };
+    vm.set_return_value(make_object!(value: val, done: Value::Bool(false)));
     Ok(())
 }
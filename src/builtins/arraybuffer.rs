@@ -0,0 +1,119 @@
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+thread_local!(
+    pub static ARRAY_BUFFER_PROTOTYPE: Value = {
+        make_object!(
+            slice:  Value::default_builtin_function(array_buffer_prototype_slice)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = ARRAY_BUFFER_PROTOTYPE.with(|x| x.clone());
+    // ArrayBuffer constructor
+    let obj = Value::builtin_function(
+        array_buffer,
+        None,
+        &mut make_npp!(
+            isView: Value::default_builtin_function(array_buffer_is_view)
+        ),
+        Some(prototype.clone()),
+    );
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-arraybuffer-length
+fn array_buffer(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let byte_length = to_index(args.get(0))?;
+    vm.set_return_value(Value::array_buffer(vec![0; byte_length]));
+    Ok(())
+}
+
+fn array_buffer_is_view(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(match args.get(0) {
+        Some(Value::Object(_, ObjectKind::TypedArray(_)))
+        | Some(Value::Object(_, ObjectKind::DataView(_))) => true,
+        _ => false,
+    }));
+    Ok(())
+}
+
+/// ArrayBuffer.prototype.slice(begin, end) copies the bytes into a new ArrayBuffer.
+fn array_buffer_prototype_slice(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let buffer = match *callobj.this {
+        Value::Object(_, ObjectKind::ArrayBuffer(ref buffer)) => buffer.clone(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: ArrayBuffer.prototype.slice called on incompatible receiver"
+                    .to_string(),
+            ))
+        }
+    };
+    let len = buffer.data.len();
+    let begin = relative_index(args.get(0), len, 0);
+    let end = relative_index(args.get(1), len, len);
+    let data = if begin < end {
+        buffer.data[begin..end].to_vec()
+    } else {
+        vec![]
+    };
+    vm.set_return_value(Value::array_buffer(data));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-toindex
+pub fn to_index(val: Option<&Value>) -> Result<usize, RuntimeError> {
+    let n = match val {
+        None | Some(Value::Undefined) => return Ok(0),
+        Some(val) => val.to_number(),
+    };
+    let n = if n.is_nan() { 0.0 } else { n.trunc() };
+    if n < 0.0 || n > 9007199254740991.0 {
+        return Err(RuntimeError::General(
+            "range error: invalid array buffer length or offset".to_string(),
+        ));
+    }
+    Ok(n as usize)
+}
+
+/// Convert a relative index argument (negative values count from the end) into
+/// an index clamped to 0..len. 'default' is used if the argument is undefined.
+pub fn relative_index(val: Option<&Value>, len: usize, default: usize) -> usize {
+    let n = match val {
+        None | Some(Value::Undefined) => return default,
+        Some(val) => val.to_number(),
+    };
+    let n = if n.is_nan() { 0.0 } else { n.trunc() };
+    if n < 0.0 {
+        (len as f64 + n).max(0.0) as usize
+    } else {
+        n.min(len as f64) as usize
+    }
+}
+
+/// A copy of the bytes of an ArrayBuffer, or of the bytes viewed by a typed array or a
+/// DataView. None for other values.
+pub fn buffer_source_bytes(val: &Value) -> Option<Vec<u8>> {
+    match val {
+        Value::Object(_, ObjectKind::ArrayBuffer(ref buffer)) => Some(buffer.data.clone()),
+        Value::Object(_, ObjectKind::TypedArray(ref ta)) => {
+            let end = ta.byte_offset + ta.length * ta.kind.size();
+            Some(ta.buffer.data[ta.byte_offset..end].to_vec())
+        }
+        Value::Object(_, ObjectKind::DataView(ref view)) => {
+            let end = view.byte_offset + view.byte_length;
+            Some(view.buffer.data[view.byte_offset..end].to_vec())
+        }
+        _ => None,
+    }
+}
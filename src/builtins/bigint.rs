@@ -0,0 +1,250 @@
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+thread_local!(
+    pub static BIGINT_PROTOTYPE: Value = {
+        make_object!(
+            toString:   Value::default_builtin_function(bigint_prototype_to_string),
+            valueOf:    Value::default_builtin_function(bigint_prototype_value_of)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = BIGINT_PROTOTYPE.with(|x| x.clone());
+    // BigInt function
+    let obj = Value::builtin_function(
+        bigint,
+        None,
+        &mut make_npp!(
+            asIntN:     Value::default_builtin_function(bigint_as_int_n),
+            asUintN:    Value::default_builtin_function(bigint_as_uint_n)
+        ),
+        Some(prototype.clone()),
+    );
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-bigint-constructor-number-value
+pub fn bigint(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let n = match args
+        .get(0)
+        .unwrap_or(&Value::Undefined)
+        .clone()
+        .to_primitive()
+    {
+        Value::Number(n) => {
+            if n.is_finite() && n.trunc() == n {
+                BigInt::from_f64(n).unwrap()
+            } else {
+                return Err(RuntimeError::General(format!(
+                    "range error: {} cannot be converted to a BigInt because it is not an integer",
+                    number_to_string(n)
+                )));
+            }
+        }
+        val => to_bigint(val)?,
+    };
+    vm.set_return_value(Value::bigint(n));
+    Ok(())
+}
+
+/// new BigInt(value) always throws.
+pub fn bigint_new(_: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    Err(RuntimeError::Type(
+        "type error: BigInt is not a constructor".to_string(),
+    ))
+}
+
+/// https://tc39.github.io/ecma262/#sec-tobigint
+pub fn to_bigint(val: Value) -> Result<BigInt, RuntimeError> {
+    match val.to_primitive() {
+        Value::BigInt(box n) => Ok(n),
+        Value::Bool(b) => Ok(BigInt::from_u8(b as u8).unwrap()),
+        Value::String(s) => match str_to_bigint(&s.to_string()) {
+            Some(n) => Ok(n),
+            None => Err(RuntimeError::General(format!(
+                "syntax error: cannot convert {} to a BigInt",
+                s
+            ))),
+        },
+        val => Err(RuntimeError::Type(format!(
+            "type error: cannot convert {} to a BigInt",
+            val.to_string()
+        ))),
+    }
+}
+
+/// BigInt.asUintN(bits, bigint): bigint modulo 2^bits.
+fn bigint_as_uint_n(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let (bits, n) = as_n_args(args)?;
+    vm.set_return_value(Value::bigint(modulo_pow2(&n, bits)));
+    Ok(())
+}
+
+/// BigInt.asIntN(bits, bigint): bigint modulo 2^bits, as a signed integer.
+fn bigint_as_int_n(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let (bits, n) = as_n_args(args)?;
+    let m = modulo_pow2(&n, bits);
+    vm.set_return_value(Value::bigint(if bits > 0 && m.bits() == bits {
+        m - (BigInt::one() << bits)
+    } else {
+        m
+    }));
+    Ok(())
+}
+
+fn as_n_args(args: &Vec<Value>) -> Result<(usize, BigInt), RuntimeError> {
+    let bits = args.get(0).unwrap_or(&Value::Undefined).to_number();
+    let bits = if bits.is_nan() { 0.0 } else { bits.trunc() };
+    if bits < 0.0 || bits > 9007199254740991.0 {
+        return Err(RuntimeError::General(
+            "range error: invalid number of bits".to_string(),
+        ));
+    }
+    let n = to_bigint(args.get(1).unwrap_or(&Value::Undefined).clone())?;
+    Ok((bits as usize, n))
+}
+
+/// n modulo 2^bits. The result is always non-negative.
+fn modulo_pow2(n: &BigInt, bits: usize) -> BigInt {
+    let modulus = BigInt::one() << bits;
+    let m = n % &modulus;
+    if m.is_negative() {
+        m + modulus
+    } else {
+        m
+    }
+}
+
+/// get 'this' as a BigInt for BigInt.prototype methods.
+fn this_bigint_value(callobj: &CallObjectRef) -> Result<BigInt, RuntimeError> {
+    match *callobj.this {
+        Value::BigInt(ref n) => Ok((**n).clone()),
+        _ => Err(RuntimeError::Type(
+            "type error: BigInt.prototype method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+fn bigint_prototype_to_string(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let n = this_bigint_value(&callobj)?;
+
+    let radix = match args.get(0) {
+        None | Some(Value::Undefined) => 10.0,
+        Some(val) => val.to_number().trunc(),
+    };
+    if !(2.0 <= radix && radix <= 36.0) {
+        return Err(RuntimeError::General(
+            "range error: toString() radix must be between 2 and 36".to_string(),
+        ));
+    }
+
+    vm.set_return_value(Value::string(n.to_str_radix(radix as u32)));
+    Ok(())
+}
+
+fn bigint_prototype_value_of(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let n = this_bigint_value(&callobj)?;
+    vm.set_return_value(Value::bigint(n));
+    Ok(())
+}
+
+// *** Operators on BigInt ***
+
+pub fn mixing_error() -> RuntimeError {
+    RuntimeError::Type(
+        "type error: cannot mix BigInt and other types, use explicit conversions".to_string(),
+    )
+}
+
+fn zero_division_error() -> RuntimeError {
+    RuntimeError::General("range error: division by zero".to_string())
+}
+
+pub fn div(l: &BigInt, r: &BigInt) -> Result<BigInt, RuntimeError> {
+    if r.is_zero() {
+        return Err(zero_division_error());
+    }
+    Ok(l / r)
+}
+
+pub fn rem(l: &BigInt, r: &BigInt) -> Result<BigInt, RuntimeError> {
+    if r.is_zero() {
+        return Err(zero_division_error());
+    }
+    Ok(l % r)
+}
+
+pub fn exp(base: &BigInt, exponent: &BigInt) -> Result<BigInt, RuntimeError> {
+    if exponent.is_negative() {
+        return Err(RuntimeError::General(
+            "range error: exponent must be non-negative".to_string(),
+        ));
+    }
+    match exponent.to_usize() {
+        Some(exponent) => Ok(::num_traits::pow(base.clone(), exponent)),
+        None => Err(RuntimeError::General(
+            "range error: maximum BigInt size exceeded".to_string(),
+        )),
+    }
+}
+
+/// l << r. A negative r shifts to the right.
+pub fn shl(l: &BigInt, r: &BigInt) -> Result<BigInt, RuntimeError> {
+    let amount = match r.abs().to_usize() {
+        Some(amount) => amount,
+        None => {
+            return Err(RuntimeError::General(
+                "range error: maximum BigInt size exceeded".to_string(),
+            ))
+        }
+    };
+    Ok(if r.is_negative() {
+        // Rounds toward -Infinity, unlike BigInt's Shr.
+        if l.is_negative() {
+            -((-l - BigInt::one()) >> amount) - BigInt::one()
+        } else {
+            l >> amount
+        }
+    } else {
+        l << amount
+    })
+}
+
+pub fn shr(l: &BigInt, r: &BigInt) -> Result<BigInt, RuntimeError> {
+    shl(l, &-r)
+}
+
+/// ~n, which is -n - 1 in two's complement.
+pub fn not(n: &BigInt) -> BigInt {
+    -n - BigInt::one()
+}
+
+/// Apply a bitwise operator on the two's complement representations of l and r.
+pub fn bitwise<F: Fn(u8, u8) -> u8>(l: &BigInt, r: &BigInt, op: F) -> BigInt {
+    // Sign-extend both operands to the same length, with room for the sign bit.
+    let len = l.bits().max(r.bits()) / 8 + 2;
+    let to_bytes = |n: &BigInt| {
+        let mut bytes = n.to_signed_bytes_le();
+        bytes.resize(len, if n.is_negative() { 0xff } else { 0 });
+        bytes
+    };
+    let bytes: Vec<u8> = to_bytes(l)
+        .iter()
+        .zip(to_bytes(r).iter())
+        .map(|(&l, &r)| op(l, r))
+        .collect();
+    BigInt::from_signed_bytes_le(&bytes)
+}
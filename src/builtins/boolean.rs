@@ -0,0 +1,72 @@
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+thread_local!(
+    pub static BOOLEAN_PROTOTYPE: Value = {
+        make_object!(
+            toString:   Value::default_builtin_function(boolean_prototype_to_string),
+            valueOf:    Value::default_builtin_function(boolean_prototype_value_of)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = BOOLEAN_PROTOTYPE.with(|x| x.clone());
+    // Boolean constructor
+    let obj = Value::builtin_function(boolean, None, &mut vec![], Some(prototype.clone()));
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-boolean-constructor-boolean-value
+pub fn boolean(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let b = match args.get(0) {
+        Some(val) => val.to_boolean(),
+        None => false,
+    };
+    vm.set_return_value(Value::Bool(b));
+    Ok(())
+}
+
+/// new Boolean(value)
+pub fn boolean_new(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    boolean(vm, args, callobj)?;
+    let b = vm.state.stack.pop().unwrap();
+    vm.set_return_value(Value::primitive_wrapper(b));
+    Ok(())
+}
+
+/// get 'this' as a boolean for Boolean.prototype methods.
+fn this_boolean_value(callobj: &CallObjectRef) -> Result<bool, RuntimeError> {
+    match *callobj.this {
+        Value::Bool(b) => Ok(b),
+        Value::Object(_, ObjectKind::PrimitiveWrapper(box Value::Bool(b))) => Ok(b),
+        _ => Err(RuntimeError::Type(
+            "type error: Boolean.prototype method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+fn boolean_prototype_to_string(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let b = this_boolean_value(&callobj)?;
+    vm.set_return_value(Value::string(b.to_string()));
+    Ok(())
+}
+
+fn boolean_prototype_value_of(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let b = this_boolean_value(&callobj)?;
+    vm.set_return_value(Value::Bool(b));
+    Ok(())
+}
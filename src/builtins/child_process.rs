@@ -0,0 +1,208 @@
+use builtins::error::new_error;
+use builtins::object::{option, own_enumerable_keys};
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'child_process' module, loaded by require('child_process'). Only the synchronous
+// functions are provided. The output of commands is decoded as UTF-8.
+
+pub fn init() -> Value {
+    make_object!(
+        execSync:   Value::default_builtin_function(exec_sync),
+        spawnSync:  Value::default_builtin_function(spawn_sync)
+    )
+}
+
+/// Quote 'arg' for the shell so that it is passed to the command as it is.
+fn quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// a command which runs 'command' with /bin/sh.
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+/// Run 'cmd' with the options 'cwd', 'env' and 'input', and wait for it to exit. 'env' replaces
+/// the environment variables, and 'input' is written to the standard input. Returns the process
+/// id and the output.
+fn run(cmd: &mut Command, options: Option<&Value>) -> io::Result<(u32, Output)> {
+    match option(options, "cwd") {
+        Value::Undefined | Value::Null => {}
+        cwd => {
+            cmd.current_dir(cwd.to_string());
+        }
+    }
+    match option(options, "env") {
+        env @ Value::Object(_, _) => {
+            cmd.env_clear();
+            for key in own_enumerable_keys(&env) {
+                let val = env.get_property(Value::string(key.clone()), None);
+                cmd.env(key, val.to_string());
+            }
+        }
+        _ => {}
+    }
+    let input = match option(options, "input") {
+        Value::Undefined | Value::Null => None,
+        input => Some(input.to_string()),
+    };
+
+    cmd.stdin(if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let pid = child.id();
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // Written on another thread, so that a command filling its output does not block it.
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    child.wait_with_output().map(|output| (pid, output))
+}
+
+fn output_string(output: &[u8]) -> Value {
+    Value::string(String::from_utf8_lossy(output).into_owned())
+}
+
+/// the exit status and the name of the signal which killed the command.
+fn status_and_signal(status: ExitStatus) -> (Value, Value) {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => (Value::Number(code as f64), Value::Null),
+        (None, Some(signal)) => (Value::Null, Value::string(signal_name(signal))),
+        (None, None) => (Value::Null, Value::Null),
+    }
+}
+
+fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        6 => "SIGABRT",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return format!("SIG{}", signal),
+    };
+    name.to_string()
+}
+
+/// make an Error for 'err' which happened when starting 'command'. As in Node.js, the message
+/// is like "spawnSync <command> ENOENT" and 'code' is the name of the error.
+fn spawn_error(err: &io::Error, command: &str) -> Value {
+    let code = match err.kind() {
+        io::ErrorKind::NotFound => "ENOENT".to_string(),
+        io::ErrorKind::PermissionDenied => "EACCES".to_string(),
+        _ => err.to_string(),
+    };
+    let mut error = new_error(format!("spawnSync {} {}", command, code));
+    error.set_property_with_name("code".to_string(), Value::string(code));
+    error.set_property_with_name("path".to_string(), Value::string(command.to_string()));
+    error
+}
+
+/// child_process.execSync(command[, { cwd, env, input }]). Runs 'command' with the shell, and
+/// returns its standard output. Throws an Error which has 'status', 'signal', 'stdout' and
+/// 'stderr' if the command fails.
+fn exec_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let command = match args.get(0) {
+        Some(Value::String(command)) => command.to_string(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: child_process.execSync(): command must be a string".to_string(),
+            ))
+        }
+    };
+
+    let (_, output) = run(&mut shell_command(&command), args.get(1))
+        .map_err(|err| RuntimeError::Exception(spawn_error(&err, &command)))?;
+    let stdout = output_string(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut error = new_error(format!("Command failed: {}\n{}", command, stderr));
+        let (status, signal) = status_and_signal(output.status);
+        error.set_property_with_name("status".to_string(), status);
+        error.set_property_with_name("signal".to_string(), signal);
+        error.set_property_with_name("stdout".to_string(), stdout);
+        error.set_property_with_name("stderr".to_string(), Value::string(stderr));
+        return Err(RuntimeError::Exception(error));
+    }
+
+    vm.set_return_value(stdout);
+    Ok(())
+}
+
+/// child_process.spawnSync(command[, args][, { cwd, env, input, shell }]). Runs 'command' with
+/// 'args', and returns { pid, status, signal, stdout, stderr, output }. The arguments are passed
+/// as they are, or quoted for the shell if 'shell' is true. If the command cannot be started,
+/// 'error' of the result has the Error.
+fn spawn_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let command = match args.get(0) {
+        Some(Value::String(command)) => command.to_string(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: child_process.spawnSync(): command must be a string".to_string(),
+            ))
+        }
+    };
+    let (command_args, options): (Vec<String>, _) = match args.get(1) {
+        Some(Value::Object(_, ObjectKind::Array(array))) => (
+            array.values()?.iter().map(|arg| arg.to_string()).collect(),
+            args.get(2),
+        ),
+        Some(Value::Undefined) | Some(Value::Null) => (vec![], args.get(2)),
+        options => (vec![], options),
+    };
+
+    let mut cmd = if option(options, "shell").to_boolean() {
+        let line: Vec<String> = Some(command.clone())
+            .into_iter()
+            .chain(command_args.iter().map(|arg| quote(arg)))
+            .collect();
+        shell_command(&line.join(" "))
+    } else {
+        let mut cmd = Command::new(&command);
+        cmd.args(&command_args);
+        cmd
+    };
+
+    let result = match run(&mut cmd, options) {
+        Ok((pid, output)) => {
+            let (status, signal) = status_and_signal(output.status);
+            let (stdout, stderr) = (output_string(&output.stdout), output_string(&output.stderr));
+            make_object!(
+                pid:    Value::Number(pid as f64),
+                status: status,
+                signal: signal,
+                stdout: stdout.clone(),
+                stderr: stderr.clone(),
+                output: Value::array_from_elems(vec![Value::Null, stdout, stderr])
+            )
+        }
+        Err(err) => make_object!(
+            error:  spawn_error(&err, &command),
+            pid:    Value::Number(0.0),
+            status: Value::Null,
+            signal: Value::Null,
+            stdout: Value::Null,
+            stderr: Value::Null,
+            output: Value::Null
+        ),
+    };
+    vm.set_return_value(result);
+    Ok(())
+}
@@ -0,0 +1,576 @@
+use libc;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+use std::fs;
+use std::time::Instant;
+use vm::{
+    error::RuntimeError,
+    profiler::{self, Profiler},
+    value::*,
+    vm::VM,
+};
+
+// The 'console' global. console.log() is made by the VM because the JIT knows it.
+
+/// objects nested deeper than this are shown as [Object] or [Array] by default.
+const MAX_DEPTH: usize = 2;
+
+// ANSI escape codes which start and end the styles of values, as Node.js uses.
+const STYLE_NUMBER: (u8, u8) = (33, 39);
+const STYLE_STRING: (u8, u8) = (32, 39);
+const STYLE_NULL: (u8, u8) = (1, 22);
+const STYLE_UNDEFINED: (u8, u8) = (90, 39);
+const STYLE_SPECIAL: (u8, u8) = (36, 39);
+const STYLE_DATE: (u8, u8) = (35, 39);
+
+thread_local!(
+    /// labels of console.time().
+    static TIMERS: RefCell<FxHashMap<String, Instant>> = RefCell::new(FxHashMap::default());
+    /// labels of console.count().
+    static COUNTERS: RefCell<FxHashMap<String, usize>> = RefCell::new(FxHashMap::default());
+);
+
+pub fn init(console_log: Value) -> Value {
+    make_object!(
+        log:        console_log,
+        debug:      Value::default_builtin_function(console_debug),
+        info:       Value::default_builtin_function(console_info),
+        warn:       Value::default_builtin_function(console_warn),
+        error:      Value::default_builtin_function(console_error),
+        assert:     Value::default_builtin_function(console_assert),
+        time:       Value::default_builtin_function(console_time),
+        timeLog:    Value::default_builtin_function(console_time_log),
+        timeEnd:    Value::default_builtin_function(console_time_end),
+        count:      Value::default_builtin_function(console_count),
+        countReset: Value::default_builtin_function(console_count_reset),
+        table:      Value::default_builtin_function(console_table),
+        profile:    Value::default_builtin_function(console_profile),
+        profileEnd: Value::default_builtin_function(console_profile_end)
+    )
+}
+
+/// how inspect_with() shows values.
+pub struct InspectOptions {
+    /// objects nested deeper than this are shown as [Object] or [Array]. None shows all.
+    pub depth: Option<usize>,
+    /// whether values are styled with ANSI escape codes.
+    pub colors: bool,
+}
+
+impl Default for InspectOptions {
+    fn default() -> InspectOptions {
+        InspectOptions {
+            depth: Some(MAX_DEPTH),
+            colors: false,
+        }
+    }
+}
+
+/// Show 'val' for console.log() and the REPL. Strings are quoted if 'nest' is true.
+pub fn inspect(val: &Value, nest: bool) -> String {
+    inspect_with(val, nest, &InspectOptions::default())
+}
+
+/// Show 'val' as inspect() does, with 'options'. util.inspect() uses this.
+pub fn inspect_with(val: &Value, nest: bool, options: &InspectOptions) -> String {
+    let mut inspector = Inspector {
+        seen: vec![],
+        max_depth: options.depth,
+        colors: options.colors,
+    };
+    inspector.inspect(val, nest, 0)
+}
+
+struct Inspector {
+    /// addresses of the objects being shown, to detect cycles.
+    seen: Vec<usize>,
+    max_depth: Option<usize>,
+    colors: bool,
+}
+
+impl Inspector {
+    fn inspect(&mut self, val: &Value, nest: bool, depth: usize) -> String {
+        match val {
+            Value::Empty => "empty".to_string(),
+            Value::Null => self.stylize(val.to_string(), STYLE_NULL),
+            Value::Undefined => self.stylize(val.to_string(), STYLE_UNDEFINED),
+            Value::Bool(_) | Value::Number(_) => self.stylize(val.to_string(), STYLE_NUMBER),
+            Value::BigInt(_) => self.stylize(format!("{}n", val.to_string()), STYLE_NUMBER),
+            Value::String(_) if nest => {
+                self.stylize(format!("'{}'", val.to_string()), STYLE_STRING)
+            }
+            Value::String(_) => val.to_string(),
+            Value::Object(_, ObjectKind::Function(_))
+            | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {
+                self.stylize("[Function]".to_string(), STYLE_SPECIAL)
+            }
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => format!(
+                "[{}: {}]",
+                match primitive {
+                    Value::Number(_) => "Number",
+                    Value::String(_) => "String",
+                    _ => "Boolean",
+                },
+                self.inspect(primitive, true, depth)
+            ),
+            // TODO: Date needs toString() ?
+            Value::Object(_, ObjectKind::Date(box time_val)) => {
+                self.stylize(time_val.to_rfc3339(), STYLE_DATE)
+            }
+            Value::Object(_, ObjectKind::ArrayBuffer(_))
+            | Value::Object(_, ObjectKind::TypedArray(_))
+            | Value::Object(_, ObjectKind::DataView(_))
+            | Value::Object(_, ObjectKind::WeakTable(_)) => val.format(1, false),
+            Value::Object(map, kind) => {
+                if self.seen.contains(&map.addr()) {
+                    return self.stylize("[Circular]".to_string(), STYLE_SPECIAL);
+                }
+                let is_array = match kind {
+                    ObjectKind::Array(_) | ObjectKind::Arguments(_) => true,
+                    _ => false,
+                };
+                if self.max_depth.map_or(false, |max_depth| depth > max_depth) {
+                    let name = if is_array { "[Array]" } else { "[Object]" };
+                    return self.stylize(name.to_string(), STYLE_SPECIAL);
+                }
+
+                self.seen.push(map.addr());
+                let mut items = match kind {
+                    ObjectKind::Array(ary) => self.array_items(ary, depth),
+                    ObjectKind::Arguments(callobj) => (0..callobj.arguments.len())
+                        .map(|i| match callobj.get_arguments_nth_value(i) {
+                            Ok(val) => self.inspect(&val, true, depth + 1),
+                            Err(_) => "".to_string(),
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                items.extend(self.properties(map, depth));
+                self.seen.pop();
+
+                let (open, close) = if is_array { ("[", "]") } else { ("{", "}") };
+                if items.len() == 0 {
+                    format!("{}{}", open, close)
+                } else {
+                    format!("{} {} {}", open, items.join(", "), close)
+                }
+            }
+        }
+    }
+
+    /// elements of an array. Runs of holes are shown as <N empty items>.
+    fn array_items(&mut self, ary: &ArrayValue, depth: usize) -> Vec<String> {
+        let mut items = vec![];
+        let mut next = 0;
+        for (n, elem) in ary.elements() {
+            if n > next {
+                items.push(self.stylize(empty_items(n - next), STYLE_UNDEFINED));
+            }
+            items.push(self.property(elem, depth));
+            next = n + 1;
+        }
+        if ary.length > next {
+            items.push(self.stylize(empty_items(ary.length - next), STYLE_UNDEFINED));
+        }
+        items
+    }
+
+    /// enumerable properties in the order of enumeration, shown as 'name': value.
+    fn properties(&mut self, map: &PropMapRef, depth: usize) -> Vec<String> {
+        map.iter()
+            .filter(|(_, prop)| prop.enumerable)
+            .map(|(key, prop)| format!("'{}': {}", key, self.property(prop, depth)))
+            .collect()
+    }
+
+    fn property(&mut self, prop: &Property, depth: usize) -> String {
+        match prop.accessor {
+            Some(_) => self.stylize("[Getter/Setter]".to_string(), STYLE_SPECIAL),
+            None => self.inspect(&prop.val, true, depth + 1),
+        }
+    }
+
+    /// 's' in 'style' if colors are enabled.
+    fn stylize(&self, s: String, style: (u8, u8)) -> String {
+        if self.colors {
+            format!("\x1b[{}m{}\x1b[{}m", style.0, s, style.1)
+        } else {
+            s
+        }
+    }
+}
+
+fn empty_items(n: usize) -> String {
+    format!("<{} empty item{}>", n, if n >= 2 { "s" } else { "" })
+}
+
+/// Pass the arguments of console.log() etc. to the debugger, which shows them in DevTools.
+/// 'kind' is the type of Runtime.consoleAPICalled. See vm/inspector.rs.
+pub fn notify_debugger(vm: &mut VM, kind: &str, args: &[Value]) {
+    if let Some(mut debugger) = vm.debugger.take() {
+        debugger.console(vm, kind, args);
+        vm.debugger = Some(debugger);
+    }
+}
+
+/// arguments of console.log() joined with spaces.
+fn format_args(args: &[Value]) -> String {
+    args.iter()
+        .map(|arg| inspect(arg, false))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn print_stdout(s: &str) {
+    // Use printf() as console.log() does so that the output is not reordered.
+    printf_str(s);
+    unsafe {
+        libc::putchar('\n' as libc::c_int);
+    }
+}
+
+/// Write 's' to stdout by printf(). NULs in it are written by putchar() since printf() stops
+/// at them.
+pub fn printf_str(s: &str) {
+    unsafe {
+        for (i, part) in s.split('\0').enumerate() {
+            if i > 0 {
+                libc::putchar(0);
+            }
+            libc::printf(
+                "%.*s\0".as_ptr() as RawStringPtr,
+                part.len() as libc::c_int,
+                part.as_ptr(),
+            );
+        }
+    }
+}
+
+fn print_stderr(s: &str) {
+    unsafe {
+        libc::fflush(::std::ptr::null_mut());
+    }
+    eprintln!("{}", s);
+}
+
+/// the label given as the first argument of console.time(), console.count() and
+/// console.profile().
+fn label(args: &Vec<Value>) -> String {
+    match args.get(0) {
+        Some(Value::Undefined) | None => "default".to_string(),
+        Some(label) => label.to_string(),
+    }
+}
+
+fn console_debug(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    print_stdout(&format_args(args));
+    notify_debugger(vm, "debug", args);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+fn console_info(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    print_stderr(&format_args(args));
+    notify_debugger(vm, "info", args);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+fn console_warn(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    print_stderr(&format_args(args));
+    notify_debugger(vm, "warning", args);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+fn console_error(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    print_stderr(&format_args(args));
+    notify_debugger(vm, "error", args);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.assert(value, ...message). Prints the message to stderr if 'value' is falsy.
+fn console_assert(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let ok = args.get(0).map_or(false, |val| val.to_boolean());
+    if !ok {
+        print_stderr(&match args.len() {
+            0 | 1 => "Assertion failed".to_string(),
+            _ => format!("Assertion failed: {}", format_args(&args[1..])),
+        });
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.time([label])
+fn console_time(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let label = label(args);
+    TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        if timers.contains_key(&label) {
+            print_stderr(&format!("Warning: Label '{}' already exists", label));
+        } else {
+            timers.insert(label, Instant::now());
+        }
+    });
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// print the time elapsed since console.time('label'), and stop the timer if 'end' is true.
+fn print_timer(args: &Vec<Value>, end: bool) {
+    let label = label(args);
+    let start = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        if end {
+            timers.remove(&label)
+        } else {
+            timers.get(&label).cloned()
+        }
+    });
+    match start {
+        Some(start) => {
+            let elapsed = start.elapsed();
+            let ms = elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1e6;
+            let mut line = format!("{}: {:.3}ms", label, ms);
+            if args.len() > 1 {
+                line = format!("{} {}", line, format_args(&args[1..]));
+            }
+            print_stdout(&line);
+        }
+        None => print_stderr(&format!("Warning: No such label '{}'", label)),
+    }
+}
+
+/// console.timeLog([label][, ...data])
+fn console_time_log(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    print_timer(args, false);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.timeEnd([label])
+fn console_time_end(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let label = args.iter().take(1).cloned().collect::<Vec<Value>>();
+    print_timer(&label, true);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.count([label])
+fn console_count(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let label = label(args);
+    let count = COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        let count = counters.entry(label.clone()).or_insert(0);
+        *count += 1;
+        *count
+    });
+    print_stdout(&format!("{}: {}", label, count));
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.countReset([label])
+fn console_count_reset(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let label = label(args);
+    COUNTERS.with(|counters| {
+        if counters.borrow_mut().remove(&label).is_none() {
+            print_stderr(&format!("Warning: Count for '{}' does not exist", label));
+        }
+    });
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.profile([label]). Starts sampling the CPU. See vm/profiler.rs.
+fn console_profile(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let label = label(args);
+    let profiler = vm
+        .profiler
+        .get_or_insert_with(|| Profiler::new(false, profiler::SAMPLING_INTERVAL));
+    if !profiler.start_session(label.clone()) {
+        print_stderr(&format!("Warning: Profile '{}' already exists", label));
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.profileEnd([label]). Writes the samples taken since console.profile(label) to
+/// '<label>.folded' in the collapsed format of flamegraph tools.
+fn console_profile_end(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let label = label(args);
+    match vm.profiler.as_mut().and_then(|p| p.end_session(&label)) {
+        Some(samples) => {
+            let file_name = format!("{}.folded", label);
+            match fs::write(&file_name, profiler::collapsed(&samples)) {
+                Ok(()) => print_stderr(&format!("Profile '{}' written to {}", label, file_name)),
+                Err(e) => print_stderr(&format!("Couldn't write '{}': {}", file_name, e)),
+            }
+        }
+        None => print_stderr(&format!("Warning: No such profile '{}'", label)),
+    }
+    if vm.profiler.as_ref().map_or(false, |p| p.is_idle()) {
+        vm.profiler = None;
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// console.table(data). Rows are the elements (or properties) of 'data', and columns are the
+/// properties of the rows. Rows which are not objects are shown in the 'Values' column.
+fn console_table(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    match args.get(0) {
+        Some(data @ Value::Object(_, ObjectKind::Ordinary))
+        | Some(data @ Value::Object(_, ObjectKind::Array(_))) => print_stdout(&table(data)),
+        _ => print_stdout(&format_args(args)),
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+fn table(data: &Value) -> String {
+    fn cells(val: &Value) -> Vec<(String, Value)> {
+        match val {
+            Value::Object(_, ObjectKind::Array(ary)) => ary
+                .iter()
+                .enumerate()
+                .filter_map(|(i, elem)| elem.map(|elem| (i.to_string(), elem.val.clone())))
+                .collect(),
+            Value::Object(map, ObjectKind::Ordinary) => map
+                .iter()
+                .filter(|(_, prop)| prop.enumerable)
+                .map(|(key, prop)| (key.clone(), prop.val.clone()))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    let mut columns: Vec<String> = vec![];
+    let mut has_values = false;
+    let rows = cells(data);
+    for (_, row) in &rows {
+        match row {
+            Value::Object(_, ObjectKind::Array(_)) | Value::Object(_, ObjectKind::Ordinary) => {
+                for (key, _) in cells(row) {
+                    if !columns.contains(&key) {
+                        columns.push(key);
+                    }
+                }
+            }
+            _ => has_values = true,
+        }
+    }
+
+    let mut header = vec!["(index)".to_string()];
+    header.extend(columns.iter().cloned());
+    if has_values {
+        header.push("Values".to_string());
+    }
+
+    let mut lines = vec![header];
+    for (index, row) in &rows {
+        let mut line = vec![index.clone()];
+        match row {
+            Value::Object(_, ObjectKind::Array(_)) | Value::Object(_, ObjectKind::Ordinary) => {
+                let row_cells = cells(row);
+                for column in &columns {
+                    line.push(
+                        row_cells
+                            .iter()
+                            .find(|(key, _)| key == column)
+                            .map_or("".to_string(), |(_, val)| inspect(val, true)),
+                    );
+                }
+                if has_values {
+                    line.push("".to_string());
+                }
+            }
+            val => {
+                line.extend(columns.iter().map(|_| "".to_string()));
+                line.push(inspect(val, true));
+            }
+        }
+        lines.push(line);
+    }
+
+    let widths: Vec<usize> = (0..lines[0].len())
+        .map(|i| {
+            lines
+                .iter()
+                .map(|line| line[i].chars().count() + 2)
+                .max()
+                .unwrap()
+        })
+        .collect();
+    let border = |left: &str, middle: &str, right: &str| {
+        let bars: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+        format!("{}{}{}", left, bars.join(middle), right)
+    };
+    let row = |line: &Vec<String>| {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| {
+                let space = width - cell.chars().count();
+                format!(
+                    "{}{}{}",
+                    " ".repeat(space / 2),
+                    cell,
+                    " ".repeat(space - space / 2)
+                )
+            })
+            .collect();
+        format!("│{}│", cells.join("│"))
+    };
+
+    let mut output = vec![border("┌", "┬", "┐"), row(&lines[0]), border("├", "┼", "┤")];
+    output.extend(lines[1..].iter().map(|line| row(line)));
+    output.push(border("└", "┴", "┘"));
+    output.join("\n")
+}
+
+#[test]
+fn test() {
+    let mut obj = make_object!(
+        a:  Value::Number(1.0),
+        b:  Value::array_from_elems(vec![
+                Value::string("x".to_string()),
+                Value::Empty,
+                Value::Empty,
+                make_object!(c: make_object!(d: Value::Number(2.0)))
+            ])
+    );
+    assert_eq!(
+        inspect(&obj, false),
+        "{ 'a': 1, 'b': [ 'x', <2 empty items>, { 'c': [Object] } ] }"
+    );
+    let cycle = obj.clone();
+    obj.set_property_with_name("self".to_string(), cycle);
+    assert!(inspect(&obj, false).ends_with("'self': [Circular] }"));
+    assert_eq!(inspect(&Value::string("s".to_string()), false), "s");
+    assert_eq!(inspect(&Value::array_from_elems(vec![]), true), "[]");
+
+    let data = Value::array_from_elems(vec![
+        make_object!(a: Value::Number(1.0)),
+        Value::string("x".to_string()),
+    ]);
+    assert_eq!(
+        table(&data),
+        "┌─────────┬───┬────────┐\n\
+         │ (index) │ a │ Values │\n\
+         ├─────────┼───┼────────┤\n\
+         │    0    │ 1 │        │\n\
+         │    1    │   │  'x'   │\n\
+         └─────────┴───┴────────┘"
+    );
+}
@@ -0,0 +1,401 @@
+use builtins::arraybuffer::buffer_source_bytes;
+use builtins::error::new_error;
+use builtins::text_encoding::base64_encode;
+use builtins::typedarray::TypedArrayKind;
+use std::fs::File;
+use std::io::{self, Read};
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'crypto' global, which is also loaded by require('crypto'). Random values come from
+// /dev/urandom.
+
+/// internal properties of a Hash made by createHash().
+const ALGORITHM: &str = "[[Algorithm]]";
+/// the data given to update(), or undefined after digest().
+const DATA: &str = "[[Data]]";
+
+/// the most bytes getRandomValues() fills at once.
+const MAX_RANDOM_BYTES: usize = 65536;
+
+thread_local!(
+    pub static HASH_PROTOTYPE: Value = {
+        make_object!(
+            update:     Value::default_builtin_function(hash_prototype_update),
+            digest:     Value::default_builtin_function(hash_prototype_digest)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    make_object!(
+        randomUUID:         Value::default_builtin_function(random_uuid),
+        getRandomValues:    Value::default_builtin_function(get_random_values),
+        createHash:         Value::default_builtin_function(create_hash)
+    )
+}
+
+/// Fill 'buf' with cryptographically secure random bytes.
+fn random_bytes(buf: &mut [u8]) -> Result<(), RuntimeError> {
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(buf))
+        .map_err(|err: io::Error| {
+            RuntimeError::Exception(new_error(format!("Error: /dev/urandom: {}", err)))
+        })
+}
+
+/// crypto.randomUUID() returns a random UUID (version 4). e.g. '1b9d6bcd-bbfd-4b2d-9b5d-ab8dfbbd4bed'
+fn random_uuid(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut bytes = [0u8; 16];
+    random_bytes(&mut bytes)?;
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex = to_hex(&bytes);
+    let uuid = format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    );
+    vm.set_return_value(Value::string(uuid));
+    Ok(())
+}
+
+/// crypto.getRandomValues(typedArray) fills an integer typed array with random values, and
+/// returns it.
+fn get_random_values(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let array = args.get(0).cloned().unwrap_or(Value::Undefined);
+    match array {
+        Value::Object(_, ObjectKind::TypedArray(ref ta))
+            if ta.kind != TypedArrayKind::Float32 && ta.kind != TypedArrayKind::Float64 =>
+        {
+            let (start, length) = (ta.byte_offset, ta.byte_length());
+            if length > MAX_RANDOM_BYTES {
+                return Err(RuntimeError::Exception(new_error(format!(
+                    "QuotaExceededError: The ArrayBufferView's byte length ({}) exceeds the \
+                     number of bytes of entropy available via this API ({})",
+                    length, MAX_RANDOM_BYTES
+                ))));
+            }
+            let mut buffer = ta.buffer.clone();
+            random_bytes(&mut buffer.data[start..start + length])?;
+        }
+        _ => {
+            return Err(RuntimeError::Exception(new_error(
+                "TypeMismatchError: The data argument must be an integer-type TypedArray"
+                    .to_string(),
+            )))
+        }
+    }
+    vm.set_return_value(array);
+    Ok(())
+}
+
+fn internal(hash: &Value, name: &str) -> Value {
+    hash.get_property(Value::string(name.to_string()), None)
+}
+
+fn set_internal(hash: &Value, name: &str, val: Value) {
+    if let Value::Object(map, _) = hash {
+        map.clone().insert(
+            name.to_string(),
+            Property {
+                val: val,
+                writable: true,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+}
+
+/// the function computing the digest by 'algorithm', as named in Node.js.
+fn hash_function(algorithm: &str) -> Option<fn(&[u8]) -> Vec<u8>> {
+    match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => Some(md5),
+        "sha1" => Some(sha1),
+        "sha256" => Some(sha256),
+        _ => None,
+    }
+}
+
+/// crypto.createHash(algorithm) makes a Hash, where 'algorithm' is 'md5', 'sha1' or 'sha256'.
+fn create_hash(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let algorithm = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    if hash_function(&algorithm).is_none() {
+        return Err(RuntimeError::Exception(new_error(
+            "Error: Digest method not supported".to_string(),
+        )));
+    }
+    let hash = make_object!(
+        __proto__:  HASH_PROTOTYPE.with(|x| x.clone())
+    );
+    set_internal(&hash, ALGORITHM, Value::string(algorithm));
+    set_internal(&hash, DATA, Value::array_buffer(vec![]));
+    vm.set_return_value(hash);
+    Ok(())
+}
+
+/// Hash.prototype.update(data) adds 'data', a string as UTF-8 or the bytes of a typed array,
+/// ArrayBuffer or DataView. Returns the Hash.
+fn hash_prototype_update(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let hash = (*callobj.this).clone();
+    let bytes = match args.get(0) {
+        Some(Value::String(s)) => s.to_string().into_bytes(),
+        Some(data) => buffer_source_bytes(data).ok_or_else(|| {
+            RuntimeError::Type(
+                "type error: Hash.update(): data must be a string or a typed array".to_string(),
+            )
+        })?,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: Hash.update(): data must be a string or a typed array".to_string(),
+            ))
+        }
+    };
+    match internal(&hash, DATA) {
+        Value::Object(_, ObjectKind::ArrayBuffer(mut buffer)) => {
+            buffer.data.extend_from_slice(&bytes)
+        }
+        _ => {
+            return Err(RuntimeError::Exception(new_error(
+                "Error: Digest already called".to_string(),
+            )))
+        }
+    }
+    vm.set_return_value(hash);
+    Ok(())
+}
+
+/// Hash.prototype.digest([encoding]) returns the digest of the data as a string of 'encoding',
+/// 'hex' or 'base64', or in a Uint8Array if no encoding is given. The Hash cannot be used after
+/// this.
+fn hash_prototype_digest(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let hash = &*callobj.this;
+    let data = match internal(hash, DATA) {
+        Value::Object(_, ObjectKind::ArrayBuffer(buffer)) => buffer.data.clone(),
+        _ => {
+            return Err(RuntimeError::Exception(new_error(
+                "Error: Digest already called".to_string(),
+            )))
+        }
+    };
+    let algorithm = internal(hash, ALGORITHM).to_string();
+    let digest = match hash_function(&algorithm) {
+        Some(hash_function) => hash_function(&data),
+        None => unreachable!(),
+    };
+    set_internal(hash, DATA, Value::Undefined);
+
+    let val = match args.get(0) {
+        None | Some(Value::Undefined) => {
+            let length = digest.len();
+            let buffer = Value::array_buffer(digest);
+            Value::typed_array(TypedArrayKind::Uint8, buffer, 0, length)
+        }
+        Some(encoding) => match encoding.to_string().as_str() {
+            "hex" => Value::string(to_hex(&digest)),
+            "base64" => Value::string(base64_encode(&digest)),
+            encoding => {
+                return Err(RuntimeError::Type(format!(
+                    "type error: Hash.digest(): unsupported encoding '{}'",
+                    encoding
+                )))
+            }
+        },
+    };
+    vm.set_return_value(val);
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Pad 'data' for MD5 and SHA: 0x80, zeros, and the length in bits as 64 bits.
+fn pad(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    if big_endian {
+        padded.extend_from_slice(&bits.to_be_bytes());
+    } else {
+        padded.extend_from_slice(&bits.to_le_bytes());
+    }
+    padded
+}
+
+/// MD5 (RFC 1321)
+fn md5(data: &[u8]) -> Vec<u8> {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in pad(data, false).chunks(64) {
+        let m: Vec<u32> = block
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let (mut a, mut b, mut c, mut d) = (h[0], h[1], h[2], h[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    h.iter().flat_map(|h| h.to_le_bytes().to_vec()).collect()
+}
+
+/// SHA-1 (FIPS 180-4)
+fn sha1(data: &[u8]) -> Vec<u8> {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    for block in pad(data, true).chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    h.iter().flat_map(|h| h.to_be_bytes().to_vec()).collect()
+}
+
+/// the round constants of SHA-256.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 (FIPS 180-4)
+fn sha256(data: &[u8]) -> Vec<u8> {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    for block in pad(data, true).chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let mut v = h;
+        for i in 0..64 {
+            let (a, b, c, d, e, f, g) = (v[0], v[1], v[2], v[3], v[4], v[5], v[6]);
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+            v = [
+                temp1.wrapping_add(temp2),
+                a,
+                b,
+                c,
+                d.wrapping_add(temp1),
+                e,
+                f,
+                g,
+            ];
+        }
+        for (h, v) in h.iter_mut().zip(&v) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    h.iter().flat_map(|h| h.to_be_bytes().to_vec()).collect()
+}
+
+#[test]
+fn test_hash() {
+    let digests = |data: &str| {
+        (
+            to_hex(&md5(data.as_bytes())),
+            to_hex(&sha1(data.as_bytes())),
+            to_hex(&sha256(data.as_bytes())),
+        )
+    };
+    assert_eq!(
+        digests(""),
+        (
+            "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+        )
+    );
+    // longer than a block
+    let data = "a".repeat(1000);
+    assert_eq!(
+        digests(&data),
+        (
+            "cabe45dcc9ae5b66ba86600cca6b8ba8".to_string(),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba".to_string(),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3".to_string()
+        )
+    );
+}
@@ -0,0 +1,156 @@
+use builtins::arraybuffer::to_index;
+use builtins::typedarray::TypedArrayKind;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+macro_rules! data_view_accessors {
+    ($($getter:ident, $setter:ident, $kind:expr);*) => {
+        $(
+            fn $getter(
+                vm: &mut VM,
+                args: &Vec<Value>,
+                callobj: CallObjectRef,
+            ) -> Result<(), RuntimeError> {
+                let n = get_view_value(&callobj, args, $kind)?;
+                vm.set_return_value(Value::Number(n));
+                Ok(())
+            }
+
+            fn $setter(
+                vm: &mut VM,
+                args: &Vec<Value>,
+                callobj: CallObjectRef,
+            ) -> Result<(), RuntimeError> {
+                set_view_value(&callobj, args, $kind)?;
+                vm.set_return_value(Value::Undefined);
+                Ok(())
+            }
+        )*
+    };
+}
+
+data_view_accessors!(
+    data_view_prototype_get_int8,       data_view_prototype_set_int8,       TypedArrayKind::Int8;
+    data_view_prototype_get_uint8,      data_view_prototype_set_uint8,      TypedArrayKind::Uint8;
+    data_view_prototype_get_int16,      data_view_prototype_set_int16,      TypedArrayKind::Int16;
+    data_view_prototype_get_uint16,     data_view_prototype_set_uint16,     TypedArrayKind::Uint16;
+    data_view_prototype_get_int32,      data_view_prototype_set_int32,      TypedArrayKind::Int32;
+    data_view_prototype_get_uint32,     data_view_prototype_set_uint32,     TypedArrayKind::Uint32;
+    data_view_prototype_get_float32,    data_view_prototype_set_float32,    TypedArrayKind::Float32;
+    data_view_prototype_get_float64,    data_view_prototype_set_float64,    TypedArrayKind::Float64
+);
+
+thread_local!(
+    pub static DATA_VIEW_PROTOTYPE: Value = {
+        make_object!(
+            getInt8:    Value::default_builtin_function(data_view_prototype_get_int8),
+            setInt8:    Value::default_builtin_function(data_view_prototype_set_int8),
+            getUint8:   Value::default_builtin_function(data_view_prototype_get_uint8),
+            setUint8:   Value::default_builtin_function(data_view_prototype_set_uint8),
+            getInt16:   Value::default_builtin_function(data_view_prototype_get_int16),
+            setInt16:   Value::default_builtin_function(data_view_prototype_set_int16),
+            getUint16:  Value::default_builtin_function(data_view_prototype_get_uint16),
+            setUint16:  Value::default_builtin_function(data_view_prototype_set_uint16),
+            getInt32:   Value::default_builtin_function(data_view_prototype_get_int32),
+            setInt32:   Value::default_builtin_function(data_view_prototype_set_int32),
+            getUint32:  Value::default_builtin_function(data_view_prototype_get_uint32),
+            setUint32:  Value::default_builtin_function(data_view_prototype_set_uint32),
+            getFloat32: Value::default_builtin_function(data_view_prototype_get_float32),
+            setFloat32: Value::default_builtin_function(data_view_prototype_set_float32),
+            getFloat64: Value::default_builtin_function(data_view_prototype_get_float64),
+            setFloat64: Value::default_builtin_function(data_view_prototype_set_float64)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = DATA_VIEW_PROTOTYPE.with(|x| x.clone());
+    // DataView constructor
+    let obj = Value::builtin_function(data_view, None, &mut make_npp!(), Some(prototype.clone()));
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-dataview-buffer-byteoffset-bytelength
+fn data_view(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let buffer_len = match args.get(0) {
+        Some(Value::Object(_, ObjectKind::ArrayBuffer(buffer))) => buffer.data.len(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: first argument to DataView constructor must be an ArrayBuffer"
+                    .to_string(),
+            ))
+        }
+    };
+    let byte_offset = to_index(args.get(1))?;
+    if byte_offset > buffer_len {
+        return Err(RuntimeError::General(
+            "range error: start offset is outside the bounds of the buffer".to_string(),
+        ));
+    }
+    let byte_length = match args.get(2) {
+        None | Some(Value::Undefined) => buffer_len - byte_offset,
+        byte_length => {
+            let byte_length = to_index(byte_length)?;
+            if byte_offset + byte_length > buffer_len {
+                return Err(RuntimeError::General(
+                    "range error: invalid DataView length".to_string(),
+                ));
+            }
+            byte_length
+        }
+    };
+    vm.set_return_value(Value::data_view(args[0].clone(), byte_offset, byte_length));
+    Ok(())
+}
+
+fn this_data_view(callobj: &CallObjectRef) -> Result<DataView, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::DataView(ref view)) => Ok((**view).clone()),
+        _ => Err(RuntimeError::Type(
+            "type error: DataView.prototype method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+/// Compute the index in the buffer of the element at the byte offset 'request_index'.
+fn view_index(
+    view: &DataView,
+    request_index: Option<&Value>,
+    kind: TypedArrayKind,
+) -> Result<usize, RuntimeError> {
+    let index = to_index(request_index)?;
+    if index + kind.size() > view.byte_length {
+        return Err(RuntimeError::General(
+            "range error: offset is outside the bounds of the DataView".to_string(),
+        ));
+    }
+    Ok(view.byte_offset + index)
+}
+
+/// https://tc39.github.io/ecma262/#sec-getviewvalue
+/// Values are read in big endian unless 'littleEndian' is true.
+fn get_view_value(
+    callobj: &CallObjectRef,
+    args: &Vec<Value>,
+    kind: TypedArrayKind,
+) -> Result<f64, RuntimeError> {
+    let view = this_data_view(callobj)?;
+    let index = view_index(&view, args.get(0), kind)?;
+    let little_endian = args.get(1).map_or(false, |val| val.to_boolean());
+    Ok(kind.read(&view.buffer.data[index..], little_endian))
+}
+
+/// https://tc39.github.io/ecma262/#sec-setviewvalue
+fn set_view_value(
+    callobj: &CallObjectRef,
+    args: &Vec<Value>,
+    kind: TypedArrayKind,
+) -> Result<(), RuntimeError> {
+    let mut view = this_data_view(callobj)?;
+    let index = view_index(&view, args.get(0), kind)?;
+    let n = args.get(1).unwrap_or(&Value::Undefined).to_number();
+    let little_endian = args.get(2).map_or(false, |val| val.to_boolean());
+    kind.write(&mut view.buffer.data[index..], n, little_endian);
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use chrono::Utc;
+use builtins::intl::{self, DateTimeDefaults};
+use chrono::{DateTime, Utc};
 use vm::value::*;
 use vm::{error::RuntimeError, vm::VM};
 
@@ -6,6 +7,9 @@ thread_local!(
     pub static DATE_PROTOTYPE: Value = {
         make_object!(
             // TODO: Add methods
+            toLocaleString:     Value::default_builtin_function(date_prototype_to_locale_string),
+            toLocaleDateString: Value::default_builtin_function(date_prototype_to_locale_date_string),
+            toLocaleTimeString: Value::default_builtin_function(date_prototype_to_locale_time_string)
         )
     };
 
@@ -49,3 +53,71 @@ pub fn date_now(vm: &mut VM, _args: &Vec<Value>, _: CallObjectRef) -> Result<(),
     vm.state.stack.push(Value::Number(now_millis as f64));
     Ok(())
 }
+
+fn this_time(callobj: &CallObjectRef) -> Result<DateTime<Utc>, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::Date(box time)) => Ok(time),
+        _ => Err(RuntimeError::Type(
+            "type error: this is not a Date object.".to_string(),
+        )),
+    }
+}
+
+/// Format the date 'this' by Intl.DateTimeFormat.
+fn to_locale_string(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+    required: DateTimeDefaults,
+    defaults: DateTimeDefaults,
+) -> Result<(), RuntimeError> {
+    let time = this_time(&callobj)?;
+    let s = intl::format_date_time(args.get(0), args.get(1), required, defaults, &time)?;
+    vm.set_return_value(Value::string(s));
+    Ok(())
+}
+
+/// Date.prototype.toLocaleString([locales[, options]])
+fn date_prototype_to_locale_string(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    to_locale_string(
+        vm,
+        args,
+        callobj,
+        DateTimeDefaults::All,
+        DateTimeDefaults::All,
+    )
+}
+
+/// Date.prototype.toLocaleDateString([locales[, options]])
+fn date_prototype_to_locale_date_string(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    to_locale_string(
+        vm,
+        args,
+        callobj,
+        DateTimeDefaults::Date,
+        DateTimeDefaults::Date,
+    )
+}
+
+/// Date.prototype.toLocaleTimeString([locales[, options]])
+fn date_prototype_to_locale_time_string(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    to_locale_string(
+        vm,
+        args,
+        callobj,
+        DateTimeDefaults::Time,
+        DateTimeDefaults::Time,
+    )
+}
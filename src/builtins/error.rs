@@ -1,6 +1,9 @@
-use vm::value::{CallObjectRef, Property, Value};
+use vm::value::{CallObjectRef, FuncId, ObjectKind, Property, Value};
 use vm::{error::RuntimeError, vm::VM};
 
+/// the initial value of Error.stackTraceLimit.
+const STACK_TRACE_LIMIT: f64 = 10.0;
+
 thread_local! {
     pub static ERROR_PROTOTYPE: Value = {
         make_object!(
@@ -12,24 +15,163 @@ thread_local! {
 
 pub fn init() -> Value {
     let mut prototype = ERROR_PROTOTYPE.with(|x| x.clone());
-    let obj = Value::builtin_function(error_new, None, &mut vec![], Some(prototype.clone()));
+    let obj = Value::builtin_function(
+        error_new,
+        None,
+        &mut make_npp!(
+            captureStackTrace:  Value::default_builtin_function(capture_stack_trace),
+            stackTraceLimit:    Value::Number(STACK_TRACE_LIMIT)
+        ),
+        Some(prototype.clone()),
+    );
     prototype.set_constructor(obj.clone());
 
     obj
 }
 
+/// make a new Error object. e.g. to throw it from builtins as RuntimeError::Exception.
+pub fn new_error(message: String) -> Value {
+    let prototype = ERROR_PROTOTYPE.with(|x| x.clone());
+    make_object!(
+        message:    Value::string(message),
+        name:       Value::string("Error".to_string()),
+        __proto__:  prototype
+    )
+}
+
 fn error_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
     let message = match args.len() {
         0 => "".to_string(),
         _ => args[0].to_string(),
     };
-    let prototype = ERROR_PROTOTYPE.with(|x| x.clone());
-    let obj = make_object!(
-        message:    Value::string(message),
-        name:       Value::string("Error".to_string()),
-        __proto__:  prototype
-    );
-    vm.set_return_value(obj);
+    let mut error = new_error(message);
+    set_stack(vm, &mut error, None);
+    vm.set_return_value(error);
 
     Ok(())
 }
+
+/// Error.captureStackTrace(obj[, constructorOpt]) sets the 'stack' of 'obj' to the current
+/// stack trace. The frames above the latest call of 'constructorOpt' and the call itself are
+/// left out, so that the trace starts where an error class was constructed.
+fn capture_stack_trace(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut obj = match args.get(0) {
+        Some(obj @ Value::Object(_, _)) => obj.clone(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Error.captureStackTrace: the target is not an object".to_string(),
+            ))
+        }
+    };
+    let omit_to = match args.get(1) {
+        Some(Value::Object(_, ObjectKind::Function(box (ref info, _)))) => Some(info.id),
+        _ => None,
+    };
+    set_stack(vm, &mut obj, omit_to);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// Set the non-enumerable 'stack' of 'error' to its name and message followed by the frames of
+/// the stack trace, up to Error.stackTraceLimit of them. Frames are known only by the source map.
+fn set_stack(vm: &VM, error: &mut Value, omit_to: Option<FuncId>) {
+    let name = match error.get_property(Value::string("name".to_string()), None) {
+        Value::Undefined => "Error".to_string(),
+        name => name.to_string(),
+    };
+    let mut stack = match error.get_property(Value::string("message".to_string()), None) {
+        Value::Undefined => name,
+        ref message if message.to_string() == "" => name,
+        message => format!("{}: {}", name, message.to_string()),
+    };
+
+    let file = match vm.source_map {
+        Some(ref source_map) => source_map.file.as_str(),
+        None => "",
+    };
+    let trace = vm.builtin_stack_trace(omit_to);
+    for frame in trace.iter().take(stack_trace_limit()) {
+        if frame.name.is_empty() {
+            stack += &format!("\n    at {}:{}:{}", file, frame.line, frame.column);
+        } else {
+            stack += &format!(
+                "\n    at {} ({}:{}:{})",
+                frame.name, file, frame.line, frame.column
+            );
+        }
+    }
+
+    if let Value::Object(map, _) = error {
+        map.insert(
+            "stack".to_string(),
+            Property {
+                enumerable: false,
+                ..Property::new(Value::string(stack))
+            },
+        );
+    }
+}
+
+/// Error.stackTraceLimit as a number of frames. Values other than positive numbers are 0.
+fn stack_trace_limit() -> usize {
+    let error = ERROR_PROTOTYPE
+        .with(|prototype| prototype.get_property(Value::string("constructor".to_string()), None));
+    match error.get_property(Value::string("stackTraceLimit".to_string()), None) {
+        Value::Number(limit) if limit > 0.0 => limit as usize,
+        _ => 0,
+    }
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+    use sourcemap::SourceMap;
+    use std::mem;
+
+    let code = "function MyError(message) {
+  this.message = message
+  this.name = 'MyError'
+  Error.captureStackTrace(this, MyError)
+}
+function thrower() {
+  return new MyError('bad')
+}
+var custom = thrower().stack
+function inner() {
+  return new Error('y').stack
+}
+var plain = inner()
+Error.stackTraceLimit = 1
+var limited = inner()
+Error.stackTraceLimit = 0
+var none = new Error('z').stack
+";
+    let mut parser = Parser::new(code.to_string());
+    let node = parser.parse_all().unwrap();
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    vm.codegen.record_positions = true;
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    vm.codegen.record_positions = false;
+    let positions = mem::replace(&mut vm.codegen.function_positions, vec![]);
+    vm.source_map = Some(SourceMap::new("a.js", &positions, &parser.lexer));
+    vm.run(iseq).unwrap();
+    let global = |name: &str| vm.state.scope[0].get_value(name).unwrap().to_string();
+
+    // The frame of MyError, which captured the trace, is left out.
+    let custom = global("custom");
+    assert!(custom.starts_with("MyError: bad\n    at thrower (a.js:7:"));
+    assert!(custom.contains("\n    at a.js:9:"));
+    assert_eq!(custom.lines().count(), 3);
+
+    let plain = global("plain");
+    assert!(plain.starts_with("Error: y\n    at inner (a.js:11:"));
+    assert!(plain.contains("\n    at a.js:13:"));
+    assert_eq!(global("limited").lines().count(), 2);
+    assert_eq!(global("none"), "Error: z");
+}
@@ -0,0 +1,97 @@
+use builtins::error::new_error;
+use node::Node;
+use parser;
+use std::rc::Rc;
+use vm::{error::RuntimeError, value::*, vm::VM};
+use vm_codegen;
+
+// eval(). The code is compiled when eval() is called, by the compiler of the VM. A direct eval,
+// a call written as 'eval(code)', runs the code in the scope of the caller (see
+// VMInst::CALL_EVAL), and other calls of eval run it in the global scope.
+
+/// eval(code) called indirectly. e.g. 'var e = eval; e(code)'
+pub fn eval(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let global = vm.state.scope[0].clone();
+    vm.state.scope.push(global);
+    let res = run(vm, args);
+    vm.state.scope.pop();
+    res
+}
+
+/// eval(code) called directly.
+pub fn direct_eval(vm: &mut VM, args: &Vec<Value>) -> Result<(), RuntimeError> {
+    run(vm, args)
+}
+
+pub fn is_eval(val: &Value) -> bool {
+    match val {
+        Value::Object(_, ObjectKind::BuiltinFunction(box (ref info, _))) => {
+            info.func as usize == eval as usize
+        }
+        _ => false,
+    }
+}
+
+/// Run the code in args[0] in the current scope. A value other than a string is returned as it
+/// is.
+fn run(vm: &mut VM, args: &Vec<Value>) -> Result<(), RuntimeError> {
+    let code = match args.get(0) {
+        Some(Value::String(code)) => code.to_string(),
+        Some(val) => {
+            vm.set_return_value(val.clone());
+            return Ok(());
+        }
+        None => {
+            vm.set_return_value(Value::Undefined);
+            return Ok(());
+        }
+    };
+
+    let node = parse(code)?;
+    let val = run_script(vm, &node)?;
+    vm.set_return_value(val);
+    Ok(())
+}
+
+/// Parse 'code' as a script. A syntax error is thrown as a SyntaxError.
+pub fn parse(code: String) -> Result<Node, RuntimeError> {
+    let mut parser = parser::Parser::new(code);
+    match parser.parse_all() {
+        Ok(node) => Ok(node),
+        Err(err) => {
+            let (_, msg) = parser.error_position_and_message(err);
+            Err(syntax_error(msg))
+        }
+    }
+}
+
+/// Compile the script and run it in the current scope. Returns the value of its last expression
+/// statement.
+pub fn run_script(vm: &mut VM, node: &Node) -> Result<Value, RuntimeError> {
+    let mut iseq = vec![];
+    let global_scope = vm.codegen.global_scope;
+    vm.codegen.global_scope = vm.state.scope.last() == vm.state.scope.first();
+    let res = vm.codegen.compile(node, &mut iseq, true);
+    vm.codegen.global_scope = global_scope;
+    match res {
+        Ok(()) => {}
+        Err(vm_codegen::Error::General { msg, .. })
+        | Err(vm_codegen::Error::Unimplemented { msg, .. }) => return Err(syntax_error(msg)),
+    }
+
+    let sp = vm.state.stack.len();
+    vm.do_run(Rc::new(iseq))?;
+    let val = if vm.state.stack.len() > sp {
+        vm.state.stack.pop().unwrap()
+    } else {
+        Value::Undefined
+    };
+    Ok(match val {
+        Value::Empty => Value::Undefined,
+        val => val,
+    })
+}
+
+pub fn syntax_error(msg: String) -> RuntimeError {
+    RuntimeError::Exception(new_error(format!("SyntaxError: {}", msg)))
+}
@@ -0,0 +1,195 @@
+use builtins::error::new_error;
+use builtins::{json, promise};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use ureq;
+use vm::{error::RuntimeError, task::Task, value::*, vm::VM};
+
+// fetch(). The request is sent by a blocking HTTP client on another thread, and the event loop
+// settles the promise when the response has been received (see Task::Fetch).
+
+thread_local!(
+    pub static RESPONSE_PROTOTYPE: Value = {
+        make_object!(
+            text:   Value::default_builtin_function(response_text),
+            json:   Value::default_builtin_function(response_json)
+        )
+    };
+);
+
+/// A response received by the HTTP client thread.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub url: String,
+    pub status: u16,
+    pub status_text: String,
+    /// names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The result of a request, set by the HTTP client thread when it finishes.
+pub type FetchResult = Arc<Mutex<Option<Result<HttpResponse, String>>>>;
+
+/// fetch(url[, { method, headers, body }]). Returns a promise of a Response.
+pub fn fetch(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let url = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: fetch(): url must be a string".to_string(),
+            ))
+        }
+    };
+    let option = |name: &str| match args.get(1) {
+        Some(options @ Value::Object(_, ObjectKind::Ordinary)) => {
+            options.get_property(Value::string(name.to_string()), None)
+        }
+        _ => Value::Undefined,
+    };
+
+    let method = match option("method") {
+        Value::Undefined => "GET".to_string(),
+        method => method.to_string().to_uppercase(),
+    };
+    let headers = match option("headers") {
+        Value::Object(map, ObjectKind::Ordinary) => map
+            .iter()
+            .filter(|(_, prop)| prop.enumerable)
+            .map(|(name, prop)| (name.clone(), prop.val.to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let body = match option("body") {
+        Value::Undefined | Value::Null => None,
+        body => Some(body.to_string()),
+    };
+
+    let result: FetchResult = Arc::new(Mutex::new(None));
+    let result_ = result.clone();
+    thread::spawn(move || {
+        let response = request(&method, &url, &headers, body);
+        *result_.lock().unwrap() = Some(response);
+    });
+
+    let promise = promise::new_promise();
+    vm.task_mgr.add_task(Task::Fetch {
+        promise: promise.clone(),
+        result: result,
+    });
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// Send a request and receive the whole response. Responses with error statuses like 404 are
+/// not errors, as in fetch().
+fn request(
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: Option<String>,
+) -> Result<HttpResponse, String> {
+    let mut req = ureq::request(method, url);
+    for (name, val) in headers {
+        req = req.set(name, val);
+    }
+    let res = match body {
+        Some(body) => req.send_string(&body),
+        None => req.call(),
+    };
+    let res = match res {
+        Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let url = res.get_url().to_string();
+    let status = res.status();
+    let status_text = res.status_text().to_string();
+    let headers = res
+        .headers_names()
+        .into_iter()
+        .map(|name| {
+            let val = res.all(&name).join(", ");
+            (name.to_lowercase(), val)
+        })
+        .collect();
+    let mut body = vec![];
+    res.into_reader()
+        .read_to_end(&mut body)
+        .map_err(|err| err.to_string())?;
+
+    Ok(HttpResponse {
+        url: url,
+        status: status,
+        status_text: status_text,
+        headers: headers,
+        body: body,
+    })
+}
+
+/// Settle the promise returned by fetch() with the result of the request.
+pub fn settle(vm: &mut VM, promise: &Value, result: Result<HttpResponse, String>) {
+    match result {
+        Ok(res) => {
+            let response = new_response(res);
+            promise::resolve(vm, promise, response)
+        }
+        Err(msg) => promise::reject(vm, promise, new_error(format!("fetch failed: {}", msg))),
+    }
+}
+
+/// make a Response object: { status, statusText, ok, url, headers } with text() and json().
+fn new_response(res: HttpResponse) -> Value {
+    let headers = Value::object_from_npp(
+        &res.headers
+            .into_iter()
+            .map(|(name, val)| (name, Property::new(Value::string(val))))
+            .collect(),
+    );
+    let body = String::from_utf8_lossy(&res.body).into_owned();
+
+    // The body is held by the object itself, out of reach of scripts.
+    Value::Object(
+        Value::propmap_from_npp(&make_npp!(
+            status:     Value::Number(res.status as f64),
+            statusText: Value::string(res.status_text),
+            ok:         Value::Bool(200 <= res.status && res.status < 300),
+            url:        Value::string(res.url),
+            headers:    headers,
+            __proto__:  RESPONSE_PROTOTYPE.with(|x| x.clone())
+        )),
+        ObjectKind::Response(Box::new(body)),
+    )
+}
+
+/// the body of 'this' Response.
+fn body(callobj: &CallObjectRef) -> Result<String, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::Response(box ref body)) => Ok(body.clone()),
+        _ => Err(RuntimeError::Type(
+            "type error: Response method called on a non-response".to_string(),
+        )),
+    }
+}
+
+/// Response.prototype.text(). Returns a promise of the body decoded as UTF-8.
+fn response_text(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let body = body(&callobj)?;
+    let promise = promise::new_promise();
+    promise::resolve(vm, &promise, Value::string(body));
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// Response.prototype.json(). Returns a promise of the body parsed as JSON.
+fn response_json(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let body = body(&callobj)?;
+    let promise = promise::new_promise();
+    match json::parse(&body) {
+        Ok(val) => promise::resolve(vm, &promise, val),
+        Err(msg) => promise::reject(vm, &promise, new_error(format!("SyntaxError: {}", msg))),
+    }
+    vm.set_return_value(promise);
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use builtins::error::new_error;
+use builtins::object::option;
+use builtins::typedarray::TypedArrayKind;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'fs' module, loaded by require('fs'). Only the synchronous functions are provided.
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+thread_local!(
+    pub static STATS_PROTOTYPE: Value = {
+        make_object!(
+            isFile:         Value::default_builtin_function(stats_is_file),
+            isDirectory:    Value::default_builtin_function(stats_is_directory)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    make_object!(
+        readFileSync:   Value::default_builtin_function(read_file_sync),
+        writeFileSync:  Value::default_builtin_function(write_file_sync),
+        existsSync:     Value::default_builtin_function(exists_sync),
+        readdirSync:    Value::default_builtin_function(readdir_sync),
+        statSync:       Value::default_builtin_function(stat_sync),
+        mkdirSync:      Value::default_builtin_function(mkdir_sync),
+        rmdirSync:      Value::default_builtin_function(rmdir_sync),
+        unlinkSync:     Value::default_builtin_function(unlink_sync)
+    )
+}
+
+/// get the argument 'n' of fs.'func'() as a path.
+fn path_arg(args: &Vec<Value>, n: usize, func: &str) -> Result<String, RuntimeError> {
+    match args.get(n) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        _ => Err(RuntimeError::Type(format!(
+            "type error: fs.{}(): path must be a string",
+            func
+        ))),
+    }
+}
+
+/// make an Error to be thrown for 'err'. The message is like "<err>, <syscall> '<path>'" as
+/// Node.js does.
+fn io_error(err: io::Error, syscall: &str, path: &str) -> RuntimeError {
+    RuntimeError::Exception(new_error(format!("{}, {} '{}'", err, syscall, path)))
+}
+
+/// fs.readFileSync(path[, encoding]). 'encoding' is a string or { encoding }.
+/// Returns a string if an encoding is given, or a Uint8Array.
+fn read_file_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "readFileSync")?;
+    let encoding = match args.get(1) {
+        Some(Value::String(s)) => Value::String(s.clone()),
+        options => option(options, "encoding"),
+    };
+
+    let data = fs::read(&path).map_err(|err| io_error(err, "open", &path))?;
+
+    let val = match encoding {
+        Value::Undefined | Value::Null => {
+            let length = data.len();
+            let buffer = Value::array_buffer(data);
+            Value::typed_array(TypedArrayKind::Uint8, buffer, 0, length)
+        }
+        encoding => match encoding.to_string().to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Value::string(String::from_utf8_lossy(&data).into_owned()),
+            _ => {
+                return Err(RuntimeError::Type(format!(
+                    "type error: fs.readFileSync(): unsupported encoding '{}'",
+                    encoding.to_string()
+                )))
+            }
+        },
+    };
+    vm.set_return_value(val);
+    Ok(())
+}
+
+/// fs.writeFileSync(path, data). 'data' is written as UTF-8 unless it is a typed array.
+fn write_file_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "writeFileSync")?;
+    let data = match args.get(1) {
+        Some(Value::Object(_, ObjectKind::TypedArray(ta))) => {
+            let start = ta.byte_offset;
+            ta.buffer.data[start..start + ta.byte_length()].to_vec()
+        }
+        Some(val) => val.to_string().into_bytes(),
+        None => "undefined".to_string().into_bytes(),
+    };
+
+    fs::write(&path, data).map_err(|err| io_error(err, "open", &path))?;
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// fs.existsSync(path)
+fn exists_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let exists = match args.get(0) {
+        Some(Value::String(s)) => Path::new(&s.to_string()).exists(),
+        _ => false,
+    };
+    vm.set_return_value(Value::Bool(exists));
+    Ok(())
+}
+
+/// fs.readdirSync(path). Returns the names of the entries sorted.
+fn readdir_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "readdirSync")?;
+    let mut names = vec![];
+    for entry in fs::read_dir(&path).map_err(|err| io_error(err, "scandir", &path))? {
+        let entry = entry.map_err(|err| io_error(err, "scandir", &path))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    vm.set_return_value(Value::array_from_elems(
+        names.into_iter().map(Value::string).collect(),
+    ));
+    Ok(())
+}
+
+/// fs.statSync(path). Returns { size, mode, mtimeMs } with isFile() and isDirectory().
+fn stat_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "statSync")?;
+    let metadata = fs::metadata(&path).map_err(|err| io_error(err, "stat", &path))?;
+    let mtime_ms = match metadata
+        .modified()
+        .map(|time| time.duration_since(UNIX_EPOCH))
+    {
+        Ok(Ok(duration)) => {
+            duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+        }
+        _ => ::std::f64::NAN,
+    };
+    vm.set_return_value(make_object!(
+        size:       Value::Number(metadata.size() as f64),
+        mode:       Value::Number(metadata.mode() as f64),
+        mtimeMs:    Value::Number(mtime_ms),
+        __proto__:  STATS_PROTOTYPE.with(|x| x.clone())
+    ));
+    Ok(())
+}
+
+/// file type bits of the mode of 'this' Stats object.
+fn stats_file_type(callobj: &CallObjectRef) -> u32 {
+    let mode = callobj
+        .this
+        .get_property(Value::string("mode".to_string()), None)
+        .to_number();
+    mode as u32 & S_IFMT
+}
+
+fn stats_is_file(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(stats_file_type(&callobj) == S_IFREG));
+    Ok(())
+}
+
+fn stats_is_directory(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(stats_file_type(&callobj) == S_IFDIR));
+    Ok(())
+}
+
+/// fs.mkdirSync(path[, { recursive }])
+fn mkdir_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "mkdirSync")?;
+    let res = if option(args.get(1), "recursive").to_boolean() {
+        fs::create_dir_all(&path)
+    } else {
+        fs::create_dir(&path)
+    };
+    res.map_err(|err| io_error(err, "mkdir", &path))?;
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// fs.rmdirSync(path). The directory must be empty.
+fn rmdir_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "rmdirSync")?;
+    fs::remove_dir(&path).map_err(|err| io_error(err, "rmdir", &path))?;
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// fs.unlinkSync(path)
+fn unlink_sync(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = path_arg(args, 0, "unlinkSync")?;
+    fs::remove_file(&path).map_err(|err| io_error(err, "unlink", &path))?;
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
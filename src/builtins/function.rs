@@ -1,10 +1,11 @@
-use builtins::object;
-use vm::{
-    callobj::CallObject,
-    error::RuntimeError,
-    value::*,
-    vm::{call_function, VM},
-};
+use builtins::{eval, object};
+use node::NodeBase;
+use vm::{callobj::CallObject, error::RuntimeError, value::*, vm::VM};
+
+/// internal variables of a bound function, held in its call object.
+const BOUND_TARGET: &str = "[[BoundTargetFunction]]";
+const BOUND_THIS: &str = "[[BoundThis]]";
+const BOUND_ARGUMENTS: &str = "[[BoundArguments]]";
 
 thread_local! {
     pub static FUNCTION_PROTOTYPE: Value = {
@@ -13,6 +14,8 @@ thread_local! {
             name:       Value::string("".to_string()),
             apply:      Value::default_builtin_function(prototype_apply),
             call:       Value::default_builtin_function(prototype_call),
+            bind:       Value::default_builtin_function(prototype_bind),
+            toString:   Value::default_builtin_function(prototype_to_string),
             __proto__:  object::OBJECT_PROTOTYPE.with(|x| x.clone())
         ));
         let co = CallObject::new_with_this(Value::Undefined);
@@ -38,8 +41,66 @@ pub fn init() -> Value {
     obj
 }
 
-fn new(_vm: &mut VM, _args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    unimplemented!("sorry");
+/// new Function(p1, p2, ..., body). The function is compiled in the global scope.
+fn new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let (params, body) = match args.split_last() {
+        Some((body, params)) => (
+            params
+                .iter()
+                .map(|param| param.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            body.to_string(),
+        ),
+        None => ("".to_string(), "".to_string()),
+    };
+    let source = format!("function anonymous({}\n) {{\n{}\n}}", params, body);
+
+    // The whole source must be one function expression. e.g. a body like '}; f(); {' is an error.
+    let node = eval::parse(format!("({})", source))?;
+    let is_one_function = match node.base {
+        NodeBase::StatementList(ref nodes) if nodes.len() == 1 => match nodes[0].base {
+            NodeBase::FunctionExpr(_, _, _, ref text, _) => *text == source,
+            _ => false,
+        },
+        _ => false,
+    };
+    if !is_one_function {
+        return Err(eval::syntax_error(
+            "invalid parameters or body of Function".to_string(),
+        ));
+    }
+
+    let global = vm.state.scope[0].clone();
+    vm.state.scope.push(global);
+    let res = eval::run_script(vm, &node);
+    vm.state.scope.pop();
+    vm.set_return_value(res?);
+    Ok(())
+}
+
+/// Function.prototype.toString(). Returns the source text of the function.
+fn prototype_to_string(
+    vm: &mut VM,
+    _args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let native = "function () { [native code] }".to_string();
+    let source = match *callobj.this {
+        Value::Object(_, ObjectKind::Function(box (ref info, _))) => match info.source {
+            Some(ref source) => (**source).clone(),
+            None => native,
+        },
+        Value::Object(_, ObjectKind::BuiltinFunction(_)) => native,
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Function.prototype.toString requires that 'this' be a Function"
+                    .to_string(),
+            ))
+        }
+    };
+    vm.set_return_value(Value::string(source));
+    Ok(())
 }
 
 fn prototype_apply(
@@ -50,14 +111,7 @@ fn prototype_apply(
     let callee = &*callobj.this;
     let arg_this = args[0].clone();
     let arg = match args[1].clone() {
-        Value::Object(_, ObjectKind::Array(aryval)) => {
-            let aryval = &aryval;
-            let mut elems = vec![];
-            for i in 0..aryval.length {
-                elems.push(aryval.elems[i].val.clone());
-            }
-            elems
-        }
+        Value::Object(_, ObjectKind::Array(aryval)) => aryval.values()?,
         Value::Object(_, ObjectKind::Arguments(callobj)) => {
             let mut elems = vec![];
             let length = callobj.get_arguments_length();
@@ -69,20 +123,7 @@ fn prototype_apply(
         _ => vec![],
     };
 
-    match callee {
-        Value::Object(_, ObjectKind::BuiltinFunction(box (ref info, callobj))) => {
-            let mut callobj = callobj.clone();
-            *callobj.this = arg_this;
-            (info.func)(vm, &arg, callobj)?;
-        }
-        Value::Object(_, ObjectKind::Function(box (func_info, callobj))) => {
-            let mut callobj = callobj.clone();
-            *callobj.this = arg_this;
-            call_function(vm, func_info.clone(), &mut callobj, &arg).unwrap();
-        }
-        _ => vm.state.stack.push(Value::Undefined),
-    };
-    Ok(())
+    call_with_this(vm, callee, arg_this, &arg)
 }
 
 fn prototype_call(
@@ -92,16 +133,107 @@ fn prototype_call(
 ) -> Result<(), RuntimeError> {
     let callee = &*callobj.this;
     let arg_this = args[0].clone();
-    match callee {
-        Value::Object(_, ObjectKind::BuiltinFunction(box (ref info, callobj))) => {
-            let mut callobj = callobj.clone();
-            *callobj.this = arg_this;
-            (info.func)(vm, &args[1..].to_vec(), callobj)?;
+    call_with_this(vm, callee, arg_this, &args[1..].to_vec())
+}
+
+/// Function.prototype.bind(thisArg, ...args)
+fn prototype_bind(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let target = (*callobj.this).clone();
+    match target {
+        Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {}
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Bind must be called on a function".to_string(),
+            ))
         }
-        Value::Object(_, ObjectKind::Function(box (func_info, callobj))) => {
-            let mut callobj = callobj.clone();
-            *callobj.this = arg_this;
-            call_function(vm, func_info.clone(), &mut callobj, &args[1..].to_vec()).unwrap();
+    }
+    let bound_args = args.iter().skip(1).cloned().collect::<Vec<Value>>();
+
+    let length = match target.get_property(Value::string("length".to_string()), None) {
+        Value::Number(n) => (n - bound_args.len() as f64).max(0.0),
+        _ => 0.0,
+    };
+    let name = match target.get_property(Value::string("name".to_string()), None) {
+        Value::String(name) => name.to_string(),
+        _ => "".to_string(),
+    };
+    let mut func = Value::builtin_function(
+        bound_function,
+        None,
+        &mut make_npp!(
+            length:     Value::Number(length),
+            name:       Value::string(format!("bound {}", name)),
+            __proto__:  FUNCTION_PROTOTYPE.with(|x| x.clone())
+        ),
+        None,
+    );
+    if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = func {
+        callobj.set_value(BOUND_TARGET.to_string(), target);
+        callobj.set_value(
+            BOUND_THIS.to_string(),
+            args.get(0).cloned().unwrap_or(Value::Undefined),
+        );
+        callobj.set_value(
+            BOUND_ARGUMENTS.to_string(),
+            Value::array_from_elems(bound_args),
+        );
+    }
+    vm.set_return_value(func);
+    Ok(())
+}
+
+/// the target function and the arguments bound to a bound function followed by 'args'.
+fn bound_target_and_args(
+    callobj: &CallObjectRef,
+    args: &Vec<Value>,
+) -> Result<(Value, Vec<Value>), RuntimeError> {
+    let target = callobj.get_value(BOUND_TARGET)?;
+    let mut all_args = vec![];
+    if let Value::Object(_, ObjectKind::Array(aryval)) = callobj.get_value(BOUND_ARGUMENTS)? {
+        all_args = aryval.values()?;
+    }
+    all_args.extend(args.iter().cloned());
+    Ok((target, all_args))
+}
+
+/// A function made by bind(). Calls the target function with the bound this and arguments.
+pub fn bound_function(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let (target, args) = bound_target_and_args(&callobj, args)?;
+    let this = callobj.get_value(BOUND_THIS)?;
+    call_with_this(vm, &target, this, &args)
+}
+
+/// 'new' for a function made by bind(). The bound this is ignored.
+pub fn bound_function_new(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let (target, args) = bound_target_and_args(&callobj, args)?;
+    vm.construct_function_simply(&target, &args)?;
+    Ok(())
+}
+
+/// call 'callee' with 'this'. If 'callee' is not a function, undefined is returned.
+pub fn call_with_this(
+    vm: &mut VM,
+    callee: &Value,
+    this: Value,
+    args: &Vec<Value>,
+) -> Result<(), RuntimeError> {
+    match callee {
+        Value::Object(_, ObjectKind::BuiltinFunction(_))
+        | Value::Object(_, ObjectKind::Function(_)) => {
+            vm.call_function_with_this(callee, this, args)?;
         }
         _ => vm.state.stack.push(Value::Undefined),
     };
@@ -0,0 +1,37 @@
+use gc;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'gc' global, which runs GC and shows its statistics to diagnose the memory usage of
+// scripts. Collections are also logged by 'rapidus --trace-gc'.
+
+pub fn init() -> Value {
+    make_object!(
+        collect:    Value::default_builtin_function(collect),
+        stats:      Value::default_builtin_function(stats)
+    )
+}
+
+/// gc.collect(). Runs a major collection now.
+fn collect(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    gc::mark_and_sweep(vm);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// gc.stats(). Pauses are in milliseconds.
+fn stats(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let stats = gc::stats();
+    let stats = make_object!(
+        objects:          Value::Number(gc::object_count() as f64),
+        heapBytes:        Value::Number(gc::allocated_bytes() as f64),
+        collections:      Value::Number(stats.collections as f64),
+        minorCollections: Value::Number(stats.minor_collections as f64),
+        freedObjects:     Value::Number(stats.freed_objects as f64),
+        freedBytes:       Value::Number(stats.freed_bytes as f64),
+        totalPauseMs:     Value::Number(gc::duration_ms(stats.total_pause)),
+        maxPauseMs:       Value::Number(gc::duration_ms(stats.max_pause)),
+        lastPauseMs:      Value::Number(gc::duration_ms(stats.last_pause))
+    );
+    vm.set_return_value(stats);
+    Ok(())
+}
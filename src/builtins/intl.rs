@@ -0,0 +1,1009 @@
+use builtins::error::new_error;
+use builtins::number::to_fixed;
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Timelike, Utc,
+};
+use std::env;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// Intl.NumberFormat and Intl.DateTimeFormat for a fixed set of locales, without CLDR data: en-US
+// (the default), en-GB, de-DE, fr-FR and ja-JP. A requested locale which is not supported falls
+// back to one of the same language, and then to en-US. The patterns are the ones CLDR gives for
+// the common options, and are approximated for the rest.
+//
+// A formatter keeps the object returned by resolvedOptions(), which is parsed again as the
+// options when it formats.
+
+thread_local!(
+    pub static NUMBER_FORMAT_PROTOTYPE: Value = {
+        make_object!(
+            format:             Value::default_builtin_function(number_format_prototype_format),
+            resolvedOptions:    Value::default_builtin_function(prototype_resolved_options)
+        )
+    };
+
+    pub static DATE_TIME_FORMAT_PROTOTYPE: Value = {
+        make_object!(
+            format:             Value::default_builtin_function(date_time_format_prototype_format),
+            resolvedOptions:    Value::default_builtin_function(prototype_resolved_options)
+        )
+    };
+);
+
+/// internal property of formatters, the object returned by resolvedOptions().
+const RESOLVED_OPTIONS: &str = "[[ResolvedOptions]]";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LocaleId {
+    EnUs,
+    EnGb,
+    De,
+    Fr,
+    Ja,
+}
+
+struct Locale {
+    id: LocaleId,
+    tag: &'static str,
+    decimal: &'static str,
+    group: &'static str,
+    /// whether the currency symbol follows the number.
+    currency_after: bool,
+    /// what is between a number and '%'.
+    percent_space: &'static str,
+    months: [&'static str; 12],
+    short_months: [&'static str; 12],
+    /// from Sunday.
+    weekdays: [&'static str; 7],
+    short_weekdays: [&'static str; 7],
+    /// AM and PM.
+    day_periods: [&'static str; 2],
+    /// whether the 12-hour clock is used by default.
+    hour12: bool,
+    /// whether hours of the 24-hour clock have two digits.
+    pad_hour: bool,
+    /// whether days and months of numeric dates have two digits.
+    pad_numeric_date: bool,
+    /// what is between a date and a time.
+    date_time_separator: &'static str,
+}
+
+const EN_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const EN_SHORT_MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const EN_WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const EN_SHORT_WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const JA_MONTHS: [&str; 12] = [
+    "1月", "2月", "3月", "4月", "5月", "6月", "7月", "8月", "9月", "10月", "11月", "12月",
+];
+
+static LOCALES: [Locale; 5] = [
+    Locale {
+        id: LocaleId::EnUs,
+        tag: "en-US",
+        decimal: ".",
+        group: ",",
+        currency_after: false,
+        percent_space: "",
+        months: EN_MONTHS,
+        short_months: EN_SHORT_MONTHS,
+        weekdays: EN_WEEKDAYS,
+        short_weekdays: EN_SHORT_WEEKDAYS,
+        day_periods: ["AM", "PM"],
+        hour12: true,
+        pad_hour: false,
+        pad_numeric_date: false,
+        date_time_separator: ", ",
+    },
+    Locale {
+        id: LocaleId::EnGb,
+        tag: "en-GB",
+        decimal: ".",
+        group: ",",
+        currency_after: false,
+        percent_space: "",
+        months: EN_MONTHS,
+        short_months: EN_SHORT_MONTHS,
+        weekdays: EN_WEEKDAYS,
+        short_weekdays: EN_SHORT_WEEKDAYS,
+        day_periods: ["am", "pm"],
+        hour12: false,
+        pad_hour: true,
+        pad_numeric_date: true,
+        date_time_separator: ", ",
+    },
+    Locale {
+        id: LocaleId::De,
+        tag: "de-DE",
+        decimal: ",",
+        group: ".",
+        currency_after: true,
+        percent_space: "\u{a0}",
+        months: [
+            "Januar",
+            "Februar",
+            "März",
+            "April",
+            "Mai",
+            "Juni",
+            "Juli",
+            "August",
+            "September",
+            "Oktober",
+            "November",
+            "Dezember",
+        ],
+        short_months: [
+            "Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.", "Nov.",
+            "Dez.",
+        ],
+        weekdays: [
+            "Sonntag",
+            "Montag",
+            "Dienstag",
+            "Mittwoch",
+            "Donnerstag",
+            "Freitag",
+            "Samstag",
+        ],
+        short_weekdays: ["So.", "Mo.", "Di.", "Mi.", "Do.", "Fr.", "Sa."],
+        day_periods: ["AM", "PM"],
+        hour12: false,
+        pad_hour: true,
+        pad_numeric_date: false,
+        date_time_separator: ", ",
+    },
+    Locale {
+        id: LocaleId::Fr,
+        tag: "fr-FR",
+        decimal: ",",
+        group: "\u{202f}",
+        currency_after: true,
+        percent_space: "\u{202f}",
+        months: [
+            "janvier",
+            "février",
+            "mars",
+            "avril",
+            "mai",
+            "juin",
+            "juillet",
+            "août",
+            "septembre",
+            "octobre",
+            "novembre",
+            "décembre",
+        ],
+        short_months: [
+            "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.",
+            "nov.", "déc.",
+        ],
+        weekdays: [
+            "dimanche", "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi",
+        ],
+        short_weekdays: ["dim.", "lun.", "mar.", "mer.", "jeu.", "ven.", "sam."],
+        day_periods: ["AM", "PM"],
+        hour12: false,
+        pad_hour: true,
+        pad_numeric_date: true,
+        date_time_separator: " ",
+    },
+    Locale {
+        id: LocaleId::Ja,
+        tag: "ja-JP",
+        decimal: ".",
+        group: ",",
+        currency_after: false,
+        percent_space: "",
+        months: JA_MONTHS,
+        short_months: JA_MONTHS,
+        weekdays: [
+            "日曜日",
+            "月曜日",
+            "火曜日",
+            "水曜日",
+            "木曜日",
+            "金曜日",
+            "土曜日",
+        ],
+        short_weekdays: ["日", "月", "火", "水", "木", "金", "土"],
+        day_periods: ["午前", "午後"],
+        hour12: false,
+        pad_hour: false,
+        pad_numeric_date: false,
+        date_time_separator: " ",
+    },
+];
+
+pub fn init() -> Value {
+    let mut number_format_prototype = NUMBER_FORMAT_PROTOTYPE.with(|x| x.clone());
+    let number_format = Value::builtin_function(
+        number_format_new,
+        None,
+        &mut vec![],
+        Some(number_format_prototype.clone()),
+    );
+    number_format_prototype.set_constructor(number_format.clone());
+
+    let mut date_time_format_prototype = DATE_TIME_FORMAT_PROTOTYPE.with(|x| x.clone());
+    let date_time_format = Value::builtin_function(
+        date_time_format_new,
+        None,
+        &mut vec![],
+        Some(date_time_format_prototype.clone()),
+    );
+    date_time_format_prototype.set_constructor(date_time_format.clone());
+
+    make_object!(
+        NumberFormat:   number_format,
+        DateTimeFormat: date_time_format
+    )
+}
+
+/// the supported locale 'tag' names, or one of its language.
+fn find_locale(tag: &str) -> Option<&'static Locale> {
+    let tag = tag.to_ascii_lowercase();
+    let language = tag.split(|c| c == '-' || c == '_').next().unwrap_or("");
+    LOCALES
+        .iter()
+        .find(|locale| locale.tag.to_ascii_lowercase() == tag)
+        .or_else(|| {
+            LOCALES
+                .iter()
+                .find(|locale| locale.tag.split('-').next() == Some(language))
+        })
+}
+
+/// the first supported locale of 'locales', a tag or an array of tags, or the default one.
+fn resolve_locale(locales: Option<&Value>) -> &'static Locale {
+    let tags = match locales {
+        None | Some(Value::Undefined) => vec![],
+        Some(Value::Object(_, ObjectKind::Array(array))) => {
+            array.elements().map(|(_, prop)| prop.val.clone()).collect()
+        }
+        Some(tag) => vec![tag.clone()],
+    };
+    tags.iter()
+        .filter_map(|tag| find_locale(tag.to_string().as_str()))
+        .next()
+        .unwrap_or(&LOCALES[0])
+}
+
+/// the property 'name' of 'options', or undefined if 'options' is not an object.
+fn get_option(options: Option<&Value>, name: &str) -> Value {
+    match options {
+        Some(options @ Value::Object(_, _)) => {
+            options.get_property(Value::string(name.to_string()), None)
+        }
+        _ => Value::Undefined,
+    }
+}
+
+/// the option 'name', which must be one of 'allowed'.
+fn string_option(
+    options: Option<&Value>,
+    name: &str,
+    allowed: &[&'static str],
+) -> Result<Option<&'static str>, RuntimeError> {
+    match get_option(options, name) {
+        Value::Undefined => Ok(None),
+        val => {
+            let val = val.to_string();
+            match allowed.iter().find(|allowed| **allowed == val.as_str()) {
+                Some(allowed) => Ok(Some(*allowed)),
+                None => Err(range_error(format!(
+                    "Value {} out of range for options property {}",
+                    val, name
+                ))),
+            }
+        }
+    }
+}
+
+/// the option 'name', which must be an integer from 'min' to 'max'.
+fn digits_option(
+    options: Option<&Value>,
+    name: &str,
+    min: usize,
+    max: usize,
+) -> Result<Option<usize>, RuntimeError> {
+    match get_option(options, name) {
+        Value::Undefined => Ok(None),
+        val => {
+            let n = val.to_number();
+            if n.is_nan() || n < min as f64 || max as f64 + 1.0 <= n {
+                return Err(range_error(format!("{} value is out of range.", name)));
+            }
+            Ok(Some(n as usize))
+        }
+    }
+}
+
+fn bool_option(options: Option<&Value>, name: &str) -> Option<bool> {
+    match get_option(options, name) {
+        Value::Undefined => None,
+        val => Some(val.to_boolean()),
+    }
+}
+
+fn range_error(msg: String) -> RuntimeError {
+    RuntimeError::Exception(new_error(format!("RangeError: {}", msg)))
+}
+
+/// Make a formatter whose prototype is 'prototype' and which keeps 'resolved'.
+fn new_formatter(prototype: Value, resolved: Value) -> Value {
+    let formatter = make_object!(
+        __proto__:  prototype
+    );
+    if let Value::Object(ref map, _) = formatter {
+        map.clone().insert(
+            RESOLVED_OPTIONS.to_string(),
+            Property {
+                val: resolved,
+                writable: false,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+    formatter
+}
+
+/// the resolved options kept by the formatter 'this', whose 'method' is called.
+fn this_resolved_options(callobj: &CallObjectRef, method: &str) -> Result<Value, RuntimeError> {
+    if let Value::Object(ref map, ObjectKind::Ordinary) = *callobj.this {
+        if let Some(prop) = map.get(RESOLVED_OPTIONS) {
+            return Ok(prop.val.clone());
+        }
+    }
+    Err(RuntimeError::Type(format!(
+        "type error: Method {} called on incompatible receiver",
+        method
+    )))
+}
+
+/// Intl.NumberFormat.prototype.resolvedOptions() and Intl.DateTimeFormat.prototype.
+/// resolvedOptions(). Returns a copy, so that changing it does not change the formatter.
+fn prototype_resolved_options(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let resolved = this_resolved_options(&callobj, "resolvedOptions")?;
+    let npp: Vec<NamePropPair> = match resolved {
+        Value::Object(ref map, _) => map
+            .iter()
+            .map(|(name, prop)| (name.clone(), Property::new(prop.val.clone())))
+            .collect(),
+        _ => vec![],
+    };
+    vm.set_return_value(Value::object_from_npp(&npp));
+    Ok(())
+}
+
+// Intl.NumberFormat
+
+#[derive(Clone, Debug, PartialEq)]
+enum NumberStyle {
+    Decimal,
+    Percent,
+    Currency(String),
+}
+
+struct NumberFormatOptions {
+    locale: &'static Locale,
+    style: NumberStyle,
+    min_fraction: usize,
+    max_fraction: usize,
+    use_grouping: bool,
+}
+
+impl NumberFormatOptions {
+    fn new(locales: Option<&Value>, options: Option<&Value>) -> Result<Self, RuntimeError> {
+        let locale = resolve_locale(locales);
+        let style = match string_option(options, "style", &["decimal", "percent", "currency"])? {
+            Some("percent") => NumberStyle::Percent,
+            Some("currency") => match get_option(options, "currency") {
+                Value::Undefined => {
+                    return Err(RuntimeError::Type(
+                        "type error: Currency code is required with currency style.".to_string(),
+                    ))
+                }
+                code => {
+                    let code = code.to_string();
+                    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+                        return Err(range_error(format!("Invalid currency code : {}", code)));
+                    }
+                    NumberStyle::Currency(code.to_ascii_uppercase())
+                }
+            },
+            _ => NumberStyle::Decimal,
+        };
+
+        let (default_min, default_max) = match style {
+            NumberStyle::Decimal => (0, 3),
+            NumberStyle::Percent => (0, 0),
+            NumberStyle::Currency(ref code) => {
+                let digits = currency_digits(code);
+                (digits, digits)
+            }
+        };
+        let min = digits_option(options, "minimumFractionDigits", 0, 20)?;
+        let max = digits_option(options, "maximumFractionDigits", 0, 20)?;
+        let min = match (min, max) {
+            (Some(min), _) => min,
+            (None, Some(max)) => default_min.min(max),
+            (None, None) => default_min,
+        };
+        let max = max.unwrap_or(default_max.max(min));
+        if min > max {
+            return Err(range_error(
+                "maximumFractionDigits value is out of range.".to_string(),
+            ));
+        }
+
+        Ok(NumberFormatOptions {
+            locale: locale,
+            style: style,
+            min_fraction: min,
+            max_fraction: max,
+            use_grouping: bool_option(options, "useGrouping").unwrap_or(true),
+        })
+    }
+
+    fn resolved(&self) -> Value {
+        let mut npp = vec![
+            (
+                "locale".to_string(),
+                Property::new(Value::string(self.locale.tag.to_string())),
+            ),
+            (
+                "numberingSystem".to_string(),
+                Property::new(Value::string("latn".to_string())),
+            ),
+        ];
+        let style = match self.style {
+            NumberStyle::Decimal => "decimal",
+            NumberStyle::Percent => "percent",
+            NumberStyle::Currency(_) => "currency",
+        };
+        npp.push((
+            "style".to_string(),
+            Property::new(Value::string(style.to_string())),
+        ));
+        if let NumberStyle::Currency(ref code) = self.style {
+            npp.push((
+                "currency".to_string(),
+                Property::new(Value::string(code.clone())),
+            ));
+        }
+        npp.push((
+            "minimumFractionDigits".to_string(),
+            Property::new(Value::Number(self.min_fraction as f64)),
+        ));
+        npp.push((
+            "maximumFractionDigits".to_string(),
+            Property::new(Value::Number(self.max_fraction as f64)),
+        ));
+        npp.push((
+            "useGrouping".to_string(),
+            Property::new(Value::Bool(self.use_grouping)),
+        ));
+        Value::object_from_npp(&npp)
+    }
+
+    fn format(&self, x: f64) -> String {
+        if x.is_nan() {
+            return "NaN".to_string();
+        }
+        let locale = self.locale;
+        let x = match self.style {
+            NumberStyle::Percent => x * 100.0,
+            _ => x,
+        };
+        let digits = if x.is_infinite() {
+            "∞".to_string()
+        } else {
+            self.format_digits(x.abs())
+        };
+        let formatted = match self.style {
+            NumberStyle::Decimal => digits,
+            NumberStyle::Percent => format!("{}{}%", digits, locale.percent_space),
+            NumberStyle::Currency(ref code) => {
+                let symbol = currency_symbol(code, locale);
+                if locale.currency_after {
+                    format!("{}\u{a0}{}", digits, symbol)
+                } else if symbol.len() == 3 && symbol == code.as_str() {
+                    format!("{}\u{a0}{}", symbol, digits)
+                } else {
+                    format!("{}{}", symbol, digits)
+                }
+            }
+        };
+        if x.is_sign_negative() {
+            format!("-{}", formatted)
+        } else {
+            formatted
+        }
+    }
+
+    /// format non-negative finite 'x' by the digit options.
+    fn format_digits(&self, x: f64) -> String {
+        let fixed = to_fixed(x, self.max_fraction);
+        let (int, frac) = match fixed.find('.') {
+            Some(point) => (&fixed[..point], &fixed[point + 1..]),
+            None => (fixed.as_str(), ""),
+        };
+        let mut frac = frac.to_string();
+        while frac.len() > self.min_fraction && frac.ends_with('0') {
+            frac.pop();
+        }
+
+        let mut formatted = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if self.use_grouping && i > 0 && (int.len() - i) % 3 == 0 {
+                formatted.push_str(self.locale.group);
+            }
+            formatted.push(c);
+        }
+        if !frac.is_empty() {
+            formatted.push_str(self.locale.decimal);
+            formatted.push_str(frac.as_str());
+        }
+        formatted
+    }
+}
+
+/// the number of fraction digits of the currency.
+fn currency_digits(code: &str) -> usize {
+    match code {
+        "JPY" | "KRW" | "VND" | "CLP" | "ISK" => 0,
+        _ => 2,
+    }
+}
+
+/// the symbol of the currency in 'locale'. The code itself if it has no symbol.
+fn currency_symbol(code: &str, locale: &Locale) -> String {
+    let symbol = match (code, locale.id) {
+        ("USD", LocaleId::EnGb) => "US$",
+        ("USD", _) => "$",
+        ("EUR", _) => "€",
+        ("GBP", _) => "£",
+        ("JPY", LocaleId::Ja) => "￥",
+        ("JPY", _) => "¥",
+        (code, _) => code,
+    };
+    symbol.to_string()
+}
+
+/// Format 'x' as Intl.NumberFormat(locales, options) does. For Number.prototype.toLocaleString().
+pub fn format_number(
+    locales: Option<&Value>,
+    options: Option<&Value>,
+    x: f64,
+) -> Result<String, RuntimeError> {
+    Ok(NumberFormatOptions::new(locales, options)?.format(x))
+}
+
+/// new Intl.NumberFormat([locales[, options]])
+fn number_format_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let resolved = NumberFormatOptions::new(args.get(0), args.get(1))?.resolved();
+    let prototype = NUMBER_FORMAT_PROTOTYPE.with(|x| x.clone());
+    vm.set_return_value(new_formatter(prototype, resolved));
+    Ok(())
+}
+
+/// Intl.NumberFormat.prototype.format(number)
+fn number_format_prototype_format(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let resolved = this_resolved_options(&callobj, "Intl.NumberFormat.prototype.format")?;
+    let locale = get_option(Some(&resolved), "locale");
+    let options = NumberFormatOptions::new(Some(&locale), Some(&resolved))?;
+    let x = args.get(0).unwrap_or(&Value::Undefined).to_number();
+    vm.set_return_value(Value::string(options.format(x)));
+    Ok(())
+}
+
+// Intl.DateTimeFormat
+
+/// Which components are formatted when the options specify none: those of the date, the time,
+/// or both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateTimeDefaults {
+    Date,
+    Time,
+    All,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeZoneKind {
+    Local,
+    Fixed(FixedOffset),
+}
+
+struct DateTimeFormatOptions {
+    locale: &'static Locale,
+    time_zone: TimeZoneKind,
+    /// the name of the time zone, shown by resolvedOptions().
+    time_zone_name: String,
+    hour12: bool,
+    weekday: Option<&'static str>,
+    year: Option<&'static str>,
+    month: Option<&'static str>,
+    day: Option<&'static str>,
+    hour: Option<&'static str>,
+    minute: Option<&'static str>,
+    second: Option<&'static str>,
+}
+
+const NUMERIC: &[&str] = &["numeric", "2-digit"];
+const TEXTUAL: &[&str] = &["narrow", "short", "long"];
+const MONTH: &[&str] = &["numeric", "2-digit", "narrow", "short", "long"];
+
+impl DateTimeFormatOptions {
+    /// If the options have none of the components of 'required', those of 'defaults' are
+    /// formatted.
+    fn new(
+        locales: Option<&Value>,
+        options: Option<&Value>,
+        required: DateTimeDefaults,
+        defaults: DateTimeDefaults,
+    ) -> Result<Self, RuntimeError> {
+        let locale = resolve_locale(locales);
+        let (time_zone, time_zone_name) = time_zone(get_option(options, "timeZone"))?;
+        let mut format = DateTimeFormatOptions {
+            locale: locale,
+            time_zone: time_zone,
+            time_zone_name: time_zone_name,
+            hour12: bool_option(options, "hour12").unwrap_or(locale.hour12),
+            weekday: string_option(options, "weekday", TEXTUAL)?,
+            year: string_option(options, "year", NUMERIC)?,
+            month: string_option(options, "month", MONTH)?,
+            day: string_option(options, "day", NUMERIC)?,
+            hour: string_option(options, "hour", NUMERIC)?,
+            minute: string_option(options, "minute", NUMERIC)?,
+            second: string_option(options, "second", NUMERIC)?,
+        };
+
+        let has_date = format.weekday.is_some()
+            || format.year.is_some()
+            || format.month.is_some()
+            || format.day.is_some();
+        let has_time = format.hour.is_some() || format.minute.is_some() || format.second.is_some();
+        let need_defaults = match required {
+            DateTimeDefaults::Date => !has_date,
+            DateTimeDefaults::Time => !has_time,
+            DateTimeDefaults::All => !has_date && !has_time,
+        };
+        if need_defaults {
+            if defaults != DateTimeDefaults::Time {
+                format.year = Some("numeric");
+                format.month = Some("numeric");
+                format.day = Some("numeric");
+            }
+            if defaults != DateTimeDefaults::Date {
+                format.hour = Some("numeric");
+                format.minute = Some("numeric");
+                format.second = Some("numeric");
+            }
+        }
+        Ok(format)
+    }
+
+    fn resolved(&self) -> Value {
+        let string = |s: &str| Property::new(Value::string(s.to_string()));
+        let mut npp = vec![
+            ("locale".to_string(), string(self.locale.tag)),
+            ("calendar".to_string(), string("gregory")),
+            ("numberingSystem".to_string(), string("latn")),
+            ("timeZone".to_string(), string(self.time_zone_name.as_str())),
+        ];
+        if self.hour.is_some() {
+            npp.push((
+                "hour12".to_string(),
+                Property::new(Value::Bool(self.hour12)),
+            ));
+        }
+        let components = [
+            ("weekday", self.weekday),
+            ("year", self.year),
+            ("month", self.month),
+            ("day", self.day),
+            ("hour", self.hour),
+            ("minute", self.minute),
+            ("second", self.second),
+        ];
+        for &(name, style) in components.iter() {
+            if let Some(style) = style {
+                npp.push((name.to_string(), string(style)));
+            }
+        }
+        Value::object_from_npp(&npp)
+    }
+
+    fn format(&self, time: &DateTime<Utc>) -> String {
+        let time = match self.time_zone {
+            TimeZoneKind::Local => time.with_timezone(&Local).naive_local(),
+            TimeZoneKind::Fixed(offset) => time.with_timezone(&offset).naive_local(),
+        };
+        let date = self.format_date(&time);
+        let time = self.format_time(&time);
+        match (date.is_empty(), time.is_empty()) {
+            (false, false) => format!("{}{}{}", date, self.locale.date_time_separator, time),
+            (true, _) => time,
+            (_, true) => date,
+        }
+    }
+
+    fn format_date(&self, time: &NaiveDateTime) -> String {
+        let locale = self.locale;
+        let numeric = |style: &str, n: u32, pad: bool| match style {
+            "2-digit" => format!("{:02}", n % 100),
+            _ if pad => format!("{:02}", n),
+            _ => n.to_string(),
+        };
+        let narrow = |name: &str| match locale.id {
+            LocaleId::Ja => name.to_string(),
+            _ => name.chars().take(1).collect::<String>().to_uppercase(),
+        };
+
+        let month0 = time.month0() as usize;
+        let textual_month = match self.month {
+            Some("narrow") | Some("short") | Some("long") => true,
+            _ => false,
+        };
+        let pad = !textual_month && locale.pad_numeric_date;
+        let year = self.year.map(|style| match style {
+            "2-digit" => format!("{:02}", time.year() % 100),
+            _ => time.year().to_string(),
+        });
+        let month = self.month.map(|style| match style {
+            "long" => locale.months[month0].to_string(),
+            "short" => locale.short_months[month0].to_string(),
+            "narrow" => narrow(locale.months[month0]),
+            style => numeric(style, time.month(), pad),
+        });
+        let day = self.day.map(|style| numeric(style, time.day(), pad));
+        let weekday0 = time.weekday().num_days_from_sunday() as usize;
+        let weekday = self.weekday.map(|style| match style {
+            "long" => locale.weekdays[weekday0].to_string(),
+            "short" => locale.short_weekdays[weekday0].to_string(),
+            _ => narrow(locale.weekdays[weekday0]),
+        });
+
+        let date = if locale.id == LocaleId::Ja {
+            if textual_month {
+                join(&[year.map(|y| y + "年"), month, day.map(|d| d + "日")], "")
+            } else {
+                join(&[year, month, day], "/")
+            }
+        } else if textual_month {
+            match locale.id {
+                LocaleId::EnUs => {
+                    let month_day = join(&[month, day], " ");
+                    join(&[non_empty(month_day), year], ", ")
+                }
+                LocaleId::De => join(&[day.map(|d| d + "."), month, year], " "),
+                _ => join(&[day, month, year], " "),
+            }
+        } else {
+            match locale.id {
+                LocaleId::EnUs => join(&[month, day, year], "/"),
+                LocaleId::De => join(&[day, month, year], "."),
+                _ => join(&[day, month, year], "/"),
+            }
+        };
+
+        match (weekday, locale.id) {
+            (None, _) => date,
+            (Some(weekday), LocaleId::Ja) => match self.weekday {
+                Some("long") => date + weekday.as_str(),
+                _ if date.is_empty() => weekday,
+                _ => format!("{}({})", date, weekday),
+            },
+            (Some(weekday), LocaleId::Fr) | (Some(weekday), LocaleId::EnGb) => {
+                join(&[Some(weekday), non_empty(date)], " ")
+            }
+            (Some(weekday), _) => join(&[Some(weekday), non_empty(date)], ", "),
+        }
+    }
+
+    fn format_time(&self, time: &NaiveDateTime) -> String {
+        let locale = self.locale;
+        let hour = self.hour.map(|style| {
+            let hour = match self.hour12 {
+                true if time.hour() % 12 == 0 => 12,
+                true => time.hour() % 12,
+                false => time.hour(),
+            };
+            match style {
+                "2-digit" => format!("{:02}", hour),
+                _ if !self.hour12 && locale.pad_hour => format!("{:02}", hour),
+                _ => hour.to_string(),
+            }
+        });
+        // Minutes and seconds have two digits after hours or minutes.
+        let minute = self.minute.map(|style| match style {
+            "numeric" if self.hour.is_none() => time.minute().to_string(),
+            _ => format!("{:02}", time.minute()),
+        });
+        let second = self.second.map(|style| match style {
+            "numeric" if self.hour.is_none() && self.minute.is_none() => time.second().to_string(),
+            _ => format!("{:02}", time.second()),
+        });
+        let has_hour = hour.is_some();
+        let formatted = join(&[hour, minute, second], ":");
+
+        if !has_hour || !self.hour12 {
+            return formatted;
+        }
+        let day_period = locale.day_periods[(time.hour() >= 12) as usize];
+        match locale.id {
+            LocaleId::Ja => format!("{}{}", day_period, formatted),
+            _ => format!("{} {}", formatted, day_period),
+        }
+    }
+}
+
+/// join the parts which are there with 'separator'.
+fn join(parts: &[Option<String>], separator: &str) -> String {
+    parts
+        .iter()
+        .filter_map(|part| part.clone())
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+/// the time zone of the option 'timeZone', and its name. The local time zone is named by the TZ
+/// environment variable, or by its current offset from UTC.
+fn time_zone(option: Value) -> Result<(TimeZoneKind, String), RuntimeError> {
+    let local_name = match env::var("TZ") {
+        Ok(ref tz) if !tz.is_empty() && !tz.starts_with(':') => tz.clone(),
+        _ => offset_name(Local::now().offset().fix()),
+    };
+    let name = match option {
+        Value::Undefined => return Ok((TimeZoneKind::Local, local_name)),
+        name => name.to_string(),
+    };
+    match name.to_ascii_uppercase().as_str() {
+        "UTC" | "GMT" | "ETC/UTC" | "ETC/GMT" => {
+            return Ok((TimeZoneKind::Fixed(FixedOffset::east(0)), "UTC".to_string()))
+        }
+        _ => {}
+    }
+    if name == local_name {
+        return Ok((TimeZoneKind::Local, local_name));
+    }
+    match parse_offset(name.as_str()) {
+        Some(offset) => Ok((TimeZoneKind::Fixed(offset), offset_name(offset))),
+        None => Err(range_error(format!(
+            "Invalid time zone specified: {}",
+            name
+        ))),
+    }
+}
+
+/// parse an offset from UTC, '+HH:MM' or '-HH:MM'.
+fn parse_offset(name: &str) -> Option<FixedOffset> {
+    let sign = match name.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let mut parts = name[1..].splitn(2, ':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = parts.next().unwrap_or("0").parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn offset_name(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    format!(
+        "{}{:02}:{:02}",
+        if seconds < 0 { '-' } else { '+' },
+        seconds.abs() / 3600,
+        seconds.abs() % 3600 / 60
+    )
+}
+
+/// the time of the argument of format(): a Date, a time value, or now if it is undefined.
+fn date_time_arg(val: Option<&Value>) -> Result<DateTime<Utc>, RuntimeError> {
+    match val {
+        None | Some(Value::Undefined) => Ok(Utc::now()),
+        Some(Value::Object(_, ObjectKind::Date(box time))) => Ok(*time),
+        Some(val) => {
+            let millis = val.to_number();
+            if !millis.is_finite() || millis.abs() > 8.64e15 {
+                return Err(range_error("Invalid time value".to_string()));
+            }
+            Ok(Utc.timestamp_millis(millis as i64))
+        }
+    }
+}
+
+/// Format 'time' as Intl.DateTimeFormat(locales, options) does, with the components of
+/// 'defaults' if the options have none of 'required'. For Date.prototype.toLocaleString() and
+/// the like.
+pub fn format_date_time(
+    locales: Option<&Value>,
+    options: Option<&Value>,
+    required: DateTimeDefaults,
+    defaults: DateTimeDefaults,
+    time: &DateTime<Utc>,
+) -> Result<String, RuntimeError> {
+    Ok(DateTimeFormatOptions::new(locales, options, required, defaults)?.format(time))
+}
+
+/// new Intl.DateTimeFormat([locales[, options]])
+fn date_time_format_new(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let resolved = DateTimeFormatOptions::new(
+        args.get(0),
+        args.get(1),
+        DateTimeDefaults::All,
+        DateTimeDefaults::Date,
+    )?
+    .resolved();
+    let prototype = DATE_TIME_FORMAT_PROTOTYPE.with(|x| x.clone());
+    vm.set_return_value(new_formatter(prototype, resolved));
+    Ok(())
+}
+
+/// Intl.DateTimeFormat.prototype.format([date])
+fn date_time_format_prototype_format(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let resolved = this_resolved_options(&callobj, "Intl.DateTimeFormat.prototype.format")?;
+    let locale = get_option(Some(&resolved), "locale");
+    let options = DateTimeFormatOptions::new(
+        Some(&locale),
+        Some(&resolved),
+        DateTimeDefaults::All,
+        DateTimeDefaults::Date,
+    )?;
+    let time = date_time_arg(args.get(0))?;
+    vm.set_return_value(Value::string(options.format(&time)));
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use vm::value::*;
+
+// A JSON parser producing Values. Used by Response.prototype.json() of fetch().
+
+/// Parse 'text' as JSON. The error message tells where the parser failed.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let val = parser.value()?;
+    parser.skip_whitespaces();
+    if parser.pos < parser.text.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(val)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn unexpected(&self) -> String {
+        match self.text.get(self.pos) {
+            Some(_) => {
+                let rest = String::from_utf8_lossy(&self.text[self.pos..]);
+                format!(
+                    "Unexpected token {} in JSON at position {}",
+                    rest.chars().next().unwrap(),
+                    self.pos
+                )
+            }
+            None => "Unexpected end of JSON input".to_string(),
+        }
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.text.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    /// skip 'word' if the text continues with it.
+    fn skip(&mut self, word: &str) -> bool {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_whitespaces();
+        if self.text.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespaces();
+        match self.text.get(self.pos).cloned() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::string(self.string()?)),
+            Some(b'-') | Some(b'0'...b'9') => self.number(),
+            _ if self.skip("true") => Ok(Value::Bool(true)),
+            _ if self.skip("false") => Ok(Value::Bool(false)),
+            _ if self.skip("null") => Ok(Value::Null),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1; // {
+        let mut npp = vec![];
+        self.skip_whitespaces();
+        if self.text.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Value::object_from_npp(&npp));
+        }
+        loop {
+            self.skip_whitespaces();
+            if self.text.get(self.pos) != Some(&b'"') {
+                return Err(self.unexpected());
+            }
+            let name = self.string()?;
+            self.expect(b':')?;
+            let val = self.value()?;
+            npp.push((name, Property::new(val)));
+
+            self.skip_whitespaces();
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::object_from_npp(&npp));
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1; // [
+        let mut elems = vec![];
+        self.skip_whitespaces();
+        if self.text.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Value::array_from_elems(elems));
+        }
+        loop {
+            elems.push(self.value()?);
+            self.skip_whitespaces();
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::array_from_elems(elems));
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let digits = |parser: &mut Parser| {
+            let start = parser.pos;
+            while let Some(b'0'...b'9') = parser.text.get(parser.pos) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+
+        self.skip("-");
+        if !self.skip("0") && !digits(self) {
+            return Err(self.unexpected());
+        }
+        if self.skip(".") && !digits(self) {
+            return Err(self.unexpected());
+        }
+        if let Some(b'e') | Some(b'E') = self.text.get(self.pos) {
+            self.pos += 1;
+            if !self.skip("+") {
+                self.skip("-");
+            }
+            if !digits(self) {
+                return Err(self.unexpected());
+            }
+        }
+
+        let number = String::from_utf8_lossy(&self.text[start..self.pos]);
+        Ok(Value::Number(number.parse().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1; // "
+        let mut utf16 = vec![];
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(c) = self.text.get(self.pos) {
+                if *c == b'"' || *c == b'\\' || *c < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            if self.pos > start {
+                s += &String::from_utf8_lossy(&self.text[start..self.pos]);
+            }
+
+            match self.text.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.text.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\x08',
+                        Some(b'f') => '\x0c',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            // A surrogate pair is written as two escapes.
+                            utf16.clear();
+                            while self.text.get(self.pos) == Some(&b'u') {
+                                let hex = self.text.get(self.pos + 1..self.pos + 5);
+                                let code = hex
+                                    .and_then(|hex| ::std::str::from_utf8(hex).ok())
+                                    .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+                                match code {
+                                    Some(code) => utf16.push(code),
+                                    None => return Err(self.unexpected()),
+                                }
+                                self.pos += 5;
+                                if !self.text[self.pos..].starts_with(b"\\u") {
+                                    break;
+                                }
+                                self.pos += 1;
+                            }
+                            s += &String::from_utf16_lossy(&utf16);
+                            continue;
+                        }
+                        _ => return Err(self.unexpected()),
+                    };
+                    s.push(c);
+                    self.pos += 1;
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let val = parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"\u3042\ud83d\ude00"}} "#);
+    let val = val.unwrap();
+    assert_eq!(
+        val.get_property(Value::string("a".to_string()), None)
+            .format(1, false),
+        "[1,-25,true,null]".to_string()
+    );
+    assert_eq!(
+        val.get_property(Value::string("b".to_string()), None)
+            .format(1, false),
+        "{c:'x\"あ😀'}".to_string()
+    );
+    assert_eq!(
+        parse(r#"{"a": 1, "a": 2}"#).unwrap().format(1, false),
+        "{a:2}".to_string()
+    );
+    assert_eq!(
+        parse("[1, [2, {}], []]").unwrap().format(3, false),
+        "[1,[2,{}],[]]".to_string()
+    );
+    assert_eq!(
+        parse("{\"a\": 1,}").unwrap_err(),
+        "Unexpected token } in JSON at position 8"
+    );
+    assert_eq!(parse("[1").unwrap_err(), "Unexpected end of JSON input");
+    assert!(parse("01").is_err());
+    assert!(parse("1.").is_err());
+}
@@ -1,227 +1,292 @@
 //use libc;
+use builtin::BuiltinFuncTy;
+#[cfg(feature = "jit")]
 use builtin::BuiltinJITFuncInfo;
+#[cfg(feature = "jit")]
 use jit::TracingJit;
+#[cfg(feature = "jit")]
 use llvm::core::*;
-use rand::random;
+use std::f64::consts;
+#[cfg(feature = "jit")]
 use std::ffi::CString;
 use vm::value::*;
 use vm::{error::RuntimeError, vm::VM};
 
+/// Value properties of Math. https://tc39.github.io/ecma262/#sec-value-properties-of-the-math-object
+static CONSTANTS: &[(&str, f64)] = &[
+    ("E", consts::E),
+    ("LN10", consts::LN_10),
+    ("LN2", consts::LN_2),
+    ("LOG10E", consts::LOG10_E),
+    ("LOG2E", consts::LOG2_E),
+    ("PI", consts::PI),
+    ("SQRT1_2", consts::FRAC_1_SQRT_2),
+    ("SQRT2", consts::SQRT_2),
+];
+
+/// Function properties of Math. To add a new method, define it below and append it here.
+/// (floor and pow are registered separately since they have JIT implementations.)
+static METHODS: &[(&str, BuiltinFuncTy)] = &[
+    ("abs", math_abs),
+    ("acos", math_acos),
+    ("acosh", math_acosh),
+    ("asin", math_asin),
+    ("asinh", math_asinh),
+    ("atan", math_atan),
+    ("atanh", math_atanh),
+    ("atan2", math_atan2),
+    ("cbrt", math_cbrt),
+    ("ceil", math_ceil),
+    ("clz32", math_clz32),
+    ("cos", math_cos),
+    ("cosh", math_cosh),
+    ("exp", math_exp),
+    ("expm1", math_expm1),
+    ("fround", math_fround),
+    ("hypot", math_hypot),
+    ("imul", math_imul),
+    ("log", math_log),
+    ("log1p", math_log1p),
+    ("log10", math_log10),
+    ("log2", math_log2),
+    ("max", math_max),
+    ("min", math_min),
+    ("random", math_random),
+    ("round", math_round),
+    ("sign", math_sign),
+    ("sin", math_sin),
+    ("sinh", math_sinh),
+    ("sqrt", math_sqrt),
+    ("tan", math_tan),
+    ("tanh", math_tanh),
+    ("trunc", math_trunc),
+];
+
+/// Constants and methods of Math which have no JIT counterpart.
+fn properties() -> Vec<NamePropPair> {
+    let mut npp: Vec<NamePropPair> = vec![];
+
+    for &(name, val) in CONSTANTS {
+        npp.push((name.to_string(), Property::new(Value::Number(val))));
+    }
+
+    for &(name, func) in METHODS {
+        npp.push((
+            name.to_string(),
+            Property::new(Value::default_builtin_function(func)),
+        ));
+    }
+
+    npp
+}
+
+#[cfg(not(feature = "jit"))]
+pub fn init() -> Value {
+    let mut npp = properties();
+
+    for &(name, func) in &[("floor", math_floor as BuiltinFuncTy), ("pow", math_pow)] {
+        npp.push((
+            name.to_string(),
+            Property::new(Value::default_builtin_function(func)),
+        ));
+    }
+
+    Value::object_from_npp(&npp)
+}
+
+#[cfg(feature = "jit")]
 pub fn init(jit: TracingJit) -> Value {
-    make_object!(
-        PI:     Value::Number(::std::f64::consts::PI),
-        abs:    Value::default_builtin_function(math_abs),
-        acos:   Value::default_builtin_function(math_acos),
-        acosh:  Value::default_builtin_function(math_acosh),
-        asin:   Value::default_builtin_function(math_asin),
-        asinh:  Value::default_builtin_function(math_asinh),
-        atan:   Value::default_builtin_function(math_atan),
-        atanh:  Value::default_builtin_function(math_atanh),
-        atan2:  Value::default_builtin_function(math_atan2),
-        cbrt:   Value::default_builtin_function(math_cbrt),
-        ceil:   Value::default_builtin_function(math_ceil),
-        clz32:  Value::default_builtin_function(math_clz32),
-        cos:    Value::default_builtin_function(math_cos),
-        cosh:   Value::default_builtin_function(math_cosh),
-        exp:    Value::default_builtin_function(math_exp),
-        expm1:  Value::default_builtin_function(math_expm1),
-        fround: Value::default_builtin_function(math_fround),
-        hypot:  Value::default_builtin_function(math_hypot),
-        log:    Value::default_builtin_function(math_log),
-        log1p:  Value::default_builtin_function(math_log1p),
-        log10:  Value::default_builtin_function(math_log10),
-        log2:   Value::default_builtin_function(math_log2),
-        max:    Value::default_builtin_function(math_max),
-        min:    Value::default_builtin_function(math_min),
-        round:  Value::default_builtin_function(math_round),
-        sign:   Value::default_builtin_function(math_sign),
-        sin:    Value::default_builtin_function(math_sin),
-        sinh:   Value::default_builtin_function(math_sinh),
-        sqrt:   Value::default_builtin_function(math_sqrt),
-        tan:    Value::default_builtin_function(math_tan),
-        tanh:   Value::default_builtin_function(math_tanh),
-        trunc:  Value::default_builtin_function(math_trunc),
-        floor:  {
-            let llvm_func = unsafe {
-                LLVMAddFunction(
-                    jit.module,
-                    CString::new("jit_math_floor").unwrap().as_ptr(),
-                    LLVMFunctionType(
-                        LLVMDoubleTypeInContext(jit.context),
-                        vec![LLVMDoubleTypeInContext(jit.context)]
-                            .as_mut_slice()
-                            .as_mut_ptr(),
-                        1,
-                        0,
-                    ),
-                )
-            };
-            Value::builtin_function_with_jit(
-                math_floor,
-                BuiltinJITFuncInfo::Normal {
-                    func: jit_math_floor as *mut libc::c_void,
-                    llvm_func,
-                },
-            )
-        },
-        random: {
-            let llvm_func = unsafe {
-                LLVMAddFunction(
-                    jit.module,
-                    CString::new("jit_math_random").unwrap().as_ptr(),
-                    LLVMFunctionType(
-                        LLVMDoubleTypeInContext(jit.context),
-                        vec![].as_mut_slice().as_mut_ptr(),
-                        0,
-                        0,
-                    ),
-                )
-            };
-            Value::builtin_function_with_jit(
-                math_random,
-                BuiltinJITFuncInfo::Normal {
-                    func: jit_math_random as *mut libc::c_void,
-                    llvm_func,
-                },
-            )
+    let mut npp = properties();
+
+    npp.push((
+        "floor".to_string(),
+        Property::new(builtin_function_with_llvm_func(
+            &jit,
+            "jit_math_floor",
+            math_floor,
+            jit_math_floor as *mut libc::c_void,
+            1,
+        )),
+    ));
+    npp.push((
+        "pow".to_string(),
+        Property::new(builtin_function_with_llvm_func(
+            &jit,
+            "jit_math_pow",
+            math_pow,
+            jit_math_pow as *mut libc::c_void,
+            2,
+        )),
+    ));
+
+    Value::object_from_npp(&npp)
+}
+
+/// Declare the LLVM function 'jit_name' that takes 'argc' doubles and returns a double,
+/// and make a builtin function that is replaced with it in JIT-compiled code.
+#[cfg(feature = "jit")]
+fn builtin_function_with_llvm_func(
+    jit: &TracingJit,
+    jit_name: &str,
+    func: BuiltinFuncTy,
+    jit_func: *mut libc::c_void,
+    argc: u32,
+) -> Value {
+    let llvm_func = unsafe {
+        let mut params_ty = vec![LLVMDoubleTypeInContext(jit.context); argc as usize];
+        LLVMAddFunction(
+            jit.module,
+            CString::new(jit_name).unwrap().as_ptr(),
+            LLVMFunctionType(
+                LLVMDoubleTypeInContext(jit.context),
+                params_ty.as_mut_slice().as_mut_ptr(),
+                argc,
+                0,
+            ),
+        )
+    };
+    Value::builtin_function_with_jit(
+        func,
+        BuiltinJITFuncInfo::Normal {
+            func: jit_func,
+            llvm_func,
         },
-        pow:    {
-            let llvm_func = unsafe {
-                LLVMAddFunction(
-                    jit.module,
-                    CString::new("jit_math_pow").unwrap().as_ptr(),
-                    LLVMFunctionType(
-                        LLVMDoubleTypeInContext(jit.context),
-                        vec![
-                            LLVMDoubleTypeInContext(jit.context),
-                            LLVMDoubleTypeInContext(jit.context),
-                        ]
-                            .as_mut_slice()
-                            .as_mut_ptr(),
-                        2,
-                        0,
-                    ),
-                )
-            };
-            Value::builtin_function_with_jit(
-                math_pow,
-                BuiltinJITFuncInfo::Normal {
-                    func: jit_math_pow as *mut libc::c_void,
-                    llvm_func,
-                },
-            )
-                }
     )
 }
 
+/// ToNumber(args[n]). Missing arguments are NaN.
+fn arg_to_number(args: &Vec<Value>, n: usize) -> f64 {
+    match args.get(n) {
+        Some(val) => val.to_number(),
+        None => ::std::f64::NAN,
+    }
+}
+
 macro_rules! simple_math {
-    ($name:ident, $f:ident) => {
-        #[allow(unused_variables)]
-        fn $name(
-            vm: &mut VM,
-            args: &Vec<Value>,
-            callobj: CallObjectRef,
-        ) -> Result<(), RuntimeError> {
-            if let Value::Number(n) = args[0] {
-                vm.state.stack.push(Value::Number(n.$f()));
-                return Ok(());
-            }
-            vm.state.stack.push(Value::Undefined);
+    ($name:ident, $f:expr) => {
+        fn $name(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+            let n = arg_to_number(args, 0);
+            vm.state.stack.push(Value::Number($f(n)));
             Ok(())
         }
     };
 }
 
-simple_math!(math_floor, floor);
-simple_math!(math_abs, abs);
-simple_math!(math_acos, acos);
-simple_math!(math_acosh, acosh);
-simple_math!(math_asin, asin);
-simple_math!(math_asinh, asinh);
-simple_math!(math_atan, atan);
-simple_math!(math_atanh, atanh);
+simple_math!(math_floor, f64::floor);
+simple_math!(math_abs, f64::abs);
+simple_math!(math_acos, f64::acos);
+simple_math!(math_acosh, f64::acosh);
+simple_math!(math_asin, f64::asin);
+simple_math!(math_asinh, f64::asinh);
+simple_math!(math_atan, f64::atan);
+simple_math!(math_atanh, f64::atanh);
+simple_math!(math_cbrt, f64::cbrt);
+simple_math!(math_ceil, f64::ceil);
+simple_math!(math_clz32, clz32);
+simple_math!(math_cos, f64::cos);
+simple_math!(math_cosh, f64::cosh);
+simple_math!(math_exp, f64::exp);
+simple_math!(math_expm1, f64::exp_m1);
+simple_math!(math_fround, fround);
+simple_math!(math_log, f64::ln);
+simple_math!(math_log1p, f64::ln_1p);
+simple_math!(math_log10, f64::log10);
+simple_math!(math_log2, f64::log2);
+simple_math!(math_round, round);
+simple_math!(math_sign, sign);
+simple_math!(math_sin, f64::sin);
+simple_math!(math_sinh, f64::sinh);
+simple_math!(math_sqrt, f64::sqrt);
+simple_math!(math_tan, f64::tan);
+simple_math!(math_tanh, f64::tanh);
+simple_math!(math_trunc, f64::trunc);
 
-fn math_atan2(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(n1) = args[0] {
-        if let Value::Number(n2) = args[1] {
-            vm.state.stack.push(Value::Number(n1.atan2(n2)));
-            return Ok(());
-        }
-        vm.state.stack.push(Value::Undefined)
+/// https://tc39.github.io/ecma262/#sec-touint32
+fn to_uint32(n: f64) -> u32 {
+    if !n.is_finite() {
+        return 0;
     }
-    Ok(())
+    let modulo = 4294967296.0;
+    ((n.trunc() % modulo + modulo) % modulo) as u32
 }
-simple_math!(math_cbrt, cbrt);
-simple_math!(math_ceil, ceil);
-
-fn math_clz32(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(n) = args[0] {
-        vm.state.stack.push(Value::Number(if n == 0.0 {
-            32.0
-        } else {
-            // TODO: >> ? >>> ?
-            31.0 - ((n as i32 >> 0) as f64 * ::std::f64::consts::LOG2_E)
-                .log(::std::f64::consts::E)
-                .floor()
-        }));
-        return Ok(());
-    }
-    vm.state.stack.push(Value::Undefined);
-    Ok(())
+
+fn clz32(n: f64) -> f64 {
+    to_uint32(n).leading_zeros() as f64
 }
-simple_math!(math_cos, cos);
-simple_math!(math_cosh, cosh);
-simple_math!(math_exp, exp);
-simple_math!(math_expm1, exp_m1);
-simple_math!(math_fround, round);
 
-fn math_hypot(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    let mut sum2 = 0.0;
-    for n in args {
-        if let Value::Number(n) = n {
-            sum2 += n * n;
-        }
+fn fround(n: f64) -> f64 {
+    n as f32 as f64
+}
+
+/// Rounds half toward +Infinity, unlike f64::round.
+fn round(n: f64) -> f64 {
+    let floor = n.floor();
+    let rounded = if n - floor >= 0.5 { floor + 1.0 } else { floor };
+    // Math.round(-0.4) is -0
+    if rounded == 0.0 && n < 0.0 {
+        -0.0
+    } else {
+        rounded
     }
-    vm.state.stack.push(Value::Number(sum2.sqrt()));
-;    Ok(())
 }
 
-fn math_log(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(n1) = args[0] {
-        vm.state
-            .stack
-            .push(Value::Number(n1.log(::std::f64::consts::E)));
-        return Ok(());
+fn sign(n: f64) -> f64 {
+    if n.is_nan() || n == 0.0 {
+        n
+    } else if n > 0.0 {
+        1.0
+    } else {
+        -1.0
     }
-    vm.state.stack.push(Value::Undefined);
+}
+
+fn math_atan2(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let y = arg_to_number(args, 0);
+    let x = arg_to_number(args, 1);
+    vm.state.stack.push(Value::Number(y.atan2(x)));
     Ok(())
 }
 
-fn math_log1p(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(n1) = args[0] {
-        vm.state
-            .stack
-            .push(Value::Number(n1.log(1.0 + ::std::f64::consts::E)));
-        return Ok(());
+fn math_hypot(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut sum2 = 0.0;
+    let mut is_nan = false;
+    for i in 0..args.len() {
+        let n = arg_to_number(args, i);
+        if n.is_infinite() {
+            vm.state.stack.push(Value::Number(::std::f64::INFINITY));
+            return Ok(());
+        }
+        is_nan |= n.is_nan();
+        sum2 += n * n;
     }
-    vm.state.stack.push(Value::Undefined);
+    vm.state.stack.push(Value::Number(if is_nan {
+        ::std::f64::NAN
+    } else {
+        sum2.sqrt()
+    }));
     Ok(())
 }
 
-simple_math!(math_log10, log10);
-simple_math!(math_log2, log2);
+fn math_imul(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let a = to_uint32(arg_to_number(args, 0));
+    let b = to_uint32(arg_to_number(args, 1));
+    vm.state
+        .stack
+        .push(Value::Number(a.wrapping_mul(b) as i32 as f64));
+    Ok(())
+}
 
 fn math_max(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    let mut max = if let Value::Number(n) = args[0] {
-        n
-    } else {
-        0.0
-    };
-    for n in args[1..].iter() {
-        if let Value::Number(n) = n {
-            if n > &max {
-                max = *n;
-            }
+    let mut max = ::std::f64::NEG_INFINITY;
+    for i in 0..args.len() {
+        let n = arg_to_number(args, i);
+        if n.is_nan() {
+            max = n;
+            break;
+        }
+        // +0 is larger than -0
+        if n > max || (n == 0.0 && max == 0.0 && max.is_sign_negative()) {
+            max = n;
         }
     }
     vm.state.stack.push(Value::Number(max));
@@ -229,59 +294,55 @@ fn math_max(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), Runt
 }
 
 fn math_min(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    let mut min = if let Value::Number(n) = args[0] {
-        n
-    } else {
-        0.0
-    };
-    for n in args[1..].iter() {
-        if let Value::Number(n) = n {
-            if n < &min {
-                min = *n;
-            }
+    let mut min = ::std::f64::INFINITY;
+    for i in 0..args.len() {
+        let n = arg_to_number(args, i);
+        if n.is_nan() {
+            min = n;
+            break;
+        }
+        // -0 is smaller than +0
+        if n < min || (n == 0.0 && min == 0.0 && n.is_sign_negative()) {
+            min = n;
         }
     }
     vm.state.stack.push(Value::Number(min));
     Ok(())
 }
 
-simple_math!(math_round, round);
-
-fn math_sign(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(n) = args[0] {
-        vm.state.stack.push(Value::Number(if n == 0.0 {
-            n
-        } else if n > 0.0 {
-            1.0
-        } else {
-            -1.0
-        }));
-        return Ok(());
-    }
-    vm.state.stack.push(Value::Undefined);
+/// Math.random() steps the xorshift generator whose state is VM::random_state. It has no JIT
+/// counterpart, which could not reach the state of the VM.
+fn math_random(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut x = vm.random_state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    vm.random_state = x;
+    // the upper 53 bits, so that the result is below 1.
+    let n = (x >> 11) as f64 / (1u64 << 53) as f64;
+    vm.state.stack.push(Value::Number(n));
     Ok(())
 }
 
-simple_math!(math_sin, sin);
-simple_math!(math_sinh, sinh);
-simple_math!(math_sqrt, sqrt);
-simple_math!(math_tan, tan);
-simple_math!(math_tanh, tanh);
-simple_math!(math_trunc, trunc);
-
-fn math_random(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    vm.state.stack.push(Value::Number(random::<f64>()));
-    Ok(())
+/// the state of the generator of Math.random() which 'seed' gives. The same seed gives the same
+/// sequence, so that the output of scripts is reproducible.
+pub fn random_state(seed: u64) -> u64 {
+    // splitmix64, so that close seeds give unrelated states. xorshift needs a nonzero state.
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    if z == 0 {
+        0xf6d582196d588cac
+    } else {
+        z
+    }
 }
 
 fn math_pow(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
-    if let Value::Number(f1) = args[0] {
-        if let Value::Number(f2) = args[1] {
-            vm.state.stack.push(Value::Number(f1.powf(f2)));
-            return Ok(());
-        }
-    }
-    vm.state.stack.push(Value::Undefined);
+    let x = arg_to_number(args, 0);
+    let y = arg_to_number(args, 1);
+    vm.state.stack.push(Value::Number(jit_math_pow(x, y)));
     Ok(())
 }
 
@@ -292,19 +353,11 @@ pub extern "C" fn jit_math_floor(n: f64) -> f64 {
     n.floor()
 }
 
-// TODO: Find a better way for rand gen. (rand::random is slow)
-pub static mut MATH_RAND_SEED: u64 = 0xf6d582196d588cac;
-#[no_mangle]
-pub extern "C" fn jit_math_random() -> f64 {
-    unsafe {
-        MATH_RAND_SEED = MATH_RAND_SEED ^ (MATH_RAND_SEED << 13);
-        MATH_RAND_SEED = MATH_RAND_SEED ^ (MATH_RAND_SEED >> 17);
-        MATH_RAND_SEED = MATH_RAND_SEED ^ (MATH_RAND_SEED << 5);
-        (MATH_RAND_SEED as f64) / ::std::u64::MAX as f64
-    }
-}
-
+/// https://tc39.github.io/ecma262/#sec-applying-the-exp-operator
 #[no_mangle]
 pub extern "C" fn jit_math_pow(x: f64, y: f64) -> f64 {
+    if y.is_nan() || (x.abs() == 1.0 && y.is_infinite()) {
+        return ::std::f64::NAN;
+    }
     x.powf(y)
 }
@@ -1,7 +1,34 @@
 pub mod array;
+pub mod arraybuffer;
+pub mod bigint;
+pub mod boolean;
+pub mod child_process;
+pub mod console;
+pub mod crypto;
+pub mod dataview;
 pub mod date;
 pub mod error;
+pub mod eval;
+pub mod fetch;
+pub mod fs;
 pub mod function;
+pub mod gc;
+pub mod intl;
+pub mod json;
 pub mod math;
 pub mod number;
 pub mod object;
+pub mod os;
+pub mod path;
+pub mod performance;
+pub mod process;
+pub mod promise;
+pub mod string;
+pub mod structured_clone;
+pub mod text_encoding;
+pub mod typedarray;
+pub mod uri;
+pub mod util;
+pub mod wasm;
+pub mod weak;
+pub mod worker;
@@ -1,22 +1,253 @@
+use builtins::intl;
 use vm::{error::RuntimeError, value::*, vm::VM};
 
 thread_local!(
-    pub static NUMBER_PROTOTYPE: Value =
-        { make_object!(toString: Value::default_builtin_function(number_prototype_tostring)) };
+    pub static NUMBER_PROTOTYPE: Value = {
+        make_object!(
+            toString:       Value::default_builtin_function(number_prototype_tostring),
+            toFixed:        Value::default_builtin_function(number_prototype_tofixed),
+            toPrecision:    Value::default_builtin_function(number_prototype_toprecision),
+            toLocaleString: Value::default_builtin_function(number_prototype_tolocalestring),
+            valueOf:        Value::default_builtin_function(number_prototype_valueof)
+        )
+    };
 );
 
-pub fn number_prototype_tostring(
+pub fn init() -> Value {
+    let mut prototype = NUMBER_PROTOTYPE.with(|x| x.clone());
+    // Number constructor
+    let obj = Value::builtin_function(
+        number,
+        None,
+        &mut make_npp!(
+            EPSILON:            Value::Number(::std::f64::EPSILON),
+            MAX_SAFE_INTEGER:   Value::Number(9007199254740991.0),
+            MIN_SAFE_INTEGER:   Value::Number(-9007199254740991.0),
+            MAX_VALUE:          Value::Number(::std::f64::MAX),
+            MIN_VALUE:          Value::Number(5e-324),
+            NaN:                Value::Number(::std::f64::NAN),
+            POSITIVE_INFINITY:  Value::Number(::std::f64::INFINITY),
+            NEGATIVE_INFINITY:  Value::Number(::std::f64::NEG_INFINITY),
+            isFinite:           Value::default_builtin_function(number_is_finite),
+            isInteger:          Value::default_builtin_function(number_is_integer),
+            isNaN:              Value::default_builtin_function(number_is_nan),
+            isSafeInteger:      Value::default_builtin_function(number_is_safe_integer),
+            parseFloat:         Value::default_builtin_function(parse_float),
+            parseInt:           Value::default_builtin_function(parse_int)
+        ),
+        Some(prototype.clone()),
+    );
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-number-constructor-number-value
+pub fn number(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let n = match args.get(0) {
+        Some(val) => val.to_number(),
+        None => 0.0,
+    };
+    vm.set_return_value(Value::Number(n));
+    Ok(())
+}
+
+/// new Number(value)
+pub fn number_new(
     vm: &mut VM,
     args: &Vec<Value>,
     callobj: CallObjectRef,
 ) -> Result<(), RuntimeError> {
-    let number = if let Value::Number(num) = *callobj.this {
-        num
+    number(vm, args, callobj)?;
+    let n = vm.state.stack.pop().unwrap();
+    vm.set_return_value(Value::primitive_wrapper(n));
+    Ok(())
+}
+
+fn number_is_finite(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(match args.get(0) {
+        Some(Value::Number(n)) => n.is_finite(),
+        _ => false,
+    }));
+    Ok(())
+}
+
+fn number_is_integer(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(match args.get(0) {
+        Some(Value::Number(n)) => n.is_finite() && n.trunc() == *n,
+        _ => false,
+    }));
+    Ok(())
+}
+
+fn number_is_nan(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(match args.get(0) {
+        Some(Value::Number(n)) => n.is_nan(),
+        _ => false,
+    }));
+    Ok(())
+}
+
+fn number_is_safe_integer(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Bool(match args.get(0) {
+        Some(Value::Number(n)) => n.trunc() == *n && n.abs() <= 9007199254740991.0,
+        _ => false,
+    }));
+    Ok(())
+}
+
+/// global isFinite(). Unlike Number.isFinite(), the argument is converted to Number.
+pub fn is_finite(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let n = args.get(0).unwrap_or(&Value::Undefined).to_number();
+    vm.set_return_value(Value::Bool(n.is_finite()));
+    Ok(())
+}
+
+/// global isNaN(). Unlike Number.isNaN(), the argument is converted to Number.
+pub fn is_nan(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let n = args.get(0).unwrap_or(&Value::Undefined).to_number();
+    vm.set_return_value(Value::Bool(n.is_nan()));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-parsefloat-string
+pub fn parse_float(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let string = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let s = string.trim_left_matches(is_js_whitespace);
+    let bytes = s.as_bytes();
+
+    let mut i = 0;
+    if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+        i += 1;
+    }
+
+    if s[i..].starts_with("Infinity") {
+        vm.set_return_value(Value::Number(if bytes[0] == b'-' {
+            ::std::f64::NEG_INFINITY
+        } else {
+            ::std::f64::INFINITY
+        }));
+        return Ok(());
+    }
+
+    let skip_digits = |mut i: usize| -> usize {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    // Find the longest prefix that satisfies the syntax of a StrDecimalLiteral.
+    let int_start = i;
+    i = skip_digits(i);
+    let mut has_digits = i > int_start;
+    if i < bytes.len() && bytes[i] == b'.' {
+        let frac_start = i + 1;
+        let frac_end = skip_digits(frac_start);
+        if has_digits || frac_end > frac_start {
+            has_digits = true;
+            i = frac_end;
+        }
+    }
+
+    if !has_digits {
+        vm.set_return_value(Value::Number(::std::f64::NAN));
+        return Ok(());
+    }
+
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        let mut j = i + 1;
+        if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+            j += 1;
+        }
+        let exp_end = skip_digits(j);
+        if exp_end > j {
+            i = exp_end;
+        }
+    }
+
+    let n = s[..i]
+        .trim_right_matches('.')
+        .parse::<f64>()
+        .unwrap_or(::std::f64::NAN);
+    vm.set_return_value(Value::Number(n));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-parseint-string-radix
+pub fn parse_int(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let string = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let mut s = string.trim_left_matches(is_js_whitespace);
+
+    let sign = if s.starts_with('-') { -1.0 } else { 1.0 };
+    if s.starts_with('-') || s.starts_with('+') {
+        s = &s[1..];
+    }
+
+    let radix = match args.get(1) {
+        Some(radix) => radix.to_number(),
+        None => 0.0,
+    };
+    let mut radix = if radix.is_finite() {
+        radix.trunc() as i32
     } else {
-        vm.set_return_value(Value::Undefined);
+        0
+    };
+
+    let has_hex_prefix = s.starts_with("0x") || s.starts_with("0X");
+    if radix == 0 {
+        radix = if has_hex_prefix { 16 } else { 10 };
+    }
+    if radix == 16 && has_hex_prefix {
+        s = &s[2..];
+    }
+
+    if radix < 2 || 36 < radix {
+        vm.set_return_value(Value::Number(::std::f64::NAN));
+        return Ok(());
+    }
+
+    let digits: String = s.chars().take_while(|c| c.is_digit(radix as u32)).collect();
+
+    if digits.is_empty() {
+        vm.set_return_value(Value::Number(::std::f64::NAN));
         return Ok(());
+    }
+
+    let n = if radix == 10 {
+        digits.parse::<f64>().unwrap()
+    } else {
+        digits.chars().fold(0.0, |acc, c| {
+            acc * radix as f64 + c.to_digit(radix as u32).unwrap() as f64
+        })
     };
 
+    vm.set_return_value(Value::Number(sign * n));
+    Ok(())
+}
+
+/// get 'this' as a number for Number.prototype methods.
+fn this_number_value(callobj: &CallObjectRef) -> Result<f64, RuntimeError> {
+    match *callobj.this {
+        Value::Number(n) => Ok(n),
+        Value::Object(_, ObjectKind::PrimitiveWrapper(box Value::Number(n))) => Ok(n),
+        _ => Err(RuntimeError::Type(
+            "type error: Number.prototype method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+pub fn number_prototype_tostring(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let number = this_number_value(&callobj)?;
+
     let base = match args.get(0) {
         Some(val) => {
             let num = val.to_number();
@@ -29,18 +260,208 @@ pub fn number_prototype_tostring(
         _ => 10,
     };
 
-    vm.set_return_value(Value::string(f64_to_string(number, base)));
+    vm.set_return_value(Value::string(if base == 10 {
+        number_to_string(number)
+    } else {
+        f64_to_string(number, base)
+    }));
+
+    Ok(())
+}
 
+fn number_prototype_valueof(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let number = this_number_value(&callobj)?;
+    vm.set_return_value(Value::Number(number));
     Ok(())
 }
 
+/// https://tc39.github.io/ecma262/#sec-number.prototype.tofixed
+fn number_prototype_tofixed(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let x = this_number_value(&callobj)?;
+    let f = match args.get(0) {
+        Some(val) => val.to_number(),
+        None => 0.0,
+    };
+    let f = if f.is_nan() { 0.0 } else { f.trunc() };
+
+    if f < 0.0 || 100.0 < f {
+        return Err(RuntimeError::General(
+            "range error: toFixed() digits argument must be between 0 and 100".to_string(),
+        ));
+    }
+
+    if !x.is_finite() || x.abs() >= 1e21 {
+        vm.set_return_value(Value::string(number_to_string(x)));
+        return Ok(());
+    }
+
+    let mut s = to_fixed(x.abs(), f as usize);
+    if x < 0.0 {
+        s.insert(0, '-');
+    }
+
+    vm.set_return_value(Value::string(s));
+    Ok(())
+}
+
+/// format non-negative finite 'x' with 'f' fraction digits, rounding ties up. e.g. 2.5 -> "3"
+pub fn to_fixed(x: f64, f: usize) -> String {
+    // Every finite f64 has at most 1074 fraction digits, so this is exact.
+    let exact = format!("{:.1074}", x);
+    let point = exact.find('.').unwrap();
+    let mut digits: Vec<u8> = exact[..point]
+        .bytes()
+        .chain(exact[point + 1..point + 1 + f].bytes())
+        .collect();
+    // If there are two nearest candidates, pick the larger one.
+    if exact.as_bytes()[point + 1 + f] >= b'5' {
+        increment_digits(&mut digits);
+    }
+
+    let int_len = digits.len() - f;
+    let mut s = String::from_utf8(digits).unwrap();
+    if f > 0 {
+        s.insert(int_len, '.');
+    }
+    s
+}
+
+/// Number.prototype.toLocaleString([locales[, options]]), formatted as by Intl.NumberFormat.
+fn number_prototype_tolocalestring(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let x = this_number_value(&callobj)?;
+    let s = intl::format_number(args.get(0), args.get(1), x)?;
+    vm.set_return_value(Value::string(s));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-number.prototype.toprecision
+fn number_prototype_toprecision(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let x = this_number_value(&callobj)?;
+    let p = match args.get(0) {
+        Some(Value::Undefined) | None => {
+            vm.set_return_value(Value::string(number_to_string(x)));
+            return Ok(());
+        }
+        Some(val) => val.to_number(),
+    };
+    let p = if p.is_nan() { 0.0 } else { p.trunc() };
+
+    if !x.is_finite() {
+        vm.set_return_value(Value::string(number_to_string(x)));
+        return Ok(());
+    }
+
+    if p < 1.0 || 100.0 < p {
+        return Err(RuntimeError::General(
+            "range error: toPrecision() argument must be between 1 and 100".to_string(),
+        ));
+    }
+    let p = p as i32;
+
+    let (mut digits, e) = if x == 0.0 {
+        (vec![b'0'; p as usize], 0)
+    } else {
+        let (digits, exponent) = exact_decimal_digits(x.abs());
+        round_digits(digits, exponent, p)
+    };
+    digits.resize(p as usize, b'0');
+    let digits = String::from_utf8(digits).unwrap();
+
+    let s = if e < -6 || e >= p {
+        let (first, rest) = digits.split_at(1);
+        format!(
+            "{}{}{}e{}{}",
+            first,
+            if rest.is_empty() { "" } else { "." },
+            rest,
+            if e < 0 { "-" } else { "+" },
+            e.abs()
+        )
+    } else if e == p - 1 {
+        digits
+    } else if e >= 0 {
+        let (int, frac) = digits.split_at(e as usize + 1);
+        format!("{}.{}", int, frac)
+    } else {
+        format!("0.{}{}", "0".repeat((-e - 1) as usize), digits)
+    };
+
+    vm.set_return_value(Value::string(if x < 0.0 { format!("-{}", s) } else { s }));
+    Ok(())
+}
+
+/// get the significant digits of the exact decimal expansion of positive finite 'x',
+/// and the exponent of the first digit. e.g. 12.5 -> ("125", 1)
+fn exact_decimal_digits(x: f64) -> (Vec<u8>, i32) {
+    // Every finite f64 has at most 1074 fraction digits.
+    let exact = format!("{:.1074}", x);
+    let point = exact.find('.').unwrap() as i32;
+    let all: Vec<u8> = exact.bytes().filter(|b| *b != b'.').collect();
+    let first_nonzero = all.iter().position(|b| *b != b'0').unwrap_or(0);
+    let last_nonzero = all.iter().rposition(|b| *b != b'0').unwrap_or(0);
+    (
+        all[first_nonzero..last_nonzero + 1].to_vec(),
+        point - 1 - first_nonzero as i32,
+    )
+}
+
+/// round 'digits' to 'len' significant digits. ties are rounded up.
+/// returns the rounded digits (may be shorter than 'len') and the new exponent.
+fn round_digits(mut digits: Vec<u8>, mut exponent: i32, len: i32) -> (Vec<u8>, i32) {
+    let len = len as usize;
+    if digits.len() <= len {
+        return (digits, exponent);
+    }
+
+    let round_up = digits[len] >= b'5';
+    digits.truncate(len);
+
+    if round_up && increment_digits(&mut digits) {
+        // e.g. 999 -> 1000
+        digits.truncate(len);
+        exponent += 1;
+    }
+
+    (digits, exponent)
+}
+
+/// add 1 to the last digit of decimal 'digits'. returns true if a new digit is prepended.
+fn increment_digits(digits: &mut Vec<u8>) -> bool {
+    for digit in digits.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            return false;
+        }
+    }
+    digits.insert(0, b'1');
+    true
+}
+
 // TODO: Maybe, this function had better be somewhere else. (like ./src/util.rs)
 fn f64_to_string(f: f64, radix: usize) -> String {
     if f.is_nan() {
         return "NaN".to_string();
     }
     if f.is_infinite() {
-        return "Infinity".to_string();
+        return if f < 0.0 { "-Infinity" } else { "Infinity" }.to_string();
     }
 
     let chars = "0123456789abcdefghijklmnopqrstuvwxyz";
@@ -56,6 +477,9 @@ fn f64_to_string(f: f64, radix: usize) -> String {
     }
 
     s = s.chars().rev().collect();
+    if s.is_empty() {
+        s.push('0');
+    }
     if fraction != 0.0 {
         s.push('.');
     }
@@ -1,3 +1,4 @@
+use builtins::function::call_with_this;
 use vm::{error::RuntimeError, value::*, vm::VM};
 
 thread_local!(
@@ -5,8 +6,12 @@ thread_local!(
         // can not use Value::object_from_npp() here.
         { Value::Object(
             Value::propmap_from_npp(&make_npp!(
-                __proto__: Value::Null,
-                toString: Value::default_builtin_function(to_string)
+                __proto__:            Value::Null,
+                hasOwnProperty:       Value::default_builtin_function(has_own_property),
+                isPrototypeOf:        Value::default_builtin_function(is_prototype_of),
+                propertyIsEnumerable: Value::default_builtin_function(property_is_enumerable),
+                toString:             Value::default_builtin_function(to_string),
+                valueOf:              Value::default_builtin_function(value_of)
             )),
             ObjectKind::Ordinary
         ) };
@@ -18,7 +23,13 @@ pub fn init() -> Value {
     let obj = Value::builtin_function(
         new,
         None,
-        &mut make_npp!(create: Value::default_builtin_function(create)),
+        &mut make_npp!(
+            create:         Value::default_builtin_function(create),
+            fromEntries:    Value::default_builtin_function(from_entries),
+            getPrototypeOf: Value::default_builtin_function(get_prototype_of),
+            keys:           Value::default_builtin_function(keys),
+            setPrototypeOf: Value::default_builtin_function(set_prototype_of)
+        ),
         Some(prototype.clone()),
     );
     prototype.set_constructor(obj.clone());
@@ -60,17 +71,11 @@ fn create(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), Runtim
     };
 
     let obj = match maybe_obj {
-        Value::Object(map, ObjectKind::Ordinary) => {
-            let new_obj = Value::object_from_npp(&vec![]);
-            let proto = new_obj.get_property(Value::string("__proto__".to_string()), None);
-            for (name, prop) in (*map).iter() {
-                proto
-                    .clone()
-                    .set_property(Value::string(name.to_string()), prop.clone().val, None)
-            }
-            new_obj
+        Value::Object(_, _) | Value::Null => {
+            let mut map = Value::propmap_from_npp(&vec![]);
+            map.set_proto(maybe_obj.clone());
+            Value::object(map)
         }
-        Value::Null => Value::object_from_npp(&vec![]),
         _ => {
             return Err(RuntimeError::Type(
                 "type error: Object.create: 1st argument must be Object or null".to_string(),
@@ -83,10 +88,278 @@ fn create(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), Runtim
     Ok(())
 }
 
+/// Object.fromEntries(iterable) makes an object of [key, value] entries. There are no iterators
+/// yet, so arrays are read directly, and other objects are stepped by their next() as the
+/// iterators of arrays are.
+fn from_entries(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let entries = match args.get(0) {
+        Some(Value::Object(_, ObjectKind::Array(ary))) => ary.values()?,
+        Some(iter @ Value::Object(_, _)) => {
+            let next = iter.get_property(Value::string("next".to_string()), None);
+            match next {
+                Value::Object(_, ObjectKind::Function(_))
+                | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {}
+                _ => {
+                    return Err(RuntimeError::Type(
+                        "type error: Object.fromEntries: the argument is not iterable".to_string(),
+                    ))
+                }
+            }
+            let mut entries = vec![];
+            loop {
+                call_with_this(vm, &next, iter.clone(), &vec![])?;
+                let result = vm.state.stack.pop().unwrap_or(Value::Undefined);
+                if result
+                    .get_property(Value::string("done".to_string()), None)
+                    .to_boolean()
+                {
+                    break;
+                }
+                entries.push(result.get_property(Value::string("value".to_string()), None));
+            }
+            entries
+        }
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Object.fromEntries: the argument is not iterable".to_string(),
+            ))
+        }
+    };
+
+    let mut npp = vec![];
+    for entry in entries {
+        match entry {
+            Value::Object(_, _) => {}
+            _ => {
+                return Err(RuntimeError::Type(
+                    "type error: Object.fromEntries: an entry is not an object".to_string(),
+                ))
+            }
+        }
+        let key = entry.get_property(Value::Number(0.0), None);
+        let val = entry.get_property(Value::Number(1.0), None);
+        npp.push((key.to_string(), Property::new(val)));
+    }
+    vm.set_return_value(Value::object_from_npp(&npp));
+    Ok(())
+}
+
+/// Object.getPrototypeOf(obj)
+fn get_prototype_of(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    match args.get(0) {
+        None | Some(Value::Undefined) | Some(Value::Null) => Err(RuntimeError::Type(
+            "type error: Object.getPrototypeOf: Cannot convert undefined or null to object"
+                .to_string(),
+        )),
+        Some(obj) => {
+            vm.set_return_value(obj.prototype());
+            Ok(())
+        }
+    }
+}
+
+/// Object.keys(obj) returns the names of the enumerable own properties in the order of
+/// enumeration: the indices of the elements first, then the other names.
+fn keys(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let obj = match args.get(0) {
+        None | Some(Value::Undefined) | Some(Value::Null) => {
+            return Err(RuntimeError::Type(
+                "type error: Object.keys: Cannot convert undefined or null to object".to_string(),
+            ));
+        }
+        Some(obj) => obj,
+    };
+    let keys = own_enumerable_keys(obj)
+        .into_iter()
+        .map(|key| Value::string(key))
+        .collect();
+    vm.set_return_value(Value::array_from_elems(keys));
+    Ok(())
+}
+
+/// The names of the enumerable own properties of 'obj' in the order of enumeration: the indices
+/// of the elements of arrays, strings, typed arrays and arguments first, then the other names.
+pub fn own_enumerable_keys(obj: &Value) -> Vec<String> {
+    let mut keys = vec![];
+    match obj {
+        Value::String(s) | Value::Object(_, ObjectKind::PrimitiveWrapper(box Value::String(s))) => {
+            keys.extend((0..s.len()).map(|i| i.to_string()))
+        }
+        Value::Object(_, ObjectKind::Array(ary)) => keys.extend(
+            ary.elements()
+                .filter(|(_, prop)| prop.enumerable)
+                .map(|(i, _)| i.to_string()),
+        ),
+        Value::Object(_, ObjectKind::TypedArray(ta)) => {
+            keys.extend((0..ta.length).map(|i| i.to_string()))
+        }
+        Value::Object(_, ObjectKind::Arguments(callobj)) => {
+            keys.extend((0..callobj.get_arguments_length()).map(|i| i.to_string()))
+        }
+        _ => {}
+    }
+    if let Value::Object(map, _) = obj {
+        keys.extend(
+            map.iter()
+                .filter(|(_, prop)| prop.enumerable)
+                .map(|(key, _)| key.clone()),
+        );
+    }
+    keys
+}
+
+/// The property 'name' of 'options', the object of options given to builtin functions such as
+/// fs.readFileSync() and child_process.execSync(). undefined if 'options' is not an object.
+pub fn option(options: Option<&Value>, name: &str) -> Value {
+    match options {
+        Some(options @ Value::Object(_, ObjectKind::Ordinary)) => {
+            options.get_property(Value::string(name.to_string()), None)
+        }
+        _ => Value::Undefined,
+    }
+}
+
+/// Object.setPrototypeOf(obj, proto)
+fn set_prototype_of(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut obj = match args.get(0) {
+        None | Some(Value::Undefined) | Some(Value::Null) => {
+            return Err(RuntimeError::Type(
+                "type error: Object.setPrototypeOf: Cannot convert undefined or null to object"
+                    .to_string(),
+            ));
+        }
+        Some(obj) => obj.clone(),
+    };
+    match args.get(1) {
+        Some(proto @ Value::Object(_, _)) | Some(proto @ Value::Null) => {
+            obj.set_prototype(proto.clone())?
+        }
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Object.setPrototypeOf: Object prototype may only be an Object or null"
+                    .to_string(),
+            ));
+        }
+    }
+    vm.set_return_value(obj);
+    Ok(())
+}
+
 fn to_string(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
     let this = *callobj.this.clone();
-    let obj = Value::string(this.to_string());
+    let obj = Value::string(this.try_to_string()?);
     vm.set_return_value(obj);
 
     Ok(())
 }
+
+/// Whether 'key' is an own property of 'obj', and if so, whether it is enumerable. The elements
+/// and the length of arrays, strings and arguments are own properties too. __proto__ is not.
+fn own_property(obj: &Value, key: &Value) -> Option<bool> {
+    let is_length = match key {
+        Value::String(s) => **s == "length",
+        _ => false,
+    };
+    let index = array_index(key);
+    match obj {
+        Value::String(s) | Value::Object(_, ObjectKind::PrimitiveWrapper(box Value::String(s))) => {
+            match index {
+                Some(n) if n < s.len() => return Some(true),
+                _ if is_length => return Some(false),
+                _ => {}
+            }
+        }
+        Value::Object(_, ObjectKind::Array(ary)) => match index {
+            Some(n) => return ary.get(n).map(|prop| prop.enumerable),
+            None if is_length => return Some(false),
+            None => {}
+        },
+        Value::Object(_, ObjectKind::TypedArray(ta)) => match index {
+            Some(n) => return if n < ta.length { Some(true) } else { None },
+            None => {}
+        },
+        Value::Object(_, ObjectKind::Arguments(callobj)) => match index {
+            Some(n) if n < callobj.get_arguments_length() => return Some(true),
+            _ if is_length => return Some(false),
+            _ => {}
+        },
+        _ => {}
+    }
+    match obj {
+        Value::Object(map, _) => map.get(&key.to_string()).map(|prop| prop.enumerable),
+        _ => None,
+    }
+}
+
+fn this_object_coercible(callobj: &CallObjectRef, name: &str) -> Result<Value, RuntimeError> {
+    match *callobj.this {
+        Value::Undefined | Value::Null => Err(RuntimeError::Type(format!(
+            "type error: Object.prototype.{}: Cannot convert undefined or null to object",
+            name
+        ))),
+        ref this => Ok(this.clone()),
+    }
+}
+
+/// Object.prototype.hasOwnProperty(key)
+fn has_own_property(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let this = this_object_coercible(&callobj, "hasOwnProperty")?;
+    let key = args.get(0).cloned().unwrap_or(Value::Undefined);
+    vm.set_return_value(Value::Bool(own_property(&this, &key).is_some()));
+    Ok(())
+}
+
+/// Object.prototype.propertyIsEnumerable(key) is true if 'key' is an enumerable own property.
+fn property_is_enumerable(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let this = this_object_coercible(&callobj, "propertyIsEnumerable")?;
+    let key = args.get(0).cloned().unwrap_or(Value::Undefined);
+    vm.set_return_value(Value::Bool(own_property(&this, &key) == Some(true)));
+    Ok(())
+}
+
+/// Object.prototype.isPrototypeOf(obj) is true if 'this' is on the prototype chain of 'obj'.
+fn is_prototype_of(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut cur = match args.get(0) {
+        Some(obj @ Value::Object(_, _)) => obj.prototype(),
+        _ => {
+            vm.set_return_value(Value::Bool(false));
+            return Ok(());
+        }
+    };
+    let addr = match this_object_coercible(&callobj, "isPrototypeOf")? {
+        Value::Object(map, _) => map.addr(),
+        _ => {
+            vm.set_return_value(Value::Bool(false));
+            return Ok(());
+        }
+    };
+    let found = loop {
+        let next = match cur {
+            Value::Object(ref map, _) if map.addr() == addr => break true,
+            Value::Object(_, _) => cur.prototype(),
+            _ => break false,
+        };
+        cur = next;
+    };
+    vm.set_return_value(Value::Bool(found));
+    Ok(())
+}
+
+/// Object.prototype.valueOf()
+fn value_of(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let this = this_object_coercible(&callobj, "valueOf")?;
+    vm.set_return_value(this);
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use builtins::process;
+use libc;
+use std::env;
+use std::fs;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'os' module, loaded by require('os').
+
+pub fn init() -> Value {
+    let eol = if cfg!(windows) { "\r\n" } else { "\n" };
+    make_object!(
+        EOL:        Value::string(eol.to_string()),
+        platform:   Value::default_builtin_function(platform),
+        type:       Value::default_builtin_function(os_type),
+        arch:       Value::default_builtin_function(arch),
+        homedir:    Value::default_builtin_function(homedir),
+        tmpdir:     Value::default_builtin_function(tmpdir),
+        hostname:   Value::default_builtin_function(hostname),
+        cpus:       Value::default_builtin_function(cpus)
+    )
+}
+
+/// os.platform(), the same as process.platform.
+fn platform(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::string(process::platform().to_string()));
+    Ok(())
+}
+
+/// os.type(), the name of the operating system as uname(1) shows.
+fn os_type(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let name = match env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "Darwin",
+        "windows" => "Windows_NT",
+        "freebsd" => "FreeBSD",
+        os => os,
+    };
+    vm.set_return_value(Value::string(name.to_string()));
+    Ok(())
+}
+
+/// os.arch(), named as in Node.js. e.g. 'x64'.
+fn arch(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let arch = match env::consts::ARCH {
+        "x86_64" => "x64",
+        "x86" => "ia32",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    vm.set_return_value(Value::string(arch.to_string()));
+    Ok(())
+}
+
+/// os.homedir()
+fn homedir(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    let home = env::var(var).unwrap_or_default();
+    vm.set_return_value(Value::string(home));
+    Ok(())
+}
+
+/// os.tmpdir(), without a trailing separator.
+fn tmpdir(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let dir = env::temp_dir().to_string_lossy().to_string();
+    let dir = if dir.len() > 1 {
+        dir.trim_right_matches(|c| c == '/' || c == '\\')
+            .to_string()
+    } else {
+        dir
+    };
+    vm.set_return_value(Value::string(dir));
+    Ok(())
+}
+
+/// os.hostname()
+fn hostname(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        if libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) == 0 {
+            buf.iter().position(|&b| b == 0).unwrap_or(buf.len())
+        } else {
+            0
+        }
+    };
+    vm.set_return_value(Value::string(
+        String::from_utf8_lossy(&buf[..len]).to_string(),
+    ));
+    Ok(())
+}
+
+/// os.cpus(), an array of { model, speed } for each logical CPU. The model and the speed in MHz
+/// are read from /proc/cpuinfo where it exists, and are 'unknown' and 0 otherwise.
+fn cpus(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize;
+    let (mut models, mut speeds): (Vec<String>, Vec<f64>) = (vec![], vec![]);
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        for line in cpuinfo.lines() {
+            let mut kv = line.splitn(2, ':').map(|s| s.trim());
+            match (kv.next(), kv.next()) {
+                (Some("model name"), Some(model)) => models.push(model.to_string()),
+                (Some("cpu MHz"), Some(speed)) => speeds.push(speed.parse().unwrap_or(0.0)),
+                _ => {}
+            }
+        }
+    }
+
+    let cpus = (0..count)
+        .map(|n| {
+            let model = models.get(n).map_or("unknown", |model| model.as_str());
+            let speed = speeds.get(n).cloned().unwrap_or(0.0);
+            make_object!(
+                model:  Value::string(model.to_string()),
+                speed:  Value::Number(speed.floor())
+            )
+        })
+        .collect();
+    vm.set_return_value(Value::array_from_elems(cpus));
+    Ok(())
+}
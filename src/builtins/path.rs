@@ -0,0 +1,376 @@
+use builtin::BuiltinFuncTy;
+use std::env;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'path' module, loaded by require('path'). path.posix and path.win32 handle the paths of
+// each platform, and path itself is the one of the running platform.
+
+/// Name of the variable which tells the functions of path.win32 from the ones of path.posix.
+const WIN32: &str = "win32";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Posix,
+    Win32,
+}
+
+impl Style {
+    /// the style of the running platform.
+    pub fn native() -> Style {
+        if cfg!(windows) {
+            Style::Win32
+        } else {
+            Style::Posix
+        }
+    }
+
+    pub fn sep(self) -> char {
+        match self {
+            Style::Posix => '/',
+            Style::Win32 => '\\',
+        }
+    }
+
+    pub fn is_sep(self, c: char) -> bool {
+        c == '/' || (self == Style::Win32 && c == '\\')
+    }
+
+    /// Split 'path' into the device (e.g. 'C:' or '\\server\share' on Windows), whether it is
+    /// absolute, and the rest after the root.
+    fn parse_root(self, path: &str) -> (String, bool, String) {
+        let bytes = path.as_bytes();
+        let is_sep = |b: u8| self.is_sep(b as char);
+        if self == Style::Posix {
+            return (
+                "".to_string(),
+                path.starts_with('/'),
+                path.trim_left_matches('/').to_string(),
+            );
+        }
+
+        if bytes.len() >= 2 && is_sep(bytes[0]) && is_sep(bytes[1]) {
+            // UNC path: \\server\share\rest
+            let mut parts = path[2..].splitn(3, |c| self.is_sep(c));
+            match (parts.next(), parts.next()) {
+                (Some(server), Some(share)) if server != "" && share != "" => {
+                    let rest = parts.next().unwrap_or("");
+                    return (format!("\\\\{}\\{}", server, share), true, rest.to_string());
+                }
+                _ => {}
+            }
+        }
+        if bytes.len() >= 2 && (bytes[0] as char).is_ascii_alphabetic() && bytes[1] == b':' {
+            let is_absolute = bytes.len() >= 3 && is_sep(bytes[2]);
+            let rest = if is_absolute { &path[3..] } else { &path[2..] };
+            return (path[..2].to_string(), is_absolute, rest.to_string());
+        }
+        match bytes.first() {
+            Some(&b) if is_sep(b) => ("".to_string(), true, path[1..].to_string()),
+            _ => ("".to_string(), false, path.to_string()),
+        }
+    }
+
+    /// Resolve '.' and '..' in 'path', which has no root. '..' beyond the start is kept if
+    /// 'allow_above_root', or dropped.
+    fn normalize_segments(self, path: &str, allow_above_root: bool) -> String {
+        let mut segments: Vec<&str> = vec![];
+        for segment in path.split(|c| self.is_sep(c)) {
+            match segment {
+                "" | "." => {}
+                ".." => match segments.last() {
+                    Some(&last) if last != ".." => {
+                        segments.pop();
+                    }
+                    _ if allow_above_root => segments.push(".."),
+                    _ => {}
+                },
+                segment => segments.push(segment),
+            }
+        }
+        segments.join(&self.sep().to_string())
+    }
+
+    fn root(self, device: &str, is_absolute: bool) -> String {
+        let mut root = device.to_string();
+        if is_absolute {
+            root.push(self.sep());
+        }
+        root
+    }
+
+    pub fn normalize(self, path: &str) -> String {
+        if path.is_empty() {
+            return ".".to_string();
+        }
+        let (device, is_absolute, rest) = self.parse_root(path);
+        let trailing_sep = !rest.is_empty() && path.ends_with(|c| self.is_sep(c));
+        let mut tail = self.normalize_segments(&rest, !is_absolute);
+        if tail.is_empty() && !is_absolute {
+            tail.push('.');
+        }
+        if !tail.is_empty() && trailing_sep {
+            tail.push(self.sep());
+        }
+        self.root(&device, is_absolute) + &tail
+    }
+
+    pub fn join(self, paths: &[String]) -> String {
+        let paths: Vec<&str> = paths
+            .iter()
+            .filter(|path| !path.is_empty())
+            .map(|path| path.as_str())
+            .collect();
+        if paths.is_empty() {
+            return ".".to_string();
+        }
+        self.normalize(&paths.join(&self.sep().to_string()))
+    }
+
+    /// the absolute path made by resolving 'paths' from right to left, and then 'cwd', until
+    /// it becomes absolute.
+    pub fn resolve(self, paths: &[String], cwd: &str) -> String {
+        let (mut device, mut is_absolute, mut tail) = ("".to_string(), false, "".to_string());
+        for path in paths
+            .iter()
+            .rev()
+            .map(|path| path.as_str())
+            .chain(Some(cwd))
+        {
+            if path.is_empty() {
+                continue;
+            }
+            let (path_device, path_is_absolute, rest) = self.parse_root(path);
+            if !path_device.is_empty() {
+                if device.is_empty() {
+                    device = path_device;
+                } else if !device.eq_ignore_ascii_case(&path_device) {
+                    // a path on another drive.
+                    continue;
+                }
+            }
+            if !is_absolute {
+                tail = format!("{}{}{}", rest, self.sep(), tail);
+                is_absolute = path_is_absolute;
+            }
+            if is_absolute && (self == Style::Posix || !device.is_empty()) {
+                break;
+            }
+        }
+        let tail = self.normalize_segments(&tail, !is_absolute);
+        let resolved = self.root(&device, is_absolute) + &tail;
+        if resolved.is_empty() {
+            ".".to_string()
+        } else {
+            resolved
+        }
+    }
+
+    pub fn dirname(self, path: &str) -> String {
+        if path.is_empty() {
+            return ".".to_string();
+        }
+        let (device, is_absolute, rest) = self.parse_root(path);
+        let root = self.root(&device, is_absolute);
+        let rest = rest.trim_right_matches(|c| self.is_sep(c));
+        match rest.rfind(|c| self.is_sep(c)) {
+            Some(end) => root + rest[..end].trim_right_matches(|c| self.is_sep(c)),
+            None if root.is_empty() => ".".to_string(),
+            None => root,
+        }
+    }
+
+    /// the last part of 'path', without 'ext' if it ends with 'ext'.
+    pub fn basename(self, path: &str, ext: Option<&str>) -> String {
+        let (_, _, rest) = self.parse_root(path);
+        let rest = rest.trim_right_matches(|c| self.is_sep(c));
+        let base = match rest.rfind(|c| self.is_sep(c)) {
+            Some(start) => &rest[start + 1..],
+            None => rest,
+        };
+        match ext {
+            Some(ext) if !ext.is_empty() && base != ext && base.ends_with(ext) => {
+                base[..base.len() - ext.len()].to_string()
+            }
+            _ => base.to_string(),
+        }
+    }
+
+    /// the extension of the last part of 'path' from its last '.'. Empty if there is none, or
+    /// the name only starts with '.'.
+    pub fn extname(self, path: &str) -> String {
+        let base = self.basename(path, None);
+        match base.rfind('.') {
+            Some(0) | None => "".to_string(),
+            _ if base == ".." => "".to_string(),
+            Some(dot) => base[dot..].to_string(),
+        }
+    }
+
+    pub fn is_absolute(self, path: &str) -> bool {
+        self.parse_root(path).1
+    }
+}
+
+pub fn init() -> Value {
+    let mut path = make_module(Style::native());
+    path.set_property_with_name("posix".to_string(), make_module(Style::Posix));
+    path.set_property_with_name("win32".to_string(), make_module(Style::Win32));
+    path
+}
+
+fn make_module(style: Style) -> Value {
+    let func = |func: BuiltinFuncTy| {
+        let mut func = Value::default_builtin_function(func);
+        if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = func {
+            callobj.set_value(WIN32.to_string(), Value::Bool(style == Style::Win32));
+        }
+        func
+    };
+    let delimiter = match style {
+        Style::Posix => ":",
+        Style::Win32 => ";",
+    };
+    make_object!(
+        sep:            Value::string(style.sep().to_string()),
+        delimiter:      Value::string(delimiter.to_string()),
+        normalize:      func(normalize),
+        join:           func(join),
+        resolve:        func(resolve),
+        dirname:        func(dirname),
+        basename:       func(basename),
+        extname:        func(extname),
+        isAbsolute:     func(is_absolute)
+    )
+}
+
+fn style(callobj: &CallObjectRef) -> Result<Style, RuntimeError> {
+    Ok(if callobj.get_value(WIN32)?.to_boolean() {
+        Style::Win32
+    } else {
+        Style::Posix
+    })
+}
+
+/// get the argument 'n' of path.'func'(), which must be a string.
+fn string_arg(args: &Vec<Value>, n: usize, func: &str) -> Result<String, RuntimeError> {
+    match args.get(n) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        _ => Err(RuntimeError::Type(format!(
+            "type error: path.{}(): the arguments must be strings",
+            func
+        ))),
+    }
+}
+
+fn string_args(args: &Vec<Value>, func: &str) -> Result<Vec<String>, RuntimeError> {
+    (0..args.len()).map(|n| string_arg(args, n, func)).collect()
+}
+
+/// path.normalize(path)
+fn normalize(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = string_arg(args, 0, "normalize")?;
+    vm.set_return_value(Value::string(style(&callobj)?.normalize(&path)));
+    Ok(())
+}
+
+/// path.join(...paths)
+fn join(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let paths = string_args(args, "join")?;
+    vm.set_return_value(Value::string(style(&callobj)?.join(&paths)));
+    Ok(())
+}
+
+/// path.resolve(...paths). Relative paths are resolved from the current directory.
+fn resolve(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let paths = string_args(args, "resolve")?;
+    let cwd = env::current_dir()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+    vm.set_return_value(Value::string(style(&callobj)?.resolve(&paths, &cwd)));
+    Ok(())
+}
+
+/// path.dirname(path)
+fn dirname(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = string_arg(args, 0, "dirname")?;
+    vm.set_return_value(Value::string(style(&callobj)?.dirname(&path)));
+    Ok(())
+}
+
+/// path.basename(path[, ext])
+fn basename(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = string_arg(args, 0, "basename")?;
+    let ext = match args.get(1) {
+        Some(Value::Undefined) | None => None,
+        Some(_) => Some(string_arg(args, 1, "basename")?),
+    };
+    let base = style(&callobj)?.basename(&path, ext.as_ref().map(|ext| ext.as_str()));
+    vm.set_return_value(Value::string(base));
+    Ok(())
+}
+
+/// path.extname(path)
+fn extname(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = string_arg(args, 0, "extname")?;
+    vm.set_return_value(Value::string(style(&callobj)?.extname(&path)));
+    Ok(())
+}
+
+/// path.isAbsolute(path)
+fn is_absolute(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let path = string_arg(args, 0, "isAbsolute")?;
+    vm.set_return_value(Value::Bool(style(&callobj)?.is_absolute(&path)));
+    Ok(())
+}
+
+#[test]
+fn test_path() {
+    let posix = Style::Posix;
+    assert_eq!(posix.normalize("/a//b/../c/./d/"), "/a/c/d/");
+    assert_eq!(posix.normalize("../a/../../b"), "../../b");
+    assert_eq!(posix.normalize("a/.."), ".");
+    assert_eq!(posix.normalize("/.."), "/");
+    assert_eq!(
+        posix.join(&["a".to_string(), "".to_string(), "../b".to_string()]),
+        "b"
+    );
+    assert_eq!(
+        posix.resolve(&["b".to_string(), "../c".to_string()], "/x/y"),
+        "/x/y/c"
+    );
+    assert_eq!(
+        posix.resolve(&["/a".to_string(), "b/".to_string()], "/x"),
+        "/a/b"
+    );
+    assert_eq!(posix.dirname("/a/b/"), "/a");
+    assert_eq!(posix.dirname("/a"), "/");
+    assert_eq!(posix.dirname("a"), ".");
+    assert_eq!(posix.basename("/a/b.js", Some(".js")), "b");
+    assert_eq!(posix.basename("/a/b/", None), "b");
+    assert_eq!(posix.extname("a/b.tar.gz"), ".gz");
+    assert_eq!(posix.extname(".profile"), "");
+    assert_eq!(posix.extname("a."), ".");
+
+    let win32 = Style::Win32;
+    assert_eq!(win32.normalize("C:/a\\b/../c"), "C:\\a\\c");
+    assert_eq!(win32.normalize("C:"), "C:.");
+    assert_eq!(
+        win32.normalize("\\\\server\\share\\a\\..\\b"),
+        "\\\\server\\share\\b"
+    );
+    assert_eq!(
+        win32.join(&["C:\\a".to_string(), "b/c".to_string()]),
+        "C:\\a\\b\\c"
+    );
+    assert_eq!(
+        win32.resolve(&["D:\\x".to_string(), "y".to_string()], "C:\\cwd"),
+        "D:\\x\\y"
+    );
+    assert_eq!(win32.resolve(&["a".to_string()], "C:\\cwd"), "C:\\cwd\\a");
+    assert_eq!(win32.dirname("C:\\a\\b"), "C:\\a");
+    assert_eq!(win32.dirname("C:\\a"), "C:\\");
+    assert_eq!(win32.basename("C:\\a\\b.txt", Some(".txt")), "b");
+    assert!(win32.is_absolute("C:/a"));
+    assert!(!win32.is_absolute("C:a"));
+    assert!(win32.is_absolute("\\a"));
+}
@@ -0,0 +1,304 @@
+use builtins::error::new_error;
+use builtins::process;
+use chrono::Utc;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'performance' global. Times are milliseconds since the start of the process, measured by
+// a monotonic clock.
+
+/// internal property of performance, the array of the marks and measures recorded so far.
+const ENTRIES: &str = "[[Entries]]";
+
+pub fn init() -> Value {
+    let performance = make_object!(
+        now:                Value::default_builtin_function(now),
+        timeOrigin:         Value::Number(Utc::now().timestamp_millis() as f64 - now_millis()),
+        mark:               Value::default_builtin_function(mark),
+        measure:            Value::default_builtin_function(measure),
+        getEntries:         Value::default_builtin_function(get_entries),
+        getEntriesByName:   Value::default_builtin_function(get_entries_by_name),
+        getEntriesByType:   Value::default_builtin_function(get_entries_by_type),
+        clearMarks:         Value::default_builtin_function(clear_marks),
+        clearMeasures:      Value::default_builtin_function(clear_measures)
+    );
+    if let Value::Object(ref map, _) = performance {
+        map.clone().insert(
+            ENTRIES.to_string(),
+            Property {
+                val: Value::array_from_elems(vec![]),
+                writable: true,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+    performance
+}
+
+fn now_millis() -> f64 {
+    let elapsed = process::elapsed();
+    elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 / 1e6
+}
+
+/// the recorded entries, in the order they were recorded.
+fn entries(performance: &Value) -> Vec<Value> {
+    match performance.get_property(Value::string(ENTRIES.to_string()), None) {
+        Value::Object(_, ObjectKind::Array(array)) => {
+            array.elements().map(|(_, prop)| prop.val.clone()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Replace the recorded entries with 'new_entries'.
+fn set_entries(performance: &Value, new_entries: Vec<Value>) {
+    if let Value::Object(_, ObjectKind::Array(mut array)) =
+        performance.get_property(Value::string(ENTRIES.to_string()), None)
+    {
+        array.set_length(0);
+        for entry in new_entries {
+            array.push(entry);
+        }
+    }
+}
+
+fn add_entry(performance: &Value, entry: Value) {
+    let mut new_entries = entries(performance);
+    new_entries.push(entry);
+    set_entries(performance, new_entries);
+}
+
+fn entry_property(entry: &Value, name: &str) -> Value {
+    entry.get_property(Value::string(name.to_string()), None)
+}
+
+fn make_entry(
+    name: String,
+    entry_type: &str,
+    start_time: f64,
+    duration: f64,
+    detail: Value,
+) -> Value {
+    make_object!(
+        name:       Value::string(name),
+        entryType:  Value::string(entry_type.to_string()),
+        startTime:  Value::Number(start_time),
+        duration:   Value::Number(duration),
+        detail:     detail
+    )
+}
+
+/// the entries sorted by their start time, optionally only the ones of 'name' and 'entry_type'.
+fn find_entries(performance: &Value, name: Option<String>, entry_type: Option<String>) -> Value {
+    let mut found: Vec<Value> = entries(performance)
+        .into_iter()
+        .filter(|entry| {
+            name.as_ref().map_or(true, |name| {
+                entry_property(entry, "name").to_string() == *name
+            }) && entry_type.as_ref().map_or(true, |entry_type| {
+                entry_property(entry, "entryType").to_string() == *entry_type
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| {
+        let (a, b) = (
+            entry_property(a, "startTime").to_number(),
+            entry_property(b, "startTime").to_number(),
+        );
+        a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    Value::array_from_elems(found)
+}
+
+/// Remove the entries of 'entry_type', or only the ones of 'name' if it is given.
+fn clear_entries(performance: &Value, name: Option<&Value>, entry_type: &str) {
+    let name = match name {
+        Some(Value::Undefined) | None => None,
+        Some(name) => Some(name.to_string()),
+    };
+    let remaining = entries(performance)
+        .into_iter()
+        .filter(|entry| {
+            entry_property(entry, "entryType").to_string() != entry_type
+                || name.as_ref().map_or(false, |name| {
+                    entry_property(entry, "name").to_string() != *name
+                })
+        })
+        .collect();
+    set_entries(performance, remaining);
+}
+
+/// the time of a start or end given to measure(): a number, or the name of a mark.
+fn time_of(performance: &Value, time: &Value) -> Result<f64, RuntimeError> {
+    match time {
+        Value::Number(time) => Ok(*time),
+        Value::String(_) => {
+            let name = time.to_string();
+            entries(performance)
+                .iter()
+                .rev()
+                .find(|entry| {
+                    entry_property(entry, "entryType").to_string() == "mark"
+                        && entry_property(entry, "name").to_string() == name
+                })
+                .map(|entry| entry_property(entry, "startTime").to_number())
+                .ok_or_else(|| {
+                    RuntimeError::Exception(new_error(format!(
+                        "SyntaxError: The mark '{}' does not exist",
+                        name
+                    )))
+                })
+        }
+        _ => Ok(time.to_number()),
+    }
+}
+
+/// performance.now()
+fn now(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::Number(now_millis()));
+    Ok(())
+}
+
+/// performance.mark(name[, { startTime, detail }]). Records the time as 'name', and returns the
+/// entry.
+fn mark(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let name = match args.get(0) {
+        Some(Value::Undefined) | None => {
+            return Err(RuntimeError::Type(
+                "type error: performance.mark() needs a name".to_string(),
+            ))
+        }
+        Some(name) => name.to_string(),
+    };
+    let (mut start_time, mut detail) = (now_millis(), Value::Null);
+    if let Some(options @ Value::Object(_, _)) = args.get(1) {
+        match entry_property(options, "startTime") {
+            Value::Undefined => {}
+            time => start_time = time.to_number(),
+        }
+        match entry_property(options, "detail") {
+            Value::Undefined => {}
+            val => detail = val,
+        }
+    }
+    if start_time < 0.0 || start_time.is_nan() {
+        return Err(RuntimeError::Type(
+            "type error: performance.mark(): startTime must not be negative".to_string(),
+        ));
+    }
+
+    let entry = make_entry(name, "mark", start_time, 0.0, detail);
+    add_entry(&*callobj.this, entry.clone());
+    vm.set_return_value(entry);
+    Ok(())
+}
+
+/// performance.measure(name[, startMark[, endMark]]) or
+/// performance.measure(name, { start, end, duration, detail }). Records the time between the
+/// start and the end as 'name', and returns the entry. The start is 0 and the end is now unless
+/// they are given, as numbers or the names of marks.
+fn measure(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let performance = &*callobj.this;
+    let name = match args.get(0) {
+        Some(Value::Undefined) | None => {
+            return Err(RuntimeError::Type(
+                "type error: performance.measure() needs a name".to_string(),
+            ))
+        }
+        Some(name) => name.to_string(),
+    };
+
+    let (start, end, duration, detail) = match args.get(1) {
+        Some(options @ Value::Object(_, ObjectKind::Ordinary)) => (
+            entry_property(options, "start"),
+            entry_property(options, "end"),
+            entry_property(options, "duration"),
+            entry_property(options, "detail"),
+        ),
+        start => (
+            start.cloned().unwrap_or(Value::Undefined),
+            args.get(2).cloned().unwrap_or(Value::Undefined),
+            Value::Undefined,
+            Value::Undefined,
+        ),
+    };
+    let time = |val: &Value| match val {
+        Value::Undefined => Ok(None),
+        val => time_of(performance, val).map(Some),
+    };
+    let (start, end) = (time(&start)?, time(&end)?);
+    let duration = match duration {
+        Value::Undefined => None,
+        duration => Some(duration.to_number()),
+    };
+
+    let (start, end) = match (start, end, duration) {
+        (Some(start), Some(end), _) => (start, end),
+        (Some(start), None, Some(duration)) => (start, start + duration),
+        (None, Some(end), Some(duration)) => (end - duration, end),
+        (start, end, _) => (start.unwrap_or(0.0), end.unwrap_or_else(now_millis)),
+    };
+
+    let detail = match detail {
+        Value::Undefined => Value::Null,
+        detail => detail,
+    };
+    let entry = make_entry(name, "measure", start, end - start, detail);
+    add_entry(performance, entry.clone());
+    vm.set_return_value(entry);
+    Ok(())
+}
+
+/// performance.getEntries()
+fn get_entries(vm: &mut VM, _: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let found = find_entries(&*callobj.this, None, None);
+    vm.set_return_value(found);
+    Ok(())
+}
+
+/// performance.getEntriesByName(name[, type])
+fn get_entries_by_name(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let name = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let entry_type = match args.get(1) {
+        Some(Value::Undefined) | None => None,
+        Some(entry_type) => Some(entry_type.to_string()),
+    };
+    let found = find_entries(&*callobj.this, Some(name), entry_type);
+    vm.set_return_value(found);
+    Ok(())
+}
+
+/// performance.getEntriesByType(type)
+fn get_entries_by_type(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let entry_type = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let found = find_entries(&*callobj.this, None, Some(entry_type));
+    vm.set_return_value(found);
+    Ok(())
+}
+
+/// performance.clearMarks([name])
+fn clear_marks(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    clear_entries(&*callobj.this, args.get(0), "mark");
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// performance.clearMeasures([name])
+fn clear_measures(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    clear_entries(&*callobj.this, args.get(0), "measure");
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use builtins::promise;
+use libc;
+use num_bigint::BigInt;
+use std::env;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::process;
+use std::ptr;
+use std::time::{Duration, Instant};
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'process' global.
+
+thread_local!(
+    /// the origin of process.hrtime().
+    static START_TIME: Instant = Instant::now();
+);
+
+/// internal property of process, an object which has the array of the listeners of each event.
+const LISTENERS: &str = "[[Listeners]]";
+
+pub fn init(stdout_write: Value) -> Value {
+    let mut hrtime = Value::default_builtin_function(hrtime);
+    hrtime.set_property_with_name(
+        "bigint".to_string(),
+        Value::default_builtin_function(hrtime_bigint),
+    );
+
+    let process = make_object!(
+        stdout:     make_object!(write: stdout_write),
+        stdin:      make_object!(
+                        readLineSync: Value::default_builtin_function(stdin_read_line_sync)
+                    ),
+        argv:       Value::array_from_elems(vec![]),
+        env:        env_object(),
+        platform:   Value::string(platform().to_string()),
+        exit:       Value::default_builtin_function(exit),
+        exitCode:   Value::Undefined,
+        on:         Value::default_builtin_function(on),
+        off:        Value::default_builtin_function(off),
+        cwd:        Value::default_builtin_function(cwd),
+        hrtime:     hrtime
+    );
+    if let Value::Object(ref map, _) = process {
+        map.clone().insert(
+            LISTENERS.to_string(),
+            Property {
+                val: Value::object_from_npp(&vec![]),
+                writable: true,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+    process
+}
+
+/// Set process.argv. As in Node.js, 'argv' should be [executable, script, ...arguments].
+pub fn set_argv(vm: &mut VM, argv: Vec<String>) {
+    if let Ok(mut process) = vm.codegen.global_varmap.get_value("process") {
+        process.set_property_with_name(
+            "argv".to_string(),
+            Value::array_from_elems(argv.into_iter().map(Value::string).collect()),
+        );
+    }
+}
+
+/// process.env is a snapshot of the environment variables taken when the VM is created.
+/// Assigning to it does not change the environment.
+fn env_object() -> Value {
+    let npp: Vec<NamePropPair> = env::vars_os()
+        .map(|(name, val)| {
+            let val = Value::string(val.to_string_lossy().into_owned());
+            (name.to_string_lossy().into_owned(), Property::new(val))
+        })
+        .collect();
+    Value::object_from_npp(&npp)
+}
+
+/// the name of the platform as Node.js calls it.
+pub fn platform() -> &'static str {
+    match env::consts::OS {
+        "macos" => "darwin",
+        "windows" => "win32",
+        os => os,
+    }
+}
+
+/// the status the process exits with when the script ends: process.exitCode, or 0 if it is not
+/// set.
+pub fn exit_code(vm: &mut VM) -> i32 {
+    match vm.codegen.global_varmap.get_value("process") {
+        Ok(process) => match process.get_property(Value::string("exitCode".to_string()), None) {
+            Value::Undefined => 0,
+            code => code.to_number() as i32,
+        },
+        Err(_) => 0,
+    }
+}
+
+/// process.exit([code]). Without 'code', process.exitCode is used.
+fn exit(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let code = match args.get(0) {
+        Some(Value::Undefined) | None => exit_code(vm),
+        Some(code) => code.to_number() as i32,
+    };
+    // The output of console.log() and process.stdout.write() is buffered by printf().
+    unsafe {
+        libc::fflush(ptr::null_mut());
+    }
+    process::exit(code)
+}
+
+/// the array of the listeners of 'event' in 'process', which is made if it does not exist.
+fn listener_array(process: &Value, event: &str) -> Value {
+    let mut listeners = process.get_property(Value::string(LISTENERS.to_string()), None);
+    match listeners.get_property(Value::string(event.to_string()), None) {
+        array @ Value::Object(_, ObjectKind::Array(_)) => array,
+        _ => {
+            let array = Value::array_from_elems(vec![]);
+            listeners.set_property_with_name(event.to_string(), array.clone());
+            array
+        }
+    }
+}
+
+/// the listeners of 'event' registered by process.on().
+fn listeners(vm: &mut VM, event: &str) -> Vec<Value> {
+    match vm.codegen.global_varmap.get_value("process") {
+        Ok(process) => match listener_array(&process, event) {
+            Value::Object(_, ObjectKind::Array(array)) => {
+                array.elements().map(|(_, prop)| prop.val.clone()).collect()
+            }
+            _ => vec![],
+        },
+        Err(_) => vec![],
+    }
+}
+
+/// the listener passed to process.on() or process.off().
+fn listener_arg(args: &Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.get(1) {
+        Some(listener @ Value::Object(_, ObjectKind::Function(_)))
+        | Some(listener @ Value::Object(_, ObjectKind::BuiltinFunction(_))) => Ok(listener.clone()),
+        _ => Err(RuntimeError::Type(
+            "type error: The \"listener\" argument must be of type function".to_string(),
+        )),
+    }
+}
+
+/// process.on(event, listener). The events are 'uncaughtException' and 'unhandledRejection'.
+fn on(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let event = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let listener = listener_arg(args)?;
+    if let Value::Object(_, ObjectKind::Array(mut array)) =
+        listener_array(&*callobj.this, event.as_str())
+    {
+        array.push(listener);
+    }
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// process.off(event, listener) removes the listener added last.
+fn off(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let event = args.get(0).unwrap_or(&Value::Undefined).to_string();
+    let listener = listener_arg(args)?;
+    if let Value::Object(_, ObjectKind::Array(array)) =
+        listener_array(&*callobj.this, event.as_str())
+    {
+        let mut values = array.values()?;
+        if let Some(n) = values.iter().rposition(|val| *val == listener) {
+            values.remove(n);
+            let mut listeners = callobj
+                .this
+                .get_property(Value::string(LISTENERS.to_string()), None);
+            listeners.set_property_with_name(event, Value::array_from_elems(values));
+        }
+    }
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// Call each listener with 'args'. An error thrown by a listener is returned, and the rest are
+/// not called.
+fn emit(vm: &mut VM, listeners: Vec<Value>, args: Vec<Value>) -> Result<(), RuntimeError> {
+    // A listener may remove the others.
+    vm.with_roots(&listeners, |vm| {
+        for listener in &listeners {
+            vm.call_function_simply(listener, &args)?;
+            vm.state.stack.pop(); // return value is not used
+        }
+        Ok(())
+    })
+}
+
+/// Pass an error which the script did not catch to the 'uncaughtException' listeners, with
+/// 'origin' ('uncaughtException' or 'unhandledRejection') as in Node.js. The error is returned
+/// if there are no listeners, and the host reports it.
+pub fn emit_uncaught_exception(
+    vm: &mut VM,
+    err: RuntimeError,
+    origin: &str,
+) -> Result<(), RuntimeError> {
+    match err {
+        RuntimeError::Exception(_)
+        | RuntimeError::Type(_)
+        | RuntimeError::Reference(_)
+        | RuntimeError::General(_) => {}
+        err => return Err(err),
+    }
+    let listeners = listeners(vm, "uncaughtException");
+    if listeners.is_empty() {
+        return Err(err);
+    }
+    let args = vec![err.to_value(), Value::string(origin.to_string())];
+    emit(vm, listeners, args)
+}
+
+/// Report the promises rejected without handlers since the last call: they are passed to the
+/// 'unhandledRejection' listeners, or without listeners, thrown as uncaught exceptions.
+pub fn emit_unhandled_rejections(vm: &mut VM) -> Result<(), RuntimeError> {
+    let promises = mem::replace(&mut vm.unhandled_rejections, vec![]);
+    for promise in promises {
+        let reason = promise::result(&promise);
+        let listeners = listeners(vm, "unhandledRejection");
+        if listeners.is_empty() {
+            let err = RuntimeError::Exception(reason);
+            emit_uncaught_exception(vm, err, "unhandledRejection")?;
+        } else {
+            emit(vm, listeners, vec![reason, promise])?;
+        }
+    }
+    Ok(())
+}
+
+/// process.stdin.readLineSync([prompt]). Shows 'prompt' and reads a line from stdin.
+/// Returns the line without the line terminator, or null at the end of input.
+fn stdin_read_line_sync(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    unsafe {
+        if let Some(prompt) = args.get(0) {
+            let prompt = CString::new(prompt.to_string()).unwrap();
+            libc::printf("%s\0".as_ptr() as RawStringPtr, prompt.as_ptr());
+        }
+        libc::fflush(ptr::null_mut());
+    }
+
+    let mut line = String::new();
+    let len = io::stdin().read_line(&mut line).map_err(|err| {
+        RuntimeError::General(format!("error: process.stdin.readLineSync(): {}", err))
+    })?;
+    let val = if len == 0 {
+        Value::Null
+    } else {
+        let line = line.trim_right_matches(|c| c == '\n' || c == '\r');
+        Value::string(line.to_string())
+    };
+    vm.set_return_value(val);
+    Ok(())
+}
+
+/// process.cwd()
+fn cwd(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let cwd = env::current_dir()
+        .map_err(|err| RuntimeError::General(format!("error: process.cwd(): {}", err)))?;
+    vm.set_return_value(Value::string(cwd.to_string_lossy().into_owned()));
+    Ok(())
+}
+
+/// the time elapsed since an arbitrary point in the past, which is the same in the process.
+pub fn elapsed() -> Duration {
+    START_TIME.with(|start| start.elapsed())
+}
+
+/// process.hrtime([time]). Returns [seconds, nanoseconds], or the time since 'time' if it is
+/// the result of a previous call.
+fn hrtime(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let now = elapsed();
+    let (mut secs, mut nanos) = (now.as_secs() as f64, now.subsec_nanos() as f64);
+
+    match args.get(0) {
+        Some(Value::Undefined) | None => {}
+        Some(prev @ Value::Object(_, ObjectKind::Array(_))) => {
+            secs -= prev.get_property(Value::Number(0.0), None).to_number();
+            nanos -= prev.get_property(Value::Number(1.0), None).to_number();
+            if nanos < 0.0 {
+                secs -= 1.0;
+                nanos += 1e9;
+            }
+        }
+        Some(_) => {
+            return Err(RuntimeError::Type(
+                "type error: process.hrtime(): time must be an array".to_string(),
+            ))
+        }
+    }
+
+    vm.set_return_value(Value::array_from_elems(vec![
+        Value::Number(secs),
+        Value::Number(nanos),
+    ]));
+    Ok(())
+}
+
+/// process.hrtime.bigint(). Returns the time in nanoseconds.
+fn hrtime_bigint(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let now = elapsed();
+    let nanos = now.as_secs() * 1_000_000_000 + now.subsec_nanos() as u64;
+    vm.set_return_value(Value::bigint(BigInt::from(nanos)));
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use builtin::BuiltinFuncTy;
+use builtins::error::new_error;
+use vm::{
+    error::RuntimeError,
+    task::{Job, JobQueue},
+    value::*,
+    vm::VM,
+};
+
+// Promise. Reactions are run as jobs, after the current script or task.
+
+/// internal properties of a promise, which are not enumerable.
+const STATE: &str = "[[PromiseState]]";
+const RESULT: &str = "[[PromiseResult]]";
+/// [onFulfilled, onRejected, derived promise, ...] registered while the promise is pending.
+const REACTIONS: &str = "[[PromiseReactions]]";
+const ALREADY_RESOLVED: &str = "[[AlreadyResolved]]";
+
+/// Name of the variable which holds the promise in the call object of resolving functions.
+const PROMISE: &str = "promise";
+
+thread_local!(
+    pub static PROMISE_PROTOTYPE: Value = {
+        make_object!(
+            then:   Value::default_builtin_function(promise_prototype_then),
+            catch:  Value::default_builtin_function(promise_prototype_catch)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = PROMISE_PROTOTYPE.with(|x| x.clone());
+    let obj = Value::builtin_function(
+        promise_new,
+        None,
+        &mut make_npp!(
+            resolve:    Value::default_builtin_function(promise_resolve),
+            reject:     Value::default_builtin_function(promise_reject)
+        ),
+        Some(prototype.clone()),
+    );
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// Make a pending promise. Builtins settle it by resolve() or reject().
+pub fn new_promise() -> Value {
+    let promise = make_object!(
+        __proto__:  PROMISE_PROTOTYPE.with(|x| x.clone())
+    );
+    set_internal(&promise, STATE, Value::string("pending".to_string()));
+    set_internal(&promise, RESULT, Value::Undefined);
+    set_internal(&promise, REACTIONS, Value::array_from_elems(vec![]));
+    set_internal(&promise, ALREADY_RESOLVED, Value::Bool(false));
+    promise
+}
+
+pub fn is_promise(val: &Value) -> bool {
+    match val {
+        Value::Object(map, ObjectKind::Ordinary) => map.contains_key(STATE),
+        _ => false,
+    }
+}
+
+/// Resolve 'promise' with 'val'. If 'val' is a promise, 'promise' follows it.
+/// Does nothing if 'promise' has already been resolved or rejected.
+pub fn resolve(vm: &mut VM, promise: &Value, val: Value) {
+    if internal(promise, ALREADY_RESOLVED).to_boolean() {
+        return;
+    }
+    set_internal(promise, ALREADY_RESOLVED, Value::Bool(true));
+    resolve_promise(vm, promise, val);
+}
+
+/// Reject 'promise' with 'reason'.
+/// Does nothing if 'promise' has already been resolved or rejected.
+pub fn reject(vm: &mut VM, promise: &Value, reason: Value) {
+    if internal(promise, ALREADY_RESOLVED).to_boolean() {
+        return;
+    }
+    set_internal(promise, ALREADY_RESOLVED, Value::Bool(true));
+    settle(vm, promise, "rejected", reason);
+}
+
+/// the value or the reason of a settled promise.
+pub fn result(promise: &Value) -> Value {
+    internal(promise, RESULT)
+}
+
+fn internal(promise: &Value, name: &str) -> Value {
+    match promise {
+        Value::Object(map, _) => map
+            .get(name)
+            .map_or(Value::Undefined, |prop| prop.val.clone()),
+        _ => Value::Undefined,
+    }
+}
+
+fn set_internal(promise: &Value, name: &str, val: Value) {
+    if let Value::Object(map, _) = promise {
+        let mut map = map.clone();
+        map.insert(
+            name.to_string(),
+            Property {
+                val: val,
+                writable: true,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+}
+
+fn is_function(val: &Value) -> bool {
+    match val {
+        Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => true,
+        _ => false,
+    }
+}
+
+/// call 'callee' and get the return value.
+fn call(vm: &mut VM, callee: &Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    vm.call_function_simply(callee, &args)?;
+    Ok(vm.state.stack.pop().unwrap_or(Value::Undefined))
+}
+
+fn resolve_promise(vm: &mut VM, promise: &Value, val: Value) {
+    match (promise, &val) {
+        (Value::Object(map1, _), Value::Object(map2, _)) if map1 == map2 => {
+            let reason = new_error("TypeError: Chaining cycle detected for promise".to_string());
+            settle(vm, promise, "rejected", reason)
+        }
+        _ if is_promise(&val) => add_reaction(
+            vm,
+            &val,
+            Value::Undefined,
+            Value::Undefined,
+            promise.clone(),
+        ),
+        _ => settle(vm, promise, "fulfilled", val),
+    }
+}
+
+fn settle(vm: &mut VM, promise: &Value, state: &str, val: Value) {
+    let reactions = internal(promise, REACTIONS);
+    set_internal(promise, STATE, Value::string(state.to_string()));
+    set_internal(promise, RESULT, val.clone());
+    set_internal(promise, REACTIONS, Value::Undefined);
+
+    if let Value::Object(_, ObjectKind::Array(reactions)) = reactions {
+        // Reported by the event loop unless a handler is added before it.
+        if state == "rejected" && reactions.length == 0 {
+            vm.unhandled_rejections.push(promise.clone());
+        }
+        let reactions: Vec<Value> = reactions
+            .elements()
+            .map(|(_, prop)| prop.val.clone())
+            .collect();
+        for reaction in reactions.chunks(3) {
+            let handler = if state == "fulfilled" {
+                &reaction[0]
+            } else {
+                &reaction[1]
+            };
+            enqueue_reaction(vm, handler.clone(), reaction[2].clone(), state, val.clone());
+        }
+    }
+}
+
+/// Call 'on_fulfilled' or 'on_rejected' when 'promise' is settled, and resolve 'derived' with
+/// the result. If the handler is not a function, 'derived' is settled in the same way.
+fn add_reaction(
+    vm: &mut VM,
+    promise: &Value,
+    on_fulfilled: Value,
+    on_rejected: Value,
+    derived: Value,
+) {
+    match internal(promise, STATE).to_string().as_str() {
+        "pending" => {
+            if let Value::Object(_, ObjectKind::Array(mut reactions)) = internal(promise, REACTIONS)
+            {
+                reactions.push(on_fulfilled);
+                reactions.push(on_rejected);
+                reactions.push(derived);
+            }
+        }
+        "fulfilled" => {
+            enqueue_reaction(
+                vm,
+                on_fulfilled,
+                derived,
+                "fulfilled",
+                internal(promise, RESULT),
+            );
+        }
+        _ => {
+            if let Value::Object(ref map, _) = promise {
+                vm.unhandled_rejections.retain(|rejected| match rejected {
+                    Value::Object(ref rejected, _) => rejected != map,
+                    _ => true,
+                });
+            }
+            enqueue_reaction(
+                vm,
+                on_rejected,
+                derived,
+                "rejected",
+                internal(promise, RESULT),
+            );
+        }
+    }
+}
+
+fn enqueue_reaction(vm: &mut VM, handler: Value, derived: Value, state: &str, val: Value) {
+    vm.task_mgr.enqueue_job(Job {
+        callback: Value::default_builtin_function(reaction_job),
+        args: vec![handler, derived, Value::string(state.to_string()), val],
+    });
+}
+
+/// reaction_job(handler, derived, state, value), run from the job queue.
+fn reaction_job(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let (handler, derived, state, val) = (&args[0], &args[1], args[2].to_string(), &args[3]);
+    if is_function(handler) {
+        match call(vm, handler, vec![val.clone()]) {
+            Ok(ret) => resolve_promise(vm, derived, ret),
+            Err(RuntimeError::Exception(reason)) => settle(vm, derived, "rejected", reason),
+            Err(e) => return Err(e),
+        }
+    } else if state == "fulfilled" {
+        resolve_promise(vm, derived, val.clone());
+    } else {
+        settle(vm, derived, "rejected", val.clone());
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// make the resolve and reject functions passed to the executor of new Promise().
+fn resolving_functions(promise: &Value) -> (Value, Value) {
+    let make = |func: BuiltinFuncTy| {
+        let mut func = Value::default_builtin_function(func);
+        if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = func {
+            callobj.set_value(PROMISE.to_string(), promise.clone());
+        }
+        func
+    };
+    (make(resolve_function), make(reject_function))
+}
+
+fn resolve_function(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let promise = callobj.get_value(PROMISE)?;
+    resolve(
+        vm,
+        &promise,
+        args.get(0).cloned().unwrap_or(Value::Undefined),
+    );
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+fn reject_function(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let promise = callobj.get_value(PROMISE)?;
+    reject(
+        vm,
+        &promise,
+        args.get(0).cloned().unwrap_or(Value::Undefined),
+    );
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// new Promise(executor)
+fn promise_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let executor = match args.get(0) {
+        Some(executor) if is_function(executor) => executor.clone(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: Promise resolver is not a function".to_string(),
+            ))
+        }
+    };
+
+    let promise = new_promise();
+    // The executor may drop the resolving functions, the only others which refer to it.
+    vm.root(promise.clone());
+    let (resolve_fn, reject_fn) = resolving_functions(&promise);
+    match call(vm, &executor, vec![resolve_fn, reject_fn]) {
+        Ok(_) => {}
+        Err(RuntimeError::Exception(reason)) => reject(vm, &promise, reason),
+        Err(e) => return Err(e),
+    }
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// Promise.resolve(value). Returns 'value' itself if it is a promise.
+fn promise_resolve(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let val = args.get(0).cloned().unwrap_or(Value::Undefined);
+    if is_promise(&val) {
+        vm.set_return_value(val);
+        return Ok(());
+    }
+    let promise = new_promise();
+    resolve(vm, &promise, val);
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// Promise.reject(reason)
+fn promise_reject(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let promise = new_promise();
+    reject(
+        vm,
+        &promise,
+        args.get(0).cloned().unwrap_or(Value::Undefined),
+    );
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// Promise.prototype.then(onFulfilled, onRejected)
+fn promise_prototype_then(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let promise = (*callobj.this).clone();
+    if !is_promise(&promise) {
+        return Err(RuntimeError::Type(
+            "type error: Promise.prototype.then called on a non-promise".to_string(),
+        ));
+    }
+    let on_fulfilled = args.get(0).cloned().unwrap_or(Value::Undefined);
+    let on_rejected = args.get(1).cloned().unwrap_or(Value::Undefined);
+    let derived = new_promise();
+    add_reaction(vm, &promise, on_fulfilled, on_rejected, derived.clone());
+    vm.set_return_value(derived);
+    Ok(())
+}
+
+/// Promise.prototype.catch(onRejected)
+fn promise_prototype_catch(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let on_rejected = args.get(0).cloned().unwrap_or(Value::Undefined);
+    promise_prototype_then(vm, &vec![Value::Undefined, on_rejected], callobj)
+}
@@ -0,0 +1,307 @@
+use vm::{error::RuntimeError, jsstring::JsString, value::*, vm::VM};
+
+thread_local!(
+    pub static STRING_PROTOTYPE: Value = {
+        make_object!(
+            toString:    Value::default_builtin_function(string_prototype_value_of),
+            valueOf:     Value::default_builtin_function(string_prototype_value_of),
+            replace:     Value::default_builtin_function(string_prototype_replace),
+            at:          Value::default_builtin_function(string_prototype_at),
+            charAt:      Value::default_builtin_function(string_prototype_char_at),
+            charCodeAt:  Value::default_builtin_function(string_prototype_char_code_at),
+            codePointAt: Value::default_builtin_function(string_prototype_code_point_at)
+        )
+    };
+);
+
+pub fn init() -> Value {
+    let mut prototype = STRING_PROTOTYPE.with(|x| x.clone());
+    // String constructor
+    let obj = Value::builtin_function(
+        string,
+        None,
+        &mut make_npp!(
+            raw: Value::default_builtin_function(raw)
+        ),
+        Some(prototype.clone()),
+    );
+    prototype.set_constructor(obj.clone());
+
+    obj
+}
+
+/// https://tc39.github.io/ecma262/#sec-string-constructor-string-value
+pub fn string(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let s = match args.get(0) {
+        Some(val) => val.to_string(),
+        None => "".to_string(),
+    };
+    vm.set_return_value(Value::string(s));
+    Ok(())
+}
+
+/// https://tc39.github.io/ecma262/#sec-string.raw
+/// String.raw(template, ...substitutions) joins the raw strings of 'template' with the
+/// substitutions. e.g. String.raw`a\n${1}` is 'a\\n1'
+fn raw(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let raw = match args.get(0) {
+        None | Some(Value::Undefined) | Some(Value::Null) => Value::Undefined,
+        Some(template) => template.get_property(Value::string("raw".to_string()), None),
+    };
+    match raw {
+        Value::Undefined | Value::Null => {
+            return Err(RuntimeError::Type(
+                "type error: String.raw needs an object with 'raw'".to_string(),
+            ))
+        }
+        _ => {}
+    }
+
+    let length = raw
+        .get_property(Value::string("length".to_string()), None)
+        .to_number();
+    let length = if length.is_nan() || length <= 0.0 {
+        0
+    } else {
+        length.min(MAX_ARRAY_LENGTH) as usize
+    };
+    let mut s = JsString::default();
+    for i in 0..length {
+        let segment = raw.get_property(Value::Number(i as f64), None);
+        s = s.concat(&segment.to_js_string());
+        if i + 1 < length {
+            if let Some(substitution) = args.get(i + 1) {
+                s = s.concat(&substitution.to_js_string());
+            }
+        }
+    }
+    vm.set_return_value(Value::js_string(s));
+    Ok(())
+}
+
+/// new String(value)
+pub fn string_new(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    string(vm, args, callobj)?;
+    let s = vm.state.stack.pop().unwrap();
+    vm.set_return_value(Value::primitive_wrapper(s));
+    Ok(())
+}
+
+/// get 'this' as a string for String.prototype methods.
+fn this_string_value(callobj: &CallObjectRef) -> Result<Value, RuntimeError> {
+    match *callobj.this {
+        Value::String(_) => Ok((*callobj.this).clone()),
+        Value::Object(_, ObjectKind::PrimitiveWrapper(box ref s @ Value::String(_))) => {
+            Ok(s.clone())
+        }
+        _ => Err(RuntimeError::Type(
+            "type error: String.prototype method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+/// String.prototype.toString() and String.prototype.valueOf()
+fn string_prototype_value_of(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?;
+    vm.set_return_value(s);
+    Ok(())
+}
+
+/// the position in args[0] as an index of a string. None if it is out of 's'.
+fn position(s: &JsString, args: &Vec<Value>) -> Option<usize> {
+    let pos = args.get(0).map_or(0.0, |pos| pos.to_number());
+    let pos = if pos.is_nan() { 0.0 } else { pos.trunc() };
+    if 0.0 <= pos && pos < s.len() as f64 {
+        Some(pos as usize)
+    } else {
+        None
+    }
+}
+
+/// String.prototype.at(index). A negative index counts from the end.
+fn string_prototype_at(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?.to_js_string();
+    let index = args.get(0).map_or(0.0, |index| index.to_number());
+    let index = if index.is_nan() { 0.0 } else { index.trunc() };
+    let index = if index < 0.0 {
+        s.len() as f64 + index
+    } else {
+        index
+    };
+    let c = if 0.0 <= index && index < s.len() as f64 {
+        Value::js_string(s.substring(index as usize, index as usize + 1))
+    } else {
+        Value::Undefined
+    };
+    vm.set_return_value(c);
+    Ok(())
+}
+
+/// String.prototype.charAt(pos)
+fn string_prototype_char_at(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?.to_js_string();
+    let c = match position(&s, args) {
+        Some(pos) => s.substring(pos, pos + 1),
+        None => JsString::new(),
+    };
+    vm.set_return_value(Value::js_string(c));
+    Ok(())
+}
+
+/// String.prototype.charCodeAt(pos)
+fn string_prototype_char_code_at(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?.to_js_string();
+    let code = match position(&s, args) {
+        Some(pos) => s.code_unit_at(pos).unwrap() as f64,
+        None => ::std::f64::NAN,
+    };
+    vm.set_return_value(Value::Number(code));
+    Ok(())
+}
+
+/// String.prototype.codePointAt(pos)
+fn string_prototype_code_point_at(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?.to_js_string();
+    let code = match position(&s, args) {
+        Some(pos) => Value::Number(s.code_point_at(pos).unwrap() as f64),
+        None => Value::Undefined,
+    };
+    vm.set_return_value(code);
+    Ok(())
+}
+
+/// String.prototype.replace(searchValue, replaceValue). There is no RegExp yet, so
+/// 'searchValue' is converted to a string and its first occurrence is replaced.
+/// 'replaceValue' is either a function called with (matched, position, string), or a string
+/// in which the patterns of GetSubstitution() are expanded.
+fn string_prototype_replace(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let s = this_string_value(&callobj)?.to_js_string();
+    let search = args.get(0).unwrap_or(&Value::Undefined).to_js_string();
+    let replace_value = args.get(1).cloned().unwrap_or(Value::Undefined);
+
+    let pos = match s.find(&search, 0) {
+        Some(pos) => pos,
+        None => {
+            vm.set_return_value(Value::js_string(s));
+            return Ok(());
+        }
+    };
+
+    let replacement = match replace_value {
+        Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {
+            vm.call_function_simply(
+                &replace_value,
+                &vec![
+                    Value::js_string(search.clone()),
+                    Value::Number(pos as f64),
+                    Value::js_string(s.clone()),
+                ],
+            )?;
+            vm.state
+                .stack
+                .pop()
+                .unwrap_or(Value::Undefined)
+                .to_js_string()
+        }
+        _ => get_substitution(&search, &s, pos, &[], &replace_value.to_js_string()),
+    };
+
+    let result = s
+        .substring(0, pos)
+        .concat(&replacement)
+        .concat(&s.substring(pos + search.len(), s.len()));
+    vm.set_return_value(Value::js_string(result));
+    Ok(())
+}
+
+/// Expand $$, $&, $`, $' and $n in 'replacement'. 'position' is the index of 'matched' in
+/// 's', and 'captures' are the values of the capturing groups.
+/// https://tc39.github.io/ecma262/#sec-getsubstitution
+pub fn get_substitution(
+    matched: &JsString,
+    s: &JsString,
+    position: usize,
+    captures: &[Value],
+    replacement: &JsString,
+) -> JsString {
+    let capture = |n: usize| match captures.get(n - 1) {
+        Some(Value::Undefined) | None => JsString::new(),
+        Some(val) => val.to_js_string(),
+    };
+    let mut result: Vec<u16> = vec![];
+    let replacement = replacement.units();
+    let mut units = replacement.iter().cloned().peekable();
+    while let Some(unit) = units.next() {
+        if unit != '$' as u16 {
+            result.push(unit);
+            continue;
+        }
+        // A surrogate is not a pattern character.
+        let next = units.peek().and_then(|&next| char::from_u32(next as u32));
+        match next {
+            Some('$') => result.push(unit),
+            Some('&') => result.extend_from_slice(&matched.units()),
+            Some('`') => result.extend_from_slice(&s.units()[..position]),
+            Some('\'') => result.extend_from_slice(&s.units()[position + matched.len()..]),
+            Some(d) if d.is_ascii_digit() => {
+                let n = d.to_digit(10).unwrap() as usize;
+                units.next();
+                // $nn is used if it refers to a group, and otherwise $n.
+                let nn = units
+                    .peek()
+                    .and_then(|&d| char::from_u32(d as u32))
+                    .and_then(|d| d.to_digit(10))
+                    .map(|d| n * 10 + d as usize);
+                match nn {
+                    Some(nn) if 1 <= nn && nn <= captures.len() => {
+                        units.next();
+                        result.extend_from_slice(&capture(nn).units());
+                    }
+                    _ if 1 <= n && n <= captures.len() => {
+                        result.extend_from_slice(&capture(n).units())
+                    }
+                    _ => {
+                        result.push(unit);
+                        result.push(d as u16);
+                    }
+                }
+                continue;
+            }
+            _ => {
+                result.push(unit);
+                continue;
+            }
+        }
+        units.next();
+    }
+    JsString::from_units(result)
+}
@@ -0,0 +1,235 @@
+use builtins::error::new_error;
+use builtins::typedarray::TypedArrayKind;
+use chrono::{DateTime, Utc};
+use num_bigint::BigInt;
+use rustc_hash::FxHashMap;
+use vm::{error::RuntimeError, jsstring::JsString, value::*, vm::VM};
+
+// The structured clone algorithm, which deep-copies values: structuredClone(value) and
+// postMessage() of workers. A value is serialized into data owned by Rust, which can be sent to
+// another thread, and deserialized into new objects in the VM which receives it.
+//
+// Objects referred to more than once, including cycles, are serialized once and referred to by
+// their indexes in the order they are serialized, so that the copies are shared in the same way.
+// Typed arrays and DataViews on the same ArrayBuffer share the copy of the buffer. Functions and
+// other objects which cannot be copied throw a DataCloneError.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Serialized {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    BigInt(BigInt),
+    /// code units.
+    String(Vec<u16>),
+    /// an object serialized before, by its index in the order objects are serialized.
+    Reference(usize),
+    /// the length and the elements of an array with their indices. Holes are left out.
+    Array {
+        length: usize,
+        elems: Vec<(usize, Serialized)>,
+    },
+    /// enumerable own properties of an ordinary object.
+    Object(Vec<(String, Serialized)>),
+    Date(DateTime<Utc>),
+    /// Number, String or Boolean object.
+    PrimitiveWrapper(Box<Serialized>),
+    ArrayBuffer(Vec<u8>),
+    /// 'buffer' is an ArrayBuffer or a Reference to it.
+    TypedArray {
+        kind: TypedArrayKind,
+        buffer: Box<Serialized>,
+        byte_offset: usize,
+        length: usize,
+    },
+    DataView {
+        buffer: Box<Serialized>,
+        byte_offset: usize,
+        byte_length: usize,
+    },
+}
+
+/// structuredClone(value)
+pub fn structured_clone(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let data = serialize(args.get(0).unwrap_or(&Value::Undefined))?;
+    vm.set_return_value(deserialize(&data));
+    Ok(())
+}
+
+pub fn serialize(val: &Value) -> Result<Serialized, RuntimeError> {
+    Serializer {
+        ids: FxHashMap::default(),
+    }
+    .serialize(val)
+}
+
+pub fn deserialize(data: &Serialized) -> Value {
+    Deserializer { objects: vec![] }.deserialize(data)
+}
+
+struct Serializer {
+    /// indexes of the objects serialized, by their addresses.
+    ids: FxHashMap<usize, usize>,
+}
+
+impl Serializer {
+    /// Reference to the object at 'addr' if it has been serialized. Otherwise it is given the
+    /// next index.
+    fn reference(&mut self, addr: usize) -> Option<Serialized> {
+        if let Some(&id) = self.ids.get(&addr) {
+            return Some(Serialized::Reference(id));
+        }
+        let id = self.ids.len();
+        self.ids.insert(addr, id);
+        None
+    }
+
+    fn serialize(&mut self, val: &Value) -> Result<Serialized, RuntimeError> {
+        let map = match val {
+            Value::Empty | Value::Undefined => return Ok(Serialized::Undefined),
+            Value::Null => return Ok(Serialized::Null),
+            Value::Bool(b) => return Ok(Serialized::Bool(*b)),
+            Value::Number(n) => return Ok(Serialized::Number(*n)),
+            Value::BigInt(n) => return Ok(Serialized::BigInt((**n).clone())),
+            Value::String(s) => return Ok(Serialized::String(s.units().to_vec())),
+            // An ArrayBuffer is known by the buffer, which is all typed arrays have.
+            Value::Object(_, ObjectKind::ArrayBuffer(ref buffer)) => {
+                return Ok(self.serialize_buffer(buffer))
+            }
+            Value::Object(ref map, _) => map,
+        };
+        if let Some(reference) = self.reference(map.addr()) {
+            return Ok(reference);
+        }
+
+        Ok(match val {
+            Value::Object(_, ObjectKind::Array(ref array)) => {
+                let mut elems = vec![];
+                for (i, prop) in array.elements() {
+                    elems.push((i, self.serialize(&prop.val)?));
+                }
+                Serialized::Array {
+                    length: array.length,
+                    elems: elems,
+                }
+            }
+            Value::Object(ref map, ObjectKind::Ordinary) => {
+                let mut props = vec![];
+                for (name, prop) in map.iter() {
+                    if !prop.enumerable || prop.accessor.is_some() {
+                        continue;
+                    }
+                    props.push((name.clone(), self.serialize(&prop.val)?));
+                }
+                Serialized::Object(props)
+            }
+            Value::Object(_, ObjectKind::Date(box time)) => Serialized::Date(*time),
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => {
+                Serialized::PrimitiveWrapper(Box::new(self.serialize(primitive)?))
+            }
+            Value::Object(_, ObjectKind::TypedArray(ref ta)) => Serialized::TypedArray {
+                kind: ta.kind,
+                buffer: Box::new(self.serialize_buffer(&ta.buffer)),
+                byte_offset: ta.byte_offset,
+                length: ta.length,
+            },
+            Value::Object(_, ObjectKind::DataView(ref view)) => Serialized::DataView {
+                buffer: Box::new(self.serialize_buffer(&view.buffer)),
+                byte_offset: view.byte_offset,
+                byte_length: view.byte_length,
+            },
+            _ => {
+                return Err(RuntimeError::Exception(new_error(format!(
+                    "DataCloneError: {} could not be cloned",
+                    val.to_string()
+                ))))
+            }
+        })
+    }
+
+    fn serialize_buffer(&mut self, buffer: &ArrayBufferRef) -> Serialized {
+        match self.reference(buffer.addr()) {
+            Some(reference) => reference,
+            None => Serialized::ArrayBuffer(buffer.data.clone()),
+        }
+    }
+}
+
+struct Deserializer {
+    /// the objects made, in the order they were serialized.
+    objects: Vec<Value>,
+}
+
+impl Deserializer {
+    fn deserialize(&mut self, data: &Serialized) -> Value {
+        match data {
+            Serialized::Undefined => Value::Undefined,
+            Serialized::Null => Value::Null,
+            Serialized::Bool(b) => Value::Bool(*b),
+            Serialized::Number(n) => Value::Number(*n),
+            Serialized::BigInt(n) => Value::bigint(n.clone()),
+            Serialized::String(units) => Value::js_string(JsString::from_units(units.clone())),
+            Serialized::Reference(id) => self.objects[*id].clone(),
+            // Arrays and objects are registered before their contents, which may refer to them.
+            Serialized::Array { length, elems } => {
+                let mut array = Value::array_from_elems(vec![]);
+                self.objects.push(array.clone());
+                if let Value::Object(_, ObjectKind::Array(ref mut array)) = array {
+                    for (i, elem) in elems {
+                        array.set(*i, self.deserialize(elem));
+                    }
+                    array.set_length(*length);
+                }
+                array
+            }
+            Serialized::Object(props) => {
+                let object = Value::object_from_npp(&vec![]);
+                self.objects.push(object.clone());
+                if let Value::Object(ref map, _) = object {
+                    let mut map = map.clone();
+                    for (name, val) in props {
+                        map.insert(name.clone(), Property::new(self.deserialize(val)));
+                    }
+                }
+                object
+            }
+            // The others are made after their contents, which cannot refer to them.
+            _ => {
+                let id = self.objects.len();
+                self.objects.push(Value::Undefined);
+                let object = match data {
+                    Serialized::Date(time) => Value::date(*time),
+                    Serialized::PrimitiveWrapper(primitive) => {
+                        Value::primitive_wrapper(self.deserialize(primitive))
+                    }
+                    Serialized::ArrayBuffer(bytes) => Value::array_buffer(bytes.clone()),
+                    Serialized::TypedArray {
+                        kind,
+                        buffer,
+                        byte_offset,
+                        length,
+                    } => {
+                        let buffer = self.deserialize(buffer);
+                        Value::typed_array(*kind, buffer, *byte_offset, *length)
+                    }
+                    Serialized::DataView {
+                        buffer,
+                        byte_offset,
+                        byte_length,
+                    } => {
+                        let buffer = self.deserialize(buffer);
+                        Value::data_view(buffer, *byte_offset, *byte_length)
+                    }
+                    _ => unreachable!(),
+                };
+                self.objects[id] = object.clone();
+                object
+            }
+        }
+    }
+}
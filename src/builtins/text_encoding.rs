@@ -0,0 +1,235 @@
+use builtins::arraybuffer::buffer_source_bytes;
+use builtins::error::new_error;
+use builtins::typedarray::TypedArrayKind;
+use vm::{error::RuntimeError, jsstring::JsString, value::*, vm::VM};
+
+// TextEncoder and TextDecoder, which convert between strings and UTF-8 bytes in typed arrays,
+// and btoa() and atob(), which convert between binary strings and base64.
+
+thread_local!(
+    pub static TEXT_ENCODER_PROTOTYPE: Value = {
+        make_object!(
+            encode:     Value::default_builtin_function(text_encoder_prototype_encode)
+        )
+    };
+
+    pub static TEXT_DECODER_PROTOTYPE: Value = {
+        make_object!(
+            decode:     Value::default_builtin_function(text_decoder_prototype_decode)
+        )
+    };
+);
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn init() -> Vec<(&'static str, Value)> {
+    let mut encoder_prototype = TEXT_ENCODER_PROTOTYPE.with(|x| x.clone());
+    let encoder = Value::builtin_function(
+        text_encoder_new,
+        None,
+        &mut vec![],
+        Some(encoder_prototype.clone()),
+    );
+    encoder_prototype.set_constructor(encoder.clone());
+
+    let mut decoder_prototype = TEXT_DECODER_PROTOTYPE.with(|x| x.clone());
+    let decoder = Value::builtin_function(
+        text_decoder_new,
+        None,
+        &mut vec![],
+        Some(decoder_prototype.clone()),
+    );
+    decoder_prototype.set_constructor(decoder.clone());
+
+    vec![
+        ("TextEncoder", encoder),
+        ("TextDecoder", decoder),
+        ("btoa", Value::default_builtin_function(btoa)),
+        ("atob", Value::default_builtin_function(atob)),
+    ]
+}
+
+/// new TextEncoder()
+fn text_encoder_new(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(make_object!(
+        encoding:   Value::string("utf-8".to_string()),
+        __proto__:  TEXT_ENCODER_PROTOTYPE.with(|x| x.clone())
+    ));
+    Ok(())
+}
+
+/// TextEncoder.prototype.encode([string]) returns the UTF-8 bytes in a Uint8Array. Lone
+/// surrogates are encoded as U+FFFD.
+fn text_encoder_prototype_encode(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let bytes = match args.get(0) {
+        None | Some(Value::Undefined) => vec![],
+        Some(val) => val.to_string().into_bytes(),
+    };
+    let length = bytes.len();
+    let buffer = Value::array_buffer(bytes);
+    vm.set_return_value(Value::typed_array(TypedArrayKind::Uint8, buffer, 0, length));
+    Ok(())
+}
+
+/// new TextDecoder([label[, { fatal, ignoreBOM }]]). Only UTF-8 is supported.
+fn text_decoder_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    match args.get(0) {
+        None | Some(Value::Undefined) => {}
+        Some(label) => match label.to_string().trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => {}
+            label => {
+                return Err(RuntimeError::Exception(new_error(format!(
+                    "RangeError: The encoding label provided ('{}') is not supported",
+                    label
+                ))))
+            }
+        },
+    }
+    let option = |name: &str| match args.get(1) {
+        Some(options @ Value::Object(_, _)) => options
+            .get_property(Value::string(name.to_string()), None)
+            .to_boolean(),
+        _ => false,
+    };
+    vm.set_return_value(make_object!(
+        encoding:   Value::string("utf-8".to_string()),
+        fatal:      Value::Bool(option("fatal")),
+        ignoreBOM:  Value::Bool(option("ignoreBOM")),
+        __proto__:  TEXT_DECODER_PROTOTYPE.with(|x| x.clone())
+    ));
+    Ok(())
+}
+
+/// TextDecoder.prototype.decode([bytes]). Invalid bytes are decoded as U+FFFD, or throw a
+/// TypeError if the decoder is fatal. A leading BOM is removed unless ignoreBOM is set.
+fn text_decoder_prototype_decode(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let bytes = match args.get(0) {
+        None | Some(Value::Undefined) => vec![],
+        Some(val) => match buffer_source_bytes(val) {
+            Some(bytes) => bytes,
+            None => {
+                return Err(RuntimeError::Type(
+                    "type error: TextDecoder.prototype.decode(): bytes must be a buffer"
+                        .to_string(),
+                ))
+            }
+        },
+    };
+    let this = &*callobj.this;
+    let flag = |name: &str| {
+        this.get_property(Value::string(name.to_string()), None)
+            .to_boolean()
+    };
+
+    let mut text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) if flag("fatal") => {
+            return Err(RuntimeError::Exception(new_error(
+                "TypeError: The encoded data was not valid for encoding utf-8".to_string(),
+            )))
+        }
+        Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    };
+    if !flag("ignoreBOM") && text.starts_with('\u{feff}') {
+        text.remove(0);
+    }
+    vm.set_return_value(Value::string(text));
+    Ok(())
+}
+
+/// btoa(string) encodes a string of code units up to 0xFF into base64.
+fn btoa(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let units = match args.get(0) {
+        Some(Value::String(s)) => s.units().to_vec(),
+        Some(val) => val.to_string().encode_utf16().collect(),
+        None => "undefined".encode_utf16().collect(),
+    };
+    if units.iter().any(|&unit| unit > 0xff) {
+        return Err(RuntimeError::Exception(new_error(
+            "InvalidCharacterError: The string to be encoded contains characters outside of the \
+             Latin1 range"
+                .to_string(),
+        )));
+    }
+    let bytes: Vec<u8> = units.into_iter().map(|unit| unit as u8).collect();
+    vm.set_return_value(Value::string(base64_encode(&bytes)));
+    Ok(())
+}
+
+/// atob(string) decodes base64 into a string of code units up to 0xFF.
+fn atob(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let text = match args.get(0) {
+        Some(val) => val.to_string(),
+        None => "undefined".to_string(),
+    };
+    let bytes = match base64_decode(&text) {
+        Some(bytes) => bytes,
+        None => {
+            return Err(RuntimeError::Exception(new_error(
+                "InvalidCharacterError: The string to be decoded is not correctly encoded"
+                    .to_string(),
+            )))
+        }
+    };
+    let units = bytes.into_iter().map(|byte| byte as u16).collect();
+    vm.set_return_value(Value::js_string(JsString::from_units(units)));
+    Ok(())
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode base64 by the forgiving-base64 decode of the HTML standard: ASCII whitespace is
+/// ignored and the padding may be omitted. None if 'text' is not valid.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut digits: Vec<u8> = text
+        .bytes()
+        .filter(|b| !b" \t\n\x0c\r".contains(b))
+        .collect();
+    if digits.len() % 4 == 0 {
+        for _ in 0..2 {
+            if digits.last() == Some(&b'=') {
+                digits.pop();
+            }
+        }
+    }
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut bytes = vec![];
+    for chunk in digits.chunks(4) {
+        let mut n = 0u32;
+        for (i, digit) in chunk.iter().enumerate() {
+            let value = BASE64_CHARS.iter().position(|c| c == digit)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
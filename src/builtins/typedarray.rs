@@ -0,0 +1,356 @@
+use builtin::BuiltinFuncTy;
+use builtins::arraybuffer::{relative_index, to_index};
+use builtins::object::OBJECT_PROTOTYPE;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+/// Element types of typed arrays. The order must match TYPED_ARRAYS.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TypedArrayKind {
+    Int8,
+    Uint8,
+    Uint8Clamped,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+/// Constructor name, element type and constructor of each typed array.
+static TYPED_ARRAYS: &[(&str, TypedArrayKind, BuiltinFuncTy)] = &[
+    ("Int8Array", TypedArrayKind::Int8, int8_array),
+    ("Uint8Array", TypedArrayKind::Uint8, uint8_array),
+    (
+        "Uint8ClampedArray",
+        TypedArrayKind::Uint8Clamped,
+        uint8_clamped_array,
+    ),
+    ("Int16Array", TypedArrayKind::Int16, int16_array),
+    ("Uint16Array", TypedArrayKind::Uint16, uint16_array),
+    ("Int32Array", TypedArrayKind::Int32, int32_array),
+    ("Uint32Array", TypedArrayKind::Uint32, uint32_array),
+    ("Float32Array", TypedArrayKind::Float32, float32_array),
+    ("Float64Array", TypedArrayKind::Float64, float64_array),
+];
+
+thread_local!(
+    /// %TypedArray%.prototype, which has the methods shared by all typed arrays.
+    pub static TYPED_ARRAY_PROTOTYPE: Value = {
+        make_object!(
+            fill:       Value::default_builtin_function(typed_array_prototype_fill),
+            join:       Value::default_builtin_function(typed_array_prototype_join),
+            set:        Value::default_builtin_function(typed_array_prototype_set),
+            subarray:   Value::default_builtin_function(typed_array_prototype_subarray),
+            __proto__:  OBJECT_PROTOTYPE.with(|x| x.clone())
+        )
+    };
+
+    /// Prototypes of each typed array, indexed by TypedArrayKind.
+    pub static TYPED_ARRAY_PROTOTYPES: Vec<Value> = {
+        TYPED_ARRAYS
+            .iter()
+            .map(|(_, kind, _)| {
+                make_object!(
+                    BYTES_PER_ELEMENT:  Value::Number(kind.size() as f64),
+                    __proto__:          TYPED_ARRAY_PROTOTYPE.with(|x| x.clone())
+                )
+            })
+            .collect()
+    };
+);
+
+/// Returns the constructors of all typed arrays with their names.
+pub fn init() -> Vec<(&'static str, Value)> {
+    TYPED_ARRAYS
+        .iter()
+        .map(|&(name, kind, func)| {
+            let mut prototype = TYPED_ARRAY_PROTOTYPES.with(|x| x[kind as usize].clone());
+            let obj = Value::builtin_function(
+                func,
+                None,
+                &mut make_npp!(
+                    BYTES_PER_ELEMENT:  Value::Number(kind.size() as f64)
+                ),
+                Some(prototype.clone()),
+            );
+            prototype.set_constructor(obj.clone());
+            (name, obj)
+        })
+        .collect()
+}
+
+impl TypedArrayKind {
+    pub fn name(self) -> &'static str {
+        TYPED_ARRAYS[self as usize].0
+    }
+
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            TypedArrayKind::Int8 | TypedArrayKind::Uint8 | TypedArrayKind::Uint8Clamped => 1,
+            TypedArrayKind::Int16 | TypedArrayKind::Uint16 => 2,
+            TypedArrayKind::Int32 | TypedArrayKind::Uint32 | TypedArrayKind::Float32 => 4,
+            TypedArrayKind::Float64 => 8,
+        }
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-rawbytestonumeric
+    pub fn read(self, bytes: &[u8], little_endian: bool) -> f64 {
+        let size = self.size();
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(&bytes[..size]);
+        if !little_endian {
+            buf[..size].reverse();
+        }
+        let bits = u64::from_le_bytes(buf);
+        match self {
+            TypedArrayKind::Int8 => bits as u8 as i8 as f64,
+            TypedArrayKind::Uint8 | TypedArrayKind::Uint8Clamped => bits as u8 as f64,
+            TypedArrayKind::Int16 => bits as u16 as i16 as f64,
+            TypedArrayKind::Uint16 => bits as u16 as f64,
+            TypedArrayKind::Int32 => bits as u32 as i32 as f64,
+            TypedArrayKind::Uint32 => bits as u32 as f64,
+            TypedArrayKind::Float32 => f32::from_bits(bits as u32) as f64,
+            TypedArrayKind::Float64 => f64::from_bits(bits),
+        }
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-numerictorawbytes
+    pub fn write(self, bytes: &mut [u8], n: f64, little_endian: bool) {
+        let size = self.size();
+        let bits = match self {
+            TypedArrayKind::Float32 => (n as f32).to_bits() as u64,
+            TypedArrayKind::Float64 => n.to_bits(),
+            TypedArrayKind::Uint8Clamped => to_uint8_clamp(n) as u64,
+            // Integers are stored modulo 2^32, and then truncated to the element size.
+            _ => Value::Number(n).to_uint32() as u64,
+        };
+        let mut buf = bits.to_le_bytes();
+        if !little_endian {
+            buf[..size].reverse();
+        }
+        bytes[..size].copy_from_slice(&buf[..size]);
+    }
+}
+
+/// https://tc39.github.io/ecma262/#sec-touint8clamp
+fn to_uint8_clamp(n: f64) -> u8 {
+    if n.is_nan() || n <= 0.0 {
+        return 0;
+    }
+    if n >= 255.0 {
+        return 255;
+    }
+    // Rounds half to even.
+    let f = n.floor();
+    let diff = n - f;
+    if diff < 0.5 || (diff == 0.5 && f % 2.0 == 0.0) {
+        f as u8
+    } else {
+        f as u8 + 1
+    }
+}
+
+macro_rules! typed_array_constructor {
+    ($name:ident, $kind:expr) => {
+        fn $name(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+            let typed_array = new_typed_array($kind, args)?;
+            vm.set_return_value(typed_array);
+            Ok(())
+        }
+    };
+}
+
+typed_array_constructor!(int8_array, TypedArrayKind::Int8);
+typed_array_constructor!(uint8_array, TypedArrayKind::Uint8);
+typed_array_constructor!(uint8_clamped_array, TypedArrayKind::Uint8Clamped);
+typed_array_constructor!(int16_array, TypedArrayKind::Int16);
+typed_array_constructor!(uint16_array, TypedArrayKind::Uint16);
+typed_array_constructor!(int32_array, TypedArrayKind::Int32);
+typed_array_constructor!(uint32_array, TypedArrayKind::Uint32);
+typed_array_constructor!(float32_array, TypedArrayKind::Float32);
+typed_array_constructor!(float64_array, TypedArrayKind::Float64);
+
+/// https://tc39.github.io/ecma262/#sec-typedarray-constructors
+/// - new XXXArray(length)
+/// - new XXXArray(typedArray or array): copies the elements.
+/// - new XXXArray(buffer, byteOffset, length): a view of the buffer. No bytes are copied.
+fn new_typed_array(kind: TypedArrayKind, args: &Vec<Value>) -> Result<Value, RuntimeError> {
+    let size = kind.size();
+    match args.get(0) {
+        Some(Value::Object(_, ObjectKind::ArrayBuffer(buffer))) => {
+            let buffer_len = buffer.data.len();
+            let byte_offset = to_index(args.get(1))?;
+            if byte_offset % size != 0 {
+                return Err(RuntimeError::General(format!(
+                    "range error: start offset of {} should be a multiple of {}",
+                    kind.name(),
+                    size
+                )));
+            }
+            let length = match args.get(2) {
+                None | Some(Value::Undefined) => {
+                    if buffer_len % size != 0 || byte_offset > buffer_len {
+                        return Err(RuntimeError::General(format!(
+                            "range error: byte length of {} should be a multiple of {}",
+                            kind.name(),
+                            size
+                        )));
+                    }
+                    (buffer_len - byte_offset) / size
+                }
+                length => {
+                    let length = to_index(length)?;
+                    if byte_offset + length * size > buffer_len {
+                        return Err(RuntimeError::General(format!(
+                            "range error: invalid typed array length: {}",
+                            length
+                        )));
+                    }
+                    length
+                }
+            };
+            Ok(Value::typed_array(
+                kind,
+                args[0].clone(),
+                byte_offset,
+                length,
+            ))
+        }
+        Some(Value::Object(_, ObjectKind::TypedArray(src))) => {
+            let elems = (0..src.length).map(|i| src.get(i as f64)).collect();
+            Ok(typed_array_from_elems(kind, elems))
+        }
+        Some(Value::Object(_, ObjectKind::Array(src))) => {
+            let elems = src.values()?;
+            Ok(typed_array_from_elems(kind, elems))
+        }
+        // TODO: Support iterable and array-like objects.
+        Some(Value::Object(_, _)) => Ok(typed_array_from_elems(kind, vec![])),
+        length => {
+            let length = to_index(length)?;
+            let buffer = Value::array_buffer(vec![0; length * size]);
+            Ok(Value::typed_array(kind, buffer, 0, length))
+        }
+    }
+}
+
+/// make a typed array on a new ArrayBuffer, which has the elements converted to Number.
+fn typed_array_from_elems(kind: TypedArrayKind, elems: Vec<Value>) -> Value {
+    let buffer = Value::array_buffer(vec![0; elems.len() * kind.size()]);
+    let mut typed_array = Value::typed_array(kind, buffer, 0, elems.len());
+    if let Value::Object(_, ObjectKind::TypedArray(ref mut ta)) = typed_array {
+        for (i, elem) in elems.iter().enumerate() {
+            ta.set(i as f64, elem.to_number());
+        }
+    }
+    typed_array
+}
+
+/// get 'this' as a typed array for %TypedArray%.prototype methods.
+fn this_typed_array(callobj: &CallObjectRef) -> Result<TypedArray, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::TypedArray(ref ta)) => Ok((**ta).clone()),
+        _ => Err(RuntimeError::Type(
+            "type error: this is not a typed array".to_string(),
+        )),
+    }
+}
+
+/// %TypedArray%.prototype.fill(value, start, end)
+fn typed_array_prototype_fill(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut ta = this_typed_array(&callobj)?;
+    let n = args.get(0).unwrap_or(&Value::Undefined).to_number();
+    let start = relative_index(args.get(1), ta.length, 0);
+    let end = relative_index(args.get(2), ta.length, ta.length);
+    for i in start..end {
+        ta.set(i as f64, n);
+    }
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// %TypedArray%.prototype.join(separator)
+fn typed_array_prototype_join(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let ta = this_typed_array(&callobj)?;
+    let separator = match args.get(0) {
+        None | Some(Value::Undefined) => ",".to_string(),
+        Some(val) => val.to_string(),
+    };
+    let joined = (0..ta.length)
+        .map(|i| ta.get(i as f64).to_string())
+        .collect::<Vec<String>>()
+        .join(separator.as_str());
+    vm.set_return_value(Value::string(joined));
+    Ok(())
+}
+
+/// %TypedArray%.prototype.set(source, offset) copies the elements of an array or a typed
+/// array into this typed array.
+fn typed_array_prototype_set(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut ta = this_typed_array(&callobj)?;
+    // Read all the source elements first since the source may share the buffer.
+    let elems: Vec<f64> = match args.get(0) {
+        Some(Value::Object(_, ObjectKind::TypedArray(src))) => (0..src.length)
+            .map(|i| src.get(i as f64).to_number())
+            .collect(),
+        Some(Value::Object(_, ObjectKind::Array(src))) => {
+            src.values()?.iter().map(|val| val.to_number()).collect()
+        }
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: source must be an array or a typed array".to_string(),
+            ))
+        }
+    };
+    let offset = to_index(args.get(1))?;
+    if offset + elems.len() > ta.length {
+        return Err(RuntimeError::General(
+            "range error: offset is out of bounds".to_string(),
+        ));
+    }
+    for (i, n) in elems.iter().enumerate() {
+        ta.set((offset + i) as f64, *n);
+    }
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// %TypedArray%.prototype.subarray(begin, end) returns a new view of the same buffer.
+fn typed_array_prototype_subarray(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let ta = this_typed_array(&callobj)?;
+    let begin = relative_index(args.get(0), ta.length, 0);
+    let end = relative_index(args.get(1), ta.length, ta.length).max(begin);
+    let buffer = match obj_find_val((*callobj.this).clone(), "buffer") {
+        buffer @ Value::Object(_, ObjectKind::ArrayBuffer(_)) => buffer,
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: buffer is not an ArrayBuffer".to_string(),
+            ))
+        }
+    };
+    vm.set_return_value(Value::typed_array(
+        ta.kind,
+        buffer,
+        ta.byte_offset + begin * ta.kind.size(),
+        end - begin,
+    ));
+    Ok(())
+}
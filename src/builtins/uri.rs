@@ -0,0 +1,229 @@
+use builtins::error::new_error;
+use vm::{error::RuntimeError, jsstring::JsString, value::*, vm::VM};
+
+// Global functions handling URIs: encodeURI(), decodeURI(), encodeURIComponent(),
+// decodeURIComponent(), and escape() and unescape() of Annex B.
+
+const URI_RESERVED: &str = ";/?:@&=+$,";
+const URI_MARK: &str = "-_.!~*'()";
+/// characters escape() leaves as they are, besides ASCII letters and digits.
+const ESCAPE_UNESCAPED: &str = "@*_+-./";
+
+pub fn init() -> Vec<(&'static str, Value)> {
+    vec![
+        ("encodeURI", Value::default_builtin_function(encode_uri)),
+        ("decodeURI", Value::default_builtin_function(decode_uri)),
+        (
+            "encodeURIComponent",
+            Value::default_builtin_function(encode_uri_component),
+        ),
+        (
+            "decodeURIComponent",
+            Value::default_builtin_function(decode_uri_component),
+        ),
+        ("escape", Value::default_builtin_function(escape)),
+        ("unescape", Value::default_builtin_function(unescape)),
+    ]
+}
+
+fn uri_error() -> RuntimeError {
+    RuntimeError::Exception(new_error("URIError: URI malformed".to_string()))
+}
+
+fn arg_string(args: &Vec<Value>) -> JsString {
+    args.get(0).unwrap_or(&Value::Undefined).to_js_string()
+}
+
+fn is_uri_unescaped(unit: u16) -> bool {
+    unit < 0x80 && {
+        let c = unit as u8 as char;
+        c.is_ascii_alphanumeric() || URI_MARK.contains(c)
+    }
+}
+
+fn in_set(unit: u16, set: &str) -> bool {
+    unit < 0x80 && set.contains(unit as u8 as char)
+}
+
+fn hex_digit(unit: u16) -> Option<u8> {
+    if unit < 0x80 {
+        (unit as u8 as char).to_digit(16).map(|d| d as u8)
+    } else {
+        None
+    }
+}
+
+/// the byte written as "%XX" at 'k' in 'units'.
+fn percent_byte(units: &[u16], k: usize) -> Option<u8> {
+    if k + 2 >= units.len() || units[k] != '%' as u16 {
+        return None;
+    }
+    Some(hex_digit(units[k + 1])? << 4 | hex_digit(units[k + 2])?)
+}
+
+fn push_str(result: &mut Vec<u16>, s: &str) {
+    result.extend(s.encode_utf16());
+}
+
+/// Encode(string, unescapedSet)
+/// https://tc39.github.io/ecma262/#sec-encode
+fn encode(s: &JsString, extra_unescaped: &str) -> Result<JsString, RuntimeError> {
+    let units = s.units();
+    let mut result = vec![];
+    let mut k = 0;
+    while k < units.len() {
+        let unit = units[k];
+        if is_uri_unescaped(unit) || in_set(unit, extra_unescaped) {
+            result.push(unit);
+            k += 1;
+            continue;
+        }
+        // A lone surrogate cannot be encoded in UTF-8.
+        let c = match ::std::char::decode_utf16(units[k..].iter().cloned()).next() {
+            Some(Ok(c)) => c,
+            _ => return Err(uri_error()),
+        };
+        let mut buf = [0; 4];
+        for byte in c.encode_utf8(&mut buf).bytes() {
+            push_str(&mut result, &format!("%{:02X}", byte));
+        }
+        k += c.len_utf16();
+    }
+    Ok(JsString::from_units(result))
+}
+
+/// Decode(string, reservedSet)
+/// https://tc39.github.io/ecma262/#sec-decode
+fn decode(s: &JsString, reserved: &str) -> Result<JsString, RuntimeError> {
+    let units = s.units();
+    let mut result = vec![];
+    let mut k = 0;
+    while k < units.len() {
+        if units[k] != '%' as u16 {
+            result.push(units[k]);
+            k += 1;
+            continue;
+        }
+        let start = k;
+        let byte = percent_byte(&units, k).ok_or_else(uri_error)?;
+        k += 3;
+        if byte < 0x80 {
+            if in_set(byte as u16, reserved) {
+                result.extend_from_slice(&units[start..k]);
+            } else {
+                result.push(byte as u16);
+            }
+            continue;
+        }
+        // The number of bytes of the UTF-8 sequence is the number of leading 1 bits.
+        let n = (!byte).leading_zeros() as usize;
+        if n < 2 || n > 4 {
+            return Err(uri_error());
+        }
+        let mut bytes = vec![byte];
+        for _ in 1..n {
+            let byte = percent_byte(&units, k).ok_or_else(uri_error)?;
+            if byte & 0xc0 != 0x80 {
+                return Err(uri_error());
+            }
+            bytes.push(byte);
+            k += 3;
+        }
+        // from_utf8() also rejects overlong forms and surrogates.
+        match ::std::str::from_utf8(&bytes) {
+            Ok(c) => push_str(&mut result, c),
+            Err(_) => return Err(uri_error()),
+        }
+    }
+    Ok(JsString::from_units(result))
+}
+
+/// encodeURI(uri)
+fn encode_uri(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let encoded = encode(&arg_string(args), &format!("{}#", URI_RESERVED))?;
+    vm.set_return_value(Value::js_string(encoded));
+    Ok(())
+}
+
+/// decodeURI(encodedURI). Escape sequences of reserved characters are left as they are.
+fn decode_uri(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let decoded = decode(&arg_string(args), &format!("{}#", URI_RESERVED))?;
+    vm.set_return_value(Value::js_string(decoded));
+    Ok(())
+}
+
+/// encodeURIComponent(uriComponent)
+fn encode_uri_component(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let encoded = encode(&arg_string(args), "")?;
+    vm.set_return_value(Value::js_string(encoded));
+    Ok(())
+}
+
+/// decodeURIComponent(encodedURIComponent)
+fn decode_uri_component(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let decoded = decode(&arg_string(args), "")?;
+    vm.set_return_value(Value::js_string(decoded));
+    Ok(())
+}
+
+/// escape(string). A code unit is written as %XX if it is less than 256, and as %uXXXX if not.
+/// https://tc39.github.io/ecma262/#sec-escape-string
+fn escape(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let mut result = vec![];
+    for &unit in arg_string(args).units().iter() {
+        if (unit < 0x80 && (unit as u8).is_ascii_alphanumeric()) || in_set(unit, ESCAPE_UNESCAPED) {
+            result.push(unit);
+        } else if unit < 0x100 {
+            push_str(&mut result, &format!("%{:02X}", unit));
+        } else {
+            push_str(&mut result, &format!("%u{:04X}", unit));
+        }
+    }
+    vm.set_return_value(Value::js_string(JsString::from_units(result)));
+    Ok(())
+}
+
+/// unescape(string). A '%' which does not start an escape sequence is left as it is.
+/// https://tc39.github.io/ecma262/#sec-unescape-string
+fn unescape(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let s = arg_string(args);
+    let units = s.units();
+    let hex = |digits: &[u16]| {
+        digits
+            .iter()
+            .try_fold(0u16, |n, &d| hex_digit(d).map(|d| n << 4 | d as u16))
+    };
+
+    let mut result = vec![];
+    let mut k = 0;
+    while k < units.len() {
+        if units[k] == '%' as u16 {
+            if units.get(k + 1) == Some(&('u' as u16)) && k + 6 <= units.len() {
+                if let Some(unit) = hex(&units[k + 2..k + 6]) {
+                    result.push(unit);
+                    k += 6;
+                    continue;
+                }
+            }
+            if k + 3 <= units.len() {
+                if let Some(unit) = hex(&units[k + 1..k + 3]) {
+                    result.push(unit);
+                    k += 3;
+                    continue;
+                }
+            }
+        }
+        result.push(units[k]);
+        k += 1;
+    }
+    vm.set_return_value(Value::js_string(JsString::from_units(result)));
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use builtins::console::{inspect_with, InspectOptions};
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The 'util' module, loaded by require('util').
+
+pub fn init() -> Value {
+    make_object!(
+        inspect:        Value::default_builtin_function(inspect)
+    )
+}
+
+/// util.inspect(value[, options]) shows 'value' as console.log() does, but strings are quoted.
+/// 'options.depth' is how deep objects are shown (2 by default), where null and Infinity show
+/// everything. 'options.colors' styles the output with ANSI escape codes.
+fn inspect(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let val = args.get(0).cloned().unwrap_or(Value::Undefined);
+    let mut options = InspectOptions::default();
+    if let Some(opts @ Value::Object(_, ObjectKind::Ordinary)) = args.get(1) {
+        match opts.get_property(Value::string("depth".to_string()), None) {
+            Value::Undefined => {}
+            Value::Null => options.depth = None,
+            depth => {
+                let depth = depth.to_number();
+                options.depth = if depth == ::std::f64::INFINITY {
+                    None
+                } else if depth > 0.0 {
+                    Some(depth as usize)
+                } else {
+                    Some(0)
+                };
+            }
+        }
+        options.colors = opts
+            .get_property(Value::string("colors".to_string()), None)
+            .to_boolean();
+    }
+    vm.set_return_value(Value::string(inspect_with(&val, true, &options)));
+    Ok(())
+}
@@ -0,0 +1,398 @@
+use builtins::arraybuffer::buffer_source_bytes;
+use builtins::error::new_error;
+use builtins::promise;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive};
+use std::cell::RefCell;
+use std::fmt;
+use vm::{error::RuntimeError, value::*, vm::VM};
+use wasmi::{
+    Error, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef, HostError,
+    ImportResolver, MemoryDescriptor, MemoryRef, Module, ModuleInstance, ModuleRef, RuntimeArgs,
+    RuntimeValue, Signature, TableDescriptor, TableRef, Trap, TrapKind, ValueType,
+};
+
+// The WebAssembly global, backed by the wasmi interpreter.
+//
+//   WebAssembly.instantiate(bytes, { env: { log: function(x) { ... } } })
+//     .then(function(result) { result.instance.exports.add(1, 2) })
+//
+// Imports can only be functions, and only functions are exported: memories, tables and globals
+// are not supported. Numbers are passed as i32, f32 and f64, and BigInts as i64. An exception
+// thrown by an imported function stops the wasm code and is thrown from the exported function
+// which ran it.
+
+/// Names of the variables in the call object of exported functions.
+const INSTANCE: &str = "instance";
+const EXPORT: &str = "export";
+/// the imported functions, kept in the call object so that they are not collected.
+const IMPORTS: &str = "imports";
+
+/// An instance made by instantiate().
+struct Instance {
+    module: ModuleRef,
+    /// the result types of the imported functions, in the order of their indexes.
+    import_results: Vec<Option<ValueType>>,
+}
+
+thread_local!(
+    /// Instances live as long as the thread since it is not known when their exported
+    /// functions are collected.
+    static INSTANCES: RefCell<Vec<Instance>> = RefCell::new(vec![]);
+);
+
+pub fn init() -> Value {
+    make_object!(
+        instantiate:    Value::default_builtin_function(instantiate),
+        validate:       Value::default_builtin_function(validate)
+    )
+}
+
+/// WebAssembly.instantiate(bytes[, imports]). Returns a promise of { module, instance }, whose
+/// instance.exports has the exported functions.
+fn instantiate(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let bytes = match args.get(0).and_then(buffer_source_bytes) {
+        Some(bytes) => bytes,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: WebAssembly.instantiate(): bytes must be an ArrayBuffer or a view"
+                    .to_string(),
+            ))
+        }
+    };
+    let imports = args.get(1).cloned().unwrap_or(Value::Undefined);
+    let promise = promise::new_promise();
+    match new_instance(vm, &bytes, imports) {
+        Ok(exports) => {
+            let result = make_object!(
+                module:     make_object!(),
+                instance:   make_object!(exports: exports)
+            );
+            promise::resolve(vm, &promise, result)
+        }
+        Err(RuntimeError::Exception(err)) => promise::reject(vm, &promise, err),
+        Err(err) => return Err(err),
+    }
+    vm.set_return_value(promise);
+    Ok(())
+}
+
+/// WebAssembly.validate(bytes)
+fn validate(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let valid = match args.get(0).and_then(buffer_source_bytes) {
+        Some(bytes) => Module::from_buffer(&bytes).is_ok(),
+        None => false,
+    };
+    vm.set_return_value(Value::Bool(valid));
+    Ok(())
+}
+
+/// Instantiate the module and run its start function. Returns the exports object.
+fn new_instance(vm: &mut VM, bytes: &[u8], imports: Value) -> Result<Value, RuntimeError> {
+    let module = Module::from_buffer(bytes).map_err(|e| error("CompileError", e))?;
+    let resolver = Resolver {
+        imports: imports,
+        funcs: RefCell::new(vec![]),
+        results: RefCell::new(vec![]),
+    };
+    let not_started = ModuleInstance::new(&module, &resolver).map_err(|e| error("LinkError", e))?;
+    let funcs = resolver.funcs.into_inner();
+    let import_results = resolver.results.into_inner();
+
+    // The start function may remove the imported functions from 'imports'.
+    for func in &funcs {
+        vm.root(func.clone());
+    }
+    let module = {
+        let mut host = Host::new(vm, funcs.clone(), import_results.clone());
+        let res = not_started.run_start(&mut host);
+        host.finish(res)?
+    };
+
+    let index = INSTANCES.with(|instances| {
+        let mut instances = instances.borrow_mut();
+        instances.push(Instance {
+            module: module,
+            import_results: import_results,
+        });
+        instances.len() - 1
+    });
+    let funcs = Value::array_from_elems(funcs);
+    let exports: Vec<NamePropPair> = export_names(bytes)
+        .into_iter()
+        .map(|name| {
+            let mut func = Value::default_builtin_function(call_export);
+            if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = func {
+                callobj.set_value(INSTANCE.to_string(), Value::Number(index as f64));
+                callobj.set_value(EXPORT.to_string(), Value::string(name.clone()));
+                callobj.set_value(IMPORTS.to_string(), funcs.clone());
+            }
+            (name, Property::new(func))
+        })
+        .collect();
+    Ok(Value::object_from_npp(&exports))
+}
+
+/// An exported function.
+fn call_export(vm: &mut VM, args: &Vec<Value>, callobj: CallObjectRef) -> Result<(), RuntimeError> {
+    let index = callobj.get_value(INSTANCE)?.to_number() as usize;
+    let name = callobj.get_value(EXPORT)?.to_string();
+    let funcs = match callobj.get_value(IMPORTS)? {
+        Value::Object(_, ObjectKind::Array(ref array)) => {
+            array.elems.iter().map(|prop| prop.val.clone()).collect()
+        }
+        _ => vec![],
+    };
+    let (module, import_results) = INSTANCES.with(|instances| {
+        let instances = instances.borrow();
+        let instance = &instances[index];
+        (instance.module.clone(), instance.import_results.clone())
+    });
+    let func = match module
+        .export_by_name(&name)
+        .and_then(|e| e.as_func().cloned())
+    {
+        Some(func) => func,
+        None => unreachable!("exported function not found"),
+    };
+
+    let mut params = vec![];
+    for (i, ty) in func.signature().params().iter().enumerate() {
+        params.push(to_wasm(args.get(i).unwrap_or(&Value::Undefined), *ty)?);
+    }
+    let result = {
+        let mut host = Host::new(vm, funcs, import_results);
+        let res = FuncInstance::invoke(&func, &params, &mut host);
+        host.finish(res)?
+    };
+    vm.set_return_value(result.map_or(Value::Undefined, from_wasm));
+    Ok(())
+}
+
+fn error<E: fmt::Display>(name: &str, err: E) -> RuntimeError {
+    RuntimeError::Exception(new_error(format!("{}: {}", name, err)))
+}
+
+fn to_wasm(val: &Value, ty: ValueType) -> Result<RuntimeValue, RuntimeError> {
+    Ok(match ty {
+        ValueType::I32 => RuntimeValue::I32(val.to_uint32() as u32 as i32),
+        ValueType::I64 => match val {
+            // Wrapped to 64 bits like BigInt.asIntN(64, n).
+            Value::BigInt(n) => {
+                let modulus = BigInt::from_u64(u64::max_value()).unwrap() + BigInt::one();
+                let mut n = &**n % &modulus;
+                if n.is_negative() {
+                    n = n + modulus;
+                }
+                RuntimeValue::I64(n.to_u64().unwrap_or(0) as i64)
+            }
+            _ => {
+                return Err(RuntimeError::Type(format!(
+                    "type error: {} is not a BigInt",
+                    val.to_string()
+                )))
+            }
+        },
+        ValueType::F32 => RuntimeValue::F32((val.to_number() as f32).into()),
+        ValueType::F64 => RuntimeValue::F64(val.to_number().into()),
+    })
+}
+
+fn from_wasm(val: RuntimeValue) -> Value {
+    match val {
+        RuntimeValue::I32(n) => Value::Number(n as f64),
+        RuntimeValue::I64(n) => Value::bigint(BigInt::from_i64(n).unwrap()),
+        RuntimeValue::F32(n) => Value::Number(n.to_float() as f64),
+        RuntimeValue::F64(n) => Value::Number(n.to_float()),
+    }
+}
+
+/// Finds the imported functions in the imports object by their module and field names.
+struct Resolver {
+    imports: Value,
+    funcs: RefCell<Vec<Value>>,
+    results: RefCell<Vec<Option<ValueType>>>,
+}
+
+impl ImportResolver for Resolver {
+    fn resolve_func(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, Error> {
+        let func = self
+            .imports
+            .get_property(Value::string(module_name.to_string()), None)
+            .get_property(Value::string(field_name.to_string()), None);
+        match func {
+            Value::Object(_, ObjectKind::Function(_))
+            | Value::Object(_, ObjectKind::BuiltinFunction(_)) => {}
+            _ => {
+                return Err(Error::Instantiation(format!(
+                    "import {}.{} is not a function",
+                    module_name, field_name
+                )))
+            }
+        }
+        let mut funcs = self.funcs.borrow_mut();
+        funcs.push(func);
+        self.results.borrow_mut().push(signature.return_type());
+        Ok(FuncInstance::alloc_host(signature.clone(), funcs.len() - 1))
+    }
+
+    fn resolve_global(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &GlobalDescriptor,
+    ) -> Result<GlobalRef, Error> {
+        Err(unsupported_import("global", module_name, field_name))
+    }
+
+    fn resolve_memory(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &MemoryDescriptor,
+    ) -> Result<MemoryRef, Error> {
+        Err(unsupported_import("memory", module_name, field_name))
+    }
+
+    fn resolve_table(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &TableDescriptor,
+    ) -> Result<TableRef, Error> {
+        Err(unsupported_import("table", module_name, field_name))
+    }
+}
+
+fn unsupported_import(kind: &str, module_name: &str, field_name: &str) -> Error {
+    Error::Instantiation(format!(
+        "import {}.{}: {} imports are not supported",
+        module_name, field_name, kind
+    ))
+}
+
+/// Runs the imported functions called by wasm code.
+struct Host<'a> {
+    vm: &'a mut VM,
+    funcs: Vec<Value>,
+    results: Vec<Option<ValueType>>,
+    /// the error of an imported function, which has stopped the wasm code.
+    error: Option<RuntimeError>,
+}
+
+/// The trap of an error in an imported function. The error itself is kept in Host.
+#[derive(Debug)]
+struct ImportError;
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an imported function threw an exception")
+    }
+}
+
+impl HostError for ImportError {}
+
+impl<'a> Host<'a> {
+    fn new(vm: &'a mut VM, funcs: Vec<Value>, results: Vec<Option<ValueType>>) -> Host<'a> {
+        Host {
+            vm: vm,
+            funcs: funcs,
+            results: results,
+            error: None,
+        }
+    }
+
+    /// the result of running wasm code. The error of an imported function is returned as it
+    /// is, and traps are thrown as RuntimeErrors.
+    fn finish<T, E: fmt::Display>(self, res: Result<T, E>) -> Result<T, RuntimeError> {
+        match (res, self.error) {
+            (Ok(val), _) => Ok(val),
+            (Err(_), Some(err)) => Err(err),
+            (Err(err), None) => Err(error("RuntimeError", err)),
+        }
+    }
+
+    fn call_import(&mut self, index: usize, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.vm.call_function_simply(&self.funcs[index], &args)?;
+        Ok(self.vm.state.stack.pop().unwrap_or(Value::Undefined))
+    }
+}
+
+impl<'a> Externals for Host<'a> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let args = args.as_ref().iter().map(|val| from_wasm(*val)).collect();
+        let result = match self.call_import(index, args) {
+            Ok(val) => match self.results[index] {
+                Some(ty) => to_wasm(&val, ty).map(Some),
+                None => Ok(None),
+            },
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                self.error = Some(err);
+                Err(Trap::new(TrapKind::Host(Box::new(ImportError))))
+            }
+        }
+    }
+}
+
+/// the names of the exported functions, read from the export section of the module.
+fn export_names(bytes: &[u8]) -> Vec<String> {
+    fn leb128(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+        let mut n = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.get(*pos)?;
+            *pos += 1;
+            n |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Some(n);
+            }
+            shift += 7;
+        }
+    }
+
+    fn exports(bytes: &[u8]) -> Option<Vec<String>> {
+        const EXPORT_SECTION: u8 = 7;
+        const FUNCTION: u8 = 0;
+        // The magic number and the version.
+        let mut pos = 8;
+        while pos < bytes.len() {
+            let id = bytes[pos];
+            pos += 1;
+            let size = leb128(bytes, &mut pos)?;
+            if id != EXPORT_SECTION {
+                pos += size;
+                continue;
+            }
+            let mut names = vec![];
+            for _ in 0..leb128(bytes, &mut pos)? {
+                let len = leb128(bytes, &mut pos)?;
+                let name = bytes.get(pos..pos + len)?;
+                pos += len;
+                let kind = *bytes.get(pos)?;
+                pos += 1;
+                leb128(bytes, &mut pos)?;
+                if kind == FUNCTION {
+                    names.push(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            return Some(names);
+        }
+        Some(vec![])
+    }
+
+    // The module has been validated by wasmi.
+    exports(bytes).unwrap_or(vec![])
+}
@@ -0,0 +1,311 @@
+use builtin::BuiltinFuncTy;
+use gc;
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// WeakMap, WeakSet, WeakRef and FinalizationRegistry, which refer to objects without keeping
+// them alive. Their objects are kept in a WeakTable, whose entries the GC removes when it frees
+// the objects (see gc.rs). An object is identified by the address of its property map.
+
+thread_local!(
+    pub static WEAK_MAP_PROTOTYPE: Value = {
+        make_object!(
+            get:        Value::default_builtin_function(weak_map_prototype_get),
+            set:        Value::default_builtin_function(weak_map_prototype_set),
+            has:        Value::default_builtin_function(weak_prototype_has),
+            delete:     Value::default_builtin_function(weak_prototype_delete)
+        )
+    };
+
+    pub static WEAK_SET_PROTOTYPE: Value = {
+        make_object!(
+            add:        Value::default_builtin_function(weak_set_prototype_add),
+            has:        Value::default_builtin_function(weak_prototype_has),
+            delete:     Value::default_builtin_function(weak_prototype_delete)
+        )
+    };
+
+    pub static WEAK_REF_PROTOTYPE: Value = {
+        make_object!(
+            deref:      Value::default_builtin_function(weak_ref_prototype_deref)
+        )
+    };
+
+    pub static FINALIZATION_REGISTRY_PROTOTYPE: Value = {
+        make_object!(
+            register:   Value::default_builtin_function(finalization_registry_prototype_register),
+            unregister: Value::default_builtin_function(finalization_registry_prototype_unregister)
+        )
+    };
+);
+
+pub fn init() -> Vec<(&'static str, Value)> {
+    let constructors: [(&'static str, WeakKind, BuiltinFuncTy); 4] = [
+        ("WeakMap", WeakKind::WeakMap, weak_map_new),
+        ("WeakSet", WeakKind::WeakSet, weak_set_new),
+        ("WeakRef", WeakKind::WeakRef, weak_ref_new),
+        (
+            "FinalizationRegistry",
+            WeakKind::FinalizationRegistry,
+            finalization_registry_new,
+        ),
+    ];
+    constructors
+        .iter()
+        .map(|&(name, kind, func)| {
+            let mut prototype = prototype(kind);
+            let obj = Value::builtin_function(func, None, &mut vec![], Some(prototype.clone()));
+            prototype.set_constructor(obj.clone());
+            (name, obj)
+        })
+        .collect()
+}
+
+pub fn prototype(kind: WeakKind) -> Value {
+    match kind {
+        WeakKind::WeakMap => WEAK_MAP_PROTOTYPE.with(|x| x.clone()),
+        WeakKind::WeakSet => WEAK_SET_PROTOTYPE.with(|x| x.clone()),
+        WeakKind::WeakRef => WEAK_REF_PROTOTYPE.with(|x| x.clone()),
+        WeakKind::FinalizationRegistry => FINALIZATION_REGISTRY_PROTOTYPE.with(|x| x.clone()),
+    }
+}
+
+/// the address which identifies 'val' if it is an object. Only objects are held weakly.
+fn object_addr(val: Option<&Value>) -> Option<usize> {
+    match val {
+        Some(Value::Object(ref map, _)) => Some(map.addr()),
+        _ => None,
+    }
+}
+
+/// the table of 'this', which must be of 'kind'.
+fn this_table(callobj: &CallObjectRef, kind: WeakKind) -> Result<WeakTableRef, RuntimeError> {
+    match *callobj.this {
+        Value::Object(_, ObjectKind::WeakTable(ref table)) if table.kind == kind => {
+            Ok(table.clone())
+        }
+        _ => Err(RuntimeError::Type(format!(
+            "type error: {} method called on incompatible receiver",
+            kind.name()
+        ))),
+    }
+}
+
+/// new WeakMap()
+fn weak_map_new(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::weak_table(WeakKind::WeakMap, Value::Undefined));
+    Ok(())
+}
+
+/// new WeakSet()
+fn weak_set_new(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    vm.set_return_value(Value::weak_table(WeakKind::WeakSet, Value::Undefined));
+    Ok(())
+}
+
+/// new WeakRef(target)
+fn weak_ref_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let addr = match object_addr(args.get(0)) {
+        Some(addr) => addr,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: WeakRef: target must be an object".to_string(),
+            ))
+        }
+    };
+    let weak_ref = Value::weak_table(WeakKind::WeakRef, Value::Undefined);
+    if let Value::Object(_, ObjectKind::WeakTable(ref table)) = weak_ref {
+        let mut table = table.clone();
+        table.entries.insert(addr, args[0].clone());
+    }
+    vm.set_return_value(weak_ref);
+    Ok(())
+}
+
+/// new FinalizationRegistry(cleanup)
+fn finalization_registry_new(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    _: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let cleanup = match args.get(0) {
+        Some(Value::Object(_, ObjectKind::Function(_)))
+        | Some(Value::Object(_, ObjectKind::BuiltinFunction(_))) => args[0].clone(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: FinalizationRegistry: cleanup must be callable".to_string(),
+            ))
+        }
+    };
+    vm.set_return_value(Value::weak_table(WeakKind::FinalizationRegistry, cleanup));
+    Ok(())
+}
+
+/// WeakMap.prototype.get(key)
+fn weak_map_prototype_get(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let table = this_table(&callobj, WeakKind::WeakMap)?;
+    let val = object_addr(args.get(0))
+        .and_then(|addr| table.entries.get(&addr).cloned())
+        .unwrap_or(Value::Undefined);
+    vm.set_return_value(val);
+    Ok(())
+}
+
+/// WeakMap.prototype.set(key, value)
+fn weak_map_prototype_set(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut table = this_table(&callobj, WeakKind::WeakMap)?;
+    let addr = match object_addr(args.get(0)) {
+        Some(addr) => addr,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: Invalid value used as weak map key".to_string(),
+            ))
+        }
+    };
+    let val = args.get(1).cloned().unwrap_or(Value::Undefined);
+    table.entries.insert(addr, val);
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// WeakSet.prototype.add(value)
+fn weak_set_prototype_add(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut table = this_table(&callobj, WeakKind::WeakSet)?;
+    let addr = match object_addr(args.get(0)) {
+        Some(addr) => addr,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: Invalid value used in weak set".to_string(),
+            ))
+        }
+    };
+    table.entries.insert(addr, Value::Undefined);
+    vm.set_return_value((*callobj.this).clone());
+    Ok(())
+}
+
+/// the table of a WeakMap or a WeakSet 'this'.
+fn this_map_or_set(callobj: &CallObjectRef) -> Result<WeakTableRef, RuntimeError> {
+    this_table(callobj, WeakKind::WeakMap).or_else(|_| this_table(callobj, WeakKind::WeakSet))
+}
+
+/// WeakMap.prototype.has(key) and WeakSet.prototype.has(value)
+fn weak_prototype_has(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let table = this_map_or_set(&callobj)?;
+    let has = object_addr(args.get(0)).map_or(false, |addr| table.entries.contains_key(&addr));
+    vm.set_return_value(Value::Bool(has));
+    Ok(())
+}
+
+/// WeakMap.prototype.delete(key) and WeakSet.prototype.delete(value)
+fn weak_prototype_delete(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut table = this_map_or_set(&callobj)?;
+    let deleted = match object_addr(args.get(0)) {
+        Some(addr) => table.entries.remove(&addr).is_some(),
+        None => false,
+    };
+    vm.set_return_value(Value::Bool(deleted));
+    Ok(())
+}
+
+/// WeakRef.prototype.deref() returns the target, or undefined if it has been freed.
+fn weak_ref_prototype_deref(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let table = this_table(&callobj, WeakKind::WeakRef)?;
+    let target = table
+        .entries
+        .values()
+        .next()
+        .cloned()
+        .unwrap_or(Value::Undefined);
+    vm.set_return_value(target);
+    Ok(())
+}
+
+/// FinalizationRegistry.prototype.register(target, held[, token])
+fn finalization_registry_prototype_register(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut table = this_table(&callobj, WeakKind::FinalizationRegistry)?;
+    let target = match object_addr(args.get(0)) {
+        Some(addr) => addr,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: register(): target must be an object".to_string(),
+            ))
+        }
+    };
+    // The held value would keep the target alive.
+    if object_addr(args.get(1)) == Some(target) {
+        return Err(RuntimeError::Type(
+            "type error: register(): target and holdings must not be same".to_string(),
+        ));
+    }
+    let token = match args.get(2) {
+        None | Some(Value::Undefined) => None,
+        Some(_) => match object_addr(args.get(2)) {
+            Some(addr) => Some(addr),
+            None => {
+                return Err(RuntimeError::Type(
+                    "type error: register(): unregisterToken must be an object".to_string(),
+                ))
+            }
+        },
+    };
+    gc::write_barrier(&mut *table);
+    table.registrations.push(Registration {
+        target: target,
+        held: args.get(1).cloned().unwrap_or(Value::Undefined),
+        token: token,
+    });
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// FinalizationRegistry.prototype.unregister(token) removes the registrations made with 'token'
+/// and returns whether there were any.
+fn finalization_registry_prototype_unregister(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let mut table = this_table(&callobj, WeakKind::FinalizationRegistry)?;
+    let token = match object_addr(args.get(0)) {
+        Some(addr) => addr,
+        None => {
+            return Err(RuntimeError::Type(
+                "type error: unregister(): unregisterToken must be an object".to_string(),
+            ))
+        }
+    };
+    let count = table.registrations.len();
+    table
+        .registrations
+        .retain(|registration| registration.token != Some(token));
+    vm.set_return_value(Value::Bool(table.registrations.len() < count));
+    Ok(())
+}
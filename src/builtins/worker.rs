@@ -0,0 +1,320 @@
+use builtins::structured_clone::{deserialize, serialize, Serialized};
+use parser::Parser;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::thread;
+use vm::{error::RuntimeError, task::Task, value::*, vm::VM};
+
+// Worker. A worker runs a script on its own thread in a new VM. The parent and the worker send
+// each other messages by postMessage(), whose values are copied by the structured clone
+// algorithm, and receive them in onmessage. The event loop of each side delivers the messages
+// as tasks (see Task::Message):
+//
+//   var worker = new Worker('worker.js')
+//   worker.onmessage = function(e) { console.log(e.data) }
+//   worker.postMessage(1)
+//
+//   // worker.js
+//   onmessage = function(e) { postMessage(e.data * 2) }
+//
+// The parent waits for the worker while it runs, and a worker keeps running while it has an
+// onmessage handler, until it calls close() or the parent calls worker.terminate(). Workers run
+// without the JIT.
+
+/// Name of the internal property of a Worker which holds the index in WORKERS.
+const WORKER_ID: &str = "[[WorkerId]]";
+
+thread_local!(
+    pub static WORKER_PROTOTYPE: Value = {
+        make_object!(
+            postMessage:    Value::default_builtin_function(worker_prototype_post_message),
+            terminate:      Value::default_builtin_function(worker_prototype_terminate)
+        )
+    };
+
+    /// the workers started by this thread, with the flags which terminate their VMs.
+    static WORKERS: RefCell<Vec<(Port, Arc<AtomicBool>)>> = RefCell::new(vec![]);
+
+    /// the port to the parent if this thread runs a worker.
+    static PARENT: RefCell<Option<Port>> = RefCell::new(None);
+);
+
+/// messages sent to one side.
+#[derive(Debug, Default)]
+struct Mailbox {
+    messages: VecDeque<Serialized>,
+    /// set when either side has closed the channel.
+    closed: bool,
+}
+
+/// One side of the channel between a worker and its parent.
+#[derive(Debug, Clone)]
+pub struct Port {
+    inbox: Arc<Mutex<Mailbox>>,
+    outbox: Arc<Mutex<Mailbox>>,
+}
+
+impl Port {
+    /// the ports of a parent and its worker.
+    fn pair() -> (Port, Port) {
+        let to_parent = Arc::new(Mutex::new(Mailbox::default()));
+        let to_worker = Arc::new(Mutex::new(Mailbox::default()));
+        (
+            Port {
+                inbox: to_parent.clone(),
+                outbox: to_worker.clone(),
+            },
+            Port {
+                inbox: to_worker,
+                outbox: to_parent,
+            },
+        )
+    }
+
+    /// Send a message to the other side. It is dropped if the channel has been closed.
+    fn send(&self, message: Serialized) {
+        let mut outbox = self.outbox.lock().unwrap();
+        if !outbox.closed {
+            outbox.messages.push_back(message);
+        }
+    }
+
+    fn receive(&self) -> Option<Serialized> {
+        self.inbox.lock().unwrap().messages.pop_front()
+    }
+
+    /// true while messages may be received.
+    fn is_open(&self) -> bool {
+        let inbox = self.inbox.lock().unwrap();
+        !inbox.closed || !inbox.messages.is_empty()
+    }
+
+    /// Close the channel. The messages not received by this side are dropped, while the other
+    /// side still receives the messages sent before.
+    fn close(&self) {
+        let mut inbox = self.inbox.lock().unwrap();
+        inbox.closed = true;
+        inbox.messages.clear();
+        self.outbox.lock().unwrap().closed = true;
+    }
+}
+
+pub fn init() -> Value {
+    let mut prototype = WORKER_PROTOTYPE.with(|x| x.clone());
+    let obj = Value::builtin_function(worker_new, None, &mut vec![], Some(prototype.clone()));
+    prototype.set_constructor(obj.clone());
+    obj
+}
+
+/// new Worker(file)
+fn worker_new(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let file_name = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => {
+            return Err(RuntimeError::Type(
+                "type error: new Worker(): file must be a string".to_string(),
+            ))
+        }
+    };
+    let (port, worker_port) = Port::pair();
+    let terminate_flag = Arc::new(AtomicBool::new(false));
+    {
+        let terminate_flag = terminate_flag.clone();
+        thread::spawn(move || run_worker(file_name, worker_port, terminate_flag));
+    }
+
+    let id = WORKERS.with(|workers| {
+        let mut workers = workers.borrow_mut();
+        workers.push((port.clone(), terminate_flag));
+        workers.len() - 1
+    });
+    let worker = make_object!(
+        onmessage:  Value::Null,
+        __proto__:  WORKER_PROTOTYPE.with(|x| x.clone())
+    );
+    if let Value::Object(ref map, _) = worker {
+        let mut map = map.clone();
+        map.insert(
+            WORKER_ID.to_string(),
+            Property {
+                val: Value::Number(id as f64),
+                writable: false,
+                enumerable: false,
+                configurable: false,
+                accessor: None,
+            },
+        );
+    }
+    vm.task_mgr.add_task(Task::Message {
+        target: Some(worker.clone()),
+        port: port,
+    });
+    vm.set_return_value(worker);
+    Ok(())
+}
+
+/// the port and the terminate flag of the Worker 'this'.
+fn this_worker(callobj: &CallObjectRef) -> Result<(Port, Arc<AtomicBool>), RuntimeError> {
+    let id = match *callobj.this {
+        Value::Object(ref map, _) => map.get(WORKER_ID).map(|prop| prop.val.to_number()),
+        _ => None,
+    };
+    match id {
+        Some(id) => Ok(WORKERS.with(|workers| workers.borrow()[id as usize].clone())),
+        None => Err(RuntimeError::Type(
+            "type error: Worker method called on incompatible receiver".to_string(),
+        )),
+    }
+}
+
+/// Worker.prototype.postMessage(message)
+fn worker_prototype_post_message(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let (port, _) = this_worker(&callobj)?;
+    port.send(serialize(args.get(0).unwrap_or(&Value::Undefined))?);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// Worker.prototype.terminate() stops the worker at once.
+fn worker_prototype_terminate(
+    vm: &mut VM,
+    _: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let (port, terminate_flag) = this_worker(&callobj)?;
+    port.close();
+    terminate_flag.store(true, Ordering::SeqCst);
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// postMessage(message) in a worker.
+fn post_message(vm: &mut VM, args: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    let message = serialize(args.get(0).unwrap_or(&Value::Undefined))?;
+    PARENT.with(|parent| {
+        if let Some(ref port) = *parent.borrow() {
+            port.send(message)
+        }
+    });
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// close() in a worker stops receiving messages, so that the worker ends when its other tasks
+/// are done.
+fn close(vm: &mut VM, _: &Vec<Value>, _: CallObjectRef) -> Result<(), RuntimeError> {
+    PARENT.with(|parent| {
+        if let Some(ref port) = *parent.borrow() {
+            port.close()
+        }
+    });
+    vm.set_return_value(Value::Undefined);
+    Ok(())
+}
+
+/// Run the script of a worker. This is the body of the thread of the worker.
+fn run_worker(file_name: String, port: Port, terminate_flag: Arc<AtomicBool>) {
+    let source = match fs::read_to_string(&file_name) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Worker: Couldn't read '{}': {}", file_name, e);
+            port.close();
+            return;
+        }
+    };
+
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    vm.terminate_flag = terminate_flag;
+    {
+        let global = &mut vm.codegen.global_varmap;
+        global.set_value(
+            "postMessage".to_string(),
+            Value::default_builtin_function(post_message),
+        );
+        global.set_value("close".to_string(), Value::default_builtin_function(close));
+    }
+    PARENT.with(|parent| *parent.borrow_mut() = Some(port.clone()));
+
+    let mut parser = Parser::new(source);
+    let node = match parser.parse_all() {
+        Ok(node) => node,
+        Err(err) => {
+            parser.handle_error(err);
+            port.close();
+            return;
+        }
+    };
+    let mut iseq = vec![];
+    if vm.codegen.compile(&node, &mut iseq, false).is_err() {
+        eprintln!("Worker: Couldn't compile '{}'", file_name);
+        port.close();
+        return;
+    }
+
+    vm.task_mgr.add_task(Task::Message {
+        target: None,
+        port: port.clone(),
+    });
+    match vm.run(iseq) {
+        Ok(_) | Err(RuntimeError::Terminated) => {}
+        Err(e) => e.show_error_message(),
+    }
+    port.close();
+}
+
+/// Deliver the messages received through 'port' to the onmessage handler of 'target', which is
+/// a Worker, or of the global object of a worker if it is None. Run by the event loop.
+pub fn deliver_messages(
+    vm: &mut VM,
+    target: Option<Value>,
+    port: Port,
+) -> Result<(), RuntimeError> {
+    // The task is not in the event loop while it runs, so nothing else may refer to 'target'.
+    let roots = target.iter().cloned().collect::<Vec<Value>>();
+    vm.with_roots(&roots, |vm| {
+        while let Some(message) = port.receive() {
+            if let Some(handler) = handler(vm, &target) {
+                let event = make_object!(data: deserialize(&message));
+                vm.call_function_simply(&handler, &vec![event])?;
+                vm.state.stack.pop(); // return value is not used
+            }
+        }
+        Ok(())
+    })?;
+    // A worker stops waiting for messages when it has no handler.
+    let waiting = target.is_some() || handler(vm, &target).is_some();
+    if port.is_open() && waiting {
+        vm.task_mgr.retain_task(Task::Message {
+            target: target,
+            port: port,
+        });
+    }
+    Ok(())
+}
+
+/// the onmessage function of 'target'.
+fn handler(vm: &VM, target: &Option<Value>) -> Option<Value> {
+    let handler = match target {
+        Some(worker) => worker.get_property(Value::string("onmessage".to_string()), None),
+        None => vm
+            .codegen
+            .global_varmap
+            .get_value("onmessage")
+            .unwrap_or(Value::Undefined),
+    };
+    match handler {
+        Value::Object(_, ObjectKind::Function(_))
+        | Value::Object(_, ObjectKind::BuiltinFunction(_)) => Some(handler),
+        _ => None,
+    }
+}
@@ -0,0 +1,421 @@
+use atom::Atom;
+use bytecode_gen::{ByteCode, ByteCodeGen};
+use num_bigint::BigInt;
+use std::rc::Rc;
+use verifier;
+use vm::callobj::CallObject;
+use vm::jsstring::JsString;
+use vm::upvalue::UpvalueSource;
+use vm::value::*;
+use vm::vm::{ConstantTable, VM};
+
+// Precompiled script (.rbc) format. All integers are little endian.
+//
+//   magic                 b"RBC\0"
+//   version               u32
+//   inline cache count    u32
+//   variable names        u32 count, then strings
+//   constant values       u32 count, then values
+//   top-level code        bytes
+//
+// A string is its u32 length followed by UTF-8 bytes, a JS string is its u32 length followed
+// by u16 code units, and bytes are the u32 length followed by the raw bytes. A value is a tag
+// byte followed by its payload:
+//
+//   0 Empty, 1 Null, 2 Undefined
+//   3 Bool        u8
+//   4 Number      u64 (bits of f64)
+//   5 String      JS string
+//   6 BigInt      string (decimal)
+//   7 Function    u32 count of parameters, then (string name, u8 rest param?) for each,
+//                 u8 strict?, u8 source text? followed by the string if 1, string name,
+//                 u32 length, the code as bytes, and u32 count of upvalues, then
+//                 (u8 kind, u32 index) for each, where kind 0 is a slot and 1 an upvalue of
+//                 the enclosing function
+
+const MAGIC: &[u8] = b"RBC\0";
+
+/// Incremented whenever the format or the instruction set changes.
+pub const VERSION: u32 = 14;
+
+/// Encode 'iseq' and the constant table it refers to.
+pub fn serialize(iseq: &ByteCode, bytecode_gen: &ByteCodeGen) -> Result<Vec<u8>, String> {
+    let mut w = Writer { buf: vec![] };
+    w.buf.extend_from_slice(MAGIC);
+    w.u32(VERSION);
+    w.u32(bytecode_gen.inline_cache_count as u32);
+
+    let const_table = &bytecode_gen.const_table;
+    w.u32(const_table.string.len() as u32);
+    for name in &const_table.string {
+        w.string(name.as_str());
+    }
+    w.u32(const_table.value.len() as u32);
+    for val in &const_table.value {
+        w.value(val)?;
+    }
+
+    w.bytes(iseq);
+    Ok(w.buf)
+}
+
+/// Decode a precompiled script. The constant table and the inline cache count of 'vm' are
+/// replaced with the ones in the file, and the top-level code is returned.
+pub fn deserialize(vm: &mut VM, buf: &[u8]) -> Result<ByteCode, String> {
+    let mut r = Reader { buf: buf, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err("not a precompiled script".to_string());
+    }
+    let version = r.u32()?;
+    if version != VERSION {
+        return Err(format!(
+            "precompiled script version {} is not supported (expected {})",
+            version, VERSION
+        ));
+    }
+    let inline_cache_count = r.u32()? as usize;
+
+    let mut const_table = ConstantTable::new();
+    for _ in 0..r.u32()? {
+        const_table.string.push(Atom::new(&r.string()?));
+    }
+    let global = vm.codegen.global_varmap.vals.clone();
+    for _ in 0..r.u32()? {
+        let val = r.value(&global)?;
+        const_table.value.push(val);
+    }
+
+    let iseq = r.bytes()?;
+    if r.pos != buf.len() {
+        return Err("trailing data in precompiled script".to_string());
+    }
+
+    vm.codegen.bytecode_gen.const_table = const_table;
+    vm.codegen.bytecode_gen.inline_cache_count = inline_cache_count;
+    Ok(iseq)
+}
+
+/// deserialize() which also verifies the code, so that a broken or crafted file is rejected
+/// before it runs.
+pub fn load(vm: &mut VM, buf: &[u8]) -> Result<ByteCode, String> {
+    let iseq = deserialize(vm, buf)?;
+    verifier::verify_script(&iseq, &vm.codegen.bytecode_gen.const_table)
+        .map_err(|msg| format!("invalid bytecode: {}", msg))?;
+    Ok(iseq)
+}
+
+/// Whether 'buf' starts like a precompiled script.
+pub fn is_precompiled(buf: &[u8]) -> bool {
+    buf.starts_with(MAGIC)
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+
+    fn u32(&mut self, n: u32) {
+        for i in 0..4 {
+            self.buf.push((n >> (i * 8)) as u8);
+        }
+    }
+
+    fn u64(&mut self, n: u64) {
+        for i in 0..8 {
+            self.buf.push((n >> (i * 8)) as u8);
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+
+    fn js_string(&mut self, s: &JsString) {
+        self.u32(s.len() as u32);
+        for unit in s.units().iter() {
+            self.buf.push(*unit as u8);
+            self.buf.push((*unit >> 8) as u8);
+        }
+    }
+
+    fn value(&mut self, val: &Value) -> Result<(), String> {
+        match val {
+            Value::Empty => self.u8(0),
+            Value::Null => self.u8(1),
+            Value::Undefined => self.u8(2),
+            Value::Bool(b) => {
+                self.u8(3);
+                self.u8(*b as u8);
+            }
+            Value::Number(n) => {
+                self.u8(4);
+                self.u64(n.to_bits());
+            }
+            Value::String(s) => {
+                self.u8(5);
+                self.js_string(s);
+            }
+            Value::BigInt(n) => {
+                self.u8(6);
+                self.string(&n.to_str_radix(10));
+            }
+            Value::Object(
+                map,
+                ObjectKind::Function(box (
+                    FuncInfo {
+                        iseq,
+                        params,
+                        strict,
+                        source,
+                        upvalues,
+                        ..
+                    },
+                    _,
+                )),
+            ) => {
+                self.u8(7);
+                self.u32(params.len() as u32);
+                for (name, is_rest_param) in params {
+                    self.string(name);
+                    self.u8(*is_rest_param as u8);
+                }
+                self.u8(*strict as u8);
+                match source {
+                    Some(source) => {
+                        self.u8(1);
+                        self.string(source);
+                    }
+                    None => self.u8(0),
+                }
+                let prop = |key: &str| map.get(key).map_or(Value::Undefined, |p| p.val.clone());
+                self.string(&prop("name").to_string());
+                self.u32(prop("length").to_number() as u32);
+                self.bytes(iseq);
+                self.u32(upvalues.len() as u32);
+                for source in upvalues.iter() {
+                    match *source {
+                        UpvalueSource::Slot(slot) => {
+                            self.u8(0);
+                            self.u32(slot as u32);
+                        }
+                        UpvalueSource::Upvalue(n) => {
+                            self.u8(1);
+                            self.u32(n as u32);
+                        }
+                    }
+                }
+            }
+            val => {
+                return Err(format!(
+                    "cannot serialize constant {}",
+                    val.format(1, false)
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() - self.pos < len {
+            return Err("unexpected end of precompiled script".to_string());
+        }
+        self.pos += len;
+        Ok(&self.buf[self.pos - len..self.pos])
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok((0..4).fold(0, |n, i| n | (bytes[i] as u32) << (i * 8)))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let bytes = self.take(8)?;
+        Ok((0..8).fold(0, |n, i| n | (bytes[i] as u64) << (i * 8)))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?).map_err(|_| "invalid UTF-8 string".to_string())
+    }
+
+    fn js_string(&mut self) -> Result<JsString, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len * 2)?;
+        Ok(JsString::from_units(
+            bytes
+                .chunks(2)
+                .map(|unit| unit[0] as u16 | (unit[1] as u16) << 8)
+                .collect(),
+        ))
+    }
+
+    fn value(&mut self, global: &PropMapRef) -> Result<Value, String> {
+        Ok(match self.u8()? {
+            0 => Value::Empty,
+            1 => Value::Null,
+            2 => Value::Undefined,
+            3 => Value::Bool(self.u8()? != 0),
+            4 => Value::Number(f64::from_bits(self.u64()?)),
+            5 => Value::js_string(self.js_string()?),
+            6 => match BigInt::parse_bytes(self.string()?.as_bytes(), 10) {
+                Some(n) => Value::bigint(n),
+                None => return Err("invalid BigInt constant".to_string()),
+            },
+            7 => {
+                let mut params = vec![];
+                for _ in 0..self.u32()? {
+                    let name = self.string()?;
+                    params.push((name, self.u8()? != 0));
+                }
+                let strict = self.u8()? != 0;
+                let source = match self.u8()? {
+                    0 => None,
+                    _ => Some(Rc::new(self.string()?)),
+                };
+                let name = self.string()?;
+                let length = self.u32()? as usize;
+                let iseq = self.bytes()?;
+                let mut upvalues = vec![];
+                for _ in 0..self.u32()? {
+                    upvalues.push(match (self.u8()?, self.u32()?) {
+                        (0, slot) if slot <= 0xff => UpvalueSource::Slot(slot as u8),
+                        (1, n) => UpvalueSource::Upvalue(n as usize),
+                        _ => return Err("invalid upvalue".to_string()),
+                    });
+                }
+                // Same as the code generator does for function declarations and expressions.
+                let this = if strict {
+                    Value::Undefined
+                } else {
+                    Value::object(global.clone())
+                };
+                let callobj = CallObject::new_with_this(this);
+                let mut val = Value::function(iseq, params, strict, source, name, length, callobj);
+                if let Value::Object(_, ObjectKind::Function(box (ref mut func_info, _))) = val {
+                    func_info.upvalues = Rc::new(upvalues);
+                }
+                val
+            }
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        })
+    }
+}
+
+#[test]
+fn test() {
+    let mut bytecode_gen = ByteCodeGen::new();
+    bytecode_gen.inline_cache_count = 3;
+    bytecode_gen.const_table.string.push(Atom::new("x"));
+    let mut closure = Value::function(
+        vec![0x01, 0x02],
+        vec![("a".to_string(), false), ("b".to_string(), true)],
+        true,
+        Some(Rc::new("function f(a, ...b) {}".to_string())),
+        "f".to_string(),
+        1,
+        CallObject::new_with_this(Value::Undefined),
+    );
+    if let Value::Object(_, ObjectKind::Function(box (ref mut func_info, _))) = closure {
+        func_info.upvalues = Rc::new(vec![UpvalueSource::Slot(3), UpvalueSource::Upvalue(1)]);
+    }
+    bytecode_gen.const_table.value.extend(vec![
+        Value::Undefined,
+        Value::Bool(true),
+        Value::Number(-0.5),
+        Value::string("死して屍".to_string()),
+        Value::bigint(BigInt::parse_bytes(b"-123456789012345678901234567890", 10).unwrap()),
+        closure,
+        Value::js_string(JsString::from_units(vec![0xd800, 0x61])),
+    ]);
+    let buf = serialize(&vec![0x0a, 0x0b, 0x0c], &bytecode_gen).unwrap();
+    assert!(is_precompiled(&buf));
+
+    let mut vm = VM::new();
+    assert_eq!(deserialize(&mut vm, &buf).unwrap(), vec![0x0a, 0x0b, 0x0c]);
+    let bytecode_gen = &vm.codegen.bytecode_gen;
+    assert_eq!(bytecode_gen.inline_cache_count, 3);
+    assert_eq!(bytecode_gen.const_table.string, vec![Atom::new("x")]);
+    let values: Vec<String> = bytecode_gen
+        .const_table
+        .value
+        .iter()
+        .map(|val| val.format(1, false))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            "undefined",
+            "true",
+            "-0.5",
+            "'死して屍'",
+            "-123456789012345678901234567890n",
+            "[Function]",
+            "'\u{fffd}a'",
+        ]
+    );
+    // A lone surrogate is kept as it is.
+    assert_eq!(
+        bytecode_gen.const_table.value[6],
+        Value::js_string(JsString::from_units(vec![0xd800, 0x61]))
+    );
+    match &bytecode_gen.const_table.value[5] {
+        Value::Object(
+            map,
+            ObjectKind::Function(box (
+                FuncInfo {
+                    iseq,
+                    params,
+                    strict,
+                    source,
+                    upvalues,
+                    ..
+                },
+                _,
+            )),
+        ) => {
+            assert_eq!(**iseq, vec![0x01, 0x02]);
+            assert_eq!(
+                **upvalues,
+                vec![UpvalueSource::Slot(3), UpvalueSource::Upvalue(1)]
+            );
+            assert!(strict);
+            assert_eq!(source, &Some(Rc::new("function f(a, ...b) {}".to_string())));
+            assert_eq!(map.get("name").unwrap().val, Value::string("f".to_string()));
+            assert_eq!(map.get("length").unwrap().val, Value::Number(1.0));
+            assert_eq!(
+                params,
+                &vec![("a".to_string(), false), ("b".to_string(), true)]
+            );
+        }
+        _ => panic!(),
+    }
+
+    // The code runs past its end, and the function has a CONSTRUCT without its operand.
+    assert!(load(&mut vm, &buf).is_err());
+    assert!(deserialize(&mut vm, &buf[..buf.len() - 1]).is_err());
+    assert!(deserialize(&mut vm, b"RBC\0\xff\0\0\0").is_err());
+}
@@ -1,3 +1,4 @@
+use atom::Atom;
 use vm::{value::Value, vm::ConstantTable};
 
 pub type ByteCode = Vec<u8>;
@@ -6,7 +7,7 @@ pub type ByteCode = Vec<u8>;
 pub mod VMInst {
     pub const END: u8 = 0x00;
     pub const CREATE_CONTEXT: u8 = 0x01;
-    pub const CONSTRUCT: u8 = 0x02;
+    pub const CONSTRUCT: u8 = 0x02; // argc. same operands as CALL, whose 'this' is not used
     pub const CREATE_OBJECT: u8 = 0x03;
     pub const CREATE_ARRAY: u8 = 0x04;
     pub const PUSH_INT8: u8 = 0x05;
@@ -43,7 +44,7 @@ pub mod VMInst {
     pub const SET_MEMBER: u8 = 0x24;
     pub const JMP_IF_FALSE: u8 = 0x25;
     pub const JMP: u8 = 0x26;
-    pub const CALL: u8 = 0x27;
+    pub const CALL: u8 = 0x27; // argc. pops the callee, 'this' and the arguments
     pub const RETURN: u8 = 0x28;
     pub const DOUBLE: u8 = 0x29;
     pub const POP: u8 = 0x2a;
@@ -63,21 +64,276 @@ pub mod VMInst {
     pub const RETURN_TRY: u8 = 0x38;
     pub const PUSH_SCOPE: u8 = 0x39;
     pub const POP_SCOPE: u8 = 0x3a;
-
+    pub const SET_GETTER: u8 = 0x3b; // pops the getter and the name, and defines it on the object
+    pub const SET_SETTER: u8 = 0x3c; // pops the setter and the name, and defines it on the object
+    pub const EXP: u8 = 0x3d;
+    // Register-addressed instructions. Operands named r are registers of the current frame.
+    pub const PUSH_REG: u8 = 0x3e; // r
+    pub const POP_REG: u8 = 0x3f; // r
+    pub const LOAD_CONST_REG: u8 = 0x40; // r, const id
+    pub const GET_VALUE_REG: u8 = 0x41; // r, name id
+    pub const ADD_REG: u8 = 0x42; // r_dst, r_lhs, r_rhs
+    pub const SUB_REG: u8 = 0x43;
+    pub const MUL_REG: u8 = 0x44;
+    pub const DIV_REG: u8 = 0x45;
+    pub const REM_REG: u8 = 0x46;
+    pub const LT_REG: u8 = 0x47;
+    pub const GT_REG: u8 = 0x48;
+    pub const LE_REG: u8 = 0x49;
+    pub const GE_REG: u8 = 0x4a;
+    // Same as SET_VALUE, but throws a ReferenceError if the variable is not declared.
+    pub const SET_VALUE_STRICT: u8 = 0x4b; // name id
+    pub const PUSH_WITH_SCOPE: u8 = 0x4c; // pops an object and pushes a scope of its properties
+    pub const CALL_EVAL: u8 = 0x4d; // argc. same as CALL, but eval runs in the current scope
+    pub const TAIL_CALL: u8 = 0x4e; // argc. same as CALL followed by RETURN
+    pub const DOUBLE2: u8 = 0x4f; // duplicates the top two values
+    pub const ROTATE: u8 = 0x50; // n. moves the top value below the n values under it
+    pub const CREATE_CLOSURE: u8 = 0x51; // replaces the function on the top with its closure
+    pub const GET_UPVALUE: u8 = 0x52; // upvalue id
+    pub const SET_UPVALUE: u8 = 0x53; // upvalue id. pops the value
+    pub const GET_GLOBAL: u8 = 0x54; // name id, cache id. same as GET_VALUE in the global scope
+    pub const BNOT: u8 = 0x55; // ~ of the top value
+    pub const COPY_DATA_PROPERTIES: u8 = 0x56; // pops the source, and copies it to the object
+    pub const DEFINE_PROPERTY: u8 = 0x57; // pops the value and the name, and defines it on the object
+
+    /// the size of an instruction including its operands, or None if 'inst' is unknown.
     pub fn get_inst_size(inst: u8) -> Option<usize> {
-        match inst {
-            CREATE_CONTEXT | THROW | LEAVE_TRY | CATCH | FINALLY | POP_SCOPE | PUSH_SCOPE => {
-                Some(1)
+        super::Inst::operands_size(inst).map(|size| 1 + size)
+    }
+}
+
+/// A type of the operands of instructions. They are stored in little endian.
+trait Operand: Sized {
+    const SIZE: usize;
+    fn read(code: &[u8]) -> Self;
+    fn write(self, iseq: &mut ByteCode);
+}
+
+impl Operand for u8 {
+    const SIZE: usize = 1;
+    fn read(code: &[u8]) -> u8 {
+        code[0]
+    }
+    fn write(self, iseq: &mut ByteCode) {
+        iseq.push(self);
+    }
+}
+
+impl Operand for i8 {
+    const SIZE: usize = 1;
+    fn read(code: &[u8]) -> i8 {
+        code[0] as i8
+    }
+    fn write(self, iseq: &mut ByteCode) {
+        iseq.push(self as u8);
+    }
+}
+
+impl Operand for i32 {
+    const SIZE: usize = 4;
+    fn read(code: &[u8]) -> i32 {
+        slice_to_int32(code)
+    }
+    fn write(self, iseq: &mut ByteCode) {
+        for i in 0..4 {
+            iseq.push((self >> (i * 8)) as u8);
+        }
+    }
+}
+
+// Defines Inst from the list of instructions, 'Name { operand: type, ... } = OPCODE,'. The
+// operands are encoded in the order they are listed, after the opcode.
+macro_rules! instructions {
+    ($($name:ident $({ $($operand:ident: $ty:ident),* })* = $opcode:ident,)*) => {
+        /// A decoded instruction.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Inst {
+            $($name $({ $($operand: $ty),* })*,)*
+        }
+
+        impl Inst {
+            pub fn opcode(&self) -> u8 {
+                match *self {
+                    $(Inst::$name $({ $($operand: _),* })* => VMInst::$opcode,)*
+                }
+            }
+
+            /// the total size of the operands of 'opcode', or None if it is unknown.
+            fn operands_size(opcode: u8) -> Option<usize> {
+                match opcode {
+                    $(VMInst::$opcode => Some(0 $($(+ <$ty as Operand>::SIZE)*)*),)*
+                    _ => None,
+                }
+            }
+
+            /// Decode the instruction at the beginning of 'code'. Returns None if the opcode
+            /// is unknown or 'code' ends in the operands.
+            pub fn decode(code: &[u8]) -> Option<Inst> {
+                let size = VMInst::get_inst_size(*code.get(0)?)?;
+                if code.len() < size {
+                    return None;
+                }
+                let mut pc = 1;
+                let mut operand = |size: usize| {
+                    pc += size;
+                    &code[pc - size..]
+                };
+                Some(match code[0] {
+                    $(VMInst::$opcode => Inst::$name $({ $(
+                        $operand: <$ty as Operand>::read(operand(<$ty as Operand>::SIZE))
+                    ),* })*,)*
+                    _ => unreachable!(),
+                })
+            }
+
+            /// Append the encoded instruction to 'iseq'.
+            pub fn encode(&self, iseq: &mut ByteCode) {
+                iseq.push(self.opcode());
+                match *self {
+                    $(Inst::$name $({ $($operand),* })* => { $($($operand.write(iseq);)*)* })*
+                }
+            }
+        }
+    };
+}
+
+// The operands of every opcode in VMInst. get_inst_size(), the decoder and the encoder come
+// from this list, and the match on Inst in inst_to_string() fails to compile until a new
+// instruction is added there.
+//
+// Jumps are relative: 'offset' of JMP and JMP_IF_FALSE is from the next instruction, and the
+// others are from the instruction itself.
+instructions! {
+    End = END,
+    CreateContext = CREATE_CONTEXT,
+    Construct { argc: i32 } = CONSTRUCT,
+    CreateObject { len: i32 } = CREATE_OBJECT,
+    CreateArray { len: i32 } = CREATE_ARRAY,
+    PushInt8 { n: i8 } = PUSH_INT8,
+    PushInt32 { n: i32 } = PUSH_INT32,
+    PushFalse = PUSH_FALSE,
+    PushTrue = PUSH_TRUE,
+    PushConst { id: i32 } = PUSH_CONST,
+    PushThis = PUSH_THIS,
+    PushArguments = PUSH_ARGUMENTS,
+    PushUndefined = PUSH_UNDEFINED,
+    LNot = LNOT,
+    Posi = POSI,
+    Neg = NEG,
+    Add = ADD,
+    Sub = SUB,
+    Mul = MUL,
+    Div = DIV,
+    Rem = REM,
+    Lt = LT,
+    Gt = GT,
+    Le = LE,
+    Ge = GE,
+    Eq = EQ,
+    Ne = NE,
+    SEq = SEQ,
+    SNe = SNE,
+    And = AND,
+    Or = OR,
+    Xor = XOR,
+    Shl = SHL,
+    Shr = SHR,
+    ZFShr = ZFSHR,
+    GetMember { cache: i32 } = GET_MEMBER,
+    SetMember { cache: i32 } = SET_MEMBER,
+    JmpIfFalse { offset: i32 } = JMP_IF_FALSE,
+    Jmp { offset: i32 } = JMP,
+    Call { argc: i32 } = CALL,
+    Return = RETURN,
+    Double = DOUBLE,
+    Pop = POP,
+    LAnd = LAND,
+    LOr = LOR,
+    UpdateParentScope = UPDATE_PARENT_SCOPE,
+    GetValue { name: i32 } = GET_VALUE,
+    SetValue { name: i32 } = SET_VALUE,
+    DeclVar { name: i32 } = DECL_VAR,
+    CondOp = COND_OP,
+    LoopStart { end: i32 } = LOOP_START,
+    Throw = THROW,
+    EnterTry { catch: i32, finally: i32 } = ENTER_TRY,
+    LeaveTry = LEAVE_TRY,
+    Catch = CATCH,
+    Finally = FINALLY,
+    ReturnTry { finally: i32 } = RETURN_TRY,
+    PushScope = PUSH_SCOPE,
+    PopScope = POP_SCOPE,
+    SetGetter = SET_GETTER,
+    SetSetter = SET_SETTER,
+    Exp = EXP,
+    PushReg { r: u8 } = PUSH_REG,
+    PopReg { r: u8 } = POP_REG,
+    LoadConstReg { r: u8, id: i32 } = LOAD_CONST_REG,
+    GetValueReg { r: u8, name: i32 } = GET_VALUE_REG,
+    AddReg { dst: u8, lhs: u8, rhs: u8 } = ADD_REG,
+    SubReg { dst: u8, lhs: u8, rhs: u8 } = SUB_REG,
+    MulReg { dst: u8, lhs: u8, rhs: u8 } = MUL_REG,
+    DivReg { dst: u8, lhs: u8, rhs: u8 } = DIV_REG,
+    RemReg { dst: u8, lhs: u8, rhs: u8 } = REM_REG,
+    LtReg { dst: u8, lhs: u8, rhs: u8 } = LT_REG,
+    GtReg { dst: u8, lhs: u8, rhs: u8 } = GT_REG,
+    LeReg { dst: u8, lhs: u8, rhs: u8 } = LE_REG,
+    GeReg { dst: u8, lhs: u8, rhs: u8 } = GE_REG,
+    SetValueStrict { name: i32 } = SET_VALUE_STRICT,
+    PushWithScope = PUSH_WITH_SCOPE,
+    CallEval { argc: i32 } = CALL_EVAL,
+    TailCall { argc: i32 } = TAIL_CALL,
+    Double2 = DOUBLE2,
+    Rotate { n: u8 } = ROTATE,
+    CreateClosure = CREATE_CLOSURE,
+    GetUpvalue { id: i32 } = GET_UPVALUE,
+    SetUpvalue { id: i32 } = SET_UPVALUE,
+    GetGlobal { name: i32, cache: i32 } = GET_GLOBAL,
+    BNot = BNOT,
+    CopyDataProperties = COPY_DATA_PROPERTIES,
+    DefineProperty = DEFINE_PROPERTY,
+}
+
+impl Inst {
+    pub fn size(&self) -> usize {
+        VMInst::get_inst_size(self.opcode()).unwrap()
+    }
+
+    /// Absolute destinations of the jump at 'pc'.
+    pub fn destinations(&self, pc: isize) -> Vec<isize> {
+        match *self {
+            Inst::Jmp { offset } | Inst::JmpIfFalse { offset } => {
+                vec![pc + self.size() as isize + offset as isize]
+            }
+            Inst::LoopStart { end: offset } | Inst::ReturnTry { finally: offset } => {
+                vec![pc + offset as isize]
+            }
+            Inst::EnterTry { catch, finally } => vec![pc + catch as isize, pc + finally as isize],
+            _ => vec![],
+        }
+    }
+
+    /// Make the jump at 'pc' go to 'dsts', given in the same order as destinations().
+    pub fn set_destinations(&mut self, pc: isize, dsts: &[isize]) {
+        let next = pc + self.size() as isize;
+        match *self {
+            Inst::Jmp { ref mut offset } | Inst::JmpIfFalse { ref mut offset } => {
+                *offset = (dsts[0] - next) as i32
             }
-            CONSTRUCT | CREATE_OBJECT | PUSH_CONST | PUSH_INT32 | CREATE_ARRAY | JMP_IF_FALSE
-            | RETURN_TRY | DECL_VAR | LOOP_START | JMP | SET_VALUE | GET_VALUE | CALL => Some(5),
-            PUSH_INT8 => Some(2),
-            PUSH_FALSE | END | PUSH_TRUE | PUSH_THIS | ADD | SUB | MUL | DIV | REM | LT
-            | PUSH_ARGUMENTS | NEG | POSI | GT | LE | GE | EQ | NE | GET_MEMBER | RETURN | SNE
-            | ZFSHR | POP | DOUBLE | AND | COND_OP | OR | SEQ | SET_MEMBER
-            | UPDATE_PARENT_SCOPE | PUSH_UNDEFINED | LAND | SHR | SHL | XOR | LOR => Some(1),
-            ENTER_TRY => Some(9),
-            _ => None,
+            Inst::LoopStart {
+                end: ref mut offset,
+            }
+            | Inst::ReturnTry {
+                finally: ref mut offset,
+            } => *offset = (dsts[0] - pc) as i32,
+            Inst::EnterTry {
+                ref mut catch,
+                ref mut finally,
+            } => {
+                *catch = (dsts[0] - pc) as i32;
+                *finally = (dsts[1] - pc) as i32;
+            }
+            _ => {}
         }
     }
 }
@@ -85,48 +341,46 @@ pub mod VMInst {
 #[derive(Debug, Clone)]
 pub struct ByteCodeGen {
     pub const_table: ConstantTable,
+    /// number of inline caches allocated to GET_MEMBER, SET_MEMBER and GET_GLOBAL.
+    pub inline_cache_count: usize,
 }
 
 impl ByteCodeGen {
     pub fn new() -> ByteCodeGen {
         ByteCodeGen {
             const_table: ConstantTable::new(),
+            inline_cache_count: 0,
         }
     }
 }
 
 impl ByteCodeGen {
     pub fn gen_end(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::END);
+        Inst::End.encode(iseq);
     }
 
     pub fn gen_create_context(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CREATE_CONTEXT);
+        Inst::CreateContext.encode(iseq);
     }
 
     pub fn gen_constract(&self, argc: usize, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CONSTRUCT);
-        self.gen_int32(argc as i32, iseq);
+        Inst::Construct { argc: argc as i32 }.encode(iseq);
     }
 
     pub fn gen_create_object(&self, len: usize, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CREATE_OBJECT);
-        self.gen_int32(len as i32, iseq);
+        Inst::CreateObject { len: len as i32 }.encode(iseq);
     }
 
     pub fn gen_create_array(&self, len: usize, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CREATE_ARRAY);
-        self.gen_int32(len as i32, iseq);
+        Inst::CreateArray { len: len as i32 }.encode(iseq);
     }
 
     pub fn gen_push_int8(&self, n: i8, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_INT8);
-        self.gen_int8(n, iseq);
+        Inst::PushInt8 { n }.encode(iseq);
     }
 
     pub fn gen_push_int32(&self, n: i32, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_INT32);
-        self.gen_int32(n, iseq);
+        Inst::PushInt32 { n }.encode(iseq);
     }
 
     pub fn gen_push_number(&mut self, n: f64, iseq: &mut ByteCode) {
@@ -146,30 +400,29 @@ impl ByteCodeGen {
     }
 
     pub fn gen_push_bool(&self, b: bool, iseq: &mut ByteCode) {
-        iseq.push(if b {
-            VMInst::PUSH_TRUE
+        if b {
+            Inst::PushTrue.encode(iseq)
         } else {
-            VMInst::PUSH_FALSE
-        })
+            Inst::PushFalse.encode(iseq)
+        }
     }
 
     pub fn gen_push_const(&mut self, val: Value, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_CONST);
         let id = self.const_table.value.len();
         self.const_table.value.push(val);
-        self.gen_int32(id as i32, iseq);
+        Inst::PushConst { id: id as i32 }.encode(iseq);
     }
 
     pub fn gen_push_this(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_THIS);
+        Inst::PushThis.encode(iseq);
     }
 
     pub fn gen_push_arguments(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_ARGUMENTS);
+        Inst::PushArguments.encode(iseq);
     }
 
     pub fn gen_push_undefined(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_UNDEFINED);
+        Inst::PushUndefined.encode(iseq);
     }
 
     // pub fn gen_push_null(&self, iseq: &mut ByteCode) {
@@ -177,195 +430,278 @@ impl ByteCodeGen {
     // }
 
     pub fn gen_lnot(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LNOT);
+        Inst::LNot.encode(iseq);
+    }
+
+    pub fn gen_bnot(&self, iseq: &mut ByteCode) {
+        Inst::BNot.encode(iseq);
     }
 
     pub fn gen_posi(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::POSI);
+        Inst::Posi.encode(iseq);
     }
 
     pub fn gen_neg(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::NEG);
+        Inst::Neg.encode(iseq);
     }
 
     pub fn gen_add(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::ADD);
+        Inst::Add.encode(iseq);
     }
     pub fn gen_sub(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SUB);
+        Inst::Sub.encode(iseq);
     }
     pub fn gen_mul(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::MUL);
+        Inst::Mul.encode(iseq);
     }
     pub fn gen_div(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::DIV);
+        Inst::Div.encode(iseq);
     }
     pub fn gen_rem(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::REM);
+        Inst::Rem.encode(iseq);
+    }
+    pub fn gen_exp(&self, iseq: &mut ByteCode) {
+        Inst::Exp.encode(iseq);
     }
     pub fn gen_lt(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LT);
+        Inst::Lt.encode(iseq);
     }
     pub fn gen_gt(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::GT);
+        Inst::Gt.encode(iseq);
     }
     pub fn gen_le(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LE);
+        Inst::Le.encode(iseq);
     }
     pub fn gen_ge(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::GE);
+        Inst::Ge.encode(iseq);
     }
     pub fn gen_eq(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::EQ);
+        Inst::Eq.encode(iseq);
     }
     pub fn gen_ne(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::NE);
+        Inst::Ne.encode(iseq);
     }
     pub fn gen_seq(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SEQ);
+        Inst::SEq.encode(iseq);
     }
     pub fn gen_sne(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SNE);
+        Inst::SNe.encode(iseq);
     }
     pub fn gen_and(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::AND);
+        Inst::And.encode(iseq);
     }
     pub fn gen_or(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::OR);
+        Inst::Or.encode(iseq);
     }
     pub fn gen_xor(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::XOR);
+        Inst::Xor.encode(iseq);
     }
     pub fn gen_shl(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SHL);
+        Inst::Shl.encode(iseq);
     }
     pub fn gen_shr(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SHR);
+        Inst::Shr.encode(iseq);
     }
     pub fn gen_zfshr(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::ZFSHR);
+        Inst::ZFShr.encode(iseq);
     }
 
     pub fn gen_land(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LAND);
+        Inst::LAnd.encode(iseq);
     }
     pub fn gen_lor(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LOR);
+        Inst::LOr.encode(iseq);
     }
 
     pub fn gen_double(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::DOUBLE);
+        Inst::Double.encode(iseq);
+    }
+    pub fn gen_double2(&self, iseq: &mut ByteCode) {
+        Inst::Double2.encode(iseq);
+    }
+    pub fn gen_rotate(&self, n: u8, iseq: &mut ByteCode) {
+        Inst::Rotate { n }.encode(iseq);
     }
     pub fn gen_pop(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::POP);
+        Inst::Pop.encode(iseq);
     }
 
-    pub fn gen_get_member(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::GET_MEMBER);
+    pub fn gen_get_member(&mut self, iseq: &mut ByteCode) {
+        let cache = self.new_inline_cache();
+        Inst::GetMember {
+            cache: cache as i32,
+        }
+        .encode(iseq);
     }
 
-    pub fn gen_set_member(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::SET_MEMBER);
+    pub fn gen_set_member(&mut self, iseq: &mut ByteCode) {
+        let cache = self.new_inline_cache();
+        Inst::SetMember {
+            cache: cache as i32,
+        }
+        .encode(iseq);
+    }
+
+    pub fn gen_set_getter(&self, iseq: &mut ByteCode) {
+        Inst::SetGetter.encode(iseq);
+    }
+
+    pub fn gen_set_setter(&self, iseq: &mut ByteCode) {
+        Inst::SetSetter.encode(iseq);
+    }
+
+    pub fn gen_copy_data_properties(&self, iseq: &mut ByteCode) {
+        Inst::CopyDataProperties.encode(iseq);
+    }
+
+    pub fn gen_define_property(&self, iseq: &mut ByteCode) {
+        Inst::DefineProperty.encode(iseq);
     }
 
     pub fn gen_call(&self, argc: u32, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CALL);
-        self.gen_int32(argc as i32, iseq);
+        Inst::Call { argc: argc as i32 }.encode(iseq);
+    }
+
+    pub fn gen_call_eval(&self, argc: u32, iseq: &mut ByteCode) {
+        Inst::CallEval { argc: argc as i32 }.encode(iseq);
     }
 
     pub fn gen_jmp(&self, dst: i32, iseq: &mut ByteCode) {
-        iseq.push(VMInst::JMP);
-        self.gen_int32(dst, iseq);
+        Inst::Jmp { offset: dst }.encode(iseq);
     }
 
     pub fn gen_jmp_if_false(&self, dst: i32, iseq: &mut ByteCode) {
-        iseq.push(VMInst::JMP_IF_FALSE);
-        self.gen_int32(dst, iseq);
+        Inst::JmpIfFalse { offset: dst }.encode(iseq);
     }
 
     pub fn gen_return(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::RETURN);
+        Inst::Return.encode(iseq);
     }
 
     pub fn gen_return_try(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::RETURN_TRY);
-        self.gen_int32(0, iseq);
+        Inst::ReturnTry { finally: 0 }.encode(iseq);
     }
 
     pub fn gen_push_scope(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::PUSH_SCOPE);
+        Inst::PushScope.encode(iseq);
+    }
+
+    pub fn gen_push_with_scope(&self, iseq: &mut ByteCode) {
+        Inst::PushWithScope.encode(iseq);
     }
 
     pub fn gen_pop_scope(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::POP_SCOPE);
+        Inst::PopScope.encode(iseq);
     }
 
     pub fn gen_update_parent_scope(&self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::UPDATE_PARENT_SCOPE);
+        Inst::UpdateParentScope.encode(iseq);
+    }
+
+    pub fn gen_create_closure(&self, iseq: &mut ByteCode) {
+        Inst::CreateClosure.encode(iseq);
+    }
+
+    pub fn gen_get_upvalue(&self, n: usize, iseq: &mut ByteCode) {
+        Inst::GetUpvalue { id: n as i32 }.encode(iseq);
+    }
+
+    pub fn gen_set_upvalue(&self, n: usize, iseq: &mut ByteCode) {
+        Inst::SetUpvalue { id: n as i32 }.encode(iseq);
     }
 
     pub fn gen_get_value(&mut self, name: &String, iseq: &mut ByteCode) {
         let id = self.add_const_string(name);
-        iseq.push(VMInst::GET_VALUE);
-        self.gen_int32(id as i32, iseq);
+        Inst::GetValue { name: id as i32 }.encode(iseq);
+    }
+
+    pub fn gen_get_global(&mut self, name: &String, iseq: &mut ByteCode) {
+        let id = self.add_const_string(name);
+        let cache = self.new_inline_cache();
+        Inst::GetGlobal {
+            name: id as i32,
+            cache: cache as i32,
+        }
+        .encode(iseq);
     }
 
     pub fn gen_set_value(&mut self, name: &String, iseq: &mut ByteCode) {
         let id = self.add_const_string(name);
-        iseq.push(VMInst::SET_VALUE);
-        self.gen_int32(id as i32, iseq);
+        Inst::SetValue { name: id as i32 }.encode(iseq);
+    }
+
+    pub fn gen_set_value_strict(&mut self, name: &String, iseq: &mut ByteCode) {
+        let id = self.add_const_string(name);
+        Inst::SetValueStrict { name: id as i32 }.encode(iseq);
     }
 
     pub fn gen_decl_var(&mut self, name: &String, iseq: &mut ByteCode) {
         let id = self.add_const_string(name);
-        iseq.push(VMInst::DECL_VAR);
-        self.gen_int32(id as i32, iseq);
+        Inst::DeclVar { name: id as i32 }.encode(iseq);
     }
 
     pub fn gen_cond_op(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::COND_OP);
+        Inst::CondOp.encode(iseq);
     }
 
     pub fn gen_loop_start(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LOOP_START);
-        self.gen_int32(0, iseq);
+        Inst::LoopStart { end: 0 }.encode(iseq);
     }
 
     pub fn gen_throw(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::THROW);
+        Inst::Throw.encode(iseq);
     }
 
     pub fn gen_enter_try(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::ENTER_TRY);
-        self.gen_int32(0, iseq); // distance from ENTER_TRY to CATCH
-        self.gen_int32(0, iseq); // distance from ENTER_TRY to FINALLY
+        // The distances from ENTER_TRY to CATCH and FINALLY are set later.
+        Inst::EnterTry {
+            catch: 0,
+            finally: 0,
+        }
+        .encode(iseq);
     }
 
     pub fn gen_leave_try(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::LEAVE_TRY);
+        Inst::LeaveTry.encode(iseq);
     }
 
     pub fn gen_catch(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::CATCH);
+        Inst::Catch.encode(iseq);
     }
 
     pub fn gen_finally(&mut self, iseq: &mut ByteCode) {
-        iseq.push(VMInst::FINALLY);
+        Inst::Finally.encode(iseq);
     }
 
-    // Utils
+    pub fn gen_push_reg(&self, r: u8, iseq: &mut ByteCode) {
+        Inst::PushReg { r }.encode(iseq);
+    }
 
-    pub fn gen_int8(&self, n: i8, iseq: &mut ByteCode) {
-        iseq.push(n as u8);
+    pub fn gen_pop_reg(&self, r: u8, iseq: &mut ByteCode) {
+        Inst::PopReg { r }.encode(iseq);
     }
 
-    pub fn gen_int32(&self, n: i32, iseq: &mut ByteCode) {
-        iseq.push(((n >> 0) & 0xff as i32) as u8);
-        iseq.push(((n >> 8) & 0xff as i32) as u8);
-        iseq.push(((n >> 16) & 0xff as i32) as u8);
-        iseq.push(((n >> 24) & 0xff as i32) as u8);
+    pub fn gen_load_const_reg(&mut self, r: u8, val: Value, iseq: &mut ByteCode) {
+        let id = self.const_table.value.len();
+        self.const_table.value.push(val);
+        Inst::LoadConstReg { r, id: id as i32 }.encode(iseq);
+    }
+
+    pub fn gen_get_value_reg(&mut self, r: u8, name: &String, iseq: &mut ByteCode) {
+        let id = self.add_const_string(name);
+        Inst::GetValueReg { r, name: id as i32 }.encode(iseq);
     }
 
+    /// 'op' is one of ADD_REG, SUB_REG, ..., GE_REG.
+    pub fn gen_binary_op_reg(&self, op: u8, dst: u8, lhs: u8, rhs: u8, iseq: &mut ByteCode) {
+        iseq.push(op);
+        iseq.push(dst);
+        iseq.push(lhs);
+        iseq.push(rhs);
+    }
+
+    // Utils
+
     pub fn replace_int32(&self, n: i32, iseq: &mut [u8]) {
         iseq[3] = (n >> 24) as u8;
         iseq[2] = (n >> 16) as u8;
@@ -373,15 +709,21 @@ impl ByteCodeGen {
         iseq[0] = (n >> 0) as u8;
     }
 
+    fn new_inline_cache(&mut self) -> usize {
+        self.inline_cache_count += 1;
+        self.inline_cache_count - 1
+    }
+
     fn add_const_string(&mut self, name: &String) -> usize {
+        let atom = Atom::new(name);
         for (i, string) in self.const_table.string.iter().enumerate() {
-            if name == string {
+            if atom == *string {
                 return i;
             }
         }
 
         let id = self.const_table.string.len();
-        self.const_table.string.push(name.clone());
+        self.const_table.string.push(atom);
         id
     }
 }
@@ -390,13 +732,6 @@ pub fn slice_to_int32(iseq: &[u8]) -> i32 {
     ((iseq[3] as i32) << 24) + ((iseq[2] as i32) << 16) + ((iseq[1] as i32) << 8) + (iseq[0] as i32)
 }
 
-pub fn read_int32(iseq: &ByteCode, pc: usize) -> i32 {
-    (((iseq[pc as usize + 3] as u32) << 24)
-        + ((iseq[pc as usize + 2] as u32) << 16)
-        + ((iseq[pc as usize + 1] as u32) << 8)
-        + (iseq[pc as usize + 0] as u32)) as i32
-}
-
 pub fn show(code: &ByteCode, const_table: &ConstantTable) {
     let mut i = 0;
     while i < code.len() {
@@ -411,201 +746,157 @@ pub fn show(code: &ByteCode, const_table: &ConstantTable) {
 }
 
 pub fn show_inst(code: &ByteCode, i: usize, const_table: &ConstantTable) {
-    print!("{:04x} ", i);
-    match code[i] {
-        VMInst::END => {
-            print!("End");
-        }
-        VMInst::CREATE_CONTEXT => {
-            print!("CreateContext");
-        }
-        VMInst::CONSTRUCT => {
-            let int32 = read_int32(code, i + 1);
-            print!("Construct {} params", int32);
-        }
-        VMInst::CREATE_OBJECT => {
-            let int32 = read_int32(code, i + 1);
-            print!("CreateObject {} params", int32);
-        }
-        VMInst::CREATE_ARRAY => {
-            let int32 = read_int32(code, i + 1);
-            print!("CreateArray {} params", int32);
-        }
-        VMInst::PUSH_INT8 => {
-            let int8 = code[i + 1] as i32;
-            print!("PushInt8 {}", int8);
-        }
-        VMInst::PUSH_INT32 => {
-            let int32 = read_int32(code, i + 1);
-            print!("PushInt32 {}", int32);
-        }
-        VMInst::PUSH_FALSE => {
-            print!("PushFalse");
-        }
-        VMInst::PUSH_TRUE => {
-            print!("PushTrue");
-        }
-        VMInst::PUSH_CONST => {
-            let int32 = read_int32(code, i + 1);
-            let value = &const_table.value[int32 as usize];
-            print!("PushConst {}", value.format(1, false));
-        }
-        VMInst::PUSH_THIS => {
-            print!("PushThis");
-        }
-        VMInst::PUSH_ARGUMENTS => {
-            print!("PushArguments");
-        }
-        VMInst::PUSH_UNDEFINED => {
-            print!("PushUndefined");
-        }
-        VMInst::LNOT => {
-            print!("LogNot");
-        }
-        VMInst::POSI => {
-            print!("Posi");
-        }
-        VMInst::NEG => {
-            print!("Neg");
-        }
-        VMInst::ADD => {
-            print!("Add");
-        }
-        VMInst::SUB => {
-            print!("Sub");
-        }
-        VMInst::MUL => {
-            print!("Mul");
-        }
-        VMInst::DIV => {
-            print!("Div");
-        }
-        VMInst::REM => {
-            print!("Rem");
-        }
-        VMInst::LT => {
-            print!("Lt");
-        }
-        VMInst::GT => {
-            print!("Gt");
-        }
-        VMInst::LE => {
-            print!("Le");
-        }
-        VMInst::GE => {
-            print!("Ge");
-        }
-        VMInst::EQ => {
-            print!("Eq");
-        }
-        VMInst::NE => {
-            print!("Ne");
-        }
-        VMInst::SEQ => {
-            print!("SEq");
-        }
-        VMInst::SNE => {
-            print!("SNeg");
-        }
-        VMInst::AND => {
-            print!("And");
-        }
-        VMInst::OR => {
-            print!("Or");
-        }
-        VMInst::XOR => {
-            print!("Xor");
-        }
-        VMInst::SHL => {
-            print!("Shift-L");
-        }
-        VMInst::SHR => {
-            print!("Shift-R");
-        }
-        VMInst::ZFSHR => {
-            print!("ZeroFill-Shift-R");
-        }
-        VMInst::GET_MEMBER => {
-            print!("GetMember");
-        }
-        VMInst::SET_MEMBER => {
-            print!("SetMember");
-        }
-        VMInst::JMP_IF_FALSE => {
-            let int32 = read_int32(code, i + 1);
-            print!("JmpIfFalse {:04x}", i as i32 + int32 + 5);
-        }
-        VMInst::JMP => {
-            let int32 = read_int32(code, i + 1);
-            print!("Jmp {:04x}", i as i32 + int32 + 5);
-        }
-        VMInst::CALL => {
-            let int32 = read_int32(code, i + 1);
-            print!("Call {} params", int32);
-        }
-        VMInst::RETURN => {
-            print!("Return");
-        }
-        VMInst::DOUBLE => {
-            print!("Double");
-        }
-        VMInst::POP => {
-            print!("Pop");
-        }
-        VMInst::LAND => {
-            print!("LogAnd");
-        }
-        VMInst::LOR => {
-            print!("LogOr");
-        }
-        VMInst::UPDATE_PARENT_SCOPE => {
-            print!("UpdateParentScope");
-        }
-        VMInst::GET_VALUE => {
-            let int32 = read_int32(code, i + 1);
-            let name = &const_table.string[int32 as usize];
-            print!("GetValue '{}'", name);
-        }
-        VMInst::SET_VALUE => {
-            let int32 = read_int32(code, i + 1);
-            let name = &const_table.string[int32 as usize];
-            print!("SetValue '{}'", name);
-        }
-        VMInst::DECL_VAR => {
-            let int32 = read_int32(code, i + 1);
-            let name = &const_table.string[int32 as usize];
-            print!("DeclVar '{}'", name);
-        }
-        VMInst::COND_OP => {
-            print!("CondOp");
-        }
-        VMInst::LOOP_START => {
-            print!("LoopStart");
-        }
-        VMInst::THROW => {
-            print!("Throw");
-        }
-        VMInst::ENTER_TRY => {
-            print!("EnterTry");
-        }
-        VMInst::LEAVE_TRY => {
-            print!("LeaveTry");
-        }
-        VMInst::CATCH => {
-            print!("Catch");
-        }
-        VMInst::FINALLY => {
-            print!("Finally");
-        }
-        VMInst::RETURN_TRY => {
-            print!("ReturnTry");
-        }
-        VMInst::PUSH_SCOPE => {
-            print!("PushScope");
-        }
-        VMInst::POP_SCOPE => {
-            print!("PopScope");
-        }
-        _ => unreachable!("sorry. need to implement more opcodes"),
+    print!("{:04x} {}", i, inst_to_string(code, i, const_table));
+}
+
+/// the mnemonic and the operands of the instruction at 'i'.
+pub fn inst_to_string(code: &ByteCode, i: usize, const_table: &ConstantTable) -> String {
+    let inst = match Inst::decode(&code[i..]) {
+        Some(inst) => inst,
+        None => unreachable!("sorry. need to implement more opcodes"),
+    };
+    let value = |id: i32| const_table.value[id as usize].format(1, false);
+    let name = |id: i32| &const_table.string[id as usize];
+    let dsts = inst.destinations(i as isize);
+    match inst {
+        Inst::End => "End".to_string(),
+        Inst::CreateContext => "CreateContext".to_string(),
+        Inst::Construct { argc } => format!("Construct {} params", argc),
+        Inst::CreateObject { len } => format!("CreateObject {} params", len),
+        Inst::CreateArray { len } => format!("CreateArray {} params", len),
+        Inst::PushInt8 { n } => format!("PushInt8 {}", n),
+        Inst::PushInt32 { n } => format!("PushInt32 {}", n),
+        Inst::PushFalse => "PushFalse".to_string(),
+        Inst::PushTrue => "PushTrue".to_string(),
+        Inst::PushConst { id } => format!("PushConst {}", value(id)),
+        Inst::PushThis => "PushThis".to_string(),
+        Inst::PushArguments => "PushArguments".to_string(),
+        Inst::PushUndefined => "PushUndefined".to_string(),
+        Inst::LNot => "LogNot".to_string(),
+        Inst::BNot => "BitNot".to_string(),
+        Inst::Posi => "Posi".to_string(),
+        Inst::Neg => "Neg".to_string(),
+        Inst::Add => "Add".to_string(),
+        Inst::Sub => "Sub".to_string(),
+        Inst::Mul => "Mul".to_string(),
+        Inst::Div => "Div".to_string(),
+        Inst::Rem => "Rem".to_string(),
+        Inst::Exp => "Exp".to_string(),
+        Inst::Lt => "Lt".to_string(),
+        Inst::Gt => "Gt".to_string(),
+        Inst::Le => "Le".to_string(),
+        Inst::Ge => "Ge".to_string(),
+        Inst::Eq => "Eq".to_string(),
+        Inst::Ne => "Ne".to_string(),
+        Inst::SEq => "SEq".to_string(),
+        Inst::SNe => "SNeg".to_string(),
+        Inst::And => "And".to_string(),
+        Inst::Or => "Or".to_string(),
+        Inst::Xor => "Xor".to_string(),
+        Inst::Shl => "Shift-L".to_string(),
+        Inst::Shr => "Shift-R".to_string(),
+        Inst::ZFShr => "ZeroFill-Shift-R".to_string(),
+        Inst::GetMember { cache } => format!("GetMember [cache:{}]", cache),
+        Inst::SetMember { cache } => format!("SetMember [cache:{}]", cache),
+        Inst::JmpIfFalse { .. } => format!("JmpIfFalse {:04x}", dsts[0]),
+        Inst::Jmp { .. } => format!("Jmp {:04x}", dsts[0]),
+        Inst::Call { argc } => format!("Call {} params", argc),
+        Inst::CallEval { argc } => format!("CallEval {} params", argc),
+        Inst::TailCall { argc } => format!("TailCall {} params", argc),
+        Inst::Return => "Return".to_string(),
+        Inst::Double => "Double".to_string(),
+        Inst::Double2 => "Double2".to_string(),
+        Inst::Rotate { n } => format!("Rotate {}", n),
+        Inst::Pop => "Pop".to_string(),
+        Inst::LAnd => "LogAnd".to_string(),
+        Inst::LOr => "LogOr".to_string(),
+        Inst::UpdateParentScope => "UpdateParentScope".to_string(),
+        Inst::CreateClosure => "CreateClosure".to_string(),
+        Inst::GetUpvalue { id } => format!("GetUpvalue {}", id),
+        Inst::SetUpvalue { id } => format!("SetUpvalue {}", id),
+        Inst::GetValue { name: id } => format!("GetValue '{}'", name(id)),
+        Inst::GetGlobal { name: id, cache } => {
+            format!("GetGlobal '{}' [cache:{}]", name(id), cache)
+        }
+        Inst::SetValue { name: id } => format!("SetValue '{}'", name(id)),
+        Inst::SetValueStrict { name: id } => format!("SetValueStrict '{}'", name(id)),
+        Inst::DeclVar { name: id } => format!("DeclVar '{}'", name(id)),
+        Inst::CondOp => "CondOp".to_string(),
+        Inst::LoopStart { .. } => "LoopStart".to_string(),
+        Inst::Throw => "Throw".to_string(),
+        Inst::EnterTry { .. } => "EnterTry".to_string(),
+        Inst::LeaveTry => "LeaveTry".to_string(),
+        Inst::Catch => "Catch".to_string(),
+        Inst::Finally => "Finally".to_string(),
+        Inst::ReturnTry { .. } => "ReturnTry".to_string(),
+        Inst::PushScope => "PushScope".to_string(),
+        Inst::PushWithScope => "PushWithScope".to_string(),
+        Inst::PopScope => "PopScope".to_string(),
+        Inst::SetGetter => "SetGetter".to_string(),
+        Inst::SetSetter => "SetSetter".to_string(),
+        Inst::CopyDataProperties => "CopyDataProperties".to_string(),
+        Inst::DefineProperty => "DefineProperty".to_string(),
+        Inst::PushReg { r } => format!("PushReg r{}", r),
+        Inst::PopReg { r } => format!("PopReg r{}", r),
+        Inst::LoadConstReg { r, id } => format!("LoadConstReg r{} {}", r, value(id)),
+        Inst::GetValueReg { r, name: id } => format!("GetValueReg r{} '{}'", r, name(id)),
+        Inst::AddReg { dst, lhs, rhs } => format!("AddReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::SubReg { dst, lhs, rhs } => format!("SubReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::MulReg { dst, lhs, rhs } => format!("MulReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::DivReg { dst, lhs, rhs } => format!("DivReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::RemReg { dst, lhs, rhs } => format!("RemReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::LtReg { dst, lhs, rhs } => format!("LtReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::GtReg { dst, lhs, rhs } => format!("GtReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::LeReg { dst, lhs, rhs } => format!("LeReg r{} r{} r{}", dst, lhs, rhs),
+        Inst::GeReg { dst, lhs, rhs } => format!("GeReg r{} r{} r{}", dst, lhs, rhs),
     }
 }
+
+#[test]
+fn test_inst() {
+    // All the opcodes from END to DEFINE_PROPERTY are defined.
+    for opcode in VMInst::END..VMInst::DEFINE_PROPERTY + 1 {
+        assert!(VMInst::get_inst_size(opcode).is_some());
+    }
+    assert_eq!(VMInst::get_inst_size(VMInst::DEFINE_PROPERTY + 1), None);
+
+    let insts = vec![
+        Inst::End,
+        Inst::PushInt8 { n: -1 },
+        Inst::PushInt32 { n: -100000 },
+        Inst::EnterTry {
+            catch: 9,
+            finally: 0x12345678,
+        },
+        Inst::GetGlobal { name: 3, cache: 7 },
+        Inst::LoadConstReg { r: 2, id: 256 },
+        Inst::AddReg {
+            dst: 0,
+            lhs: 1,
+            rhs: 255,
+        },
+    ];
+    let mut iseq = vec![];
+    for inst in &insts {
+        let pc = iseq.len();
+        inst.encode(&mut iseq);
+        assert_eq!(iseq.len() - pc, inst.size());
+        assert_eq!(iseq[pc], inst.opcode());
+    }
+    assert_eq!(iseq.len(), 1 + 2 + 5 + 9 + 9 + 6 + 4);
+
+    let mut pc = 0;
+    for inst in &insts {
+        assert_eq!(Inst::decode(&iseq[pc..]).as_ref(), Some(inst));
+        pc += inst.size();
+    }
+    // Cut off in the operands.
+    assert_eq!(Inst::decode(&iseq[8..12]), None);
+    assert_eq!(Inst::decode(&[0xff]), None);
+    assert_eq!(Inst::decode(&[]), None);
+
+    let mut jmp = Inst::Jmp { offset: 0 };
+    jmp.set_destinations(10, &[3]);
+    assert_eq!(jmp, Inst::Jmp { offset: -12 });
+    assert_eq!(jmp.destinations(10), vec![3]);
+}
@@ -0,0 +1,843 @@
+use builtins;
+use num_bigint::BigInt;
+use parser;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use vm::error::RuntimeError;
+use vm::value::*;
+use vm::vm::{VMOptions, VM};
+use vm_codegen;
+
+/// A function implemented in Rust and called from scripts.
+pub type NativeFunction = Box<dyn Fn(&[JsValue]) -> Result<JsValue, JsError>>;
+
+/// Name of the variable which holds the index of a native function in vm.native_functions.
+/// It is set in the call object of the builtin function wrapping the native function, which
+/// builtins do not use for variables.
+const NATIVE_FUNCTION_ID: &str = "native function id";
+
+/// A JavaScript interpreter embedded in a Rust program. Scripts evaluated by an engine share
+/// the global scope.
+pub struct Engine {
+    vm: VM,
+}
+
+/// A handle to stop the scripts run by an Engine from another thread. See
+/// Engine::terminate_handle().
+#[derive(Clone, Debug)]
+pub struct TerminateHandle {
+    flag: Arc<AtomicBool>,
+}
+
+/// An object to be made by Engine::register_object(), which can have native functions.
+pub struct NativeObject {
+    props: Vec<(String, NativeProperty)>,
+}
+
+enum NativeProperty {
+    Value(JsValue),
+    Function(NativeFunction),
+    Object(NativeObject),
+}
+
+/// A JavaScript value copied out of (or to be copied into) an Engine.
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsValue {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    BigInt(BigInt),
+    String(String),
+    Array(Vec<JsValue>),
+    /// own enumerable properties of an object.
+    Object(Vec<(String, JsValue)>),
+    /// A function. Functions cannot be taken out of the engine; call them by name with
+    /// Engine::call_function().
+    Function,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsError {
+    /// the source could not be parsed or compiled.
+    Syntax { line: usize, msg: String },
+    /// an exception was thrown and not caught.
+    Exception(JsValue),
+    /// other runtime errors. e.g. a reference to an undefined variable.
+    Runtime(String),
+    /// the script was stopped by TerminateHandle::terminate().
+    Terminated,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::with_options(VMOptions::default())
+    }
+
+    /// Make an engine whose scripts are limited by 'options'. A script exceeding a limit gets
+    /// a RangeError, which comes out as JsError::Exception unless the script catches it.
+    pub fn with_options(options: VMOptions) -> Engine {
+        let mut vm = VM::new();
+        // JIT-compiled code writes to stdout directly and is not for embedders.
+        vm.jit_on = false;
+        vm.options = options;
+        Engine { vm: vm }
+    }
+
+    /// a handle whose terminate() stops the script running in this engine, e.g. an infinite
+    /// loop. The script cannot catch it, and Engine::eval() returns JsError::Terminated.
+    pub fn terminate_handle(&self) -> TerminateHandle {
+        TerminateHandle {
+            flag: self.vm.terminate_flag.clone(),
+        }
+    }
+
+    /// the underlying VM, for what is not covered by Engine.
+    pub fn vm(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// Run 'source' as a script and return the value of its last expression statement.
+    /// Global variables and functions are kept for later calls.
+    pub fn eval(&mut self, source: &str) -> Result<JsValue, JsError> {
+        let mut parser = parser::Parser::new(source.to_string());
+        let node = match parser.parse_all() {
+            Ok(node) => node,
+            Err(err) => {
+                let (pos, msg) = parser.error_position_and_message(err);
+                return Err(syntax_error(&parser, pos, msg));
+            }
+        };
+
+        let mut iseq = vec![];
+        match self.vm.codegen.compile(&node, &mut iseq, true) {
+            Ok(()) => {}
+            Err(vm_codegen::Error::General { msg, token_pos })
+            | Err(vm_codegen::Error::Unimplemented { msg, token_pos }) => {
+                return Err(syntax_error(&parser, token_pos, msg))
+            }
+        }
+
+        let res = self.vm.run(iseq);
+        self.take_result(res)
+    }
+
+    /// Call the global function 'name' with 'args'.
+    pub fn call_function(&mut self, name: &str, args: &[JsValue]) -> Result<JsValue, JsError> {
+        let callee = self.global_value(name)?;
+        let args = args.iter().map(|arg| arg.to_value()).collect();
+        self.vm.instruction_count = 0;
+        let res = self.vm.call_function_simply(&callee, &args);
+        self.take_result(res)
+    }
+
+    /// Run the jobs (microtasks), e.g. promise reactions, until the queue is empty. eval() runs
+    /// them by itself, but call_function() leaves them to this. If a job throws, the error is
+    /// returned and the rest of the jobs are left in the queue.
+    pub fn run_jobs(&mut self) -> Result<(), JsError> {
+        self.vm.instruction_count = 0;
+        let res = self.vm.run_jobs();
+        self.vm.state.stack.clear();
+        Ok(res?)
+    }
+
+    /// Make Math.random() return the same sequence for the same 'seed', e.g. for tests whose
+    /// output must be reproducible. Each engine has its own state, so other engines are not
+    /// affected.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.vm.random_state = builtins::math::random_state(seed);
+    }
+
+    /// Define (or overwrite) the global variable 'name'.
+    pub fn set_global<T: Into<JsValue>>(&mut self, name: &str, val: T) {
+        let val = val.into().to_value();
+        self.vm
+            .codegen
+            .global_varmap
+            .set_value(name.to_string(), val);
+    }
+
+    /// value of the global variable 'name'.
+    pub fn get_global(&mut self, name: &str) -> Result<JsValue, JsError> {
+        let val = self.global_value(name)?;
+        Ok(JsValue::from_value(&val))
+    }
+
+    /// Define the global function 'name' implemented by 'f'.
+    pub fn register_fn<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[JsValue]) -> Result<JsValue, JsError> + 'static,
+    {
+        let func = self.native_function(Box::new(f));
+        self.vm
+            .codegen
+            .global_varmap
+            .set_value(name.to_string(), func);
+    }
+
+    /// Define the global variable 'name' as the object made from 'obj'.
+    pub fn register_object(&mut self, name: &str, obj: NativeObject) {
+        let obj = self.native_object(obj);
+        self.vm
+            .codegen
+            .global_varmap
+            .set_value(name.to_string(), obj);
+    }
+
+    fn native_function(&mut self, f: NativeFunction) -> Value {
+        let mut func = Value::default_builtin_function(call_native_function);
+        if let Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut callobj))) = func {
+            callobj.set_value(
+                NATIVE_FUNCTION_ID.to_string(),
+                Value::Number(self.vm.native_functions.len() as f64),
+            );
+        }
+        self.vm.native_functions.push(f);
+        func
+    }
+
+    fn native_object(&mut self, obj: NativeObject) -> Value {
+        let mut npp = vec![];
+        for (name, prop) in obj.props {
+            let val = match prop {
+                NativeProperty::Value(val) => val.to_value(),
+                NativeProperty::Function(f) => self.native_function(f),
+                NativeProperty::Object(obj) => self.native_object(obj),
+            };
+            npp.push((name, Property::new(val)));
+        }
+        Value::object_from_npp(&npp)
+    }
+
+    fn global_value(&self, name: &str) -> Result<Value, JsError> {
+        self.vm
+            .codegen
+            .global_varmap
+            .get_value(name)
+            .map_err(JsError::from)
+    }
+
+    /// Pop the value left on the stack by vm.run() or a function call.
+    fn take_result(&mut self, res: Result<bool, RuntimeError>) -> Result<JsValue, JsError> {
+        let val = self.vm.state.stack.pop().unwrap_or(Value::Undefined);
+        self.vm.state.stack.clear();
+        res?;
+        Ok(JsValue::from_value(&val))
+    }
+}
+
+fn call_native_function(
+    vm: &mut VM,
+    args: &Vec<Value>,
+    callobj: CallObjectRef,
+) -> Result<(), RuntimeError> {
+    let id = match callobj.get_value(NATIVE_FUNCTION_ID)? {
+        Value::Number(id) => id as usize,
+        _ => unreachable!(),
+    };
+    let args: Vec<JsValue> = args.iter().map(JsValue::from_value).collect();
+    let ret = (vm.native_functions[id])(&args)?;
+    vm.set_return_value(ret.to_value());
+    Ok(())
+}
+
+impl TerminateHandle {
+    /// Stop the script running in the engine. If no script is running, the next one is stopped
+    /// soon after it starts.
+    pub fn terminate(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+}
+
+impl NativeObject {
+    pub fn new() -> NativeObject {
+        NativeObject { props: vec![] }
+    }
+
+    /// Set the property 'name' to 'val'.
+    pub fn set<T: Into<JsValue>>(&mut self, name: &str, val: T) -> &mut NativeObject {
+        self.props
+            .push((name.to_string(), NativeProperty::Value(val.into())));
+        self
+    }
+
+    /// Set the property 'name' to the function implemented by 'f'.
+    pub fn register_fn<F>(&mut self, name: &str, f: F) -> &mut NativeObject
+    where
+        F: Fn(&[JsValue]) -> Result<JsValue, JsError> + 'static,
+    {
+        self.props
+            .push((name.to_string(), NativeProperty::Function(Box::new(f))));
+        self
+    }
+
+    /// Set the property 'name' to the object made from 'obj'.
+    pub fn register_object(&mut self, name: &str, obj: NativeObject) -> &mut NativeObject {
+        self.props
+            .push((name.to_string(), NativeProperty::Object(obj)));
+        self
+    }
+}
+
+/// Conversion of arguments of native functions. See arg().
+pub trait FromJsValue: Sized {
+    /// name of the type used in error messages.
+    const TYPE_NAME: &'static str;
+
+    fn from_js_value(val: &JsValue) -> Option<Self>;
+}
+
+/// Convert the 'n'th argument to T. A missing argument is undefined.
+pub fn arg<T: FromJsValue>(args: &[JsValue], n: usize) -> Result<T, JsError> {
+    let val = args.get(n).unwrap_or(&JsValue::Undefined);
+    T::from_js_value(val).ok_or_else(|| {
+        JsError::Runtime(format!(
+            "type error: argument {} must be {}",
+            n + 1,
+            T::TYPE_NAME
+        ))
+    })
+}
+
+impl FromJsValue for JsValue {
+    const TYPE_NAME: &'static str = "any value";
+
+    fn from_js_value(val: &JsValue) -> Option<JsValue> {
+        Some(val.clone())
+    }
+}
+
+impl FromJsValue for f64 {
+    const TYPE_NAME: &'static str = "a number";
+
+    fn from_js_value(val: &JsValue) -> Option<f64> {
+        val.as_f64()
+    }
+}
+
+impl FromJsValue for i64 {
+    const TYPE_NAME: &'static str = "an integer";
+
+    fn from_js_value(val: &JsValue) -> Option<i64> {
+        match val.as_f64() {
+            Some(n) if n.fract() == 0.0 && n.abs() <= 9007199254740991.0 => Some(n as i64),
+            _ => None,
+        }
+    }
+}
+
+impl FromJsValue for bool {
+    const TYPE_NAME: &'static str = "a boolean";
+
+    fn from_js_value(val: &JsValue) -> Option<bool> {
+        val.as_bool()
+    }
+}
+
+impl FromJsValue for String {
+    const TYPE_NAME: &'static str = "a string";
+
+    fn from_js_value(val: &JsValue) -> Option<String> {
+        val.as_str().map(|s| s.to_string())
+    }
+}
+
+impl<T: FromJsValue> FromJsValue for Vec<T> {
+    const TYPE_NAME: &'static str = "an array";
+
+    fn from_js_value(val: &JsValue) -> Option<Vec<T>> {
+        val.as_array()?.iter().map(T::from_js_value).collect()
+    }
+}
+
+impl<T: FromJsValue> FromJsValue for HashMap<String, T> {
+    const TYPE_NAME: &'static str = "an object";
+
+    fn from_js_value(val: &JsValue) -> Option<HashMap<String, T>> {
+        match val {
+            JsValue::Object(props) => props
+                .iter()
+                .map(|(name, val)| T::from_js_value(val).map(|val| (name.clone(), val)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// undefined and null are None.
+impl<T: FromJsValue> FromJsValue for Option<T> {
+    const TYPE_NAME: &'static str = T::TYPE_NAME;
+
+    fn from_js_value(val: &JsValue) -> Option<Option<T>> {
+        match val {
+            JsValue::Undefined | JsValue::Null => Some(None),
+            val => T::from_js_value(val).map(Some),
+        }
+    }
+}
+
+fn syntax_error(parser: &parser::Parser, pos: usize, msg: String) -> JsError {
+    let (_, _, line) = parser.lexer.get_code_around_err_point(pos);
+    JsError::Syntax {
+        line: line,
+        msg: msg,
+    }
+}
+
+impl JsValue {
+    pub fn from_value(val: &Value) -> JsValue {
+        match val {
+            Value::Empty | Value::Undefined => JsValue::Undefined,
+            Value::Null => JsValue::Null,
+            Value::Bool(b) => JsValue::Bool(*b),
+            Value::Number(n) => JsValue::Number(*n),
+            Value::BigInt(n) => JsValue::BigInt((**n).clone()),
+            Value::String(_) => JsValue::String(val.to_string()),
+            Value::Object(_, ObjectKind::Function(_))
+            | Value::Object(_, ObjectKind::BuiltinFunction(_)) => JsValue::Function,
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box val)) => JsValue::from_value(val),
+            Value::Object(_, ObjectKind::Array(aryval)) => JsValue::Array(
+                aryval
+                    .values()
+                    .iter()
+                    .map(|val| JsValue::from_value(val))
+                    .collect(),
+            ),
+            Value::Object(map, _) => JsValue::Object(
+                map.iter()
+                    .filter(|(_, prop)| prop.enumerable && prop.accessor.is_none())
+                    .map(|(name, prop)| (name.clone(), JsValue::from_value(&prop.val)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Make a new Value. JsValue::Function becomes undefined.
+    pub fn to_value(&self) -> Value {
+        match self {
+            JsValue::Undefined | JsValue::Function => Value::Undefined,
+            JsValue::Null => Value::Null,
+            JsValue::Bool(b) => Value::Bool(*b),
+            JsValue::Number(n) => Value::Number(*n),
+            JsValue::BigInt(n) => Value::bigint(n.clone()),
+            JsValue::String(s) => Value::string(s.clone()),
+            JsValue::Array(elems) => {
+                Value::array_from_elems(elems.iter().map(|elem| elem.to_value()).collect())
+            }
+            JsValue::Object(props) => Value::object_from_npp(
+                &props
+                    .iter()
+                    .map(|(name, val)| (name.clone(), Property::new(val.to_value())))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsValue::String(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsValue>> {
+        match self {
+            JsValue::Array(elems) => Some(elems),
+            _ => None,
+        }
+    }
+
+    /// property 'name' of an object.
+    pub fn get(&self, name: &str) -> Option<&JsValue> {
+        match self {
+            JsValue::Object(props) => props.iter().find(|(n, _)| n == name).map(|(_, val)| val),
+            _ => None,
+        }
+    }
+}
+
+impl From<()> for JsValue {
+    fn from(_: ()) -> JsValue {
+        JsValue::Undefined
+    }
+}
+
+impl From<bool> for JsValue {
+    fn from(b: bool) -> JsValue {
+        JsValue::Bool(b)
+    }
+}
+
+impl From<f64> for JsValue {
+    fn from(n: f64) -> JsValue {
+        JsValue::Number(n)
+    }
+}
+
+impl From<i32> for JsValue {
+    fn from(n: i32) -> JsValue {
+        JsValue::Number(n as f64)
+    }
+}
+
+impl From<i64> for JsValue {
+    fn from(n: i64) -> JsValue {
+        JsValue::Number(n as f64)
+    }
+}
+
+impl From<BigInt> for JsValue {
+    fn from(n: BigInt) -> JsValue {
+        JsValue::BigInt(n)
+    }
+}
+
+impl<'a> From<&'a str> for JsValue {
+    fn from(s: &'a str) -> JsValue {
+        JsValue::String(s.to_string())
+    }
+}
+
+impl From<String> for JsValue {
+    fn from(s: String) -> JsValue {
+        JsValue::String(s)
+    }
+}
+
+impl<T: Into<JsValue>> From<Vec<T>> for JsValue {
+    fn from(elems: Vec<T>) -> JsValue {
+        JsValue::Array(elems.into_iter().map(|elem| elem.into()).collect())
+    }
+}
+
+impl<T: Into<JsValue>> From<HashMap<String, T>> for JsValue {
+    fn from(map: HashMap<String, T>) -> JsValue {
+        JsValue::Object(map.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: Into<JsValue>> From<Option<T>> for JsValue {
+    fn from(val: Option<T>) -> JsValue {
+        match val {
+            Some(val) => val.into(),
+            None => JsValue::Null,
+        }
+    }
+}
+
+impl From<RuntimeError> for JsError {
+    fn from(err: RuntimeError) -> JsError {
+        match err {
+            RuntimeError::Exception(val) => JsError::Exception(JsValue::from_value(&val)),
+            RuntimeError::Type(msg) | RuntimeError::Reference(msg) | RuntimeError::General(msg) => {
+                JsError::Runtime(msg)
+            }
+            RuntimeError::Unimplemented => JsError::Runtime("unimplemented feature".to_string()),
+            RuntimeError::Unknown => JsError::Runtime("unknown error occurred".to_string()),
+            RuntimeError::Terminated => JsError::Terminated,
+        }
+    }
+}
+
+impl From<JsError> for RuntimeError {
+    fn from(err: JsError) -> RuntimeError {
+        match err {
+            JsError::Exception(val) => RuntimeError::Exception(val.to_value()),
+            JsError::Runtime(msg) => RuntimeError::General(msg),
+            JsError::Terminated => RuntimeError::Terminated,
+            err @ JsError::Syntax { .. } => RuntimeError::General(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsError::Syntax { line, msg } => write!(f, "parse error(line {}): {}", line, msg),
+            JsError::Exception(val) => {
+                write!(f, "Uncaught Exception: {}", val.to_value().format(3, false))
+            }
+            JsError::Runtime(msg) => write!(f, "runtime error: {}", msg),
+            JsError::Terminated => write!(f, "script terminated"),
+        }
+    }
+}
+
+impl error::Error for JsError {}
+
+#[test]
+fn test() {
+    let mut engine = Engine::new();
+    engine.set_global("n", 10);
+    engine.set_global("names", vec!["a", "b"]);
+    engine.set_global(
+        "point",
+        JsValue::Object(vec![
+            ("x".to_string(), 1.into()),
+            ("y".to_string(), 2.into()),
+        ]),
+    );
+    assert_eq!(
+        engine.eval("function add(x, y) { return x + y }; n * 2"),
+        Ok(JsValue::Number(20.0))
+    );
+    assert_eq!(engine.eval("names.join('-')"), Ok("a-b".into()));
+    assert_eq!(engine.eval("point.x + point.y"), Ok(3.into()));
+    assert_eq!(
+        engine.call_function("add", &[2.into(), 3.into()]),
+        Ok(5.into())
+    );
+    assert_eq!(
+        engine.call_function("add", &["x".into(), JsValue::Null]),
+        Ok("xnull".into())
+    );
+
+    let obj = engine
+        .eval("var o = { a: [1, true, null], s: 'str' }; o")
+        .unwrap();
+    assert_eq!(
+        obj.get("a"),
+        Some(&JsValue::Array(vec![1.into(), true.into(), JsValue::Null]))
+    );
+    assert_eq!(obj.get("s").and_then(|s| s.as_str()), Some("str"));
+    assert_eq!(engine.get_global("add"), Ok(JsValue::Function));
+    assert_eq!(
+        engine.eval("12345678901234567890n * 10n"),
+        Ok(JsValue::BigInt("123456789012345678900".parse().unwrap()))
+    );
+
+    assert_eq!(
+        engine.eval("throw 'oops'"),
+        Err(JsError::Exception("oops".into()))
+    );
+    match engine.eval("var a = ;") {
+        Err(JsError::Syntax { line: 1, .. }) => {}
+        e => panic!("{:?}", e),
+    }
+    match engine.call_function("nothing", &[]) {
+        Err(JsError::Runtime(_)) => {}
+        e => panic!("{:?}", e),
+    }
+    // The engine keeps working after errors.
+    assert_eq!(engine.eval("add(n, 1)"), Ok(11.into()));
+}
+
+#[test]
+fn test_native() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut engine = Engine::new();
+    engine.register_fn("add", |args| {
+        let x: f64 = arg(args, 0)?;
+        let y: f64 = arg(args, 1)?;
+        Ok((x + y).into())
+    });
+    let count = Rc::new(Cell::new(0));
+    let mut host = NativeObject::new();
+    {
+        let count = count.clone();
+        host.set("name", "rapidus")
+            .register_fn("count", move |_| {
+                count.set(count.get() + 1);
+                Ok(count.get().into())
+            })
+            .register_fn("join", |args| {
+                let strs: Vec<String> = arg(args, 0)?;
+                let sep: Option<String> = arg(args, 1)?;
+                Ok(strs.join(&sep.unwrap_or(",".to_string())).into())
+            })
+            .register_fn("fail", |_| Err(JsError::Exception("failed".into())));
+    }
+    let mut math = NativeObject::new();
+    math.register_fn("twice", |args| Ok((arg::<i64>(args, 0)? * 2).into()));
+    host.register_object("math", math);
+    engine.register_object("host", host);
+
+    assert_eq!(engine.eval("add(1, 2) + add(0.5, 0.25)"), Ok(3.75.into()));
+    assert_eq!(engine.eval("host.count(); host.count()"), Ok(2.into()));
+    assert_eq!(count.get(), 2);
+    assert_eq!(
+        engine.eval("host.join(['a', host.name]) + host.join(['b', 'c'], '-')"),
+        Ok("a,rapidusb-c".into())
+    );
+    assert_eq!(engine.eval("host.math.twice(21)"), Ok(42.into()));
+    assert_eq!(
+        engine.eval("try { host.fail() } catch (e) { 'caught ' + e }"),
+        Ok("caught failed".into())
+    );
+    assert_eq!(
+        engine.eval("add(1, 'x')"),
+        Err(JsError::Runtime(
+            "type error: argument 2 must be a number".to_string()
+        ))
+    );
+    assert!(engine.eval("host.math.twice(1.5)").is_err());
+}
+
+#[test]
+fn test_limits() {
+    fn range_error(res: Result<JsValue, JsError>) -> String {
+        match res {
+            Err(JsError::Exception(err)) => err
+                .get("message")
+                .and_then(|msg| msg.as_str())
+                .unwrap_or("")
+                .to_string(),
+            res => panic!("{:?}", res),
+        }
+    }
+
+    let mut engine = Engine::with_options(VMOptions {
+        max_stack_frames: 100,
+        max_heap_bytes: None,
+        max_instructions: Some(100_000),
+    });
+    let msg = range_error(engine.eval("function f(n) { return 1 + f(n) }; f(0)"));
+    assert!(
+        msg.starts_with("RangeError: Maximum call stack size"),
+        "{}",
+        msg
+    );
+    assert_eq!(
+        engine.eval("try { f(0) } catch (e) { 'caught' }"),
+        Ok("caught".into())
+    );
+    let msg = range_error(engine.eval("while (true) { try { } catch (e) { } }"));
+    assert!(msg.starts_with("RangeError: Instruction limit"), "{}", msg);
+    // The count starts again for each script.
+    assert_eq!(engine.eval("1 + 1"), Ok(2.into()));
+    assert!(engine.call_function("f", &[0.into()]).is_err());
+
+    // The builtins are allocated too, so the heap limit is relative to them.
+    let mut engine = Engine::new();
+    engine.vm().options.max_heap_bytes = Some(::gc::heap_bytes() + 64 * 1024);
+    // Garbage does not count.
+    assert_eq!(
+        engine.eval("for (var i = 0; i < 5000; i++) { var o = { i: i } }; i"),
+        Ok(5000.into())
+    );
+    engine.vm().options.max_heap_bytes = Some(::gc::heap_bytes() + 64 * 1024);
+    let msg = range_error(engine.eval("var a = []; while (true) { a.push({ n: 1 }) }"));
+    assert!(msg.starts_with("RangeError: Out of memory"), "{}", msg);
+    // What objects own counts as well, though no objects are made.
+    engine.vm().options.max_heap_bytes = Some(::gc::heap_bytes() + 64 * 1024);
+    let msg = range_error(engine.eval("var b = []; while (true) { b.push(1) }"));
+    assert!(msg.starts_with("RangeError: Out of memory"), "{}", msg);
+}
+
+#[test]
+fn test_terminate() {
+    use std::thread;
+    use std::time::Duration;
+
+    let mut engine = Engine::new();
+    let handle = engine.terminate_handle();
+    let terminator = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        handle.terminate();
+    });
+    // try-catch and finally do not stop the termination.
+    assert_eq!(
+        engine.eval(
+            "var n = 0;
+             function spin() { while (true) { try { n++ } catch (e) { } } }
+             try { spin() } finally { while (true) { } }"
+        ),
+        Err(JsError::Terminated)
+    );
+    terminator.join().unwrap();
+    // The engine can run scripts again.
+    assert_eq!(engine.eval("n > 0"), Ok(true.into()));
+
+    // Pending timers are discarded.
+    engine.register_fn("stop", |_| Err(JsError::Terminated));
+    assert_eq!(
+        engine.eval("setTimeout(function() { n = -1 }, 0); stop()"),
+        Err(JsError::Terminated)
+    );
+    assert_eq!(engine.eval("n > 0"), Ok(true.into()));
+}
+
+#[test]
+fn test_run_jobs() {
+    let mut engine = Engine::new();
+    engine
+        .eval(
+            "var log = [];
+             function logged() { return log.join() }
+             function start() {
+               Promise.resolve(1).then(function(x) { log.push('then ' + x) });
+               queueMicrotask(function() { log.push('microtask') });
+               queueMicrotask(function() { throw 'oops' });
+               queueMicrotask(function() { log.push('after') });
+               log.push('sync');
+             }",
+        )
+        .unwrap();
+    engine.call_function("start", &[]).unwrap();
+    assert_eq!(engine.call_function("logged", &[]), Ok("sync".into()));
+    // Jobs run in the order they were enqueued.
+    assert_eq!(engine.run_jobs(), Err(JsError::Exception("oops".into())));
+    assert_eq!(
+        engine.call_function("logged", &[]),
+        Ok("sync,then 1,microtask".into())
+    );
+    assert_eq!(engine.run_jobs(), Ok(()));
+    assert_eq!(
+        engine.call_function("logged", &[]),
+        Ok("sync,then 1,microtask,after".into())
+    );
+}
+
+#[test]
+fn test_random_seed() {
+    use std::thread;
+
+    let mut engine = Engine::new();
+    let script = "var r = []; for (var i = 0; i < 5; i++) { r.push(Math.random()) }; r";
+    engine.set_random_seed(42);
+    let first = engine.eval(script).unwrap();
+    engine.set_random_seed(42);
+    // Seeding or drawing numbers in other engines, on this thread or another, does not change
+    // the sequence of this one.
+    let mut other = Engine::new();
+    other.set_random_seed(43);
+    other.eval(script).unwrap();
+    thread::spawn(move || {
+        let mut engine = Engine::new();
+        engine.set_random_seed(43);
+        engine.eval(script).unwrap();
+    })
+    .join()
+    .unwrap();
+    assert_eq!(engine.eval(script).unwrap(), first);
+    let mut seeded = Engine::new();
+    seeded.set_random_seed(42);
+    assert_eq!(seeded.eval(script).unwrap(), first);
+    engine.set_random_seed(43);
+    assert!(engine.eval(script).unwrap() != first);
+    for n in first.as_array().unwrap() {
+        let n = n.as_f64().unwrap();
+        assert!(n >= 0.0 && n < 1.0, "{}", n);
+    }
+}
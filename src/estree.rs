@@ -0,0 +1,735 @@
+use node::{
+    BinOp, FormalParameter, FormalParameters, FunctionKind, MethodDefinitionKind, Node, NodeBase,
+    PropertyDefinition, UnaryOp,
+};
+use std::fmt;
+use std::slice;
+use vm::value::number_to_string;
+
+// Conversion of the AST into ESTree (https://github.com/estree/estree), the JSON shape of
+// JavaScript syntax trees shared by tools such as linters and formatters.
+//
+// The AST does not keep everything ESTree has, so some details are approximated: a node has
+// 'start', the position where it begins, but no 'end', 'let' and 'const' declarations come
+// out as 'var' ones, and property keys other than computed ones are identifiers or strings.
+
+/// A JSON value. Display writes it compactly, and '{:#}' writes it indented.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+/// Convert a script returned by Parser::parse_all() into an ESTree Program.
+pub fn program(script: &Node) -> Json {
+    let body = match script.base {
+        NodeBase::StatementList(ref body) => body.iter().map(statement).collect(),
+        _ => vec![statement(script)],
+    };
+    node(
+        "Program",
+        script.pos,
+        vec![
+            ("sourceType", string("script")),
+            ("body", Json::Array(body)),
+        ],
+    )
+}
+
+fn node(ty: &'static str, pos: usize, mut props: Vec<(&'static str, Json)>) -> Json {
+    let mut obj = vec![("type", string(ty)), ("start", Json::Number(pos as f64))];
+    obj.append(&mut props);
+    Json::Object(obj)
+}
+
+fn string(s: &str) -> Json {
+    Json::String(s.to_string())
+}
+
+fn identifier(name: &str, pos: usize) -> Json {
+    node("Identifier", pos, vec![("name", string(name))])
+}
+
+fn literal(val: Json, pos: usize) -> Json {
+    node("Literal", pos, vec![("value", val)])
+}
+
+/// A variable declaration list is a statement list of VarDecl.
+fn is_variable_declaration_list(body: &[Node]) -> bool {
+    !body.is_empty()
+        && body.iter().all(|node| match node.base {
+            NodeBase::VarDecl(_, _) => true,
+            _ => false,
+        })
+}
+
+fn statement(stmt: &Node) -> Json {
+    let pos = stmt.pos;
+    match stmt.base {
+        NodeBase::StatementList(ref body) if is_variable_declaration_list(body) => {
+            variable_declaration(body, pos)
+        }
+        NodeBase::StatementList(_) => block(stmt),
+        NodeBase::VarDecl(_, _) => variable_declaration(slice::from_ref(stmt), pos),
+        NodeBase::FunctionDecl(ref name, ref params, ref body, _, kind) => {
+            function("FunctionDeclaration", Some(name), params, body, kind, pos)
+        }
+        NodeBase::If(ref cond, ref then_, ref else_) => node(
+            "IfStatement",
+            pos,
+            vec![
+                ("test", expression(cond)),
+                ("consequent", statement(then_)),
+                ("alternate", optional_statement(else_)),
+            ],
+        ),
+        NodeBase::While(ref cond, ref body) => node(
+            "WhileStatement",
+            pos,
+            vec![("test", expression(cond)), ("body", statement(body))],
+        ),
+        NodeBase::For(ref init, ref cond, ref step, ref body) => {
+            let init = match init.base {
+                NodeBase::Nope => Json::Null,
+                NodeBase::StatementList(_) | NodeBase::VarDecl(_, _) => statement(init),
+                _ => expression(init),
+            };
+            node(
+                "ForStatement",
+                pos,
+                vec![
+                    ("init", init),
+                    ("test", expression(cond)),
+                    ("update", expression(step)),
+                    ("body", statement(body)),
+                ],
+            )
+        }
+        NodeBase::ForAwaitOf(ref target, ref iterable, ref body) => {
+            let left = match target.base {
+                NodeBase::StatementList(_) | NodeBase::VarDecl(_, _) => statement(target),
+                _ => expression(target),
+            };
+            node(
+                "ForOfStatement",
+                pos,
+                vec![
+                    ("await", Json::Bool(true)),
+                    ("left", left),
+                    ("right", expression(iterable)),
+                    ("body", statement(body)),
+                ],
+            )
+        }
+        NodeBase::With(ref obj, ref body) => node(
+            "WithStatement",
+            pos,
+            vec![("object", expression(obj)), ("body", statement(body))],
+        ),
+        NodeBase::Return(ref arg) => node(
+            "ReturnStatement",
+            pos,
+            vec![(
+                "argument",
+                arg.as_ref().map_or(Json::Null, |arg| expression(arg)),
+            )],
+        ),
+        NodeBase::Label(ref name, ref body) => node(
+            "LabeledStatement",
+            pos,
+            vec![("label", identifier(name, pos)), ("body", statement(body))],
+        ),
+        NodeBase::Break(ref label) => node(
+            "BreakStatement",
+            pos,
+            vec![("label", optional_label(label, pos))],
+        ),
+        NodeBase::Continue(ref label) => node(
+            "ContinueStatement",
+            pos,
+            vec![("label", optional_label(label, pos))],
+        ),
+        NodeBase::Try(ref try, ref catch, ref param, ref finally) => {
+            let handler = match catch.base {
+                NodeBase::Nope => Json::Null,
+                _ => node(
+                    "CatchClause",
+                    catch.pos,
+                    vec![("param", expression(param)), ("body", block(catch))],
+                ),
+            };
+            let finalizer = match finally.base {
+                NodeBase::Nope => Json::Null,
+                _ => block(finally),
+            };
+            node(
+                "TryStatement",
+                pos,
+                vec![
+                    ("block", block(try)),
+                    ("handler", handler),
+                    ("finalizer", finalizer),
+                ],
+            )
+        }
+        NodeBase::Throw(ref arg) => {
+            node("ThrowStatement", pos, vec![("argument", expression(arg))])
+        }
+        NodeBase::Nope => node("EmptyStatement", pos, vec![]),
+        _ => node(
+            "ExpressionStatement",
+            pos,
+            vec![("expression", expression(stmt))],
+        ),
+    }
+}
+
+/// A missing statement (e.g. 'else' part) is null.
+fn optional_statement(stmt: &Node) -> Json {
+    match stmt.base {
+        NodeBase::Nope => Json::Null,
+        _ => statement(stmt),
+    }
+}
+
+fn optional_label(label: &Option<String>, pos: usize) -> Json {
+    label
+        .as_ref()
+        .map_or(Json::Null, |label| identifier(label, pos))
+}
+
+fn block(stmt: &Node) -> Json {
+    let body = match stmt.base {
+        NodeBase::StatementList(ref body) => body.iter().map(statement).collect(),
+        _ => vec![statement(stmt)],
+    };
+    node(
+        "BlockStatement",
+        stmt.pos,
+        vec![("body", Json::Array(body))],
+    )
+}
+
+fn variable_declaration(decls: &[Node], pos: usize) -> Json {
+    let declarations = decls
+        .iter()
+        .filter_map(|decl| match decl.base {
+            NodeBase::VarDecl(ref name, ref init) => Some(node(
+                "VariableDeclarator",
+                decl.pos,
+                vec![
+                    ("id", identifier(name, decl.pos)),
+                    (
+                        "init",
+                        init.as_ref().map_or(Json::Null, |init| expression(init)),
+                    ),
+                ],
+            )),
+            _ => None,
+        })
+        .collect();
+    node(
+        "VariableDeclaration",
+        pos,
+        vec![
+            ("declarations", Json::Array(declarations)),
+            ("kind", string("var")),
+        ],
+    )
+}
+
+fn function(
+    ty: &'static str,
+    name: Option<&String>,
+    params: &FormalParameters,
+    body: &Node,
+    kind: FunctionKind,
+    pos: usize,
+) -> Json {
+    node(
+        ty,
+        pos,
+        vec![
+            ("id", name.map_or(Json::Null, |name| identifier(name, pos))),
+            (
+                "params",
+                Json::Array(params.iter().map(|param| parameter(param, pos)).collect()),
+            ),
+            ("body", block(body)),
+            (
+                "generator",
+                Json::Bool(kind == FunctionKind::Generator || kind == FunctionKind::AsyncGenerator),
+            ),
+            (
+                "async",
+                Json::Bool(kind == FunctionKind::Async || kind == FunctionKind::AsyncGenerator),
+            ),
+        ],
+    )
+}
+
+fn parameter(param: &FormalParameter, pos: usize) -> Json {
+    let id = identifier(&param.name, pos);
+    if param.is_rest_param {
+        node("RestElement", pos, vec![("argument", id)])
+    } else if let Some(ref init) = param.init {
+        node(
+            "AssignmentPattern",
+            pos,
+            vec![("left", id), ("right", expression(init))],
+        )
+    } else {
+        id
+    }
+}
+
+fn expressions(exprs: &[Node]) -> Json {
+    Json::Array(exprs.iter().map(expression).collect())
+}
+
+/// A missing expression (e.g. the condition of 'for (;;)') is null.
+fn expression(expr: &Node) -> Json {
+    let pos = expr.pos;
+    match expr.base {
+        NodeBase::Identifier(ref name) => identifier(name, pos),
+        NodeBase::This => node("ThisExpression", pos, vec![]),
+        NodeBase::Arguments => identifier("arguments", pos),
+        NodeBase::Undefined => identifier("undefined", pos),
+        NodeBase::Null => literal(Json::Null, pos),
+        NodeBase::String(ref s) => literal(string(s), pos),
+        NodeBase::Boolean(b) => literal(Json::Bool(b), pos),
+        NodeBase::Number(n) => literal(Json::Number(n), pos),
+        NodeBase::BigInt(ref n) => node(
+            "Literal",
+            pos,
+            vec![
+                ("value", Json::Null),
+                ("bigint", Json::String(n.to_str_radix(10))),
+            ],
+        ),
+        NodeBase::Array(ref elems) => node(
+            "ArrayExpression",
+            pos,
+            vec![("elements", expressions(elems))],
+        ),
+        NodeBase::Object(ref props) => node(
+            "ObjectExpression",
+            pos,
+            vec![(
+                "properties",
+                Json::Array(props.iter().map(|prop| property(prop, pos)).collect()),
+            )],
+        ),
+        NodeBase::FunctionExpr(ref name, ref params, ref body, _, kind) => {
+            function("FunctionExpression", name.as_ref(), params, body, kind, pos)
+        }
+        NodeBase::Template(ref cooked, ref raw, ref substitutions) => {
+            let quasis = cooked
+                .iter()
+                .zip(raw)
+                .enumerate()
+                .map(|(i, (cooked_string, raw_string))| {
+                    let value = vec![
+                        ("raw", string(raw_string)),
+                        (
+                            "cooked",
+                            cooked_string.as_ref().map_or(Json::Null, |s| string(s)),
+                        ),
+                    ];
+                    node(
+                        "TemplateElement",
+                        pos,
+                        vec![
+                            ("value", Json::Object(value)),
+                            ("tail", Json::Bool(i + 1 == raw.len())),
+                        ],
+                    )
+                })
+                .collect();
+            node(
+                "TemplateLiteral",
+                pos,
+                vec![
+                    ("quasis", Json::Array(quasis)),
+                    ("expressions", expressions(substitutions)),
+                ],
+            )
+        }
+        NodeBase::TaggedTemplate(ref tag, ref template) => node(
+            "TaggedTemplateExpression",
+            pos,
+            vec![("tag", expression(tag)), ("quasi", expression(template))],
+        ),
+        NodeBase::Member(ref obj, ref name) => node(
+            "MemberExpression",
+            pos,
+            vec![
+                ("object", expression(obj)),
+                ("property", identifier(name, pos)),
+                ("computed", Json::Bool(false)),
+            ],
+        ),
+        NodeBase::Index(ref obj, ref idx) => node(
+            "MemberExpression",
+            pos,
+            vec![
+                ("object", expression(obj)),
+                ("property", expression(idx)),
+                ("computed", Json::Bool(true)),
+            ],
+        ),
+        NodeBase::New(ref callee) => {
+            let (callee, args) = match callee.base {
+                NodeBase::Call(ref callee, ref args) => (expression(callee), expressions(args)),
+                _ => (expression(callee), Json::Array(vec![])),
+            };
+            node(
+                "NewExpression",
+                pos,
+                vec![("callee", callee), ("arguments", args)],
+            )
+        }
+        NodeBase::Call(ref callee, ref args) => node(
+            "CallExpression",
+            pos,
+            vec![
+                ("callee", expression(callee)),
+                ("arguments", expressions(args)),
+            ],
+        ),
+        NodeBase::Assign(ref lhs, ref rhs) => node(
+            "AssignmentExpression",
+            pos,
+            vec![
+                ("operator", Json::String("=".to_string())),
+                ("left", expression(lhs)),
+                ("right", expression(rhs)),
+            ],
+        ),
+        NodeBase::CompoundAssign(ref lhs, ref rhs, ref op) => node(
+            "AssignmentExpression",
+            pos,
+            vec![
+                (
+                    "operator",
+                    Json::String(format!("{}=", binary_operator(op))),
+                ),
+                ("left", expression(lhs)),
+                ("right", expression(rhs)),
+            ],
+        ),
+        NodeBase::UnaryOp(ref arg, ref op) => {
+            let (ty, operator, prefix) = match op {
+                UnaryOp::PrInc => ("UpdateExpression", "++", true),
+                UnaryOp::PrDec => ("UpdateExpression", "--", true),
+                UnaryOp::PoInc => ("UpdateExpression", "++", false),
+                UnaryOp::PoDec => ("UpdateExpression", "--", false),
+                UnaryOp::Delete => ("UnaryExpression", "delete", true),
+                UnaryOp::Void => ("UnaryExpression", "void", true),
+                UnaryOp::Typeof => ("UnaryExpression", "typeof", true),
+                UnaryOp::Plus => ("UnaryExpression", "+", true),
+                UnaryOp::Minus => ("UnaryExpression", "-", true),
+                UnaryOp::BitwiseNot => ("UnaryExpression", "~", true),
+                UnaryOp::Not => ("UnaryExpression", "!", true),
+            };
+            node(
+                ty,
+                pos,
+                vec![
+                    ("operator", string(operator)),
+                    ("prefix", Json::Bool(prefix)),
+                    ("argument", expression(arg)),
+                ],
+            )
+        }
+        NodeBase::BinaryOp(_, _, BinOp::Comma) => {
+            let mut exprs = vec![];
+            flatten_sequence(expr, &mut exprs);
+            node(
+                "SequenceExpression",
+                pos,
+                vec![("expressions", Json::Array(exprs))],
+            )
+        }
+        NodeBase::BinaryOp(ref lhs, ref rhs, ref op) => {
+            let ty = match op {
+                BinOp::LAnd | BinOp::LOr => "LogicalExpression",
+                _ => "BinaryExpression",
+            };
+            node(
+                ty,
+                pos,
+                vec![
+                    ("operator", string(binary_operator(op))),
+                    ("left", expression(lhs)),
+                    ("right", expression(rhs)),
+                ],
+            )
+        }
+        NodeBase::TernaryOp(ref cond, ref then_, ref else_) => node(
+            "ConditionalExpression",
+            pos,
+            vec![
+                ("test", expression(cond)),
+                ("consequent", expression(then_)),
+                ("alternate", expression(else_)),
+            ],
+        ),
+        NodeBase::Await(ref arg) => {
+            node("AwaitExpression", pos, vec![("argument", expression(arg))])
+        }
+        NodeBase::Yield(ref arg, delegate) => node(
+            "YieldExpression",
+            pos,
+            vec![
+                (
+                    "argument",
+                    arg.as_ref().map_or(Json::Null, |arg| expression(arg)),
+                ),
+                ("delegate", Json::Bool(delegate)),
+            ],
+        ),
+        NodeBase::Nope => Json::Null,
+        _ => statement(expr),
+    }
+}
+
+/// 'a, b, c' is parsed as '(a, b), c'.
+fn flatten_sequence(expr: &Node, exprs: &mut Vec<Json>) {
+    match expr.base {
+        NodeBase::BinaryOp(ref lhs, ref rhs, BinOp::Comma) => {
+            flatten_sequence(lhs, exprs);
+            flatten_sequence(rhs, exprs);
+        }
+        _ => exprs.push(expression(expr)),
+    }
+}
+
+fn property(prop: &PropertyDefinition, pos: usize) -> Json {
+    if let PropertyDefinition::Spread(arg) = prop {
+        return node("SpreadElement", pos, vec![("argument", expression(arg))]);
+    }
+    let key = |name: &str| {
+        let is_identifier_name = name
+            .chars()
+            .next()
+            .map_or(false, |c| c.is_alphabetic() || c == '_' || c == '$')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+        if is_identifier_name {
+            identifier(name, pos)
+        } else {
+            literal(string(name), pos)
+        }
+    };
+    let (key, val, kind, computed, method, shorthand) = match prop {
+        PropertyDefinition::IdentifierReference(name) => {
+            (key(name), identifier(name, pos), "init", false, false, true)
+        }
+        PropertyDefinition::Property(name, val) => {
+            (key(name), expression(val), "init", false, false, false)
+        }
+        PropertyDefinition::ComputedProperty(key, val) => {
+            (expression(key), expression(val), "init", true, false, false)
+        }
+        PropertyDefinition::MethodDefinition(kind, name, func) => {
+            let (kind, method) = match kind {
+                MethodDefinitionKind::Ordinary => ("init", true),
+                MethodDefinitionKind::Get => ("get", false),
+                MethodDefinitionKind::Set => ("set", false),
+            };
+            (key(name), expression(func), kind, false, method, false)
+        }
+        PropertyDefinition::Spread(_) => unreachable!(),
+    };
+    node(
+        "Property",
+        pos,
+        vec![
+            ("key", key),
+            ("value", val),
+            ("kind", string(kind)),
+            ("computed", Json::Bool(computed)),
+            ("method", Json::Bool(method)),
+            ("shorthand", Json::Bool(shorthand)),
+        ],
+    )
+}
+
+fn binary_operator(op: &BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Rem => "%",
+        BinOp::Exp => "**",
+        BinOp::And => "&",
+        BinOp::Or => "|",
+        BinOp::Xor => "^",
+        BinOp::LAnd => "&&",
+        BinOp::LOr => "||",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::SEq => "===",
+        BinOp::SNe => "!==",
+        BinOp::Lt => "<",
+        BinOp::Gt => ">",
+        BinOp::Le => "<=",
+        BinOp::Ge => ">=",
+        BinOp::Shl => "<<",
+        BinOp::Shr => ">>",
+        BinOp::ZFShr => ">>>",
+        BinOp::Comma => ",",
+        BinOp::Assign => "=",
+    }
+}
+
+impl Json {
+    fn write(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pretty = f.alternate();
+        let newline = |f: &mut fmt::Formatter, indent: usize| {
+            if pretty {
+                write!(f, "\n{}", "  ".repeat(indent))
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // NaN and Infinity cannot be written in JSON.
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", number_to_string(*n)),
+            Json::String(s) => write_string(f, s),
+            Json::Array(elems) if elems.is_empty() => write!(f, "[]"),
+            Json::Array(elems) => {
+                write!(f, "[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent + 1)?;
+                    elem.write(f, indent + 1)?;
+                }
+                newline(f, indent)?;
+                write!(f, "]")
+            }
+            Json::Object(props) if props.is_empty() => write!(f, "{{}}"),
+            Json::Object(props) => {
+                write!(f, "{{")?;
+                for (i, (name, val)) in props.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent + 1)?;
+                    write_string(f, name)?;
+                    write!(f, "{}", if pretty { ": " } else { ":" })?;
+                    val.write(f, indent + 1)?;
+                }
+                newline(f, indent)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            '\u{0}'...'\u{1f}' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+
+    let estree = |code: &str| program(&Parser::new(code.to_string()).parse_all().unwrap());
+    assert_eq!(
+        estree("var a = 1, b").to_string(),
+        "{\"type\":\"Program\",\"start\":0,\"sourceType\":\"script\",\"body\":[\
+         {\"type\":\"VariableDeclaration\",\"start\":0,\"declarations\":[\
+         {\"type\":\"VariableDeclarator\",\"start\":4,\
+         \"id\":{\"type\":\"Identifier\",\"start\":4,\"name\":\"a\"},\
+         \"init\":{\"type\":\"Literal\",\"start\":8,\"value\":1}},\
+         {\"type\":\"VariableDeclarator\",\"start\":11,\
+         \"id\":{\"type\":\"Identifier\",\"start\":11,\"name\":\"b\"},\"init\":null}],\
+         \"kind\":\"var\"}]}"
+    );
+    assert_eq!(
+        format!("{:#}", estree("x += 'a\"'")),
+        r#"{
+  "type": "Program",
+  "start": 0,
+  "sourceType": "script",
+  "body": [
+    {
+      "type": "ExpressionStatement",
+      "start": 0,
+      "expression": {
+        "type": "AssignmentExpression",
+        "start": 0,
+        "operator": "+=",
+        "left": {
+          "type": "Identifier",
+          "start": 0,
+          "name": "x"
+        },
+        "right": {
+          "type": "Literal",
+          "start": 5,
+          "value": "a\""
+        }
+      }
+    }
+  ]
+}"#
+    );
+
+    let ty = |json: &Json| match json {
+        Json::Object(props) => props[0].1.clone(),
+        _ => Json::Null,
+    };
+    let body = |code: &str| match estree(code) {
+        Json::Object(props) => match props[3].1 {
+            Json::Array(ref body) => body.iter().map(ty).collect::<Vec<Json>>(),
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    assert_eq!(
+        body(
+            "function f(a, b, ...c) { return a, b } if (a) {} else ; for (;;) break; \
+              try {} catch (e) {} new A; a && b ? -a++ : {x, [y]: 1, get z() {}}"
+        ),
+        vec![
+            string("FunctionDeclaration"),
+            string("IfStatement"),
+            string("ForStatement"),
+            string("TryStatement"),
+            string("ExpressionStatement"),
+            string("ExpressionStatement"),
+        ]
+    );
+}
@@ -0,0 +1,87 @@
+use node::{FunctionKind, Node, NodeBase, UnaryOp};
+use vm_codegen::Error;
+
+// Feature gate. The parser reads some constructs which the code generator cannot compile yet.
+// This pass runs on the whole script before the code generation, and reports the first of them
+// as an error like "the typeof operator is not supported yet", shown with its line, instead of
+// failing somewhere in the code generator. The constructs are listed in unsupported_feature();
+// a construct is removed from there when the code generator supports it.
+
+/// Check that 'node', including the bodies of the functions in it, has no unsupported
+/// construct.
+pub fn check(node: &Node) -> Result<(), Error> {
+    if let Some(feature) = unsupported_feature(node) {
+        return Err(Error::Unimplemented {
+            msg: format!("{} is not supported yet", feature),
+            token_pos: node.pos,
+        });
+    }
+    for child in node.children() {
+        check(child)?;
+    }
+    Ok(())
+}
+
+/// the name of the construct 'node' is, if it is not supported.
+fn unsupported_feature(node: &Node) -> Option<&'static str> {
+    match node.base {
+        NodeBase::UnaryOp(_, UnaryOp::Delete) => Some("the delete operator"),
+        NodeBase::UnaryOp(_, UnaryOp::Void) => Some("the void operator"),
+        NodeBase::UnaryOp(_, UnaryOp::Typeof) => Some("the typeof operator"),
+        NodeBase::FunctionDecl(_, _, _, _, kind) | NodeBase::FunctionExpr(_, _, _, _, kind) => {
+            match kind {
+                FunctionKind::Normal => None,
+                FunctionKind::Generator => Some("function*"),
+                FunctionKind::Async => Some("async function"),
+                FunctionKind::AsyncGenerator => Some("async function*"),
+            }
+        }
+        // Only in the functions above.
+        NodeBase::ForAwaitOf(_, _, _) => Some("for await"),
+        NodeBase::Await(_) => Some("the await operator"),
+        NodeBase::Yield(_, _) => Some("yield"),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_feature_gate() {
+    use parser::Parser;
+
+    let check_code = |code: &str| {
+        let node = Parser::new(code.to_string()).parse_all().unwrap();
+        match check(&node) {
+            Ok(()) => Ok(()),
+            Err(Error::Unimplemented { msg, token_pos }) => Err((msg, token_pos)),
+            Err(Error::General { msg, .. }) => panic!("{}", msg),
+        }
+    };
+
+    assert_eq!(
+        check_code("var a = [1, { x: -a }]; function f(n) { return a[n]++ }"),
+        Ok(())
+    );
+    assert_eq!(
+        check_code("var a = 1\nvar b = typeof a"),
+        Err(("the typeof operator is not supported yet".to_string(), 18))
+    );
+    // in the bodies of functions
+    assert!(check_code("function f() { return { x: [void 0] } }").is_err());
+    assert!(check_code("var f = function (o) { o.g(delete o.p) }").is_err());
+
+    assert_eq!(
+        check_code("var async;\nasync function f() { await g() }"),
+        Err(("async function is not supported yet".to_string(), 11))
+    );
+    assert_eq!(
+        check_code("x = function* () { yield 1 }"),
+        Err(("function* is not supported yet".to_string(), 14))
+    );
+    assert_eq!(
+        check_code("async function* f() { for await (var x of g()) ; }"),
+        Err(("async function* is not supported yet".to_string(), 0))
+    );
+    // 'async' on another line is a variable.
+    assert_eq!(check_code("async\nfunction f() {}"), Ok(()));
+    assert_eq!(check_code("var await, yield; await + yield"), Ok(()));
+}
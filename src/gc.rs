@@ -1,23 +1,98 @@
-use rustc_hash::{FxHashMap, FxHashSet};
-use std::cell::RefCell;
+use builtins::error::new_error;
+use chrono::Utc;
+use rustc_hash::FxHashSet;
+use std::cell::{Cell, RefCell};
 use std::fmt::{Debug, Formatter, Result};
 use std::hash::{Hash, Hasher};
 use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{self, AtomicUsize};
+use std::time::{Duration, Instant};
 use stopwatch::Stopwatch;
 use vm::{
     callobj::CallObject,
-    value::{ArrayValue, ObjectKind, Property, Value},
+    error::RuntimeError,
+    jsstring::string_bytes,
+    task::{Task, TimerKind},
+    upvalue::Upvalue,
+    value::{
+        Accessor, ArrayBuffer, ArrayValue, ObjectKind, PropMap, Property, Registration, UpvalueRef,
+        Value, WeakTable, WeakTableRef,
+    },
     vm::VM,
 };
 
-static ALLOCATED_MEM_SIZE_BYTE: AtomicUsize = AtomicUsize::new(0);
+// The GC has two generations. Objects are made young, and the young objects are collected by a
+// minor collection when there are NURSERY_LIMIT of them. A minor collection does not trace the
+// old objects but the roots and the old objects which may refer to young objects, which
+// write_barrier() remembers, and promotes the young objects alive to the old generation. A major
+// collection traces and collects the whole heap, and runs instead of a minor one when the old
+// generation has grown enough. gc.collect() runs a major collection.
 
+// The old generation.
 thread_local!(pub static GC_MEM: RefCell<FxHashSet<GcPtr>> = {
     RefCell::new(FxHashSet::default())
 });
 
+// The young generation (nursery): the objects made since the last collection, in the order they
+// were made. Objects cannot move since GcType refers to them by their addresses, so the nursery
+// is a list of them rather than a region, whose end is bumped to add an object.
+thread_local!(static NURSERY: RefCell<Vec<GcPtr>> = RefCell::new(vec![]));
+
+// The old objects which may refer to young objects, remembered by write_barrier().
+thread_local!(static REMEMBERED: RefCell<FxHashSet<GcPtr>> = {
+    RefCell::new(FxHashSet::default())
+});
+
+// Set while a minor collection runs.
+thread_local!(static MINOR: Cell<bool> = Cell::new(false));
+
+// The number of the old objects after the last major collection.
+thread_local!(static OLD_AFTER_MAJOR: Cell<usize> = Cell::new(0));
+
+/// the number of young objects which starts a minor collection.
+pub const NURSERY_LIMIT: usize = 4096;
+
+/// A major collection runs instead of a minor one when the old generation has grown by this
+/// factor since the last major collection.
+const OLD_GROWTH_FACTOR: usize = 2;
+
+// Counted per thread like GC_MEM, so that VMs on other threads do not share the heap limit.
+thread_local!(static ALLOCATED_MEM_SIZE_BYTE: Cell<usize> = Cell::new(0));
+
+// What the objects own besides themselves when the heap was last measured, and the heap checks
+// left until it is measured again. See check_heap_limit().
+thread_local!(static OWNED_BYTES: Cell<usize> = Cell::new(0));
+thread_local!(static CHECKS_UNTIL_MEASURE: Cell<usize> = Cell::new(0));
+
+/// The heap is measured again after a check per this many bytes of it, so that walking it takes
+/// constant time per check on average. An instruction adds a few elements or properties at most,
+/// apart from strings, which are counted at once, so the heap grows by about its size at most
+/// until it is measured again.
+const BYTES_PER_CHECK: usize = 64 * 1024;
+
+// The WeakMaps, WeakSets, WeakRefs and FinalizationRegistries made on this thread and not freed
+// yet, whose entries are removed when their objects are freed.
+thread_local!(static WEAK_TABLES: RefCell<Vec<WeakTableRef>> = RefCell::new(vec![]));
+
+// Counted per thread for the same reason.
+thread_local!(static STATS: RefCell<GcStats> = RefCell::new(GcStats::default()));
+
+/// Statistics of the collections on this thread, shown by gc.stats() (see builtins/gc.rs).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// the number of collections.
+    pub collections: u64,
+    /// the number of minor collections, which are counted in 'collections' as well.
+    pub minor_collections: u64,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+    pub last_pause: Duration,
+    /// bytes freed by all the collections. See allocated_bytes().
+    pub freed_bytes: usize,
+    /// objects freed by all the collections.
+    pub freed_objects: usize,
+}
+
 #[derive(Clone, Debug, Eq)]
 pub struct GcPtr(*mut Gc);
 
@@ -42,18 +117,17 @@ impl Hash for GcPtr {
 ///
 /// ### Usage
 /// ```
-/// # extern crate rustc_hash;
 /// # use rapidus::gc;
-/// # use rapidus::vm::value::{Value, CallObjectRef};
+/// # use rapidus::vm::value::{Value, CallObjectRef, PropMap};
 /// # use rapidus::vm::callobj::CallObject;
-/// # use rustc_hash::FxHashMap;
 /// let callobjectref =
 ///     gc::new(CallObject {
-///         vals: gc::new(FxHashMap::default()),
+///         vals: gc::new(PropMap::new()),
 ///         rest_params: None,
 ///         arguments: vec![],
 ///         this: Box::new(Value::Undefined),
 ///         parent: None,
+///         upvalues: vec![],
 ///     }
 /// );
 ///
@@ -77,6 +151,13 @@ impl<X: Gc> Clone for GcType<X> {
     }
 }
 
+impl<X: Gc> GcType<X> {
+    /// the address of X, which identifies the object.
+    pub fn addr(&self) -> usize {
+        self.inner
+    }
+}
+
 impl<X: Gc> Deref for GcType<X> {
     type Target = X;
 
@@ -114,6 +195,27 @@ impl<X: Gc> Debug for GcType<X> {
 pub trait Gc {
     fn free(&self) -> usize;
     fn trace(&mut self, &mut FxHashSet<GcPtr>);
+    /// bytes taken by the object and what it owns, such as the elements of an array, except
+    /// strings, which are counted by jsstring::string_bytes(). See heap_bytes().
+    fn heap_size(&self) -> usize;
+}
+
+/// bytes owned by 'val' besides itself, except strings and the objects it refers to.
+fn owned_bytes(val: &Value) -> usize {
+    match val {
+        Value::String(s) => mem::size_of_val(&**s),
+        Value::BigInt(n) => mem::size_of_val(&**n) + n.bits() / 8,
+        Value::Object(_, ObjectKind::Function(f)) => mem::size_of_val(&**f),
+        Value::Object(_, ObjectKind::BuiltinFunction(f)) => mem::size_of_val(&**f),
+        Value::Object(_, ObjectKind::Date(date)) => mem::size_of_val(&**date),
+        Value::Object(_, ObjectKind::PrimitiveWrapper(primitive)) => {
+            mem::size_of_val(&**primitive) + owned_bytes(primitive)
+        }
+        Value::Object(_, ObjectKind::TypedArray(ta)) => mem::size_of_val(&**ta),
+        Value::Object(_, ObjectKind::DataView(view)) => mem::size_of_val(&**view),
+        Value::Object(_, ObjectKind::Response(body)) => mem::size_of_val(&**body) + body.capacity(),
+        _ => 0,
+    }
 }
 
 impl Gc for Value {
@@ -122,6 +224,10 @@ impl Gc for Value {
         mem::size_of::<Value>()
     }
 
+    fn heap_size(&self) -> usize {
+        mem::size_of::<Value>() + owned_bytes(self)
+    }
+
     fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
         if !mark(self, marked) {
             return;
@@ -132,42 +238,83 @@ impl Gc for Value {
             | Value::Undefined
             | Value::Bool(_)
             | Value::Number(_)
+            | Value::BigInt(_)
             | Value::String(_) => {}
             Value::Object(map, ObjectKind::Function(box (_, c))) => {
                 map.trace(marked);
                 c.trace(marked);
             }
             // Never trace _xxx
-            Value::Object(_, ObjectKind::BuiltinFunction(box (_, c))) => (*c).trace(marked),
+            Value::Object(map, ObjectKind::BuiltinFunction(box (_, c))) => {
+                // The map is traced so that a builtin function held weakly stays alive.
+                map.trace(marked);
+                (*c).trace(marked)
+            }
 
             Value::Object(map, ObjectKind::Array(a)) => {
                 map.trace(marked);
                 a.trace(marked);
             }
-            Value::Object(map, ObjectKind::Ordinary) | Value::Object(map, ObjectKind::Date(_)) => {
+            Value::Object(map, ObjectKind::Ordinary)
+            | Value::Object(map, ObjectKind::Date(_))
+            | Value::Object(map, ObjectKind::PrimitiveWrapper(_))
+            | Value::Object(map, ObjectKind::Response(_)) => {
                 map.trace(marked);
             }
             Value::Object(map, ObjectKind::Arguments(c)) => {
                 map.trace(marked);
                 c.trace(marked);
             }
+            Value::Object(map, ObjectKind::ArrayBuffer(buffer)) => {
+                map.trace(marked);
+                buffer.trace(marked);
+            }
+            Value::Object(map, ObjectKind::TypedArray(box ta)) => {
+                map.trace(marked);
+                ta.buffer.trace(marked);
+            }
+            Value::Object(map, ObjectKind::DataView(box view)) => {
+                map.trace(marked);
+                view.buffer.trace(marked);
+            }
+            Value::Object(map, ObjectKind::WeakTable(table)) => {
+                map.trace(marked);
+                table.trace(marked);
+            }
         }
     }
 }
 
-impl Gc for FxHashMap<String, Property> {
+impl Gc for PropMap {
     fn free(&self) -> usize {
         mem::drop(self);
-        mem::size_of::<FxHashMap<String, Property>>()
+        mem::size_of::<PropMap>()
+    }
+
+    fn heap_size(&self) -> usize {
+        let mut size = mem::size_of::<PropMap>() + owned_bytes(&self.proto());
+        for (name, prop) in self.iter() {
+            // The name is kept twice, with the property and in the index of the names.
+            size += mem::size_of::<(String, Property, String, usize)>() + name.capacity() * 2;
+            size += owned_bytes(&prop.val);
+            if prop.accessor.is_some() {
+                size += mem::size_of::<Accessor>();
+            }
+        }
+        size
     }
 
     fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
         if !mark(self, marked) {
             return;
         };
-        let map = self;
-        for (_, prop) in map {
+        self.proto_mut().trace(marked);
+        for prop in self.properties_mut() {
             prop.val.trace(marked);
+            if let Some(ref mut accessor) = prop.accessor {
+                accessor.get.trace(marked);
+                accessor.set.trace(marked);
+            }
         }
     }
 }
@@ -178,6 +325,17 @@ impl Gc for CallObject {
         mem::size_of::<CallObject>()
     }
 
+    fn heap_size(&self) -> usize {
+        let mut size = mem::size_of::<CallObject>() + mem::size_of::<Value>();
+        size += owned_bytes(&self.this);
+        size += self.rest_params.as_ref().map_or(0, |name| name.capacity());
+        size += self.arguments.capacity() * mem::size_of::<(Option<String>, Value)>();
+        for (name, val) in &self.arguments {
+            size += name.as_ref().map_or(0, |name| name.capacity()) + owned_bytes(val);
+        }
+        size + self.upvalues.capacity() * mem::size_of::<UpvalueRef>()
+    }
+
     fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
         if !mark(self, marked) {
             return;
@@ -187,37 +345,113 @@ impl Gc for CallObject {
         for (_, val) in &mut self.arguments {
             val.trace(marked);
         }
-        /*
-        if let Some(ref mut parent) = *self.parent {
+        // A closure may be the only one which refers to the scope it was made in.
+        if let Some(ref mut parent) = self.parent {
             parent.trace(marked);
         }
-        */
+        for upvalue in &mut self.upvalues {
+            upvalue.trace(marked);
+        }
         self.this.trace(marked);
     }
 }
 
+impl Gc for Upvalue {
+    fn free(&self) -> usize {
+        mem::drop(self);
+        mem::size_of::<Upvalue>()
+    }
+
+    fn heap_size(&self) -> usize {
+        mem::size_of::<Upvalue>()
+            + match *self {
+                Upvalue::Closed(ref val) => owned_bytes(val),
+                Upvalue::Open(_) => 0,
+            }
+    }
+
+    /// An open upvalue refers to a register, which is traced as a root.
+    fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        if !mark(self, marked) {
+            return;
+        };
+        if let Upvalue::Closed(ref mut val) = *self {
+            val.trace(marked);
+        }
+    }
+}
+
 impl Gc for ArrayValue {
     fn free(&self) -> usize {
         mem::drop(self);
         mem::size_of::<ArrayValue>()
     }
 
+    fn heap_size(&self) -> usize {
+        let mut size = mem::size_of::<ArrayValue>();
+        size += self.elems.capacity() * mem::size_of::<Property>();
+        size += self.sparse.len() * mem::size_of::<(usize, Property)>();
+        for prop in self.elems.iter().chain(self.sparse.values()) {
+            size += owned_bytes(&prop.val);
+        }
+        size
+    }
+
     fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
         if !mark(self, marked) {
             return;
         };
-        for prop in &mut self.elems {
+        for prop in self.elems.iter_mut().chain(self.sparse.values_mut()) {
             prop.val.trace(marked)
         }
     }
 }
 
+impl Gc for ArrayBuffer {
+    fn free(&self) -> usize {
+        mem::drop(self);
+        mem::size_of::<ArrayBuffer>()
+    }
+
+    fn heap_size(&self) -> usize {
+        mem::size_of::<ArrayBuffer>() + self.data.capacity()
+    }
+
+    fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        mark(self, marked);
+    }
+}
+
+impl Gc for WeakTable {
+    fn free(&self) -> usize {
+        mem::drop(self);
+        mem::size_of::<WeakTable>()
+    }
+
+    fn heap_size(&self) -> usize {
+        mem::size_of::<WeakTable>()
+            + self.entries.len() * mem::size_of::<(usize, Value)>()
+            + self.registrations.capacity() * mem::size_of::<Registration>()
+    }
+
+    /// The entries are traced by trace_weak_tables() instead, only while their objects are alive.
+    fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        if !mark(self, marked) {
+            return;
+        };
+        self.cleanup.trace(marked);
+        for registration in &mut self.registrations {
+            registration.held.trace(marked);
+        }
+    }
+}
+
 pub fn new<X: Gc + 'static>(data: X) -> GcType<X> {
     let data_size = mem::size_of_val(&data);
     // get a raw pointer which points to the data copied on the heap.
     let ptr = Box::into_raw(Box::new(data));
-    let _prev_size = ALLOCATED_MEM_SIZE_BYTE.fetch_add(data_size, atomic::Ordering::SeqCst);
-    GC_MEM.with(|m| m.borrow_mut().insert(GcPtr(ptr)));
+    ALLOCATED_MEM_SIZE_BYTE.with(|size| size.set(size.get() + data_size));
+    NURSERY.with(|nursery| nursery.borrow_mut().push(GcPtr(ptr)));
     GcType {
         inner: ptr as usize,
         //gc_mark: false,
@@ -225,30 +459,169 @@ pub fn new<X: Gc + 'static>(data: X) -> GcType<X> {
     }
 }
 
-pub fn mark_and_sweep(vm: &mut VM) {
-    fn over16kb_allocated() -> bool {
-        //ALLOCATED_MEM_SIZE_BYTE.load(atomic::Ordering::SeqCst) > 16 * 1024
-        true
+/// Write barrier, called when a reference to an object may be stored into 'obj'. An old 'obj' is
+/// remembered, since the object stored may be young.
+pub fn write_barrier<X: Gc + 'static>(obj: &mut X) {
+    let ptr = GcPtr(obj as *mut X as *mut Gc);
+    if GC_MEM.with(|mem| mem.borrow().contains(&ptr)) {
+        REMEMBERED.with(|remembered| remembered.borrow_mut().insert(ptr));
     }
+}
 
-    if vm.gc_on && over16kb_allocated() {
-        let _sw = Stopwatch::start_new();
-        let mut marked = FxHashSet::default();
-        let pre_alloc_size = ALLOCATED_MEM_SIZE_BYTE.load(atomic::Ordering::SeqCst);
-        let pre_gc_size = GC_MEM.with(|mem| mem.borrow_mut().len());
+/// Called where objects are made. Runs a minor collection if the nursery is full, or a major
+/// collection instead if the old generation has grown enough since the last one.
+pub fn collect_if_needed(vm: &mut VM) {
+    if NURSERY.with(|nursery| nursery.borrow().len()) < NURSERY_LIMIT {
+        return;
+    }
+    let old_count = GC_MEM.with(|mem| mem.borrow().len());
+    let threshold = OLD_AFTER_MAJOR.with(|count| count.get()).max(NURSERY_LIMIT);
+    collect(vm, old_count < threshold * OLD_GROWTH_FACTOR);
+}
+
+/// Run a major collection now.
+pub fn mark_and_sweep(vm: &mut VM) {
+    collect(vm, false)
+}
+
+fn collect(vm: &mut VM, minor: bool) {
+    if !vm.gc_on {
+        return;
+    }
+    let _sw = Stopwatch::start_new();
+    let start = Instant::now();
+    let mut marked = FxHashSet::default();
+    let pre_alloc_size = allocated_bytes();
+    let pre_gc_size = object_count();
+    if minor {
+        MINOR.with(|m| m.set(true));
         trace(vm, &mut marked);
+        trace_remembered(&mut marked);
+        trace_weak_tables(&mut marked);
+        sweep_weak_tables(vm, &marked);
+        free_young(&marked);
+        MINOR.with(|m| m.set(false));
+    } else {
+        promote_all();
+        trace(vm, &mut marked);
+        trace_weak_tables(&mut marked);
+        sweep_weak_tables(vm, &marked);
         free(&marked);
-        if vm.is_debug {
-            println!(
-                "GC executed: pause duration {} ms. {} -> {} bytes. {} => {} objects",
-                _sw.elapsed_ms(),
-                pre_alloc_size,
-                ALLOCATED_MEM_SIZE_BYTE.load(atomic::Ordering::SeqCst),
-                pre_gc_size,
-                GC_MEM.with(|mem| mem.borrow_mut().len()),
-            );
+        OLD_AFTER_MAJOR.with(|count| count.set(GC_MEM.with(|mem| mem.borrow().len())));
+    }
+    // No old objects refer to young ones now, since there are none.
+    REMEMBERED.with(|remembered| remembered.borrow_mut().clear());
+
+    let pause = start.elapsed();
+    let stats = STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        stats.collections += 1;
+        if minor {
+            stats.minor_collections += 1;
         }
+        stats.total_pause += pause;
+        stats.max_pause = stats.max_pause.max(pause);
+        stats.last_pause = pause;
+        stats.freed_bytes += pre_alloc_size - allocated_bytes();
+        stats.freed_objects += pre_gc_size - object_count();
+        stats.clone()
+    });
+    if vm.trace_gc {
+        eprintln!(
+            "[gc] #{} {}: {} -> {} bytes, {} -> {} objects, {:.3} ms",
+            stats.collections,
+            if minor { "minor" } else { "major" },
+            pre_alloc_size,
+            allocated_bytes(),
+            pre_gc_size,
+            object_count(),
+            duration_ms(pause),
+        );
+    }
+    if vm.is_debug {
+        println!(
+            "GC executed: pause duration {} ms. {} -> {} bytes. {} => {} objects",
+            _sw.elapsed_ms(),
+            pre_alloc_size,
+            allocated_bytes(),
+            pre_gc_size,
+            object_count(),
+        );
+    }
+}
+
+/// Make the GC remove the entries of 'table' when their objects are freed.
+pub fn register_weak_table(table: WeakTableRef) {
+    WEAK_TABLES.with(|tables| tables.borrow_mut().push(table));
+}
+
+/// bytes taken by the objects made by gc::new() and not freed yet. Only the objects themselves
+/// are counted, not what they own such as the elements of arrays. See heap_bytes().
+pub fn allocated_bytes() -> usize {
+    ALLOCATED_MEM_SIZE_BYTE.with(|size| size.get())
+}
+
+/// bytes taken by the objects not freed yet together with what they own, such as the elements
+/// of arrays, the properties of objects and strings. This walks the whole heap.
+pub fn heap_bytes() -> usize {
+    let size_of = |p: &GcPtr| unsafe { (*p.0).heap_size() };
+    GC_MEM.with(|mem| mem.borrow().iter().map(size_of).sum::<usize>())
+        + NURSERY.with(|nursery| nursery.borrow().iter().map(size_of).sum::<usize>())
+        + string_bytes()
+}
+
+/// the number of the objects made by gc::new() and not freed yet.
+pub fn object_count() -> usize {
+    GC_MEM.with(|mem| mem.borrow().len()) + NURSERY.with(|nursery| nursery.borrow().len())
+}
+
+pub fn stats() -> GcStats {
+    STATS.with(|stats| stats.borrow().clone())
+}
+
+/// 'duration' in milliseconds.
+pub fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/// Throw a RangeError if the heap takes more than vm.options.max_heap_bytes after GC (see
+/// heap_bytes()). The heap is walked once in a number of checks in proportion to its size. In
+/// between, the objects made since and strings are counted as they are, and what the objects
+/// own as it was when the heap was walked.
+pub fn check_heap_limit(vm: &mut VM) -> std::result::Result<(), RuntimeError> {
+    let limit = match vm.options.max_heap_bytes {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let due = CHECKS_UNTIL_MEASURE.with(|checks| {
+        let left = checks.get();
+        checks.set(left.saturating_sub(1));
+        left == 0
+    });
+    let estimate = allocated_bytes() + string_bytes() + OWNED_BYTES.with(|bytes| bytes.get());
+    if !due && estimate <= limit {
+        return Ok(());
     }
+    if measure_heap() <= limit {
+        return Ok(());
+    }
+    mark_and_sweep(vm);
+    if measure_heap() <= limit {
+        return Ok(());
+    }
+    Err(RuntimeError::Exception(new_error(format!(
+        "RangeError: Out of memory: the heap limit of {} bytes is exceeded",
+        limit
+    ))))
+}
+
+/// heap_bytes(), remembering what the objects own for check_heap_limit().
+fn measure_heap() -> usize {
+    let size = heap_bytes();
+    let owned = size - allocated_bytes() - string_bytes();
+    OWNED_BYTES.with(|bytes| bytes.set(owned));
+    CHECKS_UNTIL_MEASURE.with(|checks| checks.set(size / BYTES_PER_CHECK));
+    size
 }
 
 fn trace(vm: &mut VM, marked: &mut FxHashSet<GcPtr>) {
@@ -259,10 +632,43 @@ fn trace(vm: &mut VM, marked: &mut FxHashSet<GcPtr>) {
     for val in &mut vm.state.stack {
         val.trace(marked);
     }
+    for val in &mut vm.state.regs {
+        val.trace(marked);
+    }
     //let after_stack = marked.len();
     for scope in &mut vm.state.scope {
         scope.trace(marked);
     }
+    for frame in &mut vm.state.frames {
+        if let Some(ref mut new_this) = frame.new_this {
+            new_this.trace(marked);
+        }
+    }
+    for inline_cache in &mut vm.inline_caches {
+        inline_cache.trace(marked);
+    }
+    for global_cache in &mut vm.global_caches {
+        global_cache.trace(marked);
+    }
+    for val in &mut vm.inspected_values {
+        val.trace(marked);
+    }
+    for callobj in &mut vm.callobj_pool {
+        callobj.trace(marked);
+    }
+    for callobj in &mut vm.builtin_callobjs {
+        callobj.trace(marked);
+    }
+    for val in &mut vm.builtin_roots {
+        val.trace(marked);
+    }
+    for upvalue in &mut vm.open_upvalues {
+        upvalue.trace(marked);
+    }
+    for promise in &mut vm.unhandled_rejections {
+        promise.trace(marked);
+    }
+    vm.task_mgr.trace(marked);
     //let after_scope = marked.len();
     /*
     println!(
@@ -272,6 +678,110 @@ fn trace(vm: &mut VM, marked: &mut FxHashSet<GcPtr>) {
     */
 }
 
+/// Trace the values of the entries of the weak tables alive whose objects are alive. This is
+/// repeated until nothing more is marked, since a value may keep the object of another entry
+/// alive.
+fn trace_weak_tables(marked: &mut FxHashSet<GcPtr>) {
+    WEAK_TABLES.with(|tables| {
+        let mut tables = tables.borrow_mut();
+        loop {
+            let marked_count = marked.len();
+            for table in tables.iter_mut() {
+                if !is_marked::<WeakTable>(table.addr(), marked) {
+                    continue;
+                }
+                for (addr, val) in table.entries.iter_mut() {
+                    if is_marked::<PropMap>(*addr, marked) {
+                        val.trace(marked);
+                    }
+                }
+            }
+            if marked.len() == marked_count {
+                break;
+            }
+        }
+    });
+}
+
+/// Remove the entries of the objects which are about to be freed, and the weak tables which are
+/// about to be freed. The cleanup callbacks of the FinalizationRegistries are called with the
+/// held values of the objects by the event loop.
+fn sweep_weak_tables(vm: &mut VM, marked: &FxHashSet<GcPtr>) {
+    let mut cleanups = vec![];
+    WEAK_TABLES.with(|tables| {
+        tables.borrow_mut().retain(|table| {
+            if !is_marked::<WeakTable>(table.addr(), marked) {
+                return false;
+            }
+            let mut table = table.clone();
+            table
+                .entries
+                .retain(|addr, _| is_marked::<PropMap>(*addr, marked));
+            let cleanup = table.cleanup.clone();
+            table.registrations.retain(|registration| {
+                let alive = is_marked::<PropMap>(registration.target, marked);
+                if !alive {
+                    cleanups.push((cleanup.clone(), registration.held.clone()));
+                }
+                alive
+            });
+            true
+        })
+    });
+    for (cleanup, held) in cleanups {
+        vm.task_mgr.add_timer(Task::Timer {
+            kind: TimerKind::Timeout {
+                now: Utc::now().timestamp_millis(),
+                timeout: 0,
+            },
+            id: 0,
+            callback: cleanup,
+            args: vec![held],
+        });
+    }
+}
+
+/// true if the object of type X at 'addr' has been marked, or is old during a minor collection.
+fn is_marked<X: Gc + 'static>(addr: usize, marked: &FxHashSet<GcPtr>) -> bool {
+    let ptr = GcPtr(addr as *mut X as *mut Gc);
+    marked.contains(&ptr)
+        || MINOR.with(|minor| minor.get()) && GC_MEM.with(|mem| mem.borrow().contains(&ptr))
+}
+
+/// Trace the remembered objects, through which old objects may refer to young ones, in a minor
+/// collection.
+fn trace_remembered(marked: &mut FxHashSet<GcPtr>) {
+    let remembered: Vec<GcPtr> =
+        REMEMBERED.with(|remembered| remembered.borrow().iter().cloned().collect());
+    for p in remembered {
+        unsafe { (*p.0).trace(marked) }
+    }
+}
+
+/// Free the young objects which are not marked, and promote the others to the old generation.
+fn free_young(marked: &FxHashSet<GcPtr>) {
+    let nursery = NURSERY.with(|nursery| mem::replace(&mut *nursery.borrow_mut(), vec![]));
+    GC_MEM.with(|mem| {
+        let mut mem = mem.borrow_mut();
+        for p in nursery {
+            if marked.contains(&p) {
+                mem.insert(p);
+            } else {
+                unsafe {
+                    let released_size = (*p.0).free();
+                    ALLOCATED_MEM_SIZE_BYTE.with(|size| size.set(size.get() - released_size));
+                }
+            }
+        }
+    });
+}
+
+/// Promote all the young objects to the old generation before a major collection.
+fn promote_all() {
+    let nursery = NURSERY.with(|nursery| mem::replace(&mut *nursery.borrow_mut(), vec![]));
+    GC_MEM.with(|mem| mem.borrow_mut().extend(nursery));
+}
+
 fn free(marked: &FxHashSet<GcPtr>) {
     GC_MEM.with(|mem| {
         mem.borrow_mut().retain(|p| {
@@ -283,7 +793,7 @@ fn free(marked: &FxHashSet<GcPtr>) {
                     //let released_size = mem::size_of_val(&*b);
                     //Box::leak(b);
                     let released_size = (*p.0).free();
-                    ALLOCATED_MEM_SIZE_BYTE.fetch_sub(released_size, atomic::Ordering::SeqCst);
+                    ALLOCATED_MEM_SIZE_BYTE.with(|size| size.set(size.get() - released_size));
                 }
             }
             is_marked
@@ -292,11 +802,14 @@ fn free(marked: &FxHashSet<GcPtr>) {
 }
 
 pub fn free_all() {
+    WEAK_TABLES.with(|tables| tables.borrow_mut().clear());
+    REMEMBERED.with(|remembered| remembered.borrow_mut().clear());
+    promote_all();
     GC_MEM.with(|mem| {
         mem.borrow_mut().retain(|p| {
             unsafe {
                 let released_size = (*p.0).free();
-                ALLOCATED_MEM_SIZE_BYTE.fetch_sub(released_size, atomic::Ordering::SeqCst);
+                ALLOCATED_MEM_SIZE_BYTE.with(|size| size.set(size.get() - released_size));
             }
             false
         });
@@ -304,12 +817,23 @@ pub fn free_all() {
     /*
     println!(
         "\nallocated_size: {} all:{}",
-        ALLOCATED_MEM_SIZE_BYTE.load(atomic::Ordering::SeqCst),
+        allocated_bytes(),
         GC_MEM.with(|mem| mem.borrow_mut().len()),
     );
     */
 }
 
+/// Mark the object at 'p'. false if it has been marked, or is an old object not remembered during
+/// a minor collection, so that what it refers to is not traced.
 fn mark(p: *mut Gc, marked: &mut FxHashSet<GcPtr>) -> bool {
-    marked.insert(GcPtr(p))
+    let ptr = GcPtr(p);
+    if MINOR.with(|minor| minor.get()) && is_old(&ptr) {
+        return false;
+    }
+    marked.insert(ptr)
+}
+
+fn is_old(ptr: &GcPtr) -> bool {
+    GC_MEM.with(|mem| mem.borrow().contains(ptr))
+        && !REMEMBERED.with(|remembered| remembered.borrow().contains(ptr))
 }
@@ -1,21 +1,29 @@
 use builtin::{BuiltinFuncInfo, BuiltinJITFuncInfo, BuiltinJITFuncTy};
-use builtins::math;
-use bytecode_gen::{ByteCode, VMInst};
+use bytecode_gen::{ByteCode, Inst, VMInst};
 use id::Id;
 use libc;
 use llvm;
 use llvm::core::*;
 use llvm::prelude::*;
-use rand::{random, thread_rng, RngCore};
+use rand::random;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::ffi::CString;
 use std::mem::transmute;
 use std::ptr;
+use std::rc::Rc;
 use vm;
 use vm::value::*;
 
 const MAX_FUNCTION_PARAMS: usize = 3;
 
+/// A function is compiled after it is called this many times with numeric arguments.
+pub const FUNCTION_JIT_THRESHOLD: usize = 5;
+
+/// A loop is compiled after it is entered this many times. The compiled loop is entered
+/// in the middle of the function (on-stack replacement) and returns to the interpreter
+/// at the pc after the loop.
+pub const LOOP_JIT_THRESHOLD: usize = 7;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ValueType {
     Number,
@@ -47,23 +55,6 @@ fn get_value_type(val: &vm::value::Value) -> Option<ValueType> {
     }
 }
 
-macro_rules! get_int8 {
-    ($iseq:ident, $pc:ident, $var:ident, $ty:ty) => {
-        let $var = $iseq[$pc as usize] as $ty;
-        $pc += 1;
-    };
-}
-
-macro_rules! get_int32 {
-    ($iseq:ident, $pc:ident, $var:ident, $ty:ty) => {
-        let $var = (($iseq[$pc as usize + 3] as $ty) << 24)
-            + (($iseq[$pc as usize + 2] as $ty) << 16)
-            + (($iseq[$pc as usize + 1] as $ty) << 8)
-            + ($iseq[$pc as usize + 0] as $ty);
-        $pc += 4;
-    };
-}
-
 macro_rules! try_opt {
     ($e:expr) => {
         match $e {
@@ -150,12 +141,12 @@ pub struct TracingJit {
     pub builder: LLVMBuilderRef,
     pub pass_manager: LLVMPassManagerRef,
     pub used_builtin_funcs: FxHashSet<(BuiltinJITFuncTy, LLVMValueRef)>,
+    /// C strings of the string constants in compiled code, which must live as long as the code.
+    pub string_constants: Vec<Rc<CString>>,
 }
 
 impl TracingJit {
     pub unsafe fn new() -> TracingJit {
-        math::MATH_RAND_SEED = thread_rng().next_u64();
-
         llvm::target::LLVM_InitializeNativeTarget();
         llvm::target::LLVM_InitializeNativeAsmPrinter();
         llvm::target::LLVM_InitializeNativeAsmParser();
@@ -185,6 +176,7 @@ impl TracingJit {
             pass_manager: pm,
             cur_func: None,
             used_builtin_funcs: FxHashSet::default(),
+            string_constants: vec![],
         }
     }
 }
@@ -499,7 +491,7 @@ impl TracingJit {
 
         let arg_0 = LLVMGetParam(func, 0);
         for i in 0..local_vars.len() {
-            let local_var_name = const_table.string[local_vars[i].0].clone();
+            let local_var_name = const_table.string[local_vars[i].0].to_string();
             let local_var_ref = LLVMBuildGEP(
                 self.builder,
                 arg_0,
@@ -617,12 +609,15 @@ impl TracingJit {
         let local_scope = &*vm_state.scope.last().unwrap();
 
         while pc < end {
-            let inst_size = try_opt!(VMInst::get_inst_size(iseq[pc]));
-            match iseq[pc] {
-                VMInst::DECL_VAR | VMInst::SET_VALUE | VMInst::GET_VALUE => {
-                    pc += 1;
-                    get_int32!(iseq, pc, id, usize);
-                    let name = &const_table.string[id];
+            let inst = try_opt!(Inst::decode(&iseq[pc..]));
+            pc += inst.size();
+            match inst {
+                Inst::DeclVar { name: id }
+                | Inst::SetValue { name: id }
+                | Inst::GetValue { name: id }
+                | Inst::GetGlobal { name: id, .. } => {
+                    let id = id as usize;
+                    let name = const_table.string[id].as_str();
                     if let Some(prop) = local_scope.vals.get(name) {
                         let ty = if let Some(ty) = get_value_type(&prop.val) {
                             ty
@@ -632,7 +627,7 @@ impl TracingJit {
                         local_vars.insert((id, ty));
                     }
                 }
-                _ => pc += inst_size,
+                _ => {}
             }
         }
 
@@ -776,13 +771,12 @@ impl TracingJit {
         {
             let mut pc = bgn;
             while pc < end {
-                let inst_size = try_opt!(VMInst::get_inst_size(iseq[pc]));
-                match iseq[pc] {
-                    VMInst::END => break,
-                    VMInst::CREATE_CONTEXT if is_func_jit => break,
-                    VMInst::JMP | VMInst::JMP_IF_FALSE => {
-                        pc += 1;
-                        get_int32!(iseq, pc, dst, i32);
+                let inst = try_opt!(Inst::decode(&iseq[pc..]));
+                pc += inst.size();
+                match inst {
+                    Inst::End => break,
+                    Inst::CreateContext if is_func_jit => break,
+                    Inst::Jmp { offset: dst } | Inst::JmpIfFalse { offset: dst } => {
                         // println!("pc: {}, dst: {}, = {}", pc, dst, pc as i32 + dst);
                         labels.insert(
                             (pc as i32 + dst) as usize,
@@ -792,7 +786,7 @@ impl TracingJit {
                             )),
                         );
                     }
-                    _ => pc += inst_size,
+                    _ => {}
                 }
             }
         }
@@ -816,14 +810,14 @@ impl TracingJit {
                 *label_kind = LabelKind::Positioned(bb);
             }
 
-            match iseq[pc] {
-                VMInst::END => break,
-                VMInst::CREATE_CONTEXT => break,
-                VMInst::LOOP_START => pc += 5,
-                VMInst::CONSTRUCT | VMInst::CREATE_OBJECT | VMInst::CREATE_ARRAY => pc += 5,
-                VMInst::JMP_IF_FALSE => {
-                    pc += 1;
-                    get_int32!(iseq, pc, dst, i32);
+            let inst = try_opt!(Inst::decode(&iseq[pc..]));
+            pc += inst.size();
+            match inst {
+                Inst::End => break,
+                Inst::CreateContext => break,
+                Inst::LoopStart { .. } => {}
+                Inst::Construct { .. } | Inst::CreateObject { .. } | Inst::CreateArray { .. } => {}
+                Inst::JmpIfFalse { offset: dst } => {
                     let bb_then = LLVMAppendBasicBlock(func, CString::new("").unwrap().as_ptr());
                     let bb_else =
                         label_retrieve(try_opt!(labels.get(&((pc as i32 + dst) as usize))));
@@ -836,9 +830,7 @@ impl TracingJit {
                     LLVMBuildCondBr(self.builder, cond_val, bb_then, bb_else);
                     LLVMPositionBuilderAtEnd(self.builder, bb_then);
                 }
-                VMInst::JMP => {
-                    pc += 1;
-                    get_int32!(iseq, pc, dst, i32);
+                Inst::Jmp { offset: dst } => {
                     let bb = label_retrieve(try_opt!(labels.get(&((pc as i32 + dst) as usize))));
                     if cur_bb_has_no_terminator(self.builder) {
                         LLVMBuildBr(self.builder, bb);
@@ -847,8 +839,7 @@ impl TracingJit {
                     let bb = LLVMAppendBasicBlock(func, CString::new("").unwrap().as_ptr());
                     LLVMPositionBuilderAtEnd(self.builder, bb);
                 }
-                VMInst::COND_OP => {
-                    pc += 1;
+                Inst::CondOp => {
                     let else_val = try_stack!(stack.pop());
                     let then_val = try_stack!(stack.pop());
                     let phi = LLVMBuildPhi(
@@ -876,8 +867,7 @@ impl TracingJit {
 
                     stack.push((phi, None));
                 }
-                VMInst::LAND => {
-                    pc += 1;
+                Inst::LAnd => {
                     let phi = LLVMBuildPhi(
                         self.builder,
                         LLVMInt1TypeInContext(self.context),
@@ -905,8 +895,7 @@ impl TracingJit {
 
                     stack.push((phi, None));
                 }
-                VMInst::LOR => {
-                    pc += 1;
+                Inst::LOr => {
                     let phi = LLVMBuildPhi(
                         self.builder,
                         LLVMInt1TypeInContext(self.context),
@@ -934,8 +923,7 @@ impl TracingJit {
 
                     stack.push((phi, None));
                 }
-                VMInst::ADD => {
-                    pc += 1;
+                Inst::Add => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -948,8 +936,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::SUB => {
-                    pc += 1;
+                Inst::Sub => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -962,8 +949,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::MUL => {
-                    pc += 1;
+                Inst::Mul => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -976,8 +962,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::DIV => {
-                    pc += 1;
+                Inst::Div => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -990,37 +975,20 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::REM => {
-                    pc += 1;
+                Inst::Rem => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
-                        LLVMBuildSIToFP(
+                        LLVMBuildFRem(
                             self.builder,
-                            LLVMBuildSRem(
-                                self.builder,
-                                LLVMBuildFPToSI(
-                                    self.builder,
-                                    lhs,
-                                    LLVMInt64TypeInContext(self.context),
-                                    CString::new("").unwrap().as_ptr(),
-                                ),
-                                LLVMBuildFPToSI(
-                                    self.builder,
-                                    rhs,
-                                    LLVMInt64TypeInContext(self.context),
-                                    CString::new("").unwrap().as_ptr(),
-                                ),
-                                CString::new("frem").unwrap().as_ptr(),
-                            ),
-                            LLVMDoubleTypeInContext(self.context),
-                            CString::new("").unwrap().as_ptr(),
+                            lhs,
+                            rhs,
+                            CString::new("frem").unwrap().as_ptr(),
                         ),
                         None,
                     ));
                 }
-                VMInst::LT => {
-                    pc += 1;
+                Inst::Lt => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1034,8 +1002,7 @@ impl TracingJit {
                         None,
                     ))
                 }
-                VMInst::LE => {
-                    pc += 1;
+                Inst::Le => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1049,8 +1016,7 @@ impl TracingJit {
                         None,
                     ))
                 }
-                VMInst::GT => {
-                    pc += 1;
+                Inst::Gt => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1064,8 +1030,7 @@ impl TracingJit {
                         None,
                     ))
                 }
-                VMInst::GE => {
-                    pc += 1;
+                Inst::Ge => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1079,8 +1044,7 @@ impl TracingJit {
                         None,
                     ))
                 }
-                VMInst::EQ => {
-                    pc += 1;
+                Inst::Eq => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1094,8 +1058,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::NE => {
-                    pc += 1;
+                Inst::Ne => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1109,8 +1072,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::SEQ => {
-                    pc += 1;
+                Inst::SEq => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1124,8 +1086,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::SNE => {
-                    pc += 1;
+                Inst::SNe => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1139,16 +1100,14 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::NEG => {
-                    pc += 1;
+                Inst::Neg => {
                     let val = try_stack!(stack.pop());
                     stack.push((
                         LLVMBuildFNeg(self.builder, val, CString::new("fneg").unwrap().as_ptr()),
                         None,
                     ));
                 }
-                VMInst::AND => {
-                    pc += 1;
+                Inst::And => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1176,8 +1135,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::OR => {
-                    pc += 1;
+                Inst::Or => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1205,8 +1163,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::XOR => {
-                    pc += 1;
+                Inst::Xor => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1234,8 +1191,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::SHL => {
-                    pc += 1;
+                Inst::Shl => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1273,8 +1229,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::SHR => {
-                    pc += 1;
+                Inst::Shr => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1312,8 +1267,7 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::ZFSHR => {
-                    pc += 1;
+                Inst::ZFShr => {
                     let rhs = try_stack!(stack.pop());
                     let lhs = try_stack!(stack.pop());
                     stack.push((
@@ -1351,10 +1305,8 @@ impl TracingJit {
                         None,
                     ));
                 }
-                VMInst::GET_VALUE => {
-                    pc += 1;
-                    get_int32!(iseq, pc, id, usize);
-                    let name = &const_table.string[id];
+                Inst::GetValue { name: id } | Inst::GetGlobal { name: id, .. } => {
+                    let name = const_table.string[id as usize].as_str();
                     match env.get(name) {
                         Some(val) => {
                             stack.push((
@@ -1396,20 +1348,42 @@ impl TracingJit {
                         },
                     }
                 }
-                VMInst::SET_VALUE => {
-                    pc += 1;
-                    get_int32!(iseq, pc, id, usize);
-                    let name = &const_table.string[id];
+                Inst::SetValue { name: id } => {
+                    let name = const_table.string[id as usize].as_str();
                     let val = try_stack!(stack.pop());
-                    LLVMBuildStore(self.builder, val, self.declare_local_var(name.clone(), env));
+                    LLVMBuildStore(
+                        self.builder,
+                        val,
+                        self.declare_local_var(name.to_string(), env),
+                    );
                 }
-                VMInst::DECL_VAR => pc += 5,
-                VMInst::UPDATE_PARENT_SCOPE => pc += 1,
-                VMInst::CALL => {
-                    pc += 1;
-                    get_int32!(iseq, pc, argc, usize);
+                // Local variables in slots (see resolver.rs) are local variables of the LLVM
+                // function. A loop cannot reach the registers of the VM.
+                Inst::PushReg { r } if is_func_jit => {
+                    let var = self.declare_local_var(register_name(r as usize), env);
+                    stack.push((
+                        LLVMBuildLoad(self.builder, var, CString::new("").unwrap().as_ptr()),
+                        None,
+                    ));
+                }
+                Inst::PopReg { r } if is_func_jit => {
+                    let val = try_stack!(stack.pop());
+                    LLVMBuildStore(
+                        self.builder,
+                        val,
+                        self.declare_local_var(register_name(r as usize), env),
+                    );
+                }
+                Inst::DeclVar { .. } => {}
+                Inst::UpdateParentScope | Inst::CreateClosure => {}
+                Inst::Call { argc } | Inst::TailCall { argc } => {
+                    let is_tail_call = inst.opcode() == VMInst::TAIL_CALL;
+                    if is_tail_call && !is_func_jit {
+                        return Err(());
+                    }
 
                     let callee = try_opt!(stack.pop());
+                    try_opt!(stack.pop()); // 'this', which the compiled functions do not use
 
                     if let Some(callee) = callee.1 {
                         let mut args = vec![];
@@ -1446,9 +1420,13 @@ impl TracingJit {
                             None,
                         ));
                     }
+                    // Same as CALL followed by RETURN.
+                    if is_tail_call {
+                        let val = try_stack!(stack.pop());
+                        LLVMBuildRet(self.builder, val);
+                    }
                 }
-                VMInst::GET_MEMBER => {
-                    pc += 1; // get_member
+                Inst::GetMember { .. } => {
                     let member = try_opt!(try_opt!(stack.pop()).1);
                     let parent = try_opt!(try_opt!(stack.pop()).1);
                     match parent {
@@ -1459,9 +1437,8 @@ impl TracingJit {
                         _ => return Err(()),
                     }
                 }
-                VMInst::PUSH_CONST => {
-                    pc += 1;
-                    get_int32!(iseq, pc, n, usize);
+                Inst::PushConst { id } => {
+                    let n = id as usize;
                     match const_table.value[n] {
                         Value::Bool(false) => stack.push((
                             LLVMConstInt(LLVMInt1TypeInContext(self.context), 0, 0),
@@ -1491,19 +1468,24 @@ impl TracingJit {
                             },
                             None,
                         )),
-                        Value::String(ref s) => stack.push((
-                            LLVMBuildIntToPtr(
-                                self.builder,
-                                LLVMConstInt(
-                                    LLVMInt64TypeInContext(self.context),
-                                    s.as_ptr() as u64,
-                                    0,
+                        Value::String(ref s) => {
+                            // A string with NUL cannot be passed as a C string.
+                            let s = Rc::new(CString::new(s.to_string()).map_err(|_| ())?);
+                            self.string_constants.push(s.clone());
+                            stack.push((
+                                LLVMBuildIntToPtr(
+                                    self.builder,
+                                    LLVMConstInt(
+                                        LLVMInt64TypeInContext(self.context),
+                                        s.as_ptr() as u64,
+                                        0,
+                                    ),
+                                    LLVMPointerType(LLVMInt8TypeInContext(self.context), 0),
+                                    CString::new("").unwrap().as_ptr(),
                                 ),
-                                LLVMPointerType(LLVMInt8TypeInContext(self.context), 0),
-                                CString::new("").unwrap().as_ptr(),
-                            ),
-                            Some(const_table.value[n].clone()),
-                        )),
+                                Some(const_table.value[n].clone()),
+                            ))
+                        }
                         Value::Object(_, ObjectKind::Ordinary) => {
                             stack.push((ptr::null_mut(), Some(const_table.value[n].clone())))
                         }
@@ -1519,48 +1501,60 @@ impl TracingJit {
                         _ => return Err(()),
                     }
                 }
-                VMInst::PUSH_INT8 => {
-                    pc += 1;
-                    get_int8!(iseq, pc, n, isize);
+                Inst::PushInt8 { n } => {
                     stack.push((
                         LLVMConstReal(LLVMDoubleTypeInContext(self.context), n as f64),
                         None,
                     ));
                 }
-                VMInst::PUSH_INT32 => {
-                    pc += 1;
-                    get_int32!(iseq, pc, n, isize);
+                Inst::PushInt32 { n } => {
                     stack.push((
                         LLVMConstReal(LLVMDoubleTypeInContext(self.context), n as f64),
                         None,
                     ));
                 }
-                VMInst::PUSH_TRUE => {
-                    pc += 1;
+                Inst::PushTrue => {
                     stack.push((
                         LLVMConstInt(LLVMInt1TypeInContext(self.context), 1, 0),
                         None,
                     ));
                 }
-                VMInst::PUSH_FALSE => {
-                    pc += 1;
+                Inst::PushFalse => {
                     stack.push((
                         LLVMConstInt(LLVMInt1TypeInContext(self.context), 0, 0),
                         None,
                     ));
                 }
-                VMInst::PUSH_THIS | VMInst::PUSH_ARGUMENTS | VMInst::SET_MEMBER => pc += 1,
-                VMInst::POP => {
-                    pc += 1;
+                Inst::PushUndefined => {
+                    stack.push((ptr::null_mut(), Some(Value::Undefined)));
+                }
+                Inst::PushThis | Inst::PushArguments => {}
+                Inst::SetMember { .. } => {}
+                Inst::Pop => {
                     stack.pop();
                 }
-                VMInst::DOUBLE => {
-                    pc += 1;
+                Inst::Double => {
                     let stack_top_val = stack.last().unwrap().clone();
                     stack.push(stack_top_val);
                 }
-                VMInst::RETURN if is_func_jit => {
-                    pc += 1;
+                Inst::Double2 => {
+                    let len = stack.len();
+                    if len < 2 {
+                        return Err(());
+                    }
+                    let top2 = stack[len - 2..].to_vec();
+                    stack.extend(top2);
+                }
+                Inst::Rotate { n } => {
+                    let n = n as usize;
+                    if stack.len() < n + 1 {
+                        return Err(());
+                    }
+                    let top = stack.pop().unwrap();
+                    let len = stack.len();
+                    stack.insert(len - n, top);
+                }
+                Inst::Return if is_func_jit => {
                     let val = try_stack!(stack.pop());
                     LLVMBuildRet(self.builder, val);
                 }
@@ -1647,6 +1641,11 @@ impl TracingJit {
     }
 }
 
+/// the name of the LLVM local variable of the register 'r', which is not an identifier.
+fn register_name(r: usize) -> String {
+    format!("%r{}", r)
+}
+
 pub unsafe fn run_loop_llvm_func(
     f: fn(*mut f64) -> i32,
     vm_state: &mut vm::vm::VMState,
@@ -1657,7 +1656,7 @@ pub unsafe fn run_loop_llvm_func(
     let mut args_of_local_vars = vec![];
 
     for (id, _) in local_vars {
-        let name = &const_table.string[*id];
+        let name = const_table.string[*id].as_str();
         args_of_local_vars.push(match scope.get_value(name).unwrap() {
             vm::value::Value::Number(f) => Box::into_raw(Box::new(f)) as *mut libc::c_void,
             vm::value::Value::Bool(b) => Box::into_raw(Box::new(b)) as *mut libc::c_void,
@@ -1672,7 +1671,7 @@ pub unsafe fn run_loop_llvm_func(
     // println!("after:  farg[{:?}] local[{:?}]", args_of_arg_vars, args_of_local_vars);
 
     for (i, (id, ty)) in local_vars.iter().enumerate() {
-        let name = const_table.string[*id].clone();
+        let name = const_table.string[*id].to_string();
         scope.clone().set_value_if_exist(
             name,
             match ty {
@@ -1690,12 +1689,12 @@ pub unsafe fn run_loop_llvm_func(
 impl TracingJit {
     #[inline]
     fn func_is_called_enough_times(&mut self, id: FuncId, pc: usize) -> bool {
-        *self.count.entry(UniquePosition::new(id, pc)).or_insert(0) >= 5
+        *self.count.entry(UniquePosition::new(id, pc)).or_insert(0) >= FUNCTION_JIT_THRESHOLD
     }
 
     #[inline]
     fn loop_is_called_enough_times(&mut self, id: FuncId, pc: usize) -> bool {
-        *self.count.entry(UniquePosition::new(id, pc)).or_insert(0) >= 7
+        *self.count.entry(UniquePosition::new(id, pc)).or_insert(0) >= LOOP_JIT_THRESHOLD
     }
 
     #[inline]
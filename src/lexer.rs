@@ -1,11 +1,9 @@
 use parser::Error;
 use token::{convert_reserved_keyword, Kind, Symbol, Token};
 
+use num_bigint::BigInt;
 use std::collections::VecDeque;
 
-use encoding::all::UTF_16BE;
-use encoding::{DecoderTrap, Encoding};
-
 #[derive(Clone, Debug)]
 pub struct Lexer {
     pub code: String,
@@ -15,6 +13,12 @@ pub struct Lexer {
     pub line: usize,
     pub buf: VecDeque<Token>,
     pub pos_line_list: Vec<(usize, usize)>, // pos, line // TODO: Delete this and consider another way.
+    /// positions of number literals with a leading zero like 010 and 08, and of string literals
+    /// with legacy octal escape sequences like '\01', which are not allowed in strict mode code.
+    pub legacy_octal_literals: Vec<usize>,
+    /// for each substitution of a template literal being read, innermost last, the number of
+    /// '{' in it which are not closed yet.
+    pub template_braces: Vec<usize>,
 }
 
 impl Lexer {
@@ -25,22 +29,34 @@ impl Lexer {
             line: 1,
             buf: VecDeque::new(),
             pos_line_list: vec![(0, 1)],
+            legacy_octal_literals: vec![],
+            template_braces: vec![],
         }
     }
 
     /// tokenize all scripts.
     pub fn tokenize_all(&mut self) -> Result<(), Error> {
+        match self.tokenize_all_recovering().into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// tokenize all scripts, and return the errors. A line with an invalid token is dropped,
+    /// and tokenizing goes on from the next line.
+    pub fn tokenize_all_recovering(&mut self) -> Vec<Error> {
+        let mut errors = vec![];
         loop {
             match self.tokenize() {
                 Ok(tok) => self.buf.push_back(tok),
                 Err(Error::NormalEOF) => break,
                 Err(err) => {
-                    // when error occurs in tokenizer, pos_line_list is not completed.
-                    //
-                    self.skip_char_while(|c| c != '\n')?;
-                    self.take_char().unwrap_or(' ');
-                    self.pos_line_list.push((self.pos + 1, self.line + 1));
-                    return Err(err);
+                    errors.push(err);
+                    let line_start = self.pos_line_list.last().unwrap().0;
+                    while self.buf.back().map_or(false, |tok| tok.pos >= line_start) {
+                        self.buf.pop_back();
+                    }
+                    let _ = self.skip_char_while(|c| c != '\n');
                 }
             };
         }
@@ -55,7 +71,7 @@ impl Lexer {
             println!("{} {}", pos, line);
         }
         */
-        Ok(())
+        errors
     }
 
     pub fn print_buf(&self) {
@@ -139,17 +155,13 @@ impl Lexer {
     /// peek the next token, and when token is kind:Kind, get the token and return true.
     /// otherwise, return false.
     /// skipping line terminators.
+    /// The line terminators are left if the token is not kind:Kind, so that ';' may be inserted.
     pub fn skip_except_lineterminator(&mut self, kind: Kind) -> Result<bool, Error> {
-        match self.next_except_lineterminator() {
-            Ok(tok) => {
-                let success = tok.kind == kind;
-                if !success {
-                    self.unget(&tok)
-                }
-                Ok(success)
-            }
-            Err(e) => Err(e),
+        let success = self.peek_except_lineterminator()?.kind == kind;
+        if success {
+            self.next_except_lineterminator()?;
         }
+        Ok(success)
     }
 
     /// push back the token to the stack.
@@ -174,16 +186,35 @@ impl Lexer {
 impl Lexer {
     /// tokenize and return the token.
     fn tokenize(&mut self) -> Result<Token, Error> {
-        if self.starts_with("//") {
-            self.skip_line_comment()?;
-        } else if self.starts_with("/*") {
-            self.skip_normal_comment()?;
+        loop {
+            let pos = self.pos;
+            if self.starts_with("//") || self.is_html_comment() || self.is_hashbang_comment() {
+                self.skip_line_comment()?;
+            } else if self.starts_with("/*") {
+                // A comment containing line terminators works as a line terminator for
+                // automatic semicolon insertion.
+                if self.skip_normal_comment()? {
+                    return Ok(Token::new_line_terminator(pos));
+                }
+            } else {
+                break;
+            }
         }
 
         match self.peek_char()? {
-            'a'...'z' | 'A'...'Z' | '_' | '$' => self.read_identifier(),
+            'a'...'z' | 'A'...'Z' | '_' | '$' | '\\' => self.read_identifier(),
             '0'...'9' => self.read_number(),
+            // e.g. .5
+            '.' if self.code[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.read_number()
+            }
             '\'' | '\"' => self.read_string_literal(),
+            '`' => self.read_template(),
+            // '}' closing a substitution of a template literal
+            '}' if self.template_braces.last() == Some(&0) => {
+                self.template_braces.pop();
+                self.read_template()
+            }
             '\n' => self.read_line_terminator(),
             c if c.is_whitespace() => {
                 self.skip_whitespace()?;
@@ -199,32 +230,83 @@ impl Lexer {
         self.skip_char_while(|c| c != '\n')
     }
 
-    fn skip_normal_comment(&mut self) -> Result<(), Error> {
-        let mut last_char = ' ';
-        let mut line = self.line;
-        self.skip_char_while(|c| {
-            if c == '\n' {
-                line += 1;
+    /// skip a multi-line comment and return whether it contains line terminators.
+    fn skip_normal_comment(&mut self) -> Result<bool, Error> {
+        let start = self.pos;
+        self.pos += 2; // "/*"
+        let mut multi_line = false;
+        while !self.starts_with("*/") {
+            match self.take_char() {
+                Ok('\n') => {
+                    multi_line = true;
+                    self.line += 1;
+                    self.pos_line_list.push((self.pos, self.line));
+                }
+                Ok(_) => {}
+                Err(_) => return Err(Error::General(start, "unterminated comment.".to_string())),
             }
-            let end_of_comment = last_char == '*' && c == '/';
-            last_char = c;
-            !end_of_comment
-        })?;
-        if self.line != line {
-            self.pos_line_list.push((self.pos, line));
         }
-        self.line = line;
-        assert_eq!(self.take_char()?, '/');
-        Ok(())
+        self.pos += 2; // "*/"
+        Ok(multi_line)
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-html-like-comments
+    /// Whether an HTML-like comment starts here, which is skipped like a single-line comment.
+    /// '<!--' starts one anywhere, and '-->' only at the start of a line.
+    fn is_html_comment(&self) -> bool {
+        let at_line_start = self
+            .buf
+            .back()
+            .map_or(true, |tok| tok.kind == Kind::LineTerminator);
+        self.starts_with("<!--") || (self.starts_with("-->") && at_line_start)
+    }
+
+    /// https://tc39.github.io/proposal-hashbang/out.html
+    /// Whether a hashbang comment like '#!/usr/bin/env rapidus' starts here. It is only allowed
+    /// at the start of the source.
+    fn is_hashbang_comment(&self) -> bool {
+        self.pos == 0 && self.starts_with("#!")
     }
 }
 
 impl Lexer {
+    /// An identifier may contain unicode escape sequences. e.g. \u0061, \u{62}
     fn read_identifier(&mut self) -> Result<Token, Error> {
         let pos = self.pos;
+        let is_part = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+        let mut ident = "".to_string();
+        let mut has_escape = false;
+        loop {
+            match self.peek_char() {
+                Ok(c) if is_part(c) => {
+                    self.take_char()?;
+                    ident.push(c);
+                }
+                Ok('\\') => {
+                    self.take_char()?;
+                    if !self.take_char_if('u')? {
+                        return Err(Error::General(pos, "invalid token".to_string()));
+                    }
+                    let c = ::std::char::from_u32(self.read_unicode_escape(pos)?)
+                        .filter(|&c| is_part(c) && !(ident.is_empty() && c.is_numeric()))
+                        .ok_or_else(|| {
+                            Error::General(pos, "invalid unicode escape in identifier".to_string())
+                        })?;
+                    ident.push(c);
+                    has_escape = true;
+                }
+                _ => break,
+            }
+        }
 
-        let ident = self.take_char_while(|c| c.is_alphanumeric() || c == '_' || c == '$')?;
         if let Some(keyword) = convert_reserved_keyword(ident.as_str()) {
+            if has_escape {
+                return Err(Error::General(
+                    pos,
+                    "keywords must not contain unicode escapes".to_string(),
+                ));
+            }
             Ok(Token::new_keyword(keyword, pos))
         } else {
             Ok(Token::new_identifier(ident, pos))
@@ -233,185 +315,329 @@ impl Lexer {
 }
 
 impl Lexer {
+    /// https://tc39.github.io/ecma262/#sec-literals-numeric-literals
     pub fn read_number(&mut self) -> Result<Token, Error> {
         let pos = self.pos;
-        #[derive(Debug, Clone, PartialEq)]
-        enum NumLiteralKind {
-            Hex,
-            Dec,
-            Oct,
-            OldOct,
-            Bin,
-        }
-
-        let mut kind = NumLiteralKind::Dec;
-        let mut num_literal = "".to_string();
-
-        match self.take_char()? {
-            '0' if self.eof() => return Ok(Token::new_number(0.0, pos)),
-            '0' => {
-                let c = self.peek_char()?;
-                match c {
-                    'x' | 'X' => kind = NumLiteralKind::Hex,
-                    'b' | 'B' => kind = NumLiteralKind::Bin,
-                    'o' | 'O' => kind = NumLiteralKind::Oct,
-                    '0'...'7' => {
-                        kind = NumLiteralKind::OldOct;
-                        num_literal.push(c);
-                    }
-                    '.' => num_literal.push('.'),
-                    '8'...'9' => num_literal.push(c),
-                    _ => return Ok(Token::new_number(0.0, pos)),
-                }
-                self.take_char()?;
-            }
-            c => num_literal.push(c),
-        }
 
-        macro_rules! read_num {
-            ( $($valid_chars:pat),* ) => {
-                self.take_char_while(|c| match c.to_ascii_lowercase() {
-                    $( $valid_chars )|* => true,
-                    _ => false }
-                )?
-            };
-        }
+        let radix = match (self.peek_char()?, self.code[self.pos..].chars().nth(1)) {
+            ('0', Some('x')) | ('0', Some('X')) => 16,
+            ('0', Some('o')) | ('0', Some('O')) => 8,
+            ('0', Some('b')) | ('0', Some('B')) => 2,
+            _ => 10,
+        };
 
-        num_literal += match kind {
-            NumLiteralKind::Hex => read_num!('0'...'9', 'a'...'f'),
-            NumLiteralKind::Oct => read_num!('0'...'7'),
-            NumLiteralKind::Bin => read_num!('0'...'1'),
-            NumLiteralKind::OldOct => self.take_char_while(|c| match c {
-                '0'...'7' => true,
-                '8'...'9' => {
-                    kind = NumLiteralKind::Dec;
-                    true
-                }
-                _ => false,
-            })?,
-            NumLiteralKind::Dec => {
-                let mut last = ' ';
-                self.take_char_while(|c| {
-                    let is_f = "eE".contains(last) && "+-0123456789".contains(c);
-                    last = c;
-                    c.is_alphanumeric() || c == '.' || is_f
-                })?
+        // e.g. 0xff, 0o17, 0b101, 0x1fn
+        if radix != 10 {
+            self.pos += 2;
+            let digits = self.read_digits(radix, pos)?;
+            if digits.is_empty() {
+                return Err(Error::General(pos, "invalid token".to_string()));
             }
+            let tok = if self.take_char_if('n')? {
+                Token::new_bigint(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap(), pos)
+            } else {
+                Token::new_number(digits_to_f64(&digits, radix), pos)
+            };
+            return self.end_of_number(tok, pos);
         }
-        .as_str();
 
-        let num = match kind {
-            NumLiteralKind::Dec => match num_literal.parse() {
-                Ok(ok) => ok,
-                Err(_) => {
-                    return Err(Error::General(pos, "invalid token".to_string()));
-                }
-            },
-            NumLiteralKind::Hex => self.read_hex_num(num_literal.as_str()) as f64,
-            NumLiteralKind::Oct | NumLiteralKind::OldOct => {
-                self.read_oct_num(num_literal.as_str()) as f64
+        // LegacyOctalIntegerLiteral and NonOctalDecimalIntegerLiteral, which have a leading
+        // zero. e.g. 010, 08
+        let legacy = self.starts_with("0")
+            && self.code[self.pos + 1..]
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_ascii_digit());
+        let mut literal = if legacy {
+            self.legacy_octal_literals.push(pos);
+            let digits = self.take_char_while(|c| c.is_ascii_digit())?;
+            if digits.chars().all(|c| c < '8') {
+                let tok = Token::new_number(digits_to_f64(&digits, 8), pos);
+                return self.end_of_number(tok, pos);
+            }
+            digits
+        } else {
+            let digits = self.read_digits(10, pos)?;
+            // e.g. 0_1
+            if digits.len() > 1 && digits.starts_with('0') {
+                return Err(Error::General(pos, "invalid token".to_string()));
             }
-            NumLiteralKind::Bin => self.read_bin_num(num_literal.as_str()) as f64,
+            digits
         };
 
-        Ok(Token::new_number(num, pos))
+        let mut is_integer = true;
+        if self.take_char_if('.')? {
+            is_integer = false;
+            literal.push('.');
+            literal += self.read_digits(10, pos)?.as_str();
+        }
+        if self.take_char_if('e')? || self.take_char_if('E')? {
+            is_integer = false;
+            literal.push('e');
+            if self.take_char_if('+')? {
+                literal.push('+');
+            } else if self.take_char_if('-')? {
+                literal.push('-');
+            }
+            let exponent = self.read_digits(10, pos)?;
+            if exponent.is_empty() {
+                return Err(Error::General(pos, "invalid token".to_string()));
+            }
+            literal += exponent.as_str();
+        }
+
+        // BigInt literal. e.g. 123n. It cannot have a leading zero, a fraction or an exponent.
+        if is_integer && !legacy && self.take_char_if('n')? {
+            let n = BigInt::parse_bytes(literal.as_bytes(), 10).unwrap();
+            return self.end_of_number(Token::new_bigint(n, pos), pos);
+        }
+
+        match literal.parse() {
+            Ok(num) => self.end_of_number(Token::new_number(num, pos), pos),
+            Err(_) => Err(Error::General(pos, "invalid token".to_string())),
+        }
     }
 
-    fn read_hex_num(&mut self, num_literal: &str) -> i64 {
-        num_literal
-            .chars()
-            .fold(0, |n, c| match c.to_ascii_lowercase() {
-                '0'...'9' | 'A'...'F' | 'a'...'f' => n * 16 + c.to_digit(16).unwrap() as i64,
-                _ => n,
-            })
+    /// read digits of 'radix'. The digits may be separated by '_', which is removed.
+    /// e.g. 1_000_000
+    fn read_digits(&mut self, radix: u32, pos: usize) -> Result<String, Error> {
+        let mut digits = "".to_string();
+        loop {
+            match self.peek_char() {
+                Ok(c) if c.is_digit(radix) => {
+                    self.take_char()?;
+                    digits.push(c);
+                }
+                Ok('_') => {
+                    self.take_char()?;
+                    match self.peek_char() {
+                        Ok(c) if !digits.is_empty() && c.is_digit(radix) => {}
+                        _ => {
+                            return Err(Error::General(
+                                pos,
+                                "numeric separators are allowed only between digits".to_string(),
+                            ))
+                        }
+                    }
+                }
+                _ => return Ok(digits),
+            }
+        }
     }
 
-    fn read_oct_num(&mut self, num_literal: &str) -> i64 {
-        num_literal
-            .chars()
-            .fold(0, |n, c| match c.to_ascii_lowercase() {
-                '0'...'7' => n * 8 + c.to_digit(8).unwrap() as i64,
-                _ => n,
-            })
+    /// A numeric literal must not be followed by an identifier or a digit. e.g. 3in, 0b12
+    fn end_of_number(&mut self, tok: Token, pos: usize) -> Result<Token, Error> {
+        match self.peek_char() {
+            Ok(c) if c.is_alphanumeric() || c == '_' || c == '$' => {
+                Err(Error::General(pos, "invalid token".to_string()))
+            }
+            _ => Ok(tok),
+        }
     }
-    fn read_bin_num(&mut self, num_literal: &str) -> i64 {
-        num_literal
-            .chars()
-            .fold(0, |n, c| match c.to_ascii_lowercase() {
-                '0' | '1' => n * 2 + c.to_digit(2).unwrap() as i64,
-                _ => n,
-            })
+}
+
+/// the value of 'digits' of 'radix'. Digits too many for u64 are rounded.
+fn digits_to_f64(digits: &str, radix: u32) -> f64 {
+    match u64::from_str_radix(digits, radix) {
+        Ok(n) => n as f64,
+        Err(_) => digits.chars().fold(0.0, |n, c| {
+            n * radix as f64 + c.to_digit(radix).unwrap() as f64
+        }),
     }
 }
 
 impl Lexer {
+    /// https://tc39.github.io/ecma262/#sec-literals-string-literals
     pub fn read_string_literal(&mut self) -> Result<Token, Error> {
         let pos = self.pos;
         let quote = self.take_char()?;
-        // TODO: support escape sequence
-        let mut s = "".to_string();
+        let unterminated = || Error::General(pos, "unterminated string literal".to_string());
+
+        // The string is built of UTF-16 code units so that a surrogate pair written as two
+        // escape sequences makes one character.
+        let mut units = vec![];
         loop {
+            if self.peek_char().map_or(true, |c| c == '\n' || c == '\r') {
+                return Err(unterminated());
+            }
             match self.take_char()? {
                 q if q == quote => break,
+                '\\' => self.read_escape_sequence(&mut units, pos)?,
+                c => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+            }
+        }
+        // A string token is a Rust string, so a lone surrogate becomes U+FFFD.
+        Ok(Token::new_string(String::from_utf16_lossy(&units), pos))
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-template-literal-lexical-components
+    /// read a part of a template literal from '`', or from '}' closing a substitution, to '`' or
+    /// '${'. The substitution following '${' is tokenized as usual until its closing '}'.
+    fn read_template(&mut self) -> Result<Token, Error> {
+        let pos = self.pos;
+        let head = self.take_char()? == '`'; // '`' or '}'
+        let unterminated = || Error::General(pos, "unterminated template literal".to_string());
+
+        // The cooked string becomes None at an invalid escape sequence, which is allowed only in
+        // a tagged template.
+        let mut cooked = Some(vec![]);
+        let mut raw = "".to_string();
+        let tail = loop {
+            let c = match self.take_char().map_err(|_| unterminated())? {
+                '`' => break true,
+                '$' if self.take_char_if('{')? => {
+                    self.template_braces.push(0);
+                    break false;
+                }
                 '\\' => {
-                    for c in self.read_escaped_char()? {
-                        s.push(c)
+                    let start = self.pos - 1;
+                    // Unlike in a string literal, \1 to \9 and \0 followed by a digit are not
+                    // allowed.
+                    let legacy_octal = match self.peek_char() {
+                        Ok('1'...'9') => true,
+                        Ok('0') => {
+                            self.code[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit())
+                        }
+                        _ => false,
+                    };
+                    let mut units = vec![];
+                    if !legacy_octal && self.read_escape_sequence(&mut units, pos).is_ok() {
+                        if let Some(ref mut cooked) = cooked {
+                            cooked.extend_from_slice(&units);
+                        }
+                    } else {
+                        cooked = None;
                     }
+                    // The rest of an invalid escape sequence is read as usual characters.
+                    raw += &self.code[start..self.pos]
+                        .replace("\r\n", "\n")
+                        .replace('\r', "\n");
+                    continue;
                 }
-                c => s.push(c),
+                // CR LF and CR are normalized to LF in both the cooked and the raw strings.
+                c @ '\r' | c @ '\n' => {
+                    if c == '\n' || self.take_char_if('\n')? {
+                        self.line += 1;
+                        self.pos_line_list.push((self.pos, self.line));
+                    }
+                    '\n'
+                }
+                c => c,
+            };
+            if let Some(ref mut cooked) = cooked {
+                cooked.extend_from_slice(c.encode_utf16(&mut [0; 2]));
             }
-        }
-        Ok(Token::new_string(s, pos))
+            raw.push(c);
+        };
+        let cooked = cooked.map(|units| String::from_utf16_lossy(&units));
+        Ok(Token::new_template(cooked, raw, head, tail, pos))
     }
 
-    fn read_escaped_char(&mut self) -> Result<Vec<char>, Error> {
+    /// https://tc39.github.io/ecma262/#prod-EscapeSequence
+    /// read the escape sequence following '\\' in the string literal at 'pos', and push its code
+    /// units.
+    fn read_escape_sequence(&mut self, units: &mut Vec<u16>, pos: usize) -> Result<(), Error> {
         let c = self.take_char()?;
-        Ok(match c {
-            '\'' | '"' | '?' | '\\' => vec![c],
-            'a' => vec!['\x07'],
-            'b' => vec!['\x08'],
-            'f' => vec!['\x0c'],
-            'n' => vec!['\x0a'],
-            'r' => vec!['\x0d'],
-            't' => vec!['\x09'],
-            'v' => vec!['\x0b'],
-            'x' => {
-                let hex = self.take_char_while(|c| c.is_alphanumeric())?;
-                vec![self.read_hex_num(hex.as_str()) as u8 as char]
+        let unit = match c {
+            'b' => 0x08,
+            't' => 0x09,
+            'n' => 0x0a,
+            'v' => 0x0b,
+            'f' => 0x0c,
+            'r' => 0x0d,
+            '0' if !self.peek_char().map_or(false, |c| c.is_ascii_digit()) => 0,
+            // LegacyOctalEscapeSequence. e.g. \12, \377
+            '0'...'7' => {
+                self.add_legacy_octal_literal(pos);
+                let max_len = if c <= '3' { 3 } else { 2 };
+                let mut n = c.to_digit(8).unwrap();
+                for _ in 1..max_len {
+                    match self.peek_char().ok().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            self.take_char()?;
+                            n = n * 8 + d;
+                        }
+                        None => break,
+                    }
+                }
+                n as u16
+            }
+            '8' | '9' => {
+                self.add_legacy_octal_literal(pos);
+                c as u16
             }
+            'x' => self.read_hex_digits(2, pos)? as u16,
             'u' => {
-                let mut u8s = vec![];
-                loop {
-                    let hex = self.take_char_while(|c| c.is_alphanumeric())?;
-                    let mut i = 0;
-                    while i < hex.len() {
-                        u8s.push(
-                            self.read_hex_num(&hex[i..i + if i + 2 > hex.len() { 1 } else { 2 }])
-                                as u8,
-                        );
-                        i += 2;
-                    }
-                    if u8s.len() % 2 != 0 {
-                        // TODO: Support \u{X..X}
-                        unimplemented!("unsupported escape sequence");
-                    }
-                    let save_pos = self.pos;
-                    // TODO: Error handling
-                    if self.take_char()? == '\\' && self.take_char()? == 'u' {
-                        continue;
-                    } else {
-                        self.pos = save_pos;
-                        break;
-                    }
+                let code_point = self.read_unicode_escape(pos)?;
+                match ::std::char::from_u32(code_point) {
+                    Some(c) => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+                    // a surrogate
+                    None => units.push(code_point as u16),
+                }
+                return Ok(());
+            }
+            // LineContinuation
+            '\r' => {
+                self.take_char_if('\n')?;
+                self.line += 1;
+                self.pos_line_list.push((self.pos, self.line));
+                return Ok(());
+            }
+            '\n' => {
+                self.line += 1;
+                self.pos_line_list.push((self.pos, self.line));
+                return Ok(());
+            }
+            '\u{2028}' | '\u{2029}' => return Ok(()),
+            c => {
+                units.extend_from_slice(c.encode_utf16(&mut [0; 2]));
+                return Ok(());
+            }
+        };
+        units.push(unit);
+        Ok(())
+    }
+
+    /// record the string literal at 'pos' which has a legacy octal escape sequence.
+    fn add_legacy_octal_literal(&mut self, pos: usize) {
+        if self.legacy_octal_literals.last() != Some(&pos) {
+            self.legacy_octal_literals.push(pos);
+        }
+    }
+
+    /// read XXXX or {X...} following '\\u' and return the code point.
+    fn read_unicode_escape(&mut self, pos: usize) -> Result<u32, Error> {
+        if !self.take_char_if('{')? {
+            return self.read_hex_digits(4, pos);
+        }
+        let digits = self.take_char_while(|c| c.is_ascii_hexdigit())?;
+        match u32::from_str_radix(digits.as_str(), 16) {
+            Ok(n) if n <= 0x10ffff && self.take_char_if('}')? => Ok(n),
+            _ => Err(Error::General(
+                pos,
+                "invalid unicode escape sequence".to_string(),
+            )),
+        }
+    }
+
+    /// read just 'len' hex digits.
+    fn read_hex_digits(&mut self, len: usize, pos: usize) -> Result<u32, Error> {
+        let mut n = 0;
+        for _ in 0..len {
+            match self.peek_char().ok().and_then(|c| c.to_digit(16)) {
+                Some(d) => {
+                    self.take_char()?;
+                    n = n * 16 + d;
+                }
+                None => {
+                    return Err(Error::General(
+                        pos,
+                        "invalid hexadecimal escape sequence".to_string(),
+                    ))
                 }
-                UTF_16BE
-                    .decode(u8s.as_slice(), DecoderTrap::Strict)
-                    .unwrap()
-                    .chars()
-                    .collect::<Vec<char>>()
             }
-            _ => vec![c],
-        })
+        }
+        Ok(n)
     }
 }
 
@@ -461,7 +687,11 @@ impl Lexer {
                 if self.take_char_if('=')? {
                     symbol = Symbol::AssignMul
                 } else if self.take_char_if('*')? {
-                    symbol = Symbol::Exp
+                    if self.take_char_if('=')? {
+                        symbol = Symbol::AssignExp
+                    } else {
+                        symbol = Symbol::Exp
+                    }
                 } else {
                     symbol = Symbol::Asterisk
                 }
@@ -555,8 +785,18 @@ impl Lexer {
             ')' => symbol = Symbol::ClosingParen,
             '[' => symbol = Symbol::OpeningBoxBracket,
             ']' => symbol = Symbol::ClosingBoxBracket,
-            '{' => symbol = Symbol::OpeningBrace,
-            '}' => symbol = Symbol::ClosingBrace,
+            '{' => {
+                if let Some(n) = self.template_braces.last_mut() {
+                    *n += 1;
+                }
+                symbol = Symbol::OpeningBrace
+            }
+            '}' => {
+                if let Some(n) = self.template_braces.last_mut() {
+                    *n -= 1;
+                }
+                symbol = Symbol::ClosingBrace
+            }
             ',' => symbol = Symbol::Comma,
             ';' => symbol = Symbol::Semicolon,
             ':' => symbol = Symbol::Colon,
@@ -662,6 +902,17 @@ impl Lexer {
 }
 
 impl Lexer {
+    /// line and column of 'pos', both from 1. The column is counted in bytes.
+    pub fn line_and_column(&self, pos: usize) -> (usize, usize) {
+        let (start_pos, line) = self
+            .pos_line_list
+            .iter()
+            .take_while(|x| x.0 <= pos)
+            .last()
+            .unwrap();
+        (*line, pos - start_pos + 1)
+    }
+
     pub fn get_code_around_err_point(&self, pos: usize) -> (String, usize, usize) {
         let code = self.code.as_bytes();
         let iter = self.pos_line_list.iter();
@@ -699,6 +950,51 @@ fn number() {
     assert_eq!(lexer.next().unwrap().kind, Kind::Number(5e-3));
     assert_eq!(lexer.next().unwrap().kind, Kind::Number(999.0));
     assert_eq!(lexer.next().unwrap().kind, Kind::Number(0o123 as f64));
+    assert_eq!(lexer.legacy_octal_literals, vec![9, 37]);
+
+    let mut lexer = Lexer::new(
+        "0xFF 0o755 0b1010 1e9 1.5e-3 1_000_000 .5 5. 0.000_1 1E1_0 0xff_ff 0x1_0000_0000_0000_0000 08.5"
+            .to_string(),
+    );
+    lexer.tokenize_all().unwrap();
+    for n in &[
+        255.0,
+        493.0,
+        10.0,
+        1e9,
+        1.5e-3,
+        1_000_000.0,
+        0.5,
+        5.0,
+        0.0001,
+        1e10,
+        65535.0,
+        18446744073709551616.0,
+        8.5,
+    ] {
+        assert_eq!(lexer.next().unwrap().kind, Kind::Number(*n));
+    }
+
+    for invalid in &[
+        "1_", "1__0", "0_1", "1._5", "1_.5", "0x", "0x_1", "0b12", "0o8", "1e", "1e+", "3in",
+    ] {
+        let mut lexer = Lexer::new(invalid.to_string());
+        assert!(lexer.tokenize_all().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn bigint() {
+    let mut lexer = Lexer::new("0n 123n 0x1fn 0o17n 0b101n 98765432109876543210n".to_string());
+    lexer.tokenize_all().unwrap();
+    for n in &["0", "123", "31", "15", "5", "98765432109876543210"] {
+        assert_eq!(lexer.next().unwrap().kind, Kind::BigInt(n.parse().unwrap()));
+    }
+
+    for invalid in &["1.5n", "1e3n", "08n", "0xn"] {
+        let mut lexer = Lexer::new(invalid.to_string());
+        assert!(lexer.tokenize_all().is_err());
+    }
 }
 
 #[test]
@@ -723,6 +1019,54 @@ fn string() {
     assert_eq!(lexer.next().unwrap().kind, Kind::String("bbb".to_string()));
 }
 
+#[test]
+fn template() {
+    let template = |cooked: Option<&str>, raw: &str, head: bool, tail: bool| {
+        Kind::Template(cooked.map(|s| s.to_string()), raw.to_string(), head, tail)
+    };
+    let mut lexer = Lexer::new("`a\\tb${ {x: `${1}`} }c\r\nd` `\\unicode`".to_string());
+    lexer.tokenize_all().unwrap();
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        template(Some("a\tb"), "a\\tb", true, false)
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        Kind::Symbol(Symbol::OpeningBrace)
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        Kind::Identifier("x".to_string())
+    );
+    assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::Colon));
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        template(Some(""), "", true, false)
+    );
+    assert_eq!(lexer.next().unwrap().kind, Kind::Number(1.0));
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        template(Some(""), "", false, true)
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        Kind::Symbol(Symbol::ClosingBrace)
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        template(Some("c\nd"), "c\nd", false, true)
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        template(None, "\\unicode", true, true)
+    );
+    assert_eq!(lexer.line, 2);
+
+    for invalid in &["`abc", "`${1}", "`${1}abc"] {
+        assert!(Lexer::new(invalid.to_string()).tokenize_all().is_err());
+    }
+}
+
 #[test]
 fn keyword() {
     use token::Keyword;
@@ -780,7 +1124,7 @@ fn symbol() {
     let mut lexer = Lexer::new(
         "() {} [] , ; : . -> ++ -- + - * / % **\
          ! ~ << >> >>> < <= > >= == != === !== & | ^ && || \
-         ? = += -= *= /= %= <<= >>= &= |= ^= \
+         ? = += -= *= /= %= **= <<= >>= &= |= ^= \
          &&= ||= #"
             .to_string(),
     );
@@ -851,6 +1195,7 @@ fn symbol() {
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignMul,));
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignDiv,));
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignMod,));
+    assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignExp,));
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignShl,));
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignShr,));
     assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::AssignAnd,));
@@ -903,8 +1248,39 @@ fn escape_seq() {
     lexer.tokenize_all().unwrap();
     assert_eq!(
         lexer.next().unwrap().kind,
-        Kind::String("\' \" \\ \x07 \x08 \x0c \n \r \t \x0b \x12 𩸽".to_string())
+        Kind::String("\' \" \\ a \x08 \x0c \n \r \t \x0b \x12 𩸽".to_string())
+    );
+
+    let mut lexer = Lexer::new(
+        "'\\0 \\x41B \\u0041B \\u{1F600} \\u{41} \\101 \\08 \\a\\\nb' x \\u0061b c\\u{64}"
+            .to_string(),
+    );
+    lexer.tokenize_all().unwrap();
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        Kind::String("\0 AB AB \u{1F600} A A \08 ab".to_string())
     );
+    assert_eq!(lexer.legacy_octal_literals, vec![0]);
+    for ident in &["x", "ab", "cd"] {
+        assert_eq!(
+            lexer.next().unwrap().kind,
+            Kind::Identifier(ident.to_string())
+        );
+    }
+
+    for invalid in &[
+        "'\\x4'",
+        "'\\u41'",
+        "'\\u{110000}'",
+        "'\\u{41'",
+        "'abc",
+        "'a\nb'",
+        "\\u0031a",
+        "v\\u0061\\u0072",
+    ] {
+        let mut lexer = Lexer::new(invalid.to_string());
+        assert!(lexer.tokenize_all().is_err(), "{}", invalid);
+    }
 }
 
 #[test]
@@ -930,4 +1306,48 @@ fn comment() {
         lexer.next_except_lineterminator().unwrap().kind,
         Kind::Identifier("y".to_string())
     );
+
+    let mut lexer = Lexer::new(
+        "/* a *//* b */x /*/ c */ y // d\n<!-- e\n--> f\n  /*\n*/ --> g\nz-->w".to_string(),
+    );
+    lexer.tokenize_all().unwrap();
+    for ident in &["x", "y", "z"] {
+        assert_eq!(
+            lexer.next_except_lineterminator().unwrap().kind,
+            Kind::Identifier(ident.to_string())
+        );
+    }
+    // 'z-->w' is 'z-- > w'.
+    assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::Dec));
+
+    // Lines in a comment are counted.
+    let mut lexer = Lexer::new("/* a\nb\n*/ x\n y".to_string());
+    lexer.tokenize_all().unwrap();
+    assert_eq!(
+        lexer.get_code_around_err_point(10),
+        ("*/ x\n   ^".to_string(), 10, 3)
+    );
+    assert_eq!(lexer.get_code_around_err_point(13).2, 4);
+
+    let mut lexer = Lexer::new("x\n /* a".to_string());
+    assert_eq!(
+        lexer.tokenize_all(),
+        Err(Error::General(3, "unterminated comment.".to_string()))
+    );
+}
+
+#[test]
+fn hashbang() {
+    let mut lexer = Lexer::new("#!/usr/bin/env rapidus\nx".to_string());
+    lexer.tokenize_all().unwrap();
+    assert_eq!(
+        lexer.next_except_lineterminator().unwrap().kind,
+        Kind::Identifier("x".to_string())
+    );
+    assert_eq!(lexer.get_code_around_err_point(23).2, 2);
+
+    // Only at the start of the source. Elsewhere '#!' is read as symbols.
+    let mut lexer = Lexer::new(" #!x".to_string());
+    lexer.tokenize_all().unwrap();
+    assert_eq!(lexer.next().unwrap().kind, Kind::Symbol(Symbol::Hash));
 }
@@ -5,17 +5,30 @@
 pub mod util;
 #[macro_use]
 pub mod vm;
+pub mod atom;
 pub mod builtin;
 pub mod builtins;
+pub mod bytecode_file;
 pub mod bytecode_gen;
+pub mod engine;
+pub mod estree;
+pub mod feature_gate;
 pub mod gc;
 pub mod id;
+#[cfg(feature = "jit")]
 pub mod jit;
 pub mod lexer;
+pub mod lower;
 pub mod node;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
+#[cfg(feature = "serde")]
+pub mod serde_value;
+pub mod sourcemap;
 pub mod test;
 pub mod token;
+pub mod verifier;
 pub mod vm_codegen;
 
 extern crate ansi_term;
@@ -23,10 +36,25 @@ extern crate chrono;
 extern crate encoding;
 extern crate libc;
 extern crate libloading;
+#[cfg(feature = "jit")]
 extern crate llvm_sys as llvm;
 extern crate nix;
+extern crate num_bigint;
+extern crate num_traits;
 extern crate rand;
 extern crate rustc_hash;
 extern crate rustyline;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
 extern crate stopwatch; 
+extern crate tungstenite;
+extern crate ureq;
+extern crate wasmi;
 // extern crate cpuprofiler;
+
+pub use engine::{arg, Engine, FromJsValue, JsError, JsValue, NativeObject, TerminateHandle};
+pub use parser::{parse, SyntaxError};
+pub use vm::vm::VMOptions;
@@ -0,0 +1,68 @@
+use node::{Node, NodeBase};
+
+// Lowering of the AST. The parser keeps the constructs as they are written, and this pass
+// rewrites the syntactic sugar among them into the core nodes, which are all the nodes except
+// the ones listed below. It runs on the whole script, including the bodies of the functions,
+// before the code generation, so the code generator (and the JIT compiling its bytecode) only
+// handles the core nodes. The AST printed by --print-ast-json is the one before lowering.
+//
+// - While(cond, body), 'while (cond) body', becomes For(Nope, cond, Nope, body).
+//
+// A new syntax which can be expressed with the core nodes is added here as a new node and its
+// rewriting, instead of being compiled by the code generator. One which must evaluate a part
+// once, as 'a.b += c' does with 'a', stays a core node.
+
+/// the core AST of 'node'.
+pub fn lower(node: &Node) -> Node {
+    let mut node = node.clone();
+    lower_node(&mut node);
+    node
+}
+
+fn lower_node(node: &mut Node) {
+    for child in node.children_mut() {
+        lower_node(child);
+    }
+
+    let lowered = match node.base {
+        NodeBase::While(ref cond, ref body) => Some(NodeBase::For(
+            Box::new(Node::new(NodeBase::Nope, node.pos)),
+            cond.clone(),
+            Box::new(Node::new(NodeBase::Nope, node.pos)),
+            body.clone(),
+        )),
+        _ => None,
+    };
+    if let Some(base) = lowered {
+        node.base = base;
+    }
+}
+
+#[test]
+fn test_lower_while() {
+    use parser::Parser;
+
+    // Positions and the source text of functions differ from the code written with 'for'.
+    fn strip(node: &mut Node) {
+        node.pos = 0;
+        match node.base {
+            NodeBase::FunctionDecl(_, _, _, ref mut source, _)
+            | NodeBase::FunctionExpr(_, _, _, ref mut source, _) => source.clear(),
+            _ => {}
+        }
+        for child in node.children_mut() {
+            strip(child);
+        }
+    }
+    let parse = |code: &str| {
+        let mut node = Parser::new(code.to_string()).parse_all().unwrap();
+        strip(&mut node);
+        node
+    };
+
+    let node = parse("function f(x) { while (x) { x -= 1; while (g()) continue } }");
+    let core = parse("function f(x) { for (; x;) { x -= 1; for (; g();) continue } }");
+    assert!(node != core);
+    assert_eq!(lower(&node), core);
+    assert_eq!(lower(&core), core);
+}
@@ -1,7 +1,16 @@
 extern crate rapidus;
+use rapidus::builtins;
+use rapidus::bytecode_file;
 use rapidus::bytecode_gen;
+use rapidus::estree;
+use rapidus::node::Node;
 use rapidus::parser;
+use rapidus::sourcemap::SourceMap;
 use rapidus::vm;
+use rapidus::vm::debugger::Debugger;
+use rapidus::vm::inspector;
+use rapidus::vm::profiler::{self, Profiler};
+use rapidus::vm::tracer::Tracer;
 use rapidus::vm::vm::VM;
 use rapidus::vm_codegen;
 
@@ -10,7 +19,7 @@ extern crate libc;
 extern crate rustyline;
 
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
 extern crate nix;
 use nix::sys::wait::*;
@@ -19,8 +28,9 @@ use nix::unistd::*;
 extern crate ansi_term;
 use ansi_term::Colour;
 
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
+use std::path::Path;
 
 const VERSION_STR: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -29,49 +39,349 @@ fn main() {
         .version(VERSION_STR)
         .author("uint256_t")
         .about("A toy JavaScript engine")
+        .setting(AppSettings::TrailingVarArg)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("eval")
+                .help("Run CODE instead of a script file")
+                .short("e")
+                .long("eval")
+                .value_name("CODE")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("print")
+                .help("Print the value of the last expression statement of the script")
+                .short("p")
+                .long("print")
+                .global(true),
+        )
         .arg(
             Arg::with_name("debug")
-                .help("Show useful information for debugging")
-                .long("debug"),
+                .help("Run the script in the step debugger")
+                .long("debug")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("inspect")
+                .help("Debug the script in Chrome DevTools (listens on 127.0.0.1:9229 by default)")
+                .long("inspect")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("trace-gc")
+                .help("Log each garbage collection to stderr")
+                .long("trace-gc")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("prof")
+                .help("Sample the CPU usage of the script and write it to rapidus.folded")
+                .long("prof")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("dump")
+                .help("Show the AST and the bytecode of the script without running it")
+                .long("dump"),
         )
         .arg(
             Arg::with_name("trace")
-                .help("Trace bytecode execution for debugging")
-                .long("trace"),
+                .help("Log each instruction run to stderr, or to FILE")
+                .long("trace")
+                .value_name("FILE")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("trace-function")
+                .help("Trace only the functions named NAME")
+                .long("trace-function")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no-jit")
+                .help("Run without the tracing JIT")
+                .long("no-jit")
+                .global(true),
         )
-        .arg(Arg::with_name("file").help("Input file name").index(1));
+        .arg(
+            Arg::with_name("no-opt")
+                .help("Disable the bytecode optimizer")
+                .long("no-opt")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("registers")
+                .help("Compile arithmetic into register-addressed bytecode")
+                .long("registers")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("random-seed")
+                .help("Make Math.random() return the same sequence for the same seed N")
+                .long("random-seed")
+                .value_name("N")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("check")
+                .help("Same as the check subcommand")
+                .long("check"),
+        )
+        .arg(
+            Arg::with_name("print-ast-json")
+                .help("Print the AST of the script as ESTree JSON without running it")
+                .long("print-ast-json"),
+        )
+        .args(&script_args())
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a script (the default when a script is given)")
+                .setting(AppSettings::TrailingVarArg)
+                .args(&script_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Compile a script into a precompiled bytecode file (.rbc)")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Input file name")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file name (defaults to the input with .rbc)")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("source-map")
+                        .help("Also write a source map of the bytecode to the output with .map")
+                        .long("source-map"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Start the interactive shell (the default when no script is given)"),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check the syntax of a script without running it, reporting all errors")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Input file name")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Show the bytecode of a script or a precompiled bytecode file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Input file name")
+                        .required(true)
+                        .index(1),
+                ),
+        );
     let app_matches = app.clone().get_matches();
 
-    let file_name = match app_matches.value_of("file") {
+    // The values of the global flags are seen here wherever they are given.
+    let tracer = match app_matches.is_present("trace") {
+        true => match make_tracer(
+            app_matches.value_of("trace"),
+            app_matches.value_of("trace-function"),
+        ) {
+            Some(tracer) => Some(tracer),
+            None => std::process::exit(1),
+        },
+        false => None,
+    };
+    let random_seed = match app_matches.value_of("random-seed") {
+        Some(seed) => match seed.parse::<u64>() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                eprintln!(
+                    "{}: --random-seed must be a non-negative integer: '{}'",
+                    Colour::Red.bold().paint("error"),
+                    seed
+                );
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let options = Options {
+        tracer: tracer,
+        debug: app_matches.is_present("debug"),
+        inspect: match app_matches.is_present("inspect") {
+            true => Some(
+                app_matches
+                    .value_of("inspect")
+                    .unwrap_or(inspector::DEFAULT_ADDRESS),
+            ),
+            false => None,
+        },
+        prof: app_matches.is_present("prof"),
+        trace_gc: app_matches.is_present("trace-gc"),
+        jit: !app_matches.is_present("no-jit"),
+        registers: app_matches.is_present("registers"),
+        optimize: !app_matches.is_present("no-opt"),
+        print: app_matches.is_present("print"),
+        random_seed: random_seed,
+    };
+
+    let script_matches = match app_matches.subcommand() {
+        ("compile", Some(matches)) => {
+            let file_name = matches.value_of("file").unwrap();
+            let output = match matches.value_of("output") {
+                Some(output) => output.to_string(),
+                None => Path::new(file_name)
+                    .with_extension("rbc")
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            compile(
+                file_name,
+                output.as_str(),
+                matches.is_present("source-map"),
+                options.registers,
+                options.optimize,
+            );
+            return;
+        }
+        ("repl", Some(_)) => {
+            repl(options);
+            return;
+        }
+        ("check", Some(matches)) => {
+            if parse_script(matches.value_of("file").unwrap()).is_none() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("disasm", Some(matches)) => {
+            let file_name = matches.value_of("file").unwrap();
+            if !disasm(file_name, options.registers, options.optimize) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("run", Some(matches)) => matches,
+        _ => &app_matches,
+    };
+
+    // Arguments after '--' are passed to the script as well. With --eval, there is no script
+    // file, so all of them are.
+    let mut args: Vec<String> = script_matches
+        .values_of("args")
+        .map_or(vec![], |args| args.map(|arg| arg.to_string()).collect());
+    if let Some(code) = app_matches.value_of("eval") {
+        if let Some(file_name) = script_matches.value_of("file") {
+            args.insert(0, file_name.to_string());
+        }
+        run(Script::Code(code.to_string()), args, options);
+        return;
+    }
+
+    // Without a script, the code piped into stdin is run, as with '-'.
+    let file_name = match script_matches.value_of("file") {
         Some(file_name) => file_name,
+        None if unsafe { libc::isatty(0) } == 0 => "-",
         None => {
-            repl(app_matches.is_present("trace"));
+            repl(options);
             return;
         }
     };
 
-    // Normally run the given code
-    if !app_matches.is_present("debug") {
-        run(file_name, app_matches.is_present("trace"));
+    if app_matches.is_present("check") {
+        if parse_script(file_name).is_none() {
+            std::process::exit(1);
+        }
         return;
     }
 
-    // Show information for debugging
+    if app_matches.is_present("print-ast-json") {
+        match parse_script(file_name) {
+            Some(node) => println!("{:#}", estree::program(&node)),
+            None => std::process::exit(1),
+        }
+        return;
+    }
 
-    let mut file_body = String::new();
+    if app_matches.is_present("dump") {
+        dump(file_name, options.registers, options.optimize);
+        return;
+    }
 
-    match OpenOptions::new().read(true).open(file_name) {
-        Ok(mut ok) => ok
-            .read_to_string(&mut file_body)
-            .ok()
-            .expect("cannot read file"),
-        Err(e) => {
-            println!("error: {}", e);
+    // Normally run the given code
+    run(Script::File(file_name), args, options);
+}
+
+/// The script file and the arguments passed to it, of the top level and the run subcommand.
+fn script_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("file")
+            .help("Input file name, or - to read the script from stdin")
+            .index(1),
+        Arg::with_name("args")
+            .help("Arguments passed to the script as process.argv")
+            .multiple(true)
+            .allow_hyphen_values(true)
+            .index(2),
+    ]
+}
+
+/// How scripts are compiled and run, by the flags given.
+struct Options<'a> {
+    tracer: Option<Tracer>,
+    debug: bool,
+    /// the address the inspector listens on.
+    inspect: Option<&'a str>,
+    prof: bool,
+    trace_gc: bool,
+    jit: bool,
+    registers: bool,
+    optimize: bool,
+    /// print the value of the last expression statement.
+    print: bool,
+    /// the seed of Math.random() given by --random-seed.
+    random_seed: Option<u64>,
+}
+
+/// A script to run.
+enum Script<'a> {
+    File(&'a str),
+    /// the code given by --eval.
+    Code(String),
+}
+
+/// Show information for debugging
+fn dump(file_name: &str, registers: bool, optimize: bool) {
+    let file_body = match read_file(file_name).map(String::from_utf8) {
+        Some(Ok(file_body)) => file_body,
+        Some(Err(_)) => {
+            println!("error: cannot read file");
             return;
         }
+        None => return,
     };
 
     let mut parser = parser::Parser::new(file_body);
+    parser.file_name = Some(script_name(file_name).to_string());
 
     println!("Parser:");
     let node = match parser.parse_all() {
@@ -84,6 +394,8 @@ fn main() {
     println!("{:?}", node);
 
     let mut vm = VM::new();
+    vm.codegen.use_registers = registers;
+    vm.codegen.optimize = optimize;
     let mut iseq = vec![];
     vm.codegen.compile(&node, &mut iseq, false).unwrap();
 
@@ -95,10 +407,62 @@ fn main() {
     // vm.run(iseq);
 }
 
-fn repl(trace: bool) {
+/// Show the bytecode of a script, or of a precompiled bytecode file. Reports an error and
+/// returns false on failure.
+fn disasm(file_name: &str, registers: bool, optimize: bool) -> bool {
+    let file_body = match read_file(file_name) {
+        Some(file_body) => file_body,
+        None => return false,
+    };
+
+    let mut vm = VM::new();
+    vm.codegen.use_registers = registers;
+    vm.codegen.optimize = optimize;
+    let iseq = if bytecode_file::is_precompiled(&file_body) {
+        match bytecode_file::deserialize(&mut vm, &file_body) {
+            Ok(iseq) => iseq,
+            Err(msg) => {
+                eprintln!(
+                    "{}: Couldn't load '{}': {}",
+                    Colour::Red.bold().paint("error"),
+                    file_name,
+                    msg
+                );
+                return false;
+            }
+        }
+    } else {
+        let file_body = match String::from_utf8(file_body) {
+            Ok(file_body) => file_body,
+            Err(_) => {
+                eprintln!(
+                    "{}: Couldn't read the file '{}'",
+                    Colour::Red.bold().paint("error"),
+                    file_name,
+                );
+                return false;
+            }
+        };
+        match compile_source(&mut vm, file_name, file_body, false) {
+            Some(iseq) => iseq,
+            None => return false,
+        }
+    };
+
+    bytecode_gen::show(&iseq, &vm.codegen.bytecode_gen.const_table);
+    true
+}
+
+fn repl(options: Options) {
     // TODO: REFINE CODE!!!!
     let mut vm = vm::vm::VM::new();
-    vm.is_debug = trace;
+    vm.jit_on &= options.jit && options.tracer.is_none();
+    vm.tracer = options.tracer;
+    vm.codegen.use_registers = options.registers;
+    vm.codegen.optimize = options.optimize;
+    if let Some(seed) = options.random_seed {
+        vm.random_state = builtins::math::random_state(seed);
+    }
     let mut rl = rustyline::Editor::<()>::new();
 
     loop {
@@ -129,10 +493,10 @@ fn repl(trace: bool) {
                             if vm.state.stack.len() == 0 {
                                 vm.state.stack.push(vm::value::Value::Undefined);
                             };
-                            if vm.state.history.len() != 1 {
+                            if vm.state.frames.len() != 0 {
                                 println!(
-                                    "Warning: history length is {} (should be 1)",
-                                    vm.state.history.len()
+                                    "Warning: {} frames are left (should be 0)",
+                                    vm.state.frames.len()
                                 );
                             };
                             match res {
@@ -174,14 +538,27 @@ fn repl(trace: bool) {
     }
 }
 
-fn run(file_name: &str, trace: bool) {
+fn run(script: Script, args: Vec<String>, options: Options) {
+    let Options {
+        tracer,
+        debug,
+        inspect,
+        prof,
+        trace_gc,
+        jit,
+        registers,
+        optimize,
+        print,
+        random_seed,
+    } = options;
     match fork() {
         Ok(ForkResult::Parent { child, .. }) => {
             match waitpid(child, None) {
                 Ok(ok) => match ok {
+                    // The script may have called process.exit(status).
                     WaitStatus::Exited(_, status) => {
                         if status != 0 {
-                            panic!("exited. status: {}", status)
+                            std::process::exit(status)
                         }
                     }
                     WaitStatus::Signaled(pid, status, _) => {
@@ -194,74 +571,400 @@ fn run(file_name: &str, trace: bool) {
             }
         }
         Ok(ForkResult::Child) => {
-            let mut file_body = String::new();
+            // As in Node.js, process.argv has no script for code given by --eval.
+            let (file_name, file_body, script_argv) = match script {
+                Script::File(file_name) => match read_file(file_name) {
+                    Some(file_body) => (
+                        script_name(file_name),
+                        file_body,
+                        vec![file_name.to_string()],
+                    ),
+                    None => std::process::exit(1),
+                },
+                Script::Code(code) => ("<eval>", code.into_bytes(), vec![]),
+            };
 
-            match OpenOptions::new().read(true).open(file_name) {
-                Ok(mut ok) => match ok.read_to_string(&mut file_body).ok() {
-                    Some(x) => x,
-                    None => {
+            if file_body.len() == 0 {
+                return;
+            }
+
+            let mut vm = vm::vm::VM::new();
+            vm.codegen.use_registers = registers;
+            vm.codegen.optimize = optimize;
+            // The debugger and the inspector look variables up by name.
+            vm.codegen.resolve_locals = !debug && inspect.is_none();
+
+            // The source shown by the debugger and the inspector.
+            let mut source = String::new();
+            let iseq = if bytecode_file::is_precompiled(&file_body) {
+                vm.source_map = load_source_map(file_name);
+                match bytecode_file::load(&mut vm, &file_body) {
+                    Ok(iseq) => iseq,
+                    Err(msg) => {
+                        eprintln!(
+                            "{}: Couldn't load '{}': {}",
+                            Colour::Red.bold().paint("error"),
+                            file_name,
+                            msg
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                let file_body = match String::from_utf8(file_body) {
+                    Ok(file_body) => file_body,
+                    Err(_) => {
                         eprintln!(
                             "{}: Couldn't read the file '{}'",
                             Colour::Red.bold().paint("error"),
                             file_name,
                         );
-                        return;
+                        std::process::exit(1);
                     }
-                },
-                Err(_e) => {
-                    eprintln!(
-                        "{}: No such file or directory '{}'",
-                        Colour::Red.bold().paint("error"),
-                        file_name,
-                    );
-                    return;
+                };
+                if debug || inspect.is_some() {
+                    source = file_body.clone();
+                }
+                match compile_source(&mut vm, file_name, file_body, print) {
+                    Some(iseq) => iseq,
+                    None => std::process::exit(1),
                 }
             };
 
-            if file_body.len() == 0 {
-                return;
+            vm.trace_gc = trace_gc;
+            vm.jit_on &= jit;
+            if let Some(seed) = random_seed {
+                vm.random_state = builtins::math::random_state(seed);
             }
+            if debug {
+                // JIT-compiled code does not stop at breakpoints.
+                vm.jit_on = false;
+                vm.debugger = Some(Debugger::terminal(&source));
+            }
+            if let Some(addr) = inspect {
+                match inspector::listen(addr, file_name, &source) {
+                    Ok(inspector) => {
+                        vm.jit_on = false;
+                        vm.debugger = Some(Debugger::new(Box::new(inspector), true));
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "{}: Couldn't start the inspector on {}: {}",
+                            Colour::Red.bold().paint("error"),
+                            addr,
+                            e
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            let exe = std::env::current_exe()
+                .map(|exe| exe.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| "rapidus".to_string());
+            let mut argv = vec![exe];
+            argv.extend(script_argv);
+            argv.extend(args);
+            builtins::process::set_argv(&mut vm, argv);
 
-            if file_body.as_bytes()[0] == b'#' {
-                let first_ln = file_body.find('\n').unwrap_or(file_body.len());
-                file_body.drain(..first_ln);
+            if tracer.is_some() {
+                // JIT-compiled code is not traced.
+                vm.jit_on = false;
+                vm.tracer = tracer;
             }
 
-            let mut parser = parser::Parser::new(file_body);
+            if prof {
+                // JIT-compiled code is not sampled.
+                vm.jit_on = false;
+                vm.profiler = Some(Profiler::new(true, profiler::SAMPLING_INTERVAL));
+            }
 
-            let mut node = match parser.parse_all() {
-                Ok(ok) => ok,
-                Err(err) => {
-                    parser.handle_error(err);
-                    return;
+            let sp = vm.state.stack.len();
+            let res = vm.run(iseq);
+            // As in Node.js, the status is 1 if the script failed, and otherwise process.exitCode.
+            let mut status = builtins::process::exit_code(&mut vm);
+            match res {
+                Ok(_) if print => {
+                    let val = match vm.state.stack.len() > sp {
+                        true => vm.state.stack.pop().unwrap(),
+                        false => vm::value::Value::Undefined,
+                    };
+                    let val = match val {
+                        vm::value::Value::Empty => vm::value::Value::Undefined,
+                        val => val,
+                    };
+                    println!("{}", builtins::console::inspect(&val, false));
                 }
-            };
-
-            let mut vm = vm::vm::VM::new();
-            let mut iseq = vec![];
-            match vm.codegen.compile(&node, &mut iseq, false) {
-                Ok(()) => {}
-                Err(vm_codegen::Error::General { msg, token_pos }) => {
-                    parser.show_error_at(token_pos, msg.as_str());
-                    return;
+                Ok(_) => {}
+                Err(e) => {
+                    e.show_error_message();
+                    show_error_trace(&vm);
+                    status = 1;
                 }
-                Err(e) => panic!(e),
             }
 
-            vm.is_debug = trace;
-
-            if let Err(e) = vm.run(iseq) {
-                e.show_error_message();
+            if let Some(samples) = vm.profiler.as_mut().and_then(|p| p.end_run()) {
+                write_profile(&samples);
+            }
+            if status != 0 {
+                std::process::exit(status);
             }
         }
         Err(e) => panic!("Rapidus Internal Error: fork failed: {:?}", e),
     }
 }
 
+/// The tracer of '--trace', writing to 'file_name' or stderr. Reports an error and returns None
+/// if the file cannot be created.
+fn make_tracer(file_name: Option<&str>, function: Option<&str>) -> Option<Tracer> {
+    let output: Box<dyn Write> = match file_name {
+        Some(file_name) => match File::create(file_name) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!(
+                    "{}: Couldn't create '{}': {}",
+                    Colour::Red.bold().paint("error"),
+                    file_name,
+                    e
+                );
+                return None;
+            }
+        },
+        None => Box::new(std::io::stderr()),
+    };
+    Some(Tracer::new(output, function.map(|name| name.to_string())))
+}
+
+/// Write the samples of '--prof' to rapidus.folded.
+fn write_profile(samples: &profiler::Samples) {
+    let file_name = "rapidus.folded";
+    match std::fs::write(file_name, profiler::collapsed(samples)) {
+        Ok(()) => eprintln!("Profile written to {}", file_name),
+        Err(e) => eprintln!(
+            "{}: Couldn't write '{}': {}",
+            Colour::Red.bold().paint("error"),
+            file_name,
+            e
+        ),
+    }
+}
+
+/// The name of a script in messages: '<stdin>' for '-'.
+fn script_name(file_name: &str) -> &str {
+    match file_name {
+        "-" => "<stdin>",
+        file_name => file_name,
+    }
+}
+
+/// Read the whole file, or stdin if 'file_name' is '-'. Reports an error and returns None if it
+/// cannot be read.
+fn read_file(file_name: &str) -> Option<Vec<u8>> {
+    let mut file_body = vec![];
+    if file_name == "-" {
+        return match std::io::stdin().read_to_end(&mut file_body) {
+            Ok(_) => Some(file_body),
+            Err(e) => {
+                eprintln!(
+                    "{}: Couldn't read stdin: {}",
+                    Colour::Red.bold().paint("error"),
+                    e
+                );
+                None
+            }
+        };
+    }
+    match OpenOptions::new().read(true).open(file_name) {
+        Ok(mut ok) => match ok.read_to_end(&mut file_body).ok() {
+            Some(_) => Some(file_body),
+            None => {
+                eprintln!(
+                    "{}: Couldn't read the file '{}'",
+                    Colour::Red.bold().paint("error"),
+                    file_name,
+                );
+                None
+            }
+        },
+        Err(_e) => {
+            eprintln!(
+                "{}: No such file or directory '{}'",
+                Colour::Red.bold().paint("error"),
+                file_name,
+            );
+            None
+        }
+    }
+}
+
+/// Parse and compile a script, and make its source map. The value of the last expression
+/// statement is left on the stack if 'use_value'. Reports an error and returns None on failure.
+fn compile_source(
+    vm: &mut VM,
+    file_name: &str,
+    file_body: String,
+    use_value: bool,
+) -> Option<bytecode_gen::ByteCode> {
+    let file_name = script_name(file_name);
+    let mut parser = parser::Parser::new(file_body);
+    parser.file_name = Some(file_name.to_string());
+
+    let node = match parser.parse_all() {
+        Ok(ok) => ok,
+        Err(err) => {
+            parser.handle_error(err);
+            return None;
+        }
+    };
+
+    let mut iseq = vec![];
+    // Code compiled later, e.g. by eval(), is not in the source map.
+    vm.codegen.record_positions = true;
+    let res = vm.codegen.compile(&node, &mut iseq, use_value);
+    vm.codegen.record_positions = false;
+    match res {
+        Ok(()) => {}
+        Err(vm_codegen::Error::General { msg, token_pos })
+        | Err(vm_codegen::Error::Unimplemented { msg, token_pos }) => {
+            parser.show_error_at(token_pos, msg.as_str());
+            return None;
+        }
+    }
+    let positions = std::mem::replace(&mut vm.codegen.function_positions, vec![]);
+    vm.source_map = Some(SourceMap::new(file_name, &positions, &parser.lexer));
+    Some(iseq)
+}
+
+/// Load the source map of a precompiled script, written by 'compile --source-map', if it
+/// exists.
+fn load_source_map(file_name: &str) -> Option<SourceMap> {
+    let map_name = format!("{}.map", file_name);
+    let text = std::fs::read_to_string(&map_name).ok()?;
+    match SourceMap::from_text(&text) {
+        Ok(source_map) => Some(source_map),
+        Err(msg) => {
+            eprintln!(
+                "{}: Couldn't load '{}': {}",
+                Colour::Yellow.bold().paint("warning"),
+                map_name,
+                msg
+            );
+            None
+        }
+    }
+}
+
+/// Show where the uncaught error occurred, by the source map.
+fn show_error_trace(vm: &VM) {
+    let (source_map, trace) = match (&vm.source_map, &vm.error_trace) {
+        (Some(source_map), Some(trace)) => (source_map, trace),
+        _ => return,
+    };
+    for frame in trace {
+        if frame.name.is_empty() {
+            eprintln!("    at {}:{}:{}", source_map.file, frame.line, frame.column);
+        } else {
+            eprintln!(
+                "    at {} ({}:{}:{})",
+                frame.name, source_map.file, frame.line, frame.column
+            );
+        }
+    }
+}
+
+/// Parse a script and report all the syntax errors found. Returns None if there are any.
+fn parse_script(file_name: &str) -> Option<Node> {
+    let file_body = match read_file(file_name).map(String::from_utf8) {
+        Some(Ok(file_body)) => file_body,
+        Some(Err(_)) => {
+            eprintln!(
+                "{}: Couldn't read the file '{}'",
+                Colour::Red.bold().paint("error"),
+                file_name,
+            );
+            return None;
+        }
+        None => return None,
+    };
+
+    let file_name = script_name(file_name);
+    let mut parser = parser::Parser::new(file_body);
+    parser.file_name = Some(file_name.to_string());
+    match parser.parse_all_recovering() {
+        Ok(node) => Some(node),
+        Err(errors) => {
+            let count = errors.len();
+            for err in errors {
+                parser.handle_error(err);
+            }
+            eprintln!(
+                "{}: {} syntax error{} in '{}'",
+                Colour::Red.bold().paint("error"),
+                count,
+                if count == 1 { "" } else { "s" },
+                file_name
+            );
+            None
+        }
+    }
+}
+
+fn compile(file_name: &str, output: &str, source_map: bool, registers: bool, optimize: bool) {
+    let file_body = match read_file(file_name) {
+        Some(file_body) => file_body,
+        None => return,
+    };
+    let file_body = match String::from_utf8(file_body) {
+        Ok(file_body) => file_body,
+        Err(_) => {
+            eprintln!(
+                "{}: Couldn't read the file '{}'",
+                Colour::Red.bold().paint("error"),
+                file_name,
+            );
+            return;
+        }
+    };
+
+    let mut vm = VM::new();
+    vm.codegen.use_registers = registers;
+    vm.codegen.optimize = optimize;
+    let iseq = match compile_source(&mut vm, file_name, file_body, false) {
+        Some(iseq) => iseq,
+        None => return,
+    };
+
+    let bytes = match bytecode_file::serialize(&iseq, &vm.codegen.bytecode_gen) {
+        Ok(bytes) => bytes,
+        Err(msg) => {
+            eprintln!("{}: {}", Colour::Red.bold().paint("error"), msg);
+            return;
+        }
+    };
+    let mut files = vec![(output.to_string(), bytes)];
+    if source_map {
+        let text = vm.source_map.as_ref().unwrap().to_text();
+        files.push((format!("{}.map", output), text.into_bytes()));
+    }
+    for (name, bytes) in files {
+        if let Err(e) = File::create(&name).and_then(|mut f| f.write_all(&bytes)) {
+            eprintln!(
+                "{}: Couldn't write '{}': {}",
+                Colour::Red.bold().paint("error"),
+                name,
+                e
+            );
+            return;
+        }
+    }
+}
+
 #[test]
 fn vm_test() {
     // IMPORTANT: these tests should be run in a single thread.
-    use rapidus::test::{assert_file, execute_script, test_code, test_file};
+    use rapidus::test::{
+        assert_file, execute_script, test_code, test_file, test_file_with_registers,
+    };
     execute_script("for(var i = 0; i < 4; i++){ i }".to_string(), true);
     assert_file("trinity".to_string());
     assert_file("closure".to_string());
@@ -271,6 +974,18 @@ fn vm_test() {
         "'2,3,6,7,3,4,2,3,three1,5,4,1,2,three'".to_string(),
     );
     test_code("(100).toString(15)".to_string(), "'6a'".to_string());
+    test_code(
+        "var a = 2; a **= 2 ** 3; '' + [a, (-2) ** 2, 0.1 + 0.2, 1e21, 1e-7, -0]".to_string(),
+        "'256,4,0.30000000000000004,1e+21,1e-7,0'".to_string(),
+    );
+    test_code(
+        "[Math.round(-2.5), Math.max(), Math.min(3, 1, 2), Math.sign(-3), Math.clz32(1), Math.imul(3, 4), Math.E == Math.exp(1)]".to_string(),
+        "[-2, -1 / 0, 1, -1, 31, 12, true]".to_string(),
+    );
+    test_code(
+        "[parseInt(' -0x1fz'), parseInt('z', 36), parseFloat('3.5e2px'), Number('  12  '), isNaN('abc'), Number.isNaN('abc'), Number.isInteger(5), (1.005).toFixed(2), (2.5).toFixed(0), (123.456).toPrecision(4), (0.00001).toPrecision(1)]".to_string(),
+        "[-31, 35, 350, 12, true, false, true, '1.00', '3', '123.5', '0.00001']".to_string(),
+    );
     test_code(
         "'死して屍拾う者なし'[4]".to_string(),
         "'拾'".to_string(),
@@ -284,6 +999,34 @@ fn vm_test() {
         "[0,0,0,1,0,2,1,0,2,0,2,1,2,2]".to_string(),
     );
     test_file("this".to_string(), "[1,101,124]".to_string());
+    test_file(
+        "wrapper".to_string(),
+        "[4,true,false,true,3,'b','abc',true,false,'object is truthy',42,'12',false,'ff','xyz','true']".to_string(),
+    );
+    test_file(
+        "bigint".to_string(),
+        "[18446744073709551616n,18446744073709551615n,-3n,-1n,4n,-5n,6n,-5n,1180591620717411303424n,true,true,true,false,true,'1a',42n,16n,'ff',-1n,255n,'cannot mix','division by zero','not a constructor']".to_string(),
+    );
+    test_file(
+        "typedarray".to_string(),
+        "[8,8,2,8,4294967295,-1,2,4,7,undefined,'255,0,2,2',0.1,0,18,52,13330,1.5,-1,9,2,'-1,-1,1,2',1,8,true,'out of range']".to_string(),
+    );
+    test_file(
+        "inline_cache".to_string(),
+        "[2,2,2,10,5,10,100,6,2,undefined,undefined]".to_string(),
+    );
+    test_file(
+        "registers".to_string(),
+        "[25,-7,2.5,3,true,false,true,false,610,114,'a12','3b',7n,285]".to_string(),
+    );
+    test_file_with_registers(
+        "registers".to_string(),
+        "[25,-7,2.5,3,true,false,true,false,610,114,'a12','3b',7n,285]".to_string(),
+    );
+    test_file(
+        "object_literal".to_string(),
+        "[2,2,4,'comp',5,10,'a,1',2,'getter']".to_string(),
+    );
     test_file("trycatch".to_string(), "[ 0, 2, 1, 10110 ]".to_string());
     test_file(
         "prototypes".to_string(),
@@ -298,4 +1041,252 @@ fn vm_test() {
         "arguments2".to_string(),
         "[10,15,20,25,15,10,'OK',20,25,'OK',10,'NG',20,25,'NG']".to_string(),
     );
+    test_file(
+        "fs".to_string(),
+        "[true,false,'hello','死して屍',3,255,'a.txt,b.txt,c.bin,sub',12,true,false,true,'Error',false]"
+            .to_string(),
+    );
+    test_code(
+        "[process.argv.length, process.cwd().length > 0, process.hrtime().length, process.hrtime([0,0])[0] >= 0, process.platform.length > 0, process.env.RAPIDUS_UNDEFINED_VARIABLE]"
+            .to_string(),
+        "[0,true,2,true,true,undefined]".to_string(),
+    );
+    test_file(
+        "promise".to_string(),
+        "[0,1,'rejected',2,'oops','adopted','later']".to_string(),
+    );
+    test_file(
+        "strict".to_string(),
+        "[true,true,false,'reference error: \\'undeclared\\' is not defined',2]".to_string(),
+    );
+    test_code(
+        "var r = []; function s() { 'use strict'; try { leaked = 1 } catch (e) { r.push('caught') } return this } function l() { leaked = 2; return this } r.push(s() === undefined); r.push(l() !== undefined); r.push(leaked); r"
+            .to_string(),
+        "['caught',true,true,2]".to_string(),
+    );
+    test_file(
+        "hoisting".to_string(),
+        "['hoisted',1,5,2,[undefined,'g'],[undefined,undefined],[undefined,1],'e']".to_string(),
+    );
+    test_file(
+        "with_eval".to_string(),
+        "[3,10,'outer','global','x','captured',[0,2,'no k'],'thrown','no w',3,5,'local','global',10,'syntax error']".to_string(),
+    );
+    test_file(
+        "function".to_string(),
+        "[3,1,24,'global','function decl(a, b) { return a + b }','function (n) { return n }','method() { return 1 }','function () { [native code] }','syntax error']".to_string(),
+    );
+    test_file(
+        "bind".to_string(),
+        "[13,14,1,'bound getX',11,[5,7,undefined],3,'type error']".to_string(),
+    );
+    test_file(
+        "function_name".to_string(),
+        "[['decl',3],['anon',1],['inner',2],['assigned',0],['prop',2],['method',1],'decl',1,'bound decl']".to_string(),
+    );
+    test_file(
+        "tail_call".to_string(),
+        "[5000050000,2,42,4,5,'done']".to_string(),
+    );
+    test_file(
+        "deep_recursion".to_string(),
+        "[9000,'RangeError: Maximum call stack size exceeded',10,3001,'bottom']".to_string(),
+    );
+    test_file(
+        "member_assign".to_string(),
+        "[1,['v',4],'obj,key,val','obj,key',3,[36,2],[[11,2,3],1],[1,3,3,1],[7,7,0,0],8]".to_string(),
+    );
+    test_file(
+        "sparse_array".to_string(),
+        "[1000001,'far',undefined,'1,2',undefined,4,3,'two','not index','negative','RangeError: Invalid array length','RangeError: Invalid array length',4294967295,0,'1,2,3,,,,7',undefined,4,'1,2,3,,x','4294967294',4294967295,2,'RangeError: Invalid string length','RangeError: Too many elements passed to a list']".to_string(),
+    );
+    test_file(
+        "array_static".to_string(),
+        "[3,[1,2,3],['a'],true,false,false,[7],3,['a','b','c'],[10,21,32],['x','y'],[0,1,4],[4,5],[3,6],'not callable','null']".to_string(),
+    );
+    test_file(
+        "array_sort".to_string(),
+        "[[1,2,3],[1,10,100,9],[1,9,10,100],['a','b','c',undefined],['b','d','a','c'],4,'1,3,,',true,'thrown','not callable']".to_string(),
+    );
+    test_file(
+        "string_replace".to_string(),
+        "['a+b-c','abc','price: $10','a[a|c]c','a$1$c','hello world611','-aaa','xYz','thrown']".to_string(),
+    );
+    test_file(
+        "string_unicode".to_string(),
+        "[4,'a',55357,56832,128512,56832,'b','',undefined,NaN,undefined,true,2,3,'本',3,true,3]".to_string(),
+    );
+    test_file(
+        "string_concat".to_string(),
+        "[108890,'i0','9',1,true,64,63]".to_string(),
+    );
+    test_file(
+        "uri".to_string(),
+        "['a%20b%26c%2Fd%3F%C3%A9','http://x.com/a%20b?q=1&r=%C3%A9#h','%F0%9F%98%80','a b&c/d?é','%3B%2F é%23',true,'URIError: URI malformed','overlong','lone surrogate','a%20b+%E9%u20AC','a b€%zz%']".to_string(),
+    );
+    test_file(
+        "global_this".to_string(),
+        "[1,'bar',true,true,true,true,6,'[object Object]']".to_string(),
+    );
+    test_file(
+        "var_decl_list".to_string(),
+        "[[1,2,undefined],[undefined,'f'],30,6,[[0,3],[1,2]],['s','st','stu',undefined]]".to_string(),
+    );
+    test_file(
+        "asi".to_string(),
+        "[3,undefined,['yes','no'],[0,6],'ab',[0,2],[2,4,6],'e']".to_string(),
+    );
+    test_file(
+        "number_literal".to_string(),
+        "[255,493,10,1000000000,0.0015,1000000,65535,5.5,200,true,8,8.5,'1','1.50']".to_string(),
+    );
+    test_file(
+        "string_escape".to_string(),
+        "[5,['ABC','ABC',2,true],[0,'A',3],'line continued',1,'v']".to_string(),
+    );
+    test_file(
+        "comment".to_string(),
+        "[1,2,3,4,'/* not a comment */',6]".to_string(),
+    );
+    test_file(
+        "gc".to_string(),
+        "[true,true,true,true,true,true,6]".to_string(),
+    );
+    test_file(
+        "wasm".to_string(),
+        "[true,false,3,14,[7],true,true]".to_string(),
+    );
+    test_file(
+        "structured_clone".to_string(),
+        "[true,true,true,true,'x',true,true,true,true,20,2,true,6,true,true]".to_string(),
+    );
+    test_file(
+        "text_encoding".to_string(),
+        "[10,[97,195,226,240],'utf-8',true,'hi',true,true,'aGVsbG8=','hello',true,true,true]"
+            .to_string(),
+    );
+    test_file(
+        "weak".to_string(),
+        "[true,1,true,false,true,false,'primitive key',true,false,2,1,true,true]".to_string(),
+    );
+    test_file(
+        "gc_generations".to_string(),
+        "[true,10,9000,1000]".to_string(),
+    );
+    test_file(
+        "callobj_reuse".to_string(),
+        "[610,undefined,undefined,500500,3,1,6,4,5]".to_string(),
+    );
+    test_file(
+        "slot_locals".to_string(),
+        "[[6,undefined,1],9,2,'hoisted',['inner','outer'],5,[1,3],5,11,[3,3,4]]".to_string(),
+    );
+    test_file(
+        "closures".to_string(),
+        "[3,2,7,113,[3,3,3],[false,true],[6,7,40]]".to_string(),
+    );
+    test_file(
+        "global_cache".to_string(),
+        "[3,10,15,'not defined','defined','with','catch','eval',3,2]".to_string(),
+    );
+    test_file(
+        "process_events".to_string(),
+        "[['rejection',1],['rejection',3],['timer','uncaughtException'],'after']".to_string(),
+    );
+    test_file(
+        "intl".to_string(),
+        "[['1,234,567.891','1.234.567,891','-$1,234.50','26%','3.00','12345','1,234.5','de-DE','en-GB'],['1/5/2020','05/01/2020','5.1.2020','Sunday, January 5, 2020','Sonntag, 5. Januar 2020','2:03 PM','14:03:00','11:03 PM','+09:00'],'RangeError: Invalid time zone specified: Mars/Base']".to_string(),
+    );
+    test_file(
+        "prototype".to_string(),
+        "[true,true,true,true,true,{a:1},'hi',true,true,true,true,2,3,3,2,'cyclic']".to_string(),
+    );
+    test_file(
+        "property_order".to_string(),
+        "[['0','1','2','b','a','z','01'],['0','1','3','extra'],{9:4,10:3,y:1,x:2}]".to_string(),
+    );
+    test_file(
+        "conditional".to_string(),
+        "['a','d',['a',0,'d'],2,2,2,2,1,1,'positive','negative','zero','A','B','C','D',41,0,3,-1,'y']".to_string(),
+    );
+    test_file(
+        "trailing_commas".to_string(),
+        "[3,undefined,'1,,3',3,undefined,'x',3,1,2,2,3]".to_string(),
+    );
+    test_file(
+        "template".to_string(),
+        "['hello world!','4undefined','a1,2b','<3>',11,'ab',[3,true,true,'x',1,2],'hi!',[undefined,true],true,['0','1'],true,'x1y2z','a-bc','error']".to_string(),
+    );
+    test_file(
+        "array_prototype".to_string(),
+        "[[1,2,[3,[4]],5],[1,2,3,4,5],[1,2],[1,0,3,2],[[1],[2]],true,[1,0,0,4],['x','x','x'],',4,5,4,5',true,[1,1,2,3,5],[1,2,3,1,2],3,undefined,undefined,undefined,{value:[0,'a'],done:false},[0,1,2],['a',undefined,'c'],[[0,'a'],[1,undefined],[2,'c']],2,true,true,'error']".to_string(),
+    );
+    test_file(
+        "at".to_string(),
+        "[1,3,1,undefined,undefined,2,2,1,'a','c',undefined,undefined,1,undefined,undefined,undefined,'b','y',undefined,[undefined,undefined,1],1,undefined,3,'neg']".to_string(),
+    );
+    test_file(
+        "object_prototype".to_string(),
+        "[true,false,false,true,false,true,false,false,true,true,false,true,[true,false],true,false,false,true,true,false,true,false,'[object Object]','1,,3',true,'error']".to_string(),
+    );
+    test_file(
+        "number_edge_cases".to_string(),
+        "[Infinity,-Infinity,NaN,Infinity,-Infinity,1.5,-2,2,NaN,NaN,2,1.5,true,false,true,true,Infinity,true,true,'error']".to_string(),
+    );
+    test_file(
+        "unary_operators".to_string(),
+        "[42,0,NaN,1,0,NaN,7,3,true,true,false,true,true,false,true,-6,0,-4,-2,-1,-1,-1,-42,-3,true,12,'not']".to_string(),
+    );
+    test_file(
+        "this_binding".to_string(),
+        "[true,true,true,true,true,true,true,true,true,true,true,false,true,2,true,1,6,[[2,4],[6]]]".to_string(),
+    );
+    test_file(
+        "chained_calls".to_string(),
+        "[3,[6,8],5,10,2,6,3,12,'called']".to_string(),
+    );
+    test_file(
+        "new_expression".to_string(),
+        "[0,0,true,3,true,3,5,0,6,0,3,true,'error']".to_string(),
+    );
+    test_file(
+        "object_spread".to_string(),
+        "[{x:1,y:3,z:4,w:5},['x','y','z','w'],1,0,'got',['g'],{own:1},{},{0:1,1:2},{0:'h',1:'i'},1,{a:3,b:2},{0:'x',1:'y'},{y:30,z:40},'error']".to_string(),
+    );
+    test_file(
+        "util_inspect".to_string(),
+        "[true,true,true,true,true,true,true,true,true,true,true,true]".to_string(),
+    );
+    test_file(
+        "error_stack".to_string(),
+        "['Error: boom','Error',false,10,'Error','Custom: m','Error: c','error']".to_string(),
+    );
+    test_file(
+        "queue_microtask".to_string(),
+        "['error','sync','then 1','job','caught oops','after error','nested job','timeout','job of timeout','then of timeout','second timeout']".to_string(),
+    );
+    test_file(
+        "performance".to_string(),
+        "[true,true,true,'mark',10,0,'x',10,15,3,10,['given','a','a to b','from a','b'],2,25,1,1,'error']".to_string(),
+    );
+    test_file(
+        "path_os".to_string(),
+        "['/','a/c/d','.','/a/c/','/a/b','c.js','c','.gz','',true,false,'/a/c/d',true,true,true,true,'b',true,false,true,'error',true,true,true,true,true,true]".to_string(),
+    );
+    test_file(
+        "child_process".to_string(),
+        "[true,'in',true,3,true,true,0,true,'',true,true,true,'SIGTERM',null,'ENOENT']".to_string(),
+    );
+    test_file(
+        "crypto".to_string(),
+        "[36,'4',true,true,true,true,'type mismatch','quota exceeded','900150983cd24fb0d6963f7d28e17f72','a9993e364706816aba3e25717850c26c9cd0d89d','ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad','ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=',20,'digest already called','not supported']".to_string(),
+    );
+    test_file(
+        "return_value".to_string(),
+        "[undefined,'positive',undefined,undefined,10,10,1,3,'error',true,true]".to_string(),
+    );
+    test_file(
+        "array_length".to_string(),
+        "['1,2,3',true,3,'1,2,3',6,3,true,'1,2,3,,,',3,6,'10,20,30,,,',4,'1,2,,','1,2','1,2',2,'1',true,true,true,true,true,1]".to_string(),
+    );
 }
@@ -1,3 +1,5 @@
+use num_bigint::BigInt;
+
 // TODO: Support all features: https://tc39.github.io/ecma262/#prod-FormalParameter
 //       Especially, destructing of rest parameter.
 #[derive(Clone, Debug, PartialEq)]
@@ -24,6 +26,25 @@ impl FormalParameter {
 pub enum PropertyDefinition {
     IdentifierReference(String),
     Property(String, Node),
+    ComputedProperty(Node, Node), // Key expression, value
+    MethodDefinition(MethodDefinitionKind, String, Node), // Kind, name, function
+    Spread(Node),                 // ...expression
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MethodDefinitionKind {
+    Ordinary,
+    Get,
+    Set,
+}
+
+/// Whether a function is declared with 'async' and with '*'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FunctionKind {
+    Normal,
+    Generator,      // function*
+    Async,          // async function
+    AsyncGenerator, // async function*
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -35,8 +56,16 @@ pub struct Node {
 #[derive(Clone, Debug, PartialEq)]
 pub enum NodeBase {
     StatementList(Vec<Node>),
-    FunctionDecl(String, FormalParameters, Box<Node>), // name, params, body
-    FunctionExpr(Option<String>, FormalParameters, Box<Node>), // Name, params, body
+    // name, params, body, source text, kind
+    FunctionDecl(String, FormalParameters, Box<Node>, String, FunctionKind),
+    // Name, params, body, source text, kind
+    FunctionExpr(
+        Option<String>,
+        FormalParameters,
+        Box<Node>,
+        String,
+        FunctionKind,
+    ),
     VarDecl(String, Option<Box<Node>>),
     Member(Box<Node>, String),
     Index(Box<Node>, Box<Node>),
@@ -45,11 +74,16 @@ pub enum NodeBase {
     If(Box<Node>, Box<Node>, Box<Node>), // Cond, Then, Else
     While(Box<Node>, Box<Node>),         // Cond, Body
     For(Box<Node>, Box<Node>, Box<Node>, Box<Node>), // Init, Cond, Step, Body
+    ForAwaitOf(Box<Node>, Box<Node>, Box<Node>), // Target, iterable, body
+    With(Box<Node>, Box<Node>),          // Object, Body
     Assign(Box<Node>, Box<Node>),
+    CompoundAssign(Box<Node>, Box<Node>, BinOp), // a op= b
     UnaryOp(Box<Node>, UnaryOp),
     BinaryOp(Box<Node>, Box<Node>, BinOp),
     TernaryOp(Box<Node>, Box<Node>, Box<Node>),
     Return(Option<Box<Node>>),
+    Await(Box<Node>),
+    Yield(Option<Box<Node>>, bool), // Argument, delegating (yield*)
     Label(String, Box<Node>),
     Break(Option<String>),
     Continue(Option<String>),
@@ -57,6 +91,8 @@ pub enum NodeBase {
     Throw(Box<Node>),
     Array(Vec<Node>),
     Object(Vec<PropertyDefinition>),
+    Template(Vec<Option<String>>, Vec<String>, Vec<Node>), // Cooked strings, raw strings, substitutions
+    TaggedTemplate(Box<Node>, Box<Node>),                  // Tag, template
     Identifier(String),
     This,
     Arguments,
@@ -65,9 +101,93 @@ pub enum NodeBase {
     String(String),
     Boolean(bool),
     Number(f64),
+    BigInt(BigInt),
     Nope,
 }
 
+/// Define the method '$name' listing the nodes directly in a node, as references or as mutable
+/// references with 'mut'. Passes over the whole AST walk with it.
+macro_rules! children { ( $name:ident, $iter:ident, $as_ref:ident $(, $mut_:tt)* ) => {
+    /// The nodes directly in the node, including the default parameters and the body of a
+    /// function.
+    pub fn $name(& $($mut_)* self) -> Vec<& $($mut_)* Node> {
+        match self.base {
+            NodeBase::StatementList(ref $($mut_)* nodes)
+            | NodeBase::Array(ref $($mut_)* nodes)
+            | NodeBase::Template(_, _, ref $($mut_)* nodes) => nodes.$iter().collect(),
+            NodeBase::FunctionDecl(_, ref $($mut_)* params, ref $($mut_)* body, _, _)
+            | NodeBase::FunctionExpr(_, ref $($mut_)* params, ref $($mut_)* body, _, _) => {
+                let mut nodes: Vec<& $($mut_)* Node> = params
+                    .$iter()
+                    .filter_map(|param| param.init.$as_ref())
+                    .collect();
+                nodes.push(& $($mut_)* **body);
+                nodes
+            }
+            NodeBase::Call(ref $($mut_)* callee, ref $($mut_)* args) => {
+                let mut nodes: Vec<& $($mut_)* Node> = args.$iter().collect();
+                nodes.push(& $($mut_)* **callee);
+                nodes
+            }
+            NodeBase::Object(ref $($mut_)* properties) => properties
+                .$iter()
+                .flat_map(|property| match *property {
+                    PropertyDefinition::IdentifierReference(_) => vec![],
+                    PropertyDefinition::Property(_, ref $($mut_)* node)
+                    | PropertyDefinition::MethodDefinition(_, _, ref $($mut_)* node)
+                    | PropertyDefinition::Spread(ref $($mut_)* node) => vec![node],
+                    PropertyDefinition::ComputedProperty(ref $($mut_)* key, ref $($mut_)* node) => {
+                        vec![key, node]
+                    }
+                })
+                .collect(),
+            NodeBase::VarDecl(_, Some(ref $($mut_)* init)) => vec![& $($mut_)* **init],
+            NodeBase::Return(Some(ref $($mut_)* val))
+            | NodeBase::Yield(Some(ref $($mut_)* val), _) => vec![& $($mut_)* **val],
+            NodeBase::Member(ref $($mut_)* node, _)
+            | NodeBase::Await(ref $($mut_)* node)
+            | NodeBase::New(ref $($mut_)* node)
+            | NodeBase::UnaryOp(ref $($mut_)* node, _)
+            | NodeBase::Label(_, ref $($mut_)* node)
+            | NodeBase::Throw(ref $($mut_)* node) => vec![& $($mut_)* **node],
+            // The template of a tagged template is evaluated before its tag.
+            NodeBase::TaggedTemplate(ref $($mut_)* b, ref $($mut_)* a)
+            | NodeBase::Index(ref $($mut_)* a, ref $($mut_)* b)
+            | NodeBase::While(ref $($mut_)* a, ref $($mut_)* b)
+            | NodeBase::With(ref $($mut_)* a, ref $($mut_)* b)
+            | NodeBase::Assign(ref $($mut_)* a, ref $($mut_)* b)
+            | NodeBase::CompoundAssign(ref $($mut_)* a, ref $($mut_)* b, _)
+            | NodeBase::BinaryOp(ref $($mut_)* a, ref $($mut_)* b, _) => {
+                vec![& $($mut_)* **a, & $($mut_)* **b]
+            }
+            NodeBase::If(ref $($mut_)* a, ref $($mut_)* b, ref $($mut_)* c)
+            | NodeBase::ForAwaitOf(ref $($mut_)* a, ref $($mut_)* b, ref $($mut_)* c)
+            | NodeBase::TernaryOp(ref $($mut_)* a, ref $($mut_)* b, ref $($mut_)* c) => {
+                vec![& $($mut_)* **a, & $($mut_)* **b, & $($mut_)* **c]
+            }
+            NodeBase::For(ref $($mut_)* a, ref $($mut_)* b, ref $($mut_)* c, ref $($mut_)* d)
+            | NodeBase::Try(ref $($mut_)* a, ref $($mut_)* b, ref $($mut_)* c, ref $($mut_)* d) => {
+                vec![& $($mut_)* **a, & $($mut_)* **b, & $($mut_)* **c, & $($mut_)* **d]
+            }
+            NodeBase::VarDecl(_, None)
+            | NodeBase::Return(None)
+            | NodeBase::Yield(None, _)
+            | NodeBase::Break(_)
+            | NodeBase::Continue(_)
+            | NodeBase::Identifier(_)
+            | NodeBase::This
+            | NodeBase::Arguments
+            | NodeBase::Undefined
+            | NodeBase::Null
+            | NodeBase::String(_)
+            | NodeBase::Boolean(_)
+            | NodeBase::Number(_)
+            | NodeBase::BigInt(_)
+            | NodeBase::Nope => vec![],
+        }
+    }
+} }
+
 impl Node {
     pub fn new(base: NodeBase, pos: usize) -> Node {
         Node {
@@ -76,22 +196,41 @@ impl Node {
         }
     }
 
+    children!(children, iter, as_ref);
+    children!(children_mut, iter_mut, as_mut, mut);
+
+    /// Whether the statement list (a script or a function body) starts with a 'use strict'
+    /// directive.
+    pub fn has_use_strict_directive(&self) -> bool {
+        match self.base {
+            NodeBase::StatementList(ref body) => body
+                .iter()
+                .take_while(|node| match node.base {
+                    NodeBase::String(_) => true,
+                    _ => false,
+                })
+                .any(|node| node.base == NodeBase::String("use strict".to_string())),
+            _ => false,
+        }
+    }
+
     pub fn definitely_returns(&self) -> bool {
         match self.base {
             NodeBase::StatementList(ref body) => match body.last() {
                 Some(node) => node.definitely_returns(),
                 None => false,
             },
-            NodeBase::Label(_, ref body) => body.definitely_returns(),
-            NodeBase::FunctionExpr(_, _, ref body) | NodeBase::FunctionDecl(_, _, ref body) => {
-                body.definitely_returns()
-            }
+            NodeBase::Label(_, ref body) | NodeBase::With(_, ref body) => body.definitely_returns(),
+            NodeBase::FunctionExpr(_, _, ref body, _, _)
+            | NodeBase::FunctionDecl(_, _, ref body, _, _) => body.definitely_returns(),
             NodeBase::If(_, ref then_, ref else_) => {
                 then_.definitely_returns() && else_.definitely_returns()
             }
             NodeBase::Return(_) => true,
             NodeBase::Array(_)
             | NodeBase::Object(_)
+            | NodeBase::Template(_, _, _)
+            | NodeBase::TaggedTemplate(_, _)
             | NodeBase::Identifier(_)
             | NodeBase::This
             | NodeBase::Null
@@ -100,15 +239,20 @@ impl Node {
             | NodeBase::String(_)
             | NodeBase::Boolean(_)
             | NodeBase::Number(_)
+            | NodeBase::BigInt(_)
             | NodeBase::Nope
             | NodeBase::Break(_)
             | NodeBase::Continue(_)
             | NodeBase::Assign(_, _)
+            | NodeBase::CompoundAssign(_, _, _)
             | NodeBase::UnaryOp(_, _)
             | NodeBase::BinaryOp(_, _, _)
             | NodeBase::TernaryOp(_, _, _)
             | NodeBase::While(_, _)
             | NodeBase::For(_, _, _, _)
+            | NodeBase::ForAwaitOf(_, _, _)
+            | NodeBase::Await(_)
+            | NodeBase::Yield(_, _)
             | NodeBase::New(_)
             | NodeBase::Call(_, _)
             | NodeBase::VarDecl(_, _)
@@ -147,13 +291,16 @@ impl NodeBase {
                         BinOp::Sub => Some(NodeBase::Number(l - r)),
                         BinOp::Mul => Some(NodeBase::Number(l * r)),
                         BinOp::Div => Some(NodeBase::Number(l / r)),
-                        BinOp::Rem => Some(NodeBase::Number(((l as u64) % (r as u64)) as f64)),
+                        BinOp::Rem => Some(NodeBase::Number(l % r)),
                         BinOp::Exp => Some(NodeBase::Number(l.powf(r))),
                         BinOp::And => Some(NodeBase::Number(((l as u64) & (r as u64)) as f64)),
                         BinOp::Or => Some(NodeBase::Number(((l as u64) | (r as u64)) as f64)),
                         BinOp::Xor => Some(NodeBase::Number(((l as u64) ^ (r as u64)) as f64)),
-                        BinOp::LAnd => Some(NodeBase::Boolean((l > 0.0) && (r > 0.0))),
-                        BinOp::LOr => Some(NodeBase::Boolean((l > 0.0) || (r > 0.0))),
+                        // && and || give one of the operands, whose truthiness they test.
+                        BinOp::LAnd if l != 0.0 && !l.is_nan() => Some(NodeBase::Number(r)),
+                        BinOp::LAnd => Some(NodeBase::Number(l)),
+                        BinOp::LOr if l != 0.0 && !l.is_nan() => Some(NodeBase::Number(l)),
+                        BinOp::LOr => Some(NodeBase::Number(r)),
                         BinOp::Eq => Some(NodeBase::Boolean(l == r)),
                         BinOp::Ne => Some(NodeBase::Boolean(l != r)),
                         BinOp::SEq => Some(NodeBase::Boolean(l == r)),
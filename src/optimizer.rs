@@ -0,0 +1,540 @@
+use bytecode_gen::{self, ByteCode, VMInst};
+use rustc_hash::{FxHashMap, FxHashSet};
+use vm::{value::Value, vm::ConstantTable};
+
+// Peephole optimizer. It runs on the bytecode of a function (or of a whole script) after the
+// code generation is finished.
+//
+// - PUSH_INT32 of a value which fits in a byte becomes PUSH_INT8.
+// - Arithmetic and comparison of numeric constants are folded.
+// - A push immediately popped is removed.
+// - A jump to a JMP goes directly to the final destination, and a JMP to the next
+//   instruction is removed.
+// - Instructions after RETURN, THROW, JMP and RETURN_TRY are removed until a jump
+//   destination.
+
+/// Id of the position just past the last instruction.
+const END_ID: usize = ::std::usize::MAX;
+
+#[derive(Clone, Debug)]
+struct Inst {
+    /// index of the instruction in the original code. Jumps refer to instructions by id
+    /// so that instructions can be removed and resized.
+    id: usize,
+    /// encoded instruction. The operands of jumps are rewritten by encode().
+    code: Vec<u8>,
+    /// ids of the destinations of a jump.
+    targets: Vec<usize>,
+}
+
+pub fn optimize(iseq: &mut ByteCode, const_table: &mut ConstantTable) {
+    optimize_with_positions(iseq, const_table, &mut vec![]);
+}
+
+/// optimize() which also moves 'positions', (pc, source position) sorted by pc, to the
+/// optimized code. The position of a removed instruction goes to the next remaining one.
+pub fn optimize_with_positions(
+    iseq: &mut ByteCode,
+    const_table: &mut ConstantTable,
+    positions: &mut Vec<(usize, usize)>,
+) {
+    let mut insts = match decode(iseq) {
+        Some(insts) => insts,
+        // Leave code we don't understand as it is.
+        None => return,
+    };
+    // pcs of the original instructions, indexed by id.
+    let mut original_pcs = vec![];
+    let mut pc = 0;
+    for inst in &insts {
+        original_pcs.push(pc);
+        pc += inst.code.len();
+    }
+
+    loop {
+        let mut changed = false;
+        changed |= shrink_push_int32(&mut insts);
+        changed |= fold_constants(&mut insts, const_table);
+        changed |= remove_push_pop(&mut insts);
+        changed |= thread_jumps(&mut insts);
+        changed |= remove_dead_code(&mut insts);
+        if !changed {
+            break;
+        }
+    }
+
+    *iseq = encode(&insts);
+    move_positions(&insts, &original_pcs, positions);
+}
+
+fn move_positions(
+    insts: &Vec<Inst>,
+    original_pcs: &Vec<usize>,
+    positions: &mut Vec<(usize, usize)>,
+) {
+    let mut pcs = vec![];
+    let mut len = 0;
+    for inst in insts {
+        pcs.push(len);
+        len += inst.code.len();
+    }
+
+    let mut moved: Vec<(usize, usize)> = vec![];
+    for &(pc, pos) in positions.iter() {
+        let id = match original_pcs.binary_search(&pc) {
+            Ok(id) => id,
+            Err(id) => id.saturating_sub(1),
+        };
+        // The remaining instructions are still sorted by id.
+        let pc = match insts.binary_search_by_key(&id, |inst| inst.id) {
+            Ok(i) | Err(i) => pcs.get(i).cloned().unwrap_or(len),
+        };
+        // A later position is of a more specific part of the code.
+        match moved.last_mut() {
+            Some(last) if last.0 == pc => last.1 = pos,
+            _ => moved.push((pc, pos)),
+        }
+    }
+    *positions = moved;
+}
+
+fn decode(iseq: &ByteCode) -> Option<Vec<Inst>> {
+    let mut insts = vec![];
+    let mut ids = FxHashMap::default();
+    let mut pc = 0;
+    while pc < iseq.len() {
+        let size = VMInst::get_inst_size(iseq[pc])?;
+        if pc + size > iseq.len() {
+            return None;
+        }
+        ids.insert(pc as isize, insts.len());
+        insts.push(Inst {
+            id: insts.len(),
+            code: iseq[pc..pc + size].to_vec(),
+            targets: vec![],
+        });
+        pc += size;
+    }
+    ids.insert(pc as isize, END_ID);
+
+    let mut pc = 0;
+    for inst in &mut insts {
+        for dst in decode_inst(&inst.code).destinations(pc) {
+            inst.targets.push(*ids.get(&dst)?);
+        }
+        pc += inst.code.len() as isize;
+    }
+
+    Some(insts)
+}
+
+fn decode_inst(code: &[u8]) -> bytecode_gen::Inst {
+    bytecode_gen::Inst::decode(code).unwrap()
+}
+
+fn encode_inst(inst: bytecode_gen::Inst) -> Vec<u8> {
+    let mut code = vec![];
+    inst.encode(&mut code);
+    code
+}
+
+fn encode(insts: &Vec<Inst>) -> ByteCode {
+    let mut pcs = FxHashMap::default();
+    let mut len = 0;
+    for inst in insts {
+        pcs.insert(inst.id, len as isize);
+        len += inst.code.len();
+    }
+    pcs.insert(END_ID, len as isize);
+
+    let mut iseq = Vec::with_capacity(len);
+    for inst in insts {
+        if inst.targets.is_empty() {
+            iseq.extend(&inst.code);
+            continue;
+        }
+        let pc = iseq.len() as isize;
+        let dsts: Vec<isize> = inst.targets.iter().map(|id| pcs[id]).collect();
+        let mut jump = decode_inst(&inst.code);
+        jump.set_destinations(pc, &dsts);
+        jump.encode(&mut iseq);
+    }
+    iseq
+}
+
+/// ids of all the jump destinations.
+fn jump_targets(insts: &Vec<Inst>) -> FxHashSet<usize> {
+    insts
+        .iter()
+        .flat_map(|inst| inst.targets.iter().cloned())
+        .collect()
+}
+
+/// Remove the instructions marked in 'removed'. Jumps to a removed instruction go to the
+/// next remaining one instead.
+fn compact(insts: &mut Vec<Inst>, removed: &Vec<bool>) {
+    let mut redirect = FxHashMap::default();
+    let mut next = END_ID;
+    for (inst, removed) in insts.iter().zip(removed.iter()).rev() {
+        if *removed {
+            redirect.insert(inst.id, next);
+        } else {
+            next = inst.id;
+        }
+    }
+    if redirect.is_empty() {
+        return;
+    }
+
+    let mut i = 0;
+    insts.retain(|_| {
+        i += 1;
+        !removed[i - 1]
+    });
+    for inst in insts.iter_mut() {
+        for target in &mut inst.targets {
+            if let Some(next) = redirect.get(target) {
+                *target = *next;
+            }
+        }
+    }
+}
+
+fn shrink_push_int32(insts: &mut Vec<Inst>) -> bool {
+    let mut changed = false;
+    for inst in insts.iter_mut() {
+        if let bytecode_gen::Inst::PushInt32 { n } = decode_inst(&inst.code) {
+            if ::std::i8::MIN as i32 <= n && n <= ::std::i8::MAX as i32 {
+                inst.code = encode_inst(bytecode_gen::Inst::PushInt8 { n: n as i8 });
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+fn fold_constants(insts: &mut Vec<Inst>, const_table: &mut ConstantTable) -> bool {
+    let targets = jump_targets(insts);
+    let mut removed = vec![false; insts.len()];
+    let mut changed = false;
+
+    for i in 0..insts.len() {
+        let op = insts[i].code[0];
+        // The folded instructions except the first one must not be jumped to.
+        if op == VMInst::NEG {
+            if i < 1 || removed[i - 1] || targets.contains(&insts[i].id) {
+                continue;
+            }
+            if let Some(n) = pushed_number(&insts[i - 1], const_table) {
+                insts[i - 1].code = push_number(-n, const_table);
+                removed[i] = true;
+                changed = true;
+            }
+        } else if is_foldable_binary_op(op) {
+            if i < 2
+                || removed[i - 2]
+                || removed[i - 1]
+                || targets.contains(&insts[i - 1].id)
+                || targets.contains(&insts[i].id)
+            {
+                continue;
+            }
+            let lhs = pushed_number(&insts[i - 2], const_table);
+            let rhs = pushed_number(&insts[i - 1], const_table);
+            if let (Some(l), Some(r)) = (lhs, rhs) {
+                if let Some(code) = fold_binary_op(op, l, r, const_table) {
+                    insts[i - 2].code = code;
+                    removed[i - 1] = true;
+                    removed[i] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    compact(insts, &removed);
+    changed
+}
+
+fn is_foldable_binary_op(op: u8) -> bool {
+    match op {
+        VMInst::ADD
+        | VMInst::SUB
+        | VMInst::MUL
+        | VMInst::DIV
+        | VMInst::REM
+        | VMInst::EXP
+        | VMInst::LT
+        | VMInst::GT
+        | VMInst::LE
+        | VMInst::GE => true,
+        _ => false,
+    }
+}
+
+/// Fold 'l op r' in the same way as the VM does. Returns the instruction which pushes the
+/// result.
+fn fold_binary_op(op: u8, l: f64, r: f64, const_table: &mut ConstantTable) -> Option<Vec<u8>> {
+    let n = match op {
+        VMInst::ADD => l + r,
+        VMInst::SUB => l - r,
+        VMInst::MUL => l * r,
+        VMInst::DIV => l / r,
+        VMInst::REM => l % r,
+        VMInst::EXP if r.is_nan() || (l.abs() == 1.0 && r.is_infinite()) => ::std::f64::NAN,
+        VMInst::EXP => l.powf(r),
+        VMInst::LT => return Some(push_bool(l < r)),
+        VMInst::GT => return Some(push_bool(l > r)),
+        VMInst::LE => return Some(push_bool(l <= r)),
+        VMInst::GE => return Some(push_bool(l >= r)),
+        _ => return None,
+    };
+    Some(push_number(n, const_table))
+}
+
+/// The number an instruction pushes, if it pushes a numeric constant.
+fn pushed_number(inst: &Inst, const_table: &ConstantTable) -> Option<f64> {
+    match decode_inst(&inst.code) {
+        bytecode_gen::Inst::PushInt8 { n } => Some(n as f64),
+        bytecode_gen::Inst::PushInt32 { n } => Some(n as f64),
+        bytecode_gen::Inst::PushConst { id } => match const_table.value[id as usize] {
+            Value::Number(n) => Some(n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn push_number(n: f64, const_table: &mut ConstantTable) -> Vec<u8> {
+    // -0 must not become 0.
+    if n.fract() == 0.0 && !(n == 0.0 && n.is_sign_negative()) {
+        if ::std::i8::MIN as f64 <= n && n <= ::std::i8::MAX as f64 {
+            return encode_inst(bytecode_gen::Inst::PushInt8 { n: n as i8 });
+        }
+        if ::std::i32::MIN as f64 <= n && n <= ::std::i32::MAX as f64 {
+            return encode_inst(bytecode_gen::Inst::PushInt32 { n: n as i32 });
+        }
+    }
+
+    let id = const_table.value.len();
+    const_table.value.push(Value::Number(n));
+    encode_inst(bytecode_gen::Inst::PushConst { id: id as i32 })
+}
+
+fn push_bool(b: bool) -> Vec<u8> {
+    vec![if b {
+        VMInst::PUSH_TRUE
+    } else {
+        VMInst::PUSH_FALSE
+    }]
+}
+
+fn remove_push_pop(insts: &mut Vec<Inst>) -> bool {
+    let targets = jump_targets(insts);
+    let mut removed = vec![false; insts.len()];
+    let mut changed = false;
+
+    for i in 1..insts.len() {
+        if insts[i].code[0] == VMInst::POP
+            && !removed[i - 1]
+            && has_no_side_effect(insts[i - 1].code[0])
+            && !targets.contains(&insts[i].id)
+        {
+            removed[i - 1] = true;
+            removed[i] = true;
+            changed = true;
+        }
+    }
+
+    compact(insts, &removed);
+    changed
+}
+
+/// Whether the instruction only pushes a value.
+fn has_no_side_effect(op: u8) -> bool {
+    match op {
+        VMInst::PUSH_INT8
+        | VMInst::PUSH_INT32
+        | VMInst::PUSH_FALSE
+        | VMInst::PUSH_TRUE
+        | VMInst::PUSH_CONST
+        | VMInst::PUSH_THIS
+        | VMInst::PUSH_ARGUMENTS
+        | VMInst::PUSH_UNDEFINED
+        | VMInst::PUSH_REG
+        | VMInst::GET_UPVALUE
+        | VMInst::DOUBLE => true,
+        _ => false,
+    }
+}
+
+fn thread_jumps(insts: &mut Vec<Inst>) -> bool {
+    let index: FxHashMap<usize, usize> = insts
+        .iter()
+        .enumerate()
+        .map(|(i, inst)| (inst.id, i))
+        .collect();
+    let mut removed = vec![false; insts.len()];
+    let mut changed = false;
+
+    for i in 0..insts.len() {
+        let op = insts[i].code[0];
+        if op != VMInst::JMP && op != VMInst::JMP_IF_FALSE {
+            continue;
+        }
+
+        let mut target = insts[i].targets[0];
+        // Bounded in case jumps make a cycle.
+        for _ in 0..insts.len() {
+            match index.get(&target) {
+                Some(&j) if insts[j].code[0] == VMInst::JMP && insts[j].targets[0] != target => {
+                    target = insts[j].targets[0]
+                }
+                _ => break,
+            }
+        }
+        if target != insts[i].targets[0] {
+            insts[i].targets[0] = target;
+            changed = true;
+        }
+
+        let next = insts.get(i + 1).map_or(END_ID, |inst| inst.id);
+        if op == VMInst::JMP && target == next {
+            removed[i] = true;
+            changed = true;
+        }
+    }
+
+    compact(insts, &removed);
+    changed
+}
+
+fn remove_dead_code(insts: &mut Vec<Inst>) -> bool {
+    let targets = jump_targets(insts);
+    let mut removed = vec![false; insts.len()];
+    let mut changed = false;
+
+    let mut reachable = true;
+    for (i, inst) in insts.iter().enumerate() {
+        // END is kept so that the code never runs off its end.
+        if targets.contains(&inst.id) || inst.code[0] == VMInst::END {
+            reachable = true;
+        }
+        if !reachable {
+            removed[i] = true;
+            changed = true;
+            continue;
+        }
+        match inst.code[0] {
+            VMInst::RETURN
+            | VMInst::TAIL_CALL
+            | VMInst::THROW
+            | VMInst::JMP
+            | VMInst::RETURN_TRY => reachable = false,
+            _ => {}
+        }
+    }
+
+    compact(insts, &removed);
+    changed
+}
+
+#[test]
+fn test() {
+    use bytecode_gen::{slice_to_int32, ByteCodeGen};
+
+    let mut gen = ByteCodeGen::new();
+    let mut iseq = vec![];
+    // 1 + 2 * -3
+    gen.gen_push_int32(1, &mut iseq);
+    gen.gen_push_int8(2, &mut iseq);
+    gen.gen_push_int8(3, &mut iseq);
+    gen.gen_neg(&mut iseq);
+    gen.gen_mul(&mut iseq);
+    gen.gen_add(&mut iseq);
+    // 1 / 4 < 0.5
+    gen.gen_push_int8(1, &mut iseq);
+    gen.gen_push_int8(4, &mut iseq);
+    gen.gen_div(&mut iseq);
+    gen.gen_push_const(Value::Number(0.5), &mut iseq);
+    gen.gen_lt(&mut iseq);
+    // 1e10; undefined;
+    gen.gen_push_const(Value::Number(1e10), &mut iseq);
+    gen.gen_pop(&mut iseq);
+    gen.gen_push_undefined(&mut iseq);
+    gen.gen_pop(&mut iseq);
+    gen.gen_end(&mut iseq);
+    let mut positions = vec![
+        (0, 100),
+        (5, 101),
+        (12, 200),
+        (23, 300),
+        (29, 400),
+        (31, 500),
+    ];
+    optimize_with_positions(&mut iseq, &mut gen.const_table, &mut positions);
+    assert_eq!(
+        iseq,
+        vec![
+            VMInst::PUSH_INT8,
+            -5i8 as u8,
+            VMInst::PUSH_TRUE,
+            VMInst::END
+        ]
+    );
+    assert_eq!(positions, vec![(0, 100), (2, 200), (3, 500)]);
+
+    // 0 * -1 is -0.
+    let mut iseq = vec![];
+    gen.gen_push_int8(0, &mut iseq);
+    gen.gen_push_int8(1, &mut iseq);
+    gen.gen_neg(&mut iseq);
+    gen.gen_mul(&mut iseq);
+    optimize(&mut iseq, &mut gen.const_table);
+    assert_eq!(iseq[0], VMInst::PUSH_CONST);
+    let n = match gen.const_table.value[slice_to_int32(&iseq[1..5]) as usize] {
+        Value::Number(n) => n,
+        _ => unreachable!(),
+    };
+    assert!(n == 0.0 && n.is_sign_negative());
+
+    // 0000 JmpIfFalse 0013
+    // 0005 PushTrue
+    // 0006 Return
+    // 0007 Jmp 0018       <- dead
+    // 000c PushFalse      <- dead
+    // 000d Pop            <- dead
+    // 000e Jmp 0018       <- dead
+    // 0013 Jmp 0018       <- destination of JmpIfFalse
+    // 0018 Jmp 001d       <- jump to the next instruction
+    // 001d End
+    let mut iseq = vec![];
+    gen.gen_jmp_if_false(14, &mut iseq);
+    gen.gen_push_bool(true, &mut iseq);
+    gen.gen_return(&mut iseq);
+    gen.gen_jmp(12, &mut iseq);
+    gen.gen_push_bool(false, &mut iseq);
+    gen.gen_pop(&mut iseq);
+    gen.gen_jmp(5, &mut iseq);
+    gen.gen_jmp(0, &mut iseq);
+    gen.gen_jmp(0, &mut iseq);
+    gen.gen_end(&mut iseq);
+    optimize(&mut iseq, &mut gen.const_table);
+    // 0000 JmpIfFalse 0007
+    // 0005 PushTrue
+    // 0006 Return
+    // 0007 End
+    assert_eq!(
+        iseq,
+        vec![
+            VMInst::JMP_IF_FALSE,
+            2,
+            0,
+            0,
+            0,
+            VMInst::PUSH_TRUE,
+            VMInst::RETURN,
+            VMInst::END
+        ]
+    );
+}
@@ -1,8 +1,12 @@
 pub use lexer;
-use node::{BinOp, FormalParameter, FormalParameters, Node, NodeBase, PropertyDefinition, UnaryOp};
-use token::{get_string_for_symbol, Keyword, Kind, Symbol, Token};
+use node::{
+    BinOp, FormalParameter, FormalParameters, FunctionKind, MethodDefinitionKind, Node, NodeBase,
+    PropertyDefinition, UnaryOp,
+};
+use token::{get_string_for_keyword, get_string_for_symbol, Keyword, Kind, Symbol, Token};
 
 use ansi_term::Colour;
+use std::fmt;
 
 macro_rules! expect {
     ($self:ident, $kind:expr, $msg:expr) => {{
@@ -25,25 +29,82 @@ pub enum Error {
     General(usize, String),
 }
 
+/// A syntax error reported by parse().
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+    /// position in the source.
+    pub pos: usize,
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+/// Parse 'source' as a script. On failure, returns all the syntax errors found (see
+/// Parser::parse_all_recovering()). The AST can be converted into ESTree by estree::program().
+pub fn parse(source: &str) -> Result<Node, Vec<SyntaxError>> {
+    let mut parser = Parser::new(source.to_string());
+    parser.parse_all_recovering().map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|err| {
+                let (pos, msg) = parser.error_position_and_message(err);
+                let (_, _, line) = parser.lexer.get_code_around_err_point(pos);
+                SyntaxError {
+                    pos: pos,
+                    line: line,
+                    msg: msg,
+                }
+            })
+            .collect()
+    })
+}
+
 #[derive(Clone, Debug)]
 pub struct Parser {
     pub lexer: lexer::Lexer,
+    /// whether the code being read is strict mode code.
+    strict: bool,
+    /// whether the code being read is in an async function, where 'await' is an operator.
+    in_async: bool,
+    /// whether the code being read is in a generator, where 'yield' is an operator.
+    in_generator: bool,
+    /// whether to go on parsing after a syntax error. See parse_all_recovering().
+    recover: bool,
+    /// syntax errors recovered from.
+    errors: Vec<Error>,
+    /// name of the file the code is read from, shown in syntax errors.
+    pub file_name: Option<String>,
 }
 
 impl Parser {
     pub fn new(code: String) -> Parser {
         Parser {
             lexer: lexer::Lexer::new(code),
+            strict: false,
+            in_async: false,
+            in_generator: false,
+            recover: false,
+            errors: vec![],
+            file_name: None,
         }
     }
 
     /// display error position in the source code.
     pub fn show_error_at(&self, pos: usize, msg: &str) {
         let (source_at_err_point, _pos, line) = self.lexer.get_code_around_err_point(pos);
+        let place = match self.file_name {
+            Some(ref file_name) => format!("{}:{}", file_name, line),
+            None => format!("line {}", line),
+        };
         eprintln!(
-            "{}(line {}): {}\n{}",
+            "{}({}): {}\n{}",
             Colour::Red.bold().paint("parse error"),
-            line,
+            place,
             msg,
             source_at_err_point,
         );
@@ -51,22 +112,20 @@ impl Parser {
 
     /// display syntax error message.
     pub fn handle_error(&self, err: Error) {
+        let (pos, msg) = self.error_position_and_message(err);
+        self.show_error_at(pos, msg.as_str());
+    }
+
+    /// position in the source and message of a syntax error.
+    pub fn error_position_and_message(&self, err: Error) -> (usize, String) {
         match err {
             Error::NormalEOF => unreachable!(),
             Error::Expect(pos, msg)
             | Error::General(pos, msg)
-            | Error::UnexpectedToken(pos, msg) => {
-                self.show_error_at(pos, msg.as_str());
-                return;
-            }
-            Error::UnexpectedEOF(msg) => {
-                self.show_error_at(self.lexer.pos, format!("unexpected EOF. {}", msg).as_str())
-            }
-            Error::InvalidToken(pos) => self.show_error_at(pos, "Invalid token."),
-            Error::UnsupportedFeature(pos) => {
-                self.show_error_at(pos, "Unsupported feature.");
-                return;
-            }
+            | Error::UnexpectedToken(pos, msg) => (pos, msg),
+            Error::UnexpectedEOF(msg) => (self.lexer.pos, format!("unexpected EOF. {}", msg)),
+            Error::InvalidToken(pos) => (pos, "Invalid token.".to_string()),
+            Error::UnsupportedFeature(pos) => (pos, "Unsupported feature.".to_string()),
         }
     }
 }
@@ -76,12 +135,68 @@ impl Parser {
         self.lexer.tokenize_all()?;
         self.read_script()
     }
+
+    /// Parse the whole script, going on after syntax errors to find as many as possible.
+    /// A statement with an error is skipped up to the next ';', line terminator or the end of a
+    /// block. Returns all the errors in the order of their positions if there are any.
+    pub fn parse_all_recovering(&mut self) -> Result<Node, Vec<Error>> {
+        let mut errors = self.lexer.tokenize_all_recovering();
+        self.recover = true;
+        let res = self.read_script();
+        self.recover = false;
+        errors.append(&mut self.errors);
+        match res {
+            Ok(node) => {
+                if errors.is_empty() {
+                    return Ok(node);
+                }
+            }
+            Err(Error::NormalEOF) => errors.push(Error::UnexpectedEOF("".to_string())),
+            Err(err) => errors.push(err),
+        }
+        errors.sort_by_key(|err| self.error_position_and_message(err.clone()).0);
+        Err(errors)
+    }
 }
 
 impl Parser {
     fn read_script(&mut self) -> Result<Node, Error> {
+        self.strict = self.has_use_strict_directive();
         self.read_statement_list(false)
     }
+
+    /// https://tc39.github.io/ecma262/#sec-directive-prologues-and-the-use-strict-directive
+    /// Whether the statements to be read start with directives including 'use strict'.
+    fn has_use_strict_directive(&self) -> bool {
+        let buf = &self.lexer.buf;
+        let mut i = 0;
+        loop {
+            while buf
+                .get(i)
+                .map_or(false, |tok| tok.kind == Kind::LineTerminator)
+            {
+                i += 1;
+            }
+            let directive = match buf.get(i) {
+                Some(Token {
+                    kind: Kind::String(ref s),
+                    ..
+                }) => s,
+                _ => return false,
+            };
+            match buf.get(i + 1).map(|tok| &tok.kind) {
+                Some(Kind::Symbol(Symbol::Semicolon)) => i += 2,
+                None | Some(Kind::LineTerminator) | Some(Kind::Symbol(Symbol::ClosingBrace)) => {
+                    i += 1
+                }
+                // A string followed by an operator is not a directive. e.g. 'use strict' + x
+                _ => return false,
+            }
+            if directive == "use strict" {
+                return true;
+            }
+        }
+    }
 }
 
 impl Parser {
@@ -93,6 +208,9 @@ impl Parser {
         };
         let mut items = vec![];
         loop {
+            // Line terminators after a statement are left by the lookahead for ';'.
+            while self.lexer.skip(Kind::LineTerminator) {}
+
             match self
                 .lexer
                 .skip_except_lineterminator(Kind::Symbol(Symbol::ClosingBrace))
@@ -101,10 +219,12 @@ impl Parser {
                     if break_when_closingbrase {
                         break;
                     } else {
-                        return Err(Error::UnexpectedToken(
+                        let err = Error::UnexpectedToken(
                             self.lexer.get_prev_pos(),
                             "unexpected token '}'.".to_string(),
-                        ));
+                        );
+                        self.recover_from(err)?;
+                        continue;
                     }
                 }
                 Ok(false) => {}
@@ -116,18 +236,25 @@ impl Parser {
             /* && self.lexer.eof()*/
             {
                 if break_when_closingbrase {
-                    return Err(Error::UnexpectedEOF("expected '}'.".to_string()));
-                } else {
-                    break;
+                    let err = Error::UnexpectedEOF("expected '}'.".to_string());
+                    // Every unclosed block reaches here at the end of the script.
+                    if self.recover && self.errors.last() == Some(&err) {
+                        break;
+                    }
+                    self.recover_from(err)?;
                 }
+                break;
             }
 
             match self.read_statement_list_item() {
                 Ok(ok) => items.push(ok),
                 Err(Error::NormalEOF) => {
-                    return Err(Error::UnexpectedEOF("".to_string()));
+                    self.recover_from(Error::UnexpectedEOF("".to_string()))?;
+                }
+                Err(e) => {
+                    self.recover_from(e)?;
+                    self.skip_erroneous_statement();
                 }
-                Err(e) => return Err(e),
             }
 
             while match self
@@ -143,9 +270,46 @@ impl Parser {
         Ok(Node::new(NodeBase::StatementList(items), pos))
     }
 
+    /// Record 'err' and go on in recovery mode, or return it.
+    fn recover_from(&mut self, err: Error) -> Result<(), Error> {
+        if !self.recover {
+            return Err(err);
+        }
+        self.errors.push(err);
+        Ok(())
+    }
+
+    /// Skip the rest of a statement with a syntax error, up to a ';' or line terminator, or
+    /// the '}' closing a block opened in the statement. A '}' closing the enclosing block is
+    /// left.
+    fn skip_erroneous_statement(&mut self) {
+        let mut depth = 0;
+        while let Ok(tok) = self.lexer.peek(0) {
+            match tok.kind {
+                Kind::Symbol(Symbol::ClosingBrace) if depth == 0 => return,
+                Kind::Symbol(Symbol::ClosingBrace) if depth == 1 => {
+                    self.lexer.next().unwrap();
+                    return;
+                }
+                Kind::Symbol(Symbol::ClosingBrace) => depth -= 1,
+                Kind::Symbol(Symbol::OpeningBrace) => depth += 1,
+                Kind::Symbol(Symbol::Semicolon) | Kind::LineTerminator if depth == 0 => {
+                    self.lexer.next().unwrap();
+                    return;
+                }
+                _ => {}
+            }
+            self.lexer.next().unwrap();
+        }
+    }
+
     fn read_statement_list_item(&mut self) -> Result<Node, Error> {
-        if self.is_declaration() {
+        if self.is_hoistable_declaration() {
             self.read_declaration()
+        } else if self.is_lexical_declaration() {
+            let decl = self.read_declaration()?;
+            self.read_semicolon()?;
+            Ok(decl)
         } else {
             self.read_statement()
         }
@@ -175,33 +339,63 @@ impl Parser {
             }
         }
 
+        match tok.kind {
+            Kind::Keyword(Keyword::If) => return self.read_if_statement(),
+            Kind::Keyword(Keyword::While) => return self.read_while_statement(),
+            Kind::Keyword(Keyword::For) => return self.read_for_statement(),
+            Kind::Keyword(Keyword::Try) => return self.read_try_statement(),
+            Kind::Keyword(Keyword::With) => return self.read_with_statement(),
+            Kind::Symbol(Symbol::OpeningBrace) => return self.read_block_statement(),
+            Kind::Symbol(Symbol::Semicolon) => return Ok(Node::new(NodeBase::Nope, tok.pos)),
+            _ => {}
+        }
+
+        // The other statements end with ';'.
         let stmt = match tok.kind {
-            Kind::Keyword(Keyword::If) => self.read_if_statement(),
             Kind::Keyword(Keyword::Var) => self.read_variable_statement(),
-            Kind::Keyword(Keyword::While) => self.read_while_statement(),
-            Kind::Keyword(Keyword::For) => self.read_for_statement(),
             Kind::Keyword(Keyword::Return) => self.read_return_statement(),
             Kind::Keyword(Keyword::Break) => self.read_break_statement(),
             Kind::Keyword(Keyword::Continue) => self.read_continue_statement(),
-            Kind::Keyword(Keyword::Try) => self.read_try_statement(),
             Kind::Keyword(Keyword::Throw) => self.read_throw_statement(),
-            Kind::Symbol(Symbol::OpeningBrace) => self.read_block_statement(),
-            Kind::Symbol(Symbol::Semicolon) => return Ok(Node::new(NodeBase::Nope, tok.pos)),
             _ => {
                 self.lexer.unget(&tok);
                 self.read_expression_statement()
             }
+        }?;
+        self.read_semicolon()?;
+        Ok(stmt)
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-automatic-semicolon-insertion
+    /// Reads ';' at the end of a statement. ';' is inserted if the next token is on another line
+    /// or is '}', or at the end of the script.
+    fn read_semicolon(&mut self) -> Result<(), Error> {
+        let tok = match self.lexer.peek(0) {
+            Ok(tok) => tok,
+            Err(_) => return Ok(()),
         };
-
-        match self
-            .lexer
-            .skip_except_lineterminator(Kind::Symbol(Symbol::Semicolon))
-        {
-            Ok(_) | Err(Error::NormalEOF) => {}
-            Err(e) => return Err(e),
+        match tok.kind {
+            Kind::Symbol(Symbol::Semicolon) => {
+                self.lexer.next()?;
+                Ok(())
+            }
+            Kind::LineTerminator | Kind::Symbol(Symbol::ClosingBrace) => Ok(()),
+            _ => Err(Error::Expect(tok.pos, "expect ';'".to_string())),
         }
+    }
 
-        stmt
+    /// Whether the statement being read ends here because ';' follows or is inserted. Used by
+    /// the restricted productions, e.g. 'return [no LineTerminator here] Expression'.
+    fn is_statement_end(&mut self) -> bool {
+        match self.lexer.peek(0) {
+            Ok(tok) => match tok.kind {
+                Kind::LineTerminator
+                | Kind::Symbol(Symbol::Semicolon)
+                | Kind::Symbol(Symbol::ClosingBrace) => true,
+                _ => false,
+            },
+            Err(_) => true,
+        }
     }
 }
 
@@ -215,18 +409,32 @@ impl Parser {
 impl Parser {
     /// https://tc39.github.io/ecma262/#prod-VariableStatement
     fn read_variable_statement(&mut self) -> Result<Node, Error> {
-        self.read_variable_declaration_list()
+        self.read_variable_declaration_list(false)
     }
 
     /// https://tc39.github.io/ecma262/#prod-VariableDeclarationList
-    fn read_variable_declaration_list(&mut self) -> Result<Node, Error> {
+    /// Also reads the BindingList of 'let' and 'const', whose declarations are the same as
+    /// 'var' ones for now. Each declarator of a 'const' needs an initializer.
+    fn read_variable_declaration_list(&mut self, is_const: bool) -> Result<Node, Error> {
         let pos = self.lexer.get_prev_pos();
         let mut list = vec![];
 
         loop {
-            list.push(self.read_variable_declaration()?);
-            if !self.lexer.skip(Kind::Symbol(Symbol::Comma)) {
-                break;
+            let decl = self.read_variable_declaration()?;
+            if let NodeBase::VarDecl(ref name, None) = decl.base {
+                if is_const {
+                    return Err(Error::UnexpectedToken(
+                        decl.pos,
+                        format!("Missing initializer in const declaration '{}'.", name),
+                    ));
+                }
+            }
+            list.push(decl);
+            match self.lexer.peek_except_lineterminator() {
+                Ok(ref tok) if tok.kind == Kind::Symbol(Symbol::Comma) => {
+                    self.lexer.next_except_lineterminator()?;
+                }
+                _ => break,
             }
         }
 
@@ -246,10 +454,16 @@ impl Parser {
             }
         };
 
-        if self
+        // The declaration may be the last thing in the script.
+        let has_init = match self
             .lexer
-            .skip_except_lineterminator(Kind::Symbol(Symbol::Assign))?
+            .skip_except_lineterminator(Kind::Symbol(Symbol::Assign))
         {
+            Ok(has_init) => has_init,
+            Err(Error::NormalEOF) => false,
+            Err(e) => return Err(e),
+        };
+        if has_init {
             Ok(Node::new(
                 NodeBase::VarDecl(name, Some(Box::new(self.read_initializer()?))),
                 pos,
@@ -322,15 +536,48 @@ impl Parser {
         ))
     }
 
+    /// https://tc39.github.io/ecma262/#prod-WithStatement
+    fn read_with_statement(&mut self) -> Result<Node, Error> {
+        let pos = self.lexer.get_prev_pos();
+
+        if self.strict {
+            return Err(Error::General(
+                pos,
+                "with statement is not allowed in strict mode.".to_string(),
+            ));
+        }
+
+        expect!(self, Kind::Symbol(Symbol::OpeningParen), "expect '('");
+
+        let object = self.read_expression()?;
+
+        expect!(self, Kind::Symbol(Symbol::ClosingParen), "expect ')'");
+
+        let body = self.read_statement()?;
+
+        Ok(Node::new(
+            NodeBase::With(Box::new(object), Box::new(body)),
+            pos,
+        ))
+    }
+
     fn read_for_statement(&mut self) -> Result<Node, Error> {
         let pos = self.lexer.get_prev_pos();
 
+        if self.in_async && self.lexer.skip(Kind::Identifier("await".to_string())) {
+            return self.read_for_await_of_statement(pos);
+        }
+
         expect!(self, Kind::Symbol(Symbol::OpeningParen), "expect '('");
 
         let init = if self.lexer.skip(Kind::Keyword(Keyword::Var)) {
             let init = self.read_variable_statement()?;
             expect!(self, Kind::Symbol(Symbol::Semicolon), "expect ';'");
             init
+        } else if self.is_lexical_declaration() {
+            let init = self.read_declaration()?;
+            expect!(self, Kind::Symbol(Symbol::Semicolon), "expect ';'");
+            init
         } else if self.lexer.skip(Kind::Symbol(Symbol::Semicolon)) {
             Node::new(NodeBase::Nope, self.lexer.get_prev_pos())
         } else {
@@ -367,19 +614,48 @@ impl Parser {
             pos,
         ))
     }
+
+    /// Reads '(target of iterable) body' after 'for await'. 'pos' is the position of 'for'.
+    fn read_for_await_of_statement(&mut self, pos: usize) -> Result<Node, Error> {
+        expect!(self, Kind::Symbol(Symbol::OpeningParen), "expect '('");
+
+        let is_lexical_declaration = self.is_lexical_declaration();
+        let target = if is_lexical_declaration || self.lexer.skip(Kind::Keyword(Keyword::Var)) {
+            if is_lexical_declaration {
+                // 'let' or 'const'
+                self.lexer.next()?;
+            }
+            let decl_pos = self.lexer.get_prev_pos();
+            let decl = self.read_variable_declaration()?;
+            Node::new(NodeBase::StatementList(vec![decl]), decl_pos)
+        } else {
+            self.read_left_hand_side_expression()?
+        };
+
+        expect!(self, Kind::Identifier("of".to_string()), "expect 'of'");
+
+        let iterable = self.read_assignment_expression()?;
+
+        expect!(self, Kind::Symbol(Symbol::ClosingParen), "expect ')'");
+
+        let body = self.read_statement()?;
+
+        Ok(Node::new(
+            NodeBase::ForAwaitOf(Box::new(target), Box::new(iterable), Box::new(body)),
+            pos,
+        ))
+    }
 }
 
 impl Parser {
     fn read_break_statement(&mut self) -> Result<Node, Error> {
         let pos = self.lexer.get_prev_pos();
+        // no LineTerminator here
+        if self.is_statement_end() {
+            return Ok(Node::new(NodeBase::Break(None), pos));
+        }
         let tok = self.lexer.next()?;
         match tok.kind {
-            Kind::LineTerminator
-            | Kind::Symbol(Symbol::Semicolon)
-            | Kind::Symbol(Symbol::ClosingBrace) => {
-                self.lexer.unget(&tok);
-                Ok(Node::new(NodeBase::Break(None), pos))
-            }
             Kind::Identifier(name) => Ok(Node::new(NodeBase::Break(Some(name)), pos)),
             _ => Err(Error::UnexpectedToken(
                 tok.pos,
@@ -390,14 +666,12 @@ impl Parser {
 
     fn read_continue_statement(&mut self) -> Result<Node, Error> {
         let pos = self.lexer.get_prev_pos();
+        // no LineTerminator here
+        if self.is_statement_end() {
+            return Ok(Node::new(NodeBase::Continue(None), pos));
+        }
         let tok = self.lexer.next()?;
         match tok.kind {
-            Kind::LineTerminator
-            | Kind::Symbol(Symbol::Semicolon)
-            | Kind::Symbol(Symbol::ClosingBrace) => {
-                self.lexer.unget(&tok);
-                Ok(Node::new(NodeBase::Continue(None), pos))
-            }
             Kind::Identifier(name) => Ok(Node::new(NodeBase::Continue(Some(name)), pos)),
             _ => Err(Error::UnexpectedToken(
                 tok.pos,
@@ -410,17 +684,19 @@ impl Parser {
 macro_rules! expression { ( $name:ident, $lower:ident, [ $( $op:path ),* ] ) => {
     fn $name (&mut self) -> Result<Node, Error> {
         let mut lhs = self. $lower ()?;
-        while let Ok(tok) = self.lexer.next() {
-            let pos = self.lexer.get_current_pos();
+        // A line terminator before an operator does not end the expression.
+        while let Ok(tok) = self.lexer.peek_except_lineterminator() {
             match tok.kind {
                 Kind::Symbol(ref op) if $( op == &$op )||* => {
+                    self.lexer.next_except_lineterminator()?;
+                    let pos = self.lexer.get_current_pos();
                     lhs = Node::new(NodeBase::BinaryOp(
                         Box::new(lhs),
                         Box::new(self. $lower ()?),
                         op.as_binop().unwrap(),
                     ), pos);
                 }
-                _ => { self.lexer.unget(&tok); break }
+                _ => break,
             }
         }
         Ok(lhs)
@@ -438,22 +714,23 @@ impl Parser {
     /// https://tc39.github.io/ecma262/#prod-AssignmentExpression
     // TODO: Implement all features.
     fn read_assignment_expression(&mut self) -> Result<Node, Error> {
+        // An expression may start on the next line, e.g. an argument after '('.
+        while self.lexer.skip(Kind::LineTerminator) {}
+        let is_yield = |tok: Token| tok.kind == Kind::Identifier("yield".to_string());
+        if self.in_generator && self.lexer.peek(0).map_or(false, is_yield) {
+            return self.read_yield_expression();
+        }
         let pos = self.lexer.get_current_pos();
         let mut lhs = self.read_conditional_expression()?;
-        if let Ok(tok) = self.lexer.next() {
+        if let Ok(tok) = self.lexer.peek_except_lineterminator() {
             macro_rules! assignop {
                 ($op:ident) => {{
+                    self.lexer.next_except_lineterminator()?;
                     lhs = Node::new(
-                        NodeBase::Assign(
-                            Box::new(lhs.clone()),
-                            Box::new(Node::new(
-                                NodeBase::BinaryOp(
-                                    Box::new(lhs),
-                                    Box::new(self.read_assignment_expression()?),
-                                    BinOp::$op,
-                                ),
-                                pos,
-                            )),
+                        NodeBase::CompoundAssign(
+                            Box::new(lhs),
+                            Box::new(self.read_assignment_expression()?),
+                            BinOp::$op,
                         ),
                         pos,
                     );
@@ -461,6 +738,7 @@ impl Parser {
             }
             match tok.kind {
                 Kind::Symbol(Symbol::Assign) => {
+                    self.lexer.next_except_lineterminator()?;
                     lhs = Node::new(
                         NodeBase::Assign(
                             Box::new(lhs),
@@ -474,30 +752,61 @@ impl Parser {
                 Kind::Symbol(Symbol::AssignMul) => assignop!(Mul),
                 Kind::Symbol(Symbol::AssignDiv) => assignop!(Div),
                 Kind::Symbol(Symbol::AssignMod) => assignop!(Rem),
-                _ => self.lexer.unget(&tok),
+                Kind::Symbol(Symbol::AssignExp) => assignop!(Exp),
+                _ => {}
             }
         }
         Ok(lhs)
     }
 
+    /// https://tc39.github.io/ecma262/#prod-YieldExpression
+    fn read_yield_expression(&mut self) -> Result<Node, Error> {
+        let pos = self.lexer.get_current_pos();
+        self.lexer.next()?;
+
+        if self.lexer.skip(Kind::Symbol(Symbol::Asterisk)) {
+            let arg = self.read_assignment_expression()?;
+            return Ok(Node::new(NodeBase::Yield(Some(Box::new(arg)), true), pos));
+        }
+
+        // 'yield' has no argument at the end of a line or of an expression.
+        let has_arg = match self.lexer.peek(0) {
+            Ok(tok) => match tok.kind {
+                Kind::LineTerminator
+                | Kind::Symbol(Symbol::ClosingParen)
+                | Kind::Symbol(Symbol::ClosingBrace)
+                | Kind::Symbol(Symbol::ClosingBoxBracket)
+                | Kind::Symbol(Symbol::Colon)
+                | Kind::Symbol(Symbol::Comma)
+                | Kind::Symbol(Symbol::Semicolon) => false,
+                _ => true,
+            },
+            Err(_) => false,
+        };
+        let arg = if has_arg {
+            Some(Box::new(self.read_assignment_expression()?))
+        } else {
+            None
+        };
+        Ok(Node::new(NodeBase::Yield(arg, false), pos))
+    }
+
     /// https://tc39.github.io/ecma262/#prod-ConditionalExpression
     fn read_conditional_expression(&mut self) -> Result<Node, Error> {
         let pos = self.lexer.get_current_pos();
 
         let lhs = self.read_logical_or_expression()?;
 
-        if let Ok(tok) = self.lexer.next() {
-            match tok.kind {
-                Kind::Symbol(Symbol::Question) => {
-                    let then_ = self.read_assignment_expression()?;
-                    expect!(self, Kind::Symbol(Symbol::Colon), "expect ':'");
-                    let else_ = self.read_assignment_expression()?;
-                    return Ok(Node::new(
-                        NodeBase::TernaryOp(Box::new(lhs), Box::new(then_), Box::new(else_)),
-                        pos,
-                    ));
-                }
-                _ => self.lexer.unget(&tok),
+        if let Ok(tok) = self.lexer.peek_except_lineterminator() {
+            if tok.kind == Kind::Symbol(Symbol::Question) {
+                self.lexer.next_except_lineterminator()?;
+                let then_ = self.read_assignment_expression()?;
+                expect!(self, Kind::Symbol(Symbol::Colon), "expect ':'");
+                let else_ = self.read_assignment_expression()?;
+                return Ok(Node::new(
+                    NodeBase::TernaryOp(Box::new(lhs), Box::new(then_), Box::new(else_)),
+                    pos,
+                ));
             }
         }
 
@@ -581,8 +890,9 @@ impl Parser {
         }
         let pos = self.lexer.get_current_pos();
         let lhs = self.read_update_expression()?;
-        if let Ok(tok) = self.lexer.next() {
+        if let Ok(tok) = self.lexer.peek_except_lineterminator() {
             if let Kind::Symbol(Symbol::Exp) = tok.kind {
+                self.lexer.next_except_lineterminator()?;
                 return Ok(Node::new(
                     NodeBase::BinaryOp(
                         Box::new(lhs),
@@ -591,8 +901,6 @@ impl Parser {
                     ),
                     pos,
                 ));
-            } else {
-                self.lexer.unget(&tok);
             }
         }
         Ok(lhs)
@@ -608,6 +916,7 @@ impl Parser {
                 | Kind::Symbol(Symbol::Sub)
                 | Kind::Symbol(Symbol::BitwiseNot)
                 | Kind::Symbol(Symbol::Not) => true,
+                Kind::Identifier(ref name) if name == "await" => self.in_async,
                 _ => false,
             },
             Err(_) => false,
@@ -619,10 +928,21 @@ impl Parser {
         let pos = self.lexer.get_current_pos();
         let tok = self.lexer.next()?;
         match tok.kind {
-            Kind::Keyword(Keyword::Delete) => Ok(Node::new(
-                NodeBase::UnaryOp(Box::new(self.read_unary_expression()?), UnaryOp::Delete),
-                pos,
-            )),
+            Kind::Keyword(Keyword::Delete) => {
+                let expr = self.read_unary_expression()?;
+                if let NodeBase::Identifier(_) = expr.base {
+                    if self.strict {
+                        return Err(Error::General(
+                            pos,
+                            "delete of an unqualified identifier in strict mode.".to_string(),
+                        ));
+                    }
+                }
+                Ok(Node::new(
+                    NodeBase::UnaryOp(Box::new(expr), UnaryOp::Delete),
+                    pos,
+                ))
+            }
             Kind::Keyword(Keyword::Void) => Ok(Node::new(
                 NodeBase::UnaryOp(Box::new(self.read_unary_expression()?), UnaryOp::Void),
                 pos,
@@ -647,6 +967,11 @@ impl Parser {
                 NodeBase::UnaryOp(Box::new(self.read_unary_expression()?), UnaryOp::Not),
                 pos,
             )),
+            // https://tc39.github.io/ecma262/#prod-AwaitExpression
+            Kind::Identifier(ref name) if name == "await" && self.in_async => Ok(Node::new(
+                NodeBase::Await(Box::new(self.read_unary_expression()?)),
+                pos,
+            )),
             _ => {
                 self.lexer.unget(&tok);
                 self.read_update_expression()
@@ -707,36 +1032,59 @@ impl Parser {
     /// https://tc39.github.io/ecma262/#prod-LeftHandSideExpression
     // TODO: Implement all features.
     fn read_left_hand_side_expression(&mut self) -> Result<Node, Error> {
-        let lhs = self.read_new_expression()?;
+        let lhs = self.read_call_expression()?;
 
         Ok(lhs)
     }
 
-    /// https://tc39.github.io/ecma262/#prod-NewExpression
-    fn read_new_expression(&mut self) -> Result<Node, Error> {
-        let pos = self.lexer.get_current_pos();
-        if self.lexer.skip(Kind::Keyword(Keyword::New)) {
-            Ok(Node::new(
-                NodeBase::New(Box::new(self.read_new_expression()?)),
-                pos,
-            ))
-        } else {
-            self.read_call_expression()
-        }
-    }
-
     /// https://tc39.github.io/ecma262/#prod-CallExpression
-    // TODO: Implement all features.
+    /// A NewExpression is read here too, since it is the beginning of a CallExpression.
     fn read_call_expression(&mut self) -> Result<Node, Error> {
+        self.read_member_or_call_expression(true)
+    }
+
+    /// https://tc39.github.io/ecma262/#prod-MemberExpression
+    fn read_member_expression(&mut self) -> Result<Node, Error> {
+        self.read_member_or_call_expression(false)
+    }
+
+    /// Read a MemberExpression followed by member accesses, and calls if 'allow_call'.
+    /// 'new' takes the first arguments after its MemberExpression: 'new f().m()' calls m of the
+    /// new object, and 'new f()()' calls the new object. 'new f' is read as 'new f()', so that
+    /// the callee of 'new' is always a Call.
+    fn read_member_or_call_expression(&mut self, allow_call: bool) -> Result<Node, Error> {
         let pos = self.lexer.get_current_pos();
-        let mut lhs = self.read_primary_expression()?;
+        let mut new_without_args = false;
+        let mut lhs = if self.lexer.skip(Kind::Keyword(Keyword::New)) {
+            let callee_pos = self.lexer.get_current_pos();
+            let callee = self.read_member_expression()?;
+            new_without_args = true;
+            Node::new(
+                NodeBase::New(Box::new(Node::new(
+                    NodeBase::Call(Box::new(callee), vec![]),
+                    callee_pos,
+                ))),
+                pos,
+            )
+        } else {
+            self.read_primary_expression()?
+        };
         let mut lineterminator = false;
 
         while let Ok(tok) = self.lexer.next() {
             let pos_ = self.lexer.get_current_pos();
 
             match tok.kind {
-                Kind::Symbol(Symbol::OpeningParen) => {
+                Kind::Symbol(Symbol::OpeningParen) if new_without_args => {
+                    new_without_args = false;
+                    let args = self.read_arguments()?;
+                    if let NodeBase::New(ref mut call) = lhs.base {
+                        if let NodeBase::Call(_, ref mut call_args) = call.base {
+                            *call_args = args;
+                        }
+                    }
+                }
+                Kind::Symbol(Symbol::OpeningParen) if allow_call => {
                     let args = self.read_arguments()?;
                     lhs = Node::new(NodeBase::Call(Box::new(lhs), args), pos)
                 }
@@ -745,6 +1093,11 @@ impl Parser {
                         Kind::Identifier(name) => {
                             lhs = Node::new(NodeBase::Member(Box::new(lhs), name), pos)
                         }
+                        // A property name may be a reserved word, e.g. promise.catch
+                        Kind::Keyword(keyword) => {
+                            let name = get_string_for_keyword(keyword);
+                            lhs = Node::new(NodeBase::Member(Box::new(lhs), name), pos)
+                        }
                         _ => {
                             return Err(Error::Expect(pos_, "expect identifier".to_string()));
                         }
@@ -760,6 +1113,14 @@ impl Parser {
                     }
                     lhs = Node::new(NodeBase::Index(Box::new(lhs), Box::new(idx)), pos);
                 }
+                Kind::Template(_, _, true, _) => {
+                    self.lexer.unget(&tok);
+                    let template = self.read_template_literal(true)?;
+                    lhs = Node::new(
+                        NodeBase::TaggedTemplate(Box::new(lhs), Box::new(template)),
+                        pos,
+                    )
+                }
                 Kind::LineTerminator => lineterminator = true,
                 _ => {
                     self.lexer.unget(&tok);
@@ -820,7 +1181,12 @@ impl Parser {
         match tok.kind {
             Kind::Keyword(Keyword::This) => Ok(Node::new(NodeBase::This, tok.pos)),
             Kind::Keyword(Keyword::Arguments) => Ok(Node::new(NodeBase::Arguments, tok.pos)),
-            Kind::Keyword(Keyword::Function) => self.read_function_expression(),
+            Kind::Keyword(Keyword::Function) => self.read_function_expression(tok.pos, false),
+            // The same as is_async_function_declaration(), after 'async'.
+            Kind::Identifier(ref name) if name == "async" && self.is_function_declaration() => {
+                self.lexer.next()?;
+                self.read_function_expression(tok.pos, true)
+            }
             Kind::Symbol(Symbol::OpeningParen) => {
                 let x = self.read_expression();
                 expect!(self, Kind::Symbol(Symbol::ClosingParen), "expect ')'");
@@ -839,8 +1205,29 @@ impl Parser {
             }
             Kind::Identifier(ref i) if i == "null" => Ok(Node::new(NodeBase::Null, tok.pos)),
             Kind::Identifier(ident) => Ok(Node::new(NodeBase::Identifier(ident), tok.pos)),
+            Kind::String(_)
+                if self.strict && self.lexer.legacy_octal_literals.contains(&tok.pos) =>
+            {
+                Err(Error::General(
+                    tok.pos,
+                    "octal escape sequences are not allowed in strict mode.".to_string(),
+                ))
+            }
             Kind::String(s) => Ok(Node::new(NodeBase::String(s), tok.pos)),
+            Kind::Number(_)
+                if self.strict && self.lexer.legacy_octal_literals.contains(&tok.pos) =>
+            {
+                Err(Error::General(
+                    tok.pos,
+                    "octal literals are not allowed in strict mode.".to_string(),
+                ))
+            }
             Kind::Number(num) => Ok(Node::new(NodeBase::Number(num), tok.pos)),
+            Kind::BigInt(n) => Ok(Node::new(NodeBase::BigInt(n), tok.pos)),
+            Kind::Template(_, _, true, _) => {
+                self.lexer.unget(&tok);
+                self.read_template_literal(false)
+            }
             Kind::LineTerminator => self.read_primary_expression(),
             _ => {
                 return Err(Error::UnexpectedToken(
@@ -851,8 +1238,49 @@ impl Parser {
         }
     }
 
-    /// https://tc39.github.io/ecma262/#prod-FunctionDeclaration
-    fn read_function_expression(&mut self) -> Result<Node, Error> {
+    /// https://tc39.github.io/ecma262/#prod-TemplateLiteral
+    /// An invalid escape sequence is allowed only in a tagged template, where its cooked string
+    /// is undefined.
+    fn read_template_literal(&mut self, tagged: bool) -> Result<Node, Error> {
+        let pos = self.lexer.get_current_pos();
+        let mut cooked_strings = vec![];
+        let mut raw_strings = vec![];
+        let mut substitutions = vec![];
+        loop {
+            let tok = self.lexer.next_except_lineterminator()?;
+            let expect_head = substitutions.is_empty();
+            match tok.kind {
+                Kind::Template(None, _, _, _) if !tagged => {
+                    return Err(Error::General(
+                        tok.pos,
+                        "invalid escape sequence in template literal.".to_string(),
+                    ))
+                }
+                Kind::Template(cooked, raw, head, tail) => {
+                    if head != expect_head {
+                        return Err(Error::Expect(tok.pos, "expect '}'".to_string()));
+                    }
+                    cooked_strings.push(cooked);
+                    raw_strings.push(raw);
+                    if tail {
+                        break;
+                    }
+                }
+                _ => return Err(Error::Expect(tok.pos, "expect '}'".to_string())),
+            }
+            substitutions.push(self.read_expression()?);
+        }
+        Ok(Node::new(
+            NodeBase::Template(cooked_strings, raw_strings, substitutions),
+            pos,
+        ))
+    }
+
+    /// https://tc39.github.io/ecma262/#prod-FunctionExpression
+    /// Reads the rest of a function expression after 'function'. 'start' is the position of
+    /// 'function', or of 'async' before it.
+    fn read_function_expression(&mut self, start: usize, is_async: bool) -> Result<Node, Error> {
+        let kind = self.read_function_kind(is_async)?;
         let pos = self.lexer.get_current_pos();
         let name = if let Kind::Identifier(name) = self.lexer.peek(0)?.kind {
             self.lexer.next()?;
@@ -867,10 +1295,11 @@ impl Parser {
 
         expect!(self, Kind::Symbol(Symbol::OpeningBrace), "expect '{'");
 
-        let body = self.read_statement_list(true)?;
+        let body = self.read_function_body(pos, &params, kind)?;
+        let source = self.function_source(start);
 
         Ok(Node::new(
-            NodeBase::FunctionExpr(name, params, Box::new(body)),
+            NodeBase::FunctionExpr(name, params, Box::new(body), source, kind),
             pos,
         ))
     }
@@ -880,25 +1309,45 @@ impl Parser {
         let pos = self.lexer.get_current_pos();
         let mut elements = vec![];
 
+        // Line terminators in the brackets are skipped.
+        let eof = |_| Error::UnexpectedEOF("']' may be needed".to_string());
         loop {
-            // TODO: Support all features.
-            while self.lexer.skip(Kind::Symbol(Symbol::Comma)) {
+            // An elision makes a hole. A comma after the last element makes none.
+            while self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::Comma))
+                .map_err(eof)?
+            {
                 elements.push(Node::new(NodeBase::Nope, pos));
             }
 
-            if self.lexer.skip(Kind::Symbol(Symbol::ClosingBoxBracket)) {
+            if self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::ClosingBoxBracket))
+                .map_err(eof)?
+            {
                 break;
             }
 
-            if self.lexer.is_eof() {
-                return Err(Error::UnexpectedEOF("']' may be needed".to_string()));
-            }
+            elements.push(self.read_assignment_expression()?);
 
-            if let Ok(elem) = self.read_assignment_expression() {
-                elements.push(elem);
+            if self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::ClosingBoxBracket))
+                .map_err(eof)?
+            {
+                break;
+            }
+            if !self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::Comma))
+                .map_err(eof)?
+            {
+                return Err(Error::Expect(
+                    self.lexer.peek_except_lineterminator()?.pos,
+                    "expect ',' or ']'.".to_string(),
+                ));
             }
-
-            self.lexer.skip(Kind::Symbol(Symbol::Comma));
         }
 
         Ok(Node::new(NodeBase::Array(elements), pos))
@@ -939,22 +1388,91 @@ impl Parser {
 
     /// https://tc39.github.io/ecma262/#prod-PropertyDefinition
     fn read_property_definition(&mut self) -> Result<PropertyDefinition, Error> {
-        fn to_string(kind: Kind) -> String {
+        fn to_string(kind: Kind) -> Option<String> {
             match kind {
-                Kind::Identifier(name) => name,
-                Kind::Number(n) => format!("{}", n),
-                Kind::String(s) => s,
-                _ => unimplemented!(),
+                Kind::Identifier(name) => Some(name),
+                Kind::Number(n) => Some(format!("{}", n)),
+                Kind::BigInt(n) => Some(n.to_str_radix(10)),
+                Kind::String(s) => Some(s),
+                _ => None,
             }
         }
 
         let tok = self.lexer.next_except_lineterminator()?;
+
+        // ... AssignmentExpression
+        if tok.kind == Kind::Symbol(Symbol::Rest) {
+            return Ok(PropertyDefinition::Spread(
+                self.read_assignment_expression()?,
+            ));
+        }
+
+        // ComputedPropertyName
+        if tok.kind == Kind::Symbol(Symbol::OpeningBoxBracket) {
+            let key = self.read_assignment_expression()?;
+            expect!(self, Kind::Symbol(Symbol::ClosingBoxBracket), "expect ']'");
+            let val = if self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::OpeningParen))?
+            {
+                self.read_method(None, tok.pos)?
+            } else {
+                expect!(self, Kind::Symbol(Symbol::Colon), "expect ':'");
+                self.read_assignment_expression()?
+            };
+            return Ok(PropertyDefinition::ComputedProperty(key, val));
+        }
+
+        let name = match to_string(tok.kind.clone()) {
+            Some(name) => name,
+            None => {
+                return Err(Error::Expect(
+                    tok.pos,
+                    "Expect property definition.".to_string(),
+                ))
+            }
+        };
+
         if self
             .lexer
             .skip_except_lineterminator(Kind::Symbol(Symbol::Colon))?
         {
             let val = self.read_assignment_expression()?;
-            return Ok(PropertyDefinition::Property(to_string(tok.kind), val));
+            return Ok(PropertyDefinition::Property(name, val));
+        }
+
+        if self
+            .lexer
+            .skip_except_lineterminator(Kind::Symbol(Symbol::OpeningParen))?
+        {
+            let func = self.read_method(Some(name.clone()), tok.pos)?;
+            return Ok(PropertyDefinition::MethodDefinition(
+                MethodDefinitionKind::Ordinary,
+                name,
+                func,
+            ));
+        }
+
+        // get x() {} / set x(v) {}
+        if tok.kind == Kind::Identifier("get".to_string())
+            || tok.kind == Kind::Identifier("set".to_string())
+        {
+            let tok_name = self.lexer.peek_except_lineterminator()?;
+            if let Some(accessor_name) = to_string(tok_name.kind) {
+                self.lexer.next_except_lineterminator()?;
+                expect!(self, Kind::Symbol(Symbol::OpeningParen), "expect '('");
+                let func = self.read_method(Some(accessor_name.clone()), tok.pos)?;
+                let kind = if name == "get" {
+                    MethodDefinitionKind::Get
+                } else {
+                    MethodDefinitionKind::Set
+                };
+                return Ok(PropertyDefinition::MethodDefinition(
+                    kind,
+                    accessor_name,
+                    func,
+                ));
+            }
         }
 
         if let Kind::Identifier(name) = tok.kind {
@@ -966,6 +1484,25 @@ impl Parser {
             "Expect property definition.".to_string(),
         ))
     }
+
+    /// https://tc39.github.io/ecma262/#prod-MethodDefinition
+    /// Reads the parameters and body following '(' of a method. 'start' is the position where the
+    /// method definition starts.
+    fn read_method(&mut self, name: Option<String>, start: usize) -> Result<Node, Error> {
+        let pos = self.lexer.get_prev_pos();
+
+        let params = self.read_formal_parameters()?;
+
+        expect!(self, Kind::Symbol(Symbol::OpeningBrace), "expect '{'");
+
+        let body = self.read_function_body(pos, &params, FunctionKind::Normal)?;
+        let source = self.function_source(start);
+
+        Ok(Node::new(
+            NodeBase::FunctionExpr(name, params, Box::new(body), source, FunctionKind::Normal),
+            pos,
+        ))
+    }
 }
 
 impl Parser {
@@ -974,20 +1511,11 @@ impl Parser {
         let pos = self.lexer.get_prev_pos();
 
         // no LineTerminator here
-        if self.lexer.skip(Kind::LineTerminator) {
-            return Ok(Node::new(NodeBase::Return(None), pos));
-        }
-
-        if self.lexer.skip(Kind::Symbol(Symbol::Semicolon)) {
-            return Ok(Node::new(NodeBase::Return(None), pos));
-        }
-
-        if self.lexer.peek(0)?.kind == Kind::Symbol(Symbol::ClosingBrace) {
+        if self.is_statement_end() {
             return Ok(Node::new(NodeBase::Return(None), pos));
         }
 
         let expr = self.read_expression()?;
-        self.lexer.skip(Kind::Symbol(Symbol::Semicolon));
 
         Ok(Node::new(NodeBase::Return(Some(Box::new(expr))), pos))
     }
@@ -1086,28 +1614,33 @@ impl Parser {
         }
 
         let expr = self.read_expression()?;
-        self.lexer.skip(Kind::Symbol(Symbol::Semicolon));
 
         Ok(Node::new(NodeBase::Throw(Box::new(expr)), pos_throw))
     }
 }
 
 impl Parser {
-    fn is_declaration(&mut self) -> bool {
-        self.is_hoistable_declaration()
-    }
-
     fn read_declaration(&mut self) -> Result<Node, Error> {
         let tok = self.lexer.next()?;
         match tok.kind {
-            Kind::Keyword(Keyword::Function) => self.read_function_declaration(),
+            Kind::Keyword(Keyword::Function) => self.read_function_declaration(tok.pos, false),
+            Kind::Identifier(ref name) if name == "async" => {
+                self.lexer.next()?;
+                self.read_function_declaration(tok.pos, true)
+            }
+            Kind::Keyword(Keyword::Const) => self.read_variable_declaration_list(true),
+            Kind::Identifier(ref name) if name == "let" => {
+                self.read_variable_declaration_list(false)
+            }
             _ => unreachable!(),
         }
     }
 
     /// https://tc39.github.io/ecma262/#prod-FunctionDeclaration
-    fn read_function_declaration(&mut self) -> Result<Node, Error> {
-        let pos = self.lexer.get_prev_pos();
+    /// Reads the rest of a function declaration after 'function'. 'pos' is the position of
+    /// 'function', or of 'async' before it.
+    fn read_function_declaration(&mut self, pos: usize, is_async: bool) -> Result<Node, Error> {
+        let kind = self.read_function_kind(is_async)?;
         let name = if let Kind::Identifier(name) = self.lexer.next_except_lineterminator()?.kind {
             name
         } else {
@@ -1123,14 +1656,75 @@ impl Parser {
 
         expect!(self, Kind::Symbol(Symbol::OpeningBrace), "expect '{'");
 
-        let body = self.read_statement_list(true)?;
+        let body = self.read_function_body(pos, &params, kind)?;
+        let source = self.function_source(pos);
 
         Ok(Node::new(
-            NodeBase::FunctionDecl(name, params, Box::new(body)),
+            NodeBase::FunctionDecl(name, params, Box::new(body), source, kind),
             pos,
         ))
     }
 
+    /// Reads '*' of a generator, which may follow 'function'.
+    fn read_function_kind(&mut self, is_async: bool) -> Result<FunctionKind, Error> {
+        let is_generator = self
+            .lexer
+            .skip_except_lineterminator(Kind::Symbol(Symbol::Asterisk))?;
+        Ok(match (is_async, is_generator) {
+            (false, false) => FunctionKind::Normal,
+            (false, true) => FunctionKind::Generator,
+            (true, false) => FunctionKind::Async,
+            (true, true) => FunctionKind::AsyncGenerator,
+        })
+    }
+
+    /// Reads the statements following '{' of a function. The function is strict if it has a
+    /// 'use strict' directive or is in strict mode code. 'await' and 'yield' are operators in
+    /// it if it is an async function and a generator respectively.
+    fn read_function_body(
+        &mut self,
+        pos: usize,
+        params: &FormalParameters,
+        kind: FunctionKind,
+    ) -> Result<Node, Error> {
+        let outer = (self.strict, self.in_async, self.in_generator);
+        self.strict = self.strict || self.has_use_strict_directive();
+        self.in_async = kind == FunctionKind::Async || kind == FunctionKind::AsyncGenerator;
+        self.in_generator = kind == FunctionKind::Generator || kind == FunctionKind::AsyncGenerator;
+        let body = self.read_statement_list(true);
+        let strict = self.strict;
+        self.strict = outer.0;
+        self.in_async = outer.1;
+        self.in_generator = outer.2;
+        let body = body?;
+
+        if strict {
+            for (i, param) in params.iter().enumerate() {
+                if params[..i].iter().any(|p| p.name == param.name) {
+                    return Err(Error::General(
+                        pos,
+                        format!(
+                            "duplicate parameter name '{}' not allowed in strict mode.",
+                            param.name
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(body)
+    }
+
+    /// The source text of a function from 'start' to the '}' of the body just read.
+    fn function_source(&mut self, start: usize) -> String {
+        let closing_brace = match self.lexer.buf.front() {
+            Some(tok) => tok.prev_pos,
+            // '}' is the last token.
+            None => self.lexer.code.rfind('}').unwrap(),
+        };
+        self.lexer.code[start..closing_brace + 1].to_string()
+    }
+
     fn read_formal_parameters(&mut self) -> Result<FormalParameters, Error> {
         if self
             .lexer
@@ -1168,6 +1762,14 @@ impl Parser {
             }
 
             expect!(self, Kind::Symbol(Symbol::Comma), "expect ','");
+
+            // A trailing comma
+            if self
+                .lexer
+                .skip_except_lineterminator(Kind::Symbol(Symbol::ClosingParen))?
+            {
+                break;
+            }
         }
 
         Ok(params)
@@ -1208,7 +1810,35 @@ impl Parser {
 impl Parser {
     /// https://tc39.github.io/ecma262/#prod-HoistableDeclaration
     fn is_hoistable_declaration(&mut self) -> bool {
-        self.is_function_declaration()
+        self.is_function_declaration() || self.is_async_function_declaration()
+    }
+
+    /// https://tc39.github.io/ecma262/#prod-LexicalDeclaration
+    /// 'let' is not a reserved word, so it starts a declaration only when an identifier
+    /// follows it.
+    fn is_lexical_declaration(&mut self) -> bool {
+        match self.lexer.peek(0) {
+            Ok(ref tok) if tok.is_the_keyword(Keyword::Const) => true,
+            Ok(Token {
+                kind: Kind::Identifier(ref name),
+                ..
+            }) if name == "let" => {
+                let next = self
+                    .lexer
+                    .buf
+                    .iter()
+                    .skip(1)
+                    .find(|tok| tok.kind != Kind::LineTerminator);
+                match next {
+                    Some(Token {
+                        kind: Kind::Identifier(_),
+                        ..
+                    }) => true,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
     }
 }
 
@@ -1220,6 +1850,22 @@ impl Parser {
             Err(_) => false,
         }
     }
+
+    /// https://tc39.github.io/ecma262/#prod-AsyncFunctionDeclaration
+    /// 'async' is not a reserved word, and starts an async function only when 'function' follows
+    /// it on the same line. 'async\nfunction f() {}' is the identifier 'async' and a function.
+    fn is_async_function_declaration(&mut self) -> bool {
+        match self.lexer.peek(0) {
+            Ok(Token {
+                kind: Kind::Identifier(ref name),
+                ..
+            }) if name == "async" => self
+                .lexer
+                .peek(1)
+                .map_or(false, |tok| tok.is_the_keyword(Keyword::Function)),
+            _ => false,
+        }
+    }
 }
 
 #[test]
@@ -1248,7 +1894,7 @@ fn string() {
 
 #[test]
 fn boolean() {
-    let mut parser = Parser::new("true false".to_string());
+    let mut parser = Parser::new("true\nfalse".to_string());
     assert_eq!(
         parser.parse_all().unwrap(),
         Node::new(
@@ -1292,7 +1938,47 @@ fn array1() {
             0
         )
     );
-    for input in ["[1,2,"].iter() {
+    for input in ["[1,2,", "[1 2]", "[1,2"].iter() {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().expect_err("should be error");
+    }
+    for input in [
+        "[\n1,\n2\n]",
+        "[\n1\n,\n]",
+        "[1,\n\n,2]",
+        "[\nnew A(),\nf(\nnew B()\n)\n]",
+    ]
+    .iter()
+    {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().expect(input);
+    }
+}
+
+#[test]
+fn template() {
+    let mut parser = Parser::new("f`a${x}\\u{zz}`".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::TaggedTemplate(
+                    Box::new(Node::new(NodeBase::Identifier("f".to_string()), 0)),
+                    Box::new(Node::new(
+                        NodeBase::Template(
+                            vec![Some("a".to_string()), None],
+                            vec!["a".to_string(), "\\u{zz}".to_string()],
+                            vec![Node::new(NodeBase::Identifier("x".to_string()), 5)],
+                        ),
+                        1,
+                    )),
+                ),
+                0,
+            )]),
+            0
+        )
+    );
+    for input in ["`\\u{zz}`", "`${}`", "`${a b}`", "`${a`", "`a`${b}`"].iter() {
         let mut parser = Parser::new(input.to_string());
         parser.parse_all().expect_err("should be error");
     }
@@ -1356,11 +2042,20 @@ fn object() {
             0
         )
     );
-    for input in ["a = {}", "a = {b}"].iter() {
+    for input in [
+        "a = {}",
+        "a = {b}",
+        "a = {[b]: 1, ['c' + 1]() {}}",
+        "a = {b() { return 1 }, get c() { return 2 }, set c(v) {}}",
+        "a = {get: 1, set}",
+        "a = {...b, c: 1, ...d(), ...e ? f : g,}",
+    ]
+    .iter()
+    {
         let mut parser = Parser::new(input.to_string());
         parser.parse_all().unwrap();
     }
-    for input in ["a = {b:6 c}", "a = {b:6, 777}"].iter() {
+    for input in ["a = {b:6 c}", "a = {b:6, 777}", "a = {[b] 1}", "a = {...}"].iter() {
         let mut parser = Parser::new(input.to_string());
         parser.parse_all().expect_err(input);
     }
@@ -1673,21 +2368,25 @@ fn simple_expr_assign() {
         );
     } }
     f!(Node::new(NodeBase::Number(1.0), 4));
+    macro_rules! g { ($op:ident) => {
+        assert_eq!(
+            Node::new(NodeBase::StatementList(vec![Node::new(NodeBase::CompoundAssign(
+                Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)),
+                Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::$op
+            ), 0)]), 0),
+            parser.parse_all().unwrap()
+        );
+    } }
     parser = Parser::new("v += 1".to_string());
-    f!(Node::new(NodeBase::BinaryOp(Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)), 
-                                    Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::Add), 0));
+    g!(Add);
     parser = Parser::new("v -= 1".to_string());
-    f!(Node::new(NodeBase::BinaryOp(Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)), 
-                                    Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::Sub), 0));
+    g!(Sub);
     parser = Parser::new("v *= 1".to_string());
-    f!(Node::new(NodeBase::BinaryOp(Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)), 
-                                    Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::Mul), 0));
+    g!(Mul);
     parser = Parser::new("v /= 1".to_string());
-    f!(Node::new(NodeBase::BinaryOp(Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)), 
-                                    Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::Div), 0));
+    g!(Div);
     parser = Parser::new("v %= 1".to_string());
-    f!(Node::new(NodeBase::BinaryOp(Box::new(Node::new(NodeBase::Identifier("v".to_string()), 0)), 
-                                    Box::new(Node::new(NodeBase::Number(1.0), 5)), BinOp::Rem), 0));
+    g!(Rem);
 }
 
 #[test]
@@ -1711,6 +2410,54 @@ fn simple_expr_new() {
     );
 }
 
+#[test]
+fn new_takes_nearest_arguments() {
+    let f = || Box::new(Node::new(NodeBase::Identifier("f".to_string()), 4));
+    let new_f = || {
+        Node::new(
+            NodeBase::New(Box::new(Node::new(NodeBase::Call(f(), vec![]), 4))),
+            0,
+        )
+    };
+    let mut parser = Parser::new("new f().m()".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::Call(
+                    Box::new(Node::new(
+                        NodeBase::Member(Box::new(new_f()), "m".to_string()),
+                        0
+                    )),
+                    vec![],
+                ),
+                0,
+            )]),
+            0
+        ),
+    );
+    let mut parser = Parser::new("new f()()".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::Call(Box::new(new_f()), vec![]),
+                0
+            )]),
+            0
+        ),
+    );
+    let mut parser = Parser::new("new f".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(NodeBase::StatementList(vec![new_f()]), 0),
+    );
+    for input in ["new new f()()", "new f\n(1)", "new a.b[0]()", "f()()[0]()"].iter() {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().unwrap();
+    }
+}
+
 #[test]
 fn simple_expr_parentheses() {
     let mut parser = Parser::new("2 * (1 + 3)".to_string());
@@ -1802,6 +2549,16 @@ fn member() {
                 0,
             ),
         ),
+        (
+            "p.catch",
+            Node::new(
+                NodeBase::Member(
+                    Box::new(Node::new(NodeBase::Identifier("p".to_string()), 0)),
+                    "catch".to_string(),
+                ),
+                0,
+            ),
+        ),
     ]
     .iter()
     {
@@ -1841,6 +2598,174 @@ fn var_decl() {
     }
 }
 
+#[test]
+fn asi() {
+    // A line terminator before a binary operator does not end the statement.
+    let mut parser = Parser::new("a = b\n+c".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::Assign(
+                    Box::new(Node::new(NodeBase::Identifier("a".to_string()), 0)),
+                    Box::new(Node::new(
+                        NodeBase::BinaryOp(
+                            Box::new(Node::new(NodeBase::Identifier("b".to_string()), 4)),
+                            Box::new(Node::new(NodeBase::Identifier("c".to_string()), 7)),
+                            BinOp::Add,
+                        ),
+                        7,
+                    )),
+                ),
+                0,
+            )]),
+            0
+        )
+    );
+    // Postfix '++' may not follow a line terminator.
+    let mut parser = Parser::new("a\n++b".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![
+                Node::new(NodeBase::Identifier("a".to_string()), 0),
+                Node::new(
+                    NodeBase::UnaryOp(
+                        Box::new(Node::new(NodeBase::Identifier("b".to_string()), 4)),
+                        UnaryOp::PrInc,
+                    ),
+                    2,
+                ),
+            ]),
+            0
+        )
+    );
+    for input in [
+        "a = 1\nb = 2",
+        "var a = 1\nvar b\nlet c = 2\nconst d = 3",
+        "var a = 1\n, b = 2",
+        "x\n? 1\n: 2",
+        "f()\n.g()",
+        "function f() { return 1 }",
+        "function f() { return\n1 }",
+        "{ a = 1 } b = 2",
+        "a = 1 /*\n*/ b = 2",
+        "while (1) { break\n}",
+        "while (1) break",
+        "a;;b",
+    ]
+    .iter()
+    {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().expect(input);
+    }
+    for input in [
+        "a = 1 b = 2",
+        "var a = 1 var b",
+        "let a = 1 let b",
+        "a = 1 /* */ b = 2",
+        "if (a) b else c",
+        "a\n++",
+        "return 1 2",
+    ]
+    .iter()
+    {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().expect_err(input);
+    }
+}
+
+#[test]
+fn recovery() {
+    let code = "var a = ;
+var b = 1
+b +;
+function f() { return ) }
+'\\x4' + b
+var d = 2 }
+var e = {
+  x: 1 +
+}";
+    let mut parser = Parser::new(code.to_string());
+    let errors = parser.parse_all_recovering().unwrap_err();
+    let lines: Vec<usize> = errors
+        .into_iter()
+        .map(|err| {
+            let (pos, _) = parser.error_position_and_message(err);
+            parser.lexer.get_code_around_err_point(pos).2
+        })
+        .collect();
+    assert_eq!(lines, vec![1, 3, 4, 5, 6, 9]);
+    let errors = parse(code).unwrap_err();
+    assert_eq!(
+        errors.iter().map(|err| err.line).collect::<Vec<usize>>(),
+        lines
+    );
+    assert_eq!(errors[0].to_string(), format!("line 1: {}", errors[0].msg));
+
+    // Without errors, the result is the same as parse_all().
+    let code = "var a = 1\nfunction f() { return a }";
+    assert_eq!(
+        Parser::new(code.to_string()).parse_all_recovering(),
+        Ok(Parser::new(code.to_string()).parse_all().unwrap())
+    );
+}
+
+#[test]
+fn lexical_decl() {
+    let mut parser = Parser::new("let a, b = 1\nconst c = 2".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![
+                Node::new(
+                    NodeBase::StatementList(vec![
+                        Node::new(NodeBase::VarDecl("a".to_string(), None), 4),
+                        Node::new(
+                            NodeBase::VarDecl(
+                                "b".to_string(),
+                                Some(Box::new(Node::new(NodeBase::Number(1.0), 11))),
+                            ),
+                            7,
+                        ),
+                    ]),
+                    0,
+                ),
+                Node::new(
+                    NodeBase::StatementList(vec![Node::new(
+                        NodeBase::VarDecl(
+                            "c".to_string(),
+                            Some(Box::new(Node::new(NodeBase::Number(2.0), 23))),
+                        ),
+                        19,
+                    )]),
+                    13,
+                ),
+            ]),
+            0
+        )
+    );
+    // 'let' is an identifier unless an identifier follows it.
+    let mut parser = Parser::new("let = 1".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::Assign(
+                    Box::new(Node::new(NodeBase::Identifier("let".to_string()), 0)),
+                    Box::new(Node::new(NodeBase::Number(1.0), 6)),
+                ),
+                0,
+            )]),
+            0
+        )
+    );
+    for input in ["const a", "const a = 1, b"].iter() {
+        let mut parser = Parser::new(input.to_string());
+        parser.parse_all().expect_err("should be error");
+    }
+}
+
 #[test]
 fn block() {
     let mut parser = Parser::new("{ a=1 }".to_string());
@@ -2018,6 +2943,24 @@ fn while_() {
     );
 }
 
+#[test]
+fn with() {
+    let mut parser = Parser::new("with (o) { }".to_string());
+    assert_eq!(
+        parser.parse_all().unwrap(),
+        Node::new(
+            NodeBase::StatementList(vec![Node::new(
+                NodeBase::With(
+                    Box::new(Node::new(NodeBase::Identifier("o".to_string()), 6)),
+                    Box::new(Node::new(NodeBase::StatementList(vec![]), 9)),
+                ),
+                0,
+            )]),
+            0
+        )
+    );
+}
+
 #[test]
 fn for1() {
     let mut parser = Parser::new("for (;;) { }".to_string());
@@ -2068,6 +3011,9 @@ fn function_decl() {
                     "f".to_string(),
                     vec![],
                     Box::new(Node::new(NodeBase::StatementList(vec![]), 64)),
+                    "function\n            f\n            (\n            ) \n            { \n            }"
+                        .to_string(),
+                    FunctionKind::Normal,
                 ),
                 0,
             ),
@@ -2082,6 +3028,8 @@ fn function_decl() {
                         NodeBase::StatementList(vec![Node::new(NodeBase::Return(None), 15)]),
                         13,
                     )),
+                    "function f() { return }".to_string(),
+                    FunctionKind::Normal,
                 ),
                 0,
             ),
@@ -2110,6 +3058,8 @@ fn function_decl() {
                         )]),
                         23,
                     )),
+                    "function f(x, y, ...z) { return x + y }".to_string(),
+                    FunctionKind::Normal,
                 ),
                 0,
             ),
@@ -2132,6 +3082,8 @@ fn function_decl() {
         "function f(x,.y){}",
         "function f(x,..y){}",
         "function f(x,...y,z){}",
+        "function f(x,...y,){}",
+        "function f(x,,){}",
         "function f(x,...7){}",
     ]
     .iter()
@@ -2139,12 +3091,100 @@ fn function_decl() {
         let mut parser = Parser::new(input.to_string());
         parser.parse_all().expect_err(input);
     }
-    for input in ["a = function(x,y){b=1}"].iter() {
+    for input in [
+        "a = function(x,y){b=1}",
+        "function f(x, y,\n) {}",
+        "f(x, y,)",
+    ]
+    .iter()
+    {
         let mut parser = Parser::new(input.to_string());
         parser.parse_all().unwrap();
     }
 }
 
+#[test]
+fn async_function_and_generator() {
+    let parse = |code: &str| match Parser::new(code.to_string()).parse_all().unwrap().base {
+        NodeBase::StatementList(body) => body,
+        _ => unreachable!(),
+    };
+    fn kind(node: &Node) -> Option<FunctionKind> {
+        match node.base {
+            NodeBase::FunctionDecl(_, _, _, _, kind) | NodeBase::FunctionExpr(_, _, _, _, kind) => {
+                Some(kind)
+            }
+            _ => node.children().into_iter().filter_map(kind).next(),
+        }
+    }
+    fn has(node: &Node, pred: &dyn Fn(&NodeBase) -> bool) -> bool {
+        pred(&node.base) || node.children().into_iter().any(|child| has(child, pred))
+    }
+
+    for (input, expected) in [
+        ("function f() {}", FunctionKind::Normal),
+        ("function* f() {}", FunctionKind::Generator),
+        ("async function f() {}", FunctionKind::Async),
+        ("async function* f() {}", FunctionKind::AsyncGenerator),
+        ("(function* () {})", FunctionKind::Generator),
+        ("x = async function () {}", FunctionKind::Async),
+        ("x = async function* g() {}", FunctionKind::AsyncGenerator),
+    ]
+    .iter()
+    {
+        assert_eq!(kind(&parse(input)[0]), Some(*expected), "{}", input);
+    }
+
+    // 'async' on another line is an identifier.
+    let body = parse("async\nfunction f() {}");
+    assert_eq!(body[0].base, NodeBase::Identifier("async".to_string()));
+    assert_eq!(kind(&body[1]), Some(FunctionKind::Normal));
+    let body = parse("x = async\nfunction f() {}");
+    assert_eq!(body.len(), 2);
+    assert_eq!(kind(&body[1]), Some(FunctionKind::Normal));
+
+    let body = parse("async function f() { await g(); for await (const x of xs) h(x) }");
+    assert!(has(&body[0], &|base| match *base {
+        NodeBase::Await(_) => true,
+        _ => false,
+    }));
+    assert!(has(&body[0], &|base| match *base {
+        NodeBase::ForAwaitOf(_, _, _) => true,
+        _ => false,
+    }));
+
+    let body = parse("function* g() { yield; yield 1; yield* h() }");
+    let yields = |delegate| {
+        move |base: &NodeBase| match *base {
+            NodeBase::Yield(ref arg, d) => d == delegate && arg.is_some() == delegate,
+            _ => false,
+        }
+    };
+    assert!(has(&body[0], &yields(false)));
+    assert!(has(&body[0], &yields(true)));
+
+    // 'async', 'await' and 'yield' are identifiers elsewhere.
+    let body = parse("var async, await, yield; async + await + yield; function* g() { await }");
+    assert!(!body[..2].iter().any(|node| has(node, &|base| match *base {
+        NodeBase::Await(_) | NodeBase::Yield(_, _) => true,
+        _ => false,
+    })));
+    assert!(!has(&body[2], &|base| match *base {
+        NodeBase::Await(_) => true,
+        _ => false,
+    }));
+
+    for input in [
+        "for await (x of xs) ;",
+        "function f() { for await (x of xs) ; }",
+        "async function f() { for await (x in xs) ; }",
+    ]
+    .iter()
+    {
+        Parser::new(input.to_string()).parse_all().expect_err(input);
+    }
+}
+
 #[test]
 fn asi1() {
     let mut parser = Parser::new(
@@ -2169,6 +3209,9 @@ fn asi1() {
                         ]),
                         23,
                     )),
+                    "function f() \n         {\n             return \n             {};\n         }"
+                        .to_string(),
+                    FunctionKind::Normal,
                 ),
                 0,
             )]),
@@ -2298,3 +3341,32 @@ fn try_catch3() {
         )
     );
 }
+
+#[test]
+fn strict_mode() {
+    for code in &[
+        "'use strict'; var a = 010",
+        "'use strict'; delete a",
+        "'use strict'; function f(a, a) {}",
+        "function f(a, a) { 'use strict' }",
+        "function f() { 'use strict'; return 08 }",
+        "'use strict'; function f() { var g = function(a, b, a) {} }",
+        "'use strict'; with (a) {}",
+        "'use strict'; var s = '\\012'",
+    ] {
+        let mut parser = Parser::new(code.to_string());
+        assert!(parser.parse_all().is_err(), "{}", code);
+    }
+
+    for code in &[
+        "var a = 010; delete a; function f(a, a) {}",
+        "function f() { 'use strict' } function g(a, a) { return 010 }",
+        "'use' + ' strict'; delete a",
+        "var a = 0; 'use strict'; delete a",
+        "'use strict'; var o = {}; delete o.a; var b = 0.5 + 10",
+        "var s = '\\012'; 'use strict'",
+    ] {
+        let mut parser = Parser::new(code.to_string());
+        assert!(parser.parse_all().is_ok(), "{}", code);
+    }
+}
@@ -0,0 +1,326 @@
+use node::{FormalParameters, Node, NodeBase, PropertyDefinition};
+use rustc_hash::{FxHashMap, FxHashSet};
+use vm::upvalue::UpvalueSource;
+
+// Resolution of the variables of a function at compile time. The code generator asks how each
+// identifier refers to its variable:
+//
+// - A local variable is kept in a register of the frame (its slot), and is read and written by
+//   PUSH_REG and POP_REG instead of being looked up by name in the call object.
+// - A variable in a slot of an enclosing function is referred to through an upvalue (see
+//   vm/upvalue.rs). The upvalues of a function are listed by the order they are first referred
+//   to, and the functions between it and the one declaring the variable get the upvalue as well,
+//   to pass it to their closures.
+// - Any other identifier is global, and is looked up by name. Unless 'with' or a direct eval may
+//   bind it, the code generator may look it up in the global object directly (GET_GLOBAL).
+//
+// A function which uses 'arguments', or in which 'with' or a direct eval is used (including in
+// its nested functions), may refer to its variables by names unknown at compile time, so all its
+// variables are kept in the call object and looked up by name. So are the variables named as the
+// parameters of catch clauses, which are kept in the scopes of the clauses. A closure which
+// looks variables of enclosing functions up by name gets the scope where it is made as its outer
+// scope.
+
+/// The maximum number of slots of a function. The registers above them are left for
+/// temporaries, and the other variables are kept in the call object.
+pub const MAX_SLOTS: usize = 128;
+
+/// How an identifier refers to its variable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    /// a local variable kept in the register.
+    Slot(u8),
+    /// a variable of an enclosing function, referred to through the upvalue of this index.
+    Upvalue(usize),
+    /// a variable kept in a call object, looked up by name.
+    Scope,
+    /// a variable declared by no enclosing function, which can only be in the scope of the code
+    /// being compiled or the global object. Looked up by name.
+    Global,
+}
+
+/// The variables of a function being compiled.
+#[derive(Clone, Debug, Default)]
+pub struct FunctionScope {
+    /// parameters, variables and functions declared by the function.
+    declared: FxHashSet<String>,
+    slots: FxHashMap<String, u8>,
+    /// variables in slots which nested functions refer to.
+    captured: FxHashSet<String>,
+    /// names bound by the catch clauses of the function.
+    catch_params: FxHashSet<String>,
+    /// the variables of enclosing functions referred to through upvalues, by the indexes of the
+    /// upvalues.
+    upvalues: Vec<(String, UpvalueSource)>,
+    /// whether the function may look variables of enclosing functions up by name.
+    uses_outer_scope: bool,
+    /// whether 'with' or a direct eval is used in the function or its nested functions, which
+    /// may bind any name in its scope.
+    unknown_names: bool,
+}
+
+impl FunctionScope {
+    /// Analyze a function. Its variables get slots if 'use_slots'.
+    pub fn new(params: &FormalParameters, body: &Node, use_slots: bool) -> FunctionScope {
+        let mut analysis = Analysis::default();
+        for param in params {
+            analysis.declare(&param.name);
+        }
+        analysis.visit(body);
+
+        let mut slots = FxHashMap::default();
+        if use_slots && !analysis.dynamic {
+            for name in &analysis.declared {
+                if slots.len() == MAX_SLOTS {
+                    break;
+                }
+                if !analysis.catch_params.contains(name)
+                    && !analysis.nested_catch_params.contains(name)
+                {
+                    let slot = slots.len() as u8;
+                    slots.insert(name.clone(), slot);
+                }
+            }
+        }
+
+        FunctionScope {
+            captured: analysis
+                .captured
+                .into_iter()
+                .filter(|name| slots.contains_key(name))
+                .collect(),
+            declared: analysis.declared.into_iter().collect(),
+            slots: slots,
+            catch_params: analysis.catch_params,
+            upvalues: vec![],
+            uses_outer_scope: analysis.unknown_names,
+            unknown_names: analysis.unknown_names,
+        }
+    }
+
+    pub fn slot(&self, name: &str) -> Option<u8> {
+        self.slots.get(name).cloned()
+    }
+
+    /// the number of registers used by the slots.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn declares(&self, name: &str) -> bool {
+        self.declared.contains(name)
+    }
+
+    /// where a closure of the function finds each of its upvalues.
+    pub fn upvalue_sources(&self) -> Vec<UpvalueSource> {
+        self.upvalues.iter().map(|&(_, source)| source).collect()
+    }
+
+    /// Whether a closure of the function needs the scope where it is made, in which it looks
+    /// variables up by name.
+    pub fn uses_outer_scope(&self) -> bool {
+        self.uses_outer_scope
+    }
+
+    /// whether the function declares 'name' or binds it by a catch clause.
+    fn binds(&self, name: &str) -> bool {
+        self.declares(name) || self.catch_params.contains(name)
+    }
+
+    /// the index of the upvalue for 'name', which is added if there is none.
+    fn upvalue(&mut self, name: &str, source: UpvalueSource) -> usize {
+        match self.upvalues.iter().position(|&(ref n, _)| n == name) {
+            Some(n) => n,
+            None => {
+                self.upvalues.push((name.to_string(), source));
+                self.upvalues.len() - 1
+            }
+        }
+    }
+}
+
+/// How 'name' refers to its variable in the innermost of 'scopes', the functions being
+/// compiled from the outermost one. The upvalues and the outer scopes the innermost function
+/// needs to refer to it are recorded.
+pub fn resolve(scopes: &mut Vec<FunctionScope>, name: &str) -> Binding {
+    let innermost = match scopes.len() {
+        0 => return Binding::Global,
+        len => len - 1,
+    };
+    let depth = match scopes.iter().rposition(|scope| scope.binds(name)) {
+        Some(depth) => depth,
+        None => {
+            // The closures of the functions nested in one with unknown names look the name up
+            // in the scopes where they are made, in which it may be bound.
+            match scopes.iter().position(|scope| scope.unknown_names) {
+                Some(outermost) => {
+                    for scope in &mut scopes[outermost + 1..] {
+                        scope.uses_outer_scope = true;
+                    }
+                    return Binding::Scope;
+                }
+                None => return Binding::Global,
+            }
+        }
+    };
+
+    match scopes[depth].slot(name) {
+        Some(slot) if depth == innermost => return Binding::Slot(slot),
+        Some(slot) if scopes[depth].captured.contains(name) => {
+            // The function nested in the declaring one finds the upvalue in the slot, and the
+            // ones nested deeper in the upvalue of the function they are nested in.
+            let mut source = UpvalueSource::Slot(slot);
+            let mut n = 0;
+            for scope in &mut scopes[depth + 1..] {
+                n = scope.upvalue(name, source);
+                source = UpvalueSource::Upvalue(n);
+            }
+            return Binding::Upvalue(n);
+        }
+        _ => {}
+    }
+
+    // The closures of the functions nested in the outermost one binding 'name' look it up in
+    // the scopes where they are made.
+    let outermost = scopes[..innermost]
+        .iter()
+        .position(|scope| scope.binds(name));
+    if let Some(outermost) = outermost {
+        for scope in &mut scopes[outermost + 1..] {
+            scope.uses_outer_scope = true;
+        }
+    }
+    Binding::Scope
+}
+
+#[derive(Debug, Default)]
+struct Analysis {
+    /// in the order of the declarations, so that slots are given in the same order every time.
+    declared: Vec<String>,
+    /// names referred to by nested functions without declaring them.
+    captured: FxHashSet<String>,
+    /// names bound by catch clauses, which are kept in the scopes of the clauses.
+    catch_params: FxHashSet<String>,
+    /// names bound by the catch clauses of nested functions, which may shadow variables of this
+    /// one.
+    nested_catch_params: FxHashSet<String>,
+    /// whether the variables may be referred to by names unknown at compile time.
+    dynamic: bool,
+    /// whether 'with' or a direct eval is used, which may refer to any variable of the
+    /// enclosing functions as well.
+    unknown_names: bool,
+}
+
+impl Analysis {
+    fn declare(&mut self, name: &String) {
+        if !self.declared.contains(name) {
+            self.declared.push(name.clone());
+        }
+    }
+
+    /// Visit the code of the function, except the bodies of nested functions.
+    fn visit(&mut self, node: &Node) {
+        match node.base {
+            NodeBase::FunctionDecl(ref name, ref params, ref body, _, _) => {
+                self.declare(name);
+                self.visit_nested_function(params, body);
+            }
+            NodeBase::FunctionExpr(_, ref params, ref body, _, _) => {
+                self.visit_nested_function(params, body)
+            }
+            NodeBase::VarDecl(ref name, _) => self.declare(name),
+            NodeBase::Arguments => self.dynamic = true,
+            _ => self.visit_names(node),
+        }
+        for child in children(node) {
+            self.visit(child);
+        }
+    }
+
+    fn visit_nested_function(&mut self, params: &FormalParameters, body: &Node) {
+        let mut nested = Analysis::default();
+        for param in params {
+            nested.declare(&param.name);
+        }
+        nested.collect_free_names(body);
+        let declared = nested.declared;
+        self.captured.extend(
+            nested
+                .captured
+                .into_iter()
+                .filter(|name| !declared.contains(name)),
+        );
+        self.nested_catch_params.extend(nested.catch_params);
+        self.nested_catch_params.extend(nested.nested_catch_params);
+        self.dynamic |= nested.unknown_names;
+        self.unknown_names |= nested.unknown_names;
+    }
+
+    /// Collect the declarations of a nested function and the identifiers used in it, which
+    /// include the free names of the functions nested in it.
+    fn collect_free_names(&mut self, node: &Node) {
+        match node.base {
+            NodeBase::FunctionDecl(ref name, ref params, ref body, _, _) => {
+                self.declare(name);
+                self.visit_nested_function(params, body);
+                return;
+            }
+            NodeBase::FunctionExpr(_, ref params, ref body, _, _) => {
+                self.visit_nested_function(params, body);
+                return;
+            }
+            NodeBase::VarDecl(ref name, _) => self.declare(name),
+            NodeBase::Identifier(ref name) => {
+                self.captured.insert(name.clone());
+            }
+            NodeBase::Object(ref properties) => {
+                for property in properties {
+                    if let PropertyDefinition::IdentifierReference(ref name) = *property {
+                        self.captured.insert(name.clone());
+                    }
+                }
+            }
+            _ => self.visit_names(node),
+        }
+        for child in children(node) {
+            self.collect_free_names(child);
+        }
+    }
+
+    /// Record what binds names, or refers to names unknown at compile time.
+    fn visit_names(&mut self, node: &Node) {
+        match node.base {
+            NodeBase::With(_, _) => {
+                self.dynamic = true;
+                self.unknown_names = true;
+            }
+            NodeBase::Try(_, _, ref param, _) => {
+                if let NodeBase::Identifier(ref name) = param.base {
+                    self.catch_params.insert(name.clone());
+                }
+            }
+            _ if is_direct_eval(node) => {
+                self.dynamic = true;
+                self.unknown_names = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether 'node' is a call of 'eval', which may be a direct eval.
+fn is_direct_eval(node: &Node) -> bool {
+    match node.base {
+        NodeBase::Call(ref callee, _) => callee.base == NodeBase::Identifier("eval".to_string()),
+        _ => false,
+    }
+}
+
+/// The nodes directly in 'node'. The default parameters and the body of a function are not in
+/// it.
+fn children(node: &Node) -> Vec<&Node> {
+    match node.base {
+        NodeBase::FunctionDecl(_, _, _, _, _) | NodeBase::FunctionExpr(_, _, _, _, _) => vec![],
+        _ => node.children(),
+    }
+}
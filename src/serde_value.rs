@@ -0,0 +1,678 @@
+use engine::{JsError, JsValue};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+use std::fmt;
+use vm::value::number_to_string;
+
+// serde support for JsValue, enabled by the 'serde' feature.
+//
+// Rust data is mapped to JavaScript values in the same way as serde_json maps it to JSON:
+// structs and maps are objects, sequences and tuples are arrays, None and () are null, unit
+// variants are strings and the other variants are objects with one property named after the
+// variant. All the numbers become f64, and a BigInt is serialized as its decimal string.
+
+/// Convert 'val' to a JsValue.
+pub fn to_value<T: Serialize + ?Sized>(val: &T) -> Result<JsValue, JsError> {
+    val.serialize(Serializer)
+}
+
+/// Convert 'val' to T.
+pub fn from_value<T: DeserializeOwned>(val: JsValue) -> Result<T, JsError> {
+    T::deserialize(val)
+}
+
+impl ser::Error for JsError {
+    fn custom<T: fmt::Display>(msg: T) -> JsError {
+        JsError::Runtime(msg.to_string())
+    }
+}
+
+impl de::Error for JsError {
+    fn custom<T: fmt::Display>(msg: T) -> JsError {
+        JsError::Runtime(msg.to_string())
+    }
+}
+
+impl Serialize for JsValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            JsValue::Undefined | JsValue::Null | JsValue::Function => serializer.serialize_unit(),
+            JsValue::Bool(b) => serializer.serialize_bool(*b),
+            JsValue::Number(n) => serializer.serialize_f64(*n),
+            JsValue::BigInt(n) => serializer.serialize_str(&n.to_str_radix(10)),
+            JsValue::String(s) => serializer.serialize_str(s),
+            JsValue::Array(elems) => {
+                let mut seq = serializer.serialize_seq(Some(elems.len()))?;
+                for elem in elems {
+                    seq.serialize_element(elem)?;
+                }
+                seq.end()
+            }
+            JsValue::Object(props) => {
+                let mut map = serializer.serialize_map(Some(props.len()))?;
+                for (name, val) in props {
+                    map.serialize_entry(name, val)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JsValue {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<JsValue, D::Error> {
+        deserializer.deserialize_any(JsValueVisitor)
+    }
+}
+
+struct JsValueVisitor;
+
+impl<'de> Visitor<'de> for JsValueVisitor {
+    type Value = JsValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<JsValue, E> {
+        Ok(JsValue::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<JsValue, E> {
+        Ok(JsValue::Number(n as f64))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<JsValue, E> {
+        Ok(JsValue::Number(n as f64))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<JsValue, E> {
+        Ok(JsValue::Number(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<JsValue, E> {
+        Ok(JsValue::String(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<JsValue, E> {
+        Ok(JsValue::String(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<JsValue, E> {
+        Ok(JsValue::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<JsValue, E> {
+        Ok(JsValue::Null)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<JsValue, D::Error> {
+        deserializer.deserialize_any(JsValueVisitor)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsValue, A::Error> {
+        let mut elems = vec![];
+        while let Some(elem) = seq.next_element()? {
+            elems.push(elem);
+        }
+        Ok(JsValue::Array(elems))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsValue, A::Error> {
+        let mut props = vec![];
+        while let Some(prop) = map.next_entry()? {
+            props.push(prop);
+        }
+        Ok(JsValue::Object(props))
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = JsValue;
+    type Error = JsError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, b: bool) -> Result<JsValue, JsError> {
+        Ok(JsValue::Bool(b))
+    }
+
+    fn serialize_i8(self, n: i8) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_i16(self, n: i16) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_i32(self, n: i32) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_i64(self, n: i64) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_u8(self, n: u8) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_u16(self, n: u16) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_u32(self, n: u32) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_u64(self, n: u64) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_f32(self, n: f32) -> Result<JsValue, JsError> {
+        self.serialize_f64(n as f64)
+    }
+
+    fn serialize_f64(self, n: f64) -> Result<JsValue, JsError> {
+        Ok(JsValue::Number(n))
+    }
+
+    fn serialize_char(self, c: char) -> Result<JsValue, JsError> {
+        Ok(JsValue::String(c.to_string()))
+    }
+
+    fn serialize_str(self, s: &str) -> Result<JsValue, JsError> {
+        Ok(JsValue::String(s.to_string()))
+    }
+
+    fn serialize_bytes(self, bytes: &[u8]) -> Result<JsValue, JsError> {
+        Ok(JsValue::Array(
+            bytes.iter().map(|b| JsValue::Number(*b as f64)).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<JsValue, JsError> {
+        Ok(JsValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, val: &T) -> Result<JsValue, JsError> {
+        val.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<JsValue, JsError> {
+        Ok(JsValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<JsValue, JsError> {
+        Ok(JsValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<JsValue, JsError> {
+        Ok(JsValue::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        val: &T,
+    ) -> Result<JsValue, JsError> {
+        val.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        val: &T,
+    ) -> Result<JsValue, JsError> {
+        Ok(JsValue::Object(vec![(variant.to_string(), to_value(val)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, JsError> {
+        Ok(SerializeArray {
+            variant: None,
+            elems: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, JsError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, JsError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, JsError> {
+        Ok(SerializeArray {
+            variant: Some(variant),
+            elems: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject, JsError> {
+        Ok(SerializeObject {
+            variant: None,
+            props: vec![],
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeObject, JsError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject, JsError> {
+        Ok(SerializeObject {
+            variant: Some(variant),
+            props: vec![],
+            next_key: None,
+        })
+    }
+}
+
+/// Wrap 'val' into an object { variant: val } if it is for an enum variant.
+fn wrap_variant(variant: Option<&'static str>, val: JsValue) -> JsValue {
+    match variant {
+        Some(variant) => JsValue::Object(vec![(variant.to_string(), val)]),
+        None => val,
+    }
+}
+
+struct SerializeArray {
+    variant: Option<&'static str>,
+    elems: Vec<JsValue>,
+}
+
+impl SerializeSeq for SerializeArray {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, val: &T) -> Result<(), JsError> {
+        self.elems.push(to_value(val)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        Ok(wrap_variant(self.variant, JsValue::Array(self.elems)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, val: &T) -> Result<(), JsError> {
+        SerializeSeq::serialize_element(self, val)
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, val: &T) -> Result<(), JsError> {
+        SerializeSeq::serialize_element(self, val)
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, val: &T) -> Result<(), JsError> {
+        SerializeSeq::serialize_element(self, val)
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct SerializeObject {
+    variant: Option<&'static str>,
+    props: Vec<(String, JsValue)>,
+    next_key: Option<String>,
+}
+
+impl SerializeMap for SerializeObject {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), JsError> {
+        // Property names are strings. Numbers are converted like obj[1] is obj['1'].
+        self.next_key = Some(match to_value(key)? {
+            JsValue::String(s) => s,
+            JsValue::Number(n) => number_to_string(n),
+            key => {
+                return Err(JsError::Runtime(format!(
+                    "property name must be a string or a number: {:?}",
+                    key
+                )))
+            }
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, val: &T) -> Result<(), JsError> {
+        let key = self.next_key.take().unwrap();
+        self.props.push((key, to_value(val)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        Ok(wrap_variant(self.variant, JsValue::Object(self.props)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        val: &T,
+    ) -> Result<(), JsError> {
+        self.props.push((key.to_string(), to_value(val)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = JsValue;
+    type Error = JsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        val: &T,
+    ) -> Result<(), JsError> {
+        ser::SerializeStruct::serialize_field(self, key, val)
+    }
+
+    fn end(self) -> Result<JsValue, JsError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, JsError> for JsValue {
+    type Deserializer = JsValue;
+
+    fn into_deserializer(self) -> JsValue {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for JsValue {
+    type Error = JsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsError> {
+        match self {
+            JsValue::Undefined | JsValue::Null | JsValue::Function => visitor.visit_unit(),
+            JsValue::Bool(b) => visitor.visit_bool(b),
+            // Integer types accept only integers.
+            JsValue::Number(n) if n.fract() == 0.0 && n.abs() < 9223372036854775808.0 => {
+                visitor.visit_i64(n as i64)
+            }
+            JsValue::Number(n) => visitor.visit_f64(n),
+            JsValue::BigInt(n) => visitor.visit_string(n.to_str_radix(10)),
+            JsValue::String(s) => visitor.visit_string(s),
+            JsValue::Array(elems) => {
+                let mut seq = SeqDeserializer::new(elems.into_iter());
+                let val = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(val)
+            }
+            JsValue::Object(props) => {
+                let props = props
+                    .into_iter()
+                    .map(|(name, val)| (PropertyName(name), val));
+                let mut map = MapDeserializer::new(props);
+                let val = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(val)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsError> {
+        match self {
+            JsValue::Undefined | JsValue::Null => visitor.visit_none(),
+            val => visitor.visit_some(val),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, JsError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsError> {
+        match self {
+            JsValue::String(variant) => visitor.visit_enum(EnumDeserializer {
+                variant: variant,
+                val: JsValue::Undefined,
+            }),
+            JsValue::Object(mut props) if props.len() == 1 => {
+                let (variant, val) = props.pop().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant: variant,
+                    val: val,
+                })
+            }
+            val => Err(JsError::Runtime(format!(
+                "expected a string or an object with one property for an enum: {:?}",
+                val
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// A property name. Maps with integer keys can be deserialized from objects, whose property
+/// names are always strings.
+struct PropertyName(String);
+
+macro_rules! deserialize_number_from_str {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsError> {
+                match self.0.parse() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => visitor.visit_string(self.0),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> IntoDeserializer<'de, JsError> for PropertyName {
+    type Deserializer = PropertyName;
+
+    fn into_deserializer(self) -> PropertyName {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for PropertyName {
+    type Error = JsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsError> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_number_from_str! {
+        deserialize_i8 => visit_i8, deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32, deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8, deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32, deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32, deserialize_f64 => visit_f64
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, JsError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsError> {
+        let variant: de::value::StringDeserializer<JsError> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 char str string bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    val: JsValue,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = JsError;
+    type Variant = JsValue;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, JsValue), JsError> {
+        let variant: de::value::StringDeserializer<JsError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, self.val))
+    }
+}
+
+impl<'de> VariantAccess<'de> for JsValue {
+    type Error = JsError;
+
+    fn unit_variant(self) -> Result<(), JsError> {
+        match self {
+            JsValue::Undefined | JsValue::Null => Ok(()),
+            val => Err(JsError::Runtime(format!(
+                "unexpected value for a unit variant: {:?}",
+                val
+            ))),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, JsError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, JsError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[test]
+fn test() {
+    use std::collections::BTreeMap;
+
+    let mut map = BTreeMap::new();
+    map.insert(1u32, vec![Some("a".to_string()), None]);
+    map.insert(20u32, vec![]);
+    let val = to_value(&map).unwrap();
+    assert_eq!(
+        val,
+        JsValue::Object(vec![
+            (
+                "1".to_string(),
+                JsValue::Array(vec!["a".into(), JsValue::Null])
+            ),
+            ("20".to_string(), JsValue::Array(vec![])),
+        ])
+    );
+    assert_eq!(
+        from_value::<BTreeMap<u32, Vec<Option<String>>>>(val.clone()),
+        Ok(map)
+    );
+    assert_eq!(from_value::<JsValue>(val.clone()), Ok(val.clone()));
+    assert_eq!(to_value(&val), Ok(val));
+
+    assert_eq!(
+        to_value(&(1u8, 'x', 2.5f32)),
+        Ok(vec![JsValue::from(1), "x".into(), 2.5.into()].into())
+    );
+    assert_eq!(
+        from_value::<(i64, bool)>(vec![JsValue::from(-3), true.into()].into()),
+        Ok((-3, true))
+    );
+    assert_eq!(from_value::<Option<i32>>(JsValue::Undefined), Ok(None));
+    assert!(from_value::<u8>(1.5.into()).is_err());
+    assert!(from_value::<(u8, u8)>(vec![1].into()).is_err());
+}
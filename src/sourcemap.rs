@@ -0,0 +1,222 @@
+use lexer::Lexer;
+
+// Source maps of compiled bytecode. A source map gives the line and column in the source of
+// each instruction and the function the code belongs to, so that the location of an error can
+// be shown even when a precompiled script (.rbc) runs without its source.
+//
+// 'rapidus compile --source-map' writes the map next to the .rbc file as .rbc.map, and running
+// the .rbc loads the map if it exists. The map is text:
+//
+//   rapidus-source-map <version>
+//   file <name of the source file>
+//   function <constant index or -> <line> <column> <name>
+//   <pc> <line> <column>
+//   ...
+//
+// A 'function' line starts the mappings of the code of a function, whose object is at the
+// constant index in the constant table. '-' is the top-level code. The mappings of a function
+// are sorted by pc, and an instruction is at the location of the last mapping at or before it.
+// Lines and columns start from 1, and columns are counted in bytes.
+
+/// Incremented whenever the format changes.
+pub const VERSION: u32 = 1;
+
+/// Source positions of the code of a function, recorded by the code generator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionPositions {
+    /// index of the function object in the constant table. None for the top-level code.
+    pub const_index: Option<usize>,
+    pub name: String,
+    /// position of the function in the source.
+    pub pos: usize,
+    /// (pc, position in the source) sorted by pc.
+    pub pcs: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceMap {
+    /// name of the source file.
+    pub file: String,
+    pub functions: Vec<FunctionMap>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionMap {
+    /// index of the function object in the constant table. None for the top-level code.
+    pub const_index: Option<usize>,
+    pub name: String,
+    /// (line, column) of the function.
+    pub location: (usize, usize),
+    /// (pc, line, column) sorted by pc.
+    pub mappings: Vec<(usize, usize, usize)>,
+}
+
+/// A frame of a stack trace, located by a source map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    /// name of the function. Empty for the top-level code.
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceMap {
+    /// Make the source map of the code compiled from 'file'. 'lexer' has read the source, and
+    /// converts positions into lines and columns.
+    pub fn new(file: &str, positions: &[FunctionPositions], lexer: &Lexer) -> SourceMap {
+        let functions = positions
+            .iter()
+            .map(|func| FunctionMap {
+                const_index: func.const_index,
+                name: func.name.clone(),
+                location: lexer.line_and_column(func.pos),
+                mappings: func
+                    .pcs
+                    .iter()
+                    .map(|&(pc, pos)| {
+                        let (line, column) = lexer.line_and_column(pos);
+                        (pc, line, column)
+                    })
+                    .collect(),
+            })
+            .collect();
+        SourceMap {
+            file: file.to_string(),
+            functions: functions,
+        }
+    }
+
+    /// the map of the code whose function object is at 'const_index' in the constant table, or
+    /// of the top-level code if it is None.
+    pub fn function(&self, const_index: Option<usize>) -> Option<&FunctionMap> {
+        self.functions
+            .iter()
+            .find(|func| func.const_index == const_index)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("rapidus-source-map {}\nfile {}\n", VERSION, self.file);
+        for func in &self.functions {
+            let const_index = func.const_index.map_or("-".to_string(), |i| i.to_string());
+            text += &format!(
+                "function {} {} {} {}\n",
+                const_index, func.location.0, func.location.1, func.name
+            );
+            for &(pc, line, column) in &func.mappings {
+                text += &format!("{} {} {}\n", pc, line, column);
+            }
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<SourceMap, String> {
+        fn header<'a>(line: Option<&'a str>, prefix: &str) -> Result<&'a str, String> {
+            match line {
+                Some(line) if line.starts_with(prefix) => Ok(&line[prefix.len()..]),
+                _ => Err("not a source map".to_string()),
+            }
+        }
+
+        let mut lines = text.lines();
+        let version = header(lines.next(), "rapidus-source-map ")?;
+        if version != VERSION.to_string() {
+            return Err(format!(
+                "source map version {} is not supported (expected {})",
+                version, VERSION
+            ));
+        }
+        let file = header(lines.next(), "file ")?.to_string();
+
+        let mut functions: Vec<FunctionMap> = vec![];
+        for (i, line) in lines.enumerate() {
+            // The mappings start at the third line.
+            let invalid = || format!("invalid source map at line {}", i + 3);
+            let number =
+                |s: Option<&str>| s.and_then(|s| s.parse::<usize>().ok()).ok_or_else(invalid);
+            if line.starts_with("function ") {
+                let mut fields = line["function ".len()..].splitn(4, ' ');
+                let const_index = match fields.next() {
+                    Some("-") => None,
+                    s => Some(number(s)?),
+                };
+                let location = (number(fields.next())?, number(fields.next())?);
+                functions.push(FunctionMap {
+                    const_index: const_index,
+                    name: fields.next().unwrap_or("").to_string(),
+                    location: location,
+                    mappings: vec![],
+                });
+            } else {
+                let mut fields = line.split(' ');
+                let mapping = (
+                    number(fields.next())?,
+                    number(fields.next())?,
+                    number(fields.next())?,
+                );
+                match functions.last_mut() {
+                    Some(func) => func.mappings.push(mapping),
+                    None => return Err(invalid()),
+                }
+            }
+        }
+
+        Ok(SourceMap {
+            file: file,
+            functions: functions,
+        })
+    }
+}
+
+impl FunctionMap {
+    /// (line, column) of the instruction at 'pc'. Instructions before the first mapping, such
+    /// as the declarations of variables, are at the location of the function.
+    pub fn location_of(&self, pc: usize) -> (usize, usize) {
+        let i = match self.mappings.binary_search_by_key(&pc, |mapping| mapping.0) {
+            Ok(i) => i,
+            Err(0) => return self.location,
+            Err(i) => i - 1,
+        };
+        let (_, line, column) = self.mappings[i];
+        (line, column)
+    }
+}
+
+#[test]
+fn test() {
+    let code = "var a = 1\nfunction f() {\n  return a\n}";
+    let mut lexer = Lexer::new(code.to_string());
+    lexer.tokenize_all().unwrap();
+    let positions = vec![
+        FunctionPositions {
+            const_index: Some(2),
+            name: "f".to_string(),
+            pos: 10,
+            pcs: vec![(6, 27), (8, 34)],
+        },
+        FunctionPositions {
+            const_index: None,
+            name: "".to_string(),
+            pos: 0,
+            pcs: vec![(1, 0), (3, 8)],
+        },
+    ];
+    let map = SourceMap::new("a.js", &positions, &lexer);
+    let f = map.function(Some(2)).unwrap();
+    assert_eq!(f.location, (2, 1));
+    assert_eq!(f.mappings, vec![(6, 3, 3), (8, 3, 10)]);
+    assert_eq!(f.location_of(0), (2, 1));
+    assert_eq!(f.location_of(7), (3, 3));
+    assert_eq!(f.location_of(100), (3, 10));
+    assert_eq!(map.function(None).unwrap().location_of(3), (1, 9));
+    assert_eq!(map.function(Some(0)), None);
+
+    let text = map.to_text();
+    assert_eq!(
+        text,
+        "rapidus-source-map 1\nfile a.js\nfunction 2 2 1 f\n6 3 3\n8 3 10\n\
+         function - 1 1 \n1 1 1\n3 1 9\n"
+    );
+    assert_eq!(SourceMap::from_text(&text), Ok(map));
+    assert!(SourceMap::from_text("rapidus-source-map 0\nfile a.js\n").is_err());
+    assert!(SourceMap::from_text("rapidus-source-map 1\nfile a.js\n1 2 3\n").is_err());
+}
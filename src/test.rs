@@ -7,7 +7,13 @@ use vm::value;
 
 pub fn test_file(file_name: String, answer: String) {
     println!("{}", format!("test/{}.js", file_name));
-    compare_scripts(load_file(file_name), answer);
+    compare_scripts(load_file(file_name), answer, false);
+}
+
+/// Like test_file(), but the file is compiled into register-addressed bytecode.
+pub fn test_file_with_registers(file_name: String, answer: String) {
+    println!("{} (registers)", format!("test/{}.js", file_name));
+    compare_scripts(load_file(file_name), answer, true);
 }
 
 pub fn assert_file(file_name: String) {
@@ -31,11 +37,16 @@ fn load_file(file_name: String) -> String {
 }
 
 pub fn test_code(code: String, answer: String) {
-    compare_scripts(code, answer);
+    compare_scripts(code, answer, false);
 }
 
 pub fn execute_script(text: String, debug: bool) -> String {
+    run_script(text, debug, false)
+}
+
+fn run_script(text: String, debug: bool, use_registers: bool) -> String {
     let mut vm = vm::vm::VM::new();
+    vm.codegen.use_registers = use_registers;
 
     let mut parser = parser::Parser::new(text);
     let node = parser.parse_all().unwrap();
@@ -52,8 +63,8 @@ pub fn execute_script(text: String, debug: bool) -> String {
         .format(5, true)
 }
 
-fn compare_scripts(text: String, answer: String) {
-    let res_text = execute_script(text, false);
+fn compare_scripts(text: String, answer: String, use_registers: bool) {
+    let res_text = run_script(text, false, use_registers);
     println!("file: {}", res_text);
 
     let res_answer = execute_script(answer, false);
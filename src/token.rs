@@ -1,4 +1,5 @@
 use node::BinOp;
+use num_bigint::BigInt;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
@@ -14,7 +15,12 @@ pub enum Kind {
     Keyword(Keyword),
     Identifier(String),
     Number(f64),
+    BigInt(BigInt),
     String(String),
+    /// a part of a template literal from '`' or '}' to '`' or '${': the cooked string (None if
+    /// it has an invalid escape sequence), the raw string, whether it starts the literal (from
+    /// '`'), and whether it ends the literal (to '`').
+    Template(Option<String>, String, bool, bool),
     Symbol(Symbol),
     LineTerminator,
     EOF,
@@ -27,6 +33,7 @@ pub enum Keyword {
     Break,
     Case,
     Catch,
+    Const,
     Continue,
     Debugger,
     Default,
@@ -98,6 +105,7 @@ pub enum Symbol {
     AssignMul,
     AssignDiv,
     AssignMod,
+    AssignExp,
     AssignShl,
     AssignShr,
     AssignAnd,
@@ -118,6 +126,14 @@ impl Token {
         }
     }
 
+    pub fn new_bigint(n: BigInt, pos: usize) -> Token {
+        Token {
+            kind: Kind::BigInt(n),
+            pos: pos,
+            prev_pos: 0,
+        }
+    }
+
     pub fn new_identifier(ident: String, pos: usize) -> Token {
         Token {
             kind: Kind::Identifier(ident),
@@ -142,6 +158,20 @@ impl Token {
         }
     }
 
+    pub fn new_template(
+        cooked: Option<String>,
+        raw: String,
+        head: bool,
+        tail: bool,
+        pos: usize,
+    ) -> Token {
+        Token {
+            kind: Kind::Template(cooked, raw, head, tail),
+            pos: pos,
+            prev_pos: 0,
+        }
+    }
+
     pub fn new_symbol(symbol: Symbol, pos: usize) -> Token {
         Token {
             kind: Kind::Symbol(symbol),
@@ -207,6 +237,7 @@ pub fn convert_reserved_keyword(keyword: &str) -> Option<Keyword> {
         "break" => Some(Keyword::Break),
         "case" => Some(Keyword::Case),
         "catch" => Some(Keyword::Catch),
+        "const" => Some(Keyword::Const),
         "continue" => Some(Keyword::Continue),
         "debugger" => Some(Keyword::Debugger),
         "default" => Some(Keyword::Default),
@@ -234,6 +265,41 @@ pub fn convert_reserved_keyword(keyword: &str) -> Option<Keyword> {
     }
 }
 
+pub fn get_string_for_keyword(keyword: Keyword) -> String {
+    match keyword {
+        Keyword::Abstract => "abstract",
+        Keyword::Arguments => "arguments",
+        Keyword::Break => "break",
+        Keyword::Case => "case",
+        Keyword::Catch => "catch",
+        Keyword::Const => "const",
+        Keyword::Continue => "continue",
+        Keyword::Debugger => "debugger",
+        Keyword::Default => "default",
+        Keyword::Delete => "delete",
+        Keyword::Do => "do",
+        Keyword::Else => "else",
+        Keyword::Finally => "finally",
+        Keyword::For => "for",
+        Keyword::Function => "function",
+        Keyword::If => "if",
+        Keyword::In => "in",
+        Keyword::Instanceof => "instanceof",
+        Keyword::New => "new",
+        Keyword::Return => "return",
+        Keyword::Switch => "switch",
+        Keyword::This => "this",
+        Keyword::Throw => "throw",
+        Keyword::Try => "try",
+        Keyword::Typeof => "typeof",
+        Keyword::Var => "var",
+        Keyword::Void => "void",
+        Keyword::While => "while",
+        Keyword::With => "with",
+    }
+    .to_string()
+}
+
 pub fn get_string_for_symbol(symbol: Symbol) -> String {
     match symbol {
         Symbol::OpeningParen => "(".to_string(),
@@ -0,0 +1,331 @@
+use bytecode_gen::{inst_to_string, ByteCode, Inst, VMInst};
+use vm::value::*;
+use vm::vm::ConstantTable;
+
+// Verifier of bytecode. The VM trusts the code it runs: a jump into the middle of an
+// instruction, an index out of the constant table or a pop from an empty stack make it panic
+// or misbehave somewhere later. The verifier checks before the code runs that
+//
+// - every instruction is known and has all its operands,
+// - jumps go to the beginning of an instruction, and the code never runs past its end,
+// - the indexes of constants, names and upvalues are in their tables, and
+// - the stack has the same depth whichever way an instruction is reached, and no instruction
+//   pops more values than there are.
+//
+// The stack depth is counted from the beginning of the code. The catch clause of a try
+// statement begins with the error pushed on the depth at ENTER_TRY. The output of the code
+// generator is verified in debug builds, and precompiled scripts when they are loaded.
+
+/// Verify the top-level code 'iseq' and all the functions in 'const_table'.
+pub fn verify_script(iseq: &ByteCode, const_table: &ConstantTable) -> Result<(), String> {
+    verify(iseq, const_table, None).map_err(|msg| format!("top-level code: {}", msg))?;
+    for (id, val) in const_table.value.iter().enumerate() {
+        if let Value::Object(_, ObjectKind::Function(box (ref info, _))) = val {
+            verify(&info.iseq, const_table, Some(info.upvalues.len()))
+                .map_err(|msg| format!("function (constant {}): {}", id, msg))?;
+        }
+    }
+    Ok(())
+}
+
+/// Verify the code of a function which has 'upvalues' upvalues, or of a script if it is None.
+/// The error tells the pc and the instruction at fault.
+pub fn verify(
+    iseq: &ByteCode,
+    const_table: &ConstantTable,
+    upvalues: Option<usize>,
+) -> Result<(), String> {
+    let mut is_inst = vec![false; iseq.len()];
+    let mut pc = 0;
+    while pc < iseq.len() {
+        let size = match VMInst::get_inst_size(iseq[pc]) {
+            Some(size) => size,
+            None => {
+                return Err(format!(
+                    "{:04x}: unknown instruction 0x{:02x}",
+                    pc, iseq[pc]
+                ))
+            }
+        };
+        if pc + size > iseq.len() {
+            return Err(format!("{:04x}: the instruction is cut off at the end", pc));
+        }
+        is_inst[pc] = true;
+        pc += size;
+    }
+
+    let mut pc = 0;
+    while pc < iseq.len() {
+        let inst = Inst::decode(&iseq[pc..]).unwrap();
+        check_operands(&inst, pc, const_table, upvalues)?;
+        for dst in inst.destinations(pc as isize) {
+            // The end of a loop given to LOOP_START may be the end of the code.
+            let at_end = dst == iseq.len() as isize && inst.opcode() == VMInst::LOOP_START;
+            if !at_end && (dst < 0 || dst >= iseq.len() as isize || !is_inst[dst as usize]) {
+                return Err(format!(
+                    "{:04x} {}: jumps to {:04x}, which is not an instruction",
+                    pc,
+                    inst_to_string(iseq, pc, const_table),
+                    dst
+                ));
+            }
+        }
+        pc += inst.size();
+    }
+
+    check_stack(iseq, const_table)
+}
+
+fn check_operands(
+    inst: &Inst,
+    pc: usize,
+    const_table: &ConstantTable,
+    upvalues: Option<usize>,
+) -> Result<(), String> {
+    let index = |id: i32| id as u32 as usize;
+    let (id, len, table) = match *inst {
+        Inst::PushConst { id } | Inst::LoadConstReg { id, .. } => {
+            (index(id), const_table.value.len(), "constant")
+        }
+        Inst::GetValue { name }
+        | Inst::GetGlobal { name, .. }
+        | Inst::SetValue { name }
+        | Inst::SetValueStrict { name }
+        | Inst::DeclVar { name }
+        | Inst::GetValueReg { name, .. } => (index(name), const_table.string.len(), "name"),
+        Inst::GetUpvalue { id } | Inst::SetUpvalue { id } => match upvalues {
+            Some(upvalues) => (index(id), upvalues, "upvalue"),
+            None => {
+                return Err(format!(
+                    "{:04x}: upvalue {} in the top-level code, which has no upvalues",
+                    pc,
+                    index(id)
+                ))
+            }
+        },
+        _ => return Ok(()),
+    };
+    if id >= len {
+        return Err(format!(
+            "{:04x}: {} {} is out of the {} {}s",
+            pc, table, id, len, table
+        ));
+    }
+    Ok(())
+}
+
+/// The number of values 'inst' pops, and the number it pushes.
+fn stack_effect(inst: &Inst) -> (usize, usize) {
+    let count = |n: i32| n as u32 as usize;
+    match *inst {
+        Inst::Construct { argc } | Inst::Call { argc } | Inst::CallEval { argc } => {
+            (count(argc) + 2, 1)
+        }
+        Inst::TailCall { argc } => (count(argc) + 2, 0),
+        Inst::CreateObject { len } => (count(len) * 2, 1),
+        Inst::CreateArray { len } => (count(len), 1),
+        Inst::PushInt8 { .. }
+        | Inst::PushInt32 { .. }
+        | Inst::PushFalse
+        | Inst::PushTrue
+        | Inst::PushConst { .. }
+        | Inst::PushThis
+        | Inst::PushArguments
+        | Inst::PushUndefined
+        | Inst::PushReg { .. }
+        | Inst::GetUpvalue { .. }
+        | Inst::GetValue { .. }
+        | Inst::GetGlobal { .. } => (0, 1),
+        Inst::LNot
+        | Inst::BNot
+        | Inst::Posi
+        | Inst::Neg
+        | Inst::CreateClosure
+        | Inst::UpdateParentScope => (1, 1),
+        Inst::Add
+        | Inst::Sub
+        | Inst::Mul
+        | Inst::Div
+        | Inst::Rem
+        | Inst::Exp
+        | Inst::Lt
+        | Inst::Gt
+        | Inst::Le
+        | Inst::Ge
+        | Inst::Eq
+        | Inst::Ne
+        | Inst::SEq
+        | Inst::SNe
+        | Inst::And
+        | Inst::Or
+        | Inst::Xor
+        | Inst::Shl
+        | Inst::Shr
+        | Inst::ZFShr
+        | Inst::GetMember { .. }
+        | Inst::CopyDataProperties => (2, 1),
+        Inst::SetMember { .. } => (3, 0),
+        Inst::SetGetter | Inst::SetSetter | Inst::DefineProperty => (3, 1),
+        Inst::Double => (1, 2),
+        Inst::Double2 => (2, 4),
+        Inst::Rotate { n } => (n as usize + 1, n as usize + 1),
+        Inst::Pop
+        | Inst::PopReg { .. }
+        | Inst::SetUpvalue { .. }
+        | Inst::SetValue { .. }
+        | Inst::SetValueStrict { .. }
+        | Inst::JmpIfFalse { .. }
+        | Inst::ReturnTry { .. }
+        | Inst::PushWithScope
+        | Inst::Throw
+        | Inst::Return => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+/// The instructions which may run after 'inst' at 'pc', and the stack depth they begin with,
+/// given 'depth' after it.
+fn successors(inst: &Inst, pc: usize, depth: usize) -> Vec<(usize, usize)> {
+    let next = pc + inst.size();
+    let dsts: Vec<usize> = inst
+        .destinations(pc as isize)
+        .into_iter()
+        .map(|dst| dst as usize)
+        .collect();
+    match *inst {
+        Inst::End | Inst::Return | Inst::TailCall { .. } | Inst::Throw => vec![],
+        Inst::Jmp { .. } | Inst::ReturnTry { .. } => vec![(dsts[0], depth)],
+        Inst::JmpIfFalse { .. } => vec![(next, depth), (dsts[0], depth)],
+        Inst::EnterTry { .. } => vec![(next, depth), (dsts[0], depth + 1), (dsts[1], depth)],
+        _ => vec![(next, depth)],
+    }
+}
+
+fn check_stack(iseq: &ByteCode, const_table: &ConstantTable) -> Result<(), String> {
+    if iseq.is_empty() {
+        return Ok(());
+    }
+    // the stack depth each instruction begins with, once it is found to be reached.
+    let mut depths: Vec<Option<usize>> = vec![None; iseq.len()];
+    depths[0] = Some(0);
+    let mut work = vec![0];
+    while let Some(pc) = work.pop() {
+        let depth = depths[pc].unwrap();
+        let inst = Inst::decode(&iseq[pc..]).unwrap();
+        let (pops, pushes) = stack_effect(&inst);
+        if pops > depth {
+            return Err(format!(
+                "{:04x} {}: pops {} values from the stack of {}",
+                pc,
+                inst_to_string(iseq, pc, const_table),
+                pops,
+                depth
+            ));
+        }
+        for (next, next_depth) in successors(&inst, pc, depth - pops + pushes) {
+            if next >= iseq.len() {
+                return Err(format!(
+                    "{:04x} {}: runs past the end of the code",
+                    pc,
+                    inst_to_string(iseq, pc, const_table)
+                ));
+            }
+            match depths[next] {
+                None => {
+                    depths[next] = Some(next_depth);
+                    work.push(next);
+                }
+                Some(known) if known != next_depth => {
+                    return Err(format!(
+                        "{:04x} {}: reached with {} values on the stack from {:04x}, but \
+                         {} from elsewhere",
+                        next,
+                        inst_to_string(iseq, next, const_table),
+                        next_depth,
+                        pc,
+                        known
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify() {
+    use bytecode_gen::ByteCodeGen;
+    use parser::Parser;
+    use vm::vm::VM;
+
+    let mut vm = VM::new();
+    let node = Parser::new(
+        "var a = [1, 2], o = { x: 1, get y() { return 2 } };
+         function f(n) { try { if (n) throw n; return n ? 1 : 2 } catch (e) { return e } finally { a[0]++ } }
+         for (var i = 0; i < 3; i++) { a, o.x; f(i) && i; i ? 1 : 2 }
+         with (o) { x += y }"
+            .to_string(),
+    )
+    .parse_all()
+    .unwrap();
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    let const_table = &vm.codegen.bytecode_gen.const_table;
+    assert_eq!(verify_script(&iseq, const_table), Ok(()));
+
+    // Only the instructions made by 'f' are verified.
+    fn check(f: fn(&ByteCodeGen, &mut ByteCode)) -> Result<(), String> {
+        let mut gen = ByteCodeGen::new();
+        gen.const_table.value.push(Value::Number(1.0));
+        gen.const_table.string.push(::atom::Atom::new("x"));
+        let mut iseq = vec![];
+        f(&gen, &mut iseq);
+        verify(&iseq, &gen.const_table, Some(1))
+    }
+    assert_eq!(
+        check(|gen, iseq| {
+            gen.gen_push_int8(1, iseq);
+            gen.gen_push_int8(2, iseq);
+            gen.gen_add(iseq);
+            gen.gen_return(iseq);
+        }),
+        Ok(())
+    );
+    // an unknown instruction
+    assert!(check(|_, iseq| iseq.push(0xff)).is_err());
+    // an instruction cut off
+    assert!(check(|_, iseq| iseq.extend(vec![VMInst::PUSH_CONST, 0, 0])).is_err());
+    // a jump into PUSH_INT32
+    assert!(check(|gen, iseq| {
+        gen.gen_jmp(1, iseq);
+        gen.gen_push_int32(1000, iseq);
+        gen.gen_end(iseq);
+    })
+    .is_err());
+    // running past the end
+    assert!(check(|gen, iseq| gen.gen_push_undefined(iseq)).is_err());
+    // a constant, a name and an upvalue out of the tables
+    assert!(check(|_, iseq| iseq.extend(vec![VMInst::PUSH_CONST, 1, 0, 0, 0, 0])).is_err());
+    assert!(check(|_, iseq| iseq.extend(vec![VMInst::GET_VALUE, 1, 0, 0, 0, 0])).is_err());
+    assert!(check(|gen, iseq| {
+        gen.gen_get_upvalue(1, iseq);
+        gen.gen_return(iseq);
+    })
+    .is_err());
+    // popping too much
+    assert!(check(|gen, iseq| {
+        gen.gen_push_int8(1, iseq);
+        gen.gen_add(iseq);
+        gen.gen_end(iseq);
+    })
+    .is_err());
+    // END reached with different depths
+    assert!(check(|gen, iseq| {
+        gen.gen_push_bool(true, iseq);
+        gen.gen_jmp_if_false(2, iseq);
+        gen.gen_push_int8(1, iseq);
+        gen.gen_end(iseq);
+    })
+    .is_err());
+}
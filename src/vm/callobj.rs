@@ -1,10 +1,9 @@
 use super::error::RuntimeError;
 use super::value::*;
 use gc;
-use rustc_hash::FxHashMap;
 
 #[derive(Clone)]
-/// 80 bytes
+/// 104 bytes
 pub struct CallObject {
     /// map of variables belongs to the scope.
     pub vals: PropMapRef,
@@ -16,6 +15,8 @@ pub struct CallObject {
     pub this: Box<Value>,
     /// reference to the outer scope.
     pub parent: Option<CallObjectRef>,
+    /// upvalues of the function (see upvalue.rs).
+    pub upvalues: Vec<UpvalueRef>,
 }
 
 impl PartialEq for CallObject {
@@ -36,11 +37,12 @@ impl fmt::Debug for CallObject {
 impl CallObject {
     fn new(this: Value) -> CallObject {
         CallObject {
-            vals: gc::new(FxHashMap::default()),
+            vals: gc::new(PropMap::new()),
             rest_params: None,
             arguments: vec![],
             this: Box::new(this),
             parent: None,
+            upvalues: vec![],
         }
     }
 
@@ -62,27 +64,70 @@ impl CallObject {
         };
         callobj.apply_arguments(func_info.clone(), args);
         callobj.parent = self.clone().parent;
+        callobj.upvalues = self.upvalues.clone();
         gc::new(callobj)
     }
 
+    /// Same as new_callobj_from_func(), but reuses 'callobj', which has been cleared by clear().
+    pub fn reuse_callobj_for_func(
+        &self,
+        mut callobj: CallObjectRef,
+        func_info: FuncInfo,
+        args: &Vec<Value>,
+        this: Option<Value>,
+    ) -> CallObjectRef {
+        gc::write_barrier(&mut *callobj);
+        *callobj.this = match this {
+            Some(this) => this,
+            None => *self.this.clone(),
+        };
+        callobj.apply_arguments(func_info, args);
+        callobj.parent = self.clone().parent;
+        callobj.upvalues = self.upvalues.clone();
+        callobj
+    }
+
+    /// Forget the variables, the arguments, 'this', the outer scope and the upvalues, so that the
+    /// call object can be reused for another call.
+    pub fn clear(&mut self) {
+        self.vals.clear();
+        self.rest_params = None;
+        self.arguments.clear();
+        *self.this = Value::Undefined;
+        self.parent = None;
+        self.upvalues.clear();
+    }
+
     pub fn new_global() -> CallObjectRef {
-        let vals = gc::new(FxHashMap::default());
+        let vals = gc::new(PropMap::new());
         gc::new(CallObject {
             vals: vals.clone(),
             rest_params: None,
             arguments: vec![],
             this: Box::new(Value::Object(vals.clone(), ObjectKind::Ordinary)),
             parent: None,
+            upvalues: vec![],
         })
     }
 
+    /// Replace 'this'. Use this rather than assigning it, since the GC must know when an old
+    /// CallObject may refer to a young object.
+    pub fn set_this(&mut self, this: Value) {
+        gc::write_barrier(self);
+        *self.this = this;
+    }
+
     pub fn set_value(&mut self, name: String, val: Value) {
         self.vals.insert(name, val.to_property());
     }
 
+    /// Assign 'val' to the variable 'name' of the nearest scope which has it, or define it in
+    /// the global scope. Assigning a read-only variable such as NaN does nothing.
     pub fn set_value_if_exist(&mut self, name: String, val: Value) {
-        if self.vals.contains_key(&name.clone()) {
-            self.vals.insert(name.clone(), val.to_property());
+        if let Some(writable) = self.vals.get(&name).map(|prop| prop.writable) {
+            if writable {
+                self.vals.insert(name, val.to_property());
+            }
         } else {
             match self.parent {
                 Some(ref mut parent) => {
@@ -95,7 +140,18 @@ impl CallObject {
         }
     }
 
-    pub fn get_value(&self, name: &String) -> Result<Value, RuntimeError> {
+    /// Whether the variable 'name' of the nearest scope which has it can be assigned.
+    pub fn is_writable(&self, name: &str) -> bool {
+        match self.vals.get(name) {
+            Some(prop) => prop.writable,
+            None => self
+                .parent
+                .as_ref()
+                .map_or(true, |parent| parent.is_writable(name)),
+        }
+    }
+
+    pub fn get_value(&self, name: &str) -> Result<Value, RuntimeError> {
         if let Some(prop) = self.vals.get(name) {
             return Ok(prop.val.clone());
         }
@@ -136,6 +192,7 @@ impl CallObject {
             if let Some(param_name) = param_name {
                 self.set_value(param_name, val);
             } else {
+                gc::write_barrier(self);
                 self.arguments[n].1 = val;
             }
         }
@@ -0,0 +1,402 @@
+use bytecode_gen::ByteCode;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use vm::{error::RuntimeError, value::Value, vm::VM};
+
+// A step debugger of the VM. While VM::debugger is set, the run loop calls Debugger::check()
+// before each instruction, and the debugger stops when the script reaches a line which has a
+// breakpoint or which ends a step. What happens then is up to its frontend. The terminal
+// frontend ('rapidus --debug') reads commands from its input until one of them resumes the
+// script:
+//
+//   break <line>, b <line>     set a breakpoint
+//   delete <line>, d <line>    delete a breakpoint
+//   step, s                    run to the next line, entering calls
+//   next, n                    run to the next line of the current function or its callers
+//   continue, c                run to a breakpoint
+//   locals, l                  show the variables of the current scope
+//   print <name>, p <name>     show the value of a variable
+//   stack                      show the value stack of the current frame
+//   backtrace, bt              show the frames
+//   quit, q                    stop the script
+//
+// Lines are found by the source map of the VM. Without a source map, every instruction is a
+// line of its own, numbered by its pc.
+
+const HELP: &str = "\
+break <line>, b <line>     set a breakpoint
+delete <line>, d <line>    delete a breakpoint
+step, s                    run to the next line, entering calls
+next, n                    run to the next line of the current function or its callers
+continue, c                run to a breakpoint
+locals, l                  show the variables of the current scope
+print <name>, p <name>     show the value of a variable
+stack                      show the value stack of the current frame
+backtrace, bt              show the frames
+quit, q                    stop the script";
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    /// (number of frames, code, line) of the last instruction checked. The debugger stops only
+    /// when it changes.
+    last: Option<(usize, *const ByteCode, usize)>,
+    /// the code last checked and the constant index of its function object. See
+    /// VM::function_const_index().
+    function: Option<(Rc<ByteCode>, Option<usize>)>,
+    frontend: Box<dyn Frontend>,
+}
+
+/// How the script goes on after the debugger stops.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// stop at the next line.
+    Step,
+    /// stop at a line of a frame at most this deep.
+    Next(usize),
+    /// stop at a line of a frame less deep than this.
+    Out(usize),
+    Continue,
+}
+
+/// The user interface of the debugger: a terminal (Terminal) or Chrome DevTools
+/// (inspector::Inspector).
+pub trait Frontend {
+    /// Called when the script stops before the instruction at 'pc' of the current code, which
+    /// is at 'line' if the VM has a source map. Returns how the script goes on, or
+    /// RuntimeError::Terminated to stop it.
+    fn stop(
+        &mut self,
+        vm: &mut VM,
+        breakpoints: &mut BTreeSet<usize>,
+        pc: usize,
+        line: Option<usize>,
+    ) -> Result<Mode, RuntimeError>;
+
+    /// Called when the running script reaches a new line. Returns true to stop there.
+    fn poll(&mut self, _vm: &mut VM, _breakpoints: &mut BTreeSet<usize>) -> bool {
+        false
+    }
+
+    /// Called when the script writes 'args' to the console. 'kind' is the name of the method
+    /// of console, e.g. "log".
+    fn console(&mut self, _vm: &mut VM, _kind: &str, _args: &[Value]) {}
+}
+
+impl Debugger {
+    /// A debugger which stops at the first line if 'stop_at_start' is true.
+    pub fn new(frontend: Box<dyn Frontend>, stop_at_start: bool) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            mode: if stop_at_start {
+                Mode::Step
+            } else {
+                Mode::Continue
+            },
+            last: None,
+            function: None,
+            frontend: frontend,
+        }
+    }
+
+    /// A debugger reading commands from the standard input. It stops at the first line.
+    pub fn terminal(source: &str) -> Debugger {
+        let terminal = Terminal::new(
+            source,
+            Box::new(io::BufReader::new(io::stdin())),
+            Box::new(io::stdout()),
+        );
+        Debugger::new(Box::new(terminal), true)
+    }
+
+    /// Called before the instruction at vm.state.pc is executed. Stops there if needed, and
+    /// returns RuntimeError::Terminated if the user quits.
+    pub fn check(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        let depth = vm.state.frames.len();
+        let pc = vm.state.pc as usize;
+        let line = self.line_of(vm, pc);
+
+        let location = (
+            depth,
+            &*vm.state.iseq as *const ByteCode,
+            line.unwrap_or(pc),
+        );
+        if self.last == Some(location) {
+            return Ok(());
+        }
+        self.last = Some(location);
+
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(max_depth) => depth <= max_depth,
+            Mode::Out(depth_from) => depth < depth_from,
+            Mode::Continue => false,
+        } || self.breakpoints.contains(&location.2)
+            || self.frontend.poll(vm, &mut self.breakpoints);
+        if stop {
+            self.mode = self.frontend.stop(vm, &mut self.breakpoints, pc, line)?;
+        }
+        Ok(())
+    }
+
+    /// Called by console.log() etc. See builtins::console::notify_debugger().
+    pub fn console(&mut self, vm: &mut VM, kind: &str, args: &[Value]) {
+        self.frontend.console(vm, kind, args)
+    }
+
+    /// the line of the instruction at 'pc' of the current code by the source map.
+    fn line_of(&mut self, vm: &VM, pc: usize) -> Option<usize> {
+        let source_map = vm.source_map.as_ref()?;
+        let const_index = match self.function {
+            Some((ref iseq, const_index)) if Rc::ptr_eq(iseq, &vm.state.iseq) => const_index,
+            _ => {
+                let const_index = vm.function_const_index(&vm.state.iseq);
+                self.function = Some((vm.state.iseq.clone(), const_index));
+                const_index
+            }
+        };
+        let (line, _) = source_map.function(const_index)?.location_of(pc);
+        Some(line)
+    }
+}
+
+/// The debugger on a terminal ('rapidus --debug').
+pub struct Terminal {
+    /// lines of the source, shown when the script stops.
+    source: Vec<String>,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Frontend for Terminal {
+    fn stop(
+        &mut self,
+        vm: &mut VM,
+        breakpoints: &mut BTreeSet<usize>,
+        pc: usize,
+        line: Option<usize>,
+    ) -> Result<Mode, RuntimeError> {
+        self.show_location(vm, pc, line);
+        self.read_commands(vm, breakpoints)
+    }
+}
+
+impl Terminal {
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Terminal {
+        Terminal {
+            source: source.lines().map(|line| line.to_string()).collect(),
+            input: input,
+            output: output,
+        }
+    }
+
+    fn show_location(&mut self, vm: &VM, pc: usize, line: Option<usize>) {
+        let line = match line {
+            Some(line) => line,
+            None => {
+                self.say(format!("Stopped at pc {}", pc));
+                return;
+            }
+        };
+        let file = vm.source_map.as_ref().map_or("", |map| map.file.as_str());
+        let name = vm
+            .source_location(&vm.state.iseq, pc)
+            .map_or("".to_string(), |frame| frame.name);
+        let msg = if name.is_empty() {
+            format!("Stopped at {}:{}", file, line)
+        } else {
+            format!("Stopped at {}:{} in {}", file, line, name)
+        };
+        self.say(msg);
+        if let Some(text) = self.source.get(line - 1).cloned() {
+            self.say(format!("{:>4} | {}", line, text));
+        }
+    }
+
+    /// Run the commands until one of them resumes the script. The end of the input lets the
+    /// script run to its end.
+    fn read_commands(
+        &mut self,
+        vm: &VM,
+        breakpoints: &mut BTreeSet<usize>,
+    ) -> Result<Mode, RuntimeError> {
+        loop {
+            write!(self.output, "(debug) ").ok();
+            self.output.flush().ok();
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    breakpoints.clear();
+                    return Ok(Mode::Continue);
+                }
+                Ok(_) => {}
+            }
+
+            let mut words = command.split_whitespace();
+            match (words.next(), words.next()) {
+                (None, _) => {}
+                (Some("step"), _) | (Some("s"), _) => return Ok(Mode::Step),
+                (Some("next"), _) | (Some("n"), _) => return Ok(Mode::Next(vm.state.frames.len())),
+                (Some("continue"), _) | (Some("c"), _) => return Ok(Mode::Continue),
+                (Some("quit"), _) | (Some("q"), _) => return Err(RuntimeError::Terminated),
+                (Some("break"), arg) | (Some("b"), arg) => match parse_line(arg) {
+                    Some(line) => {
+                        breakpoints.insert(line);
+                        self.say(format!("Breakpoint at line {}", line));
+                    }
+                    None => self.say("usage: break <line>".to_string()),
+                },
+                (Some("delete"), arg) | (Some("d"), arg) => match parse_line(arg) {
+                    Some(line) if breakpoints.remove(&line) => {
+                        self.say(format!("Deleted the breakpoint at line {}", line))
+                    }
+                    Some(line) => self.say(format!("No breakpoint at line {}", line)),
+                    None => self.say("usage: delete <line>".to_string()),
+                },
+                (Some("locals"), _) | (Some("l"), _) => {
+                    let mut vars: Vec<(String, String)> = match vm.state.scope.last() {
+                        Some(scope) => scope
+                            .vals
+                            .iter()
+                            .map(|(name, prop)| (name.to_string(), prop.val.format(1, false)))
+                            .collect(),
+                        None => vec![],
+                    };
+                    vars.sort();
+                    for (name, val) in vars {
+                        self.say(format!("{} = {}", name, val));
+                    }
+                }
+                (Some("print"), Some(name)) | (Some("p"), Some(name)) => {
+                    let val = vm
+                        .state
+                        .scope
+                        .last()
+                        .and_then(|scope| scope.get_value(name).ok());
+                    match val {
+                        Some(val) => self.say(format!("{} = {}", name, val.format(3, false))),
+                        None => self.say(format!("{} is not defined", name)),
+                    }
+                }
+                (Some("print"), None) | (Some("p"), None) => {
+                    self.say("usage: print <name>".to_string())
+                }
+                (Some("stack"), _) => {
+                    let sp = vm.state.frames.last().map_or(0, |frame| frame.sp);
+                    let vals: Vec<String> = vm.state.stack[sp.min(vm.state.stack.len())..]
+                        .iter()
+                        .map(|val| val.format(1, false))
+                        .collect();
+                    if vals.is_empty() {
+                        self.say("(empty)".to_string());
+                    }
+                    for (i, val) in vals.into_iter().enumerate().rev() {
+                        self.say(format!("{:>4}: {}", i, val));
+                    }
+                }
+                (Some("backtrace"), _) | (Some("bt"), _) => {
+                    let file = vm.source_map.as_ref().map_or("", |map| map.file.as_str());
+                    let frames: Vec<String> = vm
+                        .stack_trace(vm.state.pc)
+                        .iter()
+                        .enumerate()
+                        .map(|(i, frame)| {
+                            if frame.name.is_empty() {
+                                format!("#{} {}:{}:{}", i, file, frame.line, frame.column)
+                            } else {
+                                format!(
+                                    "#{} {} ({}:{}:{})",
+                                    i, frame.name, file, frame.line, frame.column
+                                )
+                            }
+                        })
+                        .collect();
+                    if frames.is_empty() {
+                        self.say("No source map to show the frames".to_string());
+                    }
+                    for frame in frames {
+                        self.say(frame);
+                    }
+                }
+                (Some("help"), _) | (Some("h"), _) => self.say(HELP.to_string()),
+                (Some(command), _) => {
+                    self.say(format!("Unknown command '{}'. Type 'help'", command))
+                }
+            }
+        }
+    }
+
+    fn say(&mut self, msg: String) {
+        writeln!(self.output, "{}", msg).ok();
+    }
+}
+
+fn parse_line(arg: Option<&str>) -> Option<usize> {
+    arg.and_then(|arg| arg.parse().ok())
+        .filter(|&line| line > 0)
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+    use sourcemap::SourceMap;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::mem;
+
+    #[derive(Clone)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let code = "var x = 1\nfunction f(a) {\n  var b = a + x\n  return b\n}\nx = f(2)\n";
+    let mut parser = Parser::new(code.to_string());
+    let node = parser.parse_all().unwrap();
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    vm.codegen.resolve_locals = false;
+    vm.codegen.record_positions = true;
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    vm.codegen.record_positions = false;
+    let positions = mem::replace(&mut vm.codegen.function_positions, vec![]);
+    vm.source_map = Some(SourceMap::new("a.js", &positions, &parser.lexer));
+
+    let output = Output(Rc::new(RefCell::new(vec![])));
+    let commands = "b 3\nc\np a\nn\nl\nbt\nfoo\nc\n";
+    let terminal = Terminal::new(
+        code,
+        Box::new(Cursor::new(commands)),
+        Box::new(output.clone()),
+    );
+    vm.debugger = Some(Debugger::new(Box::new(terminal), true));
+    vm.run(iseq).unwrap();
+    assert_eq!(
+        vm.state.scope[0].get_value("x").unwrap(),
+        Value::Number(3.0)
+    );
+
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+    assert!(output.starts_with("Stopped at a.js:1\n   1 | var x = 1\n"));
+    assert!(output.contains("Breakpoint at line 3\n"));
+    assert!(output.contains("Stopped at a.js:3 in f\n   3 |   var b = a + x\n"));
+    assert!(output.contains("a = 2\n"));
+    assert!(output.contains("Stopped at a.js:4 in f\n"));
+    assert!(output.contains("b = 3\n"));
+    assert!(output.contains("#0 f (a.js:4:"));
+    assert!(output.contains("#1 a.js:6:5\n"));
+    assert!(output.contains("Unknown command 'foo'"));
+
+    // Quitting terminates the script.
+    let mut vm = VM::new();
+    let terminal = Terminal::new("", Box::new(Cursor::new("q\n")), Box::new(io::sink()));
+    vm.debugger = Some(Debugger::new(Box::new(terminal), true));
+    assert_eq!(vm.run(vec![]), Err(RuntimeError::Terminated));
+}
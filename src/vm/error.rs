@@ -9,6 +9,8 @@ pub enum RuntimeError {
     General(String),
     Exception(Value),
     Unimplemented,
+    /// the script was stopped by the host (see VM::terminate_flag). try-catch cannot catch it.
+    Terminated,
 }
 
 impl RuntimeError {
@@ -21,6 +23,7 @@ impl RuntimeError {
             RuntimeError::Reference(ref s) => Value::string(s.clone()),
             RuntimeError::Unimplemented => Value::string("Unimplemented".to_string()),
             RuntimeError::Unknown => Value::string("Unknown".to_string()),
+            RuntimeError::Terminated => Value::string("Terminated".to_string()),
         }
     }
 
@@ -28,6 +31,7 @@ impl RuntimeError {
         match self {
             RuntimeError::Unknown => runtime_error("unknown error occurred"),
             RuntimeError::Unimplemented => runtime_error("unimplemented feature"),
+            RuntimeError::Terminated => runtime_error("script terminated"),
             RuntimeError::Reference(msg) | RuntimeError::Type(msg) | RuntimeError::General(msg) => {
                 runtime_error(msg.as_str())
             }
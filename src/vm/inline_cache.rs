@@ -0,0 +1,156 @@
+use super::shape::ShapeId;
+use super::value::*;
+use gc::{Gc, GcPtr};
+use rustc_hash::FxHashSet;
+
+/// Number of shapes an access site remembers. More shapes than this overwrite the oldest.
+const MAX_ENTRIES: usize = 4;
+
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    /// shape of the receiver's property map.
+    shape: ShapeId,
+    /// property maps of the prototypes visited by the lookup, and their shapes.
+    protos: Vec<(PropMapRef, ShapeId)>,
+    /// map which has the property: 0 is the receiver and n is protos[n - 1].
+    /// None if the property is not found.
+    holder: Option<usize>,
+}
+
+impl CacheEntry {
+    /// Look up 'key' following the prototype chain of 'map'.
+    /// Returns None if the lookup cannot be cached.
+    fn lookup(map: &PropMapRef, key: &str) -> Option<CacheEntry> {
+        // __proto__ is not a property but [[Prototype]] of the receiver.
+        if key == "__proto__" {
+            return None;
+        }
+        let mut protos: Vec<(PropMapRef, ShapeId)> = vec![];
+        let mut cur = map.clone();
+        let holder = loop {
+            if cur.contains_key(key) {
+                break Some(protos.len());
+            }
+            cur = match cur.proto() {
+                // obj_find_val() gives special treatment to builtin functions.
+                Value::Object(_, ObjectKind::BuiltinFunction(_)) => return None,
+                Value::Object(proto, _) => proto,
+                _ => break None,
+            };
+            protos.push((cur.clone(), cur.shape()));
+        };
+        Some(CacheEntry {
+            shape: map.shape(),
+            protos: protos,
+            holder: holder,
+        })
+    }
+
+    /// A receiver's shape determines its keys and its prototype, and in turn the shape of
+    /// the prototype determines the next one. So the entry is valid as long as all the
+    /// shapes are unchanged.
+    fn is_valid(&self, map: &PropMapRef) -> bool {
+        self.shape == map.shape()
+            && self
+                .protos
+                .iter()
+                .all(|(proto, shape)| proto.shape() == *shape)
+    }
+
+    fn holder(&self, map: &PropMapRef) -> Option<PropMapRef> {
+        self.holder.map(|n| match n {
+            0 => map.clone(),
+            n => self.protos[n - 1].0.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A polymorphic inline cache of a property access site (GET_MEMBER or SET_MEMBER).
+pub struct InlineCache {
+    key: String,
+    entries: Vec<CacheEntry>,
+    next: usize,
+}
+
+impl InlineCache {
+    pub fn new() -> InlineCache {
+        InlineCache {
+            key: "".to_string(),
+            entries: vec![],
+            next: 0,
+        }
+    }
+
+    /// Find the property map which has the property 'key', following the prototype chain
+    /// of 'map'. Returns Err(()) if the lookup cannot be cached.
+    pub fn lookup(&mut self, map: &PropMapRef, key: &str) -> Result<Option<PropMapRef>, ()> {
+        // A computed member like obj[key] can access various keys at the same site.
+        if self.key != key {
+            self.key = key.to_string();
+            self.entries.clear();
+            self.next = 0;
+        }
+
+        for entry in &self.entries {
+            if entry.is_valid(map) {
+                return Ok(entry.holder(map));
+            }
+        }
+
+        let entry = match CacheEntry::lookup(map, key) {
+            Some(entry) => entry,
+            None => return Err(()),
+        };
+        let holder = entry.holder(map);
+        if self.entries.len() < MAX_ENTRIES {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+            self.next = (self.next + 1) % MAX_ENTRIES;
+        }
+        Ok(holder)
+    }
+
+    /// trace the prototypes held by the cache.
+    pub fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        for entry in &mut self.entries {
+            for (proto, _) in &mut entry.protos {
+                proto.trace(marked);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A cache of a global variable read by GET_GLOBAL. The value is valid as long as the version
+/// of the global object is the one it was read at, which changes when any global variable is
+/// defined or assigned.
+pub struct GlobalCache {
+    entry: Option<(usize, Value)>,
+}
+
+impl GlobalCache {
+    pub fn new() -> GlobalCache {
+        GlobalCache { entry: None }
+    }
+
+    /// the value of the variable 'key' of 'global'. None if it is not defined.
+    pub fn lookup(&mut self, global: &PropMapRef, key: &str) -> Option<Value> {
+        if let Some((version, ref val)) = self.entry {
+            if version == global.version() {
+                return Some(val.clone());
+            }
+        }
+        let val = global.get(key).map(|prop| prop.val.clone());
+        self.entry = val.clone().map(|val| (global.version(), val));
+        val
+    }
+
+    /// trace the value held by the cache.
+    pub fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        if let Some((_, ref mut val)) = self.entry {
+            val.trace(marked);
+        }
+    }
+}
@@ -0,0 +1,828 @@
+use builtins::{console, eval};
+use rand::random;
+use serde_json::{self, Value as Json};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use tungstenite::{self, Message, WebSocket};
+use vm::debugger::{Frontend, Mode};
+use vm::{error::RuntimeError, value::*, vm::VM};
+
+// The inspector ('rapidus --inspect'): the frontend of the debugger (see debugger.rs) for
+// Chrome DevTools and VS Code, which speak the Chrome DevTools Protocol over a WebSocket.
+// https://chromedevtools.github.io/devtools-protocol/
+//
+// As in Node.js, the inspector answers GET /json/list and /json/version with the URL of the
+// WebSocket, so that the script is listed in chrome://inspect. The path of the WebSocket is a
+// random UUID made for each session, and requests whose Host header does not name this machine
+// are refused, so that web pages can neither guess the URL nor read it through DNS rebinding.
+// The script starts to run when
+// the client sends Runtime.runIfWaitingForDebugger. The supported methods are:
+//
+//   Debugger: enable, getScriptSource, setBreakpointByUrl, setBreakpoint, removeBreakpoint,
+//             pause, resume, stepInto, stepOver, stepOut, evaluateOnCallFrame
+//   Runtime:  enable, runIfWaitingForDebugger, evaluate, getProperties
+//   Console:  enable
+//
+// and console.log() etc. are sent as Runtime.consoleAPICalled. Other methods are answered with
+// an error. There is only one script, whose id is "1", so breakpoints are set on its lines
+// whatever their URLs are, and their columns are ignored.
+//
+// Objects sent to the client are kept in VM::inspected_values, and their ids are the indices
+// in it. They are released when the script resumes. The scope of the nth call frame is
+// "scope:<n>" and the global scope is "scope:global".
+
+/// the address the inspector listens on by default.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9229";
+
+/// interval between the checks for messages while the script runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// the longest description of an object sent to the client.
+const MAX_DESCRIPTION_LEN: usize = 100;
+
+pub struct Inspector {
+    /// None after the client has disconnected.
+    socket: Option<WebSocket<TcpStream>>,
+    /// the id of the target, which is the path of the WebSocket.
+    target_id: String,
+    /// URL of the script.
+    url: String,
+    source: String,
+    /// true once the client has let the script run.
+    running: bool,
+    /// (id, line) of the breakpoints set by the client.
+    breakpoints: Vec<(String, usize)>,
+    /// true if the client has asked to pause the running script.
+    pause_requested: bool,
+    last_poll: Instant,
+}
+
+/// Listen on 'addr' (host:port), and wait until a client connects.
+pub fn listen(addr: &str, file_name: &str, source: &str) -> io::Result<Inspector> {
+    accept(TcpListener::bind(addr)?, file_name, source)
+}
+
+/// Wait until a client connects to 'listener', answering the HTTP requests for the list of
+/// scripts meanwhile.
+pub fn accept(listener: TcpListener, file_name: &str, source: &str) -> io::Result<Inspector> {
+    let url = match fs::canonicalize(file_name) {
+        Ok(path) => format!("file://{}", path.to_string_lossy()),
+        Err(_) => file_name.to_string(),
+    };
+    let addr = listener.local_addr()?;
+    let target_id = new_target_id();
+    let ws_url = format!("{}/{}", addr, target_id);
+    eprintln!("Debugger listening on ws://{}", ws_url);
+    eprintln!("Open chrome://inspect in Chrome, or attach VS Code, to debug the script");
+
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut buf = [0; 1024];
+        let len = stream.peek(&mut buf)?;
+        let request = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+        if !is_local_host(&request, &addr) {
+            stream.read(&mut buf).ok();
+            stream
+                .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+                .ok();
+            continue;
+        }
+        let path = request.split(' ').nth(1).unwrap_or("");
+        if request.contains("upgrade: websocket") && path == format!("/{}", target_id) {
+            match tungstenite::accept(stream) {
+                Ok(socket) => {
+                    eprintln!("Debugger attached");
+                    return Ok(Inspector {
+                        socket: Some(socket),
+                        target_id: target_id,
+                        url: url,
+                        source: source.to_string(),
+                        running: false,
+                        breakpoints: vec![],
+                        pause_requested: false,
+                        last_poll: Instant::now(),
+                    });
+                }
+                Err(_) => continue,
+            }
+        }
+        stream.read(&mut buf).ok();
+        let body = match path.trim_right_matches('/') {
+            "/json" | "/json/list" => json!([{
+                "description": "rapidus instance",
+                "devtoolsFrontendUrl": format!(
+                    "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}",
+                    ws_url
+                ),
+                "id": target_id,
+                "title": file_name,
+                "type": "node",
+                "url": url,
+                "webSocketDebuggerUrl": format!("ws://{}", ws_url),
+            }]),
+            "/json/version" => json!({
+                "Browser": format!("rapidus/{}", env!("CARGO_PKG_VERSION")),
+                "Protocol-Version": "1.3",
+            }),
+            _ => {
+                stream
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                    .ok();
+                continue;
+            }
+        }
+        .to_string();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=UTF-8\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .ok();
+    }
+    Err(io::Error::new(ErrorKind::Other, "no client connected"))
+}
+
+/// a random UUID (version 4) to be the id of the target.
+fn new_target_id() -> String {
+    let high = random::<u64>() & !0xf000 | 0x4000;
+    let low = random::<u64>() & !(0b11 << 62) | (0b10 << 62);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Whether the Host header of 'request' (lowercased) is localhost, 127.0.0.1 or 'addr', the
+/// address listened on.
+fn is_local_host(request: &str, addr: &SocketAddr) -> bool {
+    let host = match request.lines().find(|line| line.starts_with("host:")) {
+        Some(line) => line["host:".len()..].trim(),
+        None => return false,
+    };
+    // The port is left out. IPv6 addresses are in brackets.
+    let name = if host.starts_with('[') {
+        host[1..].split(']').next().unwrap_or("")
+    } else {
+        host.split(':').next().unwrap_or("")
+    };
+    name == "localhost" || name == "127.0.0.1" || name == addr.ip().to_string()
+}
+
+impl Frontend for Inspector {
+    fn stop(
+        &mut self,
+        vm: &mut VM,
+        breakpoints: &mut BTreeSet<usize>,
+        _pc: usize,
+        line: Option<usize>,
+    ) -> Result<Mode, RuntimeError> {
+        // The first stop is before the script runs, where the client sets breakpoints.
+        if !self.running {
+            while !self.running {
+                match self.receive(true) {
+                    Some(msg) => {
+                        self.handle(vm, breakpoints, &msg);
+                    }
+                    None => return Ok(self.detach(breakpoints)),
+                }
+            }
+            let at_breakpoint = line.map_or(false, |line| breakpoints.contains(&line));
+            if !at_breakpoint && !self.pause_requested {
+                return Ok(Mode::Continue);
+            }
+        }
+
+        self.pause_requested = false;
+        let hit_breakpoints: Vec<String> = self
+            .breakpoints
+            .iter()
+            .filter(|&&(_, bp_line)| Some(bp_line) == line)
+            .map(|&(ref id, _)| id.clone())
+            .collect();
+        let call_frames = self.call_frames(vm);
+        self.send(json!({
+            "method": "Debugger.paused",
+            "params": {
+                "callFrames": call_frames,
+                "reason": "other",
+                "hitBreakpoints": hit_breakpoints,
+            }
+        }));
+
+        loop {
+            let msg = match self.receive(true) {
+                Some(msg) => msg,
+                None => return Ok(self.detach(breakpoints)),
+            };
+            if let Some(mode) = self.handle(vm, breakpoints, &msg) {
+                vm.inspected_values.clear();
+                self.send(json!({"method": "Debugger.resumed", "params": {}}));
+                return Ok(mode);
+            }
+        }
+    }
+
+    fn poll(&mut self, vm: &mut VM, breakpoints: &mut BTreeSet<usize>) -> bool {
+        if self.socket.is_none() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        while let Some(msg) = self.receive(false) {
+            self.handle(vm, breakpoints, &msg);
+        }
+        if self.socket.is_none() {
+            self.detach(breakpoints);
+        }
+        self.last_poll = Instant::now();
+        self.pause_requested
+    }
+
+    fn console(&mut self, vm: &mut VM, kind: &str, args: &[Value]) {
+        if self.socket.is_none() {
+            return;
+        }
+        let args: Vec<Json> = args.iter().map(|arg| remote_object(vm, arg)).collect();
+        let timestamp = ::chrono::Utc::now().timestamp_millis() as f64;
+        self.send(json!({
+            "method": "Runtime.consoleAPICalled",
+            "params": {
+                "type": kind,
+                "args": args,
+                "executionContextId": 1,
+                "timestamp": timestamp,
+            }
+        }));
+    }
+}
+
+impl Inspector {
+    /// Handle a message from the client. Returns how the script goes on if the message resumes
+    /// it.
+    fn handle(
+        &mut self,
+        vm: &mut VM,
+        breakpoints: &mut BTreeSet<usize>,
+        msg: &Json,
+    ) -> Option<Mode> {
+        let params = &msg["params"];
+        let depth = vm.state.frames.len();
+        let mut resume = None;
+        let result = match msg["method"].as_str().unwrap_or("") {
+            "Runtime.enable" => {
+                self.send(json!({
+                    "method": "Runtime.executionContextCreated",
+                    "params": {
+                        "context": {"id": 1, "origin": "", "name": "rapidus"}
+                    }
+                }));
+                Ok(json!({}))
+            }
+            "Runtime.runIfWaitingForDebugger" => {
+                self.running = true;
+                Ok(json!({}))
+            }
+            "Debugger.enable" => {
+                let end_line = self.source.lines().count();
+                let url = self.url.clone();
+                self.send(json!({
+                    "method": "Debugger.scriptParsed",
+                    "params": {
+                        "scriptId": "1",
+                        "url": url,
+                        "startLine": 0,
+                        "startColumn": 0,
+                        "endLine": end_line,
+                        "endColumn": 0,
+                        "executionContextId": 1,
+                        "hash": "",
+                    }
+                }));
+                Ok(json!({"debuggerId": self.target_id}))
+            }
+            "Console.enable" => Ok(json!({})),
+            "Debugger.getScriptSource" => Ok(json!({"scriptSource": self.source})),
+            "Debugger.setBreakpointByUrl" => match params["lineNumber"].as_u64() {
+                Some(line) => {
+                    let location = self.set_breakpoint(breakpoints, line as usize + 1);
+                    Ok(json!({
+                        "breakpointId": location["breakpointId"],
+                        "locations": [location["location"]],
+                    }))
+                }
+                None => Err("lineNumber is missing".to_string()),
+            },
+            "Debugger.setBreakpoint" => match params["location"]["lineNumber"].as_u64() {
+                Some(line) => {
+                    let location = self.set_breakpoint(breakpoints, line as usize + 1);
+                    Ok(json!({
+                        "breakpointId": location["breakpointId"],
+                        "actualLocation": location["location"],
+                    }))
+                }
+                None => Err("location is missing".to_string()),
+            },
+            "Debugger.removeBreakpoint" => {
+                let id = params["breakpointId"].as_str().unwrap_or("");
+                if let Some(i) = self.breakpoints.iter().position(|bp| bp.0 == id) {
+                    let (_, line) = self.breakpoints.remove(i);
+                    if self.breakpoints.iter().all(|bp| bp.1 != line) {
+                        breakpoints.remove(&line);
+                    }
+                }
+                Ok(json!({}))
+            }
+            "Debugger.pause" => {
+                self.pause_requested = true;
+                Ok(json!({}))
+            }
+            "Debugger.resume" => {
+                resume = Some(Mode::Continue);
+                Ok(json!({}))
+            }
+            "Debugger.stepInto" => {
+                resume = Some(Mode::Step);
+                Ok(json!({}))
+            }
+            "Debugger.stepOver" => {
+                resume = Some(Mode::Next(depth));
+                Ok(json!({}))
+            }
+            "Debugger.stepOut" => {
+                resume = Some(Mode::Out(depth));
+                Ok(json!({}))
+            }
+            "Debugger.evaluateOnCallFrame" => {
+                let frame = params["callFrameId"]
+                    .as_str()
+                    .and_then(|id| id.parse().ok())
+                    .unwrap_or(0);
+                match frame_scope(vm, frame) {
+                    Some(scope) => Ok(evaluate(vm, scope, params["expression"].as_str())),
+                    None => Err(format!("no call frame {}", frame)),
+                }
+            }
+            "Runtime.evaluate" => {
+                let global = vm.state.scope[0].clone();
+                Ok(evaluate(vm, global, params["expression"].as_str()))
+            }
+            "Runtime.getProperties" => {
+                let id = params["objectId"].as_str().unwrap_or("");
+                match properties(vm, id) {
+                    Some(props) => Ok(json!({ "result": props })),
+                    None => Err(format!("no object {}", id)),
+                }
+            }
+            method => Err(format!("'{}' wasn't found", method)),
+        };
+
+        // Nothing resumes the script before it runs.
+        if !self.running {
+            resume = None;
+        }
+        self.send(match result {
+            Ok(result) => json!({"id": msg["id"], "result": result}),
+            Err(message) => json!({"id": msg["id"], "error": {"code": -32601, "message": message}}),
+        });
+        resume
+    }
+
+    /// Set a breakpoint at 'line'. Returns the breakpoint id and the location as they are
+    /// answered.
+    fn set_breakpoint(&mut self, breakpoints: &mut BTreeSet<usize>, line: usize) -> Json {
+        let id = format!("1:{}:0", line - 1);
+        if self.breakpoints.iter().all(|bp| bp.0 != id) {
+            self.breakpoints.push((id.clone(), line));
+        }
+        breakpoints.insert(line);
+        json!({
+            "breakpointId": id,
+            "location": {"scriptId": "1", "lineNumber": line - 1, "columnNumber": 0},
+        })
+    }
+
+    /// the frames of the paused script from the current one, as Debugger.CallFrame. Frames
+    /// which are not in the source map, such as the code of eval(), are left out.
+    fn call_frames(&mut self, vm: &mut VM) -> Vec<Json> {
+        let mut call_frames = vec![];
+        let (mut iseq, mut pc) = (vm.state.iseq.clone(), vm.state.pc);
+        let len = vm.state.frames.len();
+        for n in 0..len {
+            let location = vm.source_location(&iseq, pc as usize);
+            if let (Some(location), Some(scope)) = (location, frame_scope(vm, n)) {
+                let global = json!({
+                    "type": "global",
+                    "object": {
+                        "type": "object",
+                        "className": "Object",
+                        "description": "Global",
+                        "objectId": "scope:global",
+                    }
+                });
+                // The scope of the top-level code is the global scope.
+                let scope_chain = if location.name.is_empty() {
+                    vec![global]
+                } else {
+                    let local = json!({
+                        "type": "local",
+                        "object": {
+                            "type": "object",
+                            "className": "Object",
+                            "description": "Local",
+                            "objectId": format!("scope:{}", n),
+                        }
+                    });
+                    vec![local, global]
+                };
+                let this = remote_object(vm, &scope.this);
+                call_frames.push(json!({
+                    "callFrameId": n.to_string(),
+                    "functionName": location.name,
+                    "location": {
+                        "scriptId": "1",
+                        "lineNumber": location.line - 1,
+                        "columnNumber": location.column - 1,
+                    },
+                    "url": self.url,
+                    "scopeChain": scope_chain,
+                    "this": this,
+                }));
+            }
+            // The caller is in the middle of the instruction which made the call.
+            let frame = &vm.state.frames[len - 1 - n];
+            iseq = frame.return_iseq.clone();
+            pc = frame.return_pc - 1;
+        }
+        call_frames
+    }
+
+    /// Read a message from the client. If 'block' is false, returns None when no message has
+    /// arrived. Also returns None when the client has disconnected.
+    fn receive(&mut self, block: bool) -> Option<Json> {
+        loop {
+            let res = {
+                let socket = self.socket.as_mut()?;
+                if !block {
+                    socket.get_mut().set_nonblocking(true).ok();
+                }
+                let res = socket.read_message();
+                if !block {
+                    socket.get_mut().set_nonblocking(false).ok();
+                }
+                res
+            };
+            match res {
+                Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                    Ok(msg) => return Some(msg),
+                    Err(_) => continue,
+                },
+                Ok(Message::Close(_)) => {}
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(ref err)) if err.kind() == ErrorKind::WouldBlock => {
+                    return None
+                }
+                Err(_) => {}
+            }
+            eprintln!("Debugger detached");
+            self.socket = None;
+            return None;
+        }
+    }
+
+    fn send(&mut self, msg: Json) {
+        let res = match self.socket {
+            Some(ref mut socket) => socket.write_message(Message::Text(msg.to_string())),
+            None => return,
+        };
+        if res.is_err() {
+            eprintln!("Debugger detached");
+            self.socket = None;
+        }
+    }
+
+    /// Let the script run without the client.
+    fn detach(&mut self, breakpoints: &mut BTreeSet<usize>) -> Mode {
+        self.running = true;
+        self.breakpoints.clear();
+        breakpoints.clear();
+        Mode::Continue
+    }
+}
+
+/// the scope of the nth frame from the current one.
+fn frame_scope(vm: &VM, n: usize) -> Option<CallObjectRef> {
+    if n == 0 {
+        return vm.state.scope.last().cloned();
+    }
+    // The scope of a caller is the last one before its callee entered.
+    let callee = vm.state.frames.get(vm.state.frames.len().checked_sub(n)?)?;
+    vm.state
+        .scope
+        .get(callee.scope_len.checked_sub(1)?)
+        .cloned()
+}
+
+/// Evaluate 'expression' in 'scope' as the console of DevTools does. Returns
+/// Runtime.evaluate's result.
+fn evaluate(vm: &mut VM, scope: CallObjectRef, expression: Option<&str>) -> Json {
+    vm.state.scope.push(scope);
+    let res = eval::parse(expression.unwrap_or("").to_string())
+        .and_then(|node| eval::run_script(vm, &node));
+    vm.state.scope.pop();
+    match res {
+        Ok(val) => json!({ "result": remote_object(vm, &val) }),
+        Err(err) => {
+            let exception = remote_object(vm, &err.to_value());
+            json!({
+                "result": exception,
+                "exceptionDetails": {
+                    "exceptionId": 1,
+                    "text": "Uncaught",
+                    "lineNumber": 0,
+                    "columnNumber": 0,
+                    "exception": exception,
+                }
+            })
+        }
+    }
+}
+
+/// 'val' as Runtime.RemoteObject. An object is kept in vm.inspected_values so that the client
+/// can ask for its properties.
+fn remote_object(vm: &mut VM, val: &Value) -> Json {
+    let description = || val.to_string();
+    match val {
+        Value::Empty | Value::Undefined => json!({"type": "undefined"}),
+        Value::Null => json!({"type": "object", "subtype": "null", "value": null}),
+        Value::Bool(b) => json!({"type": "boolean", "value": b}),
+        Value::Number(n) if n.is_finite() && !(*n == 0.0 && n.is_sign_negative()) => {
+            json!({"type": "number", "value": n, "description": description()})
+        }
+        Value::Number(n) => {
+            let unserializable = if *n == 0.0 {
+                "-0".to_string()
+            } else {
+                description()
+            };
+            json!({
+                "type": "number",
+                "unserializableValue": unserializable,
+                "description": unserializable,
+            })
+        }
+        Value::BigInt(_) => {
+            let bigint = format!("{}n", description());
+            json!({"type": "bigint", "unserializableValue": bigint, "description": bigint})
+        }
+        Value::String(_) => json!({"type": "string", "value": description()}),
+        Value::Object(_, kind) => {
+            let (type_, subtype, class_name) = match kind {
+                ObjectKind::Function(_) | ObjectKind::BuiltinFunction(_) => {
+                    ("function", None, "Function")
+                }
+                ObjectKind::Array(_) => ("object", Some("array"), "Array"),
+                ObjectKind::Date(_) => ("object", Some("date"), "Date"),
+                ObjectKind::TypedArray(_) => ("object", Some("typedarray"), "TypedArray"),
+                ObjectKind::ArrayBuffer(_) => ("object", Some("arraybuffer"), "ArrayBuffer"),
+                ObjectKind::DataView(_) => ("object", Some("dataview"), "DataView"),
+                ObjectKind::WeakTable(table) => match table.kind {
+                    WeakKind::WeakMap => ("object", Some("weakmap"), "WeakMap"),
+                    WeakKind::WeakSet => ("object", Some("weakset"), "WeakSet"),
+                    WeakKind::WeakRef => ("object", Some("weakref"), "WeakRef"),
+                    WeakKind::FinalizationRegistry => ("object", None, "FinalizationRegistry"),
+                },
+                _ => ("object", None, "Object"),
+            };
+            let mut description = console::inspect(val, true);
+            if description.chars().count() > MAX_DESCRIPTION_LEN {
+                description = description.chars().take(MAX_DESCRIPTION_LEN).collect();
+                description.push_str("...");
+            }
+            vm.inspected_values.push(val.clone());
+            let mut object = json!({
+                "type": type_,
+                "className": class_name,
+                "description": description,
+                "objectId": (vm.inspected_values.len() - 1).to_string(),
+            });
+            if let Some(subtype) = subtype {
+                object["subtype"] = json!(subtype);
+            }
+            object
+        }
+    }
+}
+
+/// the properties of the object or the scope 'id' as Runtime.getProperties' result.
+fn properties(vm: &mut VM, id: &str) -> Option<Vec<Json>> {
+    let mut props: Vec<(String, Property)> = vec![];
+    let map = if id.starts_with("scope:") {
+        let scope = match &id["scope:".len()..] {
+            "global" => vm.state.scope[0].clone(),
+            n => frame_scope(vm, n.parse().ok()?)?,
+        };
+        scope.vals.clone()
+    } else {
+        match vm.inspected_values.get(id.parse::<usize>().ok()?)? {
+            Value::Object(map, kind) => {
+                if let ObjectKind::Array(ary) = kind {
+                    let elems = ary.elems.iter().enumerate();
+                    for (i, prop) in elems.chain(ary.sparse.iter().map(|(&i, prop)| (i, prop))) {
+                        if prop.val != Value::Empty {
+                            props.push((i.to_string(), prop.clone()));
+                        }
+                    }
+                    props.push((
+                        "length".to_string(),
+                        Value::Number(ary.length as f64).to_property(),
+                    ));
+                }
+                map.clone()
+            }
+            _ => return None,
+        }
+    };
+    let mut named: Vec<(String, Property)> = map
+        .iter()
+        .map(|(name, prop)| (name.clone(), prop.clone()))
+        .collect();
+    named.sort_by(|a, b| a.0.cmp(&b.0));
+    props.extend(named);
+
+    Some(
+        props
+            .into_iter()
+            .map(|(name, prop)| {
+                json!({
+                    "name": name,
+                    "value": remote_object(vm, &prop.val),
+                    "writable": prop.writable,
+                    "configurable": prop.configurable,
+                    "enumerable": prop.enumerable,
+                    "isOwn": true,
+                })
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+    use sourcemap::SourceMap;
+    use std::mem;
+    use std::thread;
+    use vm::debugger::Debugger;
+
+    let code =
+        "var x = 1\nfunction f(a) {\n  console.log('a is', a)\n  return a + x\n}\nx = f(2)\n";
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    fn read(socket: &mut WebSocket<TcpStream>) -> Json {
+        loop {
+            if let Message::Text(text) = socket.read_message().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+    fn request(
+        socket: &mut WebSocket<TcpStream>,
+        events: &mut Vec<Json>,
+        id: u64,
+        method: &str,
+        params: Json,
+    ) -> Json {
+        let msg = json!({"id": id, "method": method, "params": params});
+        socket
+            .write_message(Message::Text(msg.to_string()))
+            .unwrap();
+        loop {
+            let msg = read(socket);
+            if msg["id"] == json!(id) {
+                return msg;
+            }
+            events.push(msg);
+        }
+    }
+
+    fn get(addr: SocketAddr, host: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    let client = thread::spawn(move || {
+        // Other hosts are refused, and the WebSocket is only at the URL in the list.
+        let response = get(addr, "example.com", "/json/list");
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+        let response = get(addr, &addr.to_string(), "/json/list");
+        let body = response.splitn(2, "\r\n\r\n").nth(1).unwrap();
+        let list: Json = serde_json::from_str(body).unwrap();
+        let url = list[0]["webSocketDebuggerUrl"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let guessed = format!("ws://{}/rapidus", addr);
+        assert_ne!(url, guessed);
+        let stream = TcpStream::connect(addr).unwrap();
+        assert!(tungstenite::client(guessed.as_str(), stream).is_err());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream).unwrap();
+        let socket = &mut socket;
+        let mut events = vec![];
+
+        request(socket, &mut events, 1, "Runtime.enable", json!({}));
+        request(socket, &mut events, 2, "Debugger.enable", json!({}));
+        let params = json!({"lineNumber": 3});
+        let bp = request(
+            socket,
+            &mut events,
+            3,
+            "Debugger.setBreakpointByUrl",
+            params,
+        );
+        assert_eq!(bp["result"]["breakpointId"], json!("1:3:0"));
+        request(
+            socket,
+            &mut events,
+            4,
+            "Runtime.runIfWaitingForDebugger",
+            json!({}),
+        );
+        while events
+            .iter()
+            .all(|event| event["method"] != json!("Debugger.paused"))
+        {
+            let msg = read(socket);
+            events.push(msg);
+        }
+
+        let params = json!({"callFrameId": "0", "expression": "a * 10"});
+        let res = request(
+            socket,
+            &mut events,
+            5,
+            "Debugger.evaluateOnCallFrame",
+            params,
+        );
+        assert_eq!(res["result"]["result"]["value"], json!(20));
+        let params = json!({"objectId": "scope:0"});
+        let res = request(socket, &mut events, 6, "Runtime.getProperties", params);
+        assert!(res["result"]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|prop| prop["name"] == json!("a") && prop["value"]["value"] == json!(2)));
+        assert!(request(socket, &mut events, 7, "Foo.bar", json!({}))["error"].is_object());
+        request(socket, &mut events, 8, "Debugger.resume", json!({}));
+        events
+    });
+
+    let mut parser = Parser::new(code.to_string());
+    let node = parser.parse_all().unwrap();
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    vm.codegen.resolve_locals = false;
+    vm.codegen.record_positions = true;
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    vm.codegen.record_positions = false;
+    let positions = mem::replace(&mut vm.codegen.function_positions, vec![]);
+    vm.source_map = Some(SourceMap::new("a.js", &positions, &parser.lexer));
+    let inspector = accept(listener, "a.js", code).unwrap();
+    vm.debugger = Some(Debugger::new(Box::new(inspector), true));
+    vm.run(iseq).unwrap();
+    assert_eq!(
+        vm.state.scope[0].get_value("x").unwrap(),
+        Value::Number(3.0)
+    );
+
+    let events = client.join().unwrap();
+    let event = |method: &str| {
+        events
+            .iter()
+            .find(|event| event["method"] == json!(method))
+            .unwrap()["params"]
+            .clone()
+    };
+    assert_eq!(event("Debugger.scriptParsed")["url"], json!("a.js"));
+    let console = event("Runtime.consoleAPICalled");
+    assert_eq!(console["type"], json!("log"));
+    assert_eq!(console["args"][1]["value"], json!(2));
+    let paused = event("Debugger.paused");
+    assert_eq!(paused["hitBreakpoints"], json!(["1:3:0"]));
+    assert_eq!(paused["callFrames"][0]["functionName"], json!("f"));
+    assert_eq!(paused["callFrames"][0]["location"]["lineNumber"], json!(3));
+    assert_eq!(paused["callFrames"][1]["location"]["lineNumber"], json!(5));
+}
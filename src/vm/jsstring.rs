@@ -0,0 +1,309 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// A concatenation whose result is at most this long copies the code units at once instead of
+/// making a rope.
+const MAX_FLAT_CONCAT: usize = 64;
+
+// Bytes taken by the strings alive on this thread. See string_bytes().
+thread_local!(static STRING_BYTES: Cell<usize> = Cell::new(0));
+
+/// bytes taken by the strings alive on this thread: the code units, and the nodes of them and
+/// of ropes. A string shares them with its clones, so they are counted once.
+pub fn string_bytes() -> usize {
+    STRING_BYTES.with(|bytes| bytes.get())
+}
+
+/// A string of JavaScript. It is a sequence of UTF-16 code units, so it may contain lone
+/// surrogates and NULs, and its length and indices are counted in code units.
+///
+/// A string is immutable and shared when cloned. A long concatenation is a rope, a node which
+/// refers to both operands, and is flattened when its code units are needed, so that building
+/// a string by repeated '+' does not copy it every time.
+#[derive(Clone)]
+pub struct JsString {
+    len: usize,
+    repr: Rc<Node>,
+}
+
+/// A node of a string, which is counted in STRING_BYTES while it is alive.
+struct Node(RefCell<Repr>);
+
+enum Repr {
+    Flat(Vec<u16>),
+    Concat(JsString, JsString),
+}
+
+impl Node {
+    fn new(repr: Repr) -> Rc<Node> {
+        let node = Node(RefCell::new(repr));
+        let size = node.size();
+        STRING_BYTES.with(|bytes| bytes.set(bytes.get() + size));
+        Rc::new(node)
+    }
+
+    /// bytes taken by the node and its code units. The operands of a rope are nodes of their
+    /// own.
+    fn size(&self) -> usize {
+        let units = match *self.0.borrow() {
+            Repr::Flat(ref units) => units.capacity() * mem::size_of::<u16>(),
+            Repr::Concat(_, _) => 0,
+        };
+        // the reference counts of Rc are allocated with the node.
+        mem::size_of::<Node>() + 2 * mem::size_of::<usize>() + units
+    }
+}
+
+impl Deref for Node {
+    type Target = RefCell<Repr>;
+
+    fn deref(&self) -> &RefCell<Repr> {
+        &self.0
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let size = self.size();
+        // The counter may be gone when the thread exits.
+        let _ = STRING_BYTES.try_with(|bytes| bytes.set(bytes.get() - size));
+    }
+}
+
+impl JsString {
+    pub fn new() -> JsString {
+        JsString::from_units(vec![])
+    }
+
+    pub fn from_units(units: Vec<u16>) -> JsString {
+        JsString {
+            len: units.len(),
+            repr: Node::new(Repr::Flat(units)),
+        }
+    }
+
+    /// the code units. A rope is flattened here.
+    pub fn units(&self) -> Ref<[u16]> {
+        self.flatten();
+        Ref::map(self.repr.borrow(), |repr| match repr {
+            Repr::Flat(units) => units.as_slice(),
+            Repr::Concat(_, _) => unreachable!(),
+        })
+    }
+
+    /// the number of code units.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// the code unit at 'n'.
+    pub fn code_unit_at(&self, n: usize) -> Option<u16> {
+        self.units().get(n).cloned()
+    }
+
+    /// the code point which starts at 'n'. A lone surrogate is returned as it is.
+    /// https://tc39.github.io/ecma262/#sec-codepointat
+    pub fn code_point_at(&self, n: usize) -> Option<u32> {
+        let first = self.code_unit_at(n)?;
+        match (first, self.code_unit_at(n + 1)) {
+            (0xd800...0xdbff, Some(second @ 0xdc00...0xdfff)) => {
+                Some(0x10000 + ((first as u32 - 0xd800) << 10) + (second as u32 - 0xdc00))
+            }
+            _ => Some(first as u32),
+        }
+    }
+
+    /// the code units from 'start' to 'end' (exclusive). Both are clamped to the length.
+    pub fn substring(&self, start: usize, end: usize) -> JsString {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        JsString::from_units(self.units()[start..end].to_vec())
+    }
+
+    pub fn concat(&self, other: &JsString) -> JsString {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+        let len = self.len + other.len;
+        if len <= MAX_FLAT_CONCAT {
+            let mut units = Vec::with_capacity(len);
+            units.extend_from_slice(&self.units());
+            units.extend_from_slice(&other.units());
+            return JsString::from_units(units);
+        }
+        JsString {
+            len: len,
+            repr: Node::new(Repr::Concat(self.clone(), other.clone())),
+        }
+    }
+
+    /// the index of the first occurrence of 'pattern' at or after 'from'.
+    pub fn find(&self, pattern: &JsString, from: usize) -> Option<usize> {
+        if pattern.len() > self.len() {
+            return None;
+        }
+        let (units, pattern) = (self.units(), pattern.units());
+        (from..units.len() - pattern.len() + 1).find(|&i| units[i..].starts_with(&pattern))
+    }
+
+    /// Replace a rope with its code units. A rope can be as deep as the number of
+    /// concatenations, so it is walked without recursion.
+    fn flatten(&self) {
+        if let Repr::Flat(_) = *self.repr.borrow() {
+            return;
+        }
+        let mut units = Vec::with_capacity(self.len);
+        let mut pending = vec![self.clone()];
+        while let Some(s) = pending.pop() {
+            match *s.repr.borrow() {
+                Repr::Flat(ref flat) => units.extend_from_slice(flat),
+                Repr::Concat(ref left, ref right) => {
+                    pending.push(right.clone());
+                    pending.push(left.clone());
+                }
+            }
+        }
+        // The node takes the code units, which it has not been counted with as a rope.
+        let size = units.capacity() * mem::size_of::<u16>();
+        STRING_BYTES.with(|bytes| bytes.set(bytes.get() + size));
+        *self.repr.borrow_mut() = Repr::Flat(units);
+    }
+}
+
+/// A rope is dropped without recursion for the same reason as flatten().
+impl Drop for JsString {
+    fn drop(&mut self) {
+        fn take_operands(s: &JsString, pending: &mut Vec<JsString>) {
+            if Rc::strong_count(&s.repr) != 1 {
+                return;
+            }
+            if let Ok(mut repr) = s.repr.try_borrow_mut() {
+                if let Repr::Concat(_, _) = *repr {
+                    if let Repr::Concat(left, right) = mem::replace(&mut *repr, Repr::Flat(vec![]))
+                    {
+                        pending.push(left);
+                        pending.push(right);
+                    }
+                }
+            }
+        }
+
+        let mut pending = vec![];
+        take_operands(self, &mut pending);
+        while let Some(s) = pending.pop() {
+            take_operands(&s, &mut pending);
+        }
+    }
+}
+
+impl Default for JsString {
+    fn default() -> JsString {
+        JsString::new()
+    }
+}
+
+impl PartialEq for JsString {
+    fn eq(&self, other: &JsString) -> bool {
+        Rc::ptr_eq(&self.repr, &other.repr)
+            || self.len == other.len && *self.units() == *other.units()
+    }
+}
+
+impl Eq for JsString {}
+
+impl PartialOrd for JsString {
+    fn partial_cmp(&self, other: &JsString) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compared by code units, as relational operators do.
+impl Ord for JsString {
+    fn cmp(&self, other: &JsString) -> Ordering {
+        self.units().cmp(&other.units())
+    }
+}
+
+impl<'a> From<&'a str> for JsString {
+    fn from(s: &'a str) -> JsString {
+        JsString::from_units(s.encode_utf16().collect())
+    }
+}
+
+impl From<String> for JsString {
+    fn from(s: String) -> JsString {
+        JsString::from(s.as_str())
+    }
+}
+
+impl<'a> PartialEq<&'a str> for JsString {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.units().iter().cloned().eq(other.encode_utf16())
+    }
+}
+
+/// Lone surrogates are shown as U+FFFD.
+impl fmt::Display for JsString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf16_lossy(&self.units()))
+    }
+}
+
+impl fmt::Debug for JsString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf16_lossy(&self.units()))
+    }
+}
+
+#[test]
+fn test() {
+    let s = JsString::from("a\u{1f600}\0b");
+    assert_eq!(s.len(), 5);
+    assert_eq!(s.code_unit_at(1), Some(0xd83d));
+    assert_eq!(s.code_point_at(1), Some(0x1f600));
+    assert_eq!(s.code_point_at(2), Some(0xde00));
+    assert_eq!(s.code_unit_at(3), Some(0));
+    assert_eq!(s.code_unit_at(5), None);
+    assert_eq!(s.to_string(), "a\u{1f600}\0b");
+    assert_eq!(s.substring(1, 2).to_string(), "\u{fffd}");
+    assert_eq!(s.substring(3, 10), "\0b");
+    assert_eq!(s.find(&JsString::from("b"), 0), Some(4));
+    assert_eq!(s.find(&JsString::from("a"), 1), None);
+    assert_eq!(JsString::from("a").concat(&JsString::from("b")), "ab");
+    // Compared by code units, not by code points.
+    assert!(JsString::from("\u{1f600}") < JsString::from("\u{ff61}"));
+
+    // A deep rope is flattened and dropped without overflowing the stack.
+    let long = JsString::from("x".repeat(MAX_FLAT_CONCAT).as_str());
+    let mut rope = JsString::new();
+    for _ in 0..100000 {
+        rope = rope.concat(&long).concat(&JsString::from("y"));
+    }
+    assert_eq!(rope.len(), 100000 * (MAX_FLAT_CONCAT + 1));
+    assert_eq!(rope.code_unit_at(MAX_FLAT_CONCAT), Some('y' as u16));
+    assert_eq!(
+        rope.substring(MAX_FLAT_CONCAT - 1, MAX_FLAT_CONCAT + 2),
+        "xyx"
+    );
+    let rope = rope.concat(&long);
+    drop(rope);
+
+    // The bytes of strings are counted while they are alive.
+    let before = string_bytes();
+    let s = JsString::from("x".repeat(1000).as_str()).concat(&long);
+    assert!(string_bytes() >= before + 2 * (1000 + MAX_FLAT_CONCAT));
+    assert_eq!(s.code_unit_at(1000), Some('x' as u16));
+    drop(s);
+    assert_eq!(string_bytes(), before);
+}
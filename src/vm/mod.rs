@@ -1,5 +1,14 @@
 pub mod callobj;
+pub mod debugger;
 pub mod error;
+pub mod inline_cache;
+pub mod inspector;
+pub mod jsstring;
+pub mod profiler;
+pub mod properties;
+pub mod shape;
 pub mod task;
+pub mod tracer;
+pub mod upvalue;
 pub mod value;
 pub mod vm;
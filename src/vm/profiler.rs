@@ -0,0 +1,201 @@
+use bytecode_gen::ByteCode;
+use rustc_hash::FxHashMap;
+use std::rc::Rc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::thread;
+use std::time::Duration;
+use vm::vm::VM;
+
+// A sampling CPU profiler ('rapidus --prof' and console.profile()). A timer thread raises a
+// flag every SAMPLING_INTERVAL, and the run loop of the VM takes a sample when it sees the
+// flag: the functions of the frames from the outermost one. Samples are counted by their
+// stacks and written in the collapsed format of flamegraph tools, one stack per line:
+//
+//   (top-level) (a.js:1);f (a.js:2);g (a.js:10) 42
+//
+// where a function is shown with its name and the location from the source map. Only the
+// interpreter is sampled, so JIT-compiled code and the time spent waiting for timers are not
+// counted.
+
+/// interval between samples.
+pub const SAMPLING_INTERVAL: Duration = Duration::from_millis(1);
+
+/// the number of samples of each stack.
+pub type Samples = FxHashMap<String, u64>;
+
+pub struct Profiler {
+    /// samples of the whole run ('--prof'). None if only console.profile() is sampling.
+    run: Option<Samples>,
+    /// profiles started by console.profile() and not ended yet, by their labels.
+    sessions: Vec<(String, Samples)>,
+    /// set by the timer thread when a sample is due.
+    requested: Arc<AtomicBool>,
+    /// set to stop the timer thread.
+    stopped: Arc<AtomicBool>,
+    /// the names of the functions by the address of their code, which is kept alive so that
+    /// the address is not reused.
+    names: FxHashMap<usize, (Rc<ByteCode>, String)>,
+}
+
+impl Profiler {
+    /// A profiler sampling the whole run if 'whole_run' is true. Otherwise it samples only
+    /// between console.profile() and console.profileEnd().
+    pub fn new(whole_run: bool, interval: Duration) -> Profiler {
+        let requested = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let requested = requested.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    thread::sleep(interval);
+                    requested.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+        Profiler {
+            run: if whole_run {
+                Some(Samples::default())
+            } else {
+                None
+            },
+            sessions: vec![],
+            requested: requested,
+            stopped: stopped,
+            names: FxHashMap::default(),
+        }
+    }
+
+    /// true if a sample is due. Clears the request.
+    #[inline]
+    pub fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+
+    /// Sample the frames of the VM.
+    pub fn sample(&mut self, vm: &VM) {
+        let mut names = vec![];
+        let mut iseq = vm.state.iseq.clone();
+        for frame in vm.state.frames.iter().rev() {
+            names.push(self.function_name(vm, &iseq));
+            iseq = frame.return_iseq.clone();
+        }
+        names.reverse();
+        let stack = names.join(";");
+
+        if let Some(ref mut samples) = self.run {
+            *samples.entry(stack.clone()).or_insert(0) += 1;
+        }
+        for &mut (_, ref mut samples) in &mut self.sessions {
+            *samples.entry(stack.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// Start a profile for console.profile(label). Returns false if it has already started.
+    pub fn start_session(&mut self, label: String) -> bool {
+        if self.sessions.iter().any(|session| session.0 == label) {
+            return false;
+        }
+        self.sessions.push((label, Samples::default()));
+        true
+    }
+
+    /// End the profile of console.profileEnd(label), and return its samples.
+    pub fn end_session(&mut self, label: &str) -> Option<Samples> {
+        let i = self
+            .sessions
+            .iter()
+            .position(|session| session.0 == label)?;
+        Some(self.sessions.remove(i).1)
+    }
+
+    /// the samples of the whole run, which end here.
+    pub fn end_run(&mut self) -> Option<Samples> {
+        self.run.take()
+    }
+
+    /// true if nothing is being sampled.
+    pub fn is_idle(&self) -> bool {
+        self.run.is_none() && self.sessions.is_empty()
+    }
+
+    fn function_name(&mut self, vm: &VM, iseq: &Rc<ByteCode>) -> String {
+        let addr = &**iseq as *const ByteCode as usize;
+        if let Some(&(_, ref name)) = self.names.get(&addr) {
+            return name.clone();
+        }
+        let const_index = vm.function_const_index(iseq);
+        let name = match vm.source_map {
+            Some(ref source_map) => match source_map.function(const_index) {
+                Some(func) if func.name.is_empty() => {
+                    format!("(top-level) ({}:{})", source_map.file, func.location.0)
+                }
+                Some(func) => format!("{} ({}:{})", func.name, source_map.file, func.location.0),
+                None => "(anonymous code)".to_string(),
+            },
+            None => match const_index {
+                Some(i) => format!("(function #{})", i),
+                None => "(top-level)".to_string(),
+            },
+        };
+        self.names.insert(addr, (iseq.clone(), name.clone()));
+        name
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 'samples' in the collapsed format, sorted by the stacks.
+pub fn collapsed(samples: &Samples) -> String {
+    let mut stacks: Vec<(&String, &u64)> = samples.iter().collect();
+    stacks.sort();
+    stacks
+        .into_iter()
+        .map(|(stack, count)| format!("{} {}\n", stack, count))
+        .collect()
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+    use sourcemap::SourceMap;
+    use std::mem;
+
+    let code = "var x = 0\nfunction f(a) {\n  return a + 1\n}\n\
+                for (var i = 0; i < 300000; i++) x = f(x)\n";
+    let mut parser = Parser::new(code.to_string());
+    let node = parser.parse_all().unwrap();
+    let mut vm = VM::new();
+    vm.jit_on = false;
+    vm.codegen.record_positions = true;
+    let mut iseq = vec![];
+    vm.codegen.compile(&node, &mut iseq, false).unwrap();
+    vm.codegen.record_positions = false;
+    let positions = mem::replace(&mut vm.codegen.function_positions, vec![]);
+    vm.source_map = Some(SourceMap::new("a.js", &positions, &parser.lexer));
+
+    vm.profiler = Some(Profiler::new(true, SAMPLING_INTERVAL));
+    vm.run(iseq).unwrap();
+    let mut profiler = vm.profiler.take().unwrap();
+    let samples = profiler.end_run().unwrap();
+    assert!(profiler.is_idle());
+    assert!(samples.len() > 0);
+    for line in collapsed(&samples).lines() {
+        assert!(line.starts_with("(top-level) (a.js:1)"));
+        let (stack, count) = line.split_at(line.rfind(' ').unwrap());
+        assert!(stack == "(top-level) (a.js:1)" || stack == "(top-level) (a.js:1);f (a.js:2)");
+        assert!(count.trim().parse::<u64>().unwrap() > 0);
+    }
+
+    assert!(profiler.start_session("a".to_string()));
+    assert!(!profiler.start_session("a".to_string()));
+    assert_eq!(profiler.end_session("a"), Some(Samples::default()));
+    assert_eq!(profiler.end_session("a"), None);
+}
@@ -0,0 +1,139 @@
+use super::value::Property;
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+
+// The properties of a property map, kept in the order they are enumerated by Object.keys() and
+// others (https://tc39.github.io/ecma262/#sec-ordinaryownpropertykeys): the keys which are
+// array indices in ascending order, then the other keys in the order they were added.
+//
+// The keys which are array indices are kept apart in a BTreeMap, which keeps them sorted.
+// The others are kept like in an index map: the properties in a vector in the order they were
+// added, and the positions of the keys in a hash map.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Properties {
+    /// properties whose keys are array indices, by the indices.
+    indices: BTreeMap<u32, (String, Property)>,
+    /// the other properties in the order they were added.
+    named: Vec<(String, Property)>,
+    /// the positions of the keys in 'named'.
+    positions: FxHashMap<String, usize>,
+}
+
+impl Properties {
+    pub fn new() -> Properties {
+        Properties::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len() + self.named.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Property> {
+        match index(key) {
+            Some(n) => self.indices.get(&n).map(|entry| &entry.1),
+            None => self
+                .positions
+                .get(key)
+                .map(|&position| &self.named[position].1),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Property> {
+        match index(key) {
+            Some(n) => self.indices.get_mut(&n).map(|entry| &mut entry.1),
+            None => match self.positions.get(key) {
+                Some(&position) => Some(&mut self.named[position].1),
+                None => None,
+            },
+        }
+    }
+
+    /// Insert the property 'key'. A key which is already there keeps its position.
+    pub fn insert(&mut self, key: String, prop: Property) -> Option<Property> {
+        if let Some(n) = index(&key) {
+            return self.indices.insert(n, (key, prop)).map(|(_, old)| old);
+        }
+        if let Some(&position) = self.positions.get(&key) {
+            return Some(::std::mem::replace(&mut self.named[position].1, prop));
+        }
+        self.positions.insert(key.clone(), self.named.len());
+        self.named.push((key, prop));
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.named.clear();
+        self.positions.clear();
+    }
+
+    /// the properties in the order of enumeration.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a String, &'a Property)> + 'a {
+        self.indices
+            .values()
+            .chain(self.named.iter())
+            .map(|entry| (&entry.0, &entry.1))
+    }
+
+    /// the keys in the order of enumeration.
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = &'a String> + 'a {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut Property> + 'a {
+        self.indices
+            .values_mut()
+            .chain(self.named.iter_mut())
+            .map(|entry| &mut entry.1)
+    }
+}
+
+/// The array index which 'key' stands for, if any: the canonical form of an integer below
+/// 2^32 - 1.
+fn index(key: &str) -> Option<u32> {
+    match key.as_bytes().first() {
+        Some(b'0') if key.len() == 1 => Some(0),
+        Some(b'1'...b'9') if key.len() <= 10 && key.bytes().all(|b| b'0' <= b && b <= b'9') => {
+            match key.parse::<u64>() {
+                Ok(n) if n < 0xffff_ffff => Some(n as u32),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test() {
+    use super::value::Value;
+
+    let mut props = Properties::new();
+    for key in &["b", "2", "a", "10", "0", "01", "4294967295"] {
+        props.insert(key.to_string(), Property::new(Value::Undefined));
+    }
+    // Replacing a property keeps its position.
+    props.insert("b".to_string(), Property::new(Value::Bool(true)));
+    assert_eq!(
+        props.keys().map(|key| key.as_str()).collect::<Vec<&str>>(),
+        vec!["0", "2", "10", "b", "a", "01", "4294967295"]
+    );
+    assert_eq!(props.len(), 7);
+    assert_eq!(
+        props.get("b").map(|prop| prop.val.clone()),
+        Some(Value::Bool(true))
+    );
+    assert!(props.contains_key("10"));
+    assert!(!props.contains_key("1"));
+
+    props.clear();
+    assert!(props.is_empty());
+}
@@ -0,0 +1,74 @@
+use atom::Atom;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+
+/// Hidden class of a property map.
+/// Maps which got the same keys in the same order and have the same prototype share a
+/// shape, so a shape tells which keys a map has and where its prototype chain goes.
+pub type ShapeId = usize;
+
+/// Shape of an empty property map.
+pub const ROOT_SHAPE: ShapeId = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Transition {
+    AddKey(Atom),
+    /// __proto__ is set to the object whose property map is at the address. 0 if the
+    /// prototype is not an object.
+    SetProto(usize),
+}
+
+#[derive(Debug)]
+pub struct ShapeTable {
+    transitions: FxHashMap<(ShapeId, Transition), ShapeId>,
+}
+
+impl ShapeTable {
+    pub fn new() -> ShapeTable {
+        ShapeTable {
+            transitions: FxHashMap::default(),
+        }
+    }
+
+    fn transition(&mut self, from: ShapeId, transition: Transition) -> ShapeId {
+        let next_id = self.transitions.len() + 1;
+        *self
+            .transitions
+            .entry((from, transition))
+            .or_insert(next_id)
+    }
+}
+
+thread_local!(pub static SHAPE_TABLE: RefCell<ShapeTable> = {
+    RefCell::new(ShapeTable::new())
+});
+
+/// shape of a map of the shape 'from' after 'key' is added.
+pub fn add_key(from: ShapeId, key: &str) -> ShapeId {
+    let transition = Transition::AddKey(Atom::new(key));
+    SHAPE_TABLE.with(|table| table.borrow_mut().transition(from, transition))
+}
+
+/// shape of a map of the shape 'from' after its prototype is set to the object whose
+/// property map is at 'proto_addr'.
+pub fn set_proto(from: ShapeId, proto_addr: usize) -> ShapeId {
+    let transition = Transition::SetProto(proto_addr);
+    SHAPE_TABLE.with(|table| table.borrow_mut().transition(from, transition))
+}
+
+#[test]
+fn test() {
+    let a = add_key(ROOT_SHAPE, "x");
+    let b = add_key(a, "y");
+    assert_ne!(a, ROOT_SHAPE);
+    assert_ne!(a, b);
+    assert_eq!(add_key(ROOT_SHAPE, "x"), a);
+    assert_eq!(add_key(a, "y"), b);
+    // The order of keys matters.
+    assert_ne!(add_key(add_key(ROOT_SHAPE, "y"), "x"), b);
+
+    let p = set_proto(b, 0x1000);
+    assert_eq!(set_proto(b, 0x1000), p);
+    assert_ne!(set_proto(b, 0x2000), p);
+    assert_ne!(set_proto(p, 0x1000), p);
+}
@@ -1,6 +1,10 @@
 // use chrono::Utc;
 use super::value::Value;
+use builtins::fetch::FetchResult;
+use builtins::worker::Port;
+use gc::{Gc, GcPtr};
 use id;
+use rustc_hash::FxHashSet;
 use std::collections::VecDeque;
 
 pub type TimerID = id::Id;
@@ -10,6 +14,7 @@ pub struct TaskManager {
     id: id::IdGen,
     tasks: VecDeque<Task>,
     mirror_tasks: VecDeque<Task>,
+    jobs: VecDeque<Job>,
 }
 
 #[derive(Debug, Clone)]
@@ -26,19 +31,41 @@ pub enum Task {
         callback: Value,
         args: Vec<Value>,
     },
-    // TODO: Add I/O, microtasks...
+    /// a request of fetch() sent on another thread. 'result' is set when it finishes.
+    Fetch { promise: Value, result: FetchResult },
+    /// messages from a worker, or to a worker if 'target' is None.
+    Message { target: Option<Value>, port: Port },
+}
+
+/// a job (microtask), which runs after the current script or task and before the next task.
+/// e.g. a promise reaction or a callback of queueMicrotask().
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub callback: Value,
+    pub args: Vec<Value>,
+}
+
+/// The queue of jobs. Promise reactions (HostEnqueuePromiseJob) and jobs enqueued by the host
+/// all go through it, so they run in the order they are enqueued.
+pub trait JobQueue {
+    fn enqueue_job(&mut self, job: Job);
+
+    /// the job enqueued first, which is removed from the queue.
+    fn dequeue_job(&mut self) -> Option<Job>;
 }
 
 impl Task {
     pub fn get_timer_id(&self) -> Option<TimerID> {
         match self {
             Task::Timer { id, .. } => Some(*id),
+            Task::Fetch { .. } | Task::Message { .. } => None,
         }
     }
 
     pub fn get_timer_id_mut(&mut self) -> Option<&mut TimerID> {
         match self {
             Task::Timer { ref mut id, .. } => Some(id),
+            Task::Fetch { .. } | Task::Message { .. } => None,
         }
     }
 }
@@ -49,6 +76,7 @@ impl TaskManager {
             id: id::IdGen::new(),
             tasks: VecDeque::new(),
             mirror_tasks: VecDeque::new(),
+            jobs: VecDeque::new(),
         }
     }
 
@@ -87,11 +115,59 @@ impl TaskManager {
         }
     }
 
+    /// add a task which is not a timer.
+    pub fn add_task(&mut self, task: Task) {
+        self.tasks.push_back(task);
+    }
+
     pub fn retain_task(&mut self, task: Task) {
         self.mirror_tasks.push_back(task)
     }
 
+    /// discard all the tasks and jobs.
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+        self.mirror_tasks.clear();
+        self.jobs.clear();
+    }
+
     pub fn no_tasks(&mut self) -> bool {
         self.tasks.len() == 0
     }
+
+    /// trace the values held by the tasks and jobs waiting to run.
+    pub fn trace(&mut self, marked: &mut FxHashSet<GcPtr>) {
+        for job in self.jobs.iter_mut() {
+            job.callback.trace(marked);
+            for arg in job.args.iter_mut() {
+                arg.trace(marked);
+            }
+        }
+        for task in self.tasks.iter_mut().chain(self.mirror_tasks.iter_mut()) {
+            match task {
+                Task::Timer { callback, args, .. } => {
+                    callback.trace(marked);
+                    for arg in args {
+                        arg.trace(marked);
+                    }
+                }
+                Task::Fetch { promise, .. } => promise.trace(marked),
+                Task::Message { target, .. } => {
+                    if let Some(target) = target {
+                        target.trace(marked)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl JobQueue for TaskManager {
+    fn enqueue_job(&mut self, job: Job) {
+        self.jobs.push_back(job);
+    }
+
+    fn dequeue_job(&mut self) -> Option<Job> {
+        self.jobs.pop_front()
+    }
 }
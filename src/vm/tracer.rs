@@ -0,0 +1,143 @@
+use bytecode_gen::{inst_to_string, ByteCode};
+use std::io::{LineWriter, Write};
+use std::rc::Rc;
+use vm::vm::VM;
+
+// An instruction tracer ('rapidus --trace'). It writes a line for each instruction the
+// interpreter runs, before running it:
+//
+//   002a f                  3  GetValue 'x'               1 | 'a' | 2
+//
+// which is the pc, the function, the length of the stack, the instruction with its operands,
+// and the values on the top of the stack with the topmost one last. The length of the stack
+// shows where it grows or shrinks unexpectedly. The trace can be limited to the instructions of
+// the functions of a name, which is found by the source map, so nothing is traced by the name
+// when a precompiled script runs without its map. JIT-compiled code is not traced.
+
+/// the number of values shown from the top of the stack.
+pub const STACK_VALUES: usize = 3;
+
+pub struct Tracer {
+    output: LineWriter<Box<dyn Write>>,
+    /// trace only the functions of this name. "" for the top-level code.
+    function: Option<String>,
+    /// the code seen last, whose name and whether it is traced are kept since the code of a
+    /// function usually runs many instructions in a row.
+    last: Option<(Rc<ByteCode>, String, bool)>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>, function: Option<String>) -> Tracer {
+        Tracer {
+            output: LineWriter::new(output),
+            function: function,
+            last: None,
+        }
+    }
+
+    /// Trace the instruction of the current frame at the pc, which is in 'iseq'.
+    pub fn trace(&mut self, vm: &VM, iseq: &Rc<ByteCode>) {
+        let seen = match self.last {
+            Some((ref last, _, _)) => Rc::ptr_eq(last, iseq),
+            None => false,
+        };
+        if !seen {
+            let name = vm
+                .source_map
+                .as_ref()
+                .and_then(|source_map| source_map.function(vm.function_const_index(iseq)))
+                .map(|func| func.name.clone());
+            let traced = match self.function {
+                Some(ref function) => name.as_ref() == Some(function),
+                None => true,
+            };
+            let name = match name {
+                Some(ref name) if name.is_empty() => "(top-level)".to_string(),
+                Some(name) => name,
+                None => "(anonymous code)".to_string(),
+            };
+            self.last = Some((iseq.clone(), name, traced));
+        }
+        let (name, traced) = match self.last {
+            Some((_, ref name, traced)) => (name, traced),
+            None => unreachable!(),
+        };
+        if !traced {
+            return;
+        }
+
+        let stack = &vm.state.stack;
+        let top = &stack[stack.len() - stack.len().min(STACK_VALUES)..];
+        let top: Vec<String> = top.iter().map(|val| val.format(1, false)).collect();
+        let pc = vm.state.pc as usize;
+        let line = format!(
+            "{:04x} {:<16} {:>3}  {:<26} {}",
+            pc,
+            name,
+            stack.len(),
+            inst_to_string(iseq, pc, &vm.codegen.bytecode_gen.const_table),
+            top.join(" | ")
+        );
+        // Errors are ignored so that tracing does not stop the script.
+        let _ = writeln!(self.output, "{}", line.trim_end());
+    }
+}
+
+#[test]
+fn test() {
+    use parser::Parser;
+    use sourcemap::SourceMap;
+    use std::cell::RefCell;
+    use std::io;
+    use std::mem;
+
+    #[derive(Clone)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(function: Option<&str>) -> String {
+        let code = "var x = 'a'\nfunction f(a) {\n  return a + 1\n}\nx = f(2)\n";
+        let mut parser = Parser::new(code.to_string());
+        let node = parser.parse_all().unwrap();
+        let mut vm = VM::new();
+        vm.jit_on = false;
+        vm.codegen.record_positions = true;
+        let mut iseq = vec![];
+        vm.codegen.compile(&node, &mut iseq, false).unwrap();
+        vm.codegen.record_positions = false;
+        let positions = mem::replace(&mut vm.codegen.function_positions, vec![]);
+        vm.source_map = Some(SourceMap::new("a.js", &positions, &parser.lexer));
+
+        let output = Output(Rc::new(RefCell::new(vec![])));
+        vm.tracer = Some(Tracer::new(
+            Box::new(output.clone()),
+            function.map(|name| name.to_string()),
+        ));
+        vm.run(iseq).unwrap();
+        vm.tracer = None;
+        let output = output.0.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    let all = trace(None);
+    assert!(all.lines().count() > 0);
+    assert!(all.starts_with("0000 (top-level)"));
+    assert!(all.contains(" f "));
+    assert!(all.contains("PushConst 'a'"));
+    assert!(all.lines().any(|line| line.ends_with("'a'")));
+
+    let f = trace(Some("f"));
+    assert!(f.lines().count() > 0);
+    assert!(f.lines().count() < all.lines().count());
+    assert!(f.lines().all(|line| line[5..].starts_with("f ")));
+    assert!(f.lines().any(|line| line.ends_with(" 2")));
+
+    assert_eq!(trace(Some("g")), "");
+}
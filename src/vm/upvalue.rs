@@ -0,0 +1,27 @@
+use super::value::Value;
+
+// Upvalues, through which closures refer to the variables of enclosing functions kept in slots
+// (see resolver.rs). As in Lua, an upvalue is open while the frame of the variable runs, and
+// refers to the register of the slot. The VM closes it when the frame is left: the value is
+// moved into the upvalue, which the closures made in the frame keep sharing.
+//
+// A closure is made by CREATE_CLOSURE, which finds its upvalues as the function describes by
+// UpvalueSource, and keeps them in its call object. The code of the closure reads and writes
+// them by GET_UPVALUE and SET_UPVALUE.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Upvalue {
+    /// the variable is in the register at this index of VMState::regs.
+    Open(usize),
+    /// the frame of the variable has been left.
+    Closed(Value),
+}
+
+/// Where a closure finds one of its upvalues when it is made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpvalueSource {
+    /// the slot of the function which makes the closure.
+    Slot(u8),
+    /// the upvalue of this index of the function which makes the closure.
+    Upvalue(usize),
+}
@@ -1,15 +1,25 @@
 #![macro_use]
 use super::callobj::CallObject;
 use super::error::*;
+use super::jsstring::JsString;
+use super::properties::Properties;
+use super::shape::{self, ShapeId, ROOT_SHAPE};
+use super::upvalue::{Upvalue, UpvalueSource};
 use builtin::{BuiltinFuncInfo, BuiltinFuncTy, BuiltinJITFuncInfo};
 use builtins::function;
-use bytecode_gen::ByteCode;
+use builtins::typedarray::TypedArrayKind;
+use bytecode_gen::{ByteCode, VMInst};
 use chrono::{DateTime, Utc};
 use gc;
 use gc::GcType;
 use id::{get_unique_id, Id};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
 pub use rustc_hash::FxHashMap;
-use std::ffi::CString;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::rc::Rc;
 
 pub type FuncId = Id;
 
@@ -17,9 +27,12 @@ pub type RawStringPtr = *mut libc::c_char;
 
 pub type NamePropPair = (String, Property);
 /// 24 bytes
-pub type PropMapRef = GcType<FxHashMap<String, Property>>;
+pub type PropMapRef = GcType<PropMap>;
 pub type CallObjectRef = GcType<CallObject>;
 pub type ArrayValueRef = GcType<ArrayValue>;
+pub type ArrayBufferRef = GcType<ArrayBuffer>;
+pub type WeakTableRef = GcType<WeakTable>;
+pub type UpvalueRef = GcType<Upvalue>;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Property {
@@ -27,25 +40,66 @@ pub struct Property {
     pub writable: bool,
     pub enumerable: bool,
     pub configurable: bool,
+    pub accessor: Option<Box<Accessor>>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// Getter and setter of an accessor property. Undefined if not defined.
+pub struct Accessor {
+    pub get: Value,
+    pub set: Value,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FuncInfo {
     pub id: FuncId,
-    pub iseq: ByteCode,
+    /// shared by the copies of FuncInfo made on every call.
+    pub iseq: Rc<ByteCode>,
     pub params: Vec<(String, bool)>, // (name, rest param?)
+    /// strict mode function. 'this' is undefined when it is called as a plain function.
+    pub strict: bool,
+    /// the source text, returned by toString().
+    pub source: Option<Rc<String>>,
+    /// true if the call object of a call may be referred to after the call returns, by a
+    /// closure, 'arguments' or a direct eval. Otherwise the VM reuses it for later calls.
+    pub captures_scope: bool,
+    /// where a closure of the function finds each of its upvalues when it is made. Shared like
+    /// 'iseq'.
+    pub upvalues: Rc<Vec<UpvalueSource>>,
 }
 
 impl FuncInfo {
     pub fn new(id: FuncId, iseq: ByteCode, params: Vec<(String, bool)>) -> FuncInfo {
         FuncInfo {
             id: id,
-            iseq: iseq,
+            captures_scope: captures_scope(&iseq),
+            iseq: Rc::new(iseq),
             params: params,
+            strict: false,
+            source: None,
+            upvalues: Rc::new(vec![]),
         }
     }
 }
 
+/// Whether 'iseq' may let its call object be referred to after it returns. Code which cannot be
+/// decoded is assumed to do so.
+fn captures_scope(iseq: &ByteCode) -> bool {
+    let mut pc = 0;
+    while pc < iseq.len() {
+        match iseq[pc] {
+            VMInst::UPDATE_PARENT_SCOPE | VMInst::PUSH_ARGUMENTS | VMInst::CALL_EVAL => {
+                return true
+            }
+            inst => match VMInst::get_inst_size(inst) {
+                Some(size) => pc += size,
+                None => return true,
+            },
+        }
+    }
+    false
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum ObjectKind {
     Function(Box<(FuncInfo, CallObjectRef)>),
@@ -54,6 +108,12 @@ pub enum ObjectKind {
     Array(ArrayValueRef),
     Date(Box<(DateTime<Utc>)>),
     Arguments(CallObjectRef),
+    PrimitiveWrapper(Box<Value>), // Number, String or Boolean object. e.g. new Number(1)
+    ArrayBuffer(ArrayBufferRef),
+    TypedArray(Box<TypedArray>),
+    DataView(Box<DataView>),
+    WeakTable(WeakTableRef),
+    Response(Box<String>), // the body of a Response of fetch()
 }
 
 // 32 bytes
@@ -64,17 +124,103 @@ pub enum Value {
     Undefined,
     Bool(bool),
     Number(f64),
-    String(Box<CString>), // TODO: Using CString is good for JIT. However, we need better one instead.
+    BigInt(Box<BigInt>),
+    String(Box<JsString>),
     Object(PropMapRef, ObjectKind), // Object(FxHashMap<String, Value>),
 }
 
 #[derive(Clone, PartialEq)]
-/// 32 bytes
+/// Elements of an array. Elements below elems.len() are stored in 'elems', where holes are
+/// Value::Empty, and the others in 'sparse'. Use the methods to access elements, since either
+/// may hold an element and 'length' may exceed both.
+/// 56 bytes
 pub struct ArrayValue {
     pub elems: Vec<Property>,
+    /// elements far beyond the end of 'elems', e.g. 'a[1000000] = 1' on a short array.
+    pub sparse: BTreeMap<usize, Property>,
     pub length: usize,
 }
 
+/// 'elems' of an array grows to take an element at most this far beyond its end. Farther
+/// elements go to 'sparse'.
+const MAX_DENSE_GAP: usize = 1024;
+
+/// the largest array index plus 1. https://tc39.github.io/ecma262/#array-index
+pub const MAX_ARRAY_LENGTH: f64 = 4294967295.0;
+
+/// the most elements an array is made into a list of, e.g. the arguments of apply().
+pub const MAX_LIST_LENGTH: usize = 1 << 24;
+
+/// the most code units a string joined from an array may have.
+pub const MAX_STRING_LENGTH: usize = (1 << 29) - 24;
+
+#[derive(Clone, PartialEq, Debug)]
+/// Byte storage of an ArrayBuffer. Typed arrays and DataViews on the buffer share it.
+pub struct ArrayBuffer {
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// A view of an ArrayBuffer as an array of numbers. e.g. Uint8Array
+pub struct TypedArray {
+    pub kind: TypedArrayKind,
+    pub buffer: ArrayBufferRef,
+    pub byte_offset: usize,
+    pub length: usize, // Number of elements
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DataView {
+    pub buffer: ArrayBufferRef,
+    pub byte_offset: usize,
+    pub byte_length: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// The objects held weakly by a WeakMap, WeakSet, WeakRef or FinalizationRegistry. The GC does
+/// not trace them, and removes the entries of the objects it frees.
+pub struct WeakTable {
+    pub kind: WeakKind,
+    /// entries by the addresses of the property maps of the objects, which identify them. The
+    /// values of a WeakMap, undefined for a WeakSet and the target of a WeakRef. A value is
+    /// traced only while its object is alive.
+    pub entries: FxHashMap<usize, Value>,
+    /// the registrations of a FinalizationRegistry.
+    pub registrations: Vec<Registration>,
+    /// the cleanup callback of a FinalizationRegistry. Undefined for the others.
+    pub cleanup: Value,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WeakKind {
+    WeakMap,
+    WeakSet,
+    WeakRef,
+    FinalizationRegistry,
+}
+
+impl WeakKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeakKind::WeakMap => "WeakMap",
+            WeakKind::WeakSet => "WeakSet",
+            WeakKind::WeakRef => "WeakRef",
+            WeakKind::FinalizationRegistry => "FinalizationRegistry",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// An object registered to a FinalizationRegistry.
+pub struct Registration {
+    /// the address of the property map of the object.
+    pub target: usize,
+    /// passed to the cleanup callback after the object is freed.
+    pub held: Value,
+    /// the address of the property map of the unregister token, if any.
+    pub token: Option<usize>,
+}
+
 #[macro_export]
 macro_rules! make_npp {
     ($($property_name:ident : $val:expr),*) => {
@@ -96,7 +242,141 @@ impl Property {
             writable: true,
             enumerable: true,
             configurable: true,
+            accessor: None,
+        }
+    }
+
+    /// create an accessor property. its value is always Empty.
+    pub fn new_accessor(get: Value, set: Value) -> Property {
+        Property {
+            val: Value::Empty,
+            writable: true,
+            enumerable: true,
+            configurable: true,
+            accessor: Some(Box::new(Accessor { get: get, set: set })),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Properties of an object, or variables of a scope, in the order of enumeration (see
+/// properties.rs). The map can be read through Deref, but has to be modified through the methods below
+/// so that its shape and its version are kept up to date.
+pub struct PropMap {
+    map: Properties,
+    /// [[Prototype]]: an object or null. Undefined until the map is given one (see
+    /// Value::object()), and for the maps of scopes, which have none.
+    proto: Value,
+    shape: ShapeId,
+    /// incremented whenever the map may be modified, so that a cache of a value read from it
+    /// can tell whether it is stale. See GlobalCache.
+    version: usize,
+}
+
+impl PropMap {
+    pub fn new() -> PropMap {
+        PropMap {
+            map: Properties::new(),
+            proto: Value::Undefined,
+            shape: ROOT_SHAPE,
+            version: 0,
+        }
+    }
+
+    pub fn shape(&self) -> ShapeId {
+        self.shape
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn proto(&self) -> Value {
+        self.proto.clone()
+    }
+
+    /// Set [[Prototype]] to 'proto'. As by the __proto__ setter, values other than objects
+    /// and null are ignored.
+    pub fn set_proto(&mut self, proto: Value) {
+        let proto_addr = |proto: &Value| match *proto {
+            Value::Object(ref map, _) => Some(map.addr()),
+            Value::Null => Some(0),
+            _ => None,
+        };
+        let new_proto = match proto_addr(&proto) {
+            Some(addr) => addr,
+            None => return,
+        };
+        if proto_addr(&self.proto) != Some(new_proto) {
+            self.shape = shape::set_proto(self.shape, new_proto);
+        }
+        if new_proto != 0 {
+            gc::write_barrier(self);
+        }
+        self.version += 1;
+        self.proto = proto;
+    }
+
+    /// Insert the property 'key'. __proto__ is not a property, and sets [[Prototype]] instead
+    /// as in an object literal.
+    pub fn insert(&mut self, key: String, prop: Property) -> Option<Property> {
+        if key == "__proto__" {
+            self.set_proto(prop.val);
+            return None;
+        }
+        if !self.map.contains_key(&key) {
+            self.shape = shape::add_key(self.shape, &key);
         }
+        let refers_to_object = match prop.val {
+            Value::Object(_, _) => true,
+            _ => prop.accessor.is_some(),
+        };
+        if refers_to_object {
+            gc::write_barrier(self);
+        }
+        self.version += 1;
+        self.map.insert(key, prop)
+    }
+
+    /// get a mutable reference to the property 'key'.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Property> {
+        gc::write_barrier(self);
+        self.version += 1;
+        self.map.get_mut(key)
+    }
+
+    pub fn properties_mut(&mut self) -> impl Iterator<Item = &mut Property> {
+        gc::write_barrier(self);
+        self.version += 1;
+        self.map.values_mut()
+    }
+
+    /// mutable reference to [[Prototype]] for the GC to trace it. Use set_proto() to replace it.
+    pub fn proto_mut(&mut self) -> &mut Value {
+        gc::write_barrier(self);
+        &mut self.proto
+    }
+
+    /// Remove all the properties and the prototype. The map gets the shape of an empty object.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.proto = Value::Undefined;
+        self.shape = ROOT_SHAPE;
+        self.version += 1;
+    }
+}
+
+impl Deref for PropMap {
+    type Target = Properties;
+
+    fn deref(&self) -> &Properties {
+        &self.map
+    }
+}
+
+impl PartialEq for PropMap {
+    fn eq(&self, other: &PropMap) -> bool {
+        self.map == other.map
     }
 }
 
@@ -111,29 +391,91 @@ impl Value {
     }
 
     pub fn string(s: String) -> Value {
-        Value::String(Box::new(CString::new(s).unwrap()))
+        Value::String(Box::new(JsString::from(s)))
+    }
+
+    pub fn js_string(s: JsString) -> Value {
+        Value::String(Box::new(s))
+    }
+
+    pub fn bigint(n: BigInt) -> Value {
+        Value::BigInt(Box::new(n))
     }
 
     /// generate JS function object.
-    pub fn function(iseq: ByteCode, params: Vec<(String, bool)>, callobj: CallObjectRef) -> Value {
+    pub fn function(
+        iseq: ByteCode,
+        params: Vec<(String, bool)>,
+        strict: bool,
+        source: Option<Rc<String>>,
+        name: String,
+        length: usize,
+        callobj: CallObjectRef,
+    ) -> Value {
         let mut prototype = Value::object_from_npp(&vec![]);
         let kind = ObjectKind::Function(Box::new((
-            FuncInfo::new(get_unique_id(), iseq, params),
+            FuncInfo {
+                strict: strict,
+                source: source,
+                ..FuncInfo::new(get_unique_id(), iseq, params)
+            },
             callobj.clone(),
         )));
-        let val = Value::Object(
-            Value::propmap_from_npp(&make_npp!(
-                prototype:  prototype.clone(),
-                __proto__:  function::FUNCTION_PROTOTYPE.with(|x| x.clone())
-            )),
-            kind.clone(),
-        );
+        let mut map = Value::propmap_from_npp(&make_npp!(
+            prototype:  prototype.clone(),
+            __proto__:  function::FUNCTION_PROTOTYPE.with(|x| x.clone())
+        ));
+        // https://tc39.github.io/ecma262/#sec-function-instances
+        // 'name' and 'length' are neither writable nor enumerable.
+        for (key, val) in vec![
+            ("name", Value::string(name)),
+            ("length", Value::Number(length as f64)),
+        ] {
+            map.insert(
+                key.to_string(),
+                Property {
+                    val: val,
+                    writable: false,
+                    enumerable: false,
+                    configurable: true,
+                    accessor: None,
+                },
+            );
+        }
+        let val = Value::Object(map, kind.clone());
 
         prototype.set_constructor(val.clone());
 
         val
     }
 
+    /// A closure of the function object 'self', made by a function declaration or expression
+    /// which is evaluated. It runs with 'callobj', and has its own properties and prototype.
+    pub fn closure(&self, callobj: CallObjectRef) -> Value {
+        match *self {
+            Value::Object(ref map, ObjectKind::Function(box (ref func_info, _))) => {
+                let mut prototype = Value::object_from_npp(&vec![]);
+                let mut map = gc::new((**map).clone());
+                if let Some(prop) = map.get("prototype").cloned() {
+                    map.insert(
+                        "prototype".to_string(),
+                        Property {
+                            val: prototype.clone(),
+                            ..prop
+                        },
+                    );
+                }
+                let val = Value::Object(
+                    map,
+                    ObjectKind::Function(Box::new((func_info.clone(), callobj))),
+                );
+                prototype.set_constructor(val.clone());
+                val
+            }
+            _ => self.clone(),
+        }
+    }
+
     /// generate builtin function with JIT, blank PropMapRef and no prototype.
     pub fn builtin_function_with_jit(
         func: BuiltinFuncTy,
@@ -167,9 +509,9 @@ impl Value {
         )
     }
 
-    /// make new property map (PropMapRef) from npp.
+    /// make new property map (PropMapRef) from npp. __proto__ in npp gives the prototype.
     pub fn propmap_from_npp(npp: &Vec<NamePropPair>) -> PropMapRef {
-        let mut map = FxHashMap::default();
+        let mut map = PropMap::new();
         for p in npp {
             map.insert(p.0.clone(), p.1.clone());
         }
@@ -186,8 +528,9 @@ impl Value {
     pub fn object(map: PropMapRef) -> Value {
         use builtins::object;
         let mut map = map.clone();
-        map.entry("__proto__".to_string())
-            .or_insert(object::OBJECT_PROTOTYPE.with(|x| x.clone()).to_property());
+        if let Value::Undefined = map.proto() {
+            map.set_proto(object::OBJECT_PROTOTYPE.with(|x| x.clone()));
+        }
         Value::Object(map, ObjectKind::Ordinary)
     }
 
@@ -204,7 +547,11 @@ impl Value {
 
     /// make new array from elements.
     pub fn array_from_elems(elms: Vec<Value>) -> Value {
-        let ary = ArrayValue::new(elms);
+        Value::array_from_value(ArrayValue::new(elms))
+    }
+
+    /// make new array of 'ary'.
+    pub fn array_from_value(ary: ArrayValue) -> Value {
         Value::Object(
             {
                 use builtins::array::ARRAY_PROTOTYPE;
@@ -217,21 +564,151 @@ impl Value {
         )
     }
 
+    /// https://tc39.github.io/ecma262/#sec-gettemplateobject
+    /// make the template object passed to the tag of a tagged template: the array of the cooked
+    /// strings (undefined for an invalid escape sequence) with 'raw', the array of the raw
+    /// strings. Both are frozen, so their elements and 'raw' are neither writable nor
+    /// configurable.
+    pub fn template_object(cooked: &Vec<Option<String>>, raw: &Vec<String>) -> Value {
+        use builtins::array::ARRAY_PROTOTYPE;
+        let frozen_array = |elems: Vec<Value>| {
+            let mut ary = ArrayValue::new(elems);
+            for elem in &mut ary.elems {
+                elem.writable = false;
+                elem.configurable = false;
+            }
+            let map = Value::propmap_from_npp(&make_npp!(
+                __proto__:  ARRAY_PROTOTYPE.with(|x| x.clone())
+            ));
+            Value::Object(map, ObjectKind::Array(gc::new(ary)))
+        };
+
+        let raw = frozen_array(raw.iter().map(|s| Value::string(s.clone())).collect());
+        let cooked = cooked
+            .iter()
+            .map(|s| {
+                s.as_ref()
+                    .map_or(Value::Undefined, |s| Value::string(s.clone()))
+            })
+            .collect();
+        let mut template = frozen_array(cooked);
+        if let Value::Object(ref mut map, _) = template {
+            map.insert(
+                "raw".to_string(),
+                Property {
+                    val: raw,
+                    writable: false,
+                    enumerable: false,
+                    configurable: false,
+                    accessor: None,
+                },
+            );
+        }
+        template
+    }
+
     pub fn date(time_val: DateTime<Utc>) -> Value {
         use builtins::date::DATE_PROTOTYPE;
         Value::Object(
             {
-                let mut hm = FxHashMap::default();
-                hm.insert(
-                    "__proto__".to_string(),
-                    Property::new(DATE_PROTOTYPE.with(|x| x.clone())),
-                );
+                let mut hm = PropMap::new();
+                hm.set_proto(DATE_PROTOTYPE.with(|x| x.clone()));
                 gc::new(hm)
             },
             ObjectKind::Date(Box::new(time_val)),
         )
     }
 
+    /// make a wrapper object (Number, String or Boolean object) of the primitive value.
+    pub fn primitive_wrapper(primitive: Value) -> Value {
+        use builtins::{
+            boolean::BOOLEAN_PROTOTYPE, number::NUMBER_PROTOTYPE, string::STRING_PROTOTYPE,
+        };
+        let prototype = match primitive {
+            Value::Number(_) => NUMBER_PROTOTYPE.with(|x| x.clone()),
+            Value::String(_) => STRING_PROTOTYPE.with(|x| x.clone()),
+            Value::Bool(_) => BOOLEAN_PROTOTYPE.with(|x| x.clone()),
+            _ => unreachable!("primitive_wrapper(): Value is not Number, String or Boolean."),
+        };
+        Value::Object(
+            Value::propmap_from_npp(&make_npp!(__proto__: prototype)),
+            ObjectKind::PrimitiveWrapper(Box::new(primitive)),
+        )
+    }
+
+    /// make a new ArrayBuffer object which holds 'data'.
+    pub fn array_buffer(data: Vec<u8>) -> Value {
+        use builtins::arraybuffer::ARRAY_BUFFER_PROTOTYPE;
+        Value::Object(
+            Value::propmap_from_npp(&make_npp!(
+                __proto__: ARRAY_BUFFER_PROTOTYPE.with(|x| x.clone())
+            )),
+            ObjectKind::ArrayBuffer(gc::new(ArrayBuffer { data: data })),
+        )
+    }
+
+    /// make a typed array of 'length' elements on the ArrayBuffer object 'buffer'.
+    pub fn typed_array(
+        kind: TypedArrayKind,
+        buffer: Value,
+        byte_offset: usize,
+        length: usize,
+    ) -> Value {
+        use builtins::typedarray::TYPED_ARRAY_PROTOTYPES;
+        let buffer_ref = match buffer {
+            Value::Object(_, ObjectKind::ArrayBuffer(ref buffer_ref)) => buffer_ref.clone(),
+            _ => unreachable!("typed_array(): buffer is not an ArrayBuffer."),
+        };
+        Value::Object(
+            Value::propmap_from_npp(&make_npp!(
+                __proto__:  TYPED_ARRAY_PROTOTYPES.with(|x| x[kind as usize].clone()),
+                buffer:     buffer
+            )),
+            ObjectKind::TypedArray(Box::new(TypedArray {
+                kind: kind,
+                buffer: buffer_ref,
+                byte_offset: byte_offset,
+                length: length,
+            })),
+        )
+    }
+
+    /// make a DataView on the ArrayBuffer object 'buffer'.
+    pub fn data_view(buffer: Value, byte_offset: usize, byte_length: usize) -> Value {
+        use builtins::dataview::DATA_VIEW_PROTOTYPE;
+        let buffer_ref = match buffer {
+            Value::Object(_, ObjectKind::ArrayBuffer(ref buffer_ref)) => buffer_ref.clone(),
+            _ => unreachable!("data_view(): buffer is not an ArrayBuffer."),
+        };
+        Value::Object(
+            Value::propmap_from_npp(&make_npp!(
+                __proto__:  DATA_VIEW_PROTOTYPE.with(|x| x.clone()),
+                buffer:     buffer
+            )),
+            ObjectKind::DataView(Box::new(DataView {
+                buffer: buffer_ref,
+                byte_offset: byte_offset,
+                byte_length: byte_length,
+            })),
+        )
+    }
+
+    /// make a new WeakMap, WeakSet, WeakRef or FinalizationRegistry, which is empty.
+    pub fn weak_table(kind: WeakKind, cleanup: Value) -> Value {
+        use builtins::weak::prototype;
+        let table = gc::new(WeakTable {
+            kind: kind,
+            entries: FxHashMap::default(),
+            registrations: vec![],
+            cleanup: cleanup,
+        });
+        gc::register_weak_table(table.clone());
+        Value::Object(
+            Value::propmap_from_npp(&make_npp!(__proto__: prototype(kind))),
+            ObjectKind::WeakTable(table),
+        )
+    }
+
     pub fn arguments(callobj: CallObjectRef) -> Value {
         Value::Object(
             Value::propmap_from_npp(&vec![]),
@@ -239,91 +716,144 @@ impl Value {
         )
     }
 
+    /// [[Prototype]] of the object, or the prototype of the wrapper object of the primitive.
+    /// Null if there is none. Builtin functions inherit FUNCTION_PROTOTYPE.
+    pub fn prototype(&self) -> Value {
+        use builtins::{
+            bigint::BIGINT_PROTOTYPE, boolean::BOOLEAN_PROTOTYPE, number::NUMBER_PROTOTYPE,
+            string::STRING_PROTOTYPE,
+        };
+        match self {
+            Value::Number(_) => NUMBER_PROTOTYPE.with(|x| x.clone()),
+            Value::String(_) => STRING_PROTOTYPE.with(|x| x.clone()),
+            Value::Bool(_) => BOOLEAN_PROTOTYPE.with(|x| x.clone()),
+            Value::BigInt(_) => BIGINT_PROTOTYPE.with(|x| x.clone()),
+            Value::Object(map, kind) => match map.proto() {
+                Value::Undefined => match kind {
+                    ObjectKind::BuiltinFunction(_) => {
+                        function::FUNCTION_PROTOTYPE.with(|x| x.clone())
+                    }
+                    _ => Value::Null,
+                },
+                proto => proto,
+            },
+            _ => Value::Null,
+        }
+    }
+
+    /// Set [[Prototype]] of the object to 'proto', which is an object or null. A prototype
+    /// chain which would be cyclic throws a TypeError. Primitives are left as they are.
+    pub fn set_prototype(&mut self, proto: Value) -> Result<(), RuntimeError> {
+        let mut map = match self {
+            Value::Object(map, _) => map.clone(),
+            _ => return Ok(()),
+        };
+        let mut cur = proto.clone();
+        loop {
+            let next = match cur {
+                Value::Object(ref cur_map, _) if cur_map.addr() == map.addr() => {
+                    return Err(RuntimeError::Type(
+                        "type error: Cyclic __proto__ value".to_string(),
+                    ));
+                }
+                Value::Object(_, _) => cur.prototype(),
+                _ => break,
+            };
+            cur = next;
+        }
+        map.set_proto(proto);
+        Ok(())
+    }
+
     pub fn get_property(&self, property: Value, callobjref: Option<CallObjectRef>) -> Value {
+        if let Value::String(ref s) = property {
+            if **s == "__proto__" {
+                return self.prototype();
+            }
+        }
+
         let property_of_number = || -> Value {
             use builtins::number::NUMBER_PROTOTYPE;
             let val = NUMBER_PROTOTYPE.with(|x| x.clone());
-            set_this(obj_find_val(val, property.to_string().as_str()), self)
+            obj_find_val(val, property.to_string().as_str())
         };
 
-        let property_of_object = |obj: Value| -> Value {
-            set_this(obj_find_val(obj, property.to_string().as_str()), self)
-        };
+        let property_of_object =
+            |obj: Value| -> Value { obj_find_val(obj, property.to_string().as_str()) };
 
-        let property_of_string = |s: &CString| -> Value {
+        let property_of_string = |s: &JsString| -> Value {
+            // The code unit at the index 'n'. A negative, fractional or too large number is not
+            // an index.
+            if let Some(n) = array_index(&property) {
+                return if n < s.len() {
+                    Value::js_string(s.substring(n, n + 1))
+                } else {
+                    Value::Undefined
+                };
+            }
             match property {
-                // Character at the index 'n'
-                Value::Number(n) if is_integer(n) => Value::string(
-                    s.to_str()
-                        .unwrap()
-                        .chars()
-                        .nth(n as usize)
-                        .unwrap()
-                        .to_string(),
-                ),
-                // Length of string. TODO: Is this implementation correct?
-                Value::String(ref member) if member.to_str().unwrap() == "length" => Value::Number(
-                    s.to_str()
-                        .unwrap()
-                        .chars()
-                        .fold(0, |x, c| x + c.len_utf16()) as f64,
-                ),
-                // TODO: Support all features.
-                _ => Value::Undefined,
+                // Length of string in code units.
+                Value::String(ref member) if **member == "length" => Value::Number(s.len() as f64),
+                _ => {
+                    use builtins::string::STRING_PROTOTYPE;
+                    let val = STRING_PROTOTYPE.with(|x| x.clone());
+                    obj_find_val(val, property.to_string().as_str())
+                }
             }
         };
 
-        let property_of_array = |obj: &Value| -> Value {
-            let get_by_idx = |n: usize| -> Value {
-                if let Value::Object(_, ObjectKind::Array(ref arrval)) = obj {
-                    let arr = &(*arrval).elems;
-                    if n >= (*arrval).length {
-                        return Value::Undefined;
-                    }
+        let property_of_boolean = || -> Value {
+            use builtins::boolean::BOOLEAN_PROTOTYPE;
+            let val = BOOLEAN_PROTOTYPE.with(|x| x.clone());
+            obj_find_val(val, property.to_string().as_str())
+        };
 
-                    match arr[n].val {
-                        Value::Empty => Value::Undefined,
-                        ref other => other.clone(),
-                    }
-                } else {
-                    unreachable!("get_property(): Value is not an array.");
-                }
-            };
+        let property_of_bigint = || -> Value {
+            use builtins::bigint::BIGINT_PROTOTYPE;
+            let val = BIGINT_PROTOTYPE.with(|x| x.clone());
+            obj_find_val(val, property.to_string().as_str())
+        };
 
+        let property_of_array = |obj: &Value| -> Value {
+            let arrval = match obj {
+                Value::Object(_, ObjectKind::Array(ref arrval)) => arrval,
+                _ => unreachable!("get_property(): Value is not an array."),
+            };
+            if let Some(n) = array_index(&property) {
+                return arrval.get_value(n);
+            }
             match property {
-                // Index
-                Value::Number(n) if is_integer(n) && n >= 0.0 => get_by_idx(n as usize),
-                Value::String(ref s) if s.to_str().unwrap() == "length" => {
-                    if let Value::Object(_, ObjectKind::Array(ref arrval)) = obj {
-                        Value::Number((*arrval).length as f64)
-                    } else {
-                        unreachable!("get_property(): Value is not an array.");
-                    }
-                }
-                Value::String(ref s) => {
-                    // https://www.ecma-international.org/ecma-262/9.0/index.html#sec-array-exotic-objects
-                    let num = property.to_uint32();
-                    if Value::Number(num).to_string() == s.to_str().unwrap() {
-                        get_by_idx(num as usize)
-                    } else {
-                        set_this(obj_find_val(obj.clone(), &property.to_string()), self)
-                    }
+                Value::String(ref s) if **s == "length" => {
+                    Value::Number(arrval.length as f64)
                 }
                 _ => obj_find_val(obj.clone(), &property.to_string()),
             }
         };
 
+        let property_of_typed_array = |ta: &TypedArray| -> Value {
+            match property {
+                // Element. Integer-indexed objects never look up the prototype chain for numbers.
+                Value::Number(n) => ta.get(n),
+                Value::String(ref s) => match s.to_string().as_str() {
+                    "length" => Value::Number(ta.length as f64),
+                    "byteLength" => Value::Number(ta.byte_length() as f64),
+                    "byteOffset" => Value::Number(ta.byte_offset as f64),
+                    _ => property_of_object(self.clone()),
+                },
+                _ => property_of_object(self.clone()),
+            }
+        };
+
         let property_of_arguments = || -> Value {
             {
+                // Index. A negative, fractional or too large number is not an index.
+                if let Some(n) = array_index(&property) {
+                    return callobjref
+                        .and_then(|co| co.get_arguments_nth_value(n).ok())
+                        .unwrap_or(Value::Undefined);
+                }
                 match property {
-                    // Index
-                    Value::Number(n) if is_integer(n) && n >= 0.0 => callobjref
-                        .and_then(|co| {
-                            let co = &*co;
-                            Some(co.get_arguments_nth_value(n as usize).unwrap())
-                        })
-                        .unwrap_or_else(|| Value::Undefined),
-                    Value::String(ref s) if s.to_str().unwrap() == "length" => {
+                    Value::String(ref s) if **s == "length" => {
                         let length = callobjref
                             .and_then(|co| Some((*co).get_arguments_length()))
                             .unwrap_or(0);
@@ -337,77 +867,101 @@ impl Value {
         match self {
             Value::Number(_) => property_of_number(),
             Value::String(ref s) => property_of_string(s),
+            Value::Bool(_) => property_of_boolean(),
+            Value::BigInt(_) => property_of_bigint(),
+            // Index and length of String object
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box Value::String(ref s))) => {
+                match property {
+                    _ if array_index(&property).is_some() => property_of_string(s),
+                    Value::String(ref member) if **member == "length" => {
+                        property_of_string(s)
+                    }
+                    _ => property_of_object(self.clone()),
+                }
+            }
             Value::Object(_, ObjectKind::Array(_)) => property_of_array(&*self),
             Value::Object(_, ObjectKind::Arguments(_)) => property_of_arguments(),
+            Value::Object(_, ObjectKind::TypedArray(ref ta)) => property_of_typed_array(ta),
+            Value::Object(_, ObjectKind::ArrayBuffer(ref buffer)) => match property {
+                Value::String(ref s) if **s == "byteLength" => {
+                    Value::Number(buffer.data.len() as f64)
+                }
+                _ => property_of_object(self.clone()),
+            },
+            Value::Object(_, ObjectKind::DataView(ref view)) => match property {
+                Value::String(ref s) if **s == "byteLength" => {
+                    Value::Number(view.byte_length as f64)
+                }
+                Value::String(ref s) if **s == "byteOffset" => {
+                    Value::Number(view.byte_offset as f64)
+                }
+                _ => property_of_object(self.clone()),
+            },
             Value::Object(_, _) => property_of_object(self.clone()),
             _ => Value::Undefined,
         }
     }
 
-    pub fn set_property(&mut self, property: Value, value: Value, callobj: Option<CallObjectRef>) {
-        fn set_by_idx(ary: &mut ArrayValue, n: usize, val: Value) {
-            if n >= ary.length as usize {
-                ary.length = n + 1;
-                while ary.elems.len() < n + 1 {
-                    ary.elems.push(Value::empty().to_property());
-                }
+    /// Set the property. Setting an invalid length of an array throws a RangeError.
+    pub fn set_property(
+        &mut self,
+        property: Value,
+        value: Value,
+        callobj: Option<CallObjectRef>,
+    ) -> Result<(), RuntimeError> {
+        if let Value::String(ref s) = property {
+            if **s == "__proto__" {
+                return match value {
+                    Value::Object(_, _) | Value::Null => self.set_prototype(value),
+                    _ => Ok(()),
+                };
             }
-            ary.elems[n] = val.to_property();
-        };
-
+        }
         match self {
             Value::Object(map, ObjectKind::Array(ref mut aryval)) => {
+                if let Some(n) = array_index(&property) {
+                    aryval.set(n, value);
+                    return Ok(());
+                }
                 match property {
-                    // Index
-                    Value::Number(n) if is_integer(n) && n >= 0.0 => {
-                        set_by_idx(&mut *aryval, n as usize, value)
-                    }
-                    Value::String(ref s) if s.to_str().unwrap() == "length" => match value {
-                        Value::Number(n) if is_integer(n) && n >= 0.0 => {
-                            (*aryval).length = n as usize;
-                            while (*aryval).elems.len() < n as usize + 1 {
-                                (*aryval).elems.push(Value::empty().to_property());
-                            }
+                    // https://tc39.github.io/ecma262/#sec-arraysetlength
+                    Value::String(ref s) if **s == "length" => {
+                        let length = value.to_uint32();
+                        if length != value.to_number() {
+                            return Err(RuntimeError::Exception(::builtins::error::new_error(
+                                "RangeError: Invalid array length".to_string(),
+                            )));
                         }
-                        _ => {}
-                    },
-                    // https://www.ecma-international.org/ecma-262/9.0/index.html#sec-array-exotic-objects
-                    Value::String(ref s)
-                        if Value::Number(property.to_uint32()).to_string()
-                            == s.to_str().unwrap() =>
-                    {
-                        let num = property.to_uint32();
-                        set_by_idx(&mut *aryval, num as usize, value)
+                        aryval.set_length(length as usize);
                     }
                     _ => {
-                        let refval = (*map)
-                            .entry(property.to_string())
-                            .or_insert_with(|| Value::Undefined.to_property());
-                        *refval = value.to_property();
+                        map.insert(property.to_string(), value.to_property());
                     }
                 }
             }
+            Value::Object(map, ObjectKind::TypedArray(ref mut ta)) => match property {
+                // Out-of-range elements are never created.
+                Value::Number(n) => ta.set(n, value.to_number()),
+                // length, byteLength and byteOffset are read-only.
+                Value::String(ref s)
+                    if ["length", "byteLength", "byteOffset"].contains(&s.to_string().as_str()) => {}
+                _ => {
+                    map.insert(property.to_string(), value.to_property());
+                }
+            },
             Value::Object(_, ObjectKind::Arguments(_)) => {
-                match property {
-                    // Index
-                    Value::Number(n) if n - n.floor() == 0.0 => {
-                        callobj
-                            .unwrap()
-                            .clone()
-                            .set_arguments_nth_value(n as usize, value);
-                    }
-                    // TODO: 'length'
-                    _ => {}
+                // Index
+                // TODO: 'length'
+                if let (Some(n), Some(mut callobj)) = (array_index(&property), callobj) {
+                    callobj.set_arguments_nth_value(n, value);
                 }
             }
             Value::Object(map, _) => {
-                let refval = (*map)
-                    .entry(property.to_string())
-                    .or_insert_with(|| Value::Undefined.to_property());
-                *refval = value.to_property();
+                map.insert(property.to_string(), value.to_property());
             }
             _ => {}
         };
+        Ok(())
     }
 
     pub fn set_number_if_possible(&mut self, n: f64) {
@@ -425,6 +979,28 @@ impl Value {
         }
     }
 
+    /// define the getter (is_getter == true) or the setter of the accessor property 'name'.
+    pub fn set_accessor(&mut self, name: String, func: Value, is_getter: bool) {
+        // __proto__ is not a property, and can not be an accessor.
+        if name == "__proto__" {
+            return;
+        }
+        if let Value::Object(map, _) = self {
+            if map.get(&name).map_or(true, |prop| prop.accessor.is_none()) {
+                map.insert(
+                    name.clone(),
+                    Property::new_accessor(Value::Undefined, Value::Undefined),
+                );
+            }
+            let accessor = map.get_mut(&name).unwrap().accessor.as_mut().unwrap();
+            if is_getter {
+                accessor.get = func;
+            } else {
+                accessor.set = func;
+            }
+        }
+    }
+
     pub fn set_property_with_name(&mut self, name: String, val: Value) {
         match self {
             Value::Object(map, _) => {
@@ -446,27 +1022,20 @@ impl Value {
                     "false".to_string()
                 }
             }
-            Value::Number(n) => {
-                if n.is_nan() {
-                    return "NaN".to_string();
-                }
-
-                if *n == 0.0 {
-                    return "0".to_string();
-                }
-
-                if n.is_infinite() {
-                    return "Infinity".to_string();
-                }
-
-                // TODO: Need a correct implementation!
-                //  ref. https://tc39.github.io/ecma262/#sec-tostring-applied-to-the-number-type
-                format!("{}", *n)
-            }
-            Value::String(s) => s.clone().into_string().unwrap(),
+            Value::Number(n) => number_to_string(*n),
+            Value::BigInt(n) => n.to_str_radix(10),
+            Value::String(s) => s.to_string(),
             Value::Object(_, ObjectKind::Array(ary_val)) => (*ary_val).to_string(),
             Value::Object(_, ObjectKind::Ordinary) => "[object Object]".to_string(),
             Value::Object(_, ObjectKind::Date(box time_val)) => time_val.to_rfc3339(),
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => primitive.to_string(),
+            Value::Object(_, ObjectKind::TypedArray(ta)) => ta.to_string(),
+            Value::Object(_, ObjectKind::ArrayBuffer(_)) => "[object ArrayBuffer]".to_string(),
+            Value::Object(_, ObjectKind::DataView(_)) => "[object DataView]".to_string(),
+            Value::Object(_, ObjectKind::Response(_)) => "[object Response]".to_string(),
+            Value::Object(_, ObjectKind::WeakTable(table)) => {
+                format!("[object {}]", table.kind.name())
+            }
             Value::Object(_, ObjectKind::Function(_)) => "[Function]".to_string(),
             Value::Object(_, ObjectKind::BuiltinFunction(_)) => "[BuiltinFunc]".to_string(),
             Value::Null => "null".to_string(),
@@ -475,27 +1044,31 @@ impl Value {
         }
     }
 
-    // TODO: Need a correct implementation!
-    pub fn to_number(&self) -> f64 {
-        fn str_to_num(s: &str) -> f64 {
-            let all_ws = s.chars().all(|c| c.is_whitespace());
-
-            if all_ws {
-                return 0.0;
-            }
+    /// to_string() without losing lone surrogates of a string.
+    /// to_string(), except that an array too long to be joined throws a RangeError.
+    pub fn try_to_string(&self) -> Result<String, RuntimeError> {
+        match self {
+            Value::Object(_, ObjectKind::Array(ary)) => ary.join(","),
+            _ => Ok(self.to_string()),
+        }
+    }
 
-            match s.parse::<f64>() {
-                Ok(n) => n,
-                _ => ::std::f64::NAN,
-            }
+    pub fn to_js_string(&self) -> JsString {
+        match self {
+            Value::String(s) => (**s).clone(),
+            _ => JsString::from(self.to_string()),
         }
+    }
 
+    // TODO: Need a correct implementation!
+    pub fn to_number(&self) -> f64 {
         fn ary_to_num(ary: &ArrayValue) -> f64 {
             match ary.length {
                 0 => 0.0,
                 // TODO: FIX!!!
-                1 => match ary.elems[0].val {
+                1 => match ary.get_value(0) {
                     Value::Bool(_) => ::std::f64::NAN,
+                    Value::Undefined => 0.0,
                     ref otherwise => otherwise.to_number(),
                 },
                 _ => ::std::f64::NAN,
@@ -507,8 +1080,10 @@ impl Value {
             Value::Bool(false) => 0.0,
             Value::Bool(true) => 1.0,
             Value::Number(n) => *n,
-            Value::String(s) => str_to_num(s.to_str().unwrap()),
+            Value::BigInt(n) => n.to_f64().unwrap_or(::std::f64::NAN),
+            Value::String(s) => str_to_number(&s.to_string()),
             Value::Object(_, ObjectKind::Array(ary)) => ary_to_num(&*ary),
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => primitive.to_number(),
             _ => ::std::f64::NAN,
         }
     }
@@ -535,6 +1110,20 @@ impl Value {
         }
     }
 
+    /// https://tc39.github.io/ecma262/#sec-toint32
+    pub fn to_int32(&self) -> i32 {
+        self.to_uint32() as u32 as i32
+    }
+
+    /// https://tc39.github.io/ecma262/#sec-toprimitive
+    // TODO: Call valueOf() or toString() of ordinary objects.
+    pub fn to_primitive(self) -> Value {
+        match self {
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => primitive,
+            other => other,
+        }
+    }
+
     // TODO: Need a correct implementation!
     pub fn to_boolean(&self) -> bool {
         match self {
@@ -542,7 +1131,8 @@ impl Value {
             Value::Bool(b) => *b,
             Value::Number(n) if *n == 0.0 || n.is_nan() => false,
             Value::Number(_) => true,
-            Value::String(s) if s.to_str().unwrap().len() == 0 => false,
+            Value::BigInt(n) => !n.is_zero(),
+            Value::String(s) if s.is_empty() => false,
             Value::String(_) => true,
             Value::Object(_, _) => true,
             _ => false,
@@ -561,22 +1151,34 @@ impl Value {
             Value::Undefined | Value::Bool(_) | Value::Number(_) | Value::Null | Value::Empty => {
                 self.to_string()
             }
+            Value::BigInt(_) => format!("{}n", self.to_string()),
             Value::String(_) => format!("'{}'", self.to_string()),
             Value::Object(_, ObjectKind::Array(aryval)) => match depth {
                 0 => "[Array]".to_string(),
                 depth => {
-                    //let aryval = &*aryval;
-                    let str = aryval.elems[0..aryval.length]
-                        .iter()
-                        .fold("".to_string(), |acc, prop| {
-                            acc + prop.val.format_(max_depth, depth - 1, indent).as_str() + ","
-                        })
-                        .trim_right_matches(",")
-                        .to_string();
-                    format!("[{}]", str)
+                    // A run of holes is shown as one item, so a sparse array of a huge length
+                    // is not walked through index by index.
+                    let holes = |n: usize| match n {
+                        1 => "empty".to_string(),
+                        n => format!("<{} empty items>", n),
+                    };
+                    let mut items = vec![];
+                    let mut next = 0;
+                    for (n, prop) in aryval.elements() {
+                        if n > next {
+                            items.push(holes(n - next));
+                        }
+                        items.push(prop.val.format_(max_depth, depth - 1, indent));
+                        next = n + 1;
+                    }
+                    if aryval.length > next {
+                        items.push(holes(aryval.length - next));
+                    }
+                    format!("[{}]", items.join(","))
                 }
             },
-            Value::Object(map, ObjectKind::Ordinary) => match depth {
+            Value::Object(map, ObjectKind::Ordinary)
+            | Value::Object(map, ObjectKind::Response(_)) => match depth {
                 0 => "[Object]".to_string(),
                 depth => {
                     let cr = |i: usize| {
@@ -590,17 +1192,13 @@ impl Value {
                     let str = map
                         .iter()
                         .fold("".to_string(), |acc, nvp| {
-                            if nvp.0.as_str() == "__proto__" {
-                                acc
-                            } else {
-                                format!(
-                                    "{}{}{}:{},",
-                                    acc,
-                                    cr(1),
-                                    nvp.0,
-                                    nvp.1.val.format_(max_depth, depth - 1, indent)
-                                )
-                            }
+                            format!(
+                                "{}{}{}:{},",
+                                acc,
+                                cr(1),
+                                nvp.0,
+                                nvp.1.val.format_(max_depth, depth - 1, indent)
+                            )
                         })
                         .trim_right_matches(",")
                         .to_string();
@@ -611,6 +1209,29 @@ impl Value {
             Value::Object(_, ObjectKind::Function(_)) => "[Function]".to_string(),
             Value::Object(_, ObjectKind::BuiltinFunction(_)) => "[BuiltinFunc]".to_string(),
             Value::Object(_, ObjectKind::Arguments(_)) => "arguments".to_string(),
+            Value::Object(_, ObjectKind::TypedArray(ta)) => match depth {
+                0 => format!("[{}]", ta.kind.name()),
+                _ => format!("{} [{}]", ta.kind.name(), ta.to_string()),
+            },
+            Value::Object(_, ObjectKind::ArrayBuffer(buffer)) => {
+                format!("ArrayBuffer {{ byteLength: {} }}", buffer.data.len())
+            }
+            Value::Object(_, ObjectKind::DataView(view)) => format!(
+                "DataView {{ byteLength: {}, byteOffset: {} }}",
+                view.byte_length, view.byte_offset
+            ),
+            Value::Object(_, ObjectKind::WeakTable(table)) => {
+                format!("{} {{ <items unknown> }}", table.kind.name())
+            }
+            Value::Object(_, ObjectKind::PrimitiveWrapper(box primitive)) => format!(
+                "[{}: {}]",
+                match primitive {
+                    Value::Number(_) => "Number",
+                    Value::String(_) => "String",
+                    _ => "Boolean",
+                },
+                primitive.format_(max_depth, depth, indent)
+            ),
         }
     }
 }
@@ -623,6 +1244,7 @@ impl Value {
             | (Value::Undefined, Value::Undefined)
             | (Value::Bool(_), Value::Bool(_))
             | (Value::Number(_), Value::Number(_))
+            | (Value::BigInt(_), Value::BigInt(_))
             | (Value::String(_), Value::String(_))
             | (Value::Object(_, ObjectKind::Ordinary), Value::Object(_, ObjectKind::Ordinary))
             | (
@@ -637,6 +1259,30 @@ impl Value {
             | (
                 Value::Object(_, ObjectKind::Arguments(_)),
                 Value::Object(_, ObjectKind::Arguments(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::PrimitiveWrapper(_)),
+                Value::Object(_, ObjectKind::PrimitiveWrapper(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::ArrayBuffer(_)),
+                Value::Object(_, ObjectKind::ArrayBuffer(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::TypedArray(_)),
+                Value::Object(_, ObjectKind::TypedArray(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::DataView(_)),
+                Value::Object(_, ObjectKind::DataView(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::WeakTable(_)),
+                Value::Object(_, ObjectKind::WeakTable(_)),
+            )
+            | (
+                Value::Object(_, ObjectKind::Response(_)),
+                Value::Object(_, ObjectKind::Response(_)),
             ) => true,
             _ => false,
         }
@@ -651,14 +1297,24 @@ impl Value {
             (&Value::Null, &Value::Undefined) | (&Value::Undefined, &Value::Null) => Ok(true),
             (&Value::Number(l), &Value::String(_)) => Ok(l == other.to_number()),
             (&Value::String(_), &Value::Number(r)) => Ok(self.to_number() == r),
+            (&Value::BigInt(ref l), &Value::Number(_))
+            | (&Value::BigInt(ref l), &Value::String(_)) => {
+                Ok(compare_bigint(l, &other) == Some(Ordering::Equal))
+            }
+            (&Value::Number(_), &Value::BigInt(ref r))
+            | (&Value::String(_), &Value::BigInt(ref r)) => {
+                Ok(compare_bigint(r, &self) == Some(Ordering::Equal))
+            }
             (&Value::Bool(_), _) => Ok(Value::Number(self.to_number()).abstract_equal(other)?),
             (_, &Value::Bool(_)) => Ok(Value::Number(other.to_number()).abstract_equal(self)?),
             (&Value::String(_), &Value::Object(_, _))
-            | (&Value::Number(_), &Value::Object(_, _)) => {
+            | (&Value::Number(_), &Value::Object(_, _))
+            | (&Value::BigInt(_), &Value::Object(_, _)) => {
                 Ok(self.abstract_equal(Value::string(other.to_string()))?)
             }
             (&Value::Object(_, _), &Value::String(_))
-            | (&Value::Object(_, _), &Value::Number(_)) => {
+            | (&Value::Object(_, _), &Value::Number(_))
+            | (&Value::Object(_, _), &Value::BigInt(_)) => {
                 Ok(Value::string(self.to_string()).abstract_equal(other)?)
             }
             // TODO: Implement the following cases:
@@ -679,6 +1335,7 @@ impl Value {
             (Value::Bool(l), Value::Bool(r)) => Ok(l == r),
             (Value::Number(l), Value::Number(r)) if l.is_nan() || r.is_nan() => Ok(false),
             (Value::Number(l), Value::Number(r)) => Ok(l == r),
+            (Value::BigInt(l), Value::BigInt(r)) => Ok(l == r),
             (Value::String(l), Value::String(r)) => Ok(l == r),
             (Value::Object(l, ObjectKind::Ordinary), Value::Object(r, ObjectKind::Ordinary)) => {
                 Ok(l == r)
@@ -694,6 +1351,30 @@ impl Value {
             (Value::Object(_, ObjectKind::Array(l)), Value::Object(_, ObjectKind::Array(r))) => {
                 Ok(l == r)
             }
+            (
+                Value::Object(l, ObjectKind::PrimitiveWrapper(_)),
+                Value::Object(r, ObjectKind::PrimitiveWrapper(_)),
+            )
+            | (
+                Value::Object(l, ObjectKind::ArrayBuffer(_)),
+                Value::Object(r, ObjectKind::ArrayBuffer(_)),
+            )
+            | (
+                Value::Object(l, ObjectKind::TypedArray(_)),
+                Value::Object(r, ObjectKind::TypedArray(_)),
+            )
+            | (
+                Value::Object(l, ObjectKind::DataView(_)),
+                Value::Object(r, ObjectKind::DataView(_)),
+            )
+            | (
+                Value::Object(l, ObjectKind::WeakTable(_)),
+                Value::Object(r, ObjectKind::WeakTable(_)),
+            )
+            | (
+                Value::Object(l, ObjectKind::Response(_)),
+                Value::Object(r, ObjectKind::Response(_)),
+            ) => Ok(l == r),
             (
                 Value::Object(_, ObjectKind::Arguments(_)),
                 Value::Object(_, ObjectKind::Arguments(_)),
@@ -710,23 +1391,228 @@ impl ArrayValue {
         let len = arr.len();
         ArrayValue {
             elems: arr.iter().map(|x| x.to_property()).collect(),
+            sparse: BTreeMap::new(),
             length: len,
         }
     }
 
-    pub fn to_string(&self) -> String {
-        self.elems[0..self.length]
+    /// the element at 'n'. None if it is a hole or 'n' is not less than the length.
+    pub fn get(&self, n: usize) -> Option<&Property> {
+        let prop = if n < self.elems.len() {
+            &self.elems[n]
+        } else {
+            self.sparse.get(&n)?
+        };
+        match prop.val {
+            Value::Empty if prop.accessor.is_none() => None,
+            _ => Some(prop),
+        }
+    }
+
+    /// the value of the element at 'n'. Undefined for a hole.
+    pub fn get_value(&self, n: usize) -> Value {
+        self.get(n)
+            .map_or(Value::Undefined, |prop| prop.val.clone())
+    }
+
+    /// the elements and their indices in ascending order, skipping holes. Only the indices in use
+    /// are visited, so a sparse array of a huge length is cheap to walk through.
+    pub fn elements<'a>(&'a self) -> impl Iterator<Item = (usize, &'a Property)> + 'a {
+        self.elems
             .iter()
-            .fold("".to_string(), |acc, prop| {
-                acc + prop.val.to_string().as_str() + ","
+            .enumerate()
+            .chain(self.sparse.iter().map(|(&n, prop)| (n, prop)))
+            .filter(|(_, prop)| match prop.val {
+                Value::Empty => prop.accessor.is_some(),
+                _ => true,
             })
-            .trim_right_matches(",")
-            .to_string()
+    }
+
+    /// the index of the first element at or after 'n', skipping holes.
+    pub fn next_index(&self, n: usize) -> Option<usize> {
+        (n..self.elems.len())
+            .find(|&i| self.get(i).is_some())
+            .or_else(|| {
+                self.sparse
+                    .range(n..)
+                    .map(|(&i, _)| i)
+                    .find(|&i| self.get(i).is_some())
+            })
+    }
+
+    /// the values of the elements. Undefined for holes. Throws a RangeError if the length is over
+    /// MAX_LIST_LENGTH.
+    pub fn values(&self) -> Result<Vec<Value>, RuntimeError> {
+        if self.length > MAX_LIST_LENGTH {
+            return Err(RuntimeError::Exception(::builtins::error::new_error(
+                "RangeError: Too many elements passed to a list".to_string(),
+            )));
+        }
+        let mut values = vec![Value::Undefined; self.length];
+        for (n, prop) in self.elements() {
+            values[n] = prop.val.clone();
+        }
+        Ok(values)
+    }
+
+    /// The elements converted to strings and joined with 'sep'. Holes are empty. Throws a
+    /// RangeError if the result would be longer than MAX_STRING_LENGTH.
+    pub fn join(&self, sep: &str) -> Result<String, RuntimeError> {
+        let too_long = || {
+            RuntimeError::Exception(::builtins::error::new_error(
+                "RangeError: Invalid string length".to_string(),
+            ))
+        };
+        let seps = self.length.saturating_sub(1);
+        if seps.saturating_mul(sep.len()) > MAX_STRING_LENGTH {
+            return Err(too_long());
+        }
+        let mut joined = String::new();
+        let mut pushed_seps = 0;
+        for (n, prop) in self.elements() {
+            for _ in pushed_seps..n {
+                joined.push_str(sep);
+            }
+            pushed_seps = n;
+            joined.push_str(&prop.val.try_to_string()?);
+            if joined.len() > MAX_STRING_LENGTH {
+                return Err(too_long());
+            }
+        }
+        for _ in pushed_seps..seps {
+            joined.push_str(sep);
+        }
+        Ok(joined)
+    }
+
+    /// Set the element at 'n', extending the length if needed.
+    pub fn set(&mut self, n: usize, val: Value) {
+        if let Value::Object(_, _) = val {
+            gc::write_barrier(self);
+        }
+        if n >= self.length {
+            self.length = n + 1;
+        }
+        if n < self.elems.len() {
+            self.elems[n] = val.to_property();
+        } else if n <= self.elems.len() + MAX_DENSE_GAP {
+            self.elems.resize(n + 1, Value::empty().to_property());
+            self.elems[n] = val.to_property();
+            // Elements which were far from the end are not any more.
+            if !self.sparse.is_empty() {
+                let far = self.sparse.split_off(&self.elems.len());
+                for (i, prop) in ::std::mem::replace(&mut self.sparse, far) {
+                    self.elems[i] = prop;
+                }
+            }
+        } else {
+            self.sparse.insert(n, val.to_property());
+        }
+    }
+
+    /// Make the element at 'n' a hole. The length does not change.
+    pub fn delete(&mut self, n: usize) {
+        if n < self.elems.len() {
+            self.elems[n] = Value::empty().to_property();
+        } else {
+            self.sparse.remove(&n);
+        }
+    }
+
+    /// Change the length. The elements at or beyond the new length are deleted.
+    pub fn set_length(&mut self, length: usize) {
+        if length < self.elems.len() {
+            self.elems.truncate(length);
+        }
+        self.sparse.retain(|&n, _| n < length);
+        self.length = length;
     }
 
     pub fn push(&mut self, val: Value) {
-        self.elems.push(Property::new(val));
-        self.length += 1;
+        if let Value::Object(_, _) = val {
+            gc::write_barrier(self);
+        }
+        if self.elems.len() == self.length {
+            self.elems.push(Property::new(val));
+            self.length += 1;
+        } else {
+            let length = self.length;
+            self.set(length, val);
+        }
+    }
+
+    /// Remove the last element and return its value. Undefined if the array is empty.
+    pub fn pop(&mut self) -> Value {
+        if self.length == 0 {
+            return Value::Undefined;
+        }
+        let val = self.get_value(self.length - 1);
+        let length = self.length - 1;
+        self.set_length(length);
+        val
+    }
+
+    /// The elements joined with commas. An array too long to be joined is an empty string, so use
+    /// join() where the RangeError can be thrown.
+    pub fn to_string(&self) -> String {
+        self.join(",").unwrap_or_default()
+    }
+}
+
+/// The index of an array which 'key' stands for, if any. Other keys are ordinary properties.
+/// https://tc39.github.io/ecma262/#array-index
+pub fn array_index(key: &Value) -> Option<usize> {
+    match key {
+        Value::Number(n) if is_integer(*n) && *n >= 0.0 && *n < MAX_ARRAY_LENGTH => {
+            Some(*n as usize)
+        }
+        Value::String(s) => {
+            let n = key.to_uint32();
+            if n < MAX_ARRAY_LENGTH && **s == Value::Number(n).to_string().as_str() {
+                Some(n as usize)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+impl TypedArray {
+    pub fn byte_length(&self) -> usize {
+        self.length * self.kind.size()
+    }
+
+    /// get the element at 'index'. Undefined if 'index' is not a valid index.
+    pub fn get(&self, index: f64) -> Value {
+        if !is_integer(index) || index < 0.0 || index >= self.length as f64 {
+            return Value::Undefined;
+        }
+        let start = self.byte_offset + index as usize * self.kind.size();
+        Value::Number(
+            self.kind
+                .read(&self.buffer.data[start..], cfg!(target_endian = "little")),
+        )
+    }
+
+    /// set the element at 'index'. Does nothing if 'index' is not a valid index.
+    pub fn set(&mut self, index: f64, n: f64) {
+        if !is_integer(index) || index < 0.0 || index >= self.length as f64 {
+            return;
+        }
+        let start = self.byte_offset + index as usize * self.kind.size();
+        self.kind.write(
+            &mut self.buffer.data[start..],
+            n,
+            cfg!(target_endian = "little"),
+        );
+    }
+
+    pub fn to_string(&self) -> String {
+        (0..self.length)
+            .map(|i| self.get(i as f64).to_string())
+            .collect::<Vec<String>>()
+            .join(",")
     }
 }
 
@@ -737,64 +1623,185 @@ fn is_integer(f: f64) -> bool {
     f - f.floor() == 0.0
 }
 
+/// WhiteSpace and LineTerminator. https://tc39.github.io/ecma262/#sec-white-space
+pub fn is_js_whitespace(c: char) -> bool {
+    c.is_whitespace() || c == '\u{FEFF}'
+}
+
+/// https://tc39.github.io/ecma262/#sec-tonumber-applied-to-the-string-type
+pub fn str_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+
+    if s.is_empty() {
+        return 0.0;
+    }
+
+    let radix = match s.get(0..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => 10,
+    };
+
+    if radix != 10 {
+        let digits = &s[2..];
+        if digits.is_empty() {
+            return ::std::f64::NAN;
+        }
+        return digits.chars().fold(0.0, |acc, c| match c.to_digit(radix) {
+            Some(d) => acc * radix as f64 + d as f64,
+            None => ::std::f64::NAN,
+        });
+    }
+
+    match s {
+        "Infinity" | "+Infinity" => return ::std::f64::INFINITY,
+        "-Infinity" => return ::std::f64::NEG_INFINITY,
+        _ => {}
+    }
+
+    // str::parse accepts "inf" and "NaN", which are not StrDecimalLiteral.
+    if !s.chars().all(|c| match c {
+        '0'...'9' | '.' | 'e' | 'E' | '+' | '-' => true,
+        _ => false,
+    }) {
+        return ::std::f64::NAN;
+    }
+
+    match s.parse::<f64>() {
+        Ok(n) => n,
+        _ => ::std::f64::NAN,
+    }
+}
+
+/// https://tc39.github.io/ecma262/#sec-stringtobigint
+pub fn str_to_bigint(s: &str) -> Option<BigInt> {
+    let s = s.trim_matches(is_js_whitespace);
+
+    if s.is_empty() {
+        return Some(BigInt::zero());
+    }
+
+    let (radix, digits) = match s.get(0..2) {
+        Some("0x") | Some("0X") => (16, &s[2..]),
+        Some("0o") | Some("0O") => (8, &s[2..]),
+        Some("0b") | Some("0B") => (2, &s[2..]),
+        // Only decimal literals can have a sign.
+        _ => (10, s.trim_left_matches(|c| c == '+' || c == '-')),
+    };
+
+    // BigInt::parse_bytes also accepts '_' as a separator, which is not allowed here.
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    let n = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+    Some(if s.starts_with('-') { -n } else { n })
+}
+
+/// Compare a BigInt with a BigInt, Number or String mathematically.
+/// Returns None if the comparison is undefined (e.g. with NaN or a non-integer string).
+pub fn compare_bigint(l: &BigInt, r: &Value) -> Option<Ordering> {
+    match r {
+        Value::BigInt(r) => Some(l.cmp(r)),
+        Value::String(r) => Some(l.cmp(&str_to_bigint(&r.to_string())?)),
+        Value::Bool(_) => compare_bigint(l, &Value::Number(r.to_number())),
+        Value::Number(r) if r.is_nan() => None,
+        Value::Number(r) if r.is_infinite() => Some(if *r > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }),
+        Value::Number(r) => {
+            let trunc = r.trunc();
+            match l.cmp(&BigInt::from_f64(trunc).unwrap()) {
+                // l is an integer, so the fraction part of r decides.
+                Ordering::Equal => trunc.partial_cmp(r),
+                ord => Some(ord),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// https://tc39.github.io/ecma262/#sec-tostring-applied-to-the-number-type
+pub fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    }
+
+    // +0 and -0
+    if n == 0.0 {
+        return "0".to_string();
+    }
+
+    if n < 0.0 {
+        return format!("-{}", number_to_string(-n));
+    }
+
+    if n.is_infinite() {
+        return "Infinity".to_string();
+    }
+
+    // Rust's LowerExp prints the shortest digits that round-trip, e.g. "1.2345e-7".
+    let exp_repr = format!("{:e}", n);
+    let (mantissa, exponent) = exp_repr.split_at(exp_repr.find('e').unwrap());
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent[1..].parse::<i32>().unwrap() + 1;
+
+    if k <= n && n <= 21 {
+        digits + "0".repeat((n - k) as usize).as_str()
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{}.{}", int, frac)
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let sign = if n - 1 < 0 { "-" } else { "+" };
+        let (first, rest) = digits.split_at(1);
+        if rest.is_empty() {
+            format!("{}e{}{}", first, sign, (n - 1).abs())
+        } else {
+            format!("{}.{}e{}{}", first, rest, sign, (n - 1).abs())
+        }
+    }
+}
+
 ///
 /// get <key> property of <val> object.
 /// if the property does not exists, trace the prototype chain.
 /// return Value::Undefined for primitives.
-/// handle as BuiltinFunction.__proto__ === FUNCTION_PROTOTYPE
+/// __proto__ is read from [[Prototype]] (see Value::prototype()).
 ///
 pub fn obj_find_val(val: Value, key: &str) -> Value {
-    let (map, is_builtin_func) = match val {
-        Value::Object(map, ObjectKind::BuiltinFunction(_)) => (map, true),
-        Value::Object(map, _) => (map, false),
+    if key == "__proto__" {
+        return val.prototype();
+    }
+    let map = match val {
+        Value::Object(ref map, _) => map.clone(),
         _ => return Value::Undefined,
     };
     match map.get(key) {
         Some(prop) => prop.val.clone(),
-        None if is_builtin_func && key == "__proto__" => {
-            return function::FUNCTION_PROTOTYPE.with(|x| x.clone());
-        }
-        None => match map.get("__proto__") {
-            Some(prop) => obj_find_val(prop.val.clone(), key),
-            None if is_builtin_func => {
-                obj_find_val(function::FUNCTION_PROTOTYPE.with(|x| x.clone()), key)
-            }
-            _ => return Value::Undefined,
+        None => match val.prototype() {
+            Value::Null => Value::Undefined,
+            proto => obj_find_val(proto, key),
         },
     }
 }
 
-///
-/// if val is Function or BuiltinFunction, clone val and set this for callobj.this.
-/// otherwise, do nothing.
-///
-pub fn set_this(val: Value, this: &Value) -> Value {
-    match val.clone() {
-        Value::Object(
-            map,
-            ObjectKind::Function(box (
-                FuncInfo {
-                    id, iseq, params, ..
-                },
-                callobj,
-            )),
-        ) => Value::Object(
-            map,
-            ObjectKind::Function(Box::new((FuncInfo::new(id, iseq, params), {
-                let co = CallObject {
-                    this: Box::new(this.clone()),
-                    ..(*callobj).clone()
-                };
-                gc::new(co)
-            }))),
-        ),
-        Value::Object(map, ObjectKind::BuiltinFunction(box (id, mut callobj))) => Value::Object(
-            map,
-            ObjectKind::BuiltinFunction(Box::new((id, {
-                *callobj.this = this.clone();
-                callobj
-            }))),
-        ),
-        val => val,
+/// find the accessor property 'key' of 'val', following the prototype chain.
+pub fn obj_find_accessor(val: Value, key: &str) -> Option<Accessor> {
+    let map = match val {
+        Value::Object(map, _) => map,
+        _ => return None,
+    };
+    match map.get(key) {
+        Some(prop) => prop.accessor.clone().map(|accessor| *accessor),
+        None => match map.proto() {
+            proto @ Value::Object(_, _) => obj_find_accessor(proto, key),
+            _ => None,
+        },
     }
 }
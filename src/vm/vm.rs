@@ -1,42 +1,304 @@
 use chrono::Utc;
+#[cfg(feature = "jit")]
 use libc;
+#[cfg(feature = "jit")]
 use llvm::core::*;
-use std::{ffi::CString, thread, time};
+#[cfg(feature = "jit")]
+use std::ffi::CString;
+use std::{
+    cmp::Ordering,
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc,
+    },
+    thread, time,
+};
 
 use super::{
     callobj::CallObject,
+    debugger::Debugger,
     error::*,
-    task::{Task, TaskManager, TimerKind},
+    inline_cache::{GlobalCache, InlineCache},
+    jsstring::JsString,
+    profiler::Profiler,
+    task::{Job, JobQueue, Task, TaskManager, TimerKind},
+    tracer::Tracer,
+    upvalue::{Upvalue, UpvalueSource},
     value::*,
 };
 
+use atom::Atom;
 use builtin;
+#[cfg(feature = "jit")]
 use builtin::BuiltinJITFuncInfo;
 use builtins;
+use builtins::bigint;
 use bytecode_gen;
-use bytecode_gen::ByteCode;
+use bytecode_gen::{ByteCode, Inst, VMInst};
+use engine::NativeFunction;
 use gc;
+#[cfg(feature = "jit")]
 use jit::TracingJit;
+use sourcemap::{SourceMap, StackFrame};
 use vm_codegen;
 
 pub struct VM {
+    #[cfg(feature = "jit")]
     pub jit: TracingJit,
     pub state: VMState,
     pub trystate_stack: Vec<TryState>,
     pub cur_func_id: FuncId, // id == 0: main
-    pub op_table: [fn(&mut VM, &ByteCode) -> Result<bool, RuntimeError>; 59],
     pub task_mgr: TaskManager,
     pub is_debug: bool,
     pub jit_on: bool,
     pub gc_on: bool,
+    /// log each collection to stderr ('rapidus --trace-gc').
+    pub trace_gc: bool,
     pub codegen: vm_codegen::VMCodeGen,
+    /// inline caches of GET_MEMBER and SET_MEMBER. Indexed by the operand of the instructions.
+    pub inline_caches: Vec<InlineCache>,
+    /// caches of GET_GLOBAL, indexed in the same way as inline_caches.
+    pub global_caches: Vec<GlobalCache>,
+    /// functions registered by embedders. See Engine::register_fn().
+    pub native_functions: Vec<NativeFunction>,
+    /// limits of the resources used by scripts.
+    pub options: VMOptions,
+    /// instructions executed since the last VM::run(). See VMOptions::max_instructions.
+    pub instruction_count: u64,
+    /// set by another thread to stop the running script. The run loop checks it every
+    /// TERMINATE_CHECK_INTERVAL instructions and clears it when it stops the script.
+    pub terminate_flag: Arc<AtomicBool>,
+    /// source map of the running script, which locates errors.
+    pub source_map: Option<SourceMap>,
+    /// the frames where the error being thrown occurred, from the innermost one. Recorded only
+    /// with a source map, and cleared when the error is caught.
+    pub error_trace: Option<Vec<StackFrame>>,
+    /// the step debugger, which stops the script before instructions. See debugger.rs.
+    pub debugger: Option<Debugger>,
+    /// values the debugger has shown to its client, which keeps them alive. See inspector.rs.
+    pub inspected_values: Vec<Value>,
+    /// the CPU profiler, which samples the frames while it is set. See profiler.rs.
+    pub profiler: Option<Profiler>,
+    /// the instruction tracer, which writes each instruction before it runs. See tracer.rs.
+    pub tracer: Option<Tracer>,
+    /// call objects left by calls which do not capture their scope, to be reused by later calls
+    /// instead of making new ones. See FuncInfo::captures_scope.
+    pub callobj_pool: Vec<CallObjectRef>,
+    /// the call objects of the builtin functions running, made for each call to hold its
+    /// 'this'. Only the GC refers to them. See VM::call_function_with_this().
+    pub builtin_callobjs: Vec<CallObjectRef>,
+    /// the objects the builtin functions running hold: their arguments and what they root with
+    /// VM::root(). A builtin may call back into scripts, which may run GC. Only the GC refers to
+    /// them.
+    pub builtin_roots: Vec<Value>,
+    /// the upvalues which refer to registers of the frames running, in the order of the
+    /// frames. See upvalue.rs.
+    pub open_upvalues: Vec<UpvalueRef>,
+    /// promises rejected without handlers, reported by the event loop unless handlers are added
+    /// before it runs next. See builtins::process::emit_unhandled_rejections().
+    pub unhandled_rejections: Vec<Value>,
+    /// the state of the xorshift generator of Math.random(). Each VM has its own, so that
+    /// seeding one does not change the numbers of the others. See Engine::set_random_seed().
+    pub random_state: u64,
+}
+
+/// Limits of the resources used by scripts, for embedders which run untrusted code. Exceeding a
+/// limit throws a RangeError, which scripts can catch and which otherwise comes out of VM::run()
+/// as an error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VMOptions {
+    /// the maximum number of frames. Calling a function beyond it throws a RangeError.
+    pub max_stack_frames: usize,
+    /// the maximum size of the heap: the objects managed by GC and what they own, such as
+    /// elements and strings (see gc::heap_bytes()). When it is exceeded, GC runs and a
+    /// RangeError is thrown if it is still exceeded.
+    pub max_heap_bytes: Option<usize>,
+    /// the maximum number of instructions executed by a VM::run(), including the functions
+    /// called by the event loop. Once it is exceeded every instruction throws a RangeError, so
+    /// that a script cannot keep running by catching it. JIT-compiled code is not counted.
+    pub max_instructions: Option<u64>,
+}
+
+/// Default of VMOptions::max_stack_frames.
+pub const DEFAULT_MAX_STACK_FRAMES: usize = 10000;
+
+/// instructions between checks of VMOptions::max_heap_bytes in the run loop. Instructions
+/// making objects and arrays check it every time.
+const HEAP_CHECK_INTERVAL: u64 = 1024;
+
+/// instructions between checks of VM::terminate_flag in the run loop.
+const TERMINATE_CHECK_INTERVAL: u64 = 1024;
+
+/// the maximum number of call objects kept in VM::callobj_pool.
+const CALLOBJ_POOL_SIZE: usize = 64;
+
+impl Default for VMOptions {
+    fn default() -> VMOptions {
+        VMOptions {
+            max_stack_frames: DEFAULT_MAX_STACK_FRAMES,
+            max_heap_bytes: None,
+            max_instructions: None,
+        }
+    }
+}
+
+/// Make 'console.log' and 'process.stdout.write' together with the LLVM functions which
+/// replace them in JIT-compiled code.
+#[cfg(feature = "jit")]
+fn jit_builtin_functions(jit: &TracingJit) -> (Value, Value) {
+    let console_log = Value::builtin_function_with_jit(
+        builtin::console_log,
+        BuiltinJITFuncInfo::ConsoleLog {
+            bool: (builtin::jit_console_log_bool as *mut libc::c_void, unsafe {
+                LLVMAddFunction(
+                    jit.module,
+                    CString::new("jit_console_log_bool").unwrap().as_ptr(),
+                    LLVMFunctionType(
+                        LLVMVoidType(),
+                        vec![LLVMInt1TypeInContext(jit.context)]
+                            .as_mut_slice()
+                            .as_mut_ptr(),
+                        1,
+                        0,
+                    ),
+                )
+            }),
+            f64: (builtin::jit_console_log_f64 as *mut libc::c_void, unsafe {
+                LLVMAddFunction(
+                    jit.module,
+                    CString::new("jit_console_log_f64").unwrap().as_ptr(),
+                    LLVMFunctionType(
+                        LLVMVoidType(),
+                        vec![LLVMDoubleTypeInContext(jit.context)]
+                            .as_mut_slice()
+                            .as_mut_ptr(),
+                        1,
+                        0,
+                    ),
+                )
+            }),
+            string: (
+                builtin::jit_console_log_string as *mut libc::c_void,
+                unsafe {
+                    LLVMAddFunction(
+                        jit.module,
+                        CString::new("jit_console_log_string").unwrap().as_ptr(),
+                        LLVMFunctionType(
+                            LLVMVoidType(),
+                            vec![LLVMPointerType(LLVMInt8TypeInContext(jit.context), 0)]
+                                .as_mut_slice()
+                                .as_mut_ptr(),
+                            1,
+                            0,
+                        ),
+                    )
+                },
+            ),
+            newline: (
+                builtin::jit_console_log_newline as *mut libc::c_void,
+                unsafe {
+                    LLVMAddFunction(
+                        jit.module,
+                        CString::new("jit_console_log_newline").unwrap().as_ptr(),
+                        LLVMFunctionType(LLVMVoidType(), vec![].as_mut_ptr(), 0, 0),
+                    )
+                },
+            ),
+        },
+    );
+
+    let llvm_process_stdout_write = unsafe {
+        LLVMAddFunction(
+            jit.module,
+            CString::new("process_stdout_write").unwrap().as_ptr(),
+            LLVMFunctionType(
+                LLVMVoidType(),
+                vec![LLVMPointerType(LLVMInt8TypeInContext(jit.context), 0)]
+                    .as_mut_slice()
+                    .as_mut_ptr(),
+                1,
+                0,
+            ),
+        )
+    };
+
+    let process_stdout_write = Value::builtin_function_with_jit(
+        builtin::process_stdout_write,
+        BuiltinJITFuncInfo::Normal {
+            func: builtin::jit_process_stdout_write as *mut libc::c_void,
+            llvm_func: llvm_process_stdout_write,
+        },
+    );
+
+    (console_log, process_stdout_write)
 }
 
 pub struct VMState {
     pub stack: Vec<Value>,
     pub scope: Vec<CallObjectRef>,
     pub pc: isize,
-    pub history: Vec<(usize, isize)>, // sp, return_pc
+    /// code of the current frame.
+    pub iseq: Rc<ByteCode>,
+    /// the current frame and its callers. The last one is the current frame.
+    pub frames: Vec<Frame>,
+    /// registers of all frames. The current frame uses regs[reg_base..].
+    pub regs: Vec<Value>,
+    pub reg_base: usize,
+}
+
+impl VMState {
+    /// value of the register 'r' of the current frame.
+    #[inline]
+    pub fn reg(&self, r: usize) -> Value {
+        match self.regs.get(self.reg_base + r) {
+            Some(val) => val.clone(),
+            None => Value::Undefined,
+        }
+    }
+
+    #[inline]
+    pub fn set_reg(&mut self, r: usize, val: Value) {
+        let i = self.reg_base + r;
+        if i >= self.regs.len() {
+            self.regs.resize(i + 1, Value::Undefined);
+        }
+        self.regs[i] = val;
+    }
+}
+
+/// A frame of a running script or function. A call of a JS function pushes a frame and the
+/// run loop continues with the code of the function, instead of calling do_run() recursively.
+/// A frame keeps the state of the caller to be restored when the frame is left.
+///
+/// A frame left by RETURN or END leaves exactly one value, its result, on the stack of the
+/// caller: the value on the top if the frame pushed any, and undefined otherwise. A frame left
+/// by an error leaves nothing. Either way, the values below 'sp' are as they were when the frame
+/// was entered.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// stack length when the frame was entered.
+    pub sp: usize,
+    pub return_pc: isize,
+    pub return_iseq: Rc<ByteCode>,
+    pub reg_base: usize,
+    /// scope length when the frame was entered. The scopes pushed after it are left with the
+    /// frame, including the call object of the function.
+    pub scope_len: usize,
+    /// length of VM::trystate_stack when the frame was entered.
+    pub trystate_len: usize,
+    /// the object made for 'this' by 'new'. It is the result of 'new' unless the function
+    /// returns an object.
+    pub new_this: Option<Value>,
+    /// id of the running function. 0 for a script.
+    pub func_id: FuncId,
+    /// true if the frame was entered by do_run(), which returns to its Rust caller when the
+    /// frame is left.
+    pub is_entry: bool,
+    /// true if the call object of the frame goes back to VM::callobj_pool when the frame is
+    /// left. Cleared by VM::capture_scope().
+    pub reuse_callobj: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -59,8 +321,10 @@ impl TryReturn {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum TryState {
-    Try(isize, isize, TryReturn), //position of (CATCH, FINALLY)
-    Catch(isize, TryReturn),      //postion of (FINALLY)
+    // position of (CATCH, FINALLY), (scope length, stack length) at ENTER_TRY
+    Try(isize, isize, TryReturn, (usize, usize)),
+    // position of (FINALLY), (scope length, stack length) at ENTER_TRY
+    Catch(isize, TryReturn, (usize, usize)),
     Finally(TryReturn),
     None,
 }
@@ -69,8 +333,8 @@ impl TryState {
     pub fn to_string(&self) -> (String, String) {
         match &self {
             TryState::None => ("None".to_string(), "".to_string()),
-            TryState::Try(_, _, tryreturn) => ("Try".to_string(), tryreturn.to_string()),
-            TryState::Catch(_, tryreturn) => ("Catch".to_string(), tryreturn.to_string()),
+            TryState::Try(_, _, tryreturn, _) => ("Try".to_string(), tryreturn.to_string()),
+            TryState::Catch(_, tryreturn, _) => ("Catch".to_string(), tryreturn.to_string()),
             TryState::Finally(tryreturn) => ("Finally".to_string(), tryreturn.to_string()),
         }
     }
@@ -79,7 +343,8 @@ impl TryState {
 #[derive(Debug, Clone)]
 pub struct ConstantTable {
     pub value: Vec<Value>,
-    pub string: Vec<String>,
+    /// names of variables.
+    pub string: Vec<Atom>,
 }
 
 impl ConstantTable {
@@ -93,6 +358,7 @@ impl ConstantTable {
 
 impl VM {
     pub fn new() -> VM {
+        #[cfg(feature = "jit")]
         let jit = unsafe { TracingJit::new() };
         let mut global_vals = CallObject::new_global();
 
@@ -110,104 +376,25 @@ impl VM {
         });
         global_vals.set_value("exports".to_string(), module_exports);
 
-        global_vals.set_value("console".to_string(), {
-            let func_log = Value::builtin_function_with_jit(
-                builtin::console_log,
-                BuiltinJITFuncInfo::ConsoleLog {
-                    bool: (builtin::jit_console_log_bool as *mut libc::c_void, unsafe {
-                        LLVMAddFunction(
-                            jit.module,
-                            CString::new("jit_console_log_bool").unwrap().as_ptr(),
-                            LLVMFunctionType(
-                                LLVMVoidType(),
-                                vec![LLVMInt1TypeInContext(jit.context)]
-                                    .as_mut_slice()
-                                    .as_mut_ptr(),
-                                1,
-                                0,
-                            ),
-                        )
-                    }),
-                    f64: (builtin::jit_console_log_f64 as *mut libc::c_void, unsafe {
-                        LLVMAddFunction(
-                            jit.module,
-                            CString::new("jit_console_log_f64").unwrap().as_ptr(),
-                            LLVMFunctionType(
-                                LLVMVoidType(),
-                                vec![LLVMDoubleTypeInContext(jit.context)]
-                                    .as_mut_slice()
-                                    .as_mut_ptr(),
-                                1,
-                                0,
-                            ),
-                        )
-                    }),
-                    string: (
-                        builtin::jit_console_log_string as *mut libc::c_void,
-                        unsafe {
-                            LLVMAddFunction(
-                                jit.module,
-                                CString::new("jit_console_log_string").unwrap().as_ptr(),
-                                LLVMFunctionType(
-                                    LLVMVoidType(),
-                                    vec![LLVMPointerType(LLVMInt8TypeInContext(jit.context), 0)]
-                                        .as_mut_slice()
-                                        .as_mut_ptr(),
-                                    1,
-                                    0,
-                                ),
-                            )
-                        },
-                    ),
-                    newline: (
-                        builtin::jit_console_log_newline as *mut libc::c_void,
-                        unsafe {
-                            LLVMAddFunction(
-                                jit.module,
-                                CString::new("jit_console_log_newline").unwrap().as_ptr(),
-                                LLVMFunctionType(LLVMVoidType(), vec![].as_mut_ptr(), 0, 0),
-                            )
-                        },
-                    ),
-                },
-            );
-            let npp = make_npp!(log: func_log);
-            Value::object_from_npp(&npp)
-        });
+        #[cfg(feature = "jit")]
+        let (console_log, process_stdout_write) = jit_builtin_functions(&jit);
+        #[cfg(not(feature = "jit"))]
+        let (console_log, process_stdout_write) = (
+            Value::default_builtin_function(builtin::console_log),
+            Value::default_builtin_function(builtin::process_stdout_write),
+        );
 
-        let llvm_process_stdout_write = unsafe {
-            LLVMAddFunction(
-                jit.module,
-                CString::new("process_stdout_write").unwrap().as_ptr(),
-                LLVMFunctionType(
-                    LLVMVoidType(),
-                    vec![LLVMPointerType(LLVMInt8TypeInContext(jit.context), 0)]
-                        .as_mut_slice()
-                        .as_mut_ptr(),
-                    1,
-                    0,
-                ),
-            )
-        };
+        global_vals.set_value("console".to_string(), builtins::console::init(console_log));
 
         global_vals.set_value(
             "process".to_string(),
-            make_object!(
-                stdout:
-                    Value::object_from_npp(
-                        &make_npp!(
-                             write:  Value::builtin_function_with_jit(
-                                 builtin::process_stdout_write,
-                                 BuiltinJITFuncInfo::Normal {
-                                     func: builtin::jit_process_stdout_write as *mut libc::c_void,
-                                     llvm_func: llvm_process_stdout_write,
-                                 },
-                             )
-                         ),
-                    )
-            ),
+            builtins::process::init(process_stdout_write),
         );
 
+        global_vals.set_value("performance".to_string(), builtins::performance::init());
+
+        global_vals.set_value("crypto".to_string(), builtins::crypto::init());
+
         global_vals.set_value(
             "setTimeout".to_string(),
             Value::default_builtin_function(builtin::set_timeout),
@@ -228,6 +415,11 @@ impl VM {
             Value::default_builtin_function(builtin::clear_timer),
         );
 
+        global_vals.set_value(
+            "queueMicrotask".to_string(),
+            Value::default_builtin_function(builtin::queue_microtask),
+        );
+
         global_vals.set_value(
             "__enableJit".to_string(),
             Value::default_builtin_function(builtin::enable_jit),
@@ -240,11 +432,102 @@ impl VM {
 
         global_vals.set_value("Object".to_string(), builtins::object::init());
         global_vals.set_value("Error".to_string(), builtins::error::init());
+        global_vals.set_value("Promise".to_string(), builtins::promise::init());
+        global_vals.set_value(
+            "fetch".to_string(),
+            Value::default_builtin_function(builtins::fetch::fetch),
+        );
         global_vals.set_value("Function".to_string(), builtins::function::init());
         global_vals.set_value("Array".to_string(), builtins::array::init());
         use builtins::date::DATE_OBJ;
         global_vals.set_value("Date".to_string(), DATE_OBJ.with(|x| x.clone()));
-        global_vals.set_value("Math".to_string(), builtins::math::init(jit.clone()));
+        global_vals.set_value("Intl".to_string(), builtins::intl::init());
+        global_vals.set_value("Math".to_string(), {
+            #[cfg(feature = "jit")]
+            let math = builtins::math::init(jit.clone());
+            #[cfg(not(feature = "jit"))]
+            let math = builtins::math::init();
+            math
+        });
+        global_vals.set_value("Number".to_string(), builtins::number::init());
+        global_vals.set_value("String".to_string(), builtins::string::init());
+        global_vals.set_value("Boolean".to_string(), builtins::boolean::init());
+        global_vals.set_value("BigInt".to_string(), builtins::bigint::init());
+        global_vals.set_value("ArrayBuffer".to_string(), builtins::arraybuffer::init());
+        global_vals.set_value("DataView".to_string(), builtins::dataview::init());
+        for (name, typed_array) in builtins::typedarray::init() {
+            global_vals.set_value(name.to_string(), typed_array);
+        }
+        global_vals.set_value(
+            "parseInt".to_string(),
+            Value::default_builtin_function(builtins::number::parse_int),
+        );
+        global_vals.set_value(
+            "parseFloat".to_string(),
+            Value::default_builtin_function(builtins::number::parse_float),
+        );
+        global_vals.set_value(
+            "isNaN".to_string(),
+            Value::default_builtin_function(builtins::number::is_nan),
+        );
+        global_vals.set_value(
+            "isFinite".to_string(),
+            Value::default_builtin_function(builtins::number::is_finite),
+        );
+        global_vals.set_value(
+            "eval".to_string(),
+            Value::default_builtin_function(builtins::eval::eval),
+        );
+        global_vals.set_value("gc".to_string(), builtins::gc::init());
+        global_vals.set_value("WebAssembly".to_string(), builtins::wasm::init());
+        global_vals.set_value("Worker".to_string(), builtins::worker::init());
+        global_vals.set_value(
+            "structuredClone".to_string(),
+            Value::default_builtin_function(builtins::structured_clone::structured_clone),
+        );
+        for (name, func) in builtins::uri::init() {
+            global_vals.set_value(name.to_string(), func);
+        }
+        for (name, val) in builtins::text_encoding::init() {
+            global_vals.set_value(name.to_string(), val);
+        }
+        for (name, val) in builtins::weak::init() {
+            global_vals.set_value(name.to_string(), val);
+        }
+
+        // The global object, which is also 'this' at the top level. Its properties are the
+        // global variables.
+        let global_object = (*global_vals.this).clone();
+        global_vals
+            .vals
+            .set_proto(builtins::object::OBJECT_PROTOTYPE.with(|x| x.clone()));
+        // NaN, Infinity and undefined can be neither assigned nor redefined.
+        for (name, val) in vec![
+            ("NaN", Value::Number(::std::f64::NAN)),
+            ("Infinity", Value::Number(::std::f64::INFINITY)),
+            ("undefined", Value::Undefined),
+        ] {
+            global_vals.vals.insert(
+                name.to_string(),
+                Property {
+                    val: val,
+                    writable: false,
+                    enumerable: false,
+                    configurable: false,
+                    accessor: None,
+                },
+            );
+        }
+        global_vals.vals.insert(
+            "globalThis".to_string(),
+            Property {
+                val: global_object,
+                writable: true,
+                enumerable: false,
+                configurable: true,
+                accessor: None,
+            },
+        );
         /*
                 println!(
                     "CallObject:{} Value:{} PropMapRef:{} ArrayValue:{}",
@@ -255,81 +538,43 @@ impl VM {
                 );
         */
         VM {
+            #[cfg(feature = "jit")]
             jit: jit,
             state: VMState {
                 stack: { Vec::with_capacity(128) },
                 scope: vec![global_vals.clone()],
-                history: vec![(0, 0)],
                 pc: 0isize,
+                iseq: Rc::new(vec![]),
+                frames: vec![],
+                regs: vec![],
+                reg_base: 0,
             },
             trystate_stack: vec![TryState::None],
             cur_func_id: 0, // 0 is main
             task_mgr: TaskManager::new(),
             is_debug: false,
-            jit_on: true,
+            jit_on: cfg!(feature = "jit"),
             gc_on: true,
+            trace_gc: false,
             codegen: vm_codegen::VMCodeGen::new(global_vals.clone()),
-            op_table: [
-                end,
-                create_context,
-                construct,
-                create_object,
-                create_array,
-                push_int8,
-                push_int32,
-                push_false,
-                push_true,
-                push_const,
-                push_this,
-                push_arguments,
-                push_undefined,
-                lnot,
-                posi,
-                neg,
-                add,
-                sub,
-                mul,
-                div,
-                rem,
-                lt,
-                gt,
-                le,
-                ge,
-                eq,
-                ne,
-                seq,
-                sne,
-                and,
-                or,
-                xor,
-                shl,
-                shr,
-                zfshr,
-                get_member,
-                set_member,
-                jmp_if_false,
-                jmp,
-                call,
-                return_,
-                double,
-                pop,
-                land,
-                lor,
-                update_parent_scope,
-                get_value,
-                set_value,
-                decl_var,
-                cond_op,
-                loop_start,
-                throw,
-                enter_try,
-                leave_try,
-                catch,
-                finally,
-                return_try,
-                push_scope,
-                pop_scope,
-            ],
+            inline_caches: vec![],
+            global_caches: vec![],
+            native_functions: vec![],
+            options: VMOptions::default(),
+            instruction_count: 0,
+            terminate_flag: Arc::new(AtomicBool::new(false)),
+            source_map: None,
+            error_trace: None,
+            debugger: None,
+            inspected_values: vec![],
+            profiler: None,
+            tracer: None,
+            callobj_pool: vec![],
+            builtin_callobjs: vec![],
+            builtin_roots: vec![],
+            open_upvalues: vec![],
+            unhandled_rejections: vec![],
+            random_state: builtins::math::random_state(::rand::random()),
         }
     }
 }
@@ -348,17 +593,38 @@ impl VM {
                 "INST".to_string(),
             );
         }
-        //self.store_state();
-        let res = self.do_run(&iseq);
+        self.instruction_count = 0;
+        self.error_trace = None;
+        let res = self.do_run(Rc::new(iseq));
+        let mut res = self.handle_uncaught_error(res);
+        if res.is_ok() {
+            res = self.run_jobs_of_task();
+        }
 
         loop {
+            if res.is_ok() {
+                res = builtins::process::emit_unhandled_rejections(self).map(|()| true);
+            }
             if self.task_mgr.no_tasks() {
                 break;
             }
+            // Timers waiting for their time do not run instructions, so the flag is checked
+            // here too.
+            if res.is_ok() && self.check_terminated().is_err() {
+                res = Err(RuntimeError::Terminated);
+            }
+            if res.is_err() {
+                // A script which failed or was terminated does not leave tasks to later scripts.
+                self.task_mgr.clear();
+                break;
+            }
 
             let now = Utc::now().timestamp_millis();
 
             while let Some(task) = self.task_mgr.get_task() {
+                if res.is_err() {
+                    break;
+                }
                 match task {
                     Task::Timer {
                         ref callback,
@@ -370,8 +636,8 @@ impl VM {
                             },
                         ..
                     } if now - task_now > timeout => {
-                        self.call_function_simply(callback, args)?;
-                        self.state.stack.pop(); // return value is not used
+                        let callback_res = self.run_task_callback(callback, args);
+                        res = self.handle_uncaught_error(callback_res.map(|()| true));
                     }
                     Task::Timer {
                         id,
@@ -379,8 +645,8 @@ impl VM {
                         ref args,
                         kind: TimerKind::Interval { previous, interval },
                     } if now - previous > interval => {
-                        self.call_function_simply(callback, args)?;
-                        self.state.stack.pop(); // return value is not used
+                        let callback_res = self.run_task_callback(callback, args);
+                        res = self.handle_uncaught_error(callback_res.map(|()| true));
 
                         self.task_mgr.retain_task(Task::Timer {
                             id,
@@ -392,8 +658,25 @@ impl VM {
                             },
                         })
                     }
+                    Task::Fetch { promise, result } => {
+                        let response = result.lock().unwrap().take();
+                        match response {
+                            Some(response) => builtins::fetch::settle(self, &promise, response),
+                            None => self.task_mgr.retain_task(Task::Fetch {
+                                promise: promise,
+                                result: result,
+                            }),
+                        }
+                    }
+                    Task::Message { target, port } => {
+                        let deliver_res = builtins::worker::deliver_messages(self, target, port);
+                        res = self.handle_uncaught_error(deliver_res.map(|()| true));
+                    }
                     _ => self.task_mgr.retain_task(task),
                 }
+                if res.is_ok() {
+                    res = self.run_jobs_of_task();
+                }
             }
 
             thread::sleep(time::Duration::from_millis(1));
@@ -404,50 +687,443 @@ impl VM {
         res
     }
 
-    /// push vm.state.history
-    fn store_state(&mut self) {
-        self.state
-            .history
-            .push((self.state.stack.len(), self.state.pc));
+    /// Pass an error which the script did not catch to the 'uncaughtException' listeners of
+    /// process. The error is returned if there are none.
+    fn handle_uncaught_error(
+        &mut self,
+        res: Result<bool, RuntimeError>,
+    ) -> Result<bool, RuntimeError> {
+        match res {
+            Err(err) => builtins::process::emit_uncaught_exception(self, err, "uncaughtException")
+                .map(|()| true),
+            res => res,
+        }
+    }
+
+    /// Run the jobs (microtasks) until the queue is empty. Jobs enqueued by a job run too.
+    /// Stops at the first error, leaving the rest of the jobs in the queue.
+    pub fn run_jobs(&mut self) -> Result<(), RuntimeError> {
+        while let Some(Job { callback, args }) = self.task_mgr.dequeue_job() {
+            self.run_task_callback(&callback, &args)?;
+        }
+        Ok(())
+    }
+
+    /// Run the jobs enqueued by the script or the task which has just finished. An error of a
+    /// job is passed to the 'uncaughtException' listeners, and the rest of the jobs go on.
+    fn run_jobs_of_task(&mut self) -> Result<bool, RuntimeError> {
+        loop {
+            match self.run_jobs() {
+                Ok(()) => return Ok(true),
+                Err(err) => {
+                    self.handle_uncaught_error(Err(err))?;
+                }
+            }
+        }
+    }
+
+    /// Call the callback of a task run by the event loop.
+    fn run_task_callback(
+        &mut self,
+        callback: &Value,
+        args: &Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        match self.call_function_simply(callback, args) {
+            Ok(_) => {
+                self.state.stack.pop(); // return value is not used
+                Ok(())
+            }
+            Err(RuntimeError::Terminated) => {
+                self.task_mgr.clear();
+                Err(RuntimeError::Terminated)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Enter a frame which runs 'iseq'. 'scope_len' is the length of the scope to be restored
+    /// when the frame is left.
+    fn push_frame(
+        &mut self,
+        iseq: Rc<ByteCode>,
+        scope_len: usize,
+        new_this: Option<Value>,
+        func_id: FuncId,
+        is_entry: bool,
+    ) -> Result<(), RuntimeError> {
+        if self.state.frames.len() >= self.options.max_stack_frames {
+            return Err(RuntimeError::Exception(builtins::error::new_error(
+                "RangeError: Maximum call stack size exceeded".to_string(),
+            )));
+        }
+        let return_iseq = mem::replace(&mut self.state.iseq, iseq);
+        self.state.frames.push(Frame {
+            sp: self.state.stack.len(),
+            return_pc: self.state.pc,
+            return_iseq: return_iseq,
+            reg_base: self.state.reg_base,
+            scope_len: scope_len,
+            trystate_len: self.trystate_stack.len(),
+            new_this: new_this,
+            func_id: func_id,
+            is_entry: is_entry,
+            reuse_callobj: false,
+        });
+        self.trystate_stack.push(TryState::None);
         self.state.pc = 0;
+        // The new frame's registers follow the ones of the caller.
+        self.state.reg_base = self.state.regs.len();
+        Ok(())
     }
 
-    /// pop vm.state.history
-    fn restore_state(&mut self) {
-        if let Some((previous_sp, return_pc)) = self.state.history.pop() {
-            if self.is_debug {
-                print!("stack trace: ");
-                for (n, v) in self.state.stack.iter().enumerate() {
-                    if n == 0 {
-                        print!("{}", v.format(1, false));
-                    } else {
-                        print!(" | {}", v.format(1, false));
-                    }
+    /// Leave the current frame, and restore the state of the caller from it. The value on the
+    /// top of the stack, or undefined if the frame pushed nothing, is left as its result.
+    fn pop_frame(&mut self) -> Frame {
+        let frame = match self.state.frames.pop() {
+            Some(frame) => frame,
+            None => unreachable!("frame stack abnormaly exhaust."),
+        };
+        if self.is_debug {
+            print!("stack trace: ");
+            for (n, v) in self.state.stack.iter().enumerate() {
+                if n == 0 {
+                    print!("{}", v.format(1, false));
+                } else {
+                    print!(" | {}", v.format(1, false));
                 }
-                println!();
             }
-            let top = self.state.stack.pop();
-            self.state.stack.truncate(previous_sp);
-            if let Some(top) = top {
-                self.state.stack.push(top);
+            println!();
+        }
+        // Values below 'sp' belong to the caller.
+        let result = if self.state.stack.len() > frame.sp {
+            self.state.stack.pop().unwrap()
+        } else {
+            Value::Undefined
+        };
+        self.state.stack.truncate(frame.sp);
+        // 'new' results in the object made for 'this' unless the function returns an object.
+        let result = match frame.new_this {
+            Some(ref new_this) => match result {
+                Value::Object(_, _) => result,
+                _ => new_this.clone(),
+            },
+            None => result,
+        };
+        self.state.stack.push(result);
+        self.state.pc = frame.return_pc;
+        self.state.iseq = frame.return_iseq.clone();
+        self.close_upvalues();
+        self.state.regs.truncate(self.state.reg_base);
+        self.state.reg_base = frame.reg_base;
+        if frame.reuse_callobj {
+            let callobj = self.state.scope.get(frame.scope_len).cloned();
+            if let Some(callobj) = callobj {
+                self.release_call_object(callobj);
+            }
+        }
+        // Scopes of catch clauses and with statements are left when an exception is thrown
+        // out of them.
+        self.state.scope.truncate(frame.scope_len);
+        self.trystate_stack.truncate(frame.trystate_len);
+        #[cfg(feature = "jit")]
+        {
+            if self.jit_on && frame.func_id != 0 {
+                self.jit
+                    .record_function_return_type(frame.func_id, self.state.stack.last().unwrap());
             }
-            self.state.pc = return_pc;
+        }
+        frame
+    }
+
+    /// Enter a JS function called with 'this'. Its call object is made from 'callobj' and 'args'.
+    /// If 'is_construct', 'this' is the object made by new, which is the result unless the
+    /// function returns an object. The code of the function runs when the run loop continues.
+    fn enter_function(
+        &mut self,
+        func_info: &FuncInfo,
+        callobj: &CallObject,
+        args: &Vec<Value>,
+        this: Value,
+        is_construct: bool,
+        is_entry: bool,
+    ) -> Result<(), RuntimeError> {
+        let scope_len = self.state.scope.len();
+        let this = if is_construct {
+            this
+        } else {
+            self.bind_this(func_info, this)
+        };
+        let new_this = if is_construct {
+            Some(this.clone())
         } else {
-            unreachable!("history stack abnormaly exhaust.")
+            None
+        };
+        let callobj = self.new_call_object(func_info, callobj, args, Some(this));
+        self.push_frame(
+            func_info.iseq.clone(),
+            scope_len,
+            new_this,
+            func_info.id,
+            is_entry,
+        )?;
+        let reuse_callobj = self.reuses_call_object(func_info);
+        self.state.frames.last_mut().unwrap().reuse_callobj = reuse_callobj;
+        self.state.scope.push(callobj);
+        Ok(())
+    }
+
+    /// 'this' of a call of 'func_info' by a caller which gives 'this'. A sloppy mode function
+    /// gets the global object for undefined and null, and a wrapper object for a primitive.
+    fn bind_this(&self, func_info: &FuncInfo, this: Value) -> Value {
+        if func_info.strict {
+            return this;
+        }
+        match this {
+            Value::Undefined | Value::Null => (*self.state.scope[0].this).clone(),
+            Value::Number(_) | Value::String(_) | Value::Bool(_) => Value::primitive_wrapper(this),
+            this => this,
         }
     }
 
-    /// main execution loop
-    pub fn do_run(&mut self, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-        self.store_state();
-        self.trystate_stack.push(TryState::None);
-        //let mut count = 0;
+    /// Whether the call object of a call of 'func_info' may be reused after the call. Not while
+    /// the debugger runs, which lets its client refer to the scopes of frames.
+    fn reuses_call_object(&self, func_info: &FuncInfo) -> bool {
+        !func_info.captures_scope && self.debugger.is_none()
+    }
+
+    /// Make the call object of a call of 'func_info' from 'base', the one of the function.
+    fn new_call_object(
+        &mut self,
+        func_info: &FuncInfo,
+        base: &CallObject,
+        args: &Vec<Value>,
+        this: Option<Value>,
+    ) -> CallObjectRef {
+        if self.reuses_call_object(func_info) {
+            if let Some(callobj) = self.callobj_pool.pop() {
+                return base.reuse_callobj_for_func(callobj, func_info.clone(), args, this);
+            }
+        }
+        base.new_callobj_from_func(func_info.clone(), args, this)
+    }
+
+    /// Put the call object of a frame left into the pool, unless the pool is full.
+    fn release_call_object(&mut self, mut callobj: CallObjectRef) {
+        if self.callobj_pool.len() < CALLOBJ_POOL_SIZE {
+            callobj.clear();
+            self.callobj_pool.push(callobj);
+        }
+    }
+
+    /// Keep the call object of the current frame from being reused, for builtins which let it
+    /// be referred to after the call returns, e.g. require() which makes it the outer scope of
+    /// the module.
+    pub fn capture_scope(&mut self) {
+        if let Some(frame) = self.state.frames.last_mut() {
+            frame.reuse_callobj = false;
+        }
+    }
+
+    /// the call object of the running function. None for a script or eval code.
+    fn function_scope(&self) -> Option<CallObjectRef> {
+        match self.state.frames.last() {
+            Some(frame) if frame.func_id != 0 => self.state.scope.get(frame.scope_len).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Make a closure of the function object 'func' in the current frame. Its upvalues are found
+    /// as the function describes, and its outer scope is the one of the running function.
+    /// UPDATE_PARENT_SCOPE which may follow replaces it with the current scope.
+    fn new_closure(&mut self, func: &Value) -> Value {
+        let base = match *func {
+            Value::Object(_, ObjectKind::Function(box (_, ref callobj))) => callobj.clone(),
+            _ => return func.clone(),
+        };
+        let sources = match *func {
+            Value::Object(_, ObjectKind::Function(box (ref func_info, _))) => {
+                func_info.upvalues.clone()
+            }
+            _ => unreachable!(),
+        };
+        let function_scope = self.function_scope();
+
+        let mut callobj = CallObject::new_with_this((*base.this).clone());
+        callobj.parent = match function_scope {
+            Some(ref scope) => scope.parent.clone(),
+            None => self.state.scope.last().cloned(),
+        };
+        for source in sources.iter() {
+            let upvalue = match *source {
+                UpvalueSource::Slot(slot) => self.open_upvalue(slot),
+                UpvalueSource::Upvalue(n) => function_scope.as_ref().unwrap().upvalues[n].clone(),
+            };
+            callobj.upvalues.push(upvalue);
+        }
+        func.closure(callobj)
+    }
+
+    /// the open upvalue of the slot of the current frame, which is made if there is none.
+    fn open_upvalue(&mut self, slot: u8) -> UpvalueRef {
+        let index = self.state.reg_base + slot as usize;
+        // The upvalues of the current frame are at the end.
+        for upvalue in self.open_upvalues.iter().rev() {
+            match **upvalue {
+                Upvalue::Open(i) if i == index => return upvalue.clone(),
+                Upvalue::Open(i) if i < self.state.reg_base => break,
+                _ => {}
+            }
+        }
+        let upvalue = gc::new(Upvalue::Open(index));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Close the upvalues of the registers of the current frame, which is being left.
+    fn close_upvalues(&mut self) {
+        while let Some(mut upvalue) = self.open_upvalues.last().cloned() {
+            let index = match *upvalue {
+                Upvalue::Open(index) if index >= self.state.reg_base => index,
+                _ => break,
+            };
+            let val = self
+                .state
+                .regs
+                .get(index)
+                .cloned()
+                .unwrap_or(Value::Undefined);
+            gc::write_barrier(&mut *upvalue);
+            *upvalue = Upvalue::Closed(val);
+            self.open_upvalues.pop();
+        }
+    }
+
+    /// the upvalue 'n' of the running function.
+    fn upvalue(&self, n: usize) -> UpvalueRef {
+        self.function_scope().unwrap().upvalues[n].clone()
+    }
+
+    /// Decode the instruction at pc, and move pc to the next instruction.
+    #[inline(always)]
+    fn fetch_inst(&mut self, iseq: &ByteCode) -> Inst {
+        let inst = Inst::decode(&iseq[self.state.pc as usize..]).unwrap();
+        self.state.pc += inst.size() as isize;
+        inst
+    }
+
+    /// execute the instruction at pc.
+    #[inline(always)]
+    fn dispatch(&mut self, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+        // A match on the opcode compiles to a jump table, and lets the compiler inline the
+        // handlers into the loop of do_run() instead of calling them through pointers.
+        match iseq[self.state.pc as usize] {
+            VMInst::END => end(self, iseq),
+            VMInst::CREATE_CONTEXT => create_context(self, iseq),
+            VMInst::CONSTRUCT => construct(self, iseq),
+            VMInst::CREATE_OBJECT => create_object(self, iseq),
+            VMInst::CREATE_ARRAY => create_array(self, iseq),
+            VMInst::PUSH_INT8 => push_int8(self, iseq),
+            VMInst::PUSH_INT32 => push_int32(self, iseq),
+            VMInst::PUSH_FALSE => push_false(self, iseq),
+            VMInst::PUSH_TRUE => push_true(self, iseq),
+            VMInst::PUSH_CONST => push_const(self, iseq),
+            VMInst::PUSH_THIS => push_this(self, iseq),
+            VMInst::PUSH_ARGUMENTS => push_arguments(self, iseq),
+            VMInst::PUSH_UNDEFINED => push_undefined(self, iseq),
+            VMInst::LNOT => lnot(self, iseq),
+            VMInst::BNOT => bnot(self, iseq),
+            VMInst::POSI => posi(self, iseq),
+            VMInst::NEG => neg(self, iseq),
+            VMInst::ADD => add(self, iseq),
+            VMInst::SUB => sub(self, iseq),
+            VMInst::MUL => mul(self, iseq),
+            VMInst::DIV => div(self, iseq),
+            VMInst::REM => rem(self, iseq),
+            VMInst::LT => lt(self, iseq),
+            VMInst::GT => gt(self, iseq),
+            VMInst::LE => le(self, iseq),
+            VMInst::GE => ge(self, iseq),
+            VMInst::EQ => eq(self, iseq),
+            VMInst::NE => ne(self, iseq),
+            VMInst::SEQ => seq(self, iseq),
+            VMInst::SNE => sne(self, iseq),
+            VMInst::AND => and(self, iseq),
+            VMInst::OR => or(self, iseq),
+            VMInst::XOR => xor(self, iseq),
+            VMInst::SHL => shl(self, iseq),
+            VMInst::SHR => shr(self, iseq),
+            VMInst::ZFSHR => zfshr(self, iseq),
+            VMInst::GET_MEMBER => get_member(self, iseq),
+            VMInst::SET_MEMBER => set_member(self, iseq),
+            VMInst::JMP_IF_FALSE => jmp_if_false(self, iseq),
+            VMInst::JMP => jmp(self, iseq),
+            VMInst::CALL => call(self, iseq),
+            VMInst::CALL_EVAL => call_eval(self, iseq),
+            VMInst::TAIL_CALL => tail_call(self, iseq),
+            VMInst::RETURN => return_(self, iseq),
+            VMInst::DOUBLE => double(self, iseq),
+            VMInst::DOUBLE2 => double2(self, iseq),
+            VMInst::ROTATE => rotate(self, iseq),
+            VMInst::POP => pop(self, iseq),
+            VMInst::LAND => land(self, iseq),
+            VMInst::LOR => lor(self, iseq),
+            VMInst::UPDATE_PARENT_SCOPE => update_parent_scope(self, iseq),
+            VMInst::CREATE_CLOSURE => create_closure(self, iseq),
+            VMInst::GET_UPVALUE => get_upvalue(self, iseq),
+            VMInst::SET_UPVALUE => set_upvalue(self, iseq),
+            VMInst::GET_VALUE => get_value(self, iseq),
+            VMInst::GET_GLOBAL => get_global(self, iseq),
+            VMInst::SET_VALUE => set_value(self, iseq),
+            VMInst::SET_VALUE_STRICT => set_value_strict(self, iseq),
+            VMInst::DECL_VAR => decl_var(self, iseq),
+            VMInst::COND_OP => cond_op(self, iseq),
+            VMInst::LOOP_START => loop_start(self, iseq),
+            VMInst::THROW => throw(self, iseq),
+            VMInst::ENTER_TRY => enter_try(self, iseq),
+            VMInst::LEAVE_TRY => leave_try(self, iseq),
+            VMInst::CATCH => catch(self, iseq),
+            VMInst::FINALLY => finally(self, iseq),
+            VMInst::RETURN_TRY => return_try(self, iseq),
+            VMInst::PUSH_SCOPE => push_scope(self, iseq),
+            VMInst::PUSH_WITH_SCOPE => push_with_scope(self, iseq),
+            VMInst::POP_SCOPE => pop_scope(self, iseq),
+            VMInst::SET_GETTER => set_getter(self, iseq),
+            VMInst::SET_SETTER => set_setter(self, iseq),
+            VMInst::COPY_DATA_PROPERTIES => copy_data_properties(self, iseq),
+            VMInst::DEFINE_PROPERTY => define_property(self, iseq),
+            VMInst::EXP => exp(self, iseq),
+            VMInst::PUSH_REG => push_reg(self, iseq),
+            VMInst::POP_REG => pop_reg(self, iseq),
+            VMInst::LOAD_CONST_REG => load_const_reg(self, iseq),
+            VMInst::GET_VALUE_REG => get_value_reg(self, iseq),
+            VMInst::ADD_REG => binary_op_reg(self, iseq, add_values),
+            VMInst::SUB_REG => binary_op_reg(self, iseq, sub_values),
+            VMInst::MUL_REG => binary_op_reg(self, iseq, mul_values),
+            VMInst::DIV_REG => binary_op_reg(self, iseq, div_values),
+            VMInst::REM_REG => binary_op_reg(self, iseq, rem_values),
+            VMInst::LT_REG => binary_op_reg(self, iseq, lt_values),
+            VMInst::GT_REG => binary_op_reg(self, iseq, gt_values),
+            VMInst::LE_REG => binary_op_reg(self, iseq, le_values),
+            VMInst::GE_REG => binary_op_reg(self, iseq, ge_values),
+            code => Err(RuntimeError::General(format!(
+                "unknown instruction: {:#04x}",
+                code
+            ))),
+        }
+    }
+
+    /// Run 'iseq' in the current scope until it ends. Functions called by it run in the same
+    /// loop.
+    pub fn do_run(&mut self, iseq: Rc<ByteCode>) -> Result<bool, RuntimeError> {
+        let scope_len = self.state.scope.len();
+        self.push_frame(iseq, scope_len, None, 0, true)?;
+        self.run_frames()
+    }
+
+    /// main execution loop. Runs until the frame entered by do_run() is left.
+    fn run_frames(&mut self) -> Result<bool, RuntimeError> {
+        let mut iseq = self.state.iseq.clone();
         loop {
-            //count += 1;
-            //if count % 1000 == 0 {
-            //    gc::mark_and_sweep(&mut self.state)
-            //};
-            let code = iseq[self.state.pc as usize];
             if self.is_debug {
                 let trystate = self.trystate_stack.last().unwrap();
                 let scopelen = self.state.scope.len();
@@ -465,82 +1141,282 @@ impl VM {
                     },
                 );
                 bytecode_gen::show_inst(
-                    iseq,
+                    &iseq,
                     self.state.pc as usize,
                     &self.codegen.bytecode_gen.const_table,
                 );
                 println!();
             }
-            match self.op_table[code as usize](self, iseq) {
-                Ok(true) => {
-                    continue;
-                }
+            if self.tracer.is_some() {
+                self.trace(&iseq);
+            }
+            let pc = self.state.pc;
+            let res = match self.check_limits().and_then(|()| self.check_debugger()) {
+                Ok(()) => self.dispatch(&iseq),
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(true) => {}
 
                 Ok(false) => {
                     // END or RETURN or, THROW occurs outer try-catch.
-                    self.trystate_stack.pop().unwrap();
-                    self.restore_state();
-                    return Ok(true);
+                    if self.pop_frame().is_entry {
+                        return Ok(true);
+                    }
                 }
 
-                Err(err) => {
-                    // Runtime error or THROW in try-catch.
-                    let mut error: Option<RuntimeError> = None;
-                    {
-                        let trystate = self.trystate_stack.last_mut().unwrap();
-                        match trystate.clone() {
-                            TryState::Try(to_catch, to_finally, ret) => {
-                                self.state.pc = to_catch;
-                                // push error object to exec stack.
-                                let err_obj = err.to_value();
-                                self.state.stack.push(err_obj);
-                                *trystate = TryState::Catch(to_finally, ret);
-                            }
-                            TryState::Catch(to_finally, ret) => {
-                                assert_eq!(ret, TryReturn::None);
-                                self.state.pc = to_finally;
-                                *trystate = TryState::Finally(TryReturn::Error(err));
-                                self.state.scope.pop();
-                            }
-                            TryState::None | TryState::Finally(_) => {
-                                error = Some(err);
-                            }
-                        };
+                // Runtime error or THROW. The frames without try-catch to catch it are left.
+                // Termination is not caught, and leaves all the frames.
+                Err(mut err) => {
+                    if err != RuntimeError::Terminated {
+                        self.record_error_trace(pc);
                     }
-                    if let Some(err) = error {
-                        match self.trystate_stack.pop().unwrap() {
-                            TryState::Finally(_) => {}
-                            x => self.trystate_stack.push(x),
+                    loop {
+                        err = match err {
+                            RuntimeError::Terminated => err,
+                            err => match self.catch_error(err) {
+                                None => {
+                                    self.error_trace = None;
+                                    break;
+                                }
+                                Some(err) => err,
+                            },
+                        };
+                        // The call which entered the frame does not leave a value.
+                        let frame = self.pop_frame();
+                        self.state.stack.truncate(frame.sp);
+                        if frame.is_entry {
+                            return Err(err);
                         }
-                        self.trystate_stack.pop().unwrap();
-                        // must push return value to exec stack.
-                        self.set_return_value(Value::Undefined);
-                        self.restore_state();
-                        return Err(err);
                     }
                 }
             }
+            // A call or a return switched the code.
+            if !Rc::ptr_eq(&iseq, &self.state.iseq) {
+                iseq = self.state.iseq.clone();
+            }
+        }
+    }
+
+    /// Count the instruction to be executed, and throw a RangeError if it exceeds a limit in
+    /// self.options.
+    #[inline(always)]
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        self.instruction_count += 1;
+        if let Some(max) = self.options.max_instructions {
+            if self.instruction_count > max {
+                return Err(RuntimeError::Exception(builtins::error::new_error(
+                    format!("RangeError: Instruction limit of {} exceeded", max),
+                )));
+            }
+        }
+        if self.instruction_count % HEAP_CHECK_INTERVAL == 0 {
+            gc::check_heap_limit(self)?;
+        }
+        if self.instruction_count % TERMINATE_CHECK_INTERVAL == 0 {
+            self.check_terminated()?;
+        }
+        let sample_due = self.profiler.as_ref().map_or(false, |p| p.take_request());
+        if sample_due {
+            self.take_sample();
+        }
+        Ok(())
+    }
+
+    /// Let the profiler sample the frames.
+    fn take_sample(&mut self) {
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.sample(self);
+            self.profiler = Some(profiler);
+        }
+    }
+
+    /// Let the tracer write the instruction at the pc.
+    fn trace(&mut self, iseq: &Rc<ByteCode>) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self, iseq);
+            self.tracer = Some(tracer);
+        }
+    }
+
+    /// Let the debugger stop the script before the current instruction.
+    #[inline(always)]
+    fn check_debugger(&mut self) -> Result<(), RuntimeError> {
+        match self.debugger.take() {
+            None => Ok(()),
+            Some(mut debugger) => {
+                let res = debugger.check(self);
+                self.debugger = Some(debugger);
+                res
+            }
+        }
+    }
+
+    /// Return RuntimeError::Terminated if the host asked to stop the script, clearing the
+    /// request so that later scripts can run.
+    fn check_terminated(&mut self) -> Result<(), RuntimeError> {
+        if self.terminate_flag.swap(false, AtomicOrdering::SeqCst) {
+            return Err(RuntimeError::Terminated);
         }
+        Ok(())
+    }
+
+    /// Pass 'err' to the try-catch running in the current frame. Returns the error if it is not
+    /// caught.
+    fn catch_error(&mut self, err: RuntimeError) -> Option<RuntimeError> {
+        let trystate = self.trystate_stack.last_mut().unwrap();
+        match trystate.clone() {
+            // The values pushed in the try or catch clause before the error are left, and the
+            // clause after it begins with the stack at ENTER_TRY.
+            TryState::Try(to_catch, to_finally, ret, (scope_len, sp)) => {
+                self.state.pc = to_catch;
+                self.state.scope.truncate(scope_len);
+                self.state.stack.truncate(sp);
+                // push error object to exec stack.
+                let err_obj = err.to_value();
+                self.state.stack.push(err_obj);
+                *trystate = TryState::Catch(to_finally, ret, (scope_len, sp));
+                None
+            }
+            TryState::Catch(to_finally, ret, (scope_len, sp)) => {
+                assert_eq!(ret, TryReturn::None);
+                self.state.pc = to_finally;
+                *trystate = TryState::Finally(TryReturn::Error(err));
+                self.state.scope.truncate(scope_len);
+                self.state.stack.truncate(sp);
+                None
+            }
+            TryState::None | TryState::Finally(_) => Some(err),
+        }
+    }
+
+    /// the index of the function object of 'iseq' in the constant table, which identifies the
+    /// code in the source map. None for the top-level code.
+    pub fn function_const_index(&self, iseq: &Rc<ByteCode>) -> Option<usize> {
+        self.codegen
+            .bytecode_gen
+            .const_table
+            .value
+            .iter()
+            .position(|val| match val {
+                Value::Object(_, ObjectKind::Function(box (ref info, _))) => {
+                    Rc::ptr_eq(&info.iseq, iseq)
+                }
+                _ => false,
+            })
+    }
+
+    /// the location of the instruction at 'pc' in 'iseq' by the source map.
+    pub fn source_location(&self, iseq: &Rc<ByteCode>, pc: usize) -> Option<StackFrame> {
+        let source_map = self.source_map.as_ref()?;
+        let func = source_map.function(self.function_const_index(iseq))?;
+        let (line, column) = func.location_of(pc);
+        Some(StackFrame {
+            name: func.name.clone(),
+            line: line,
+            column: column,
+        })
+    }
+
+    /// the locations of the frames by the source map, from the current one which is at 'pc'.
+    /// Empty without a source map.
+    pub fn stack_trace(&self, pc: isize) -> Vec<StackFrame> {
+        self.frame_locations(pc)
+            .into_iter()
+            .filter_map(|(_, location)| location)
+            .collect()
+    }
+
+    /// the stack trace seen by a builtin function, for the 'stack' of errors. If 'omit_to' is
+    /// given, the frames above the latest call of the function and the call itself are left out.
+    pub fn builtin_stack_trace(&self, omit_to: Option<FuncId>) -> Vec<StackFrame> {
+        // The builtin was called by the instruction before the pc.
+        let mut frames = self.frame_locations(self.state.pc - 1);
+        if let Some(func_id) = omit_to {
+            if let Some(n) = frames.iter().position(|(id, _)| *id == func_id) {
+                frames.drain(..n + 1);
+            }
+        }
+        frames
+            .into_iter()
+            .filter_map(|(_, location)| location)
+            .collect()
+    }
+
+    /// the id of the function and its location by the source map, of each frame from the
+    /// current one which is at 'pc'.
+    fn frame_locations(&self, pc: isize) -> Vec<(FuncId, Option<StackFrame>)> {
+        let mut locations = vec![];
+        let (mut iseq, mut pc) = (self.state.iseq.clone(), pc);
+        for frame in self.state.frames.iter().rev() {
+            locations.push((frame.func_id, self.source_location(&iseq, pc as usize)));
+            // The caller is in the middle of the instruction which made the call.
+            iseq = frame.return_iseq.clone();
+            pc = frame.return_pc - 1;
+        }
+        locations
+    }
+
+    /// Record the frames where an error occurred at 'pc' in error_trace, unless the trace of
+    /// the error has already been recorded by an inner run loop.
+    fn record_error_trace(&mut self, pc: isize) {
+        if self.source_map.is_none() || self.error_trace.is_some() {
+            return;
+        }
+        self.error_trace = Some(self.stack_trace(pc));
+    }
+
+    pub fn inline_cache(&mut self, id: usize) -> &mut InlineCache {
+        if id >= self.inline_caches.len() {
+            self.inline_caches.resize(id + 1, InlineCache::new());
+        }
+        &mut self.inline_caches[id]
+    }
+
+    pub fn global_cache(&mut self, id: usize) -> &mut GlobalCache {
+        if id >= self.global_caches.len() {
+            self.global_caches.resize(id + 1, GlobalCache::new());
+        }
+        &mut self.global_caches[id]
     }
 
     pub fn set_return_value(&mut self, val: Value) {
         self.state.stack.push(val);
     }
 
+    /// Call 'callee' with undefined for 'this', as a plain function call does.
     pub fn call_function_simply(
         &mut self,
         callee: &Value,
         args: &Vec<Value>,
     ) -> Result<bool, RuntimeError> {
-        //println!("{}", callee.format(1, true));
+        self.call_function_with_this(callee, Value::Undefined, args)
+    }
+
+    /// Call 'callee' with 'this' and 'args'. The return value is pushed.
+    pub fn call_function_with_this(
+        &mut self,
+        callee: &Value,
+        this: Value,
+        args: &Vec<Value>,
+    ) -> Result<bool, RuntimeError> {
         match callee {
-            Value::Object(_, ObjectKind::BuiltinFunction(box (ref info, callobj))) => {
-                (info.func)(self, args, callobj.clone())?;
+            Value::Object(_, ObjectKind::BuiltinFunction(box (ref info, ref callobj))) => {
+                // The copy shares the variables of the call object, in which some builtins keep
+                // their state, but has its own 'this' so that a call inside the builtin does
+                // not change it.
+                let callobj = gc::new(CallObject {
+                    this: Box::new(this),
+                    ..(**callobj).clone()
+                });
+                self.builtin_callobjs.push(callobj.clone());
+                let result = self.call_builtin(info.func, args, callobj);
+                self.builtin_callobjs.pop();
+                result?;
                 return Ok(true);
             }
             Value::Object(_, ObjectKind::Function(box (func_info, callobject))) => {
-                //println!("call this:{}", callobject.this.clone().format(1, true));
-                call_function(self, func_info.clone(), &mut callobject.clone(), args)
+                call_function(self, func_info.clone(), callobject, this, args)
             }
             ref e => Err(RuntimeError::Type(format!(
                 "type error: {:?} is not function",
@@ -548,22 +1424,105 @@ impl VM {
             ))),
         }
     }
-}
 
-macro_rules! get_int8 {
-    ($self:ident, $iseq:ident, $var:ident, $ty:ty) => {
-        let $var = $iseq[$self.state.pc as usize] as $ty;
-        $self.state.pc += 1;
-    };
+    /// Call the builtin 'func'. The objects in 'args' and the ones it roots are kept alive until
+    /// it returns.
+    fn call_builtin(
+        &mut self,
+        func: builtin::BuiltinFuncTy,
+        args: &Vec<Value>,
+        callobj: CallObjectRef,
+    ) -> Result<(), RuntimeError> {
+        self.with_roots(args, |vm| func(vm, args, callobj))
+    }
+
+    /// Run 'f' keeping the objects in 'vals' alive, for the code which holds them while it calls
+    /// back into scripts, which may run GC.
+    pub fn with_roots<T, F: FnOnce(&mut VM) -> T>(&mut self, vals: &[Value], f: F) -> T {
+        let roots_len = self.builtin_roots.len();
+        for val in vals {
+            self.root(val.clone());
+        }
+        let result = f(self);
+        self.builtin_roots.truncate(roots_len);
+        result
+    }
+
+    /// Keep 'val' alive until the builtin function running returns. A builtin roots the objects
+    /// it has made or taken out of others while it calls back into scripts, since only it refers
+    /// to them.
+    pub fn root(&mut self, val: Value) {
+        if let Value::Object(_, _) = val {
+            self.builtin_roots.push(val);
+        }
+    }
+
+    /// call 'callee' as a constructor. e.g. 'new callee(args)'
+    pub fn construct_function_simply(
+        &mut self,
+        callee: &Value,
+        args: &Vec<Value>,
+    ) -> Result<bool, RuntimeError> {
+        match callee.clone() {
+            Value::Object(map, ObjectKind::BuiltinFunction(box (x, mut callobj))) => {
+                callobj.set_this(new_this(&map));
+
+                // https://tc39.github.io/ecma262/#sec-date-constructor
+                // > The Date constructor returns a String representing the current time (UTC) when
+                // > called as a function rather than as a constructor.
+                // Likewise, Number, String and Boolean return a primitive value when called as a
+                // function, and a wrapper object when called as a constructor.
+                // BigInt can only be called as a function. A bound function constructs its target.
+                use builtin::BuiltinFuncTy;
+                use builtins::{bigint, boolean, date, function, number, string};
+                let constructors: [(BuiltinFuncTy, BuiltinFuncTy); 6] = [
+                    (date::date, date::date_new),
+                    (number::number, number::number_new),
+                    (string::string, string::string_new),
+                    (boolean::boolean, boolean::boolean_new),
+                    (bigint::bigint, bigint::bigint_new),
+                    (function::bound_function, function::bound_function_new),
+                ];
+                let func = constructors
+                    .iter()
+                    .find(|(func, _)| *func as *const u8 == x.func as *const u8)
+                    .map_or(x.func, |(_, new)| *new);
+                self.call_builtin(func, args, callobj)?;
+                Ok(true)
+            }
+            Value::Object(map, ObjectKind::Function(box (func_info, callobj))) => {
+                self.enter_function(&func_info, &callobj, args, new_this(&map), true, true)?;
+                self.run_frames()
+            }
+            c => Err(RuntimeError::Type(format!(
+                "type error(pc:{}): '{:?}' is not a constructor",
+                self.state.pc, c
+            ))),
+        }
+    }
 }
 
-macro_rules! get_int32 {
-    ($self:ident, $iseq:ident, $var:ident, $ty:ty) => {
-        let $var = (($iseq[$self.state.pc as usize + 3] as $ty) << 24)
-            + (($iseq[$self.state.pc as usize + 2] as $ty) << 16)
-            + (($iseq[$self.state.pc as usize + 1] as $ty) << 8)
-            + ($iseq[$self.state.pc as usize + 0] as $ty);
-        $self.state.pc += 4;
+/// the object made for 'this' by 'new'. Its prototype is the 'prototype' of the constructor.
+fn new_this(constructor: &PropMapRef) -> Value {
+    Value::object_from_npp(&vec![(
+        "__proto__".to_string(),
+        Property::new(
+            constructor
+                .get("prototype")
+                .map_or(Value::Undefined, |prop| prop.val.clone()),
+        ),
+    )])
+}
+
+// Decode the instruction at pc, which must be 'Name', and bind its operands to variables of
+// the same names, converted to the types given.
+// e.g. 'decode!(self_, iseq, Call { argc as usize })' binds 'argc'.
+macro_rules! decode {
+    ($self:ident, $iseq:ident, $name:ident { $($operand:ident as $ty:ty),* }) => {
+        let ($($operand,)*) = match $self.fetch_inst($iseq) {
+            Inst::$name { $($operand),* } => ($($operand as $ty,)*),
+            inst => unreachable!("{:?} is not {}", inst, stringify!($name)),
+        };
     };
 }
 
@@ -571,170 +1530,210 @@ fn end(_self: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     Ok(false)
 }
 
-fn create_context(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // create_context
-    Ok(true)
+fn create_context(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // create_context
+    Ok(true)
+}
+
+fn construct(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, Construct { argc as usize });
+
+    let callee = self_.state.stack.pop().unwrap();
+    self_.state.stack.pop(); // 'this' for CALL, which the constructor does not use
+
+    let mut args = vec![];
+    for _ in 0..argc {
+        args.push(self_.state.stack.pop().unwrap());
+    }
+
+    match callee {
+        Value::Object(ref map, ObjectKind::Function(box (ref func_info, ref callobj))) => {
+            self_.enter_function(func_info, callobj, &args, new_this(map), true, false)?;
+            Ok(true)
+        }
+        _ => self_.construct_function_simply(&callee, &args),
+    }
 }
 
-fn construct(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // construct
-    get_int32!(self_, iseq, argc, usize);
+fn call(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, Call { argc as usize });
 
     let callee = self_.state.stack.pop().unwrap();
+    let this = self_.state.stack.pop().unwrap();
 
     let mut args = vec![];
     for _ in 0..argc {
         args.push(self_.state.stack.pop().unwrap());
     }
 
-    match callee.clone() {
-        Value::Object(map, ObjectKind::BuiltinFunction(box (x, mut callobj))) => {
-            *callobj.this = Value::object_from_npp(&vec![(
-                "__proto__".to_string(),
-                Property::new(
-                    map.get("prototype")
-                        .unwrap_or(&Value::Undefined.to_property())
-                        .val
-                        .clone(),
-                ),
-            )]);
-
-            // https://tc39.github.io/ecma262/#sec-date-constructor
-            // > The Date constructor returns a String representing the current time (UTC) when
-            // > called as a function rather than as a constructor.
-            use builtins::date::{date, date_new};
-            (if x.func as *const u8 == date as *const u8 {
-                date_new
-            } else {
-                x.func
-            })(self_, &args, callobj)?;
-        }
-        Value::Object(map, ObjectKind::Function(box (func_info, callobj))) => {
-            // similar code is used some times. should make it a function.
-            let new_this = Value::object_from_npp(&vec![(
-                "__proto__".to_string(),
-                Property::new(
-                    map.get("prototype")
-                        .unwrap_or(&Property::new(Value::Undefined))
-                        .val
-                        .clone(),
-                ),
-            )]);
-            let callobj =
-                callobj.new_callobj_from_func(func_info.clone(), &args, Some(new_this.clone()));
+    call_value(self_, &callee, this, &args)
+}
 
-            self_.state.scope.push(callobj);
+fn call_eval(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, CallEval { argc as usize });
 
-            let res = self_.do_run(&func_info.iseq);
+    let callee = self_.state.stack.pop().unwrap();
+    let this = self_.state.stack.pop().unwrap();
 
-            self_.state.scope.pop();
-            let ret = self_.state.stack.last_mut().unwrap();
-            match &ret {
-                &Value::Object(_, _) => {}
-                _ => *ret = new_this,
-            };
-            return res;
+    let mut args = vec![];
+    for _ in 0..argc {
+        args.push(self_.state.stack.pop().unwrap());
+    }
+
+    if builtins::eval::is_eval(&callee) {
+        builtins::eval::direct_eval(self_, &args)?;
+        Ok(true)
+    } else {
+        call_value(self_, &callee, this, &args)
+    }
+}
+
+/// Call 'callee' with 'this' from CALL. A JS function is entered as a new frame, and a builtin
+/// function is called at once.
+fn call_value(
+    self_: &mut VM,
+    callee: &Value,
+    this: Value,
+    args: &Vec<Value>,
+) -> Result<bool, RuntimeError> {
+    match callee {
+        Value::Object(_, ObjectKind::Function(box (ref func_info, ref callobj))) => {
+            if !call_jit(self_, func_info, callobj, args) {
+                self_.enter_function(func_info, callobj, args, this, false, false)?;
+            }
         }
-        c => {
-            return Err(RuntimeError::Type(format!(
-                "type error(pc:{}): '{:?}' is not a constructor",
-                self_.state.pc, c
-            )));
+        _ => {
+            self_.call_function_with_this(callee, this, args)?;
         }
-    };
-
+    }
     Ok(true)
 }
 
-fn call(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // Call
-    get_int32!(self_, iseq, argc, usize);
+/// 'return callee(args)'. If the callee is a JS function, the current frame is reused for the
+/// call instead of entering a new one.
+fn tail_call(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, TailCall { argc as usize });
 
     let callee = self_.state.stack.pop().unwrap();
+    let this = self_.state.stack.pop().unwrap();
 
     let mut args = vec![];
     for _ in 0..argc {
         args.push(self_.state.stack.pop().unwrap());
     }
 
-    self_.call_function_simply(&callee, &args)?;
-
-    Ok(true)
+    match callee {
+        Value::Object(_, ObjectKind::Function(box (ref func_info, ref callobj))) => {
+            let this = self_.bind_this(func_info, this);
+            let callobj = self_.new_call_object(func_info, callobj, &args, Some(this));
+            let reuse_callobj = self_.reuses_call_object(func_info);
+            let (sp, scope_len, trystate_len, release_old) = {
+                let frame = self_.state.frames.last_mut().unwrap();
+                frame.func_id = func_info.id;
+                let release_old = mem::replace(&mut frame.reuse_callobj, reuse_callobj);
+                (frame.sp, frame.scope_len, frame.trystate_len, release_old)
+            };
+            if release_old {
+                let old_callobj = self_.state.scope.get(scope_len).cloned();
+                if let Some(old_callobj) = old_callobj {
+                    self_.release_call_object(old_callobj);
+                }
+            }
+            self_.state.stack.truncate(sp);
+            self_.close_upvalues();
+            self_.state.regs.truncate(self_.state.reg_base);
+            self_.state.scope.truncate(scope_len);
+            self_.state.scope.push(callobj);
+            // Only TryState::None pushed by push_frame() is left.
+            self_.trystate_stack.truncate(trystate_len + 1);
+            self_.state.iseq = func_info.iseq.clone();
+            self_.state.pc = 0;
+            Ok(true)
+        }
+        _ => {
+            self_.call_function_with_this(&callee, this, &args)?;
+            Ok(false)
+        }
+    }
 }
 
-/// invoke JS function.
+/// invoke JS function with 'this'.
 /// 1)apply arguments, 2)execute bytecode.
 pub fn call_function(
     self_: &mut VM,
     func_info: FuncInfo,
-    callobj: &mut CallObject,
+    callobj: &CallObject,
+    this: Value,
     args: &Vec<Value>,
 ) -> Result<bool, RuntimeError> {
-    let argc = args.len();
-    let args_all_numbers = args.iter().all(|val| match val {
-        Value::Number(_) => true,
-        _ => false,
-    });
-
-    let callobj = callobj.new_callobj_from_func(func_info.clone(), &args, None);
-    self_.state.scope.push(callobj);
-
-    let FuncInfo { id, iseq, .. } = func_info.clone();
-
-    if args_all_numbers && self_.jit_on {
-        let scope = (*self_.state.scope.last().unwrap()).clone();
-        if let Some(f) = unsafe {
-            self_.jit.can_jit(
-                func_info,
-                scope,
-                &self_.codegen.bytecode_gen.const_table,
-                argc,
-            )
-        } {
-            self_
-                .state
-                .stack
-                .push(unsafe { self_.jit.run_llvm_func(id, f, &args) });
-            self_.state.scope.pop();
-            return Ok(true);
+    if call_jit(self_, &func_info, callobj, args) {
+        return Ok(true);
+    }
+    self_.enter_function(&func_info, callobj, args, this, false, true)?;
+    self_.run_frames()
+}
+
+/// Run the function by the JIT if it can be compiled, and push the return value.
+/// Returns false if the function has to be run by the VM.
+#[cfg(feature = "jit")]
+fn call_jit(self_: &mut VM, func_info: &FuncInfo, callobj: &CallObject, args: &Vec<Value>) -> bool {
+    if !self_.jit_on
+        || !args.iter().all(|val| match val {
+            Value::Number(_) => true,
+            _ => false,
+        })
+    {
+        return false;
+    }
+    let scope = callobj.new_callobj_from_func(func_info.clone(), args, None);
+    match unsafe {
+        self_.jit.can_jit(
+            func_info.clone(),
+            scope,
+            &self_.codegen.bytecode_gen.const_table,
+            args.len(),
+        )
+    } {
+        Some(f) => {
+            let val = unsafe { self_.jit.run_llvm_func(func_info.id, f, args) };
+            self_.state.stack.push(val);
+            true
         }
+        None => false,
     }
+}
 
-    let res = self_.do_run(&iseq);
-
-    self_.state.scope.pop();
-    if self_.jit_on {
-        self_
-            .jit
-            .record_function_return_type(id, self_.state.stack.last().unwrap());
-    };
-    res
+#[cfg(not(feature = "jit"))]
+fn call_jit(_: &mut VM, _: &FuncInfo, _: &CallObject, _: &Vec<Value>) -> bool {
+    false
 }
 
 fn create_object(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // create_object
-    get_int32!(self_, iseq, len, usize);
+    decode!(self_, iseq, CreateObject { len as usize });
     let mut npp = vec![];
     for _ in 0..len {
-        let name = if let Value::String(name) = self_.state.stack.pop().unwrap() {
-            name.into_string().unwrap()
-        } else {
-            unreachable!()
+        let name = match self_.state.stack.pop().unwrap() {
+            Value::String(name) => name.to_string(),
+            // computed property name
+            other => other.to_string(),
         };
         let val = self_.state.stack.pop().unwrap();
         npp.push((name, Property::new(val.clone())));
     }
+    // The last property was on the top. They are defined in the order they are written, so that
+    // a later one overrides an earlier one of the same name.
+    npp.reverse();
 
     self_.state.stack.push(Value::object_from_npp(&npp));
 
-    gc::mark_and_sweep(self_);
+    gc::collect_if_needed(self_);
+    gc::check_heap_limit(self_)?;
 
     Ok(true)
 }
 
 fn create_array(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // create_array
-    get_int32!(self_, iseq, len, usize);
+    decode!(self_, iseq, CreateArray { len as usize });
 
     let mut arr = vec![];
     for _ in 0..len {
@@ -744,44 +1743,47 @@ fn create_array(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
 
     self_.state.stack.push(Value::array_from_elems(arr));
 
-    gc::mark_and_sweep(self_);
+    gc::collect_if_needed(self_);
+    gc::check_heap_limit(self_)?;
 
     Ok(true)
 }
 
+#[inline(always)]
 fn push_int8(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // push_int
-    get_int8!(self_, iseq, n, i8);
-    self_.state.stack.push(Value::Number(n as f64));
+    decode!(self_, iseq, PushInt8 { n as f64 });
+    self_.state.stack.push(Value::Number(n));
     Ok(true)
 }
 
+#[inline(always)]
 fn push_int32(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // push_int
-    get_int32!(self_, iseq, n, i32);
-    self_.state.stack.push(Value::Number(n as f64));
+    decode!(self_, iseq, PushInt32 { n as f64 });
+    self_.state.stack.push(Value::Number(n));
     Ok(true)
 }
 
+#[inline(always)]
 fn push_false(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // push_false
     self_.state.stack.push(Value::Bool(false));
     Ok(true)
 }
 
+#[inline(always)]
 fn push_true(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // push_true
     self_.state.stack.push(Value::Bool(true));
     Ok(true)
 }
 
+#[inline(always)]
 fn push_const(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // push_const
-    get_int32!(self_, iseq, n, usize);
+    decode!(self_, iseq, PushConst { id as usize });
     self_
         .state
         .stack
-        .push(self_.codegen.bytecode_gen.const_table.value[n].clone());
+        .push(self_.codegen.bytecode_gen.const_table.value[id].clone());
     Ok(true)
 }
 
@@ -799,6 +1801,7 @@ fn push_arguments(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError
     Ok(true)
 }
 
+#[inline(always)]
 fn push_undefined(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // push_defined
     self_.state.stack.push(Value::Undefined);
@@ -812,9 +1815,24 @@ fn lnot(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     Ok(true)
 }
 
+fn bnot(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // bnot
+    let expr = self_.state.stack.last_mut().unwrap();
+    *expr = match *expr {
+        Value::BigInt(ref n) => Value::bigint(bigint::not(n)),
+        ref otherwise => Value::Number(!otherwise.to_int32() as f64),
+    };
+    Ok(true)
+}
+
 fn posi(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // posi
     let expr = self_.state.stack.last_mut().unwrap();
+    if let Value::BigInt(_) = expr {
+        return Err(RuntimeError::Type(
+            "type error: cannot convert a BigInt value to a number".to_string(),
+        ));
+    }
     *expr = Value::Number(expr.to_number());
     Ok(true)
 }
@@ -824,17 +1842,40 @@ fn neg(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     let expr = self_.state.stack.last_mut().unwrap();
     *expr = match *expr {
         Value::Number(n) => Value::Number(-n),
-        _ => return Err(RuntimeError::Unimplemented),
+        Value::BigInt(ref n) => Value::bigint(-&**n),
+        ref otherwise => Value::Number(-otherwise.to_number()),
     };
     Ok(true)
 }
 
+#[inline(always)]
 fn add(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(add_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn add_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    // Arrays are joined here, where too long ones throw a RangeError.
+    let to_primitive = |val: Value| -> Result<Value, RuntimeError> {
+        Ok(match val {
+            Value::Object(_, ObjectKind::Array(ref ary)) => Value::string(ary.join(",")?),
+            val => val.to_primitive(),
+        })
+    };
+    let rhs = to_primitive(rhs)?;
+    let lhs = to_primitive(lhs)?;
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+        (Value::BigInt(box l), Value::BigInt(box r)) => Value::bigint(l + r),
+        // A string and any other primitive are concatenated. e.g. 'a' + undefined
+        (l @ Value::String(_), r) | (l, r @ Value::String(_)) => {
+            Value::js_string(l.to_js_string().concat(&r.to_js_string()))
+        }
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         (Value::Bool(false), Value::Number(x)) | (Value::Number(x), Value::Bool(false)) => {
             Value::Number(x)
         }
@@ -843,107 +1884,200 @@ fn add(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         }
         // TODO: We need the correct implementation.
         (Value::Undefined, _) | (_, Value::Undefined) => Value::Number(::std::f64::NAN),
-        (l, r) => Value::string(l.to_string() + r.to_string().as_str()),
-    });
-    Ok(true)
+        (l, r) => Value::js_string(l.to_js_string().concat(&r.to_js_string())),
+    })
 }
 
+#[inline(always)]
 fn sub(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(sub_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn sub_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Number(l - r),
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(&*l - &*r),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn mul(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(mul_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn mul_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Number(l * r),
         (Value::String(l), Value::Number(r)) => {
-            Value::string(l.to_str().unwrap().repeat(r as usize))
+            Value::js_string(JsString::from_units(l.units().repeat(r as usize)))
         }
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(&*l * &*r),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn div(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(div_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn div_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Number(l / r),
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(bigint::div(&l, &r)?),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn rem(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // $name
+    let rhs = self_.state.stack.pop().unwrap();
+    let lhs = self_.state.stack.pop().unwrap();
+    self_.state.stack.push(rem_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn rem_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
+        // Rust's % on f64 is fmod as the spec requires: the result has the sign of the
+        // dividend, and fractional operands are not truncated.
+        (Value::Number(l), Value::Number(r)) => Value::Number(l % r),
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(bigint::rem(&l, &r)?),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
+        _ => return Err(RuntimeError::Unimplemented),
+    })
+}
+
+fn exp(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
     self_.state.stack.push(match (lhs, rhs) {
-        (Value::Number(l), Value::Number(r)) => Value::Number((l as i64 % r as i64) as f64),
+        // https://tc39.github.io/ecma262/#sec-applying-the-exp-operator
+        (Value::Number(l), Value::Number(r))
+            if r.is_nan() || (l.abs() == 1.0 && r.is_infinite()) =>
+        {
+            Value::Number(::std::f64::NAN)
+        }
+        (Value::Number(l), Value::Number(r)) => Value::Number(l.powf(r)),
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(bigint::exp(&l, &r)?),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
 }
 
+#[inline(always)]
 fn lt(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(lt_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn lt_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Bool(l < r),
         (Value::String(l), Value::String(r)) => Value::Bool(l < r),
+        (Value::BigInt(l), r) => Value::Bool(compare_bigint(&l, &r) == Some(Ordering::Less)),
+        (l, Value::BigInt(r)) => Value::Bool(compare_bigint(&r, &l) == Some(Ordering::Greater)),
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn gt(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(gt_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn gt_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Bool(l > r),
         (Value::String(l), Value::String(r)) => Value::Bool(l > r),
+        (Value::BigInt(l), r) => Value::Bool(compare_bigint(&l, &r) == Some(Ordering::Greater)),
+        (l, Value::BigInt(r)) => Value::Bool(compare_bigint(&r, &l) == Some(Ordering::Less)),
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn le(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(le_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn le_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Bool(l <= r),
         (Value::String(l), Value::String(r)) => Value::Bool(l <= r),
+        (Value::BigInt(l), r) => {
+            Value::Bool(compare_bigint(&l, &r).map_or(false, |o| o != Ordering::Greater))
+        }
+        (l, Value::BigInt(r)) => {
+            Value::Bool(compare_bigint(&r, &l).map_or(false, |o| o != Ordering::Less))
+        }
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
+#[inline(always)]
 fn ge(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
     let lhs = self_.state.stack.pop().unwrap();
-    self_.state.stack.push(match (lhs, rhs) {
+    self_.state.stack.push(ge_values(lhs, rhs)?);
+    Ok(true)
+}
+
+#[inline(always)]
+fn ge_values(lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    Ok(match (lhs, rhs) {
         (Value::Number(l), Value::Number(r)) => Value::Bool(l >= r),
         (Value::String(l), Value::String(r)) => Value::Bool(l >= r),
+        (Value::BigInt(l), r) => {
+            Value::Bool(compare_bigint(&l, &r).map_or(false, |o| o != Ordering::Less))
+        }
+        (l, Value::BigInt(r)) => {
+            Value::Bool(compare_bigint(&r, &l).map_or(false, |o| o != Ordering::Greater))
+        }
         _ => return Err(RuntimeError::Unimplemented),
-    });
-    Ok(true)
+    })
 }
 
 // TODO: Need more precise implemention
+#[inline(always)]
 fn eq(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
@@ -956,6 +2090,7 @@ fn eq(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 }
 
 // TODO: Need more precise implemention
+#[inline(always)]
 fn ne(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
@@ -968,6 +2103,7 @@ fn ne(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 }
 
 // TODO: Need more precise implemention
+#[inline(always)]
 fn seq(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
@@ -977,6 +2113,7 @@ fn seq(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 }
 
 // TODO: Need more precise implemention
+#[inline(always)]
 fn sne(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // $name
     let rhs = self_.state.stack.pop().unwrap();
@@ -993,6 +2130,10 @@ fn and(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as i64 as i32) & (r as i64 as i32)) as f64)
         }
+        (Value::BigInt(l), Value::BigInt(r)) => {
+            Value::bigint(bigint::bitwise(&l, &r, |l, r| l & r))
+        }
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
@@ -1006,6 +2147,10 @@ fn or(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as i64 as i32) | (r as i64 as i32)) as f64)
         }
+        (Value::BigInt(l), Value::BigInt(r)) => {
+            Value::bigint(bigint::bitwise(&l, &r, |l, r| l | r))
+        }
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
@@ -1019,6 +2164,10 @@ fn xor(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as i64 as i32) ^ (r as i64 as i32)) as f64)
         }
+        (Value::BigInt(l), Value::BigInt(r)) => {
+            Value::bigint(bigint::bitwise(&l, &r, |l, r| l ^ r))
+        }
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
@@ -1032,6 +2181,8 @@ fn shl(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as i64 as i32) << (r as i64 as i32)) as f64)
         }
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(bigint::shl(&l, &r)?),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
@@ -1045,6 +2196,8 @@ fn shr(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as i64 as i32) >> (r as i64 as i32)) as f64)
         }
+        (Value::BigInt(l), Value::BigInt(r)) => Value::bigint(bigint::shr(&l, &r)?),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
@@ -1058,46 +2211,167 @@ fn zfshr(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
         (Value::Number(l), Value::Number(r)) => {
             Value::Number(((l as u64 as u32) >> (r as u64 as u32)) as f64)
         }
+        (Value::BigInt(_), Value::BigInt(_)) => {
+            return Err(RuntimeError::Type(
+                "type error: BigInts have no unsigned right shift, use >> instead".to_string(),
+            ))
+        }
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) => return Err(bigint::mixing_error()),
         _ => return Err(RuntimeError::Unimplemented),
     });
     Ok(true)
 }
 
-fn get_member(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // get_global
+fn get_member(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, GetMember { cache as usize });
     let member = self_.state.stack.pop().unwrap();
     let parent = self_.state.stack.pop().unwrap();
     match member.clone() {
-        Value::String(ref s) if s.to_str().unwrap() == "toString" => {}
+        Value::String(ref s) if **s == "toString" => {}
         _ => {}
     };
-    let val = parent.get_property(member, Some(self_.state.scope.last().unwrap().clone()));
+    // fast path for indexing typed arrays
+    if let (Value::Object(_, ObjectKind::TypedArray(ta)), Value::Number(n)) = (&parent, &member) {
+        self_.state.stack.push(ta.get(*n));
+        return Ok(true);
+    }
+    let val = match (&parent, &member) {
+        (Value::Object(map, ObjectKind::Ordinary), Value::String(key))
+        | (Value::Object(map, ObjectKind::Function(_)), Value::String(key)) => {
+            let key = key.to_string();
+            let key = key.as_str();
+            match self_.inline_cache(cache).lookup(map, key) {
+                Ok(Some(holder)) => holder.get(key).unwrap().val.clone(),
+                Ok(None) => Value::Undefined,
+                Err(()) => parent.get_property(
+                    member.clone(),
+                    Some(self_.state.scope.last().unwrap().clone()),
+                ),
+            }
+        }
+        _ => parent.get_property(
+            member.clone(),
+            Some(self_.state.scope.last().unwrap().clone()),
+        ),
+    };
+    // the value of an accessor property is Empty.
+    if let Value::Empty = val {
+        if let Some(accessor) = obj_find_accessor(parent.clone(), member.to_string().as_str()) {
+            match accessor.get {
+                Value::Undefined => self_.state.stack.push(Value::Undefined),
+                getter => {
+                    self_.call_function_with_this(&getter, parent.clone(), &vec![])?;
+                }
+            }
+            return Ok(true);
+        }
+    }
     self_.state.stack.push(val);
     Ok(true)
 }
 
-fn set_member(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // get_global
+fn set_member(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, SetMember { cache as usize });
     let member = self_.state.stack.pop().unwrap();
     let mut parent = self_.state.stack.pop().unwrap().clone();
     let val = self_.state.stack.pop().unwrap();
-    parent.set_property(member, val, Some(self_.state.scope.last().unwrap().clone()));
+    // fast path for indexing typed arrays
+    if let (Value::Object(_, ObjectKind::TypedArray(ta)), Value::Number(n)) = (&mut parent, &member)
+    {
+        ta.set(*n, val.to_number());
+        return Ok(true);
+    }
+    if let Value::Object(ref map, ObjectKind::Ordinary)
+    | Value::Object(ref map, ObjectKind::Function(_)) = parent
+    {
+        let key = member.to_string();
+        let accessor = match self_.inline_cache(cache).lookup(map, key.as_str()) {
+            Ok(Some(holder)) => holder.get(&key).unwrap().accessor.clone().map(|a| *a),
+            Ok(None) => None,
+            Err(()) => obj_find_accessor(parent.clone(), key.as_str()),
+        };
+        if let Some(accessor) = accessor {
+            if let Value::Undefined = accessor.set {
+                return Ok(true);
+            }
+            self_.call_function_with_this(&accessor.set, parent.clone(), &vec![val])?;
+            self_.state.stack.pop(); // return value of the setter
+            return Ok(true);
+        }
+    }
+    parent.set_property(member, val, Some(self_.state.scope.last().unwrap().clone()))?;
+    Ok(true)
+}
+
+fn set_getter(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // set_getter
+    let getter = self_.state.stack.pop().unwrap();
+    let name = self_.state.stack.pop().unwrap();
+    let obj = self_.state.stack.last_mut().unwrap();
+    obj.set_accessor(name.to_string(), getter, true);
+    Ok(true)
+}
+
+fn set_setter(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // set_setter
+    let setter = self_.state.stack.pop().unwrap();
+    let name = self_.state.stack.pop().unwrap();
+    let obj = self_.state.stack.last_mut().unwrap();
+    obj.set_accessor(name.to_string(), setter, false);
+    Ok(true)
+}
+
+/// A property of an object literal, defined after its name and value are evaluated in order.
+fn define_property(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // define_property
+    let val = self_.state.stack.pop().unwrap();
+    let name = self_.state.stack.pop().unwrap();
+    let obj = self_.state.stack.last_mut().unwrap();
+    obj.set_property_with_name(name.to_string(), val);
+    Ok(true)
+}
+
+/// https://tc39.github.io/ecma262/#sec-copydataproperties
+/// '...source' in an object literal. The enumerable own properties of 'source' are read, calling
+/// the getters, and defined on the object under it. undefined and null have no properties.
+fn copy_data_properties(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // copy_data_properties
+
+    // 'source' stays on the stack while the getters run.
+    let source = self_.state.stack.last().unwrap().clone();
+    let target = self_.state.stack.len() - 2;
+    for key in builtins::object::own_enumerable_keys(&source) {
+        let val = match source.get_property(Value::string(key.clone()), None) {
+            // the value of an accessor property is Empty.
+            Value::Empty => match obj_find_accessor(source.clone(), key.as_str()) {
+                Some(ref accessor) if accessor.get != Value::Undefined => {
+                    self_.call_function_with_this(&accessor.get, source.clone(), &vec![])?;
+                    self_.state.stack.pop().unwrap_or(Value::Undefined)
+                }
+                _ => Value::Undefined,
+            },
+            val => val,
+        };
+        self_.state.stack[target].set_property_with_name(key, val);
+    }
+    self_.state.stack.pop(); // source
     Ok(true)
 }
 
+#[inline(always)]
 fn jmp(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // jmp
-    get_int32!(self_, iseq, dst, i32);
-    self_.state.pc += dst as isize;
+    decode!(self_, iseq, Jmp { offset as isize });
+    self_.state.pc += offset;
     Ok(true)
 }
 
+#[inline(always)]
 fn jmp_if_false(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1; // jmp_if_false
-    get_int32!(self_, iseq, dst, i32);
+    decode!(self_, iseq, JmpIfFalse { offset as isize });
+    // Any falsy value jumps, not only false: 0, '', null, undefined and NaN as well.
     let cond = self_.state.stack.pop().unwrap();
-    if let Value::Bool(false) = cond {
-        self_.state.pc += dst as isize
+    if !cond.to_boolean() {
+        self_.state.pc += offset
     }
     Ok(true)
 }
@@ -1113,13 +2387,14 @@ fn throw(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 
 fn enter_try(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
     let pc = self_.state.pc;
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, to_catch, isize);
-    get_int32!(self_, iseq, to_finally, isize);
+    decode!(self_, iseq, EnterTry { catch as isize, finally as isize });
+    let scope_len = self_.state.scope.len();
+    let sp = self_.state.stack.len();
     self_.trystate_stack.push(TryState::Try(
-        pc + to_catch,
-        pc + to_finally,
+        pc + catch,
+        pc + finally,
         TryReturn::None,
+        (scope_len, sp),
     ));
     Ok(true)
 }
@@ -1133,7 +2408,7 @@ fn leave_try(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 
         let trystate = self_.trystate_stack.last_mut().unwrap();
         match trystate.clone() {
-            TryState::Try(_, to_finally, _) | TryState::Catch(to_finally, _) => {
+            TryState::Try(_, to_finally, _, _) | TryState::Catch(to_finally, _, _) => {
                 self_.state.pc = to_finally;
                 *trystate = TryState::Finally(TryReturn::Value(return_val));
             }
@@ -1157,7 +2432,7 @@ fn catch(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1;
     let trystate = self_.trystate_stack.last_mut().unwrap();
     match trystate {
-        TryState::Catch(_, _) => {}
+        TryState::Catch(_, _, _) => {}
         _ => unreachable!("catch(): invalid trystate."),
     };
 
@@ -1169,10 +2444,10 @@ fn finally(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     let trystate = self_.trystate_stack.last_mut().unwrap();
     match trystate.clone() {
         TryState::Finally(_) => {}
-        TryState::Try(_, _, x) => {
+        TryState::Try(_, _, x, _) => {
             *trystate = TryState::Finally(x.clone());
         }
-        TryState::Catch(_, x) => {
+        TryState::Catch(_, x, _) => {
             *trystate = TryState::Finally(x.clone());
         }
         _ => unreachable!("finally(): invalid trystate."),
@@ -1182,18 +2457,17 @@ fn finally(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
 
 fn return_try(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
     let pc = self_.state.pc;
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, to_finally, isize);
+    decode!(self_, iseq, ReturnTry { finally as isize });
     let val = self_.state.stack.pop().unwrap();
     let trystate = self_.trystate_stack.last_mut().unwrap();
     match trystate {
         TryState::Finally(_) => {}
-        TryState::Try(_, _, _) | TryState::Catch(_, _) => {
+        TryState::Try(_, _, _, _) | TryState::Catch(_, _, _) => {
             *trystate = TryState::Finally(TryReturn::Value(val));
         }
         _ => unreachable!(),
     };
-    self_.state.pc = pc + to_finally;
+    self_.state.pc = pc + finally;
     Ok(true)
 }
 
@@ -1210,12 +2484,36 @@ fn push_scope(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     Ok(true)
 }
 
+fn push_with_scope(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1;
+    let vals = match self_.state.stack.pop().unwrap() {
+        Value::Undefined | Value::Null => {
+            return Err(RuntimeError::Type(
+                "type error: cannot convert undefined or null to object".to_string(),
+            ))
+        }
+        Value::Object(map, _) => map,
+        // TODO: Properties of primitive values can not be referred to.
+        _ => gc::new(PropMap::new()),
+    };
+
+    let base_callobj = self_.state.scope.last().unwrap().clone();
+    let mut callobj = CallObject::new_with_this((*base_callobj.this).clone());
+    // The variables of the scope are the properties of the object.
+    callobj.vals = vals;
+    callobj.parent = Some(base_callobj);
+
+    self_.state.scope.push(callobj);
+    Ok(true)
+}
+
 fn pop_scope(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1;
     self_.state.scope.pop();
     Ok(true)
 }
 
+#[inline(always)]
 fn double(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // double
     let stack_top_val = self_.state.stack.last().unwrap().clone();
@@ -1223,6 +2521,23 @@ fn double(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     Ok(true)
 }
 
+fn double2(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // double2
+    let len = self_.state.stack.len();
+    let top2 = self_.state.stack[len - 2..].to_vec();
+    self_.state.stack.extend(top2);
+    Ok(true)
+}
+
+fn rotate(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, Rotate { n as usize });
+    let top = self_.state.stack.pop().unwrap();
+    let len = self_.state.stack.len();
+    self_.state.stack.insert(len - n, top);
+    Ok(true)
+}
+
+#[inline(always)]
 fn pop(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1; // double
     self_.state.stack.pop();
@@ -1246,32 +2561,178 @@ fn update_parent_scope(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, Runtime
     if let Some(Value::Object(_, ObjectKind::Function(box (_, ref mut callobj)))) =
         self_.state.stack.last_mut()
     {
+        gc::write_barrier(&mut **callobj);
         callobj.parent = Some(self_.state.scope.last().unwrap().clone());
     }
     Ok(true)
 }
 
+fn create_closure(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    self_.state.pc += 1; // create_closure
+    let func = self_.state.stack.pop().unwrap();
+    let closure = self_.new_closure(&func);
+    self_.state.stack.push(closure);
+
+    gc::collect_if_needed(self_);
+    gc::check_heap_limit(self_)?;
+
+    Ok(true)
+}
+
+#[inline(always)]
+fn get_upvalue(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, GetUpvalue { id as usize });
+    let val = match *self_.upvalue(id) {
+        Upvalue::Open(index) => self_
+            .state
+            .regs
+            .get(index)
+            .cloned()
+            .unwrap_or(Value::Undefined),
+        Upvalue::Closed(ref val) => val.clone(),
+    };
+    self_.state.stack.push(val);
+    Ok(true)
+}
+
+#[inline(always)]
+fn set_upvalue(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, SetUpvalue { id as usize });
+    let val = self_.state.stack.pop().unwrap();
+    let mut upvalue = self_.upvalue(id);
+    match *upvalue {
+        Upvalue::Open(index) => {
+            let regs = &mut self_.state.regs;
+            if index >= regs.len() {
+                regs.resize(index + 1, Value::Undefined);
+            }
+            regs[index] = val;
+            return Ok(true);
+        }
+        Upvalue::Closed(_) => {}
+    }
+    gc::write_barrier(&mut *upvalue);
+    *upvalue = Upvalue::Closed(val);
+    Ok(true)
+}
+
+#[inline(always)]
 fn get_value(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, name_id, usize);
-    let name = &self_.codegen.bytecode_gen.const_table.string[name_id];
+    decode!(self_, iseq, GetValue { name as usize });
+    let name = self_.codegen.bytecode_gen.const_table.string[name].as_str();
+    let val = self_.state.scope.last().unwrap().get_value(name)?;
+    self_.state.stack.push(val);
+    Ok(true)
+}
+
+/// GET_GLOBAL is compiled for the variables which can only be of the global object (see
+/// VMCodeGen::gen_get_variable()), so it skips the scopes between.
+#[inline(always)]
+fn get_global(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, GetGlobal { name as usize, cache as usize });
+    let name = self_.codegen.bytecode_gen.const_table.string[name].as_str();
+    let global = self_.state.scope[0].vals.clone();
+    match self_.global_cache(cache).lookup(&global, name) {
+        Some(val) => self_.state.stack.push(val),
+        None => {
+            return Err(RuntimeError::Reference(format!(
+                "reference error: '{}' is not defined",
+                name
+            )))
+        }
+    }
+    Ok(true)
+}
+
+#[inline(always)]
+fn get_value_reg(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, GetValueReg { r as usize, name as usize });
+    let name = self_.codegen.bytecode_gen.const_table.string[name].as_str();
     let val = self_.state.scope.last().unwrap().get_value(name)?;
+    self_.state.set_reg(r, val);
+    Ok(true)
+}
+
+#[inline(always)]
+fn push_reg(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, PushReg { r as usize });
+    let val = self_.state.reg(r);
     self_.state.stack.push(val);
     Ok(true)
 }
 
+#[inline(always)]
+fn pop_reg(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, PopReg { r as usize });
+    let val = self_.state.stack.pop().unwrap();
+    self_.state.set_reg(r, val);
+    Ok(true)
+}
+
+#[inline(always)]
+fn load_const_reg(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, LoadConstReg { r as usize, id as usize });
+    let val = self_.codegen.bytecode_gen.const_table.value[id].clone();
+    self_.state.set_reg(r, val);
+    Ok(true)
+}
+
+/// r_dst = op(r_lhs, r_rhs)
+#[inline(always)]
+fn binary_op_reg(
+    self_: &mut VM,
+    iseq: &ByteCode,
+    op: fn(Value, Value) -> Result<Value, RuntimeError>,
+) -> Result<bool, RuntimeError> {
+    let (dst, lhs, rhs) = match self_.fetch_inst(iseq) {
+        Inst::AddReg { dst, lhs, rhs }
+        | Inst::SubReg { dst, lhs, rhs }
+        | Inst::MulReg { dst, lhs, rhs }
+        | Inst::DivReg { dst, lhs, rhs }
+        | Inst::RemReg { dst, lhs, rhs }
+        | Inst::LtReg { dst, lhs, rhs }
+        | Inst::GtReg { dst, lhs, rhs }
+        | Inst::LeReg { dst, lhs, rhs }
+        | Inst::GeReg { dst, lhs, rhs } => (dst as usize, lhs as usize, rhs as usize),
+        inst => unreachable!("{:?} is not a binary operation on registers", inst),
+    };
+    let val = op(self_.state.reg(lhs), self_.state.reg(rhs))?;
+    self_.state.set_reg(dst, val);
+    Ok(true)
+}
+
+#[inline(always)]
 fn set_value(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, name_id, usize);
-    let name = self_.codegen.bytecode_gen.const_table.string[name_id].clone();
-    let mut val = self_.state.stack.pop().unwrap();
+    decode!(self_, iseq, SetValue { name as usize });
+    assign_value(self_, name);
+    Ok(true)
+}
 
-    // We have to change cobj.this to the current scope one. (./examples/this.js)
-    if let Value::Object(_, ObjectKind::Function(box (_, ref mut cobj)))
-    | Value::Object(_, ObjectKind::BuiltinFunction(box (_, ref mut cobj))) = &mut val
+#[inline(always)]
+fn set_value_strict(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    decode!(self_, iseq, SetValueStrict { name as usize });
     {
-        cobj.this = self_.state.scope.last().unwrap().this.clone();
+        let name = self_.codegen.bytecode_gen.const_table.string[name].as_str();
+        // ReferenceError if 'name' is not declared, and TypeError if it is read-only.
+        let scope = self_.state.scope.last().unwrap();
+        scope.get_value(name)?;
+        if !scope.is_writable(name) {
+            return Err(RuntimeError::Type(format!(
+                "type error: Cannot assign to read only variable '{}'",
+                name
+            )));
+        }
     }
+    assign_value(self_, name);
+    Ok(true)
+}
+
+/// Pop a value and assign it to the variable of the name 'name_id' for SET_VALUE and
+/// SET_VALUE_STRICT.
+#[inline(always)]
+fn assign_value(self_: &mut VM, name_id: usize) {
+    let name = self_.codegen.bytecode_gen.const_table.string[name_id].to_string();
+    let val = self_.state.stack.pop().unwrap();
 
     self_
         .state
@@ -1279,30 +2740,32 @@ fn set_value(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
         .last_mut()
         .unwrap()
         .set_value_if_exist(name, val);
-
-    Ok(true)
 }
 
 fn decl_var(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, name_id, usize);
-    let name = self_.codegen.bytecode_gen.const_table.string[name_id].clone();
-    (*self_.state.scope.last_mut().unwrap()).set_value(name, Value::Undefined);
+    decode!(self_, iseq, DeclVar { name as usize });
+    let name = self_.codegen.bytecode_gen.const_table.string[name].to_string();
+    let scope = self_.state.scope.last_mut().unwrap();
+    // Declaring an existing variable again does not change it. e.g. 'var x' for a parameter x
+    if !scope.vals.contains_key(&name) {
+        scope.set_value(name, Value::Undefined);
+    }
     Ok(true)
 }
 
 // 'cond_op' is for JIT compiler. Nope for VM.
+#[inline(always)]
 fn cond_op(self_: &mut VM, _iseq: &ByteCode) -> Result<bool, RuntimeError> {
     self_.state.pc += 1;
     Ok(true)
 }
 
+#[cfg(feature = "jit")]
 fn loop_start(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
     let loop_start = self_.state.pc as usize;
 
-    self_.state.pc += 1;
-    get_int32!(self_, iseq, relatinal_loop_end, usize);
-    let loop_end = loop_start + relatinal_loop_end;
+    decode!(self_, iseq, LoopStart { end as usize });
+    let loop_end = loop_start + end;
 
     let id = self_.cur_func_id;
 
@@ -1323,3 +2786,10 @@ fn loop_start(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
 
     Ok(true)
 }
+
+#[cfg(not(feature = "jit"))]
+fn loop_start(self_: &mut VM, iseq: &ByteCode) -> Result<bool, RuntimeError> {
+    // The end of the loop is only used by the JIT.
+    self_.fetch_inst(iseq);
+    Ok(true)
+}
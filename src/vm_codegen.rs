@@ -1,5 +1,15 @@
 use bytecode_gen::{ByteCode, ByteCodeGen, VMInst};
-use node::{BinOp, FormalParameter, FormalParameters, Node, NodeBase, PropertyDefinition, UnaryOp};
+use feature_gate;
+use lower;
+use node::{
+    BinOp, FormalParameter, FormalParameters, MethodDefinitionKind, Node, NodeBase,
+    PropertyDefinition, UnaryOp,
+};
+use optimizer;
+use resolver::{self, Binding, FunctionScope};
+use sourcemap::FunctionPositions;
+use std::rc::Rc;
+use verifier;
 use vm::callobj::CallObject;
 use vm::value::*;
 
@@ -15,6 +25,7 @@ pub enum Level {
     Try { return_instr_pos: Vec<isize> },
     Catch { return_instr_pos: Vec<isize> },
     Finally,
+    With,
 }
 
 #[derive(Clone, Debug)]
@@ -28,37 +39,77 @@ pub struct Jumps {
 pub struct JumpToGlobalLabel {
     continue_inst_positions: Vec<(String, isize)>,
     break_inst_positions: Vec<(String, isize)>,
+    /// scope depth (see VMCodeGen::scope_depth()) of each label being compiled.
+    scope_depths: Vec<(String, usize)>,
 }
 
 #[derive(Clone, Debug)]
 pub struct JumpFromLoop {
     continue_inst_positions: Vec<isize>,
     break_inst_positions: Vec<isize>,
-}
-
-#[derive(Clone, Debug)]
-pub enum FunctionHeaderInst {
-    Closure(String, Value),
-    DeclVar(String),
+    /// scope depth (see VMCodeGen::scope_depth()) of the loop.
+    scope_depth: usize,
 }
 
 #[derive(Clone, Debug)]
 pub struct VMCodeGen {
     pub global_varmap: CallObjectRef,
-    pub func_header_info: Vec<Vec<FunctionHeaderInst>>,
+    /// Names of the variables and functions declared in each function being compiled, which are
+    /// hoisted to the beginning of the function. The last one is of the innermost function.
+    pub var_names: Vec<Vec<String>>,
     pub bytecode_gen: ByteCodeGen,
     pub labels: Jumps,
     pub level: Vec<Level>,
+    /// Compile arithmetic and comparison into register-addressed instructions.
+    pub use_registers: bool,
+    /// Run the peephole optimizer on the generated code.
+    pub optimize: bool,
+    /// Keep the local variables of functions in registers where possible (see resolver.rs).
+    /// Turned off for the debugger, which looks variables up by name.
+    pub resolve_locals: bool,
+    /// Whether the code being compiled runs in the global scope, so that the global variables
+    /// can be read from the global object directly. Direct eval and modules run in other scopes.
+    pub global_scope: bool,
+    /// the variables of the functions being compiled, from the outermost one.
+    scopes: Vec<FunctionScope>,
+    /// The lowest register not used by the expression being compiled in the current function.
+    next_register: usize,
+    /// Whether the code being compiled is strict mode code.
+    strict: bool,
+    /// Record the source positions of the compiled code in function_positions, for source
+    /// maps.
+    pub record_positions: bool,
+    /// source positions of the code compiled so far. See sourcemap::FunctionPositions.
+    pub function_positions: Vec<FunctionPositions>,
+    /// (pc, source position) of the code of each function being compiled. The last one is of
+    /// the innermost function.
+    pcs: Vec<Vec<(usize, usize)>>,
+    /// source positions of the nodes being compiled.
+    node_positions: Vec<usize>,
 }
 
+/// Register operands are one byte.
+const MAX_REGISTERS: usize = 256;
+
 impl VMCodeGen {
     pub fn new(global: CallObjectRef) -> VMCodeGen {
         VMCodeGen {
             global_varmap: global,
-            func_header_info: vec![vec![]],
+            var_names: vec![vec![]],
             bytecode_gen: ByteCodeGen::new(),
             labels: Jumps::new(),
             level: vec![],
+            use_registers: false,
+            optimize: true,
+            resolve_locals: true,
+            global_scope: true,
+            scopes: vec![],
+            next_register: 0,
+            strict: false,
+            record_positions: false,
+            function_positions: vec![],
+            pcs: vec![],
+            node_positions: vec![],
         }
     }
 }
@@ -70,18 +121,87 @@ impl VMCodeGen {
         iseq: &mut ByteCode,
         use_value: bool,
     ) -> Result<(), Error> {
+        // The constructs which cannot be compiled are reported before generating any code.
+        feature_gate::check(node)?;
+        // The code generator only handles the core nodes.
+        let node = &lower::lower(node);
+
         self.bytecode_gen.gen_create_context(iseq);
 
-        self.run(node, iseq, use_value)?;
+        self.strict = node.has_use_strict_directive();
+        // The top-level code has no local variables. Scopes may be left by a compile error.
+        self.scopes.clear();
+
+        self.pcs.push(vec![]);
+        let res = self.run(node, iseq, use_value);
+        let mut pcs = self.pcs.pop().unwrap();
+        res?;
 
         self.bytecode_gen.gen_end(iseq);
 
-        self.set_function_header(iseq);
+        self.set_function_header(iseq, &mut pcs);
+
+        self.run_optimizer(iseq, &mut pcs);
+        self.verify(iseq, None, node.pos)?;
+
+        self.add_function_positions(None, "", node.pos, pcs);
 
         Ok(())
     }
 
+    /// Compile 'node'. The code is recorded to be at the position of the node, and the code
+    /// after the nodes in it, e.g. CALL after the arguments, is at the position again.
     fn run(&mut self, node: &Node, iseq: &mut ByteCode, use_value: bool) -> Result<(), Error> {
+        if !self.record_positions {
+            return self.run_node(node, iseq, use_value);
+        }
+        self.node_positions.push(node.pos);
+        self.add_position(iseq.len(), node.pos);
+        let res = self.run_node(node, iseq, use_value);
+        self.node_positions.pop();
+        if let Some(&pos) = self.node_positions.last() {
+            self.add_position(iseq.len(), pos);
+        }
+        res
+    }
+
+    fn add_position(&mut self, pc: usize, pos: usize) {
+        let pcs = match self.pcs.last_mut() {
+            Some(pcs) => pcs,
+            None => return,
+        };
+        match pcs.last_mut() {
+            Some(last) if last.0 == pc => last.1 = pos,
+            Some(last) if last.1 == pos => {}
+            _ => pcs.push((pc, pos)),
+        }
+    }
+
+    fn add_function_positions(
+        &mut self,
+        const_index: Option<usize>,
+        name: &str,
+        pos: usize,
+        pcs: Vec<(usize, usize)>,
+    ) {
+        if self.record_positions {
+            self.function_positions.push(FunctionPositions {
+                const_index: const_index,
+                name: name.to_string(),
+                pos: pos,
+                pcs: pcs,
+            });
+        }
+    }
+
+    fn run_node(&mut self, node: &Node, iseq: &mut ByteCode, use_value: bool) -> Result<(), Error> {
+        // The value of an expression statement such as 'x;' is not used, but is pushed anyway.
+        if !use_value && leaves_value(node) {
+            self.run_node(node, iseq, true)?;
+            self.bytecode_gen.gen_pop(iseq);
+            return Ok(());
+        }
+
         if let Some(constant) = node.base.fold_num_consts() {
             match constant {
                 NodeBase::String(ref s) => self
@@ -99,23 +219,31 @@ impl VMCodeGen {
             &NodeBase::StatementList(ref node_list) => {
                 self.run_statement_list(node_list, iseq, use_value)?
             }
-            &NodeBase::FunctionDecl(ref name, ref params, ref body) => {
-                self.run_function_decl(name, params, &*body)?
+            // Function declarations in statement lists are initialized by run_statement_list().
+            &NodeBase::FunctionDecl(ref name, ref params, ref body, ref source, _) => {
+                self.run_function_decl(name, params, &*body, source, iseq)?
             }
-            &NodeBase::FunctionExpr(ref name, ref params, ref body) => {
-                self.run_function_expr(name, params, &*body, iseq)?
+            &NodeBase::FunctionExpr(ref name, ref params, ref body, ref source, _) => {
+                self.run_function_expr(name, params, &*body, source, iseq)?
             }
             &NodeBase::VarDecl(ref name, ref init) => self.run_var_decl(name, init, iseq)?,
             &NodeBase::If(ref cond, ref then_, ref else_) => {
                 self.run_if(&*cond, &*then_, &*else_, iseq)?
             }
-            &NodeBase::While(ref cond, ref body) => self.run_while(&*cond, &*body, iseq)?,
+            &NodeBase::While(_, _) => unreachable!("lowered by lower::lower()"),
             &NodeBase::For(ref init, ref cond, ref step, ref body) => {
                 self.run_for(&*init, &*cond, &*step, &*body, iseq)?
             }
+            // 'for await', 'await' and 'yield' are only in async functions and generators.
+            &NodeBase::ForAwaitOf(_, _, _) | &NodeBase::Await(_) | &NodeBase::Yield(_, _) => {
+                unreachable!("rejected by feature_gate::check()")
+            }
             &NodeBase::Assign(ref dst, ref src) => {
                 self.run_assign(&*dst, &*src, iseq, use_value)?
             }
+            &NodeBase::CompoundAssign(ref dst, ref rhs, ref op) => {
+                self.run_compound_assign(&*dst, &*rhs, op, iseq, use_value)?
+            }
             &NodeBase::UnaryOp(ref expr, ref op) => {
                 self.run_unary_op(&*expr, op, iseq, use_value)?
             }
@@ -125,6 +253,7 @@ impl VMCodeGen {
             &NodeBase::TernaryOp(ref cond, ref then, ref else_) => {
                 self.run_ternary_op(&*cond, &*then, &*else_, iseq)?
             }
+            &NodeBase::With(ref object, ref body) => self.run_with(&*object, &*body, iseq)?,
             &NodeBase::Label(ref name, ref body) => self.run_label(name, &*body, iseq)?,
             &NodeBase::Call(ref callee, ref args) => {
                 self.run_call(&*callee, args, iseq, use_value)?
@@ -141,6 +270,12 @@ impl VMCodeGen {
             &NodeBase::New(ref expr) => self.run_new_expr(&*expr, iseq)?,
             &NodeBase::Object(ref properties) => self.run_object_literal(properties, iseq)?,
             &NodeBase::Array(ref properties) => self.run_array_literal(properties, iseq)?,
+            &NodeBase::Template(ref cooked, _, ref substitutions) => {
+                self.run_template(cooked, substitutions, iseq)?
+            }
+            &NodeBase::TaggedTemplate(ref tag, ref template) => {
+                self.run_tagged_template(&*tag, &*template, iseq, use_value)?
+            }
             &NodeBase::Identifier(ref name) => self.run_identifier(name, iseq)?,
             &NodeBase::This => self.bytecode_gen.gen_push_this(iseq),
             &NodeBase::Arguments => self.bytecode_gen.gen_push_arguments(iseq),
@@ -150,6 +285,9 @@ impl VMCodeGen {
                 .bytecode_gen
                 .gen_push_const(Value::string(s.clone()), iseq),
             &NodeBase::Number(n) => self.bytecode_gen.gen_push_number(n, iseq),
+            &NodeBase::BigInt(ref n) => self
+                .bytecode_gen
+                .gen_push_const(Value::bigint(n.clone()), iseq),
             &NodeBase::Boolean(b) => self.bytecode_gen.gen_push_bool(b, iseq),
             &NodeBase::Nope if use_value => {
                 self.bytecode_gen.gen_push_const(Value::empty(), iseq);
@@ -162,6 +300,8 @@ impl VMCodeGen {
 }
 
 impl VMCodeGen {
+    /// Function declarations in the statement list are initialized before the statements run.
+    /// Their names are declared at the beginning of the function, like 'var'.
     pub fn run_statement_list(
         &mut self,
         node_list: &Vec<Node>,
@@ -169,7 +309,17 @@ impl VMCodeGen {
         use_value: bool,
     ) -> Result<(), Error> {
         for node in node_list {
-            self.run(node, iseq, use_value)?;
+            if let NodeBase::FunctionDecl(ref name, ref params, ref body, ref source, _) = node.base
+            {
+                self.run_function_decl(name, params, &*body, source, iseq)?;
+            }
+        }
+
+        for node in node_list {
+            match node.base {
+                NodeBase::FunctionDecl(_, _, _, _, _) => {}
+                _ => self.run(node, iseq, use_value)?,
+            }
         }
 
         Ok(())
@@ -177,55 +327,170 @@ impl VMCodeGen {
 }
 
 impl VMCodeGen {
-    fn set_function_header(&mut self, iseq: &mut ByteCode) {
+    fn run_optimizer(&mut self, iseq: &mut ByteCode, pcs: &mut Vec<(usize, usize)>) {
+        if self.optimize {
+            optimizer::optimize_with_positions(iseq, &mut self.bytecode_gen.const_table, pcs);
+        }
+    }
+
+    /// Verify the code generated in debug builds, to find a bug of the code generator before
+    /// the code runs. See verifier.rs.
+    fn verify(&self, iseq: &ByteCode, upvalues: Option<usize>, pos: usize) -> Result<(), Error> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+        verifier::verify(iseq, &self.bytecode_gen.const_table, upvalues).map_err(|msg| {
+            Error::General {
+                msg: format!("error: invalid bytecode generated: {}", msg),
+                token_pos: pos,
+            }
+        })
+    }
+
+    /// Hoist 'name' to the beginning of the function being compiled.
+    fn declare_var(&mut self, name: &String) {
+        let var_names = self.var_names.last_mut().unwrap();
+        if !var_names.contains(name) {
+            var_names.push(name.clone());
+        }
+    }
+
+    /// Declare the variables and functions of the function at its beginning. Declaring does not
+    /// change a variable which already exists, such as a parameter. Variables in slots need no
+    /// declaration, since registers are undefined until they are assigned.
+    fn set_function_header(&mut self, iseq: &mut ByteCode, pcs: &mut Vec<(usize, usize)>) {
         let mut section_callobj_set = vec![];
-        let func_header_info = self.func_header_info.last_mut().unwrap();
-        while let Some(header) = func_header_info.pop() {
-            match header {
-                FunctionHeaderInst::Closure(func_name, val) => {
-                    self.bytecode_gen
-                        .gen_decl_var(&func_name, &mut section_callobj_set);
-                    self.bytecode_gen
-                        .gen_push_const(val, &mut section_callobj_set);
-                    self.bytecode_gen
-                        .gen_update_parent_scope(&mut section_callobj_set);
-                    self.bytecode_gen
-                        .gen_set_value(&func_name, &mut section_callobj_set);
-                }
-                FunctionHeaderInst::DeclVar(var_name) => {
-                    self.bytecode_gen
-                        .gen_decl_var(&var_name, &mut section_callobj_set);
-                }
+        let var_names = ::std::mem::replace(self.var_names.last_mut().unwrap(), vec![]);
+        for name in &var_names {
+            if let Binding::Slot(_) = self.resolve(name) {
+                continue;
+            }
+            self.bytecode_gen
+                .gen_decl_var(name, &mut section_callobj_set);
+        }
+        for pc in pcs.iter_mut() {
+            if pc.0 >= 1 {
+                pc.0 += section_callobj_set.len();
             }
         }
         iseq.splice(1..1, section_callobj_set);
     }
 
-    /// function name(params...) { body }
-    pub fn run_function_decl(
+    /// How 'name' refers to its variable in the function being compiled.
+    fn resolve(&mut self, name: &str) -> Binding {
+        resolver::resolve(&mut self.scopes, name)
+    }
+
+    /// Whether the global variables can only be in the global object where the code being
+    /// compiled is, so that GET_GLOBAL can read them. The scopes of with statements and catch
+    /// clauses may bind them as well.
+    fn in_global_object(&self) -> bool {
+        self.global_scope
+            && self.resolve_locals
+            && !self.level.iter().any(|level| match level {
+                Level::With | Level::Catch { .. } => true,
+                _ => false,
+            })
+    }
+
+    /// Push the value of the variable 'name'.
+    fn gen_get_variable(&mut self, name: &String, iseq: &mut ByteCode) {
+        match self.resolve(name) {
+            Binding::Slot(r) => self.bytecode_gen.gen_push_reg(r, iseq),
+            Binding::Upvalue(n) => self.bytecode_gen.gen_get_upvalue(n, iseq),
+            Binding::Global if self.in_global_object() => {
+                self.bytecode_gen.gen_get_global(name, iseq)
+            }
+            Binding::Scope | Binding::Global => self.bytecode_gen.gen_get_value(name, iseq),
+        }
+    }
+
+    /// Pop a value into the variable 'name', which is declared.
+    fn gen_set_variable(&mut self, name: &String, iseq: &mut ByteCode) {
+        match self.resolve(name) {
+            Binding::Slot(r) => self.bytecode_gen.gen_pop_reg(r, iseq),
+            Binding::Upvalue(n) => self.bytecode_gen.gen_set_upvalue(n, iseq),
+            Binding::Scope | Binding::Global => self.bytecode_gen.gen_set_value(name, iseq),
+        }
+    }
+
+    /// Number of scopes pushed by catch clauses and with statements in the current function.
+    /// They have to be popped when a jump leaves them.
+    fn scope_depth(&self) -> usize {
+        self.level
+            .iter()
+            .rev()
+            .take_while(|level| match level {
+                Level::Function => false,
+                _ => true,
+            })
+            .filter(|level| match level {
+                Level::Catch { .. } | Level::With => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Compile a function and make the function object. 'source' is kept for toString(). Also
+    /// returns whether its closures need the scope where they are made (see gen_closure()).
+    fn compile_function(
         &mut self,
-        name: &String,
+        name: &str,
         params: &FormalParameters,
         body: &Node,
-    ) -> Result<(), Error> {
-        self.func_header_info.push(vec![]);
+        source: &String,
+    ) -> Result<(Value, bool), Error> {
+        self.var_names.push(vec![]);
+        self.scopes
+            .push(FunctionScope::new(params, body, self.resolve_locals));
+
+        // A function in strict mode code, or with its own 'use strict', is strict.
+        let outer_strict = self.strict;
+        self.strict = self.strict || body.has_use_strict_directive();
+        let this = if self.strict {
+            Value::Undefined
+        } else {
+            Value::object(self.global_varmap.vals.clone())
+        };
+        let new_callobj = CallObject::new_with_this(this);
 
-        let new_callobj = CallObject::new_with_this(Value::object(self.global_varmap.vals.clone()));
         let mut func_iseq = vec![];
 
         self.bytecode_gen.gen_create_context(&mut func_iseq);
 
+        // The parameters in slots are copied from the call object.
+        for param in params {
+            if let Binding::Slot(r) = self.resolve(&param.name) {
+                self.bytecode_gen.gen_get_value(&param.name, &mut func_iseq);
+                self.bytecode_gen.gen_pop_reg(r, &mut func_iseq);
+            }
+        }
+
         self.level.push(Level::Function);
+        // A function has its own registers, which begin with the slots.
+        let next_register = self.next_register;
+        self.next_register = self.scopes.last().unwrap().slot_count();
 
-        self.run(body, &mut func_iseq, false)?;
+        self.pcs.push(vec![]);
+        let res = self.run(body, &mut func_iseq, false);
+        let mut pcs = self.pcs.pop().unwrap();
+        res?;
 
+        self.next_register = next_register;
         self.level.pop();
+        let strict = self.strict;
+        self.strict = outer_strict;
 
         if !body.definitely_returns() {
             self.bytecode_gen.gen_push_undefined(&mut func_iseq);
             self.bytecode_gen.gen_return(&mut func_iseq);
         }
 
+        // The number of parameters before the first one with a default value or the rest one.
+        let length = params
+            .iter()
+            .take_while(|param| param.init.is_none() && !param.is_rest_param)
+            .count();
         let params = params
             .clone()
             .iter()
@@ -238,70 +503,90 @@ impl VMCodeGen {
             )
             .collect();
 
-        self.set_function_header(&mut func_iseq);
+        self.set_function_header(&mut func_iseq, &mut pcs);
 
-        let val = Value::function(func_iseq.clone(), params, new_callobj);
+        self.run_optimizer(&mut func_iseq, &mut pcs);
 
-        self.func_header_info.pop();
+        self.var_names.pop();
+        let scope = self.scopes.pop().unwrap();
+        self.verify(&func_iseq, Some(scope.upvalue_sources().len()), body.pos)?;
 
-        self.func_header_info
-            .last_mut()
-            .unwrap()
-            .push(FunctionHeaderInst::Closure(name.clone(), val));
+        // The function object is pushed to the constant table next.
+        let const_index = self.bytecode_gen.const_table.value.len();
+        let map_name = if name.is_empty() { "<anonymous>" } else { name };
+        self.add_function_positions(Some(const_index), map_name, body.pos, pcs);
 
-        Ok(())
+        let mut val = Value::function(
+            func_iseq,
+            params,
+            strict,
+            Some(Rc::new(source.clone())),
+            name.to_string(),
+            length,
+            new_callobj,
+        );
+        if let Value::Object(_, ObjectKind::Function(box (ref mut func_info, _))) = val {
+            func_info.upvalues = Rc::new(scope.upvalue_sources());
+        }
+        Ok((val, scope.uses_outer_scope()))
     }
 
-    /// function(params) { body }
-    pub fn run_function_expr(
+    /// Push a closure of the function 'val' made by compile_function(). It gets the current
+    /// scope as its outer scope if it looks variables up by name in the scope, which is always
+    /// so at the top level and in catch clauses and with statements.
+    fn gen_closure(&mut self, val: Value, uses_outer_scope: bool, iseq: &mut ByteCode) {
+        self.bytecode_gen.gen_push_const(val, iseq);
+        self.bytecode_gen.gen_create_closure(iseq);
+        if uses_outer_scope || self.scopes.is_empty() || self.scope_depth() > 0 {
+            self.bytecode_gen.gen_update_parent_scope(iseq);
+        }
+    }
+
+    /// function name(params...) { body }
+    pub fn run_function_decl(
         &mut self,
-        // TODO: _name should be used.
-        _name: &Option<String>,
+        name: &String,
         params: &FormalParameters,
         body: &Node,
+        source: &String,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
-        self.func_header_info.push(vec![]);
-
-        let new_callobj = CallObject::new_with_this(Value::object(self.global_varmap.vals.clone()));
+        let (val, uses_outer_scope) = self.compile_function(name, params, body, source)?;
 
-        let mut func_iseq = vec![];
+        self.declare_var(name);
+        self.gen_closure(val, uses_outer_scope, iseq);
+        self.gen_set_variable(name, iseq);
 
-        self.bytecode_gen.gen_create_context(&mut func_iseq);
+        Ok(())
+    }
 
-        self.level.push(Level::Function);
+    /// function(params) { body }
+    pub fn run_function_expr(
+        &mut self,
+        name: &Option<String>,
+        params: &FormalParameters,
+        body: &Node,
+        source: &String,
+        iseq: &mut ByteCode,
+    ) -> Result<(), Error> {
+        let name = name.as_ref().map_or("", |name| name.as_str());
+        let (val, uses_outer_scope) = self.compile_function(name, params, body, source)?;
 
-        self.run(body, &mut func_iseq, false)?;
+        self.gen_closure(val, uses_outer_scope, iseq);
 
-        self.level.pop();
+        Ok(())
+    }
 
-        if !body.definitely_returns() {
-            self.bytecode_gen.gen_push_undefined(&mut func_iseq);
-            self.bytecode_gen.gen_return(&mut func_iseq);
+    /// Evaluate 'node' to be bound to 'name'. An anonymous function expression gets 'name' as
+    /// its name. e.g. 'var f = function() {}' makes a function named 'f'.
+    /// https://tc39.github.io/ecma262/#sec-isanonymousfunctiondefinition
+    fn run_named(&mut self, node: &Node, name: &String, iseq: &mut ByteCode) -> Result<(), Error> {
+        match node.base {
+            NodeBase::FunctionExpr(None, ref params, ref body, ref source, _) => {
+                self.run_function_expr(&Some(name.clone()), params, &*body, source, iseq)
+            }
+            _ => self.run(node, iseq, true),
         }
-
-        let params = params
-            .clone()
-            .iter()
-            .map(
-                |FormalParameter {
-                     name,
-                     is_rest_param,
-                     ..
-                 }| (name.clone(), *is_rest_param),
-            )
-            .collect();
-
-        self.set_function_header(&mut func_iseq);
-
-        let val = Value::function(func_iseq.clone(), params, new_callobj);
-
-        self.bytecode_gen.gen_push_const(val, iseq);
-        self.bytecode_gen.gen_update_parent_scope(iseq);
-
-        self.func_header_info.pop();
-
-        Ok(())
     }
 
     pub fn run_return(
@@ -309,6 +594,21 @@ impl VMCodeGen {
         val: &Option<Box<Node>>,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
+        // 'return f(x)' directly in a function body is a tail call. When f is the function
+        // itself, TAIL_CALL reuses the frame, so that tail recursion runs in constant stack.
+        if let (Some(Level::Function), &Some(ref val)) = (self.level.last(), val) {
+            if let NodeBase::Call(ref callee, ref args) = val.base {
+                self.run_call(&*callee, args, iseq, true)?;
+                let len = iseq.len();
+                if iseq[len - 1 - 4] == VMInst::CALL {
+                    iseq[len - 1 - 4] = VMInst::TAIL_CALL;
+                } else {
+                    self.bytecode_gen.gen_return(iseq);
+                }
+                return Ok(());
+            }
+        }
+
         if let &Some(ref val) = val {
             self.run(&*val, iseq, true)?
         } else {
@@ -320,20 +620,25 @@ impl VMCodeGen {
         } else {
             for level in self.level.iter_mut().rev() {
                 match level {
-                    Level::Catch { .. } => {
+                    Level::Catch { .. } | Level::With => {
                         self.bytecode_gen.gen_pop_scope(iseq);
                     }
                     Level::Function => break,
                     _ => {}
                 }
             }
-            match self.level.last_mut().unwrap() {
-                Level::Catch {
+            // with statements in try-catch do not change how to return.
+            let level = self.level.iter_mut().rev().find(|level| match level {
+                Level::With => false,
+                _ => true,
+            });
+            match level {
+                Some(Level::Catch {
                     ref mut return_instr_pos,
-                }
-                | Level::Try {
+                })
+                | Some(Level::Try {
                     ref mut return_instr_pos,
-                } => {
+                }) => {
                     return_instr_pos.push(iseq.len() as isize);
                     self.bytecode_gen.gen_return_try(iseq);
                 }
@@ -356,6 +661,8 @@ impl VMCodeGen {
 
 impl VMCodeGen {
     pub fn run_break(&mut self, name: &Option<String>, iseq: &mut ByteCode) -> Result<(), Error> {
+        self.pop_scopes_to_jump(name, iseq);
+
         let break_inst_pos = iseq.len() as isize;
         self.bytecode_gen.gen_jmp(0, iseq);
 
@@ -381,6 +688,8 @@ impl VMCodeGen {
         name: &Option<String>,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
+        self.pop_scopes_to_jump(name, iseq);
+
         let continue_inst_pos = iseq.len() as isize;
         self.bytecode_gen.gen_jmp(0, iseq);
 
@@ -400,19 +709,36 @@ impl VMCodeGen {
 
         Ok(())
     }
+
+    /// Pop the scopes left by break or continue to the loop or the label 'name'.
+    fn pop_scopes_to_jump(&mut self, name: &Option<String>, iseq: &mut ByteCode) {
+        let dst_depth = match name {
+            Some(name) => self.labels.global.scope_depth_of(name),
+            None => self.labels.local.last().map(|local| local.scope_depth),
+        };
+        if let Some(dst_depth) = dst_depth {
+            for _ in dst_depth..self.scope_depth() {
+                self.bytecode_gen.gen_pop_scope(iseq);
+            }
+        }
+    }
 }
 
 impl VMCodeGen {
+    /// 'expr' is always a Call, as the parser reads 'new F' as 'new F()'. The callee is
+    /// evaluated as a value, so that 'new a.B()' and 'new (f())()' construct what they name.
     pub fn run_new_expr(&mut self, expr: &Node, iseq: &mut ByteCode) -> Result<(), Error> {
-        // TODO: Make sure ``expr`` is Call or Identifier
-        self.run(expr, iseq, true)?;
-
-        let len = iseq.len();
-        if iseq[len - 1 - 4] == VMInst::CALL {
-            iseq[len - 1 - 4] = VMInst::CONSTRUCT;
-        } else {
-            // TODO
-            unreachable!()
+        match expr.base {
+            NodeBase::Call(ref callee, ref args) => {
+                for arg in args.iter().rev() {
+                    self.run(arg, iseq, true)?;
+                }
+                // 'this' operand of CONSTRUCT, which is not used
+                self.bytecode_gen.gen_push_undefined(iseq);
+                self.run(&*callee, iseq, true)?;
+                self.bytecode_gen.gen_constract(args.len(), iseq);
+            }
+            _ => unreachable!(),
         }
 
         Ok(())
@@ -426,18 +752,14 @@ impl VMCodeGen {
         init: &Option<Box<Node>>,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
+        self.declare_var(name);
+
+        // 'var x;' does nothing when it runs. x keeps its value.
         if let &Some(ref init) = init {
-            self.run(&*init, iseq, true)?;
-        } else {
-            self.bytecode_gen.gen_push_undefined(iseq);
+            self.run_named(&*init, name, iseq)?;
+            self.gen_set_variable(name, iseq);
         }
 
-        self.func_header_info
-            .last_mut()
-            .unwrap()
-            .push(FunctionHeaderInst::DeclVar(name.clone()));
-        self.bytecode_gen.gen_set_value(name, iseq);
-
         Ok(())
     }
 }
@@ -485,63 +807,6 @@ impl VMCodeGen {
         Ok(())
     }
 
-    pub fn run_while(
-        &mut self,
-        cond: &Node,
-        body: &Node,
-        iseq: &mut ByteCode,
-    ) -> Result<(), Error> {
-        // name:
-        //   while(...) {} // <- this while is named 'name'
-        let name = self.labels.loop_names.pop();
-
-        let pos1 = iseq.len() as isize;
-        self.labels.make_new_local();
-
-        self.bytecode_gen.gen_loop_start(iseq);
-
-        self.run(cond, iseq, true)?;
-
-        let cond_pos = iseq.len() as isize;
-        self.bytecode_gen.gen_jmp_if_false(0, iseq);
-
-        self.run(body, iseq, false)?;
-
-        let loop_pos = iseq.len() as isize;
-        self.bytecode_gen
-            .gen_jmp((pos1 - loop_pos) as i32 - 5, iseq);
-
-        self.bytecode_gen.replace_int32(
-            iseq.len() as i32 - pos1 as i32,
-            &mut iseq[pos1 as usize + 1..pos1 as usize + 5],
-        );
-
-        let break_pos = iseq.len() as isize;
-        self.labels
-            .cur_local()
-            .replace_break_dsts(&mut self.bytecode_gen, break_pos, iseq);
-
-        self.labels
-            .cur_local()
-            .replace_continue_dsts(&mut self.bytecode_gen, pos1, iseq);
-
-        if let Some(name) = name {
-            self.labels
-                .global
-                .replace_continue_dsts(&mut self.bytecode_gen, &name, pos1, iseq);
-        }
-
-        let pos2 = iseq.len() as isize;
-        self.bytecode_gen.replace_int32(
-            (pos2 - cond_pos) as i32 - 5,
-            &mut iseq[cond_pos as usize + 1..cond_pos as usize + 5],
-        );
-
-        self.labels.pop_local();
-
-        Ok(())
-    }
-
     pub fn run_for(
         &mut self,
         init: &Node,
@@ -557,7 +822,8 @@ impl VMCodeGen {
         self.run(init, iseq, false)?;
 
         let pos = iseq.len() as isize;
-        self.labels.make_new_local();
+        let scope_depth = self.scope_depth();
+        self.labels.make_new_local(scope_depth);
 
         self.bytecode_gen.gen_loop_start(iseq);
 
@@ -615,9 +881,16 @@ impl VMCodeGen {
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
         self.labels.loop_names.push(name.clone());
+        let scope_depth = self.scope_depth();
+        self.labels
+            .global
+            .scope_depths
+            .push((name.clone(), scope_depth));
 
         self.run(body, iseq, false)?;
 
+        self.labels.global.scope_depths.pop();
+
         let break_label_pos = iseq.len() as isize;
         self.labels
             .global
@@ -626,6 +899,26 @@ impl VMCodeGen {
         Ok(())
     }
 }
+impl VMCodeGen {
+    pub fn run_with(
+        &mut self,
+        object: &Node,
+        body: &Node,
+        iseq: &mut ByteCode,
+    ) -> Result<(), Error> {
+        self.run(object, iseq, true)?;
+        self.bytecode_gen.gen_push_with_scope(iseq);
+
+        self.level.push(Level::With);
+        self.run(body, iseq, false)?;
+        self.level.pop();
+
+        self.bytecode_gen.gen_pop_scope(iseq);
+
+        Ok(())
+    }
+}
+
 impl VMCodeGen {
     pub fn run_try(
         &mut self,
@@ -701,11 +994,23 @@ impl VMCodeGen {
         iseq: &mut ByteCode,
         use_value: bool,
     ) -> Result<(), Error> {
+        let is_update = match op {
+            UnaryOp::PrInc | UnaryOp::PoInc | UnaryOp::PrDec | UnaryOp::PoDec => true,
+            _ => false,
+        };
+        match expr.base {
+            NodeBase::Member(_, _) | NodeBase::Index(_, _) if is_update => {
+                return self.run_update_member(expr, op, iseq, use_value)
+            }
+            _ => {}
+        }
+
         self.run(expr, iseq, true)?;
         match op {
             &UnaryOp::Plus => self.bytecode_gen.gen_posi(iseq),
             &UnaryOp::Minus => self.bytecode_gen.gen_neg(iseq),
             &UnaryOp::Not => self.bytecode_gen.gen_lnot(iseq),
+            &UnaryOp::BitwiseNot => self.bytecode_gen.gen_bnot(iseq),
             &UnaryOp::PrInc => {
                 self.bytecode_gen.gen_push_int8(1, iseq);
                 self.bytecode_gen.gen_add(iseq);
@@ -738,12 +1043,7 @@ impl VMCodeGen {
                 self.bytecode_gen.gen_sub(iseq);
                 self.assign_stack_top(expr, iseq)?
             }
-            op => {
-                return Err(Error::Unimplemented {
-                    msg: format!("error: unary operator '{:?}' is unimplemented", op),
-                    token_pos: expr.pos,
-                });
-            }
+            op => unreachable!("{:?} is rejected by feature_gate::check()", op),
         }
 
         Ok(())
@@ -779,6 +1079,10 @@ impl VMCodeGen {
                 self.bytecode_gen.gen_land(iseq);
                 return Ok(());
             }
+            &BinOp::Comma => {
+                self.run(lhs, iseq, false)?;
+                return self.run(rhs, iseq, true);
+            }
             &BinOp::LOr => {
                 self.run(lhs, iseq, true)?;
 
@@ -812,15 +1116,29 @@ impl VMCodeGen {
             _ => {}
         };
 
+        if self.use_registers && reg_inst(op).is_some() && self.free_registers() >= 2 {
+            let r = self.run_binary_op_reg(lhs, rhs, op, iseq)?;
+            self.bytecode_gen.gen_push_reg(r, iseq);
+            self.next_register -= 1;
+            return Ok(());
+        }
+
         self.run(lhs, iseq, true)?;
         self.run(rhs, iseq, true)?;
+        self.gen_binary_op(op, iseq);
+
+        Ok(())
+    }
 
+    /// Apply 'op' to the two values on the top of the stack. '&&' and '||' are not supported.
+    fn gen_binary_op(&mut self, op: &BinOp, iseq: &mut ByteCode) {
         match op {
             &BinOp::Add => self.bytecode_gen.gen_add(iseq),
             &BinOp::Sub => self.bytecode_gen.gen_sub(iseq),
             &BinOp::Mul => self.bytecode_gen.gen_mul(iseq),
             &BinOp::Div => self.bytecode_gen.gen_div(iseq),
             &BinOp::Rem => self.bytecode_gen.gen_rem(iseq),
+            &BinOp::Exp => self.bytecode_gen.gen_exp(iseq),
             &BinOp::Eq => self.bytecode_gen.gen_eq(iseq),
             &BinOp::Ne => self.bytecode_gen.gen_ne(iseq),
             &BinOp::SEq => self.bytecode_gen.gen_seq(iseq),
@@ -836,8 +1154,80 @@ impl VMCodeGen {
             &BinOp::ZFShr => self.bytecode_gen.gen_zfshr(iseq),
             _ => {}
         }
+    }
 
-        Ok(())
+    /// Compile 'lhs op rhs' into a register and return it. At least two registers must be
+    /// free.
+    fn run_binary_op_reg(
+        &mut self,
+        lhs: &Node,
+        rhs: &Node,
+        op: &BinOp,
+        iseq: &mut ByteCode,
+    ) -> Result<u8, Error> {
+        let l = self.run_to_reg(lhs, iseq)?;
+        let r = self.run_to_reg(rhs, iseq)?;
+        self.bytecode_gen
+            .gen_binary_op_reg(reg_inst(op).unwrap(), l, l, r, iseq);
+        self.next_register -= 1;
+        Ok(l)
+    }
+
+    /// Compile 'node' into a newly allocated register and return it.
+    fn run_to_reg(&mut self, node: &Node, iseq: &mut ByteCode) -> Result<u8, Error> {
+        if let Some(NodeBase::Number(n)) = node.base.fold_num_consts() {
+            let r = self.alloc_register();
+            self.bytecode_gen
+                .gen_load_const_reg(r, Value::Number(n), iseq);
+            return Ok(r);
+        }
+
+        match &node.base {
+            &NodeBase::Identifier(ref name) => {
+                let r = self.alloc_register();
+                match self.resolve(name) {
+                    // The slot is copied, since the result of the operation is written to 'r'.
+                    Binding::Slot(slot) => {
+                        self.bytecode_gen.gen_push_reg(slot, iseq);
+                        self.bytecode_gen.gen_pop_reg(r, iseq);
+                    }
+                    Binding::Upvalue(n) => {
+                        self.bytecode_gen.gen_get_upvalue(n, iseq);
+                        self.bytecode_gen.gen_pop_reg(r, iseq);
+                    }
+                    Binding::Global if self.in_global_object() => {
+                        self.bytecode_gen.gen_get_global(name, iseq);
+                        self.bytecode_gen.gen_pop_reg(r, iseq);
+                    }
+                    Binding::Scope | Binding::Global => {
+                        self.bytecode_gen.gen_get_value_reg(r, name, iseq)
+                    }
+                }
+                Ok(r)
+            }
+            &NodeBase::BinaryOp(ref lhs, ref rhs, ref op)
+                if reg_inst(op).is_some() && self.free_registers() >= 2 =>
+            {
+                self.run_binary_op_reg(&*lhs, &*rhs, op, iseq)
+            }
+            _ => {
+                // Other expressions are evaluated on the stack.
+                self.run(node, iseq, true)?;
+                let r = self.alloc_register();
+                self.bytecode_gen.gen_pop_reg(r, iseq);
+                Ok(r)
+            }
+        }
+    }
+
+    fn alloc_register(&mut self) -> u8 {
+        assert!(self.next_register < MAX_REGISTERS);
+        self.next_register += 1;
+        (self.next_register - 1) as u8
+    }
+
+    fn free_registers(&self) -> usize {
+        MAX_REGISTERS - self.next_register
     }
 
     pub fn run_ternary_op(
@@ -847,6 +1237,18 @@ impl VMCodeGen {
         else_: &Node,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
+        // Only the arm chosen by a constant condition is compiled.
+        let constant = match cond.base.fold_num_consts() {
+            Some(NodeBase::Boolean(b)) => Some(b),
+            Some(NodeBase::Number(n)) => Some(n != 0.0 && !n.is_nan()),
+            Some(NodeBase::String(s)) => Some(!s.is_empty()),
+            _ => None,
+        };
+        if let Some(b) = constant {
+            return self.run(if b { then } else { else_ }, iseq, true);
+        }
+
+        // Only one of the arms runs, and leaves its value on the stack.
         self.run(cond, iseq, true)?;
 
         let cond_pos = iseq.len() as isize;
@@ -883,7 +1285,13 @@ impl VMCodeGen {
         iseq: &mut ByteCode,
         use_value: bool,
     ) -> Result<(), Error> {
-        self.run(src, iseq, true)?;
+        match dst.base {
+            NodeBase::Member(_, _) | NodeBase::Index(_, _) => {
+                return self.run_assign_member(dst, src, iseq, use_value)
+            }
+            NodeBase::Identifier(ref name) => self.run_named(src, name, iseq)?,
+            _ => self.run(src, iseq, true)?,
+        }
 
         if use_value {
             self.bytecode_gen.gen_double(iseq);
@@ -894,11 +1302,113 @@ impl VMCodeGen {
         Ok(())
     }
 
-    pub fn assign_stack_top(&mut self, dst: &Node, iseq: &mut ByteCode) -> Result<(), Error> {
+    /// Assign to a member expression. Its object and key are evaluated once, before 'src'.
+    fn run_assign_member(
+        &mut self,
+        dst: &Node,
+        src: &Node,
+        iseq: &mut ByteCode,
+        use_value: bool,
+    ) -> Result<(), Error> {
+        self.run_reference(dst, iseq)?;
+        self.run(src, iseq, true)?;
+        self.set_reference(iseq, use_value);
+        Ok(())
+    }
+
+    /// Compile 'dst op= rhs'. The object and the key of a member expression are evaluated once.
+    fn run_compound_assign(
+        &mut self,
+        dst: &Node,
+        rhs: &Node,
+        op: &BinOp,
+        iseq: &mut ByteCode,
+        use_value: bool,
+    ) -> Result<(), Error> {
         match dst.base {
-            NodeBase::Identifier(ref name) => {
-                self.bytecode_gen.gen_set_value(name, iseq);
+            NodeBase::Member(_, _) | NodeBase::Index(_, _) => {
+                self.run_reference(dst, iseq)?;
+                self.bytecode_gen.gen_double2(iseq);
+                self.bytecode_gen.gen_get_member(iseq);
+                self.run(rhs, iseq, true)?;
+                self.gen_binary_op(op, iseq);
+                self.set_reference(iseq, use_value);
+                return Ok(());
             }
+            _ => self.run_binary_op(dst, rhs, op, iseq)?,
+        }
+
+        if use_value {
+            self.bytecode_gen.gen_double(iseq);
+        }
+
+        self.assign_stack_top(dst, iseq)
+    }
+
+    /// Compile '++' and '--' of a member expression.
+    fn run_update_member(
+        &mut self,
+        expr: &Node,
+        op: &UnaryOp,
+        iseq: &mut ByteCode,
+        use_value: bool,
+    ) -> Result<(), Error> {
+        let is_postfix = *op == UnaryOp::PoInc || *op == UnaryOp::PoDec;
+        self.run_reference(expr, iseq)?;
+        self.bytecode_gen.gen_double2(iseq);
+        self.bytecode_gen.gen_get_member(iseq);
+        if is_postfix && use_value {
+            // The old value is left under the reference as the result.
+            self.bytecode_gen.gen_double(iseq);
+            self.bytecode_gen.gen_rotate(3, iseq);
+        }
+        self.bytecode_gen.gen_push_int8(1, iseq);
+        match op {
+            UnaryOp::PrInc | UnaryOp::PoInc => self.bytecode_gen.gen_add(iseq),
+            _ => self.bytecode_gen.gen_sub(iseq),
+        }
+        self.set_reference(iseq, use_value && !is_postfix);
+        Ok(())
+    }
+
+    /// Push the object and the key of a member expression, which set_reference() assigns to.
+    fn run_reference(&mut self, node: &Node, iseq: &mut ByteCode) -> Result<(), Error> {
+        match node.base {
+            NodeBase::Member(ref parent, ref member) => {
+                self.run(&*parent, iseq, true)?;
+                self.bytecode_gen
+                    .gen_push_const(Value::string(member.clone()), iseq);
+            }
+            NodeBase::Index(ref parent, ref idx) => {
+                self.run(&*parent, iseq, true)?;
+                self.run(&*idx, iseq, true)?;
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Assign the value on the top of the stack to the reference pushed under it by
+    /// run_reference(). The value is left on the stack if 'use_value'.
+    fn set_reference(&mut self, iseq: &mut ByteCode, use_value: bool) {
+        // SET_MEMBER takes the value under the object and the key.
+        if use_value {
+            self.bytecode_gen.gen_double(iseq);
+            self.bytecode_gen.gen_rotate(3, iseq);
+        }
+        self.bytecode_gen.gen_rotate(2, iseq);
+        self.bytecode_gen.gen_set_member(iseq);
+    }
+
+    pub fn assign_stack_top(&mut self, dst: &Node, iseq: &mut ByteCode) -> Result<(), Error> {
+        match dst.base {
+            NodeBase::Identifier(ref name) => match self.resolve(name) {
+                Binding::Slot(r) => self.bytecode_gen.gen_pop_reg(r, iseq),
+                Binding::Upvalue(n) => self.bytecode_gen.gen_set_upvalue(n, iseq),
+                // Assigning to an undeclared variable is a ReferenceError in strict mode code.
+                _ if self.strict => self.bytecode_gen.gen_set_value_strict(name, iseq),
+                _ => self.bytecode_gen.gen_set_value(name, iseq),
+            },
             NodeBase::Member(ref parent, ref member) => {
                 self.run(&*parent, iseq, true)?;
                 self.bytecode_gen
@@ -934,9 +1444,105 @@ impl VMCodeGen {
             self.run(arg, iseq, true)?;
         }
 
-        self.run(callee, iseq, true)?;
+        self.run_callee(callee, iseq)?;
 
-        self.bytecode_gen.gen_call(args.len() as u32, iseq);
+        // A call of 'eval' is a direct eval if it calls the eval builtin at runtime.
+        match callee.base {
+            NodeBase::Identifier(ref name) if name == "eval" => {
+                self.bytecode_gen.gen_call_eval(args.len() as u32, iseq)
+            }
+            _ => self.bytecode_gen.gen_call(args.len() as u32, iseq),
+        }
+
+        if !use_value {
+            self.bytecode_gen.gen_pop(iseq);
+        }
+
+        Ok(())
+    }
+
+    /// Push 'this' of a call of 'callee', and then the callee. obj.m() and obj[k]() call the
+    /// method with obj, and the other calls with undefined.
+    fn run_callee(&mut self, callee: &Node, iseq: &mut ByteCode) -> Result<(), Error> {
+        match callee.base {
+            NodeBase::Member(ref parent, ref member) => {
+                self.run(&*parent, iseq, true)?;
+                self.bytecode_gen.gen_double(iseq);
+                self.bytecode_gen
+                    .gen_push_const(Value::string(member.clone()), iseq);
+                self.bytecode_gen.gen_get_member(iseq);
+            }
+            NodeBase::Index(ref parent, ref idx) => {
+                self.run(&*parent, iseq, true)?;
+                self.bytecode_gen.gen_double(iseq);
+                self.run(&*idx, iseq, true)?;
+                self.bytecode_gen.gen_get_member(iseq);
+            }
+            _ => {
+                self.bytecode_gen.gen_push_undefined(iseq);
+                self.run(callee, iseq, true)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl VMCodeGen {
+    /// `a${x}b` is run like 'a' + x + 'b'. The first operand is a string, so the substitutions
+    /// are converted to strings.
+    fn run_template(
+        &mut self,
+        cooked: &Vec<Option<String>>,
+        substitutions: &Vec<Node>,
+        iseq: &mut ByteCode,
+    ) -> Result<(), Error> {
+        // The parser rejects invalid escape sequences unless the template is tagged.
+        let cooked: Vec<String> = cooked
+            .iter()
+            .map(|s| s.clone().unwrap_or_default())
+            .collect();
+
+        self.bytecode_gen
+            .gen_push_const(Value::string(cooked[0].clone()), iseq);
+        for (substitution, s) in substitutions.iter().zip(&cooked[1..]) {
+            self.run(substitution, iseq, true)?;
+            self.bytecode_gen.gen_add(iseq);
+            if !s.is_empty() {
+                self.bytecode_gen
+                    .gen_push_const(Value::string(s.clone()), iseq);
+                self.bytecode_gen.gen_add(iseq);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// tag`a${x}b` is run like tag(template, x). The template object is made once here, so the
+    /// tagged template gives the same one every time it runs.
+    fn run_tagged_template(
+        &mut self,
+        tag: &Node,
+        template: &Node,
+        iseq: &mut ByteCode,
+        use_value: bool,
+    ) -> Result<(), Error> {
+        let (cooked, raw, substitutions) = match template.base {
+            NodeBase::Template(ref cooked, ref raw, ref substitutions) => {
+                (cooked, raw, substitutions)
+            }
+            _ => unreachable!(),
+        };
+
+        for substitution in substitutions.iter().rev() {
+            self.run(substitution, iseq, true)?;
+        }
+        self.bytecode_gen
+            .gen_push_const(Value::template_object(cooked, raw), iseq);
+
+        self.run_callee(tag, iseq)?;
+        self.bytecode_gen
+            .gen_call(substitutions.len() as u32 + 1, iseq);
 
         if !use_value {
             self.bytecode_gen.gen_pop(iseq);
@@ -952,24 +1558,50 @@ impl VMCodeGen {
         properties: &Vec<PropertyDefinition>,
         iseq: &mut ByteCode,
     ) -> Result<(), Error> {
+        // Each property is defined in order after its name and value are evaluated, so that a
+        // later one overrides an earlier one, spreads and accessors included.
+        self.bytecode_gen.gen_create_object(0, iseq);
+
         for property in properties {
             match property {
                 PropertyDefinition::IdentifierReference(name) => {
+                    self.bytecode_gen
+                        .gen_push_const(Value::string(name.clone()), iseq);
                     self.run_identifier(name, iseq)?;
+                    self.bytecode_gen.gen_define_property(iseq);
+                }
+                PropertyDefinition::Property(name, node)
+                | PropertyDefinition::MethodDefinition(
+                    MethodDefinitionKind::Ordinary,
+                    name,
+                    node,
+                ) => {
                     self.bytecode_gen
                         .gen_push_const(Value::string(name.clone()), iseq);
+                    self.run_named(&node, name, iseq)?;
+                    self.bytecode_gen.gen_define_property(iseq);
                 }
-                PropertyDefinition::Property(name, node) => {
+                PropertyDefinition::ComputedProperty(key, node) => {
+                    self.run(&key, iseq, true)?;
                     self.run(&node, iseq, true)?;
+                    self.bytecode_gen.gen_define_property(iseq);
+                }
+                PropertyDefinition::MethodDefinition(kind, name, node) => {
                     self.bytecode_gen
                         .gen_push_const(Value::string(name.clone()), iseq);
+                    self.run(&node, iseq, true)?;
+                    match kind {
+                        MethodDefinitionKind::Get => self.bytecode_gen.gen_set_getter(iseq),
+                        _ => self.bytecode_gen.gen_set_setter(iseq),
+                    }
+                }
+                PropertyDefinition::Spread(node) => {
+                    self.run(&node, iseq, true)?;
+                    self.bytecode_gen.gen_copy_data_properties(iseq);
                 }
             }
         }
 
-        self.bytecode_gen
-            .gen_create_object(properties.len() as usize, iseq);
-
         Ok(())
     }
 
@@ -1011,12 +1643,58 @@ impl VMCodeGen {
     }
 
     fn run_identifier(&mut self, name: &String, iseq: &mut ByteCode) -> Result<(), Error> {
-        self.bytecode_gen.gen_get_value(name, iseq);
+        self.gen_get_variable(name, iseq);
 
         Ok(())
     }
 }
 
+/// Whether the code of 'node' pushes its value even if the value is not used. The other nodes
+/// push nothing, or take 'use_value'.
+fn leaves_value(node: &Node) -> bool {
+    match node.base {
+        NodeBase::UnaryOp(_, ref op) => match op {
+            UnaryOp::PrInc | UnaryOp::PoInc | UnaryOp::PrDec | UnaryOp::PoDec => false,
+            _ => true,
+        },
+        NodeBase::FunctionExpr(_, _, _, _, _)
+        | NodeBase::Member(_, _)
+        | NodeBase::Index(_, _)
+        | NodeBase::New(_)
+        | NodeBase::BinaryOp(_, _, _)
+        | NodeBase::TernaryOp(_, _, _)
+        | NodeBase::Array(_)
+        | NodeBase::Object(_)
+        | NodeBase::Template(_, _, _)
+        | NodeBase::Identifier(_)
+        | NodeBase::This
+        | NodeBase::Arguments
+        | NodeBase::Undefined
+        | NodeBase::Null
+        | NodeBase::String(_)
+        | NodeBase::Boolean(_)
+        | NodeBase::Number(_)
+        | NodeBase::BigInt(_) => true,
+        _ => false,
+    }
+}
+
+/// The register-addressed instruction of 'op', if any.
+fn reg_inst(op: &BinOp) -> Option<u8> {
+    match op {
+        &BinOp::Add => Some(VMInst::ADD_REG),
+        &BinOp::Sub => Some(VMInst::SUB_REG),
+        &BinOp::Mul => Some(VMInst::MUL_REG),
+        &BinOp::Div => Some(VMInst::DIV_REG),
+        &BinOp::Rem => Some(VMInst::REM_REG),
+        &BinOp::Lt => Some(VMInst::LT_REG),
+        &BinOp::Gt => Some(VMInst::GT_REG),
+        &BinOp::Le => Some(VMInst::LE_REG),
+        &BinOp::Ge => Some(VMInst::GE_REG),
+        _ => None,
+    }
+}
+
 // Level
 
 impl Level {
@@ -1046,8 +1724,8 @@ impl Jumps {
         }
     }
 
-    pub fn make_new_local(&mut self) {
-        self.local.push(JumpFromLoop::new());
+    pub fn make_new_local(&mut self, scope_depth: usize) {
+        self.local.push(JumpFromLoop::new(scope_depth));
     }
 
     pub fn pop_local(&mut self) {
@@ -1064,9 +1742,18 @@ impl JumpToGlobalLabel {
         JumpToGlobalLabel {
             continue_inst_positions: vec![],
             break_inst_positions: vec![],
+            scope_depths: vec![],
         }
     }
 
+    fn scope_depth_of(&self, label_name: &String) -> Option<usize> {
+        self.scope_depths
+            .iter()
+            .rev()
+            .find(|(name, _)| name == label_name)
+            .map(|(_, depth)| *depth)
+    }
+
     fn replace_break_dsts(
         &mut self,
         bytecode_gen: &mut ByteCodeGen,
@@ -1109,10 +1796,11 @@ impl JumpToGlobalLabel {
 }
 
 impl JumpFromLoop {
-    pub fn new() -> Self {
+    pub fn new(scope_depth: usize) -> Self {
         JumpFromLoop {
             continue_inst_positions: vec![],
             break_inst_positions: vec![],
+            scope_depth: scope_depth,
         }
     }
 
@@ -0,0 +1,54 @@
+var ans = []
+
+// Shrinking removes the trailing elements.
+var a = [1, 2, 3, 4, 5]
+a.length = 3
+ans.push(a.toString(), a[3] === undefined, Object.keys(a).length)
+var seen = []
+for (var i = 0; i < a.length; i++) seen.push(a[i])
+ans.push(seen.toString())
+
+// Growing adds holes, which the callback of map() skips.
+a.length = 6
+var calls = 0
+var mapped = a.map(function (x) {
+  calls++
+  return x * 10
+})
+ans.push(a.length, Object.keys(a).length, a[5] === undefined, a.toString())
+ans.push(calls, mapped.length, mapped.toString())
+
+// Elements removed while iterating are not visited.
+var b = [1, 2, 3, 4]
+var r = b.map(function (x) {
+  b.length = 2
+  return x
+})
+ans.push(r.length, r.toString(), b.toString())
+var c = [1, 2, 3, 4]
+var out = []
+for (var i = 0; i < c.length; i++) {
+  out.push(c[i])
+  c.length = 2
+}
+ans.push(out.toString())
+
+// The new length is converted to a number, and must be an integer from 0 to 2^32 - 1.
+var d = [1, 2, 3]
+d.length = '2'
+ans.push(d.length)
+d.length = true
+ans.push(d.toString())
+var invalid = [1.5, -1, NaN, 4294967296, 'x']
+for (var i = 0; i < invalid.length; i++) {
+  try {
+    d.length = invalid[i]
+    ans.push(d.length)
+  } catch (e) {
+    ans.push(e.message === 'RangeError: Invalid array length')
+  }
+}
+ans.push(d.length)
+
+console.log(ans)
+ans
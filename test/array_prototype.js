@@ -0,0 +1,58 @@
+var ans = []
+
+// flat and flatMap skip holes.
+var nested = [1, [2, [3, [4]]], , 5]
+ans.push(nested.flat(), nested.flat(10), [1, , 2].flat(0))
+ans.push(
+  [1, , 3].flatMap(function(x, i) {
+    return [x, i]
+  }),
+  [1, 2].flatMap(function(x) {
+    return [[x]]
+  })
+)
+
+// fill fills holes, and takes indices relative to the end.
+var f = [1, , 3, 4]
+var holes = new Array(3)
+ans.push(f.fill(0, 1, -1) === f, f, holes.fill('x'))
+
+// copyWithin copies holes as holes.
+var c = [1, 2, , 4, 5]
+c.copyWithin(0, 2)
+ans.push(c.toString(), c[0] === undefined)
+ans.push([1, 2, 3, 4, 5].copyWithin(1, 0, 3), [1, 2, 3, 4, 5].copyWithin(-2))
+
+// at
+var sparse = [1, , 3]
+ans.push(sparse.at(-1), sparse.at(1), sparse.at(3), sparse.at(-4))
+
+// entries, keys and values iterate holes as undefined.
+function collect(it) {
+  var out = []
+  for (var r = it.next(); !r.done; r = it.next()) {
+    out.push(r.value)
+  }
+  return out
+}
+var letters = ['a', , 'c']
+ans.push(letters.entries().next())
+ans.push(collect(letters.keys()), collect(letters.values()), collect(letters.entries()))
+
+// An iterator sees elements pushed while iterating, and stays done once it is done.
+var g = [1]
+var it = g.values()
+it.next()
+g.push(2)
+ans.push(it.next().value, it.next().done)
+g.push(3)
+ans.push(it.next().done)
+
+try {
+  Array.prototype.fill.call({}, 1)
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
@@ -0,0 +1,44 @@
+var ans = []
+
+ans.push([3, 1, 2].sort())
+ans.push([10, 9, 1, 100].sort())
+ans.push([10, 9, 1, 100].sort(function(a, b) {
+  return a - b
+}))
+ans.push(['b', undefined, 'a', 'c'].sort())
+
+var people = [
+  { name: 'a', age: 30 },
+  { name: 'b', age: 20 },
+  { name: 'c', age: 30 },
+  { name: 'd', age: 20 }
+]
+people.sort(function(x, y) {
+  return x.age - y.age
+})
+ans.push(people.map(function(p) {
+  return p.name
+}))
+
+var holes = [undefined, 3]
+holes[3] = 1
+holes.sort()
+ans.push(holes.length)
+ans.push(holes.toString())
+
+var arr = [2, 1]
+ans.push(arr.sort() === arr)
+
+try {
+  [2, 1].sort(function() {
+    throw 'thrown'
+  })
+} catch (e) {
+  ans.push(e)
+}
+try {
+  [2, 1].sort(1)
+} catch (e) {
+  ans.push('not callable')
+}
+ans
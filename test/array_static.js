@@ -0,0 +1,43 @@
+var ans = []
+
+ans.push(Array(3).length)
+ans.push(new Array(1, 2, 3))
+ans.push(Array('a'))
+
+ans.push(Array.isArray([]))
+ans.push(Array.isArray({ length: 0 }))
+ans.push(Array.isArray('abc'))
+
+ans.push(Array.of(7))
+ans.push(Array.of(1, 'two', 3).length)
+
+ans.push(Array.from('abc'))
+ans.push(Array.from([1, 2, 3], function(x, i) {
+  return x * 10 + i
+}))
+ans.push(Array.from({ length: 2, 0: 'x', 1: 'y' }))
+ans.push(Array.from({ length: 3 }, function(_, i) {
+  return i * i
+}))
+
+function args() {
+  return Array.from(arguments)
+}
+ans.push(args(4, 5))
+
+var scaler = { k: 3 }
+ans.push(Array.from([1, 2], function(x) {
+  return x * this.k
+}, scaler))
+
+try {
+  Array.from([1], 5)
+} catch (e) {
+  ans.push('not callable')
+}
+try {
+  Array.from(null)
+} catch (e) {
+  ans.push('null')
+}
+ans
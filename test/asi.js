@@ -0,0 +1,53 @@
+var ans = []
+
+var a = 1
+var b = 2
+var c = a
+  + b
+ans.push(c)
+
+function f() {
+  return
+  1
+}
+ans.push(f())
+
+function g(x) {
+  return x
+    ? 'yes'
+    : 'no'
+}
+ans.push([g(1), g(0)])
+
+var i = 0
+var j = 5
+i
+++j
+ans.push([i, j])
+
+var s = 'a' /*
+*/ var t = 'b'
+ans.push(s + t)
+
+var log = []
+for (var k = 0; k < 4; k++) {
+  if (k == 1) continue
+  if (k == 3) break
+  log.push(k)
+}
+ans.push(log)
+
+var u = [1, 2, 3]
+  .map(function (x) { return x * 2 })
+ans.push(u)
+
+function h() {
+  try {
+    throw 'e'
+  } catch (e) {
+    return e
+  }
+}
+ans.push(h())
+
+ans
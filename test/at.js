@@ -0,0 +1,24 @@
+var ans = []
+
+// A negative index counts from the end.
+var a = [1, 2, 3]
+ans.push(a.at(0), a.at(-1), a.at(-3), a.at(3), a.at(-4), a.at(1.7), a.at('1'), a.at())
+var s = 'abc'
+ans.push(s.at(0), s.at(-1), s.at(5), s.at(-4), 'a\u{1F600}'.at(-1).length)
+
+// Negative, fractional and huge numbers are not indices, but property keys.
+var w = new String('xy')
+ans.push(s[-1], s[1e300], s[1.5], s['1'], w[1], w[-1])
+function args() {
+  return [arguments[-1], arguments[1e300], arguments[0]]
+}
+function setArgs(x) {
+  arguments[-1] = 5
+  return x
+}
+ans.push(args(1), setArgs(1))
+a[-1] = 'neg'
+ans.push(a[1e300], a.length, a[-1])
+
+console.log(ans)
+ans
@@ -0,0 +1,27 @@
+var ans = []
+var big = 2n ** 64n
+
+ans.push(big, big - 1n, -7n / 2n, -7n % 2n)
+ans.push(5n & -2n, -5n | 2n, 5n ^ 3n, -9n >> 1n, 1n << 70n)
+ans.push(1n < 2, 2n > 1.5, 1n == 1, 1n === 1, 10n == '10', 1n + 'a')
+ans.push(BigInt(42), BigInt('0x10'), (255n).toString(16), BigInt.asIntN(8, 255n), BigInt.asUintN(8, -1n))
+
+try {
+  1n + 1
+} catch (e) {
+  ans.push('cannot mix')
+}
+
+try {
+  1n / 0n
+} catch (e) {
+  ans.push('division by zero')
+}
+
+try {
+  new BigInt(1)
+} catch (e) {
+  ans.push('not a constructor')
+}
+
+ans
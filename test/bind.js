@@ -0,0 +1,32 @@
+var ans = []
+
+var obj = { x: 10 }
+function getX(a, b) {
+  return this.x + a + b
+}
+var bound = getX.bind(obj, 1)
+ans.push(bound(2))
+ans.push(bound.call({ x: 100 }, 3))
+ans.push(bound.length)
+ans.push(bound.name)
+
+var other = { x: 20, f: bound }
+ans.push(other.f(0))
+
+function Point(x, y) {
+  this.x = x
+  this.y = y
+}
+var XPoint = Point.bind(obj, 5)
+var p = new XPoint(7)
+ans.push([p.x, p.y, obj.y])
+
+var max = Math.max.bind(null, 3)
+ans.push(max(1, 2))
+
+try {
+  Function.prototype.bind.call(1)
+} catch (e) {
+  ans.push('type error')
+}
+ans
@@ -0,0 +1,47 @@
+// Calls which do not capture their scope reuse the call objects of earlier calls. Each call
+// must still start with a fresh scope.
+function fib(n) {
+  return n < 2 ? n : fib(n - 1) + fib(n - 2)
+}
+function fresh(x) {
+  var y
+  var before = y
+  y = x
+  return before
+}
+function tail(n, acc) {
+  if (n === 0) return acc
+  return tail(n - 1, acc + n)
+}
+function counter() {
+  var n = 0
+  return function() {
+    n += 1
+    return n
+  }
+}
+function sum() {
+  var s = 0
+  for (var i = 0; i < arguments.length; i++) s += arguments[i]
+  return s
+}
+function Point(x) {
+  this.x = x
+}
+
+var c1 = counter()
+var c2 = counter()
+c1()
+c1()
+
+var ans = []
+ans.push(fib(15))
+ans.push(fresh(1))
+ans.push(fresh(2))
+ans.push(tail(1000, 0))
+ans.push(c1())
+ans.push(c2())
+ans.push(sum(1, 2, 3))
+ans.push(sum(4))
+ans.push(new Point(5).x)
+ans
@@ -0,0 +1,53 @@
+var ans = []
+
+// A call may be called again, and a method may be called on the result of a call.
+function adder(x) {
+  return function(y) {
+    return x + y
+  }
+}
+ans.push(adder(1)(2))
+function double(x) {
+  return x * 2
+}
+function big(x) {
+  return x > 4
+}
+ans.push([1, 2, 3, 4].map(double).filter(big))
+ans.push('a-b-c'.split('-').join('+').length)
+
+// Any expression may be called, with undefined as 'this'.
+var cond = false
+ans.push((cond ? adder(10) : double)(5))
+var fns = [double, adder(1)]
+ans.push(fns[1](1), fns[0](3))
+
+// The receiver of a chained method call is the result of the previous call.
+function Counter(n) {
+  this.n = n
+}
+Counter.prototype.inc = function() {
+  this.n++
+  return this
+}
+Counter.prototype.get = function() {
+  return this.n
+}
+ans.push(new Counter(1).inc().inc().get())
+var obj = {
+  make: function() {
+    return new Counter(10)
+  },
+}
+ans.push(obj.make().inc().get())
+
+// 'new' takes the nearest arguments.
+function Factory() {
+  return function() {
+    return 'called'
+  }
+}
+ans.push(new Factory()())
+
+console.log(ans)
+ans
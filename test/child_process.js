@@ -0,0 +1,23 @@
+var ans = []
+var cp = require('child_process')
+
+// execSync runs the command with the shell, and returns its output.
+ans.push(cp.execSync('echo hello') === 'hello\n', cp.execSync('cat', { input: 'in' }))
+ans.push(cp.execSync('echo $X', { env: { X: 'from env' } }) === 'from env\n')
+try {
+  cp.execSync('echo out; echo err >&2; exit 3')
+} catch (e) {
+  ans.push(e.status, e.stdout === 'out\n', e.stderr === 'err\n')
+}
+
+// spawnSync passes the arguments as they are, or quoted if 'shell' is true.
+var r = cp.spawnSync('printf', ['%s|', 'a b', "it's"])
+ans.push(r.status, r.stdout === "a b|it's|", r.stderr, r.pid > 0)
+ans.push(cp.spawnSync('printf', ['%s|', 'a b', "it's"], { shell: true }).stdout === "a b|it's|")
+ans.push(cp.spawnSync('pwd', { cwd: '/' }).stdout === '/\n')
+ans.push(cp.spawnSync('sh', ['-c', 'kill -TERM $$']).signal)
+
+var missing = cp.spawnSync('rapidus-no-such-command')
+ans.push(missing.status, missing.error.code)
+
+ans
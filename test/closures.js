@@ -0,0 +1,87 @@
+// Captured variables are shared through upvalues, which outlive the frames they were made in.
+function counter() {
+  var n = 0
+  return function() {
+    return ++n
+  }
+}
+var c1 = counter()
+var c2 = counter()
+c1()
+c1()
+c2()
+
+function pair() {
+  var v = 1
+  var get = function() {
+    return v
+  }
+  var set = function(x) {
+    v = x
+  }
+  set(5)
+  return [get, set]
+}
+var p = pair()
+p[1](7)
+
+function nested(a) {
+  return function(b) {
+    return function(c) {
+      a += 1
+      return a + b + c
+    }
+  }
+}
+var f = nested(1)(10)
+f(100)
+
+function loop() {
+  var fs = []
+  for (var i = 0; i < 3; i++) {
+    fs.push(function() {
+      return i
+    })
+  }
+  return fs.map(function(g) {
+    return g()
+  })
+}
+
+function recursive(n) {
+  function even(k) {
+    return k == 0 ? true : odd(k - 1)
+  }
+  function odd(k) {
+    return k == 0 ? false : even(k - 1)
+  }
+  return [even(n), odd(n)]
+}
+
+function dynamic() {
+  var x = 1
+  var o = { y: 2 }
+  var fs = []
+  with (o) {
+    fs.push(function() {
+      return x + y
+    })
+  }
+  try {
+    throw 3
+  } catch (e) {
+    fs.push(function() {
+      return x + e
+    })
+  }
+  fs.push(function() {
+    return eval('x * 10')
+  })
+  x = 4
+  return fs.map(function(g) {
+    return g()
+  })
+}
+
+var ans = [c1(), c2(), p[0](), f(100), loop(), recursive(3), dynamic()]
+ans
@@ -0,0 +1,16 @@
+var ans = []
+
+/* a *//* b */ans.push(1)
+ans.push(/*/ not closed here */ 2)
+var x = 3 <!-- an HTML-like comment
+--> also a comment
+ans.push(x)
+var y = 5
+y-->4
+ans.push(y)
+var s = '/* not a comment */'
+ans.push(s)
+ans.push(/*
+*/ 6)
+
+ans
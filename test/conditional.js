@@ -0,0 +1,35 @@
+var ans = []
+var log = []
+
+function f(x) {
+  log.push(x)
+  return x
+}
+
+// Only one arm runs.
+ans.push(true ? f('a') : f('b'))
+ans.push(f(0) ? f('c') : f('d'))
+ans.push(log)
+
+// Falsy values other than false choose the else arm.
+ans.push(0 ? 1 : 2, '' ? 1 : 2, null ? 1 : 2, undefined ? 1 : 2)
+ans.push('s' ? 1 : 2, {} ? 1 : 2)
+
+// Nested conditionals associate to the right.
+function sign(n) {
+  return n > 0 ? 'positive' : n < 0 ? 'negative' : 'zero'
+}
+ans.push(sign(5), sign(-5), sign(0))
+function grade(n) {
+  return n >= 50 ? (n >= 80 ? 'A' : 'B') : n >= 20 ? 'C' : 'D'
+}
+ans.push(grade(90), grade(60), grade(30), grade(10))
+
+// The value of a conditional can be used in an expression.
+var x = 1 + (false ? 10 : 20) * 2
+ans.push(x)
+
+// && and || give one of the operands.
+ans.push(0 && f('e'), 2 && 3, -1 || 5, 0 || 'y')
+
+ans
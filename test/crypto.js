@@ -0,0 +1,46 @@
+var ans = []
+
+// randomUUID makes version 4 UUIDs.
+var uuid = crypto.randomUUID()
+ans.push(uuid.length, uuid[14], '89ab'.indexOf(uuid[19]) >= 0, uuid !== crypto.randomUUID())
+
+// getRandomValues fills integer typed arrays, and returns them.
+var bytes = new Uint8Array(32)
+var nonzero = 0
+ans.push(crypto.getRandomValues(bytes) === bytes)
+for (var i = 0; i < bytes.length; i++) {
+  if (bytes[i] !== 0) nonzero++
+}
+ans.push(nonzero > 0)
+try {
+  crypto.getRandomValues(new Float64Array(1))
+} catch (e) {
+  ans.push('type mismatch')
+}
+try {
+  crypto.getRandomValues(new Uint8Array(65537))
+} catch (e) {
+  ans.push('quota exceeded')
+}
+
+// createHash, which is also in require('crypto').
+var hash = require('crypto').createHash
+ans.push(hash('md5').update('abc').digest('hex'))
+ans.push(hash('sha1').update('ab').update('c').digest('hex'))
+ans.push(hash('sha256').update('abc').digest('hex'))
+ans.push(hash('sha256').update(new Uint8Array([97, 98, 99])).digest('base64'))
+ans.push(hash('sha1').digest().length)
+var used = hash('md5')
+used.digest()
+try {
+  used.update('x')
+} catch (e) {
+  ans.push('digest already called')
+}
+try {
+  hash('sha3')
+} catch (e) {
+  ans.push('not supported')
+}
+
+ans
@@ -0,0 +1,39 @@
+var ans = []
+
+function depth(n) {
+  if (n == 0) return 0
+  return 1 + depth(n - 1)
+}
+ans.push(depth(9000))
+
+function forever(n) {
+  return 1 + forever(n + 1)
+}
+try {
+  forever(0)
+} catch (e) {
+  ans.push(e.message)
+}
+ans.push(depth(10))
+
+function Node(n) {
+  this.next = n > 0 ? new Node(n - 1) : null
+}
+var list = new Node(3000)
+var len = 0
+while (list) {
+  len++
+  list = list.next
+}
+ans.push(len)
+
+function thrower(n) {
+  if (n == 0) throw 'bottom'
+  return [thrower(n - 1)]
+}
+try {
+  thrower(100)
+} catch (e) {
+  ans.push(e)
+}
+ans
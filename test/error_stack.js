@@ -0,0 +1,25 @@
+var ans = []
+
+// 'stack' starts with the name and the message. The frames follow when a source map is used.
+var e = new Error('boom')
+ans.push(e.stack, new Error().stack, e.propertyIsEnumerable('stack'), Error.stackTraceLimit)
+
+// Error.captureStackTrace sets 'stack' of any object.
+var obj = {}
+Error.captureStackTrace(obj)
+var named = { name: 'Custom', message: 'm' }
+Error.captureStackTrace(named)
+ans.push(obj.stack, named.stack)
+function Custom(message) {
+  this.message = message
+  Error.captureStackTrace(this, Custom)
+}
+ans.push(new Custom('c').stack)
+try {
+  Error.captureStackTrace(1)
+} catch (err) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
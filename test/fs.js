@@ -0,0 +1,34 @@
+var fs = require('fs')
+var ans = []
+var dir = 'test/fs_tmp'
+
+fs.mkdirSync(dir + '/sub/sub', { recursive: true })
+fs.writeFileSync(dir + '/a.txt', 'hello')
+fs.writeFileSync(dir + '/b.txt', '死して屍')
+fs.writeFileSync(dir + '/c.bin', new Uint8Array([1, 2, 255]))
+
+ans.push(fs.existsSync(dir + '/a.txt'), fs.existsSync(dir + '/none'))
+ans.push(fs.readFileSync(dir + '/a.txt', 'utf8'))
+ans.push(fs.readFileSync(dir + '/b.txt', { encoding: 'utf-8' }))
+var bytes = fs.readFileSync(dir + '/c.bin')
+ans.push(bytes.length, bytes[2])
+ans.push(fs.readdirSync(dir).join())
+
+var stat = fs.statSync(dir + '/b.txt')
+ans.push(stat.size, stat.isFile(), stat.isDirectory(), fs.statSync(dir).isDirectory())
+
+try {
+  fs.readFileSync(dir + '/none')
+} catch (e) {
+  ans.push(e.name)
+}
+
+fs.unlinkSync(dir + '/a.txt')
+fs.unlinkSync(dir + '/b.txt')
+fs.unlinkSync(dir + '/c.bin')
+fs.rmdirSync(dir + '/sub/sub')
+fs.rmdirSync(dir + '/sub')
+fs.rmdirSync(dir)
+ans.push(fs.existsSync(dir))
+
+ans
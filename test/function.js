@@ -0,0 +1,28 @@
+var ans = []
+
+var add = new Function('a', 'b', 'return a+b')
+ans.push(add(1, 2))
+ans.push(Function('return 1')())
+ans.push(new Function('a, b', 'c', 'return a*b*c')(2, 3, 4))
+
+var x = 'global'
+function local() {
+  var x = 'local'
+  return new Function('return x')()
+}
+ans.push(local())
+
+function decl(a, b) { return a + b }
+ans.push(decl.toString())
+var expr = function (n) { return n }
+ans.push(expr.toString())
+var obj = { method() { return 1 } }
+ans.push(obj.method.toString())
+ans.push(Math.max.toString())
+
+try {
+  new Function('}; ans.push(1); {')
+} catch (e) {
+  ans.push('syntax error')
+}
+ans
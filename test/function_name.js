@@ -0,0 +1,24 @@
+var ans = []
+
+function decl(a, b, c) {}
+var anon = function(a) {}
+var named = function inner(a, b) {}
+var assigned
+assigned = function() {}
+var obj = {
+  prop: function(x, y) {},
+  method(x) {},
+  other: decl
+}
+function rest(a, ...b) {}
+
+ans.push([decl.name, decl.length])
+ans.push([anon.name, anon.length])
+ans.push([named.name, named.length])
+ans.push([assigned.name, assigned.length])
+ans.push([obj.prop.name, obj.prop.length])
+ans.push([obj.method.name, obj.method.length])
+ans.push(obj.other.name)
+ans.push(rest.length)
+ans.push(decl.bind(null, 1).name)
+ans
@@ -0,0 +1,23 @@
+var a = []
+for (var i = 0; i < 100; i++) a.push({ i: i })
+a = null
+
+var before = gc.stats()
+gc.collect()
+var after = gc.stats()
+
+var ans = []
+ans.push(after.collections > before.collections)
+ans.push(after.objects < before.objects)
+ans.push(after.heapBytes < before.heapBytes)
+ans.push(after.freedBytes > before.freedBytes)
+ans.push(after.lastPauseMs >= 0)
+ans.push(after.maxPauseMs >= after.lastPauseMs)
+
+// The values a builtin holds survive a collection in a callback.
+var mapped = [1, 2, 3].map(function(n) {
+  gc.collect()
+  return { n: n }
+})
+ans.push(mapped[0].n + mapped[1].n + mapped[2].n)
+ans
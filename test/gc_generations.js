@@ -0,0 +1,21 @@
+var kept = []
+var map = new WeakMap()
+gc.collect()
+
+// The young objects kept by old ones survive the minor collections.
+var before = gc.stats()
+for (var i = 0; i < 10000; i++) {
+  var o = { i: i }
+  if (i % 1000 == 0) {
+    kept.push(o)
+    map.set(o, i)
+  }
+}
+var after = gc.stats()
+
+var ans = []
+ans.push(after.minorCollections > before.minorCollections)
+ans.push(kept.length)
+ans.push(kept[9].i)
+ans.push(map.get(kept[1]))
+ans
@@ -0,0 +1,68 @@
+// Global variables are read from the global object through a cache of each site, which must see
+// them redefined, and must not be used where other scopes may bind them.
+var count = 0
+function inc() {
+  count++
+}
+function calls(n) {
+  for (var i = 0; i < n; i++) inc()
+  return count
+}
+
+function twice(x) {
+  return x * 2
+}
+function useTwice() {
+  return twice(5)
+}
+var before = useTwice()
+twice = function(x) {
+  return x * 3
+}
+var after = useTwice()
+
+function lateGlobal() {
+  try {
+    return late
+  } catch (e) {
+    return 'not defined'
+  }
+}
+var missing = lateGlobal()
+late = 'defined'
+var found = lateGlobal()
+
+function shadowedByWith() {
+  var o = { Math: 'with' }
+  var m
+  with (o) {
+    m = Math
+  }
+  return m
+}
+var shadowedByCatch
+try {
+  throw 'catch'
+} catch (Math) {
+  shadowedByCatch = Math
+}
+function shadowedByEval() {
+  eval('var Math = "eval"')
+  return (function() {
+    return Math
+  })()
+}
+
+var ans = [
+  calls(3),
+  before,
+  after,
+  missing,
+  found,
+  shadowedByWith(),
+  shadowedByCatch,
+  shadowedByEval(),
+  new Function('return count')(),
+  Math.max(1, 2)
+]
+ans
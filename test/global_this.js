@@ -0,0 +1,20 @@
+var ans = []
+
+globalThis.foo = 1
+ans.push(foo)
+
+var bar = 'bar'
+ans.push(globalThis.bar)
+ans.push(this === globalThis)
+ans.push(globalThis.Array === Array)
+ans.push(globalThis.globalThis === globalThis)
+
+function sloppyThis() {
+  return this
+}
+ans.push(sloppyThis() === globalThis)
+
+this.baz = 3
+ans.push(baz + globalThis.baz)
+ans.push(globalThis.toString())
+ans
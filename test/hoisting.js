@@ -0,0 +1,64 @@
+var ans = []
+
+ans.push(hoisted())
+function hoisted() {
+  return 'hoisted'
+}
+
+function param(x) {
+  var x
+  return x
+}
+ans.push(param(1))
+
+function useBefore() {
+  y = 5
+  var y
+  return y
+}
+ans.push(useBefore())
+
+function twice() {
+  return 1
+}
+function twice() {
+  return 2
+}
+ans.push(twice())
+
+function inBlock(flag) {
+  var before = g
+  if (flag) {
+    var inside = g()
+    function g() {
+      return 'g'
+    }
+  }
+  return [before, inside]
+}
+ans.push(inBlock(true))
+ans.push(inBlock(false))
+
+function inLoop() {
+  var before = v
+  var i = 0
+  while (i < 2) {
+    var v = i
+    i++
+  }
+  return [before, v]
+}
+ans.push(inLoop())
+
+function inCatch() {
+  try {
+    throw 'e'
+  } catch (e) {
+    function h() {
+      return e
+    }
+    return h()
+  }
+}
+ans.push(inCatch())
+ans
@@ -0,0 +1,52 @@
+var ans = []
+
+function get(o) {
+  return o.v
+}
+
+var p = { v: 1 }
+var a = {}
+a.__proto__ = p
+var b = {}
+b.__proto__ = p
+for (var i = 0; i < 3; i++) {
+  ans.push(get(a) + get(b))
+}
+
+// The cached holder is read again.
+p.v = 10
+ans.push(get(a))
+
+// An own property shadows the prototype.
+b.v = 5
+ans.push(get(b), get(a))
+
+// Replacing the prototype changes the shape.
+a.__proto__ = { v: 100 }
+ans.push(get(a))
+
+// A computed member accesses various keys at one site.
+var keys = ['x', 'y', 'z']
+var o = { x: 1, y: 2, z: 3 }
+var sum = 0
+for (var i = 0; i < 3; i++) {
+  sum += o[keys[i]]
+}
+ans.push(sum)
+
+// Setters on the prototype.
+var log = []
+var c = {}
+c.__proto__ = {
+  set w(v) {
+    log.push(v)
+  }
+}
+function setw(o, v) {
+  o.w = v
+}
+setw(c, 1)
+setw(c, 2)
+ans.push(log.length, c.w, get({}))
+
+ans
@@ -0,0 +1,43 @@
+// Intl.NumberFormat and Intl.DateTimeFormat. Dates are formatted in UTC or at fixed offsets, so
+// that the results do not depend on the local time zone.
+var numbers = [
+  new Intl.NumberFormat().format(1234567.891),
+  new Intl.NumberFormat('de-DE').format(1234567.891),
+  new Intl.NumberFormat('en-US', { style: 'currency', currency: 'USD' }).format(-1234.5),
+  new Intl.NumberFormat('en-US', { style: 'percent' }).format(0.256),
+  new Intl.NumberFormat('en', { minimumFractionDigits: 2 }).format(3),
+  new Intl.NumberFormat('en', { useGrouping: false }).format(12345),
+  (1234.5).toLocaleString(),
+  new Intl.NumberFormat('de-AT').resolvedOptions().locale,
+  new Intl.NumberFormat(['xx', 'en-GB']).resolvedOptions().locale
+]
+
+var t = 1578232980000 // 2020-01-05T14:03:00Z
+var long = {
+  timeZone: 'UTC',
+  weekday: 'long',
+  year: 'numeric',
+  month: 'long',
+  day: 'numeric'
+}
+var dates = [
+  new Intl.DateTimeFormat('en-US', { timeZone: 'UTC' }).format(t),
+  new Intl.DateTimeFormat('en-GB', { timeZone: 'UTC' }).format(t),
+  new Intl.DateTimeFormat('de-DE', { timeZone: 'UTC' }).format(t),
+  new Intl.DateTimeFormat('en-US', long).format(t),
+  new Intl.DateTimeFormat('de-DE', long).format(t),
+  new Intl.DateTimeFormat('en-US', { timeZone: 'UTC', hour: 'numeric', minute: '2-digit' }).format(t),
+  new Intl.DateTimeFormat('en-GB', { timeZone: 'UTC', hour: 'numeric', minute: 'numeric', second: 'numeric' }).format(t),
+  new Intl.DateTimeFormat('en-US', { timeZone: '+09:00', hour: 'numeric', minute: '2-digit' }).format(t),
+  new Intl.DateTimeFormat('en', { timeZone: '+09:00' }).resolvedOptions().timeZone
+]
+
+var error
+try {
+  new Intl.DateTimeFormat('en', { timeZone: 'Mars/Base' })
+} catch (e) {
+  error = e.message
+}
+
+var ans = [numbers, dates, error]
+ans
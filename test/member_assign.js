@@ -0,0 +1,63 @@
+var ans = []
+
+var a = { b: { c: { d: 0 } } }
+a.b.c.d = 1
+ans.push(a.b.c.d)
+
+var m = [[1, 2], [3, 4]]
+var i = 1
+var j = 0
+m[i][j] = 'v'
+ans.push(m[1])
+
+var log = []
+function obj() {
+  log.push('obj')
+  return o
+}
+function key() {
+  log.push('key')
+  return 'x'
+}
+function val() {
+  log.push('val')
+  return 5
+}
+var o = { x: 1 }
+obj()[key()] = val()
+ans.push(log.join(','))
+
+log = []
+obj()[key()] += 2
+ans.push(log.join(','))
+ans.push(o.x)
+
+var counter = 0
+function f() {
+  counter++
+  return 'n'
+}
+var p = { n: 10 }
+p[f()] += 2
+p[f()] *= 3
+ans.push([p.n, counter])
+
+var arr = [1, 2, 3]
+var k = 0
+arr[k++] += 10
+ans.push([arr, k])
+
+var q = { n: 1 }
+var r1 = q.n++
+var r2 = ++q.n
+var r3 = q.n--
+q['n']--
+ans.push([r1, r2, r3, q.n])
+
+var x = (a.b.c.d = 7)
+var y = (p.n -= 36)
+ans.push([x, a.b.c.d, y, p.n])
+
+a.b.c.d = a.b.c.d + 1
+ans.push(a.b.c.d)
+ans
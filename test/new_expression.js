@@ -0,0 +1,36 @@
+var ans = []
+
+function Point(x, y) {
+  this.x = x === undefined ? 0 : x
+  this.y = y === undefined ? 0 : y
+}
+
+// 'new F' is the same as 'new F()'.
+var p = new Point()
+var q = new Point
+ans.push(p.x, q.y, Point.prototype.isPrototypeOf(q))
+
+// The constructor may be a member, an element or the value of any expression.
+var ns = { Point: Point, inner: { ctors: [Point] } }
+var r = new ns.Point(1, 2)
+ans.push(r.x + r.y, Point.prototype.isPrototypeOf(r), new ns.inner.ctors[0](3).x)
+function getCtor() {
+  return Point
+}
+ans.push(new (getCtor())(4, 5).y, new (getCtor())().x)
+var which = true
+ans.push(new (which ? Point : Object)(6).x)
+
+// Builtin constructors
+ans.push(new Date(0).getTime(), new Array(3).length)
+var d = new Date
+ans.push(d.getTime() > 0)
+
+try {
+  new ns.nothing()
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
@@ -0,0 +1,30 @@
+var ans = []
+
+// Division by zero and overflow give Infinity or NaN.
+var zero = 0
+ans.push(1 / zero, -1 / zero, zero / zero, 1e308 * 10, -1e308 * 10)
+
+// % is fmod: the result has the sign of the dividend, and fractional operands work.
+var x = 7.5
+ans.push(5.5 % 2, -5 % 3, 5 % -3, 1 % zero, Infinity % 2, 2 % Infinity, x % 2)
+
+// NaN and Infinity are global properties.
+ans.push(isNaN(NaN), NaN === NaN, Infinity === 1 / zero, isNaN(globalThis.NaN))
+
+// They can not be assigned.
+NaN = 1
+Infinity = 2
+var NaN
+ans.push(Infinity, isNaN(NaN), globalThis.undefined === undefined)
+function strict() {
+  'use strict'
+  Infinity = 1
+}
+try {
+  strict()
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
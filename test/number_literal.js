@@ -0,0 +1,18 @@
+var ans = [
+  0xFF,
+  0o755,
+  0b1010,
+  1e9,
+  1.5e-3,
+  1_000_000,
+  0xff_ff,
+  .5 + 5.,
+  2E+2,
+  0x1_0000_0000_0000_0000 == 18446744073709551616,
+  010,
+  08.5,
+  1..toString(),
+  1.5.toFixed(2)
+]
+
+ans
@@ -0,0 +1,40 @@
+var ans = []
+var key = 'comp'
+var o = {
+  n: 1,
+  [key + 'uted']: 2,
+  inc() {
+    this.n++
+  },
+  get double() {
+    return this.n * 2
+  },
+  set double(v) {
+    this.n = v / 2
+  },
+  key,
+}
+
+o.inc()
+ans.push(o.n, o.computed, o.double, o.key)
+o.double = 10
+ans.push(o.n, o.double)
+
+var order = []
+function log(v) {
+  order.push(v)
+  return v
+}
+var p = {
+  [log('a')]: log(1),
+  get b() {
+    return 'getter'
+  },
+  b: 2,
+  c: 3,
+  get c() {
+    return 'getter'
+  },
+}
+ans.push(order.join(), p.b, p.c)
+ans
@@ -0,0 +1,36 @@
+var ans = []
+
+// hasOwnProperty does not look up the prototype chain.
+var proto = { inherited: 1 }
+var obj = Object.create(proto)
+obj.x = 2
+ans.push(obj.hasOwnProperty('x'), obj.hasOwnProperty('inherited'), obj.hasOwnProperty('__proto__'))
+ans.push(proto.hasOwnProperty('inherited'), {}.hasOwnProperty('toString'))
+
+// Elements and the length of arrays, strings and arguments are own properties. Holes are not.
+var a = [1, , 3]
+ans.push(a.hasOwnProperty(0), a.hasOwnProperty('1'), a.hasOwnProperty(3), a.hasOwnProperty('length'))
+ans.push('abc'.hasOwnProperty(2), 'abc'.hasOwnProperty(3), new String('ab').hasOwnProperty('length'))
+function args() {
+  return [arguments.hasOwnProperty(0), arguments.hasOwnProperty(1)]
+}
+ans.push(args(1))
+
+// The length is own but not enumerable.
+ans.push(a.propertyIsEnumerable(0), a.propertyIsEnumerable('length'), obj.propertyIsEnumerable('inherited'))
+
+// isPrototypeOf
+ans.push(proto.isPrototypeOf(obj), Object.prototype.isPrototypeOf(obj), obj.isPrototypeOf(proto))
+ans.push(Array.prototype.isPrototypeOf(a), Object.prototype.isPrototypeOf(1))
+
+// toString and valueOf
+ans.push({}.toString(), a.toString(), obj.valueOf() === obj)
+
+try {
+  Object.prototype.hasOwnProperty.call(null, 'x')
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
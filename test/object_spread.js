@@ -0,0 +1,46 @@
+var ans = []
+
+// Enumerable own properties are copied in order, and later properties override earlier ones.
+var a = { x: 1, y: 2 }
+var b = { y: 3, z: 4 }
+var merged = { ...a, ...b, w: 5 }
+ans.push(merged, Object.keys(merged))
+ans.push({ x: 0, ...a }.x, { ...a, x: 0 }.x)
+
+// Getters are called, and the copies are data properties.
+var withGetter = {
+  get g() {
+    return 'got'
+  },
+}
+var copied = { ...withGetter }
+ans.push(copied.g, Object.keys(copied))
+
+// Inherited and non-enumerable properties are not copied. undefined and null copy nothing.
+var child = Object.create(a)
+child.own = 1
+ans.push({ ...child }, { ...undefined, ...null, ...1 })
+ans.push({ ...[1, 2] }, { ...'hi' })
+
+// The spread object is a copy.
+var copy = { ...a }
+copy.x = 100
+ans.push(a.x)
+
+// Object.fromEntries
+ans.push(Object.fromEntries([['a', 1], ['b', 2], ['a', 3]]))
+ans.push(Object.fromEntries(['x', 'y'].entries()))
+var roundTrip = Object.fromEntries(
+  Object.keys(b).map(function(k) {
+    return [k, b[k] * 10]
+  })
+)
+ans.push(roundTrip)
+try {
+  Object.fromEntries([1])
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
@@ -0,0 +1,26 @@
+var ans = []
+var path = require('path')
+var os = require('os')
+
+// path is path.posix on Unix.
+ans.push(path.sep, path.join('a', 'b/../c', './d'), path.join(), path.normalize('/a//b/./../c/'))
+ans.push(path.dirname('/a/b/c.js'), path.basename('/a/b/c.js'), path.basename('/a/b/c.js', '.js'))
+ans.push(path.extname('c.tar.gz'), path.extname('.bashrc'), path.isAbsolute('/a'), path.isAbsolute('a'))
+ans.push(path.resolve('/a/b', '../c', 'd'), path.resolve('x') === path.join(process.cwd(), 'x'))
+
+// path.win32 accepts both separators, and handles drives.
+var win32 = path.win32
+ans.push(win32.join('C:\\a', 'b/c') === 'C:\\a\\b\\c', win32.normalize('C:/a/../b') === 'C:\\b')
+ans.push(win32.dirname('C:\\a\\b') === 'C:\\a', win32.basename('C:\\a\\b.txt', '.txt'))
+ans.push(win32.isAbsolute('C:\\a'), win32.isAbsolute('C:a'), win32.resolve('D:\\x', 'y') === 'D:\\x\\y')
+
+try {
+  path.join('a', 1)
+} catch (e) {
+  ans.push('error')
+}
+
+ans.push(os.platform() === process.platform, os.homedir().length > 0, os.tmpdir().length > 0)
+ans.push(os.cpus().length > 0, os.cpus()[0].speed >= 0, os.EOL === '\n')
+
+ans
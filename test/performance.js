@@ -0,0 +1,34 @@
+var ans = []
+
+// now() is monotonic, in milliseconds.
+var t0 = performance.now()
+for (var i = 0; i < 1000; i++) {}
+var t1 = performance.now()
+ans.push(t1 >= t0, t0 >= 0, performance.timeOrigin > 0)
+
+// Marks and measures are recorded as entries.
+var a = performance.mark('a', { startTime: 10, detail: 'x' })
+performance.mark('b', { startTime: 25 })
+var m = performance.measure('a to b', 'a', 'b')
+ans.push(a.entryType, a.startTime, a.duration, a.detail, m.startTime, m.duration)
+ans.push(performance.measure('given', { start: 5, duration: 3 }).duration)
+ans.push(performance.measure('from a', 'a').startTime)
+ans.push(
+  performance.getEntries().map(function(e) {
+    return e.name
+  })
+)
+ans.push(performance.getEntriesByType('mark').length, performance.getEntriesByName('b')[0].startTime)
+
+performance.clearMarks('a')
+ans.push(performance.getEntriesByType('mark').length)
+performance.clearMeasures()
+ans.push(performance.getEntries().length)
+
+try {
+  performance.measure('missing', 'nothing')
+} catch (e) {
+  ans.push('error')
+}
+
+ans
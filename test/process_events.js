@@ -0,0 +1,22 @@
+// Errors the script does not catch and promises rejected without handlers are passed to the
+// listeners of process, and the script goes on.
+var log = []
+process.on('uncaughtException', function(err, origin) {
+  log.push([err, origin])
+})
+process.on('unhandledRejection', function(reason, promise) {
+  log.push(['rejection', reason])
+})
+setTimeout(function() {
+  throw 'timer'
+}, 10)
+setTimeout(function() {
+  log.push('after')
+}, 20)
+
+Promise.reject(1)
+Promise.reject(2).catch(function() {})
+Promise.reject(3).then(function() {})
+
+var ans = log
+ans
@@ -0,0 +1,37 @@
+var log = []
+
+Promise.resolve(1)
+  .then(function(x) {
+    log.push(x)
+    return x + 1
+  })
+  .then(function(x) {
+    log.push(x)
+    throw 'oops'
+  })
+  .catch(function(e) {
+    log.push(e)
+    return Promise.resolve('adopted')
+  })
+  .then(function(x) {
+    log.push(x)
+  })
+
+new Promise(function(resolve, reject) {
+  reject('rejected')
+  resolve('ignored')
+}).then(undefined, function(e) {
+  log.push(e)
+})
+
+new Promise(function(resolve) {
+  setTimeout(function() {
+    resolve('later')
+  }, 20)
+}).then(function(x) {
+  log.push(x)
+})
+
+log.push(0)
+
+log
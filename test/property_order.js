@@ -0,0 +1,21 @@
+var ans = []
+
+// Integer keys in ascending order, then the other keys in insertion order.
+var o = { b: 1, 2: 1, a: 1, 1: 1 }
+o.z = 1
+o['0'] = 1
+o['01'] = 1
+o.b = 2
+ans.push(Object.keys(o))
+
+// Elements of an array come before its other properties.
+var arr = ['x', 'y']
+arr[3] = 'z'
+arr.extra = 1
+ans.push(Object.keys(arr))
+
+// Objects are printed in the same order.
+ans.push({ y: 1, x: 2, 10: 3, 9: 4 })
+
+console.log(ans)
+ans
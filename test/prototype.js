@@ -0,0 +1,40 @@
+var ans = []
+
+// Every object gets its prototype when it is made.
+ans.push(Object.getPrototypeOf({}) === Object.prototype)
+ans.push(Object.getPrototypeOf([]) === Array.prototype)
+ans.push(Object.getPrototypeOf(function() {}) === Function.prototype)
+ans.push(Object.getPrototypeOf(Object.prototype) === null)
+ans.push(Object.getPrototypeOf(1) === Number.prototype)
+
+// __proto__ is not an own property.
+var o = { a: 1 }
+ans.push(o)
+
+// Object.create
+var p = { greet: 'hi' }
+var c = Object.create(p)
+ans.push(c.greet, c.__proto__ === p)
+var bare = Object.create(null)
+ans.push(Object.getPrototypeOf(bare) === null, bare.toString === undefined)
+
+// Object.setPrototypeOf and the __proto__ setter
+var q = { v: 2 }
+ans.push(Object.setPrototypeOf(o, q) === o, o.v)
+o.__proto__ = { v: 3 }
+ans.push(o.v)
+o.__proto__ = 42
+ans.push(o.v)
+var arr = [1]
+arr.__proto__ = q
+ans.push(arr.v)
+
+// A cyclic chain throws.
+try {
+  Object.setPrototypeOf(q, arr)
+} catch (e) {
+  ans.push('cyclic')
+}
+
+console.log(ans)
+ans
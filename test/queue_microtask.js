@@ -0,0 +1,44 @@
+var ans = []
+
+// Jobs run after the script, in the order they are enqueued, before any timer.
+setTimeout(function() {
+  ans.push('timeout')
+  queueMicrotask(function() {
+    ans.push('job of timeout')
+  })
+  Promise.resolve().then(function() {
+    ans.push('then of timeout')
+  })
+}, 0)
+setTimeout(function() {
+  ans.push('second timeout')
+}, 0)
+Promise.resolve(1).then(function(x) {
+  ans.push('then ' + x)
+  queueMicrotask(function() {
+    ans.push('nested job')
+  })
+})
+queueMicrotask(function() {
+  ans.push('job')
+})
+
+// An error thrown by a job goes to 'uncaughtException', and the other jobs go on.
+process.on('uncaughtException', function(err) {
+  ans.push('caught ' + err)
+})
+queueMicrotask(function() {
+  throw 'oops'
+})
+queueMicrotask(function() {
+  ans.push('after error')
+})
+
+try {
+  queueMicrotask(1)
+} catch (e) {
+  ans.push('error')
+}
+ans.push('sync')
+
+ans
@@ -0,0 +1,27 @@
+var ans = []
+
+var a = 3
+var b = 4
+ans.push(a * a + b * b, (a + b) * (a - b), a / 2 + b % 3, 10 - a - b)
+ans.push(a < b, a > b, a + 1 <= b, a >= b + 1)
+
+function fib(n) {
+  if (n < 2) return n
+  return fib(n - 1) + fib(n - 2)
+}
+ans.push(fib(15))
+
+function twice(x) {
+  return x * 2
+}
+// calls in the middle of register expressions
+ans.push(a + twice(b) * twice(a + b) - 1)
+
+var s = 'a' + 1 + 2
+ans.push(s, 1 + 2 + 'b', 2n * 3n + 1n)
+
+var sum = 0
+for (var i = 0; i < 10; i++) sum = sum + i * i
+ans.push(sum)
+
+ans
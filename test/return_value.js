@@ -0,0 +1,35 @@
+var ans = []
+
+// Every call leaves exactly one value: void functions, early returns and nested calls.
+function nothing() { var x = 1 }
+function early(n) {
+  if (n > 0) return 'positive'
+  if (n < 0) return
+  n = 0
+}
+function depth(n) { return n > 0 ? 1 + depth(n - 1) : 0 }
+function sum(a, b) { return a + b }
+function C() { this.x = 1; return }
+ans.push(nothing(), early(1), early(-1), early(0))
+ans.push(depth(10), sum(sum(1, 2), sum(depth(3), nothing() === undefined ? 4 : 0)))
+ans.push(new C().x)
+
+// The values pushed before an error are left when it is caught.
+function thrower() { throw 'error' }
+function caught() {
+  var r = 0
+  for (var i = 0; i < 3; i++) {
+    try { r = 1 + thrower() } catch (e) { r += 1 }
+  }
+  return r
+}
+function inner() { return sum(1, thrower()) }
+function outer() {
+  try { return [1, 2, inner()] } catch (e) { return e }
+}
+ans.push(caught(), [5, outer()][1])
+ans.push(eval('try { 1 + thrower() } catch (e) {}') === undefined)
+ans.push(eval('var z = 1') === undefined)
+
+console.log(ans)
+ans
@@ -0,0 +1,81 @@
+// Local variables are kept in registers unless a nested function, 'with', 'arguments' or a
+// direct eval may refer to them by name.
+function locals(a, b) {
+  var sum = 0
+  for (var i = a; i < b; i++) sum += i
+  var before = later
+  var later = 1
+  return [sum, before, later]
+}
+function shadow(x) {
+  var inner = function(y) {
+    var x = y * 2
+    return x
+  }
+  return inner(x) + x
+}
+function captured() {
+  var n = 1
+  var get = function() {
+    return n
+  }
+  n = 2
+  return get()
+}
+function hoisted() {
+  return g()
+  function g() {
+    return 'hoisted'
+  }
+}
+function caught() {
+  var e = 'outer'
+  try {
+    throw 'inner'
+  } catch (e) {
+    var seen = e
+  }
+  return [seen, e]
+}
+function evaluated(x) {
+  var y = 3
+  return eval('x + y')
+}
+function withScope() {
+  var a = 1
+  var o = { a: 2 }
+  with (o) {
+    a = 3
+  }
+  return [a, o.a]
+}
+function args(a) {
+  a = 5
+  return arguments[0]
+}
+var outer = 10
+function global() {
+  return outer + 1
+}
+function updates() {
+  var a = 1
+  var b = 2
+  var c = 3
+  a += b
+  b++
+  ++c
+  return [a, b, c]
+}
+
+var ans = []
+ans.push(locals(1, 4))
+ans.push(shadow(3))
+ans.push(captured())
+ans.push(hoisted())
+ans.push(caught())
+ans.push(evaluated(2))
+ans.push(withScope())
+ans.push(args(1))
+ans.push(global())
+ans.push(updates())
+ans
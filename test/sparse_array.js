@@ -0,0 +1,65 @@
+var ans = []
+
+var a = [1, 2]
+a[1000000] = 'far'
+ans.push(a.length)
+ans.push(a[1000000])
+ans.push(a[500])
+
+var b = [1, 2, 3, 4, 5]
+b.length = 2
+ans.push(b.toString())
+b.length = 4
+ans.push(b[2])
+ans.push(b.length)
+
+var c = []
+c['2'] = 'two'
+c['02'] = 'not index'
+c[-1] = 'negative'
+ans.push(c.length)
+ans.push(c[2])
+ans.push(c['02'])
+ans.push(c[-1])
+
+try {
+  b.length = -1
+} catch (e) {
+  ans.push(e.message)
+}
+try {
+  new Array(1.5)
+} catch (e) {
+  ans.push(e.message)
+}
+
+var d = new Array(4294967295)
+ans.push(d.length)
+d.length = 0
+ans.push(d.length)
+
+var e = [1, 2, 3]
+e[6] = 7
+ans.push(e.toString())
+e.length = 5
+ans.push(e.pop())
+ans.push(e.length)
+e.push('x')
+ans.push(e.toString())
+
+var f = []
+f[4294967294] = 1
+ans.push(Object.keys(f).toString())
+var g = f.map(function (x) { return x + 1 })
+ans.push(g.length, g[4294967294])
+try {
+  f + ''
+} catch (err) {
+  ans.push(err.message)
+}
+try {
+  (function () {}).apply(null, f)
+} catch (err) {
+  ans.push(err.message)
+}
+ans
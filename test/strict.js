@@ -0,0 +1,27 @@
+'use strict'
+var ans = []
+
+function plain() {
+  return this
+}
+var obj = {
+  method: function() {
+    return this === obj
+  }
+}
+var detached = obj.method
+
+ans.push(plain() === undefined)
+ans.push(obj.method())
+ans.push(detached())
+
+try {
+  undeclared = 1
+} catch (e) {
+  ans.push(e)
+}
+
+var declared = 1
+declared = 2
+ans.push(declared)
+ans
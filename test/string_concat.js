@@ -0,0 +1,22 @@
+var ans = []
+
+var s = ''
+for (var i = 0; i < 10000; i++) {
+  s += 'item ' + i + ', '
+}
+ans.push(s.length)
+ans.push(s[0] + s[5])
+ans.push(s.charAt(s.length - 3))
+
+var t = s
+t += '!'
+ans.push(t.length - s.length)
+ans.push(t == s + '!')
+
+var parts = 'ab'
+for (var j = 0; j < 5; j++) {
+  parts = parts + parts
+}
+ans.push(parts.length)
+ans.push(parts.replace('ba', '-').length)
+ans
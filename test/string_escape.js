@@ -0,0 +1,14 @@
+var ans = []
+
+ans.push('a\tb\nc'.length)
+ans.push(['\x41\x42C', 'ABC', '\u{1F600}'.length, '\u{1F600}' == '😀'])
+ans.push(['\0'.charCodeAt(0), '\101', '\'"\\'.length])
+ans.push('line \
+continued')
+
+var a\u0062c = 1
+ans.push(abc)
+var obj = { k\u{65}y: 'v' }
+ans.push(obj.key)
+
+ans
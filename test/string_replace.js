@@ -0,0 +1,21 @@
+var ans = []
+
+ans.push('a-b-c'.replace('-', '+'))
+ans.push('abc'.replace('x', 'y'))
+ans.push('price: 10'.replace('10', '$$$&'))
+ans.push('abc'.replace('b', "[$`|$']"))
+ans.push('abc'.replace('b', '$1$'))
+ans.push('hello world'.replace('world', function(matched, offset, s) {
+  return matched.toString() + offset + s.length
+}))
+ans.push('aaa'.replace('', '-'))
+ans.push(new String('xyz').replace('y', 'Y'))
+
+try {
+  'abc'.replace('b', function() {
+    throw 'thrown'
+  })
+} catch (e) {
+  ans.push(e)
+}
+ans
@@ -0,0 +1,26 @@
+var ans = []
+
+var s = 'a😀b'
+ans.push(s.length)
+ans.push(s[0])
+ans.push(s.charCodeAt(1))
+ans.push(s.charCodeAt(2))
+ans.push(s.codePointAt(1))
+ans.push(s.codePointAt(2))
+ans.push(s.charAt(3))
+ans.push(s.charAt(4))
+ans.push(s[4])
+ans.push(s.charCodeAt(10))
+ans.push(s.codePointAt(-1))
+
+var pair = s[1] + s[2]
+ans.push(pair == '😀')
+ans.push(pair.length)
+
+ans.push('日本語'.length)
+ans.push('日本語'[1])
+ans.push(Array.from(s).length)
+
+ans.push('😀' < '｡')
+ans.push(new String('x😀').length)
+ans
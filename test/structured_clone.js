@@ -0,0 +1,32 @@
+var ans = []
+
+var obj = { a: 1, list: [1, 'x'], nested: { b: true } }
+obj.self = obj
+var copy = structuredClone(obj)
+ans.push(copy !== obj, copy.self === copy, copy.nested !== obj.nested, copy.nested.b, copy.list[1])
+
+var shared = { n: 1 }
+var pair = structuredClone([shared, shared])
+ans.push(pair[0] === pair[1], pair[0] !== shared)
+
+var date = new Date()
+var dateCopy = structuredClone(date)
+ans.push(dateCopy !== date, '' + dateCopy == '' + date)
+
+var bytes = new Uint8Array([1, 2, 3])
+var view = new Uint8Array(bytes.buffer, 1, 2)
+var views = structuredClone([bytes, view])
+views[0][1] = 20
+ans.push(views[1][0], bytes[1])
+
+var wrapper = structuredClone(new Number(5))
+ans.push(wrapper !== 5, wrapper + 1)
+ans.push(structuredClone(2n ** 70n) == 2n ** 70n)
+
+try {
+  structuredClone({ f: function() {} })
+} catch (e) {
+  ans.push(e.message.replace('DataCloneError: ', '') != e.message)
+}
+
+ans
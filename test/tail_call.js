@@ -0,0 +1,46 @@
+var ans = []
+
+function sum(n, acc) {
+  if (n == 0) return acc
+  return sum(n - 1, acc + n)
+}
+ans.push(sum(100000, 0))
+
+function countDown(n) {
+  var local = n
+  if (n == 0) return arguments.length
+  return countDown(local - 1, 'extra')
+}
+ans.push(countDown(100000))
+
+function other(x) {
+  return twice(x)
+}
+function twice(x) {
+  return x * 2
+}
+ans.push(other(21))
+
+function max(a, b) {
+  return Math.max(a, b)
+}
+ans.push(max(3, 4))
+
+var obj = {
+  n: 5,
+  get: function() {
+    return this.value()
+  },
+  value: function() {
+    return this.n
+  }
+}
+ans.push(obj.get())
+
+function inTry(n) {
+  try {
+    return n == 0 ? 'done' : inTry(n - 1)
+  } catch (e) {}
+}
+ans.push(inTry(10))
+ans
@@ -0,0 +1,52 @@
+var ans = []
+
+// Substitutions are converted to strings.
+var name = 'world'
+var n = 3
+ans.push(`hello ${name}!`, `${n + 1}${undefined}`, `a${[1, 2]}b`, `<${`${n}`}>`)
+ans.push(`line1
+line2`.length, `\u{61}\x62`)
+
+// A tag gets the template object, whose elements are the cooked strings and whose 'raw' has
+// the raw strings, and the values of the substitutions.
+function tag(strings, a, b) {
+  return [strings.length, strings[0] === '\t', strings.raw[0] === '\\t', strings[1], a, b]
+}
+ans.push(tag`\t${1}x${2}`)
+var obj = {
+  p: '!',
+  t: function(strings) {
+    return strings[0] + this.p
+  },
+}
+ans.push(obj.t`hi`)
+
+// An invalid escape sequence is allowed in a tagged template, and its cooked string is
+// undefined.
+function first(strings) {
+  return [strings[0], strings.raw[0] === '\\unicode']
+}
+ans.push(first`\unicode`)
+
+// The template object is the same every time the tagged template runs. 'raw' is not
+// enumerable.
+function site(strings) {
+  return strings
+}
+function get() {
+  return site`a${0}b`
+}
+var t = get()
+ans.push(t === get(), Object.keys(t))
+
+// String.raw
+ans.push(String.raw`a\n${n}b` === 'a\\n3b')
+ans.push(String.raw({ raw: ['x', 'y', 'z'] }, 1, 2, 3), String.raw({ raw: 'abc' }, '-'))
+try {
+  String.raw(undefined)
+} catch (e) {
+  ans.push('error')
+}
+
+console.log(ans)
+ans
@@ -0,0 +1,32 @@
+var ans = []
+
+var bytes = new TextEncoder().encode('aé€😀')
+ans.push(bytes.length)
+ans.push([bytes[0], bytes[1], bytes[3], bytes[6]])
+var decoder = new TextDecoder()
+ans.push(decoder.encoding)
+ans.push(decoder.decode(bytes) == 'aé€😀')
+ans.push(decoder.decode(new Uint8Array([0xef, 0xbb, 0xbf, 0x68, 0x69])))
+ans.push(decoder.decode(new Uint8Array([0x61, 0xff])) == 'a�')
+
+try {
+  new TextDecoder('utf-8', { fatal: true }).decode(new Uint8Array([0xff]))
+} catch (e) {
+  ans.push(e.message.replace('TypeError: ', '') != e.message)
+}
+
+ans.push(btoa('hello'))
+ans.push(atob('aGVsbG8='))
+ans.push(atob(btoa('ÿ\u0000')) == 'ÿ\u0000')
+try {
+  btoa('€')
+} catch (e) {
+  ans.push(e.message.replace('InvalidCharacterError: ', '') != e.message)
+}
+try {
+  atob('a')
+} catch (e) {
+  ans.push(e.message.replace('InvalidCharacterError: ', '') != e.message)
+}
+
+ans
@@ -0,0 +1,76 @@
+var ans = []
+
+function sloppy() {
+  return this
+}
+function strictFn() {
+  'use strict'
+  return this
+}
+
+// A plain call gets the global object in sloppy mode and undefined in strict mode. A method
+// call gets the object, and an extracted method does not keep it.
+var obj = {
+  get: function() {
+    return this
+  },
+  strictGet: strictFn,
+}
+var f = obj.get
+ans.push(sloppy() === globalThis, strictFn() === undefined, f() === globalThis, obj.get() === obj)
+ans.push(obj['get']() === obj, obj.strictGet() === obj)
+function extract() {
+  var g = obj.get
+  return g() === globalThis
+}
+ans.push(extract())
+
+// Callbacks get thisArg, or undefined.
+ans.push(
+  [1].map(function() {
+    return this === globalThis
+  })[0]
+)
+ans.push(
+  [1].map(function() {
+    return this
+  }, obj)[0] === obj
+)
+ans.push([1].map(strictFn)[0] === undefined)
+
+// A primitive 'this' is wrapped in sloppy mode only.
+ans.push(strictFn.call(5) === 5, sloppy.call(5) === 5, sloppy.call(null) === globalThis)
+ans.push(sloppy.call('ab').length)
+
+// A function called inside a method does not get the object of the method.
+var counter = {
+  n: 0,
+  inc: function() {
+    this.n++
+    function inner() {
+      return this
+    }
+    return inner() === globalThis
+  },
+}
+ans.push(counter.inc())
+ans.push(counter.n)
+var getter = {
+  v: 3,
+  get double() {
+    return this.v * 2
+  },
+}
+ans.push(getter.double)
+
+// A builtin called inside itself keeps its own 'this'.
+ans.push(
+  [[1, 2], [3]].map(function(a) {
+    return a.map(function(x) {
+      return x * 2
+    })
+  })
+)
+
+console.log(ans)
+ans
@@ -0,0 +1,21 @@
+var ans = []
+
+// Elisions make holes, and a trailing comma adds no element.
+var a = [1, , 3]
+ans.push(a.length, a[1], a.toString())
+var b = [, 'x', , ]
+ans.push(b.length, b[0], b[1])
+ans.push([1, 2, 3,].length, [,].length)
+
+// Trailing commas in object literals, parameter lists and arguments
+var o = {
+  x: 1,
+  y: 2,
+}
+function add(p, q,) {
+  return p + q
+}
+ans.push(o.y, add.length, add(1, 2,))
+
+console.log(ans)
+ans
@@ -0,0 +1,49 @@
+var ans = []
+var buf = new ArrayBuffer(8)
+var u8 = new Uint8Array(buf)
+var u32 = new Uint32Array(buf)
+ans.push(buf.byteLength, u8.length, u32.length, u32.byteLength)
+
+u8[4] = 255
+u8[5] = 255
+u8[6] = 255
+u8[7] = 255
+ans.push(u32[1])
+
+var i8 = new Int8Array(buf, 4, 2)
+ans.push(i8[0], i8.length, i8.byteOffset)
+
+u8[0] = 263
+ans.push(u8[0], u8[8])
+
+var clamped = new Uint8ClampedArray([300, -5, 1.5, 2.5])
+ans.push(clamped.join())
+
+var f = new Float64Array(2)
+f[0] = 0.1
+ans.push(f[0], f[1])
+
+var view = new DataView(buf)
+view.setUint16(0, 0x1234)
+ans.push(u8[0], u8[1], view.getUint16(0, true))
+view.setFloat32(0, 1.5, true)
+ans.push(view.getFloat32(0, true), view.getInt8(7))
+
+var sub = u8.subarray(1, 3)
+sub[0] = 9
+ans.push(u8[1], sub.length)
+
+var a = new Int16Array(4)
+a.fill(-1)
+a.set([1, 2], 2)
+ans.push(a.join())
+
+ans.push(Uint8Array.BYTES_PER_ELEMENT, Float64Array.BYTES_PER_ELEMENT, ArrayBuffer.isView(u8))
+
+try {
+  view.getInt32(6)
+} catch (e) {
+  ans.push('out of range')
+}
+
+ans
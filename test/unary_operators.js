@@ -0,0 +1,23 @@
+var ans = []
+var s = '42'
+var flag = false
+var n = 5
+
+// + is ToNumber.
+ans.push(+s, +'', +'x', +true, +null, +undefined, +[7], +new Number(3))
+
+// ! is the negation of ToBoolean.
+ans.push(!flag, !'', !'a', !0, !NaN, !{}, !!n)
+
+// ~ is the bitwise not of ToInt32.
+ans.push(~n, ~-1, ~'3', ~1.9, ~4294967296, ~NaN, ~undefined)
+
+// - converts its operand too.
+ans.push(-s, -'3', ~5n === -6n, ~~'12.7')
+
+if (!flag) {
+  ans.push('not')
+}
+
+console.log(ans)
+ans
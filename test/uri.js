@@ -0,0 +1,28 @@
+var ans = []
+
+ans.push(encodeURIComponent('a b&c/d?é'))
+ans.push(encodeURI('http://x.com/a b?q=1&r=é#h'))
+ans.push(encodeURIComponent('😀'))
+ans.push(decodeURIComponent('a%20b%26c%2Fd%3F%C3%A9'))
+ans.push(decodeURI('%3B%2F%20%C3%A9%23'))
+ans.push(decodeURIComponent('%F0%9F%98%80') == '😀')
+
+try {
+  decodeURIComponent('%E0%A4%A')
+} catch (e) {
+  ans.push(e.message)
+}
+try {
+  decodeURIComponent('%C0%80')
+} catch (e) {
+  ans.push('overlong')
+}
+try {
+  encodeURIComponent('😀'[0])
+} catch (e) {
+  ans.push('lone surrogate')
+}
+
+ans.push(escape('a b+é€'))
+ans.push(unescape('a%20b%u20AC%zz%'))
+ans
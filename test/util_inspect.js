@@ -0,0 +1,26 @@
+var util = require('util')
+var ans = []
+
+// Objects nested deeper than 'depth' (2 by default) are shown as [Object] or [Array].
+var nested = { a: { b: { c: { d: [1] } } } }
+ans.push(util.inspect(nested) === "{ 'a': { 'b': { 'c': [Object] } } }")
+ans.push(util.inspect(nested, { depth: 0 }) === "{ 'a': [Object] }")
+ans.push(util.inspect(nested, { depth: null }) === "{ 'a': { 'b': { 'c': { 'd': [ 1 ] } } } }")
+ans.push(util.inspect(nested, { depth: Infinity }) === util.inspect(nested, { depth: null }))
+ans.push(util.inspect([[[[1]]]]) === '[ [ [ [Array] ] ] ]')
+
+// Strings are quoted, unlike in console.log().
+ans.push(util.inspect('str') === "'str'", util.inspect(1.5) === '1.5')
+ans.push(util.inspect([1, 'a', , null, undefined]) === "[ 1, 'a', <1 empty item>, null, undefined ]")
+var cyclic = { name: 'c' }
+cyclic.self = cyclic
+ans.push(util.inspect(cyclic) === "{ 'name': 'c', 'self': [Circular] }")
+
+// colors styles values with ANSI escape codes.
+ans.push(util.inspect(1, { colors: true }) === '\u001b[33m1\u001b[39m')
+var colored = "{ 's': \u001b[32m'x'\u001b[39m, 'n': \u001b[1mnull\u001b[22m }"
+ans.push(util.inspect({ s: 'x', n: null }, { colors: true }) === colored)
+ans.push(util.inspect(undefined, { colors: false }) === 'undefined')
+
+console.log(ans)
+ans
@@ -0,0 +1,34 @@
+var ans = []
+
+var a = 1, b = a + 1, c
+ans.push([a, b, c])
+
+function f() {
+  return 'f'
+}
+let x, y = f()
+ans.push([x, y])
+
+const k = 10, m = k * 2
+ans.push(k + m)
+
+var sum = 0
+for (var i = 0, n = 4; i < n; i++) {
+  sum += i
+}
+ans.push(sum)
+
+var pairs = []
+for (let p = 0, q = 3; p < q; p++, q--) {
+  pairs.push([p, q])
+}
+ans.push(pairs)
+
+function inner() {
+  var s = 's', t = s + 't'
+  let u = t + 'u', v
+  return [s, t, u, v]
+}
+ans.push(inner())
+
+ans
@@ -0,0 +1,44 @@
+// (module
+//   (import "env" "double" (func $double (param i32) (result i32)))
+//   (func (export "add") (param i32 i32) (result i32)
+//     (i32.add (local.get 0) (local.get 1)))
+//   (func (export "addDouble") (param i32 i32) (result i32)
+//     (call $double (i32.add (local.get 0) (local.get 1)))))
+var bytes = new Uint8Array([
+  0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+  0x01, 0x0c, 0x02, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x01, 0x7f, 0x01, 0x7f,
+  0x02, 0x0e, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x06, 0x64, 0x6f, 0x75, 0x62, 0x6c, 0x65, 0x00, 0x01,
+  0x03, 0x03, 0x02, 0x00, 0x00,
+  0x07, 0x13, 0x02, 0x03, 0x61, 0x64, 0x64, 0x00, 0x01,
+  0x09, 0x61, 0x64, 0x64, 0x44, 0x6f, 0x75, 0x62, 0x6c, 0x65, 0x00, 0x02,
+  0x0a, 0x13, 0x02, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+  0x09, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x10, 0x00, 0x0b
+])
+
+var ans = [WebAssembly.validate(bytes), WebAssembly.validate(new Uint8Array([0, 1, 2]))]
+
+var doubled = []
+WebAssembly.instantiate(bytes, {
+  env: {
+    double: function(x) {
+      doubled.push(x)
+      return x * 2
+    }
+  }
+}).then(function(result) {
+  var exports = result.instance.exports
+  ans.push(exports.add(1, 2))
+  ans.push(exports.addDouble(3, 4))
+  ans.push(doubled)
+})
+
+var truncated = new Uint8Array([0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01])
+WebAssembly.instantiate(truncated).then(undefined, function(e) {
+  ans.push(e.message.replace('CompileError: ', '') != e.message)
+})
+
+WebAssembly.instantiate(bytes, {}).then(undefined, function(e) {
+  ans.push(e.message.replace('LinkError: ', '') != e.message)
+})
+
+ans
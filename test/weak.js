@@ -0,0 +1,35 @@
+var ans = []
+
+var map = new WeakMap()
+var key = {}
+ans.push(map.set(key, 1) === map)
+ans.push(map.get(key))
+ans.push(map.has(key))
+ans.push(map.has({}))
+ans.push(map.delete(key))
+ans.push(map.has(key))
+try {
+  map.set('key', 1)
+} catch (e) {
+  ans.push('primitive key')
+}
+
+var set = new WeakSet()
+set.add(key)
+ans.push(set.has(key))
+ans.push(set.has(map))
+
+// Values are kept while their keys are alive.
+map.set(key, { v: 2 })
+gc.collect()
+ans.push(map.get(key).v)
+
+var ref = new WeakRef({ x: 1 })
+ans.push(ref.deref().x)
+var target = { y: 2 }
+var kept = new WeakRef(target)
+gc.collect()
+ans.push(ref.deref() === undefined)
+ans.push(kept.deref() === target)
+
+ans
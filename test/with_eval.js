@@ -0,0 +1,84 @@
+var ans = []
+
+var o = { a: 1, b: 2 }
+var a = 'outer'
+with (o) {
+  ans.push(a + b)
+  a = 10
+  c = 'global'
+}
+ans.push(o.a)
+ans.push(a)
+ans.push(c)
+
+function inFunction(obj) {
+  with (obj) {
+    return x
+  }
+}
+ans.push(inFunction({ x: 'x' }))
+
+var getter
+with ({ v: 'captured' }) {
+  getter = function() {
+    return v
+  }
+}
+ans.push(getter())
+
+var r = []
+for (var i = 0; i < 5; i++) {
+  with ({ k: i }) {
+    if (k == 1) continue
+    if (k == 3) break
+    r.push(k)
+  }
+}
+try {
+  k
+} catch (e) {
+  r.push('no k')
+}
+ans.push(r)
+
+try {
+  with ({ w: 1 }) {
+    throw 'thrown'
+  }
+} catch (e) {
+  ans.push(e)
+}
+try {
+  w
+} catch (e) {
+  ans.push('no w')
+}
+
+ans.push(eval('1 + 2'))
+ans.push(eval(5))
+
+var e1 = 'global'
+function direct() {
+  var e1 = 'local'
+  return eval('e1')
+}
+function indirect() {
+  var e1 = 'local'
+  var e = eval
+  return e('e1')
+}
+ans.push(direct())
+ans.push(indirect())
+
+function declares() {
+  eval('var d = 5; function f() { return d * 2 }')
+  return f()
+}
+ans.push(declares())
+
+try {
+  eval('var = 1')
+} catch (e) {
+  ans.push('syntax error')
+}
+ans
@@ -0,0 +1,10 @@
+var ans = []
+var n = new Number(3)
+var s = new String('abc')
+var b = new Boolean(false)
+
+ans.push(n + 1, n == 3, n === 3, n.valueOf() === 3)
+ans.push(s.length, s[1], s.toString(), s == 'abc')
+ans.push(b.valueOf(), b ? 'object is truthy' : 'falsy')
+ans.push(Number('42'), String(12), Boolean(''), (255).toString(16), 'xyz'.valueOf(), true.toString())
+ans
@@ -0,0 +1,72 @@
+// Snapshot tests. Each tests/scripts/NAME.js is run by the rapidus binary, and what it prints
+// to stdout is compared with tests/scripts/NAME.out. A non-zero exit status is recorded at the
+// end of the output as "exit status: N". Math.random() is seeded so that the output is the same
+// every time.
+//
+// Run with UPDATE_SNAPSHOTS=1 to write the .out files from the current output instead.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn run_script(script: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rapidus"))
+        .arg("--no-jit")
+        .arg("--random-seed=1")
+        .arg(script)
+        .current_dir(script.parent().unwrap())
+        .output()
+        .expect("cannot run rapidus");
+    let mut stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    match output.status.code() {
+        Some(0) => {}
+        Some(code) => stdout += &format!("exit status: {}\n", code),
+        None => stdout += "killed by a signal\n",
+    }
+    stdout
+}
+
+#[test]
+fn scripts() {
+    let update = env::var("UPDATE_SNAPSHOTS").map_or(false, |val| val == "1");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "js"))
+        .collect();
+    scripts.sort();
+
+    let mut failures = vec![];
+    for script in &scripts {
+        let output = run_script(script);
+        let snapshot = script.with_extension("out");
+        if update {
+            fs::write(&snapshot, &output).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(ref expected) if expected == &output => {}
+            Ok(expected) => failures.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                script.display(),
+                expected,
+                output
+            )),
+            Err(_) => failures.push(format!(
+                "{}: no snapshot. Run with UPDATE_SNAPSHOTS=1 to make it.",
+                script.display()
+            )),
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} scripts failed\n\n{}",
+            failures.len(),
+            scripts.len(),
+            failures.join("\n")
+        );
+    }
+}
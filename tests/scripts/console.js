@@ -0,0 +1,13 @@
+console.log('hello', 1, true, null, undefined)
+console.log([1, 'a', [2, [3, [4]]]])
+
+var o = { a: 1, b: { c: { d: { e: 1 } } } }
+console.log(o)
+o.self = o
+console.log(o)
+
+console.count()
+console.count('x')
+console.count()
+
+console.table([{ a: 1, b: 'x' }, { a: 2 }])
@@ -0,0 +1,12 @@
+var registry = new FinalizationRegistry(function(held) {
+  console.log('cleaned up', held)
+})
+registry.register({}, 'a')
+var kept = {}
+registry.register(kept, 'b')
+var token = {}
+registry.register({}, 'c', token)
+console.log('unregistered', registry.unregister(token))
+
+gc.collect()
+console.log('sync')
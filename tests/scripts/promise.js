@@ -0,0 +1,9 @@
+setTimeout(function() {
+  console.log('timeout')
+}, 10)
+
+Promise.resolve(1).then(function(x) {
+  console.log('then', x)
+})
+
+console.log('sync')
@@ -0,0 +1,6 @@
+// The scripts are run with --random-seed 1, so Math.random() returns the same numbers every time.
+var r = []
+for (var i = 0; i < 5; i++) {
+  r.push(Math.floor(Math.random() * 1000))
+}
+console.log(r.join(' '))
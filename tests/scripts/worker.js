@@ -0,0 +1,22 @@
+var worker = new Worker('worker/echo.js')
+var received = 0
+worker.onmessage = function(e) {
+  received++
+  if (received == 1) {
+    console.log('number', e.data)
+  } else {
+    console.log('object', e.data.list[1], e.data.list.length, e.data.nested.b)
+    worker.terminate()
+  }
+}
+
+worker.postMessage(21)
+worker.postMessage({ list: [1, 'a'], nested: { b: true } })
+
+try {
+  worker.postMessage(function() {})
+} catch (e) {
+  console.log('cloning a function:', e.message.replace('DataCloneError: ', '') != e.message)
+}
+
+console.log('sync')
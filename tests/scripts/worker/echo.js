@@ -0,0 +1,10 @@
+// Sends the first message back doubled, and the others as they are.
+var count = 0
+onmessage = function(e) {
+  count++
+  if (count == 1) {
+    postMessage(e.data * 2)
+  } else {
+    postMessage(e.data)
+  }
+}
@@ -0,0 +1,380 @@
+// test262 runner. Runs the tests of the ECMAScript conformance suite and prints how many
+// tests pass for each feature.
+//
+//   TEST262_DIR=/path/to/test262 cargo test --test test262
+//
+// Without TEST262_DIR, the suite is looked for in target/test262, and it is cloned there if
+// TEST262_DOWNLOAD=1 is set. Tests are filtered by TEST262_FILTER (a part of the path under
+// test/, e.g. built-ins/Array), and TEST262_VERBOSE=1 shows why each test failed.
+//
+// Every test runs in a child process so that a crash or a hang does not stop the run.
+// Tests are run both in sloppy mode and in strict mode unless their flags say otherwise, and
+// module and async tests are skipped. The type of the error expected by a negative test is
+// checked only when it can be known; an exception without an own 'name' passes as any type.
+
+extern crate libc;
+extern crate nix;
+extern crate rapidus;
+
+use nix::sys::signal::Signal;
+use nix::sys::wait::*;
+use nix::unistd::*;
+use rapidus::{Engine, JsError, JsValue};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const TEST262_REPOSITORY: &str = "https://github.com/tc39/test262.git";
+
+/// seconds before a test is killed.
+const TIMEOUT_SECS: u32 = 10;
+
+/// $262 and print() used by the tests. Only what the engine can support is provided.
+const HOST_DEFINED: &str = "
+var $262 = {
+  global: this,
+  gc: function() {},
+  evalScript: function() { throw new Test262Error('$262.evalScript() is not supported') },
+  createRealm: function() { throw new Test262Error('$262.createRealm() is not supported') },
+  detachArrayBuffer: function() { throw new Test262Error('$262.detachArrayBuffer() is not supported') },
+  agent: {}
+};
+function print(message) { console.log(message) }
+";
+
+/// the metadata in /*--- ... ---*/ of a test.
+#[derive(Debug, Default)]
+struct Metadata {
+    includes: Vec<String>,
+    flags: Vec<String>,
+    features: Vec<String>,
+    /// (phase, type) of the error the test expects.
+    negative: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Crash,
+    Skip,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Stats {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Pass => self.passed += 1,
+            Outcome::Skip => self.skipped += 1,
+            _ => self.failed += 1,
+        }
+    }
+
+    fn line(&self, name: &str) -> String {
+        let run = self.passed + self.failed;
+        let rate = if run == 0 {
+            0.0
+        } else {
+            self.passed as f64 * 100.0 / run as f64
+        };
+        format!(
+            "{:<40} {:>7} {:>7} {:>7} {:>6.1}%",
+            name, self.passed, self.failed, self.skipped, rate
+        )
+    }
+}
+
+fn main() {
+    let suite = match find_suite() {
+        Some(suite) => suite,
+        None => {
+            println!(
+                "test262: the suite is not found. Set TEST262_DIR, or TEST262_DOWNLOAD=1 to \
+                 clone it into target/test262. Skipped."
+            );
+            return;
+        }
+    };
+    let filter = env::var("TEST262_FILTER").unwrap_or_default();
+    let verbose = env::var("TEST262_VERBOSE").is_ok();
+
+    let mut tests = vec![];
+    collect_tests(&suite.join("test"), &mut tests);
+    tests.sort();
+    let tests: Vec<PathBuf> = tests
+        .into_iter()
+        .filter(|path| relative_name(&suite, path).contains(filter.as_str()))
+        .collect();
+
+    let mut total = Stats::default();
+    let mut features: BTreeMap<String, Stats> = BTreeMap::new();
+    for path in &tests {
+        let name = relative_name(&suite, path);
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        let metadata = parse_metadata(&source);
+
+        for outcome in run_test(&suite, &source, &metadata, verbose) {
+            if verbose && outcome != Outcome::Pass && outcome != Outcome::Skip {
+                println!("{:?}: {}", outcome, name);
+            }
+            total.add(outcome);
+            if metadata.features.is_empty() {
+                features
+                    .entry("(none)".to_string())
+                    .or_default()
+                    .add(outcome);
+            }
+            for feature in &metadata.features {
+                features.entry(feature.clone()).or_default().add(outcome);
+            }
+        }
+    }
+
+    println!(
+        "{:<40} {:>7} {:>7} {:>7} {:>7}",
+        "feature", "passed", "failed", "skipped", "rate"
+    );
+    for (feature, stats) in &features {
+        println!("{}", stats.line(feature));
+    }
+    println!("{}", total.line("total"));
+}
+
+fn find_suite() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("TEST262_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/test262");
+    if dir.join("test").is_dir() {
+        return Some(dir);
+    }
+    if env::var("TEST262_DOWNLOAD").is_err() {
+        return None;
+    }
+    println!("test262: cloning {} ...", TEST262_REPOSITORY);
+    let status = Command::new("git")
+        .args(&["clone", "--depth", "1", TEST262_REPOSITORY])
+        .arg(&dir)
+        .status();
+    match status {
+        Ok(status) if status.success() => Some(dir),
+        _ => None,
+    }
+}
+
+fn collect_tests(dir: &Path, tests: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_tests(&path, tests);
+        } else {
+            let name = path.to_string_lossy();
+            // _FIXTURE files are loaded by other tests.
+            if name.ends_with(".js") && !name.contains("_FIXTURE") {
+                tests.push(path);
+            }
+        }
+    }
+}
+
+/// the path of a test under test/.
+fn relative_name(suite: &Path, path: &Path) -> String {
+    path.strip_prefix(suite.join("test"))
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Parse the YAML frontmatter. Only the forms used by test262 are supported:
+/// 'key: [a, b]', 'key:' followed by '- item' lines, and 'phase:' and 'type:' in 'negative:'.
+fn parse_metadata(source: &str) -> Metadata {
+    let mut metadata = Metadata::default();
+    let start = match source.find("/*---") {
+        Some(start) => start + 5,
+        None => return metadata,
+    };
+    let end = source[start..]
+        .find("---*/")
+        .map_or(source.len(), |end| start + end);
+
+    let mut key = String::new();
+    let (mut phase, mut ty) = (String::new(), String::new());
+    for line in source[start..end].lines() {
+        let trimmed = line.trim();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            push_item(&mut metadata, &key, item.trim());
+            continue;
+        }
+        let colon = match trimmed.find(':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let (name, val) = (trimmed[..colon].trim(), trimmed[colon + 1..].trim());
+        let indented = line.starts_with(' ') || line.starts_with('\t');
+        if indented && key == "negative" {
+            match name {
+                "phase" => phase = val.to_string(),
+                "type" => ty = val.to_string(),
+                _ => {}
+            }
+            continue;
+        }
+        if indented {
+            continue;
+        }
+        key = name.to_string();
+        if val.starts_with('[') {
+            for item in val.trim_matches(|c| c == '[' || c == ']').split(',') {
+                push_item(&mut metadata, &key, item.trim());
+            }
+        }
+    }
+    if !phase.is_empty() {
+        metadata.negative = Some((phase, ty));
+    }
+    metadata
+}
+
+fn push_item(metadata: &mut Metadata, key: &str, item: &str) {
+    if item.is_empty() {
+        return;
+    }
+    let list = match key {
+        "includes" => &mut metadata.includes,
+        "flags" => &mut metadata.flags,
+        "features" => &mut metadata.features,
+        _ => return,
+    };
+    list.push(item.to_string());
+}
+
+/// Run a test in the modes its flags allow. Returns the outcome of each mode.
+fn run_test(suite: &Path, source: &str, metadata: &Metadata, verbose: bool) -> Vec<Outcome> {
+    let has_flag = |flag: &str| metadata.flags.iter().any(|f| f == flag);
+    if has_flag("module") || has_flag("async") {
+        return vec![Outcome::Skip];
+    }
+
+    if has_flag("raw") {
+        return vec![run_in_child(source, &metadata.negative, verbose)];
+    }
+
+    let mut prelude = String::new();
+    let harness = ["sta.js", "assert.js"];
+    for include in harness
+        .iter()
+        .cloned()
+        .chain(metadata.includes.iter().map(|s| s.as_str()))
+    {
+        match fs::read_to_string(suite.join("harness").join(include)) {
+            Ok(include) => prelude += &include,
+            Err(_) => return vec![Outcome::Fail],
+        }
+        prelude += "\n";
+    }
+    prelude += HOST_DEFINED;
+
+    let mut outcomes = vec![];
+    if !has_flag("onlyStrict") {
+        let sloppy = format!("{}\n{}", prelude, source);
+        outcomes.push(run_in_child(&sloppy, &metadata.negative, verbose));
+    }
+    if !has_flag("noStrict") {
+        let strict = format!("'use strict';\n{}\n{}", prelude, source);
+        outcomes.push(run_in_child(&strict, &metadata.negative, verbose));
+    }
+    outcomes
+}
+
+fn run_in_child(source: &str, negative: &Option<(String, String)>, verbose: bool) -> Outcome {
+    match fork() {
+        Ok(ForkResult::Parent { child, .. }) => match waitpid(child, None) {
+            Ok(WaitStatus::Exited(_, 0)) => Outcome::Pass,
+            Ok(WaitStatus::Exited(_, _)) => Outcome::Fail,
+            Ok(WaitStatus::Signaled(_, Signal::SIGALRM, _)) => Outcome::Timeout,
+            _ => Outcome::Crash,
+        },
+        Ok(ForkResult::Child) => {
+            unsafe {
+                // Tests print things by console.log() and print().
+                let devnull = libc::open(
+                    b"/dev/null\0".as_ptr() as *const libc::c_char,
+                    libc::O_WRONLY,
+                );
+                libc::dup2(devnull, 1);
+                libc::alarm(TIMEOUT_SECS);
+            }
+            let code = match run(source, negative) {
+                Ok(()) => 0,
+                Err(msg) => {
+                    if verbose {
+                        eprintln!("  {}", msg);
+                    }
+                    1
+                }
+            };
+            unsafe {
+                libc::fflush(::std::ptr::null_mut());
+                libc::_exit(code)
+            }
+        }
+        Err(e) => panic!("test262: fork failed: {:?}", e),
+    }
+}
+
+fn run(source: &str, negative: &Option<(String, String)>) -> Result<(), String> {
+    let mut engine = Engine::new();
+    let res = engine.eval(source);
+    match (res, negative) {
+        (Ok(_), None) => Ok(()),
+        (Err(err), None) => Err(err.to_string()),
+        (Ok(_), Some((phase, ty))) => Err(format!("expected {} in the {} phase", ty, phase)),
+        (Err(err), Some((phase, ty))) => {
+            let is_syntax_error = match err {
+                JsError::Syntax { .. } => true,
+                _ => false,
+            };
+            if (phase == "parse" || phase == "early") != is_syntax_error {
+                return Err(format!("expected {} in the {} phase: {}", ty, phase, err));
+            }
+            match error_type(&err) {
+                Some(ref actual) if actual != ty => {
+                    Err(format!("expected {} but got {}: {}", ty, actual, err))
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+/// the name of the constructor of the error, if it can be known.
+fn error_type(err: &JsError) -> Option<String> {
+    match err {
+        JsError::Syntax { .. } => Some("SyntaxError".to_string()),
+        JsError::Runtime(msg) if msg.starts_with("type error") => Some("TypeError".to_string()),
+        JsError::Runtime(msg) if msg.starts_with("reference error") => {
+            Some("ReferenceError".to_string())
+        }
+        JsError::Runtime(_) | JsError::Terminated => None,
+        JsError::Exception(val @ JsValue::Object(_)) => val
+            .get("name")
+            .and_then(|name| name.as_str().map(|s| s.to_string())),
+        JsError::Exception(_) => None,
+    }
+}